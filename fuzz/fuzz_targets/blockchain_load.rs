@@ -0,0 +1,20 @@
+//! A corrupted or hostile chain file must fail to load, not take the node down
+#![no_main]
+
+use btclib::{types::Blockchain, util::Saveable};
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Blockchain::load(Cursor::new(data));
+    // past the header too, so the fuzzer doesn't have to find the magic first
+    let current = [
+        b"BTCRSCHN".as_slice(),
+        &Blockchain::FORMAT_VERSION.to_be_bytes(),
+        data,
+    ]
+    .concat();
+    let _ = Blockchain::load(Cursor::new(current));
+});
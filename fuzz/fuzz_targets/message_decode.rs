@@ -0,0 +1,14 @@
+//! Anything a peer sends must decode to a message or an error, never a panic or a huge allocation
+#![no_main]
+
+use btclib::network::Message;
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Message::decode(data);
+    // the same bytes as a framed message, length prefix, kind and all
+    let _ = Message::receive(&mut Cursor::new(data));
+});
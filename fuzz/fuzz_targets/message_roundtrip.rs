@@ -0,0 +1,12 @@
+//! Every message a node can send decodes back to itself
+#![no_main]
+
+use btclib::network::Message;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|message: Message| {
+    let encoded = message.encode().unwrap();
+    let decoded = Message::decode(&encoded).unwrap();
+    assert_eq!(decoded.encode().unwrap(), encoded);
+});
@@ -0,0 +1,140 @@
+//! Benchmarks of the paths every node runs for every block and transaction. Run with
+//! `cargo bench -p btclib`, criterion compares against the previous run and flags regressions.
+
+use btclib::{
+    chain_params::ChainParams,
+    crypto::PrivateKey,
+    types::{Amount, Block, BlockBuilder, Blockchain, Transaction, TransactionBuilder},
+    util::MerkleRoot,
+};
+
+use std::hint::black_box;
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+
+/// transactions in the mempool benchmark
+const MEMPOOL_SIZE: usize = 10_000;
+/// blocks in the UTXO rebuild benchmark
+const CHAIN_LENGTH: u64 = 100_000;
+
+fn mine(mut block: Block) -> Block {
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    block
+}
+
+/// A chain whose genesis block pays `key` `outputs` times, with one transaction spending each
+/// output back to it
+fn funded_chain(key: &PrivateKey, outputs: usize) -> (Blockchain, Vec<Transaction>) {
+    let mut blockchain = Blockchain::regtest();
+    let reward = blockchain.calculate_block_reward().to_sat();
+    let share = reward / outputs as u64;
+    let mut builder = BlockBuilder::new()
+        .timestamp(Utc::now() - Duration::minutes(10))
+        .coinbase_to(key.public_key(), Amount::from_sat(share + reward % share));
+    for _ in 1..outputs {
+        builder = builder.coinbase_to(key.public_key(), Amount::from_sat(share));
+    }
+    let genesis = builder.finalize().unwrap();
+    let spends = genesis.transactions[0]
+        .outputs
+        .iter()
+        .map(|output| {
+            TransactionBuilder::new()
+                .spend(output, key)
+                .change_to(key.public_key(), Amount::ONE_SAT)
+                .finalize()
+                .unwrap()
+        })
+        .collect();
+    blockchain.add_block(genesis).unwrap();
+    (blockchain, spends)
+}
+
+fn header_hash(c: &mut Criterion) {
+    let key = PrivateKey::new_key();
+    let block = BlockBuilder::new()
+        .coinbase_to(key.public_key(), Amount::ONE_BTC)
+        .finalize()
+        .unwrap();
+    c.bench_function("header hash", |b| {
+        b.iter(|| black_box(&block.header).hash())
+    });
+}
+
+fn merkle_root(c: &mut Criterion) {
+    let key = PrivateKey::new_key();
+    let (_, transactions) = funded_chain(&key, 1024);
+    c.bench_function("merkle root of 1024 transactions", |b| {
+        b.iter(|| MerkleRoot::calculate(black_box(&transactions)).unwrap())
+    });
+}
+
+fn block_validation(c: &mut Criterion) {
+    let key = PrivateKey::new_key();
+    let (blockchain, transactions) = funded_chain(&key, btclib::BLOCK_TRANSACTION_CAP - 1);
+    let block = mine(
+        BlockBuilder::on_top_of(&blockchain)
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .add_txs(transactions)
+            .finalize_with_fees(blockchain.utxos())
+            .unwrap(),
+    );
+    c.bench_function("validate a full block", |b| {
+        b.iter_batched(
+            || block.clone(),
+            |block| blockchain.validate_block(block).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn mempool_insertion(c: &mut Criterion) {
+    let key = PrivateKey::new_key();
+    let (blockchain, transactions) = funded_chain(&key, MEMPOOL_SIZE);
+    c.bench_function("fill the mempool with 10k transactions", |b| {
+        b.iter_batched(
+            || blockchain.clone(),
+            |mut blockchain| {
+                for transaction in &transactions {
+                    blockchain.add_to_mempool(transaction.clone()).unwrap();
+                }
+                blockchain
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn utxo_rebuild(c: &mut Criterion) {
+    let key = PrivateKey::new_key();
+    // no halvings, so no block pays nothing
+    let params = ChainParams {
+        halving_interval: CHAIN_LENGTH,
+        ..ChainParams::regtest()
+    };
+    let mut blockchain = Blockchain::with_params(Arc::new(params));
+    let start = Utc::now() - Duration::seconds(CHAIN_LENGTH as i64 + 60);
+    for height in 0..CHAIN_LENGTH {
+        let block = BlockBuilder::on_top_of(&blockchain)
+            .timestamp(start + Duration::seconds(height as i64))
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .finalize()
+            .unwrap();
+        blockchain.add_block(mine(block)).unwrap();
+    }
+    c.bench_function("rebuild UTXOs of 100k blocks", |b| {
+        b.iter(|| blockchain.rebuild_utxos())
+    });
+}
+
+criterion_group! {
+    name = benches;
+    // the setups are expensive and the paths slow enough that fewer samples are still stable
+    config = Criterion::default().sample_size(10);
+    targets = header_hash, merkle_root, block_validation, mempool_insertion, utxo_rebuild
+}
+criterion_main!(benches);
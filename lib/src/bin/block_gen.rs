@@ -1,10 +1,7 @@
+use btclib::chain_params::ChainParams;
 use btclib::crypto::PrivateKey;
-use btclib::sha256::Hash;
-use btclib::types::{Block, BlockHeader, Transaction, TransactionOutput};
-use btclib::util::{MerkleRoot, Saveable};
-
-use chrono::Utc;
-use uuid::Uuid;
+use btclib::types::BlockBuilder;
+use btclib::util::Saveable;
 
 use std::env;
 use std::process::exit;
@@ -19,21 +16,13 @@ fn main() {
 
     let private_key = PrivateKey::new_key();
 
-    let transactions = vec![Transaction::new(
-        vec![],
-        vec![TransactionOutput {
-            unique_id: Uuid::new_v4(),
-            value: btclib::INITIAL_REWARD * 10u64.pow(8),
-            pubkey: private_key.public_key(),
-        }],
-    )];
-
-    let merkle_root = MerkleRoot::calculate(&transactions);
-
-    let block = Block::new(
-        BlockHeader::new(Utc::now(), 0, Hash::zero(), merkle_root, btclib::MIN_TARGET),
-        transactions,
-    );
+    let block = BlockBuilder::new()
+        .coinbase_to(
+            private_key.public_key(),
+            ChainParams::default().block_reward(0),
+        )
+        .finalize()
+        .expect("Block has a coinbase");
 
     block.save_to_file(path).expect("Failed to save block");
 }
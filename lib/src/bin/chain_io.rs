@@ -0,0 +1,127 @@
+//! Export blocks of a saved chain to a portable file, or import such a file into a saved chain,
+//! e.g. to hand out a pre-mined chain or to collect chains for grading. Also writes a saved
+//! chain to an indexed block file, reads blocks back out of one and checks one over.
+
+use btclib::chain_params::ChainParams;
+use btclib::types::{BlockFile, Blockchain, ExportFormat};
+use btclib::util::Saveable;
+
+use std::env;
+use std::path::Path;
+use std::process::exit;
+use std::sync::Arc;
+
+const USAGE: &str = "Usage:
+  chain_io export <chain_file> <out_file> [start] [end] [--hex]
+  chain_io import <chain_file> <in_file> [--chainspec <file>]
+  chain_io blockfile <chain_file> <block_file>
+  chain_io block <block_file> <height>
+  chain_io fsck <block_file>
+  chain_io reindex <block_file>";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let hex = args.iter().any(|arg| arg == "--hex");
+    let chainspec = args
+        .iter()
+        .position(|arg| arg == "--chainspec")
+        .map(|i| args.get(i + 1).cloned().unwrap_or_else(|| usage()));
+    let args: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| {
+            *arg != "--hex" && *arg != "--chainspec" && Some(*arg) != chainspec.as_deref()
+        })
+        .collect();
+
+    let result = match args[..] {
+        ["export", chain_file, out_file, ref range @ ..] if range.len() <= 2 => {
+            let height =
+                |arg: Option<&&str>| arg.map(|h| h.parse::<u64>().unwrap_or_else(|_| usage()));
+            let start = height(range.first()).unwrap_or(0);
+            let end = height(range.get(1)).unwrap_or(u64::MAX);
+            let format = if hex {
+                ExportFormat::Hex
+            } else {
+                ExportFormat::Binary
+            };
+            let blockchain = Blockchain::load_from_file(chain_file).expect("Failed to load chain");
+            blockchain
+                .export_blocks_to_file(start..end, format, out_file)
+                .map(|count| println!("Exported {count} blocks to {out_file}"))
+        }
+        ["import", chain_file, in_file] => {
+            let params = Arc::new(match chainspec {
+                Some(path) => ChainParams::load_from_file(path).expect("Failed to load chain spec"),
+                None => ChainParams::default(),
+            });
+            let mut blockchain = if Path::new(chain_file).exists() {
+                let mut blockchain =
+                    Blockchain::load_from_file(chain_file).expect("Failed to load chain");
+                blockchain.set_params(params);
+                blockchain
+            } else {
+                Blockchain::with_params(params)
+            };
+            blockchain.rebuild_utxos();
+            let result = blockchain.import_blocks_from_file(in_file);
+            // keep whatever was valid, even if a later block was not
+            blockchain
+                .save_to_file(chain_file)
+                .expect("Failed to save chain");
+            result.map(|count| {
+                println!(
+                    "Imported {count} blocks, {chain_file} is now {} blocks high",
+                    blockchain.block_height()
+                )
+            })
+        }
+        ["blockfile", chain_file, block_file] => {
+            let blockchain = Blockchain::load_from_file(chain_file).expect("Failed to load chain");
+            BlockFile::open(block_file)
+                .and_then(|file| blockchain.write_block_file(&file).map(|n| (n, file.len())))
+                .map(|(appended, len)| {
+                    println!("Appended {appended} blocks, {block_file} is now {len} blocks long")
+                })
+        }
+        ["block", block_file, height] => {
+            let height = height.parse().unwrap_or_else(|_| usage());
+            BlockFile::open(block_file)
+                .and_then(|file| file.read_block(height))
+                .map(|block| match block {
+                    Some(block) => println!("{block:#?}"),
+                    None => println!("No block at height {height}"),
+                })
+        }
+        ["fsck", block_file] => BlockFile::open(block_file)
+            .and_then(|file| file.verify())
+            .map(|report| {
+                println!(
+                    "{} blocks in {} bytes, {} problems",
+                    report.blocks,
+                    report.bytes,
+                    report.problems.len()
+                );
+                for problem in &report.problems {
+                    println!("  {problem}");
+                }
+                if !report.is_ok() {
+                    exit(2);
+                }
+            }),
+        ["reindex", block_file] => BlockFile::open(block_file)
+            .and_then(|file| file.reindex().map(|_| file.len()))
+            .map(|len| println!("Indexed {len} blocks")),
+        _ => usage(),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{e}");
+        exit(1);
+    }
+}
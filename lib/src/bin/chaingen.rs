@@ -0,0 +1,104 @@
+//! Generate a synthetic chain, see `btclib::chaingen`. Next to the chain it saves the keys it pays
+//! to as `<chain_file>.key<i>_priv.cbor` and `_pub.pem`, every fork as an export of the branch's
+//! blocks from the fork point on in `<chain_file>.fork-<height>`, and every invalid block as
+//! `<chain_file>.invalid-<height>`. The same options and seed always give the same files.
+
+use btclib::chain_params::ChainParams;
+use btclib::chaingen::ChainGen;
+use btclib::types::ExportFormat;
+use btclib::util::Saveable;
+
+use std::env;
+use std::process::exit;
+use std::str::FromStr;
+use std::sync::Arc;
+
+const USAGE: &str = "Usage: chaingen <chain_file> [--blocks <n>] [--txs <per block>] [--keys <n>]
+                [--seed <n>] [--fork <height>]... [--invalid <height>]...
+                [--chainspec <file>]";
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    exit(1);
+}
+
+fn parse<T: FromStr>(value: Option<String>) -> T {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| usage())
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let Some(chain_file) = args.next().filter(|arg| !arg.starts_with("--")) else {
+        usage();
+    };
+
+    let mut config = ChainGen::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--blocks" => config.blocks = parse(args.next()),
+            "--txs" => config.transactions_per_block = parse(args.next()),
+            "--keys" => config.keys = parse(args.next()),
+            "--seed" => config.seed = parse(args.next()),
+            "--fork" => config.forks.push(parse(args.next())),
+            "--invalid" => config.invalid.push(parse(args.next())),
+            "--chainspec" => {
+                let path: String = parse(args.next());
+                config.params =
+                    Arc::new(ChainParams::load_from_file(path).expect("Failed to load chain spec"));
+            }
+            _ => usage(),
+        }
+    }
+
+    let generated = match config.generate() {
+        Ok(generated) => generated,
+        Err(e) => {
+            eprintln!("{e}");
+            exit(1);
+        }
+    };
+
+    generated
+        .blockchain
+        .save_to_file(&chain_file)
+        .expect("Failed to save chain");
+    println!(
+        "Saved {} blocks to {chain_file}",
+        generated.blockchain.block_height()
+    );
+
+    for (i, key) in generated.keys.iter().enumerate() {
+        let name = format!("{chain_file}.key{i}");
+        key.save_to_file(format!("{name}_priv.cbor"))
+            .expect("Failed to save private key");
+        key.public_key()
+            .save_to_file(format!("{name}_pub.pem"))
+            .expect("Failed to save public key");
+    }
+
+    for fork in &generated.forks {
+        let path = format!("{chain_file}.fork-{}", fork.height);
+        let count = fork
+            .blockchain
+            .export_blocks_to_file(fork.height.., ExportFormat::Binary, &path)
+            .expect("Failed to export fork");
+        println!(
+            "Saved {count} blocks forking off at {} to {path}",
+            fork.height
+        );
+    }
+
+    for invalid in &generated.invalid {
+        let path = format!("{chain_file}.invalid-{}", invalid.height);
+        invalid
+            .block
+            .save_to_file(&path)
+            .expect("Failed to save block");
+        println!(
+            "Saved an invalid block at {} ({}) to {path}",
+            invalid.height, invalid.corruption
+        );
+    }
+}
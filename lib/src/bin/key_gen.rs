@@ -1,18 +1,83 @@
-//! Provided a name, create a pair of keys.
+//! Provided a name, create a pair of keys. With `--encrypt` the private key file is sealed with a
+//! passphrase asked for on the terminal. With `--decoy` a second pair is made as well, its public
+//! key saved as `<name>_decoy_pub.pem` and its private key sealed into the same private key file
+//! under a duress passphrase, see `btclib::crypto::keyfile`. Keep small amounts on the decoy.
 
-use btclib::crypto::PrivateKey;
+use btclib::crypto::{Algorithm, PrivateKey};
 use btclib::util::Saveable;
 use std::env;
+use std::process::exit;
+
+const USAGE: &str = "Usage: key_gen <name> [secp256k1|ed25519|schnorr] [--encrypt|--decoy]";
+
+fn prompt_new(prompt: &str) -> String {
+    let passphrase = rpassword::prompt_password(format!("{prompt}: ")).unwrap();
+    let confirmation =
+        rpassword::prompt_password(format!("Repeat {}: ", prompt.to_lowercase())).unwrap();
+    if passphrase != confirmation {
+        eprintln!("Passphrases do not match");
+        exit(1);
+    }
+    passphrase
+}
 
 fn main() {
-    let name = env::args().nth(1).expect("Please provide a name");
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let encrypt = flags.iter().any(|flag| flag == "--encrypt");
+    let decoy = flags.iter().any(|flag| flag == "--decoy");
+    if let Some(flag) = flags
+        .iter()
+        .find(|flag| *flag != "--encrypt" && *flag != "--decoy")
+    {
+        eprintln!("unknown flag: {flag}");
+        eprintln!("{USAGE}");
+        exit(1);
+    }
+
+    let name = args.first().cloned().expect("Please provide a name");
+    // secp256k1 unless another signature scheme is asked for
+    let algorithm = match args.get(1).map(|arg| arg.parse::<Algorithm>()) {
+        None => Algorithm::Secp256k1,
+        Some(Ok(algorithm)) => algorithm,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            exit(1);
+        }
+    };
 
-    let private_key = PrivateKey::new_key();
+    let private_key = PrivateKey::generate(algorithm);
     let public_key = private_key.public_key();
 
     let public_key_file = name.clone() + "_pub.pem";
+    let decoy_public_key_file = name.clone() + "_decoy_pub.pem";
     let private_key_file = name + "_priv.cbor";
 
-    private_key.save_to_file(&private_key_file).unwrap();
+    if decoy {
+        let passphrase = prompt_new("Passphrase");
+        let decoy_passphrase = prompt_new("Duress passphrase");
+        let decoy_key = PrivateKey::generate(algorithm);
+        if let Err(e) = private_key.save_encrypted_with_decoy_to_file(
+            &private_key_file,
+            &passphrase,
+            &decoy_key,
+            &decoy_passphrase,
+        ) {
+            eprintln!("{e}");
+            exit(1);
+        }
+        decoy_key
+            .public_key()
+            .save_to_file(&decoy_public_key_file)
+            .unwrap();
+    } else if encrypt {
+        let passphrase = prompt_new("Passphrase");
+        private_key
+            .save_encrypted_to_file(&private_key_file, &passphrase)
+            .unwrap();
+    } else {
+        private_key.save_to_file(&private_key_file).unwrap();
+    }
     public_key.save_to_file(&public_key_file).unwrap();
 }
@@ -27,10 +27,10 @@ fn main() {
         println!("mining....counter: {counter}");
         counter += 1;
     }
-    let reward = &block.transactions[0].outputs[0].value / 100_000_000;
+    let reward = block.transactions[0].outputs[0].value;
 
     println!("Block mined! number of attempts: {}", block.header.nonce);
-    println!("Rewarded: {reward} BTC");
+    println!("Rewarded: {reward}");
     println!("hash was: {}", og_block.header.hash());
     println!("mined hash: {}", block.header.hash());
     // println!("original block: {:#?}", og_block);
@@ -1,9 +1,8 @@
+use btclib::chain_params::ChainParams;
 use btclib::crypto::PrivateKey;
-use btclib::types::{Transaction, TransactionOutput};
+use btclib::types::TransactionBuilder;
 use btclib::util::Saveable;
 
-use uuid::Uuid;
-
 use std::env;
 use std::process::exit;
 
@@ -17,14 +16,13 @@ fn main() {
 
     let private_key = PrivateKey::new_key();
 
-    let transaction = Transaction::new(
-        vec![],
-        vec![TransactionOutput {
-            unique_id: Uuid::new_v4(),
-            value: btclib::INITIAL_REWARD * 10u64.pow(8),
-            pubkey: private_key.public_key(),
-        }],
-    );
+    let transaction = TransactionBuilder::new()
+        .pay_to(
+            private_key.public_key(),
+            ChainParams::default().block_reward(0),
+        )
+        .finalize()
+        .expect("Failed to build transaction");
 
     transaction
         .save_to_file(path)
@@ -14,7 +14,8 @@ fn main() {
     };
 
     if let Ok(file) = File::open(path) {
-        let tx = Transaction::load(file).expect("Failed to load transaction");
+        let tx = Transaction::load_checked(file).expect("Failed to load transaction");
         println!("{:#?}", tx);
+        println!("raw: {}", tx.to_hex());
     }
 }
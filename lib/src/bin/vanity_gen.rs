@@ -0,0 +1,143 @@
+//! Grind key pairs on every core until the hex encoded public key matches a pattern, then save
+//! them the same way `key_gen` does. A pattern made only of hex digits is a prefix, anything else
+//! is a regular expression.
+
+use btclib::crypto::{Algorithm, PrivateKey};
+use btclib::util::Saveable;
+use regex::Regex;
+
+use std::env;
+use std::process::exit;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: vanity_gen <name> <prefix|regex> [secp256k1|ed25519|schnorr]";
+
+enum Pattern {
+    Prefix(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Result<Self, regex::Error> {
+        if pattern.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(Pattern::Prefix(pattern.to_lowercase()))
+        } else {
+            Regex::new(pattern).map(Pattern::Regex)
+        }
+    }
+
+    fn matches(&self, public_key: &str) -> bool {
+        match self {
+            Pattern::Prefix(prefix) => public_key.starts_with(prefix),
+            Pattern::Regex(regex) => regex.is_match(public_key),
+        }
+    }
+
+    /// expected number of keys to try, only known for prefixes
+    fn expected_attempts(&self, algorithm: Algorithm) -> Option<f64> {
+        let Pattern::Prefix(prefix) = self else {
+            return None;
+        };
+        let mut chars = prefix.chars();
+        let mut attempts = 1f64;
+        // compressed secp256k1 points always start with 02 or 03
+        if algorithm != Algorithm::Ed25519 {
+            match (chars.next(), chars.next()) {
+                (Some('0'), None) => {}
+                (Some('0'), Some('2' | '3')) => attempts *= 2.0,
+                (None, _) => {}
+                _ => return Some(f64::INFINITY),
+            }
+        }
+        Some(attempts * 16f64.powi(chars.count() as i32))
+    }
+}
+
+fn main() {
+    let (Some(name), Some(pattern)) = (env::args().nth(1), env::args().nth(2)) else {
+        eprintln!("{USAGE}");
+        exit(1);
+    };
+    let algorithm = match env::args().nth(3).map(|arg| arg.parse::<Algorithm>()) {
+        None => Algorithm::Secp256k1,
+        Some(Ok(algorithm)) => algorithm,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            exit(1);
+        }
+    };
+    let pattern = match Pattern::parse(&pattern) {
+        Ok(pattern) => Arc::new(pattern),
+        Err(e) => {
+            eprintln!("Invalid pattern: {e}");
+            exit(1);
+        }
+    };
+    let expected = pattern.expected_attempts(algorithm);
+    if expected == Some(f64::INFINITY) {
+        eprintln!("No {algorithm} public key can start with that prefix");
+        exit(1);
+    }
+
+    let found = Arc::new(AtomicBool::new(false));
+    let attempts = Arc::new(AtomicU64::new(0));
+    let (sender, receiver) = mpsc::channel();
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    for _ in 0..threads {
+        let (pattern, found, attempts, sender) = (
+            pattern.clone(),
+            found.clone(),
+            attempts.clone(),
+            sender.clone(),
+        );
+        thread::spawn(move || {
+            while !found.load(Ordering::Relaxed) {
+                let private_key = PrivateKey::generate(algorithm);
+                attempts.fetch_add(1, Ordering::Relaxed);
+                if pattern.matches(&hex::encode(private_key.public_key().to_bytes())) {
+                    found.store(true, Ordering::Relaxed);
+                    let _ = sender.send(private_key);
+                }
+            }
+        });
+    }
+    println!("Grinding {algorithm} keys on {threads} threads");
+
+    let start = Instant::now();
+    let private_key = loop {
+        match receiver.recv_timeout(Duration::from_secs(1)) {
+            Ok(private_key) => break private_key,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let tried = attempts.load(Ordering::Relaxed);
+                let rate = tried as f64 / start.elapsed().as_secs_f64();
+                match expected {
+                    Some(expected) => println!(
+                        "{tried} keys tried, {rate:.0} keys/s, ~{:.0}s expected in total",
+                        expected / rate
+                    ),
+                    None => println!("{tried} keys tried, {rate:.0} keys/s"),
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => unreachable!(),
+        }
+    };
+    let public_key = private_key.public_key();
+    println!(
+        "Found {} after {} keys in {:.1?}",
+        hex::encode(public_key.to_bytes()),
+        attempts.load(Ordering::Relaxed),
+        start.elapsed()
+    );
+
+    private_key
+        .save_to_file(name.clone() + "_priv.cbor")
+        .unwrap();
+    public_key.save_to_file(name + "_pub.pem").unwrap();
+}
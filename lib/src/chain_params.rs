@@ -0,0 +1,230 @@
+//! Consensus constants of a chain. The crate level constants describe the default network, a
+//! `chainspec.toml` can replace them to run a custom one, e.g. a classroom chain with a faster
+//! block time:
+//!
+//! ```toml
+//! name = "classroom"
+//! initial_reward = 100
+//! ideal_block_time = 5
+//! min_target = "00000fffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"
+//!
+//! [genesis]
+//! timestamp = 1735689600
+//! pubkey = "02..."
+//!
+//! # blocks two of these three sign can't be reorganized away, see `SignedCheckpoint`
+//! [checkpoints]
+//! authorities = ["02...", "03...", "02..."]
+//! threshold = 2
+//! ```
+//!
+//! A chain run on proof-of-stake instead says `consensus = "proof-of-stake"`, see `consensus`.
+//! Missing fields keep their default value.
+
+use crate::{
+    U256,
+    consensus::Consensus,
+    crypto::PublicKey,
+    error::{BtcError, Result},
+    types::{Amount, Block, BlockBuilder, Federation, SATS_PER_BTC, TransactionOutput},
+};
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainParams {
+    pub name: String,
+    /// initial reward in bitcoin
+    pub initial_reward: u64,
+    /// halving interval in blocks
+    pub halving_interval: u64,
+    /// ideal block time in seconds
+    pub ideal_block_time: u64,
+    pub difficulty_update_interval: u64,
+    /// easiest target a block may have, as 64 hex characters
+    #[serde(serialize_with = "target_to_hex", deserialize_with = "target_from_hex")]
+    pub min_target: U256,
+    /// the first block, if everyone is supposed to start from the same one
+    pub genesis: Option<Genesis>,
+    /// who signs checkpoints, none are taken without
+    pub checkpoints: Option<Federation>,
+    /// how blocks prove they may be made
+    pub consensus: Consensus,
+    /// Test chains only: blocks may be mined at `REGTEST_TARGET` rather than the chain's target,
+    /// so they take no work at all
+    pub regtest: bool,
+}
+
+/// Everything needed to build the same genesis block on every node
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Genesis {
+    /// unix timestamp in seconds
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub timestamp: DateTime<Utc>,
+    /// who the genesis coinbase pays
+    pub pubkey: PublicKey,
+    #[serde(default)]
+    pub nonce: u64,
+    #[serde(default)]
+    pub unique_id: Uuid,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        ChainParams {
+            name: "main".to_string(),
+            initial_reward: crate::INITIAL_REWARD,
+            halving_interval: crate::HALVING_INTERVAL,
+            ideal_block_time: crate::IDEAL_BLOCK_TIME,
+            difficulty_update_interval: crate::DIFFICULTY_UPDATE_INTERVAL,
+            min_target: crate::MIN_TARGET,
+            genesis: None,
+            checkpoints: None,
+            consensus: Consensus::ProofOfWork,
+            regtest: false,
+        }
+    }
+}
+
+impl ChainParams {
+    /// the default chain, taking blocks mined at `REGTEST_TARGET`
+    pub fn regtest() -> Self {
+        ChainParams {
+            regtest: true,
+            ..ChainParams::default()
+        }
+    }
+
+    pub fn from_toml(spec: &str) -> Result<Self> {
+        let params: ChainParams =
+            toml::from_str(spec).map_err(|e| BtcError::InvalidChainSpec(e.to_string()))?;
+        params.validate()?;
+        Ok(params)
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let spec = fs::read_to_string(&path)
+            .map_err(|e| BtcError::InvalidChainSpec(format!("{}: {e}", path.as_ref().display())))?;
+        Self::from_toml(&spec)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("chain params are always valid toml")
+    }
+
+    /// reject specs the chain could not run with
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: &str| Err(BtcError::InvalidChainSpec(reason.to_string()));
+        if self.halving_interval == 0 {
+            return invalid("halving_interval must be positive");
+        }
+        if self.ideal_block_time == 0 {
+            return invalid("ideal_block_time must be positive");
+        }
+        if self.difficulty_update_interval == 0 {
+            return invalid("difficulty_update_interval must be positive");
+        }
+        if self.min_target.is_zero() {
+            return invalid("min_target must be positive");
+        }
+        if self.emission_bound().is_none() {
+            return invalid("initial_reward and halving_interval allow more than MAX_MONEY");
+        }
+        if let Some(federation) = &self.checkpoints
+            && !(1..=federation.authorities.len()).contains(&federation.threshold)
+        {
+            return invalid("checkpoints need a threshold from 1 to the number of authorities");
+        }
+        Ok(())
+    }
+
+    /// the coinbase reward for a block at `height`, halved every `halving_interval` blocks
+    pub fn block_reward(&self, height: u64) -> Amount {
+        let halvings = height / self.halving_interval;
+        // after 64 halvings there is nothing left to halve
+        Amount::from_sat(
+            self.initial_reward
+                .saturating_mul(SATS_PER_BTC)
+                .checked_shr(u32::try_from(halvings).unwrap_or(u32::MAX))
+                .unwrap_or(0),
+        )
+    }
+
+    /// Upper bound on all coins this chain will ever create, never above `MAX_MONEY`. No output
+    /// or transaction may be worth more than this
+    pub fn max_money(&self) -> Amount {
+        self.emission_bound().unwrap_or(Amount::MAX_MONEY)
+    }
+
+    /// the halving rewards add up to just under twice what the first halving interval pays out,
+    /// `None` if that's more than `MAX_MONEY`
+    fn emission_bound(&self) -> Option<Amount> {
+        self.block_reward(0)
+            .checked_mul(self.halving_interval)?
+            .checked_mul(2)
+    }
+
+    /// all rewards paid by the blocks below `height`, i.e. the whole supply of a chain that high
+    pub fn issued_until(&self, height: u64) -> Amount {
+        let mut issued = Amount::ZERO;
+        let mut era_start = 0;
+        while era_start < height {
+            let reward = self.block_reward(era_start);
+            if reward == Amount::ZERO {
+                break;
+            }
+            let blocks = self.halving_interval.min(height - era_start);
+            issued = reward
+                .checked_mul(blocks)
+                .and_then(|era| issued.checked_add(era))
+                .unwrap_or(Amount::MAX_MONEY);
+            era_start = era_start.saturating_add(self.halving_interval);
+        }
+        issued
+    }
+
+    /// the genesis block described by the spec, if it has one
+    pub fn genesis_block(&self) -> Option<Result<Block>> {
+        let genesis = self.genesis.as_ref()?;
+        let coinbase = TransactionOutput {
+            value: self.block_reward(0),
+            unique_id: genesis.unique_id,
+            pubkey: genesis.pubkey.clone(),
+        };
+        Some(
+            BlockBuilder::new()
+                .timestamp(genesis.timestamp)
+                .nonce(genesis.nonce)
+                .set_target(self.min_target)
+                .coinbase_output(coinbase)
+                .finalize(),
+        )
+    }
+}
+
+fn target_to_hex<S: Serializer>(
+    target: &U256,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let mut bytes = [0u8; 32];
+    target.to_big_endian(&mut bytes);
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+fn target_from_hex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<U256, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    let bytes = hex::decode(hex.trim_start_matches("0x")).map_err(serde::de::Error::custom)?;
+    if bytes.len() > 32 {
+        return Err(serde::de::Error::custom("target is longer than 256 bits"));
+    }
+    Ok(U256::from_big_endian(&bytes))
+}
@@ -0,0 +1,392 @@
+//! Synthetic chains for benchmarks and validation tests. Everything is drawn from a seeded RNG and
+//! timestamps follow a fixed schedule, so the same `ChainGen` always produces the very same
+//! blocks. Blocks are mined at `REGTEST_TARGET`, which makes them instant to generate but also
+//! means only regtest chains take them, see `ChainParams::regtest`.
+//!
+//! Besides the chain itself it can produce competing branches that fork off at given heights and
+//! overtake it, and blocks that are invalid in one specific way, to feed reorg and validation
+//! paths.
+
+use crate::{
+    chain_params::ChainParams,
+    crypto::{Algorithm, PrivateKey},
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::{
+        Amount, Block, BlockBuilder, Blockchain, Transaction, TransactionBuilder, TransactionOutput,
+    },
+    util::MerkleRoot,
+};
+
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use uuid::Uuid;
+
+/// What to generate, see `generate`
+#[derive(Clone, Debug)]
+pub struct ChainGen {
+    pub blocks: u64,
+    /// transactions besides the coinbase, as long as the keys have outputs to spend. At most
+    /// `BLOCK_TRANSACTION_CAP - 1`
+    pub transactions_per_block: usize,
+    /// how many keys coinbases and payments go to
+    pub keys: usize,
+    /// Heights at which a competing branch forks off. It replaces the blocks from there on and
+    /// ends one block higher than the chain
+    pub forks: Vec<u64>,
+    /// heights at which to produce an invalid block on top of the chain's first blocks
+    pub invalid: Vec<u64>,
+    pub seed: u64,
+    /// timestamp of the first block, the others follow `block_time` apart
+    pub start: DateTime<Utc>,
+    /// seconds between blocks, the params' `ideal_block_time` if left out. Faster blocks make the
+    /// target harder at the next adjustment
+    pub block_time: Option<u64>,
+    /// regtest ones, or the blocks are refused
+    pub params: Arc<ChainParams>,
+}
+
+impl Default for ChainGen {
+    fn default() -> Self {
+        ChainGen {
+            blocks: 100,
+            transactions_per_block: 4,
+            keys: 4,
+            forks: vec![],
+            invalid: vec![],
+            seed: 0,
+            start: Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+            block_time: None,
+            params: Arc::new(ChainParams::regtest()),
+        }
+    }
+}
+
+/// The one thing wrong with an invalid block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corruption {
+    /// doesn't build on the block before it
+    WrongParent,
+    /// its hash doesn't meet its target
+    InsufficientWork,
+    /// the header commits to other transactions
+    BadMerkleRoot,
+    /// no later than the block before it
+    StaleTimestamp,
+    /// the coinbase pays one satoshi more than it may
+    Overpay,
+    /// a transaction is in the block twice, spending the same outputs again
+    DoubleSpend,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Corruption::WrongParent => "wrong parent",
+            Corruption::InsufficientWork => "insufficient work",
+            Corruption::BadMerkleRoot => "bad merkle root",
+            Corruption::StaleTimestamp => "stale timestamp",
+            Corruption::Overpay => "overpaying coinbase",
+            Corruption::DoubleSpend => "double spend",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A branch replacing the chain from `height` on
+#[derive(Clone, Debug)]
+pub struct Fork {
+    pub height: u64,
+    /// the whole branch, blocks before `height` are shared with the chain
+    pub blockchain: Blockchain,
+}
+
+/// A block that would be at `height`, if it were valid
+#[derive(Clone, Debug)]
+pub struct InvalidBlock {
+    pub height: u64,
+    pub corruption: Corruption,
+    pub block: Block,
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratedChain {
+    pub blockchain: Blockchain,
+    /// everything the chain pays goes to these
+    pub keys: Vec<PrivateKey>,
+    pub forks: Vec<Fork>,
+    pub invalid: Vec<InvalidBlock>,
+}
+
+/// a chain being extended, with the outputs its keys can still spend
+#[derive(Clone)]
+struct State {
+    blockchain: Blockchain,
+    spendable: Vec<(TransactionOutput, usize)>,
+}
+
+impl ChainGen {
+    pub fn generate(&self) -> Result<GeneratedChain> {
+        let cap = crate::BLOCK_TRANSACTION_CAP - 1;
+        if self.transactions_per_block > cap {
+            return Err(BtcError::InvalidChainGen(format!(
+                "at most {cap} transactions fit in a block next to the coinbase"
+            )));
+        }
+        if self.keys == 0 {
+            return Err(BtcError::InvalidChainGen("needs at least one key".into()));
+        }
+        if let Some(height) = self.forks.iter().find(|height| **height > self.blocks) {
+            return Err(BtcError::InvalidChainGen(format!(
+                "can't fork at {height}, the chain only has {} blocks",
+                self.blocks
+            )));
+        }
+        // the first block has no parent or timestamp to get wrong
+        if let Some(height) = self
+            .invalid
+            .iter()
+            .find(|height| **height == 0 || **height > self.blocks)
+        {
+            return Err(BtcError::InvalidChainGen(format!(
+                "can't make block {height} invalid, it has to be between 1 and {}",
+                self.blocks
+            )));
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let keys: Vec<PrivateKey> = (0..self.keys).map(|_| key(&mut rng)).collect();
+
+        let mut state = State {
+            blockchain: Blockchain::with_params(self.params.clone()),
+            spendable: vec![],
+        };
+        if let Some(genesis) = self.params.genesis_block() {
+            state.blockchain.add_block(genesis?)?;
+        }
+
+        let mut forks = vec![];
+        let mut invalid = vec![];
+        while state.blockchain.block_height() <= self.blocks {
+            let height = state.blockchain.block_height();
+            // branches get their own RNG, so asking for one doesn't change the chain
+            let mut branch_rng = StdRng::seed_from_u64(self.seed ^ height.rotate_left(32));
+            if self.forks.contains(&height) {
+                let mut fork = state.clone();
+                for _ in height..=self.blocks {
+                    self.extend(&mut fork, &keys, &mut branch_rng, 1)?;
+                }
+                forks.push(Fork {
+                    height,
+                    blockchain: fork.blockchain,
+                });
+            }
+            if self.invalid.contains(&height) {
+                invalid.push(self.invalid_block(&state, &keys, &mut branch_rng)?);
+            }
+            if height == self.blocks {
+                break;
+            }
+            self.extend(&mut state, &keys, &mut rng, 0)?;
+        }
+
+        Ok(GeneratedChain {
+            blockchain: state.blockchain,
+            keys,
+            forks,
+            invalid,
+        })
+    }
+
+    /// when the block after the chain's tip is mined, `offset` seconds late
+    fn timestamp(&self, blockchain: &Blockchain, offset: i64) -> DateTime<Utc> {
+        let time = match blockchain.blocks().last() {
+            Some(last) => {
+                let block_time = self.block_time.unwrap_or(self.params.ideal_block_time);
+                last.header.timestamp + Duration::seconds(block_time as i64)
+            }
+            None => self.start,
+        };
+        time + Duration::seconds(offset)
+    }
+
+    /// the next block of `state`, not added yet
+    fn next_block(
+        &self,
+        state: &mut State,
+        keys: &[PrivateKey],
+        rng: &mut StdRng,
+        offset: i64,
+    ) -> Result<Block> {
+        let mut transactions = vec![];
+        for _ in 0..self.transactions_per_block {
+            if state.spendable.is_empty() {
+                break;
+            }
+            let index = rng.gen_range(0..state.spendable.len());
+            let (output, owner) = state.spendable.swap_remove(index);
+            transactions.push(payment(&output, &keys[owner], keys, rng)?);
+        }
+
+        let mut block = BlockBuilder::on_top_of(&state.blockchain)
+            .timestamp(self.timestamp(&state.blockchain, offset))
+            .set_target(crate::REGTEST_TARGET)
+            .coinbase_output(TransactionOutput {
+                value: state.blockchain.calculate_block_reward(),
+                unique_id: unique_id(rng),
+                pubkey: keys[rng.gen_range(0..keys.len())].public_key(),
+            })
+            .add_txs(transactions)
+            .finalize_with_fees(state.blockchain.utxos())?;
+        block
+            .header
+            .mine_with_target_override(crate::REGTEST_TARGET);
+        Ok(block)
+    }
+
+    /// add the next block to `state`, its outputs become spendable
+    fn extend(
+        &self,
+        state: &mut State,
+        keys: &[PrivateKey],
+        rng: &mut StdRng,
+        offset: i64,
+    ) -> Result<()> {
+        let block = self.next_block(state, keys, rng, offset)?;
+        let outputs = block
+            .transactions
+            .iter()
+            .flat_map(|transaction| &transaction.outputs)
+            .filter_map(|output| {
+                let owner = keys
+                    .iter()
+                    .position(|key| key.public_key() == output.pubkey)?;
+                Some((output.clone(), owner))
+            })
+            .collect::<Vec<_>>();
+        state.blockchain.add_block(block)?;
+        state.spendable.extend(outputs);
+        Ok(())
+    }
+
+    fn invalid_block(
+        &self,
+        state: &State,
+        keys: &[PrivateKey],
+        rng: &mut StdRng,
+    ) -> Result<InvalidBlock> {
+        let height = state.blockchain.block_height();
+        let mut block = self.next_block(&mut state.clone(), keys, rng, 0)?;
+
+        let mut corruptions = vec![
+            Corruption::WrongParent,
+            Corruption::InsufficientWork,
+            Corruption::BadMerkleRoot,
+            Corruption::StaleTimestamp,
+            Corruption::Overpay,
+        ];
+        if block.transactions.len() > 1 {
+            corruptions.push(Corruption::DoubleSpend);
+        }
+        let corruption = corruptions[rng.gen_range(0..corruptions.len())];
+
+        match corruption {
+            Corruption::WrongParent => block.header.prev_block_hash = Hash::zero(),
+            Corruption::InsufficientWork => block.header.target = state.blockchain.target(),
+            Corruption::BadMerkleRoot => {
+                block.transactions[0].outputs[0].unique_id = unique_id(rng)
+            }
+            Corruption::StaleTimestamp => {
+                if let Some(last) = state.blockchain.blocks().last() {
+                    block.header.timestamp = last.header.timestamp;
+                }
+            }
+            Corruption::Overpay => {
+                let hash = block.transactions[0].hash();
+                let output = &mut block.transactions[0].outputs[0];
+                output.value = output
+                    .value
+                    .checked_add(Amount::ONE_SAT)
+                    .ok_or(BtcError::ValueOverflow(hash))?;
+                block.header.merkle_root = MerkleRoot::calculate(&block.transactions)?;
+            }
+            Corruption::DoubleSpend => {
+                block.transactions.push(block.transactions[1].clone());
+                block.header.merkle_root = MerkleRoot::calculate(&block.transactions)?;
+            }
+        }
+        // the hash changed with the header, but any hash still meets the target
+        if corruption == Corruption::InsufficientWork {
+            // at the chain's own target, with a nonce whose hash misses it
+            while block.header.hash().matches_target(block.header.target) {
+                block.header.nonce += 1;
+            }
+        } else {
+            block
+                .header
+                .mine_with_target_override(crate::REGTEST_TARGET);
+        }
+
+        Ok(InvalidBlock {
+            height,
+            corruption,
+            block,
+        })
+    }
+}
+
+fn key(rng: &mut StdRng) -> PrivateKey {
+    loop {
+        let mut secret = [0u8; 32];
+        rng.fill(&mut secret);
+        // practically always a valid scalar
+        if let Ok(key) = PrivateKey::from_bytes(Algorithm::Secp256k1, &secret) {
+            return key;
+        }
+    }
+}
+
+fn unique_id(rng: &mut StdRng) -> Uuid {
+    let mut bytes = [0u8; 16];
+    rng.fill(&mut bytes);
+    uuid::Builder::from_random_bytes(bytes).into_uuid()
+}
+
+/// Spend `output` paying a random part of it to a random key, the rest minus a satoshi of fee
+/// goes back to the owner
+fn payment(
+    output: &TransactionOutput,
+    owner: &PrivateKey,
+    keys: &[PrivateKey],
+    rng: &mut StdRng,
+) -> Result<Transaction> {
+    let value = output.value.to_sat();
+    let recipient = keys[rng.gen_range(0..keys.len())].public_key();
+    let mut builder = TransactionBuilder::new().spend(output, owner);
+    // too little to split and still pay a fee
+    if value < 3 {
+        return builder
+            .add_output(TransactionOutput {
+                value: output.value,
+                unique_id: unique_id(rng),
+                pubkey: recipient,
+            })
+            .finalize();
+    }
+    let paid = rng.gen_range(1..value - 1);
+    builder = builder
+        .add_output(TransactionOutput {
+            value: Amount::from_sat(paid),
+            unique_id: unique_id(rng),
+            pubkey: recipient,
+        })
+        .add_output(TransactionOutput {
+            value: Amount::from_sat(value - paid - 1),
+            unique_id: unique_id(rng),
+            pubkey: owner.public_key(),
+        });
+    builder.finalize()
+}
@@ -0,0 +1,46 @@
+//! What makes a block more than a list of transactions: the proof that whoever made it was
+//! allowed to. The chain spec picks the engine, see `ChainParams::consensus`. Proof-of-work is
+//! what every chain ran with so far, proof-of-stake is an experiment to compare it against, on
+//! chains of its own.
+
+mod stake;
+mod work;
+
+pub use stake::{BlockSignature, ProofOfStake};
+pub use work::ProofOfWork;
+
+use crate::{
+    U256,
+    error::Result,
+    types::{Block, Blockchain},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The rules an engine adds to the ones every block follows
+pub trait ConsensusEngine: Send + Sync {
+    /// Whether `block` proves it may go on `blockchain`'s tip, it builds on the tip already.
+    /// `work_target` is what its hash has to meet where work is what counts
+    fn verify_seal(&self, blockchain: &Blockchain, block: &Block, work_target: U256) -> Result<()>;
+
+    /// whether the chain's target follows how fast blocks come in
+    fn adjusts_target(&self) -> bool;
+}
+
+/// Which engine a chain runs with, `consensus = "proof-of-stake"` in a chain spec
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Consensus {
+    #[default]
+    ProofOfWork,
+    ProofOfStake,
+}
+
+impl Consensus {
+    pub fn engine(&self) -> &'static dyn ConsensusEngine {
+        match self {
+            Consensus::ProofOfWork => &ProofOfWork,
+            Consensus::ProofOfStake => &ProofOfStake,
+        }
+    }
+}
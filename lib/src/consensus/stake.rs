@@ -0,0 +1,150 @@
+//! Proof-of-stake, the simple kind. Time is cut into slots of `ideal_block_time` seconds and every
+//! slot has one leader, drawn from the keys holding unspent outputs at the tip with odds by how
+//! much they hold. Only the leader may make the block of its slot, and proves it by signing the
+//! header instead of grinding a nonce, so nonce and target mean nothing on these chains.
+//!
+//! The draw is seeded with the tip's hash, so a leader can try out block contents to pick who
+//! comes after it, and nothing stops it from signing two blocks for the same slot. It takes more
+//! than this to be more than an experiment.
+
+use super::ConsensusEngine;
+use crate::{
+    U256,
+    chain_params::ChainParams,
+    crypto::{PrivateKey, PublicKey, Signature},
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::{Amount, Block, BlockHeader, Blockchain},
+};
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// signed along with the header's hash, so the signature can't pass for anything else
+const SIGNING_PREFIX: &[u8] = b"block:";
+
+/// The slot leader's key and its signature over the block's header
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct BlockSignature {
+    pub key: PublicKey,
+    pub signature: Signature,
+}
+
+pub struct ProofOfStake;
+
+fn message(header: &BlockHeader) -> Vec<u8> {
+    [SIGNING_PREFIX, &header.hash().to_be_bytes()].concat()
+}
+
+impl ProofOfStake {
+    /// the slot `timestamp` falls in
+    pub fn slot(params: &ChainParams, timestamp: DateTime<Utc>) -> u64 {
+        u64::try_from(timestamp.timestamp()).unwrap_or(0) / params.ideal_block_time
+    }
+
+    /// when `slot` starts
+    pub fn slot_start(params: &ChainParams, slot: u64) -> DateTime<Utc> {
+        let seconds = slot.saturating_mul(params.ideal_block_time);
+        i64::try_from(seconds)
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    /// what every key holds in the chain's unspent outputs
+    pub fn stakes(blockchain: &Blockchain) -> BTreeMap<PublicKey, Amount> {
+        let mut stakes = BTreeMap::new();
+        for (output, _) in blockchain.utxos().values() {
+            let stake = stakes.entry(output.pubkey.clone()).or_insert(0u64);
+            *stake = stake.saturating_add(output.value.to_sat());
+        }
+        stakes
+            .into_iter()
+            .map(|(key, stake)| (key, Amount::from_sat(stake)))
+            .collect()
+    }
+
+    /// Who may make the block of `slot` on the chain's tip, `None` while nobody holds anything
+    pub fn slot_leader(blockchain: &Blockchain, slot: u64) -> Option<PublicKey> {
+        draw(&Self::stakes(blockchain), blockchain.tip_hash(), slot)
+    }
+
+    /// The first slot from the one `from` falls in that `key` leads, looking `slots` slots ahead.
+    /// Slots the tip's block took already don't count
+    pub fn next_slot(
+        blockchain: &Blockchain,
+        key: &PublicKey,
+        from: DateTime<Utc>,
+        slots: u64,
+    ) -> Option<u64> {
+        let params = blockchain.params();
+        let mut first = Self::slot(params, from);
+        if let Some(tip) = blockchain.blocks().last() {
+            first = first.max(Self::slot(params, tip.header.timestamp) + 1);
+        }
+        let stakes = Self::stakes(blockchain);
+        let tip = blockchain.tip_hash();
+        (first..first.saturating_add(slots))
+            .find(|slot| draw(&stakes, tip, *slot).as_ref() == Some(key))
+    }
+
+    /// sign `block` as the leader of the slot its timestamp falls in
+    pub fn sign(block: &mut Block, key: &PrivateKey) {
+        block.signature = Some(Box::new(BlockSignature {
+            key: key.public_key(),
+            signature: key.sign_message(&message(&block.header)),
+        }));
+    }
+}
+
+/// the leader of `slot` after `tip`, picked by a number from 0 to the total stake
+fn draw(stakes: &BTreeMap<PublicKey, Amount>, tip: Hash, slot: u64) -> Option<PublicKey> {
+    let total = stakes
+        .values()
+        .fold(0u64, |total, stake| total.saturating_add(stake.to_sat()));
+    if total == 0 {
+        return None;
+    }
+    let seed = Hash::hash(&(tip, slot));
+    let mut pick = U256::from_big_endian(&seed.to_be_bytes()) % U256::from(total);
+    for (key, stake) in stakes {
+        let stake = U256::from(stake.to_sat());
+        if pick < stake {
+            return Some(key.clone());
+        }
+        pick -= stake;
+    }
+    None
+}
+
+impl ConsensusEngine for ProofOfStake {
+    fn verify_seal(&self, blockchain: &Blockchain, block: &Block, _: U256) -> Result<()> {
+        let params = blockchain.params();
+        let slot = Self::slot(params, block.header.timestamp);
+        if let Some(tip) = blockchain.blocks().last()
+            && slot <= Self::slot(params, tip.header.timestamp)
+        {
+            return Err(BtcError::SlotTaken(slot));
+        }
+        let Some(signature) = &block.signature else {
+            return Err(BtcError::InvalidBlockSignature(block.header.hash()));
+        };
+        if Self::slot_leader(blockchain, slot).as_ref() != Some(&signature.key) {
+            return Err(BtcError::NotSlotLeader(slot));
+        }
+        if !signature
+            .key
+            .verify_message(&message(&block.header), &signature.signature)
+        {
+            return Err(BtcError::InvalidBlockSignature(block.header.hash()));
+        }
+        Ok(())
+    }
+
+    fn adjusts_target(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,23 @@
+use super::ConsensusEngine;
+use crate::{
+    U256,
+    error::{BtcError, Result},
+    types::{Block, Blockchain},
+};
+
+/// Blocks are found by grinding the nonce until the header's hash meets the target, which
+/// follows the block times every `difficulty_update_interval` blocks
+pub struct ProofOfWork;
+
+impl ConsensusEngine for ProofOfWork {
+    fn verify_seal(&self, _: &Blockchain, block: &Block, work_target: U256) -> Result<()> {
+        if !block.header.hash().matches_target(work_target) {
+            return Err(BtcError::InsufficientWork(block.header.hash()));
+        }
+        Ok(())
+    }
+
+    fn adjusts_target(&self) -> bool {
+        true
+    }
+}
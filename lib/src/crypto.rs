@@ -1,83 +1,484 @@
-use ecdsa::{
-    Signature as ECDSASignature, SigningKey, VerifyingKey, signature::Signer, signature::Verifier,
+//! Keys and signatures for every signature scheme the chain supports. Each output is locked to a
+//! public key of one scheme and can only be spent with a signature of that same scheme, which
+//! makes it easy to compare schemes side by side on the same chain.
+
+mod encoding;
+pub mod frost;
+pub mod keyfile;
+mod scheme;
+mod schnorr;
+
+pub use scheme::{Algorithm, Ed25519, Secp256k1Ecdsa, SignatureScheme};
+pub use schnorr::{
+    KeyAggregation, MuSigSession, PartialSignature, PublicNonce, Schnorr, SchnorrSignature,
+    SchnorrSigningKey, SchnorrVerifyingKey, SecretNonce, musig_nonce,
 };
+
+use ecdsa::{Signature as ECDSASignature, SigningKey, VerifyingKey};
 use k256::Secp256k1;
-use serde::{Deserialize, Serialize};
-use spki::EncodePublicKey;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use spki::{
+    DecodePublicKey, EncodePublicKey,
+    der::pem::{self, LineEnding},
+};
 
 use std::{
+    cmp::Ordering,
     fmt,
+    fs::File,
     io::{Error as IoError, Read, Result as IoResult, Write},
+    path::Path,
 };
 
-use crate::{sha256::Hash, util::Saveable};
+use crate::{
+    error::{self, BtcError},
+    sha256::Hash,
+    util::Saveable,
+};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Signature(ECDSASignature<Secp256k1>);
+const SCHNORR_PEM_LABEL: &str = "SCHNORR PUBLIC KEY";
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Ord, PartialOrd, Eq)]
-pub struct PublicKey(VerifyingKey<Secp256k1>);
+/// size of a compact secp256k1 public key, a compressed SEC1 point
+pub const COMPACT_PUBLIC_KEY_LEN: usize = 33;
+/// size of a compact ECDSA signature, `r || s`
+pub const COMPACT_SIGNATURE_LEN: usize = 64;
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct PrivateKey(#[serde(with = "signkey_serde")] SigningKey<Secp256k1>);
+/// prepended to anything signed with `PrivateKey::sign_message`, so a signed message can never be
+/// passed off as a signature over an output hash or anything else
+pub const SIGNED_MESSAGE_PREFIX: &[u8] = b"kme-btcrs signed message:";
 
-mod signkey_serde {
-    use serde::Deserialize;
+/// environment variable read for the passphrase of an encrypted private key before prompting
+pub const KEY_PASSPHRASE_ENV: &str = "BTCRS_KEY_PASSPHRASE";
 
-    pub fn serialize<S>(
-        key: &super::SigningKey<super::Secp256k1>,
-        serializer: S,
-    ) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        serializer.serialize_bytes(&key.to_bytes())
-    }
+#[derive(Clone, Debug)]
+pub enum Signature {
+    Secp256k1(ECDSASignature<Secp256k1>),
+    Ed25519(ed25519_dalek::Signature),
+    Schnorr(SchnorrSignature),
+}
 
-    pub fn deserialize<'de, D>(
-        deserializer: D,
-    ) -> Result<super::SigningKey<super::Secp256k1>, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let bytes: Vec<u8> = Vec::<u8>::deserialize(deserializer)?;
-        Ok(super::SigningKey::from_slice(&bytes).unwrap())
-    }
+#[derive(Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    Secp256k1(VerifyingKey<Secp256k1>),
+    Ed25519(ed25519_dalek::VerifyingKey),
+    Schnorr(SchnorrVerifyingKey),
+}
+
+#[derive(Clone)]
+pub enum PrivateKey {
+    Secp256k1(SigningKey<Secp256k1>),
+    Ed25519(ed25519_dalek::SigningKey),
+    Schnorr(SchnorrSigningKey),
 }
 
 impl PrivateKey {
+    /// generate a new secp256k1 key, the default scheme of the chain
     pub fn new_key() -> Self {
-        PrivateKey(SigningKey::random(&mut rand::thread_rng()))
+        Self::generate(Algorithm::Secp256k1)
+    }
+
+    pub fn generate(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Secp256k1 => PrivateKey::Secp256k1(Secp256k1Ecdsa::generate()),
+            Algorithm::Ed25519 => PrivateKey::Ed25519(Ed25519::generate()),
+            Algorithm::Schnorr => PrivateKey::Schnorr(Schnorr::generate()),
+        }
     }
 
     pub fn public_key(&self) -> PublicKey {
-        PublicKey(*self.0.verifying_key())
+        match self {
+            PrivateKey::Secp256k1(key) => PublicKey::Secp256k1(Secp256k1Ecdsa::public_key(key)),
+            PrivateKey::Ed25519(key) => PublicKey::Ed25519(Ed25519::public_key(key)),
+            PrivateKey::Schnorr(key) => PublicKey::Schnorr(Schnorr::public_key(key)),
+        }
+    }
+
+    /// rebuild a key from its raw secret bytes, mostly useful for fixed test keys
+    pub fn from_bytes(algorithm: Algorithm, bytes: &[u8]) -> error::Result<Self> {
+        match algorithm {
+            Algorithm::Secp256k1 => SigningKey::from_slice(bytes)
+                .ok()
+                .map(PrivateKey::Secp256k1),
+            Algorithm::Ed25519 => ed25519_dalek::SigningKey::try_from(bytes)
+                .ok()
+                .map(PrivateKey::Ed25519),
+            Algorithm::Schnorr => SchnorrSigningKey::from_bytes(bytes).map(PrivateKey::Schnorr),
+        }
+        .ok_or(BtcError::InvalidPrivateKey)
+    }
+
+    /// sign an arbitrary message to prove control of this key off-chain
+    pub fn sign_message(&self, message: &[u8]) -> Signature {
+        let message = [SIGNED_MESSAGE_PREFIX, message].concat();
+        match self {
+            PrivateKey::Secp256k1(key) => Signature::Secp256k1(Secp256k1Ecdsa::sign(key, &message)),
+            PrivateKey::Ed25519(key) => Signature::Ed25519(Ed25519::sign(key, &message)),
+            PrivateKey::Schnorr(key) => Signature::Schnorr(Schnorr::sign(key, &message)),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            PrivateKey::Secp256k1(_) => Algorithm::Secp256k1,
+            PrivateKey::Ed25519(_) => Algorithm::Ed25519,
+            PrivateKey::Schnorr(_) => Algorithm::Schnorr,
+        }
+    }
+}
+
+/// by the canonical encoding, so keys can be looked up in maps
+impl std::hash::Hash for PublicKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.to_compact().hash(state);
+    }
+}
+
+impl PublicKey {
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            PublicKey::Secp256k1(_) => Algorithm::Secp256k1,
+            PublicKey::Ed25519(_) => Algorithm::Ed25519,
+            PublicKey::Schnorr(_) => Algorithm::Schnorr,
+        }
+    }
+
+    /// raw key bytes: a compressed point for the secp256k1 schemes, 32 bytes for ed25519
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            PublicKey::Secp256k1(key) => key.to_encoded_point(true).as_bytes().to_vec(),
+            PublicKey::Ed25519(key) => key.to_bytes().to_vec(),
+            PublicKey::Schnorr(key) => key.to_bytes().to_vec(),
+        }
+    }
+
+    /// check a signature made with `PrivateKey::sign_message`
+    pub fn verify_message(&self, message: &[u8], signature: &Signature) -> bool {
+        let message = [SIGNED_MESSAGE_PREFIX, message].concat();
+        match (signature, self) {
+            (Signature::Secp256k1(signature), PublicKey::Secp256k1(key)) => {
+                Secp256k1Ecdsa::verify(key, &message, signature)
+            }
+            (Signature::Ed25519(signature), PublicKey::Ed25519(key)) => {
+                Ed25519::verify(key, &message, signature)
+            }
+            (Signature::Schnorr(signature), PublicKey::Schnorr(key)) => {
+                Schnorr::verify(key, &message, signature)
+            }
+            _ => false,
+        }
+    }
+
+    /// Canonical encoding used on chain and on the wire, `COMPACT_PUBLIC_KEY_LEN` bytes for
+    /// secp256k1 keys. See `encoding` for the other schemes.
+    pub fn to_compact(&self) -> Vec<u8> {
+        encoding::tagged(self.algorithm(), &self.to_bytes())
+    }
+
+    /// a key as `network::fingerprint` shows it, its compact form in hex
+    pub fn from_hex(s: &str) -> error::Result<Self> {
+        let bytes = hex::decode(s.trim()).map_err(|_| BtcError::InvalidPublicKey)?;
+        Self::from_compact(&bytes)
+    }
+
+    pub fn from_compact(bytes: &[u8]) -> error::Result<Self> {
+        match bytes {
+            [0x02 | 0x03, ..] if bytes.len() == COMPACT_PUBLIC_KEY_LEN => {
+                Self::from_raw(Algorithm::Secp256k1, bytes)
+            }
+            _ => encoding::untagged(bytes)
+                .and_then(|(algorithm, bytes)| Self::from_raw(algorithm, bytes)),
+        }
+        .ok_or(BtcError::InvalidPublicKey)
+    }
+
+    fn from_raw(algorithm: Algorithm, bytes: &[u8]) -> Option<Self> {
+        match algorithm {
+            Algorithm::Secp256k1 => VerifyingKey::from_sec1_bytes(bytes)
+                .ok()
+                .map(PublicKey::Secp256k1),
+            Algorithm::Ed25519 => ed25519_dalek::VerifyingKey::try_from(bytes)
+                .ok()
+                .map(PublicKey::Ed25519),
+            Algorithm::Schnorr => SchnorrVerifyingKey::from_bytes(bytes).map(PublicKey::Schnorr),
+        }
     }
 }
 
 impl Signature {
+    /// Signing is deterministic for every scheme: ECDSA derives its nonce with RFC6979, Ed25519
+    /// and Schnorr derive theirs by hashing the secret key with the message. The same key and
+    /// output hash always give the same signature, which is what `test_vectors` relies on.
     pub fn sign_output(output_hash: &Hash, private_key: &PrivateKey) -> Self {
-        let signature = private_key.0.sign(&output_hash.as_bytes());
-        Signature(signature)
+        let message = output_hash.as_bytes();
+        match private_key {
+            PrivateKey::Secp256k1(key) => Signature::Secp256k1(Secp256k1Ecdsa::sign(key, &message)),
+            PrivateKey::Ed25519(key) => Signature::Ed25519(Ed25519::sign(key, &message)),
+            PrivateKey::Schnorr(key) => Signature::Schnorr(Schnorr::sign(key, &message)),
+        }
     }
 
+    /// a signature only verifies against a public key of the same scheme
     pub fn verify(&self, output_hash: &Hash, public_key: &PublicKey) -> bool {
-        public_key
-            .0
-            .verify(&output_hash.as_bytes(), &self.0)
-            .is_ok()
+        let message = output_hash.as_bytes();
+        match (self, public_key) {
+            (Signature::Secp256k1(signature), PublicKey::Secp256k1(key)) => {
+                Secp256k1Ecdsa::verify(key, &message, signature)
+            }
+            (Signature::Ed25519(signature), PublicKey::Ed25519(key)) => {
+                Ed25519::verify(key, &message, signature)
+            }
+            // aggregated MuSig signatures verify here like any single signer one
+            (Signature::Schnorr(signature), PublicKey::Schnorr(key)) => {
+                Schnorr::verify(key, &message, signature)
+            }
+            _ => false,
+        }
+    }
+
+    /// raw signature bytes: `r || s` for ECDSA and Ed25519, compressed `R || s` for Schnorr
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Signature::Secp256k1(signature) => signature.to_bytes().to_vec(),
+            Signature::Ed25519(signature) => signature.to_bytes().to_vec(),
+            Signature::Schnorr(signature) => signature.to_bytes().to_vec(),
+        }
+    }
+
+    /// Canonical encoding used on chain and on the wire, `COMPACT_SIGNATURE_LEN` bytes for
+    /// ECDSA signatures. See `encoding` for the other schemes.
+    pub fn to_compact(&self) -> Vec<u8> {
+        encoding::tagged(self.algorithm(), &self.to_bytes())
+    }
+
+    pub fn from_compact(bytes: &[u8]) -> error::Result<Self> {
+        if bytes.len() == COMPACT_SIGNATURE_LEN {
+            Self::from_raw(Algorithm::Secp256k1, bytes)
+        } else {
+            encoding::untagged(bytes)
+                .and_then(|(algorithm, bytes)| Self::from_raw(algorithm, bytes))
+        }
+        .ok_or(BtcError::InvalidSignature)
+    }
+
+    fn from_raw(algorithm: Algorithm, bytes: &[u8]) -> Option<Self> {
+        match algorithm {
+            Algorithm::Secp256k1 => ECDSASignature::from_slice(bytes)
+                .ok()
+                .map(Signature::Secp256k1),
+            Algorithm::Ed25519 => ed25519_dalek::Signature::from_slice(bytes)
+                .ok()
+                .map(Signature::Ed25519),
+            Algorithm::Schnorr => SchnorrSignature::from_bytes(bytes).map(Signature::Schnorr),
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            Signature::Secp256k1(_) => Algorithm::Secp256k1,
+            Signature::Ed25519(_) => Algorithm::Ed25519,
+            Signature::Schnorr(_) => Algorithm::Schnorr,
+        }
     }
 }
 
-impl Saveable for PrivateKey {
-    fn load<I: Read>(reader: I) -> IoResult<Self> {
-        ciborium::de::from_reader(reader).map_err(|_| {
+impl Serialize for Signature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        encoding::serialize_compact(&self.to_compact(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        encoding::deserialize(
+            deserializer,
+            "signature",
+            Some(|bytes| Signature::from_compact(bytes).ok()),
+            |bytes| {
+                ECDSASignature::from_slice(bytes)
+                    .ok()
+                    .map(Signature::Secp256k1)
+            },
+            Signature::from_raw,
+        )
+    }
+}
+
+impl Serialize for PublicKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        encoding::serialize_compact(&self.to_compact(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        encoding::deserialize(
+            deserializer,
+            "public key",
+            Some(|bytes| PublicKey::from_compact(bytes).ok()),
+            |bytes| {
+                VerifyingKey::from_public_key_der(bytes)
+                    .ok()
+                    .map(PublicKey::Secp256k1)
+            },
+            |algorithm, bytes| match algorithm {
+                Algorithm::Secp256k1 => VerifyingKey::from_public_key_der(bytes)
+                    .ok()
+                    .map(PublicKey::Secp256k1),
+                _ => PublicKey::from_raw(algorithm, bytes),
+            },
+        )
+    }
+}
+
+impl Serialize for PrivateKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            PrivateKey::Secp256k1(key) => serializer.serialize_bytes(&key.to_bytes()),
+            PrivateKey::Ed25519(key) => {
+                encoding::serialize_tagged(Algorithm::Ed25519, key.as_bytes(), serializer)
+            }
+            PrivateKey::Schnorr(key) => {
+                encoding::serialize_tagged(Algorithm::Schnorr, &key.to_bytes(), serializer)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PrivateKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        encoding::deserialize(
+            deserializer,
+            "private key",
+            None,
+            |bytes| {
+                SigningKey::from_slice(bytes)
+                    .ok()
+                    .map(PrivateKey::Secp256k1)
+            },
+            |algorithm, bytes| match algorithm {
+                Algorithm::Secp256k1 => SigningKey::from_slice(bytes)
+                    .ok()
+                    .map(PrivateKey::Secp256k1),
+                Algorithm::Ed25519 => ed25519_dalek::SigningKey::try_from(bytes)
+                    .ok()
+                    .map(PrivateKey::Ed25519),
+                Algorithm::Schnorr => SchnorrSigningKey::from_bytes(bytes).map(PrivateKey::Schnorr),
+            },
+        )
+    }
+}
+
+impl PrivateKey {
+    /// like `save` but seals the key with a passphrase, see `keyfile` for the format
+    pub fn save_encrypted<O: Write>(&self, mut writer: O, passphrase: &str) -> IoResult<()> {
+        let mut plaintext = vec![];
+        self.save(&mut plaintext)?;
+        writer.write_all(&keyfile::encrypt(&plaintext, passphrase))
+    }
+
+    pub fn save_encrypted_to_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        passphrase: &str,
+    ) -> IoResult<()> {
+        let file = File::create(&path)?;
+        self.save_encrypted(file, passphrase)
+    }
+
+    /// Like `save_encrypted`, with `decoy` in the same file for `decoy_passphrase` to unlock
+    /// instead, see `keyfile`
+    pub fn save_encrypted_with_decoy<O: Write>(
+        &self,
+        mut writer: O,
+        passphrase: &str,
+        decoy: &PrivateKey,
+        decoy_passphrase: &str,
+    ) -> IoResult<()> {
+        if passphrase == decoy_passphrase {
+            return Err(IoError::new(
+                std::io::ErrorKind::InvalidInput,
+                "The decoy needs a passphrase of its own",
+            ));
+        }
+        let (mut plaintext, mut decoy_plaintext) = (vec![], vec![]);
+        self.save(&mut plaintext)?;
+        decoy.save(&mut decoy_plaintext)?;
+        let data =
+            keyfile::encrypt_with_decoy(&plaintext, passphrase, &decoy_plaintext, decoy_passphrase)
+                .ok_or_else(|| {
+                    IoError::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "PrivateKey is too long for a key file with a decoy",
+                    )
+                })?;
+        writer.write_all(&data)
+    }
+
+    pub fn save_encrypted_with_decoy_to_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        passphrase: &str,
+        decoy: &PrivateKey,
+        decoy_passphrase: &str,
+    ) -> IoResult<()> {
+        let file = File::create(&path)?;
+        self.save_encrypted_with_decoy(file, passphrase, decoy, decoy_passphrase)
+    }
+
+    /// load a key that may or may not be encrypted without prompting for anything
+    pub fn load_with_passphrase<I: Read>(mut reader: I, passphrase: &str) -> IoResult<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        if keyfile::is_encrypted(&data) {
+            Self::decrypt(&data, passphrase)
+        } else {
+            Self::decode(&data)
+        }
+    }
+
+    pub fn load_from_file_with_passphrase<P: AsRef<Path>>(
+        path: P,
+        passphrase: &str,
+    ) -> IoResult<Self> {
+        let file = File::open(&path)?;
+        Self::load_with_passphrase(file, passphrase)
+    }
+
+    fn decrypt(data: &[u8], passphrase: &str) -> IoResult<Self> {
+        let plaintext = keyfile::decrypt(data, passphrase).ok_or_else(|| {
+            IoError::new(
+                std::io::ErrorKind::InvalidData,
+                "Failed to decrypt PrivateKey, wrong passphrase?",
+            )
+        })?;
+        Self::decode(&plaintext)
+    }
+
+    fn decode(data: &[u8]) -> IoResult<Self> {
+        ciborium::de::from_reader(data).map_err(|_| {
             IoError::new(
                 std::io::ErrorKind::InvalidData,
                 "Failed to deserialize PrivateKey",
             )
         })
     }
+}
+
+impl Saveable for PrivateKey {
+    /// Encrypted keys take their passphrase from `KEY_PASSPHRASE_ENV` when it is set and prompt
+    /// for it on the terminal otherwise
+    fn load<I: Read>(mut reader: I) -> IoResult<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        if !keyfile::is_encrypted(&data) {
+            return Self::decode(&data);
+        }
+        let passphrase = match std::env::var(KEY_PASSPHRASE_ENV) {
+            Ok(passphrase) => passphrase,
+            Err(_) => rpassword::prompt_password("Private key passphrase: ")?,
+        };
+        Self::decrypt(&data, &passphrase)
+    }
 
     fn save<O: Write>(&self, writer: O) -> IoResult<()> {
         ciborium::ser::into_writer(self, writer).map_err(|_| {
@@ -90,28 +491,48 @@ impl Saveable for PrivateKey {
     }
 }
 
+/// PEM, for other tools to read as well, so without a footer
 impl Saveable for PublicKey {
+    const FOOTER: bool = false;
+
     fn load<I: Read>(mut reader: I) -> IoResult<Self> {
         // read PEM-encoded public key into string
         let mut buf = String::new();
         reader.read_to_string(&mut buf)?;
 
-        // decode the public key from PEM
-        let public_key = buf.parse().map_err(|_| {
+        // decode the public key from PEM, the OID inside tells which scheme it belongs to.
+        // Schnorr keys share their curve OID with ECDSA ones so they get a label of their own
+        if let Ok(public_key) = buf.parse() {
+            return Ok(PublicKey::Secp256k1(public_key));
+        }
+        if let Ok((SCHNORR_PEM_LABEL, bytes)) = pem::decode_vec(buf.as_bytes())
+            && let Some(public_key) = SchnorrVerifyingKey::from_bytes(&bytes)
+        {
+            return Ok(PublicKey::Schnorr(public_key));
+        }
+        let public_key = ed25519_dalek::VerifyingKey::from_public_key_pem(&buf).map_err(|_| {
             IoError::new(
                 std::io::ErrorKind::InvalidData,
-                "Failed to serialize PrivateKey",
+                "Failed to deserialize PublicKey",
             )
         })?;
 
-        Ok(PublicKey(public_key))
+        Ok(PublicKey::Ed25519(public_key))
     }
 
     fn save<O: Write>(&self, mut writer: O) -> IoResult<()> {
-        let s = self.0.to_public_key_pem(Default::default()).map_err(|_| {
+        let s = match self {
+            PublicKey::Secp256k1(key) => key.to_public_key_pem(Default::default()),
+            PublicKey::Ed25519(key) => key.to_public_key_pem(Default::default()),
+            PublicKey::Schnorr(key) => {
+                pem::encode_string(SCHNORR_PEM_LABEL, LineEnding::default(), &key.to_bytes())
+                    .map_err(Into::into)
+            }
+        }
+        .map_err(|_| {
             IoError::new(
                 std::io::ErrorKind::InvalidData,
-                "Failed to serialize PrivateKey",
+                "Failed to serialize PublicKey",
             )
         })?;
         writer.write_all(s.as_bytes())?;
@@ -119,24 +540,38 @@ impl Saveable for PublicKey {
     }
 }
 
+impl PartialOrd for PublicKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PublicKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.algorithm()
+            .cmp(&other.algorithm())
+            .then_with(|| self.to_bytes().cmp(&other.to_bytes()))
+    }
+}
+
 impl fmt::Display for PublicKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let encoded = self.0.to_encoded_point(true);
-        write!(f, "PublicKey({})", hex::encode(encoded.as_bytes()))
+        match self.algorithm() {
+            Algorithm::Secp256k1 => write!(f, "PublicKey({})", hex::encode(self.to_bytes())),
+            algorithm => write!(f, "PublicKey({algorithm}:{})", hex::encode(self.to_bytes())),
+        }
     }
 }
 
 impl fmt::Debug for PublicKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let encoded = self.0.to_encoded_point(true);
-        write!(f, "PublicKey({})", hex::encode(encoded.as_bytes()))
+        fmt::Display::fmt(self, f)
     }
 }
 
 impl fmt::Debug for PrivateKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let matching_key = self.0.verifying_key();
-        let encoded = matching_key.to_encoded_point(true);
-        write!(f, "MatchingKey({})", hex::encode(encoded.as_bytes()))
+        let matching_key = self.public_key();
+        write!(f, "MatchingKey({})", hex::encode(matching_key.to_bytes()))
     }
 }
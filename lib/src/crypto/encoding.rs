@@ -0,0 +1,150 @@
+//! Canonical encoding of keys and signatures, used for hashing on chain and on the wire.
+//!
+//! secp256k1 ECDSA, the default scheme, uses its plain compact forms: a 33 byte compressed public
+//! key and a 64 byte `r || s` signature. Every other scheme puts a one byte tag in front of its
+//! raw bytes, which never collides with the `0x02`/`0x03` prefix of a compressed point:
+//!
+//! | scheme  | tag  | public key | signature |
+//! |---------|------|------------|-----------|
+//! | ecdsa   | none | 33         | 64        |
+//! | ed25519 | 0x10 | 1 + 32     | 1 + 64    |
+//! | schnorr | 0x11 | 1 + 33     | 1 + 65    |
+//!
+//! Human readable formats get the same bytes hex encoded. Older encodings are still accepted when
+//! decoding, so key files keep loading: secp256k1 keys as DER and signatures as a sequence of
+//! bytes, other schemes as a single entry map from their `Algorithm` to the raw bytes.
+//! Hashes are always taken over the compact encoding though, so blocks saved with an older one no
+//! longer link up and have to be fetched again.
+
+use serde::{
+    Deserializer, Serializer,
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::SerializeMap,
+};
+use serde_bytes::{ByteBuf, Bytes};
+
+use std::fmt;
+
+use super::Algorithm;
+
+type Decoder<T> = fn(&[u8]) -> Option<T>;
+
+const ED25519_TAG: u8 = 0x10;
+const SCHNORR_TAG: u8 = 0x11;
+
+/// prefix `bytes` with the tag of `algorithm`, if it has one
+pub(super) fn tagged(algorithm: Algorithm, bytes: &[u8]) -> Vec<u8> {
+    let tag = match algorithm {
+        Algorithm::Secp256k1 => return bytes.to_vec(),
+        Algorithm::Ed25519 => ED25519_TAG,
+        Algorithm::Schnorr => SCHNORR_TAG,
+    };
+    [&[tag], bytes].concat()
+}
+
+/// split a tagged encoding back into its algorithm and raw bytes
+pub(super) fn untagged(bytes: &[u8]) -> Option<(Algorithm, &[u8])> {
+    let (tag, rest) = bytes.split_first()?;
+    match *tag {
+        ED25519_TAG => Some((Algorithm::Ed25519, rest)),
+        SCHNORR_TAG => Some((Algorithm::Schnorr, rest)),
+        _ => None,
+    }
+}
+
+pub(super) fn serialize_compact<S: Serializer>(
+    bytes: &[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&hex::encode(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+pub(super) fn serialize_tagged<S>(
+    algorithm: Algorithm,
+    bytes: &[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(&algorithm, Bytes::new(bytes))?;
+    map.end()
+}
+
+/// Decode the compact form if there is one, falling back to the legacy secp256k1 form (byte
+/// string, sequence of bytes or hex string) or the tagged map produced by `serialize_tagged`
+pub(super) fn deserialize<'de, D, T>(
+    deserializer: D,
+    expecting: &'static str,
+    compact: Option<Decoder<T>>,
+    legacy: Decoder<T>,
+    tagged: fn(Algorithm, &[u8]) -> Option<T>,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(EncodingVisitor {
+        expecting,
+        compact,
+        legacy,
+        tagged,
+    })
+}
+
+struct EncodingVisitor<T> {
+    expecting: &'static str,
+    compact: Option<Decoder<T>>,
+    legacy: Decoder<T>,
+    tagged: fn(Algorithm, &[u8]) -> Option<T>,
+}
+
+impl<T> EncodingVisitor<T> {
+    fn decode_bytes<E: de::Error>(&self, bytes: &[u8]) -> Result<T, E> {
+        self.compact
+            .and_then(|compact| compact(bytes))
+            .or_else(|| (self.legacy)(bytes))
+            .ok_or_else(|| E::custom(format!("invalid {}", self.expecting)))
+    }
+
+    fn decode_legacy<E: de::Error>(&self, bytes: &[u8]) -> Result<T, E> {
+        (self.legacy)(bytes).ok_or_else(|| E::custom(format!("invalid {}", self.expecting)))
+    }
+}
+
+impl<'de, T> Visitor<'de> for EncodingVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expecting)
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<T, E> {
+        self.decode_bytes(bytes)
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<T, E> {
+        let bytes = hex::decode(s).map_err(E::custom)?;
+        self.decode_bytes(&bytes)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<T, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        self.decode_legacy(&bytes)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let Some((algorithm, bytes)) = map.next_entry::<Algorithm, ByteBuf>()? else {
+            return Err(de::Error::custom(format!("empty {}", self.expecting)));
+        };
+        (self.tagged)(algorithm, &bytes)
+            .ok_or_else(|| de::Error::custom(format!("invalid {algorithm} {}", self.expecting)))
+    }
+}
@@ -0,0 +1,431 @@
+//! FROST threshold Schnorr signatures: n participants share one key and any t of them can sign
+//! for it, which is what a shared treasury needs.
+//!
+//! Keys come out of a distributed key generation (DKG) ceremony so the group secret is never held
+//! in one place:
+//!
+//! 1. every participant samples a random polynomial of degree t-1 and publishes commitments to
+//!    its coefficients, with a proof they know the constant term (`dkg_round1`)
+//! 2. every participant evaluates its polynomial for everyone else and hands out the shares
+//!    privately (`DkgSecret::round2`)
+//! 3. every participant checks the shares it got against the commitments and adds them up into
+//!    its `KeyShare` (`DkgSecret::finish`)
+//!
+//! Signing takes two rounds as well: signers publish nonce commitments (`frost_commit`), then
+//! each produces a signature share (`FrostSession::sign`) and anyone can add the shares up into a
+//! plain Schnorr signature for the group key (`FrostSession::aggregate`). On chain a threshold
+//! spend looks exactly like a single signer Schnorr spend.
+
+use k256::{NonZeroScalar, ProjectivePoint, Scalar};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{BtcError, Result},
+    sha256::Hash,
+};
+
+use super::{
+    PublicKey, SchnorrSignature, SchnorrVerifyingKey, Signature,
+    schnorr::{Point, challenge, hash_to_scalar},
+};
+
+const PROOF_TAG: &[u8] = b"kme-btcrs/frost/proof";
+const BINDING_TAG: &[u8] = b"kme-btcrs/frost/binding";
+
+/// Participants are numbered from 1, their number is the point their share is evaluated at
+pub type ParticipantId = u16;
+
+/// Secret state a participant keeps between DKG rounds, never to be shared
+#[derive(Serialize, Deserialize)]
+pub struct DkgSecret {
+    id: ParticipantId,
+    threshold: u16,
+    participants: u16,
+    coefficients: Vec<Scalar>,
+}
+
+/// Broadcast to every other participant in the first DKG round
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DkgCommitment {
+    pub id: ParticipantId,
+    commitments: Vec<Point>,
+    proof: (Point, Scalar),
+}
+
+/// Sent privately from one participant to another in the second DKG round
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DkgShare {
+    pub from: ParticipantId,
+    pub to: ParticipantId,
+    value: Scalar,
+}
+
+/// What everyone, signers or not, needs to check signature shares for a group key
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GroupKey {
+    threshold: u16,
+    key: Point,
+    verifying_shares: Vec<(ParticipantId, Point)>,
+}
+
+/// A participant's share of the group secret, the result of a successful DKG
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KeyShare {
+    id: ParticipantId,
+    secret: Scalar,
+    group: GroupKey,
+}
+
+/// Secret nonces for one signing session. Reusing them leaks the key share, so they are consumed
+/// by `FrostSession::sign` and cannot be cloned.
+#[derive(Serialize, Deserialize)]
+pub struct SigningNonces {
+    id: ParticipantId,
+    hiding: Scalar,
+    binding: Scalar,
+}
+
+/// Public half of `SigningNonces`, sent to whoever coordinates the signing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SigningCommitment {
+    pub id: ParticipantId,
+    hiding: Point,
+    binding: Point,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SignatureShare {
+    pub id: ParticipantId,
+    z: Scalar,
+}
+
+/// First DKG round for participant `id` out of `participants`, `threshold` of whom will be needed
+/// to sign
+pub fn dkg_round1(
+    id: ParticipantId,
+    threshold: u16,
+    participants: u16,
+) -> Result<(DkgSecret, DkgCommitment)> {
+    if threshold == 0 || threshold > participants || id == 0 || id > participants {
+        return Err(BtcError::InvalidKeyShare);
+    }
+    let mut rng = rand::thread_rng();
+    // no zero coefficients, so none of the commitments is the identity
+    let coefficients: Vec<NonZeroScalar> = (0..threshold)
+        .map(|_| NonZeroScalar::random(&mut rng))
+        .collect();
+    let commitments: Vec<Point> = coefficients.iter().map(Point::base_times).collect();
+    let coefficients: Vec<Scalar> = coefficients.into_iter().map(|a| *a).collect();
+
+    // prove knowledge of the constant term so nobody can pick theirs to cancel the others out
+    let k = NonZeroScalar::random(&mut rng);
+    let r = Point::base_times(&k);
+    let c = proof_challenge(id, &commitments[0], &r);
+    let proof = (r, *k + coefficients[0] * c);
+
+    let secret = DkgSecret {
+        id,
+        threshold,
+        participants,
+        coefficients,
+    };
+    Ok((
+        secret,
+        DkgCommitment {
+            id,
+            commitments,
+            proof,
+        },
+    ))
+}
+
+impl DkgSecret {
+    pub fn id(&self) -> ParticipantId {
+        self.id
+    }
+
+    /// Check everyone's commitments and compute the share each other participant gets
+    pub fn round2(&self, commitments: &[DkgCommitment]) -> Result<Vec<DkgShare>> {
+        self.check_commitments(commitments)?;
+        Ok((1..=self.participants)
+            .filter(|to| *to != self.id)
+            .map(|to| DkgShare {
+                from: self.id,
+                to,
+                value: evaluate(&self.coefficients, to),
+            })
+            .collect())
+    }
+
+    /// Check the shares sent to us against their senders' commitments and combine them into our
+    /// key share
+    pub fn finish(self, commitments: &[DkgCommitment], shares: &[DkgShare]) -> Result<KeyShare> {
+        self.check_commitments(commitments)?;
+        let mut secret = evaluate(&self.coefficients, self.id);
+        for commitment in commitments.iter().filter(|c| c.id != self.id) {
+            let share = shares
+                .iter()
+                .find(|share| share.from == commitment.id && share.to == self.id)
+                .ok_or(BtcError::InvalidKeyShare)?;
+            if ProjectivePoint::GENERATOR * share.value
+                != evaluate_commitments(&commitment.commitments, self.id)
+            {
+                return Err(BtcError::InvalidKeyShare);
+            }
+            secret += share.value;
+        }
+
+        let key = commitments
+            .iter()
+            .fold(ProjectivePoint::IDENTITY, |acc, c| {
+                acc + c.commitments[0].projective()
+            });
+        let key = Point::new(key).ok_or(BtcError::InvalidKeyShare)?;
+        // everyone's public share is computable from the commitments alone
+        let verifying_shares = (1..=self.participants)
+            .map(|id| {
+                let share = commitments
+                    .iter()
+                    .fold(ProjectivePoint::IDENTITY, |acc, c| {
+                        acc + evaluate_commitments(&c.commitments, id)
+                    });
+                Ok((id, Point::new(share).ok_or(BtcError::InvalidKeyShare)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(KeyShare {
+            id: self.id,
+            secret,
+            group: GroupKey {
+                threshold: self.threshold,
+                key,
+                verifying_shares,
+            },
+        })
+    }
+
+    fn check_commitments(&self, commitments: &[DkgCommitment]) -> Result<()> {
+        let mut ids: Vec<ParticipantId> = commitments.iter().map(|c| c.id).collect();
+        ids.sort();
+        if ids != (1..=self.participants).collect::<Vec<_>>() {
+            return Err(BtcError::InvalidKeyShare);
+        }
+        for commitment in commitments {
+            if commitment.commitments.len() != self.threshold as usize {
+                return Err(BtcError::InvalidKeyShare);
+            }
+            let (r, mu) = commitment.proof;
+            let c = proof_challenge(commitment.id, &commitment.commitments[0], &r);
+            if ProjectivePoint::GENERATOR * mu
+                != r.projective() + commitment.commitments[0].projective() * c
+            {
+                return Err(BtcError::InvalidKeyShare);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GroupKey {
+    /// the key outputs controlled by the group get locked to
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::Schnorr(SchnorrVerifyingKey(self.key))
+    }
+
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+
+    fn verifying_share(&self, id: ParticipantId) -> Option<Point> {
+        self.verifying_shares
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, share)| *share)
+    }
+}
+
+impl KeyShare {
+    pub fn id(&self) -> ParticipantId {
+        self.id
+    }
+
+    pub fn group(&self) -> &GroupKey {
+        &self.group
+    }
+}
+
+/// First signing round: generate nonces for one session and the commitment to share
+pub fn frost_commit(share: &KeyShare) -> (SigningNonces, SigningCommitment) {
+    let mut rng = rand::thread_rng();
+    let (hiding, binding) = (
+        NonZeroScalar::random(&mut rng),
+        NonZeroScalar::random(&mut rng),
+    );
+    let commitment = SigningCommitment {
+        id: share.id,
+        hiding: Point::base_times(&hiding),
+        binding: Point::base_times(&binding),
+    };
+    let nonces = SigningNonces {
+        id: share.id,
+        hiding: *hiding,
+        binding: *binding,
+    };
+    (nonces, commitment)
+}
+
+/// Second round state for one output hash, identical for every signer once the commitments of
+/// the chosen signers are known
+pub struct FrostSession {
+    group: GroupKey,
+    commitments: Vec<SigningCommitment>,
+    binding_factors: Vec<Scalar>,
+    r: Point,
+    e: Scalar,
+}
+
+impl FrostSession {
+    /// `commitments` decide who signs, there have to be at least `threshold` of them
+    pub fn new(
+        group: GroupKey,
+        mut commitments: Vec<SigningCommitment>,
+        output_hash: &Hash,
+    ) -> Result<Self> {
+        let message = &output_hash.as_bytes();
+        commitments.sort_by_key(|c| c.id);
+        commitments.dedup_by_key(|c| c.id);
+        if commitments.len() < group.threshold as usize
+            || commitments
+                .iter()
+                .any(|c| group.verifying_share(c.id).is_none())
+        {
+            return Err(BtcError::InvalidSignature);
+        }
+
+        // bind every signer's nonce to the whole commitment list and the message
+        let encoded: Vec<u8> = commitments
+            .iter()
+            .flat_map(|c| {
+                [
+                    c.id.to_be_bytes().as_slice(),
+                    &c.hiding.to_bytes(),
+                    &c.binding.to_bytes(),
+                ]
+                .concat()
+            })
+            .collect();
+        let binding_factors: Vec<Scalar> = commitments
+            .iter()
+            .map(|c| {
+                hash_to_scalar(
+                    BINDING_TAG,
+                    &[
+                        &group.key.to_bytes(),
+                        message,
+                        &encoded,
+                        &c.id.to_be_bytes(),
+                    ],
+                )
+            })
+            .collect();
+        let r = commitments
+            .iter()
+            .zip(&binding_factors)
+            .fold(ProjectivePoint::IDENTITY, |acc, (c, rho)| {
+                acc + c.hiding.projective() + c.binding.projective() * rho
+            });
+        // commitments picked to cancel each other out
+        let r = Point::new(r).ok_or(BtcError::InvalidSignature)?;
+        let e = challenge(&r, &group.key, message);
+        Ok(FrostSession {
+            group,
+            commitments,
+            binding_factors,
+            r,
+            e,
+        })
+    }
+
+    pub fn sign(&self, share: &KeyShare, nonces: SigningNonces) -> Result<SignatureShare> {
+        let i = self.position(share.id).ok_or(BtcError::InvalidKeyShare)?;
+        if nonces.id != share.id || share.group.key != self.group.key {
+            return Err(BtcError::InvalidKeyShare);
+        }
+        let z = nonces.hiding
+            + nonces.binding * self.binding_factors[i]
+            + self.lagrange(share.id) * share.secret * self.e;
+        Ok(SignatureShare { id: share.id, z })
+    }
+
+    /// check one signer's contribution, so a bad share can be blamed on its signer
+    pub fn verify_share(&self, share: &SignatureShare) -> bool {
+        let (Some(i), Some(public)) = (
+            self.position(share.id),
+            self.group.verifying_share(share.id),
+        ) else {
+            return false;
+        };
+        let commitment = &self.commitments[i];
+        ProjectivePoint::GENERATOR * share.z
+            == commitment.hiding.projective()
+                + commitment.binding.projective() * self.binding_factors[i]
+                + public.projective() * (self.lagrange(share.id) * self.e)
+    }
+
+    /// combine the signature shares of every signer in the session into a Schnorr signature for
+    /// the group key
+    pub fn aggregate(&self, shares: &[SignatureShare]) -> Result<Signature> {
+        if shares.len() != self.commitments.len()
+            || self
+                .commitments
+                .iter()
+                .any(|c| !shares.iter().any(|s| s.id == c.id))
+            || !shares.iter().all(|share| self.verify_share(share))
+        {
+            return Err(BtcError::InvalidSignature);
+        }
+        let s = shares.iter().fold(Scalar::ZERO, |acc, share| acc + share.z);
+        Ok(Signature::Schnorr(SchnorrSignature { r: self.r, s }))
+    }
+
+    fn position(&self, id: ParticipantId) -> Option<usize> {
+        self.commitments.iter().position(|c| c.id == id)
+    }
+
+    /// Lagrange coefficient interpolating the group secret at 0 from this session's signers
+    fn lagrange(&self, id: ParticipantId) -> Scalar {
+        let x = Scalar::from(id as u64);
+        let (num, den) = self.commitments.iter().filter(|c| c.id != id).fold(
+            (Scalar::ONE, Scalar::ONE),
+            |(num, den), c| {
+                let xj = Scalar::from(c.id as u64);
+                (num * xj, den * (xj - x))
+            },
+        );
+        num * den.invert().expect("BUG: Impossible")
+    }
+}
+
+fn proof_challenge(id: ParticipantId, commitment: &Point, r: &Point) -> Scalar {
+    hash_to_scalar(
+        PROOF_TAG,
+        &[&id.to_be_bytes(), &commitment.to_bytes(), &r.to_bytes()],
+    )
+}
+
+/// evaluate the polynomial with the given coefficients at `x`
+fn evaluate(coefficients: &[Scalar], x: ParticipantId) -> Scalar {
+    let x = Scalar::from(x as u64);
+    coefficients
+        .iter()
+        .rev()
+        .fold(Scalar::ZERO, |acc, a| acc * x + a)
+}
+
+/// same as `evaluate` but on the commitments to the coefficients
+fn evaluate_commitments(commitments: &[Point], x: ParticipantId) -> ProjectivePoint {
+    let x = Scalar::from(x as u64);
+    commitments
+        .iter()
+        .rev()
+        .fold(ProjectivePoint::IDENTITY, |acc, a| acc * x + a.projective())
+}
@@ -0,0 +1,179 @@
+//! Passphrase protected private key files. The layout is
+//!
+//! `BTCRS-ENCRYPTED-KEY` | version | argon2id salt (16) | nonce (12) | ciphertext
+//!
+//! where the ciphertext is the regular CBOR encoding of the key sealed with ChaCha20-Poly1305,
+//! using everything before it as associated data. The key is derived from the passphrase with
+//! argon2id and its default parameters. Other files are sealed the same way with `seal`, under a
+//! magic of their own.
+//!
+//! A key file can also hold a decoy key next to the real one, for when someone forces the owner
+//! to unlock it: `BTCRS-ENCRYPTED-KEY` | 2 | slot | slot, each slot being salt | nonce |
+//! ciphertext of the padded key under a passphrase of its own. The slots are the same size and
+//! in random order, so nothing but what the keys hold tells the duress passphrase from the real
+//! one. The version does give away that there may be a second key.
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    ChaCha20Poly1305, KeyInit,
+    aead::{Aead, Payload},
+};
+use rand::RngCore;
+
+/// header every encrypted key file starts with, plain CBOR keys never do
+pub const ENCRYPTED_KEY_MAGIC: &[u8] = b"BTCRS-ENCRYPTED-KEY";
+pub const ENCRYPTED_KEY_VERSION: u8 = 1;
+/// version of key files with a decoy slot
+pub const DECOY_KEY_VERSION: u8 = 2;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+/// longest key a slot holds, the length and the key are padded to this with zeros
+const SLOT_PLAINTEXT_LEN: usize = 256;
+const SLOT_LEN: usize = SALT_LEN + NONCE_LEN + 2 + SLOT_PLAINTEXT_LEN + TAG_LEN;
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENCRYPTED_KEY_MAGIC)
+}
+
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Vec<u8> {
+    seal(ENCRYPTED_KEY_MAGIC, plaintext, passphrase)
+}
+
+/// `None` if the data is not an encrypted key, has an unknown version or the passphrase is wrong.
+/// For a key file with a decoy, whichever of the two keys `passphrase` opens
+pub fn decrypt(data: &[u8], passphrase: &str) -> Option<Vec<u8>> {
+    if data.get(ENCRYPTED_KEY_MAGIC.len()) == Some(&DECOY_KEY_VERSION) {
+        return open_slots(data, passphrase);
+    }
+    open(ENCRYPTED_KEY_MAGIC, data, passphrase)
+}
+
+/// Seal `plaintext` under `passphrase` and `decoy` under `decoy_passphrase` in one key file.
+/// `None` if either is longer than a slot holds
+pub fn encrypt_with_decoy(
+    plaintext: &[u8],
+    passphrase: &str,
+    decoy: &[u8],
+    decoy_passphrase: &str,
+) -> Option<Vec<u8>> {
+    let mut header = ENCRYPTED_KEY_MAGIC.to_vec();
+    header.push(DECOY_KEY_VERSION);
+    let mut slots = [
+        seal_slot(&header, plaintext, passphrase)?,
+        seal_slot(&header, decoy, decoy_passphrase)?,
+    ];
+    if rand::random() {
+        slots.swap(0, 1);
+    }
+    Some([header, slots.concat()].concat())
+}
+
+fn seal_slot(header: &[u8], plaintext: &[u8], passphrase: &str) -> Option<Vec<u8>> {
+    if plaintext.len() > SLOT_PLAINTEXT_LEN {
+        return None;
+    }
+    let mut padded = (plaintext.len() as u16).to_be_bytes().to_vec();
+    padded.extend_from_slice(plaintext);
+    padded.resize(2 + SLOT_PLAINTEXT_LEN, 0);
+
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+    let payload = Payload {
+        msg: &padded,
+        aad: header,
+    };
+    let ciphertext = cipher(passphrase, &salt)
+        .encrypt(&nonce.into(), payload)
+        .expect("BUG: Impossible");
+    Some([&salt[..], &nonce, &ciphertext].concat())
+}
+
+fn open_slots(data: &[u8], passphrase: &str) -> Option<Vec<u8>> {
+    let header_len = ENCRYPTED_KEY_MAGIC.len() + 1;
+    if data.len() != header_len + 2 * SLOT_LEN {
+        return None;
+    }
+    let (header, slots) = data.split_at(header_len);
+    // both are tried so it takes as long whichever one opens
+    let opened: Vec<Vec<u8>> = slots
+        .chunks(SLOT_LEN)
+        .filter_map(|slot| open_slot(header, slot, passphrase))
+        .collect();
+    opened.into_iter().next()
+}
+
+fn open_slot(header: &[u8], slot: &[u8], passphrase: &str) -> Option<Vec<u8>> {
+    let (salt, rest) = slot.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    let padded = cipher(passphrase, salt)
+        .decrypt(nonce.into(), payload)
+        .ok()?;
+    let len = u16::from_be_bytes([padded[0], padded[1]]) as usize;
+    padded.get(2..2 + len).map(<[u8]>::to_vec)
+}
+
+fn header_len(magic: &[u8]) -> usize {
+    magic.len() + 1 + SALT_LEN + NONCE_LEN
+}
+
+/// `encrypt` with `magic` in place of `ENCRYPTED_KEY_MAGIC`
+pub fn seal(magic: &[u8], plaintext: &[u8], passphrase: &str) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+
+    let mut data = Vec::with_capacity(header_len(magic) + plaintext.len() + 16);
+    data.extend_from_slice(magic);
+    data.push(ENCRYPTED_KEY_VERSION);
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+
+    let cipher = cipher(passphrase, &salt);
+    let payload = Payload {
+        msg: plaintext,
+        aad: &data,
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce.into(), payload)
+        .expect("BUG: Impossible");
+    data.extend_from_slice(&ciphertext);
+    data
+}
+
+/// `decrypt` for data sealed with `seal` under `magic`
+pub fn open(magic: &[u8], data: &[u8], passphrase: &str) -> Option<Vec<u8>> {
+    if !data.starts_with(magic) || data.len() < header_len(magic) {
+        return None;
+    }
+    let (header, ciphertext) = data.split_at(header_len(magic));
+    let (version, rest) = header[magic.len()..].split_first()?;
+    if *version != ENCRYPTED_KEY_VERSION {
+        return None;
+    }
+    let (salt, nonce) = rest.split_at(SALT_LEN);
+
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    cipher(passphrase, salt).decrypt(nonce.into(), payload).ok()
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .expect("BUG: Impossible");
+    ChaCha20Poly1305::new(&key.into())
+}
@@ -0,0 +1,109 @@
+use ecdsa::{
+    Signature as ECDSASignature, SigningKey, VerifyingKey, signature::Signer, signature::Verifier,
+};
+use k256::Secp256k1;
+use serde::{Deserialize, Serialize};
+
+use std::{fmt, str::FromStr};
+
+/// Tag identifying which signature scheme a key or signature belongs to. An output is spendable
+/// with whatever scheme its public key was generated with.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub enum Algorithm {
+    /// ECDSA over secp256k1, what Bitcoin used before taproot
+    Secp256k1,
+    /// EdDSA over Curve25519, deterministic and faster to verify
+    Ed25519,
+    /// Schnorr over secp256k1, keys and signatures can be aggregated for n-of-n multisig
+    Schnorr,
+}
+
+/// A signature scheme the chain can use to lock outputs. Implementations only deal with raw
+/// messages, hashing what gets signed is the job of the caller.
+pub trait SignatureScheme {
+    const ALGORITHM: Algorithm;
+    type KeyPair;
+    type PublicKey;
+    type Signature;
+
+    /// generate a fresh random key pair
+    fn generate() -> Self::KeyPair;
+    fn public_key(key_pair: &Self::KeyPair) -> Self::PublicKey;
+    fn sign(key_pair: &Self::KeyPair, message: &[u8]) -> Self::Signature;
+    fn verify(public_key: &Self::PublicKey, message: &[u8], signature: &Self::Signature) -> bool;
+}
+
+pub struct Secp256k1Ecdsa;
+
+impl SignatureScheme for Secp256k1Ecdsa {
+    const ALGORITHM: Algorithm = Algorithm::Secp256k1;
+    type KeyPair = SigningKey<Secp256k1>;
+    type PublicKey = VerifyingKey<Secp256k1>;
+    type Signature = ECDSASignature<Secp256k1>;
+
+    fn generate() -> Self::KeyPair {
+        SigningKey::random(&mut rand::thread_rng())
+    }
+
+    fn public_key(key_pair: &Self::KeyPair) -> Self::PublicKey {
+        *key_pair.verifying_key()
+    }
+
+    fn sign(key_pair: &Self::KeyPair, message: &[u8]) -> Self::Signature {
+        key_pair.sign(message)
+    }
+
+    fn verify(public_key: &Self::PublicKey, message: &[u8], signature: &Self::Signature) -> bool {
+        public_key.verify(message, signature).is_ok()
+    }
+}
+
+pub struct Ed25519;
+
+impl SignatureScheme for Ed25519 {
+    const ALGORITHM: Algorithm = Algorithm::Ed25519;
+    type KeyPair = ed25519_dalek::SigningKey;
+    type PublicKey = ed25519_dalek::VerifyingKey;
+    type Signature = ed25519_dalek::Signature;
+
+    fn generate() -> Self::KeyPair {
+        ed25519_dalek::SigningKey::generate(&mut rand::thread_rng())
+    }
+
+    fn public_key(key_pair: &Self::KeyPair) -> Self::PublicKey {
+        key_pair.verifying_key()
+    }
+
+    fn sign(key_pair: &Self::KeyPair, message: &[u8]) -> Self::Signature {
+        key_pair.sign(message)
+    }
+
+    fn verify(public_key: &Self::PublicKey, message: &[u8], signature: &Self::Signature) -> bool {
+        // strict verification rejects small order keys and malleable signatures
+        public_key.verify_strict(message, signature).is_ok()
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::Secp256k1 => write!(f, "secp256k1"),
+            Algorithm::Ed25519 => write!(f, "ed25519"),
+            Algorithm::Schnorr => write!(f, "schnorr"),
+        }
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "secp256k1" | "ecdsa" => Ok(Algorithm::Secp256k1),
+            "ed25519" => Ok(Algorithm::Ed25519),
+            "schnorr" => Ok(Algorithm::Schnorr),
+            _ => Err(format!("unknown signature algorithm: {s}")),
+        }
+    }
+}
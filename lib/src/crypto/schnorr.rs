@@ -0,0 +1,392 @@
+//! Textbook Schnorr signatures over secp256k1, plus MuSig2 style key and signature aggregation.
+//!
+//! A signature is a nonce point `R` and a scalar `s` with `s*G = R + e*X`, where `e` commits to
+//! `R`, the public key `X` and the message. Because the equation is linear, n signers can add up
+//! their keys into one aggregate key and their partial signatures into one signature, so an n-of-n
+//! multisig spend looks exactly like a single signer spend on chain.
+//!
+//! This is not BIP340: points are kept whole (33 byte compressed encoding) instead of x-only, which
+//! keeps the math free of the even-y bookkeeping and easier to follow.
+
+use k256::{
+    AffinePoint, NonZeroScalar, ProjectivePoint, Scalar, U256,
+    elliptic_curve::{PrimeField, ops::Reduce, sec1::ToEncodedPoint},
+    sha2::{Digest, Sha256},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use serde_bytes::ByteBuf;
+
+use crate::{
+    error::{BtcError, Result},
+    sha256::Hash,
+};
+
+use super::{Algorithm, PrivateKey, PublicKey, Signature, SignatureScheme, encoding};
+
+const CHALLENGE_TAG: &[u8] = b"kme-btcrs/schnorr/challenge";
+const NONCE_TAG: &[u8] = b"kme-btcrs/schnorr/nonce";
+const KEY_AGG_LIST_TAG: &[u8] = b"kme-btcrs/musig/keys";
+const KEY_AGG_COEFF_TAG: &[u8] = b"kme-btcrs/musig/coefficient";
+const NONCE_COEFF_TAG: &[u8] = b"kme-btcrs/musig/nonce";
+
+#[derive(Clone)]
+pub struct SchnorrSigningKey {
+    secret: NonZeroScalar,
+    public: SchnorrVerifyingKey,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SchnorrVerifyingKey(pub(super) Point);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SchnorrSignature {
+    pub(super) r: Point,
+    pub(super) s: Scalar,
+}
+
+/// A curve point other than the identity, which is all a key, nonce or commitment can be. The
+/// identity has no 33 byte compressed encoding, so only these go into hashes and over the wire,
+/// and decoding takes nothing else
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Point(AffinePoint);
+
+pub struct Schnorr;
+
+impl SignatureScheme for Schnorr {
+    const ALGORITHM: Algorithm = Algorithm::Schnorr;
+    type KeyPair = SchnorrSigningKey;
+    type PublicKey = SchnorrVerifyingKey;
+    type Signature = SchnorrSignature;
+
+    fn generate() -> Self::KeyPair {
+        SchnorrSigningKey::from_scalar(NonZeroScalar::random(&mut rand::thread_rng()))
+    }
+
+    fn public_key(key_pair: &Self::KeyPair) -> Self::PublicKey {
+        key_pair.public
+    }
+
+    fn sign(key_pair: &Self::KeyPair, message: &[u8]) -> Self::Signature {
+        // derive the nonce from the secret and the message so it is never reused for two
+        // different messages and signing needs no randomness
+        let k = deterministic_nonce(&key_pair.secret, message);
+        let r = Point::base_times(&k);
+        let e = challenge(&r, &key_pair.public.0, message);
+        SchnorrSignature {
+            r,
+            s: *k + e * *key_pair.secret,
+        }
+    }
+
+    fn verify(public_key: &Self::PublicKey, message: &[u8], signature: &Self::Signature) -> bool {
+        let e = challenge(&signature.r, &public_key.0, message);
+        ProjectivePoint::GENERATOR * signature.s
+            == signature.r.projective() + public_key.0.projective() * e
+    }
+}
+
+impl SchnorrSigningKey {
+    fn from_scalar(secret: NonZeroScalar) -> Self {
+        let public = SchnorrVerifyingKey(Point::base_times(&secret));
+        SchnorrSigningKey { secret, public }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; 32] = bytes.try_into().ok()?;
+        let secret = Option::<NonZeroScalar>::from(NonZeroScalar::from_repr(bytes.into()))?;
+        Some(Self::from_scalar(secret))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.secret.to_repr().into()
+    }
+}
+
+impl SchnorrVerifyingKey {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Point::from_bytes(bytes).map(SchnorrVerifyingKey)
+    }
+
+    /// 33 byte compressed point
+    pub fn to_bytes(&self) -> [u8; 33] {
+        self.0.to_bytes()
+    }
+}
+
+impl SchnorrSignature {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 65 {
+            return None;
+        }
+        let r = Point::from_bytes(&bytes[..33])?;
+        let s: [u8; 32] = bytes[33..].try_into().ok()?;
+        let s = Option::<Scalar>::from(Scalar::from_repr(s.into()))?;
+        Some(SchnorrSignature { r, s })
+    }
+
+    /// compressed nonce point followed by the scalar, 65 bytes
+    pub fn to_bytes(&self) -> [u8; 65] {
+        let mut bytes = [0u8; 65];
+        bytes[..33].copy_from_slice(&self.r.to_bytes());
+        bytes[33..].copy_from_slice(&self.s.to_repr());
+        bytes
+    }
+}
+
+impl Point {
+    /// `None` for the identity
+    pub(super) fn new(point: ProjectivePoint) -> Option<Self> {
+        (point != ProjectivePoint::IDENTITY).then(|| Point(point.to_affine()))
+    }
+
+    /// `k*G`, which a non-zero `k` keeps off the identity
+    pub(super) fn base_times(k: &NonZeroScalar) -> Self {
+        Point((ProjectivePoint::GENERATOR * **k).to_affine())
+    }
+
+    /// exactly the 33 byte compressed encoding
+    pub(super) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 33 {
+            return None;
+        }
+        let key = k256::PublicKey::from_sec1_bytes(bytes).ok()?;
+        Some(Point(*key.as_affine()))
+    }
+
+    pub(super) fn to_bytes(self) -> [u8; 33] {
+        let mut bytes = [0u8; 33];
+        bytes.copy_from_slice(self.0.to_encoded_point(true).as_bytes());
+        bytes
+    }
+
+    pub(super) fn projective(&self) -> ProjectivePoint {
+        ProjectivePoint::from(self.0)
+    }
+}
+
+impl Serialize for Point {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        encoding::serialize_compact(&self.to_bytes(), serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Point {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let bytes = if deserializer.is_human_readable() {
+            hex::decode(String::deserialize(deserializer)?).map_err(de::Error::custom)?
+        } else {
+            ByteBuf::deserialize(deserializer)?.into_vec()
+        };
+        Point::from_bytes(&bytes).ok_or_else(|| {
+            de::Error::custom("expected a 33 byte compressed point other than the identity")
+        })
+    }
+}
+
+/// The aggregate of several Schnorr public keys. Each key is weighted by a coefficient bound to
+/// the whole key list, so no participant can pick their key to cancel out the others'.
+#[derive(Clone)]
+pub struct KeyAggregation {
+    keys: Vec<SchnorrVerifyingKey>,
+    coefficients: Vec<Scalar>,
+    aggregate: SchnorrVerifyingKey,
+}
+
+impl KeyAggregation {
+    /// every key has to be a Schnorr key
+    pub fn new(keys: &[PublicKey]) -> Result<Self> {
+        let mut keys = keys
+            .iter()
+            .map(|key| match key {
+                PublicKey::Schnorr(key) => Ok(*key),
+                _ => Err(BtcError::InvalidPublicKey),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if keys.is_empty() {
+            return Err(BtcError::InvalidPublicKey);
+        }
+        // sort so every participant derives the same aggregate regardless of input order
+        keys.sort_by_key(|key| key.to_bytes());
+
+        let encoded: Vec<[u8; 33]> = keys.iter().map(|key| key.to_bytes()).collect();
+        let list_hash = Sha256::new()
+            .chain_update(KEY_AGG_LIST_TAG)
+            .chain_update(encoded.concat())
+            .finalize();
+
+        let coefficients: Vec<Scalar> = encoded
+            .iter()
+            .map(|key| hash_to_scalar(KEY_AGG_COEFF_TAG, &[&list_hash, key]))
+            .collect();
+        let aggregate = keys
+            .iter()
+            .zip(&coefficients)
+            .fold(ProjectivePoint::IDENTITY, |acc, (key, a)| {
+                acc + key.0.projective() * a
+            });
+        let aggregate = Point::new(aggregate).ok_or(BtcError::InvalidPublicKey)?;
+
+        Ok(KeyAggregation {
+            keys,
+            coefficients,
+            aggregate: SchnorrVerifyingKey(aggregate),
+        })
+    }
+
+    /// the key outputs get locked to
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::Schnorr(self.aggregate)
+    }
+
+    /// the participant keys, in the order `MuSigSession::new` expects their nonces
+    pub fn keys(&self) -> Vec<PublicKey> {
+        self.keys.iter().copied().map(PublicKey::Schnorr).collect()
+    }
+
+    fn coefficient(&self, key: &SchnorrVerifyingKey) -> Option<Scalar> {
+        self.keys
+            .iter()
+            .position(|k| k == key)
+            .map(|i| self.coefficients[i])
+    }
+}
+
+/// Secret half of a signer's nonce pair. Using it twice leaks the private key, which is why it is
+/// consumed by `MuSigSession::partial_sign` and cannot be cloned.
+pub struct SecretNonce([NonZeroScalar; 2]);
+
+/// Public half of a signer's nonce pair, sent to every other signer in the first round
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PublicNonce([Point; 2]);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartialSignature(Scalar);
+
+/// First round: every signer generates a fresh nonce pair and shares the public half
+pub fn musig_nonce() -> (SecretNonce, PublicNonce) {
+    let mut rng = rand::thread_rng();
+    let secret = [
+        NonZeroScalar::random(&mut rng),
+        NonZeroScalar::random(&mut rng),
+    ];
+    let public = secret.map(|k| Point::base_times(&k));
+    (SecretNonce(secret), PublicNonce(public))
+}
+
+/// Second round state, identical for every signer once all public nonces are known
+pub struct MuSigSession {
+    aggregation: KeyAggregation,
+    nonces: Vec<PublicNonce>,
+    b: Scalar,
+    r: Point,
+    e: Scalar,
+}
+
+impl MuSigSession {
+    /// `nonces` must be in the same order as `KeyAggregation::keys`. Nonces adding up to the
+    /// identity are refused, a signer could pick theirs to get there
+    pub fn new(
+        aggregation: KeyAggregation,
+        nonces: Vec<PublicNonce>,
+        output_hash: &Hash,
+    ) -> Result<Self> {
+        let message = &output_hash.as_bytes();
+        if nonces.len() != aggregation.keys.len() {
+            return Err(BtcError::InvalidSignature);
+        }
+        let (r1, r2) = nonces.iter().fold(
+            (ProjectivePoint::IDENTITY, ProjectivePoint::IDENTITY),
+            |(r1, r2), n| (r1 + n.0[0].projective(), r2 + n.0[1].projective()),
+        );
+        let r1 = Point::new(r1).ok_or(BtcError::InvalidSignature)?;
+        let r2 = Point::new(r2).ok_or(BtcError::InvalidSignature)?;
+        let b = hash_to_scalar(
+            NONCE_COEFF_TAG,
+            &[
+                &aggregation.aggregate.to_bytes(),
+                &r1.to_bytes(),
+                &r2.to_bytes(),
+                message,
+            ],
+        );
+        let r =
+            Point::new(r1.projective() + r2.projective() * b).ok_or(BtcError::InvalidSignature)?;
+        let e = challenge(&r, &aggregation.aggregate.0, message);
+        Ok(MuSigSession {
+            aggregation,
+            nonces,
+            b,
+            r,
+            e,
+        })
+    }
+
+    pub fn partial_sign(&self, key: &PrivateKey, nonce: SecretNonce) -> Result<PartialSignature> {
+        let PrivateKey::Schnorr(key) = key else {
+            return Err(BtcError::InvalidPrivateKey);
+        };
+        let a = self
+            .aggregation
+            .coefficient(&key.public)
+            .ok_or(BtcError::InvalidPrivateKey)?;
+        let [k1, k2] = nonce.0;
+        Ok(PartialSignature(
+            *k1 + self.b * *k2 + self.e * a * *key.secret,
+        ))
+    }
+
+    /// check one signer's contribution, so a bad partial signature can be blamed on its signer
+    pub fn verify_partial(&self, key: &PublicKey, partial: &PartialSignature) -> bool {
+        let PublicKey::Schnorr(key) = key else {
+            return false;
+        };
+        let Some(i) = self.aggregation.keys.iter().position(|k| k == key) else {
+            return false;
+        };
+        let a = self.aggregation.coefficients[i];
+        let [r1, r2] = self.nonces[i].0;
+        ProjectivePoint::GENERATOR * partial.0
+            == r1.projective() + r2.projective() * self.b + key.0.projective() * (self.e * a)
+    }
+
+    /// combine every signer's partial signature into a plain Schnorr signature for the aggregate
+    /// key
+    pub fn aggregate(&self, partials: &[PartialSignature]) -> Result<Signature> {
+        if partials.len() != self.aggregation.keys.len() {
+            return Err(BtcError::InvalidSignature);
+        }
+        let s = partials.iter().fold(Scalar::ZERO, |acc, p| acc + p.0);
+        Ok(Signature::Schnorr(SchnorrSignature { r: self.r, s }))
+    }
+}
+
+pub(super) fn challenge(r: &Point, public_key: &Point, message: &[u8]) -> Scalar {
+    hash_to_scalar(
+        CHALLENGE_TAG,
+        &[&r.to_bytes(), &public_key.to_bytes(), message],
+    )
+}
+
+pub(super) fn hash_to_scalar(tag: &[u8], parts: &[&[u8]]) -> Scalar {
+    let mut hasher = Sha256::new().chain_update(tag);
+    for part in parts {
+        hasher.update(part);
+    }
+    <Scalar as Reduce<U256>>::reduce_bytes(&hasher.finalize())
+}
+
+fn deterministic_nonce(secret: &NonZeroScalar, message: &[u8]) -> NonZeroScalar {
+    // a hash reducing to zero is astronomically unlikely, retry with a counter if it ever does
+    (0..=u8::MAX)
+        .find_map(|counter| {
+            let k = hash_to_scalar(NONCE_TAG, &[&secret.to_repr(), message, &[counter]]);
+            Option::from(NonZeroScalar::new(k))
+        })
+        .expect("BUG: Impossible")
+}
+
+impl std::fmt::Debug for SchnorrVerifyingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SchnorrVerifyingKey({})", hex::encode(self.to_bytes()))
+    }
+}
@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use thiserror::Error;
 
+use crate::{U256, sha256::Hash, types::Amount};
+
 #[derive(Error, Debug)]
 pub enum BtcError {
     #[error("Invalid transaction")]
@@ -22,6 +25,129 @@ pub enum BtcError {
     InvalidPublicKey,
     #[error("Invalid private key")]
     InvalidPrivateKey,
+    #[error("Invalid threshold key share")]
+    InvalidKeyShare,
+    #[error("Invalid chain spec: {0}")]
+    InvalidChainSpec(String),
+    #[error("Invalid block file: {0}")]
+    InvalidBlockFile(String),
+    #[error("Invalid chain generator settings: {0}")]
+    InvalidChainGen(String),
+    #[error("Invalid raw transaction: {0}")]
+    InvalidRawTransaction(String),
+    #[error("Invalid invoice: {0}")]
+    InvalidInvoice(String),
+    #[error("Invalid payout: {0}")]
+    InvalidPayout(String),
+    #[error("Invalid package: {0}")]
+    InvalidPackage(String),
+    #[error("Untrusted node: {0}")]
+    UntrustedNode(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("File has format version {found}, this build only understands up to {supported}")]
+    UnsupportedFormatVersion { found: u16, supported: u16 },
+    #[error("File is corrupted: {0}")]
+    CorruptFile(String),
+
+    // transaction validation
+    #[error("Input spends unknown output {0}")]
+    MissingUtxo(Hash),
+    #[error("Output {0} is spent more than once")]
+    DoubleSpend(Hash),
+    #[error("Output {0} is created more than once")]
+    DuplicateOutput(Hash),
+    #[error("Bad signature on input {input} of transaction {transaction}")]
+    BadSignature { transaction: Hash, input: usize },
+    #[error("Transaction {transaction} spends {inputs} but creates {outputs}")]
+    InsufficientInputs {
+        transaction: Hash,
+        inputs: Amount,
+        outputs: Amount,
+    },
+    #[error("Values in transaction {0} overflow")]
+    ValueOverflow(Hash),
+    #[error("Transaction {transaction} creates {value}, more than the {max} that can exist")]
+    AboveMaxMoney {
+        transaction: Hash,
+        value: Amount,
+        max: Amount,
+    },
+    #[error("Memo of transaction {transaction} has {len} bytes, at most {max} are allowed")]
+    MemoTooLong {
+        transaction: Hash,
+        len: usize,
+        max: usize,
+    },
+
+    #[error("Transaction {transaction} would depend on more than {max} mempool transactions")]
+    TooManyMempoolAncestors { transaction: Hash, max: usize },
+    #[error("More than {max} mempool transactions would depend on transaction {transaction}")]
+    TooManyMempoolDescendants { transaction: Hash, max: usize },
+    #[error("Transaction {transaction} has {size} bytes, at most {max} are allowed")]
+    TransactionTooLarge {
+        transaction: Hash,
+        size: usize,
+        max: usize,
+    },
+    #[error("Transaction {transaction} pays {fee}, at least {min} is required")]
+    FeeTooLow {
+        transaction: Hash,
+        fee: Amount,
+        min: Amount,
+    },
+    #[error("Mempool is full at {max} bytes, transaction {transaction} doesn't pay enough to fit")]
+    MempoolFull { transaction: Hash, max: usize },
+
+    // block validation
+    #[error("Block has no transactions")]
+    EmptyBlock,
+    #[error("Block has no coinbase transaction")]
+    MissingCoinbase,
+    #[error("Block has {count} transactions, at most {max} are allowed")]
+    OversizedBlock { count: usize, max: usize },
+    #[error("Block builds on {actual} but the chain tip is {expected}")]
+    PrevHashMismatch { expected: Hash, actual: Hash },
+    #[error("Block hash {0} does not meet its target")]
+    InsufficientWork(Hash),
+    #[error("Block has target {actual:x} but the chain is at {expected:x}")]
+    TargetMismatch { expected: U256, actual: U256 },
+    #[error("Block {0} isn't signed by its slot's leader")]
+    InvalidBlockSignature(Hash),
+    #[error("Only the leader of slot {0} may make its block")]
+    NotSlotLeader(u64),
+    #[error("Slot {0} has its block already")]
+    SlotTaken(u64),
+    #[error("Block timestamp {timestamp} is not after the previous block's {previous}")]
+    TimestampTooOld {
+        timestamp: DateTime<Utc>,
+        previous: DateTime<Utc>,
+    },
+    #[error("Block timestamp {timestamp} is too far ahead of the network's {now}")]
+    TimestampTooFarAhead {
+        timestamp: DateTime<Utc>,
+        now: DateTime<Utc>,
+    },
+    #[error("Block {0} was marked invalid")]
+    InvalidatedBlock(Hash),
+    #[error("Block {0} is not in the chain nor was it taken off it")]
+    UnknownBlock(Hash),
+    #[error("Blocks from {height} on can't come off the chain, block {checkpoint} is checkpointed")]
+    BelowCheckpoint { height: u64, checkpoint: u64 },
+    #[error("Invalid checkpoint: {0}")]
+    InvalidCheckpoint(String),
+    #[error("Coinbase transaction must have no inputs")]
+    CoinbaseHasInputs,
+    #[error("Coinbase transaction has no outputs")]
+    CoinbaseWithoutOutputs,
+    #[error("Coinbase pays {actual}, expected reward plus fees of {expected}")]
+    CoinbaseValueMismatch { expected: Amount, actual: Amount },
+
+    // supply audit
+    #[error("Unspent outputs hold more than MAX_MONEY")]
+    SupplyOverflow,
+    #[error("Unspent outputs hold {actual}, the emission schedule issued {expected}")]
+    SupplyMismatch { expected: Amount, actual: Amount },
 }
 
 pub type Result<T> = std::result::Result<T, BtcError>;
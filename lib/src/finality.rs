@@ -0,0 +1,101 @@
+//! When a block, and every transaction in it, counts as final: it's not expected to come off the
+//! chain again, so a payment in it can be treated as settled. By default that's once enough
+//! blocks are on top of it, on a chain with checkpoint authorities a checkpoint settles everything
+//! up to it at once, see `SignedCheckpoint`.
+//!
+//! Wallets pick theirs in the config, e.g. `finality = { confirmations = 10 }` or
+//! `finality = { checkpoints = 6 }`, nodes with `--final-confirmations` and
+//! `--checkpoint-finality`.
+
+use crate::{network::NodeStatus, types::Blockchain};
+
+use serde::{Deserialize, Serialize};
+
+/// What finality is judged on, all a wallet has to know about the chain
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChainTip {
+    /// blocks on the chain
+    pub height: u64,
+    /// height of the latest checkpointed block
+    pub checkpoint: Option<u64>,
+}
+
+impl From<&Blockchain> for ChainTip {
+    fn from(blockchain: &Blockchain) -> Self {
+        ChainTip {
+            height: blockchain.block_height(),
+            checkpoint: blockchain.checkpoint().map(|checkpoint| checkpoint.height),
+        }
+    }
+}
+
+impl From<&NodeStatus> for ChainTip {
+    fn from(status: &NodeStatus) -> Self {
+        ChainTip {
+            height: status.height,
+            checkpoint: status.checkpoint,
+        }
+    }
+}
+
+pub trait FinalityPolicy: Send + Sync {
+    /// the height of the highest final block, `None` while there's none
+    fn finalized_height(&self, tip: &ChainTip) -> Option<u64>;
+
+    fn is_final(&self, height: u64, tip: &ChainTip) -> bool {
+        self.finalized_height(tip)
+            .is_some_and(|finalized| height <= finalized)
+    }
+}
+
+/// Final once the block and the ones on top of it are this many, at least the block itself
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Confirmations(pub u64);
+
+impl Default for Confirmations {
+    fn default() -> Self {
+        Confirmations(crate::DEFAULT_FINAL_CONFIRMATIONS)
+    }
+}
+
+impl FinalityPolicy for Confirmations {
+    fn finalized_height(&self, tip: &ChainTip) -> Option<u64> {
+        tip.height.checked_sub(self.0.max(1))
+    }
+}
+
+/// Final as soon as it's checkpointed, or once it has the confirmations if that's sooner
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Checkpointed(pub Confirmations);
+
+impl FinalityPolicy for Checkpointed {
+    fn finalized_height(&self, tip: &ChainTip) -> Option<u64> {
+        self.0.finalized_height(tip).max(tip.checkpoint)
+    }
+}
+
+/// Which policy to go by, as configured
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Finality {
+    Confirmations(u64),
+    /// see `Checkpointed`, with the confirmations for blocks no checkpoint covers yet
+    Checkpoints(u64),
+}
+
+impl Default for Finality {
+    fn default() -> Self {
+        Finality::Confirmations(crate::DEFAULT_FINAL_CONFIRMATIONS)
+    }
+}
+
+impl Finality {
+    pub fn policy(&self) -> Box<dyn FinalityPolicy> {
+        match *self {
+            Finality::Confirmations(confirmations) => Box::new(Confirmations(confirmations)),
+            Finality::Checkpoints(confirmations) => {
+                Box::new(Checkpointed(Confirmations(confirmations)))
+            }
+        }
+    }
+}
@@ -1,7 +1,17 @@
+pub mod chain_params;
+pub mod chaingen;
+pub mod consensus;
 pub mod crypto;
 pub mod error;
+pub mod finality;
 pub mod network;
+pub mod output;
 pub mod sha256;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod test_vectors;
+#[cfg(feature = "testing")]
+mod testing;
 pub mod types;
 pub mod util;
 
@@ -15,6 +25,8 @@ construct_uint! {
     pub struct U256(4);
 }
 
+// consensus constants of the default network, see `chain_params` to run with others
+
 /// initial reward in bitcoin - multiply by 10^8 to get satoshis
 pub const INITIAL_REWARD: u64 = 50;
 /// Halving interval in blocks
@@ -22,10 +34,55 @@ pub const HALVING_INTERVAL: u64 = 210;
 /// Ideal block time in seconds
 pub const IDEAL_BLOCK_TIME: u64 = 10;
 pub const DIFFICULTY_UPDATE_INTERVAL: u64 = 50;
-/// maximum mempool transaction age in seconds
+/// how long a mempool transaction waits to be mined in seconds, unless the node's
+/// `MempoolPolicy` says otherwise
 pub const MAX_MEMPOOL_TRANSACTION_AGE: u64 = 600;
+/// most unconfirmed transactions a mempool transaction may depend on, counting itself
+pub const MAX_MEMPOOL_ANCESTORS: usize = 25;
+/// most unconfirmed transactions that may depend on a mempool transaction, counting itself
+pub const MAX_MEMPOOL_DESCENDANTS: usize = 25;
+/// most transactions submitted together as a package, a longer chain couldn't be in the mempool
+/// anyway
+pub const MAX_PACKAGE_TRANSACTIONS: usize = MAX_MEMPOOL_ANCESTORS;
+/// biggest transaction, in bytes, a node takes into its mempool unless configured otherwise
+pub const DEFAULT_MAX_TRANSACTION_SIZE: usize = 100_000;
+/// bytes of transactions a node keeps in its mempool unless configured otherwise
+pub const DEFAULT_MAX_MEMPOOL_SIZE: usize = 300_000_000;
+/// most blocks a node looks back through to answer `FetchUTXOsSince`, wallets further behind get
+/// all their outputs again
+pub const MAX_UTXO_DIFF_BLOCKS: u64 = 500;
+/// most headers a node sends for one `FetchHeaderRange`
+pub const MAX_HEADER_RANGE: u32 = 2000;
+/// most transactions a node lists for one `FetchMempool`, the best paying ones
+pub const MAX_MEMPOOL_LISTING: usize = 10_000;
+/// most windows one `GetChainStats` is answered with, longer windows are used past it
+pub const MAX_STATS_WINDOWS: u64 = 1000;
+/// most keys a node lists in its rich list
+pub const MAX_RICH_LIST: usize = 1000;
+/// most blocks a regtest node mines for one `GenerateBlocks`, it holds the chain the whole time
+pub const MAX_GENERATED_BLOCKS: u32 = 1000;
+/// blocks between calls to the progress callback of `Blockchain::rebuild_utxos_with_progress`
+pub const REBUILD_PROGRESS_STEP: u64 = 10_000;
+/// blocks at the tip a node checks when it loads its chain, see `Blockchain::self_check`
+pub const SELF_CHECK_DEPTH: u64 = 100;
 /// maximum amount of transactions allowed in a block
 pub const BLOCK_TRANSACTION_CAP: usize = 20;
+/// most keys a `Payout` splits a block's reward between
+pub const MAX_PAYOUT_SPLITS: usize = 16;
+/// No amount on any chain may exceed bitcoin's 21 million, in satoshis. Chains usually stay well
+/// below it, see `ChainParams::max_money`
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+/// seconds a block's timestamp may be ahead of the network-adjusted time
+pub const MAX_FUTURE_BLOCK_TIME: i64 = 2 * 60 * 60;
+/// most seconds a node moves its clock to agree with its peers, see `network::NetworkClock`
+pub const MAX_TIME_ADJUSTMENT: i64 = 70 * 60;
+/// seconds the local clock may be off from the network's before the node warns about it
+pub const MAX_CLOCK_SKEW: i64 = 5 * 60;
+/// blocks on top of a block, counting itself, before it's final unless a wallet or node is set
+/// up otherwise, see `finality`
+pub const DEFAULT_FINAL_CONFIRMATIONS: u64 = 6;
+/// maximum size of a transaction memo in bytes
+pub const MAX_MEMO_LEN: usize = 80;
 /// Difficulty to mine a block
 pub const MIN_TARGET: U256 = U256([
     0xFFFF_FFFF_FFFF_FFFF,
@@ -33,3 +90,6 @@ pub const MIN_TARGET: U256 = U256([
     0xFFFF_FFFF_FFFF_FFFF,
     0x0000_0FFF_FFFF_FFFF,
 ]);
+/// Trivial target used on regtest so any hash satisfies it and blocks are found instantly. Only
+/// chains with `ChainParams::regtest` set take blocks at it
+pub const REGTEST_TARGET: U256 = U256::MAX;
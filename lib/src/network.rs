@@ -1,24 +1,71 @@
+mod bloom;
+mod capabilities;
+mod clock;
+mod codec;
+mod compression;
+mod envelope;
+mod identity;
+mod memory;
+mod pipeline;
+mod status;
+mod traffic;
+
+pub use bloom::{
+    BloomFilter, MAX_BLOOM_FILTER_SIZE, MAX_BLOOM_HASH_FUNCS, MAX_FILTER_ADD_SIZE, MerkleBlock,
+};
+pub use capabilities::Capabilities;
+pub use clock::{MAX_CLOCK_SAMPLES, MIN_CLOCK_SAMPLES, NetworkClock};
+pub use codec::{Cbor, Codec, CodecError, Encoding, Json, PROTOCOL_VERSION};
+pub use compression::{COMPRESSION_THRESHOLD, Compression, CompressionMeter, CompressionStats};
+pub use envelope::{Envelope, KNOWN_KINDS};
+pub use identity::{NodeIdentity, Nonce, check_identity, fingerprint, new_nonce};
+pub use memory::{MemoryMeter, MemoryUsage, PeerMemory, Reservation};
+pub use pipeline::{MAX_MESSAGE_SIZE, MessageReader, Outbox};
+pub use status::{NodeStatus, Propagation, TaskStatus};
+pub use traffic::{Throttle, Traffic, TrafficMeter, TrafficStats};
+
 use crate::{
+    U256,
     crypto::PublicKey,
-    types::{Block, Transaction, TransactionOutput},
+    error::BtcError,
+    sha256::Hash,
+    types::{
+        AddressActivity, AddressScan, Amount, Block, BlockHeader, ChainStats, DoubleSpend,
+        EvictionReason, MempoolAcceptance, MempoolPolicy, MempoolTxInfo, Payout, SignedCheckpoint,
+        Transaction, TransactionOutput, UtxoDiff,
+    },
 };
 
 use std::io::{Error as IoError, Read, Write};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub enum Message {
     /// Fetch all UTXOs belonging to a owner/wallet/public key. That's how we are going to know how
     /// much satoshis we have
     FetchUTXOs(PublicKey),
     /// UTXOs belonging to a public key. Bool determines if marked (Already spent)
     UTXOs(Vec<(bool, TransactionOutput)>),
+    /// How a key's UTXOs changed since the chain had the given number of blocks
+    FetchUTXOsSince(PublicKey, u64),
+    /// Response to FetchUTXOsSince
+    UTXOsSince(UtxoDiff),
     /// Send a transaction to the network.
     SubmitTransaction(Transaction),
     /// Broadcast a new transaction to other nodes
     NewTransaction(Transaction),
+    /// Ask a node whether it would take a transaction into its mempool, without adding or
+    /// relaying it
+    TestTransaction(Transaction),
+    /// Response to TestTransaction
+    TransactionTested {
+        txid: Hash,
+        result: Result<MempoolAcceptance, Rejection>,
+    },
     /// Ask the node to prepare the optimal block template with the coinbase transaction paying the
     /// specified public key (e.g block mined i guess)
     FetchTemplate(PublicKey),
@@ -32,6 +79,13 @@ pub enum Message {
     TemplateValidity(bool),
     /// Submit a mined block to a node
     SubmitTemplate(Block),
+    /// FetchTemplate, also asking the node to take blocks whose hash meets `share_target` on
+    /// this connection as shares, so a miner's hashrate shows when blocks are rare. The network's
+    /// target is used if it's easier
+    FetchShareTemplate(PublicKey, U256),
+    /// Response to SubmitTemplate for a block that meets the connection's share target but not
+    /// its own, with its hash
+    ShareAccepted(Hash),
     /// Ask a node to report all the other nodes it knows about
     DiscoverNodes,
     /// Response to DiscoverNodes
@@ -44,6 +98,224 @@ pub enum Message {
     FetchBlock(usize),
     /// Broadcast a new block to other nodes
     NewBlock(Block),
+    /// A checkpoint signed by some of the chain's authorities, passed on as more of them sign it
+    Checkpoint(SignedCheckpoint),
+    /// Ask a regtest node to instantly mine the given number of blocks paying the public key, at
+    /// most `MAX_GENERATED_BLOCKS`
+    GenerateBlocks(PublicKey, u32),
+    /// Response to GenerateBlocks with the hashes of the blocks that were added
+    GeneratedBlocks(Vec<Hash>),
+    /// Ask a node for a transaction of the chain by its hash, needs a node running with --txindex
+    FetchTransaction(Hash),
+    /// Response to FetchTransaction with the height of the block holding it
+    TransactionInfo {
+        height: u64,
+        transaction: Transaction,
+    },
+    /// Ask a node for everything that happened to a public key, needs a node running with
+    /// --addrindex
+    FetchHistory(PublicKey),
+    /// Response to FetchHistory, oldest first
+    History(Vec<AddressActivity>),
+    /// Ask a node for everything a key can spend and its history from a height on, for a key a
+    /// wallet just imported. Works without --addrindex, only slower
+    ScanAddress(PublicKey, u64),
+    /// Response to ScanAddress
+    AddressScanned(AddressScan),
+    /// Only relay transactions matching the filter on this connection from now on, and a
+    /// `FilteredBlock` for every new block
+    SetFilter(BloomFilter),
+    /// Add an item, e.g. a new key, to the connection's filter
+    FilterAdd(Vec<u8>),
+    /// Stop relaying to this connection
+    FilterClear,
+    /// A new block with only the transactions matching the connection's filter
+    FilteredBlock(MerkleBlock),
+    /// A transaction matching the connection's filter left the mempool without getting mined
+    Evicted { txid: Hash, reason: EvictionReason },
+    /// Another transaction spends outputs of a transaction matching the connection's filter
+    DoubleSpendAlert(DoubleSpend),
+    /// Ask a node to audit its UTXO set against the emission schedule
+    FetchSupply,
+    /// Response to FetchSupply when the audit passed
+    Supply {
+        height: u64,
+        total: Amount,
+        max: Amount,
+    },
+    /// Ask a node for the headers of up to `count` blocks from height `start` on, at most
+    /// `MAX_HEADER_RANGE`
+    FetchHeaderRange(u64, u32),
+    /// Response to FetchHeaderRange, empty past the tip
+    Headers(Vec<CompactHeader>),
+    /// Ask a node what's waiting in its mempool
+    FetchMempool,
+    /// Response to FetchMempool, up to `MAX_MEMPOOL_LISTING` of the best paying transactions
+    /// first
+    MempoolTransactions(Vec<MempoolTxInfo>),
+    /// Ask a node whether a transaction is in its mempool and where it stands in line
+    FetchMempoolTx(Hash),
+    /// Response to FetchMempoolTx
+    MempoolTx {
+        info: MempoolTxInfo,
+        transaction: Transaction,
+    },
+    /// Ask a node for `Blockchain::stats_windows` over the blocks from `start` up to `end`
+    GetChainStats { start: u64, end: u64, window: u64 },
+    /// Response to GetChainStats, one per window
+    Stats(Vec<ChainStats>),
+    /// Ask a node how it and its chain are doing
+    GetStatus,
+    /// Response to GetStatus
+    Status(Box<NodeStatus>),
+    /// Ask a node to parse a transaction in `Transaction::to_hex` form without submitting it
+    DecodeRawTransaction(String),
+    /// Response to DecodeRawTransaction
+    DecodedTransaction {
+        txid: Hash,
+        transaction: Transaction,
+    },
+    /// SubmitTransaction for a transaction in `Transaction::to_hex` form
+    SendRawTransaction(String),
+    /// Response to SubmitTransaction/SendRawTransaction/SubmitTemplate/SubmitPackage when the node
+    /// accepted it, with its hash
+    Ack(Hash),
+    /// Response to a request the node refused, `reason` is meant for humans
+    Error { code: ErrorCode, reason: String },
+    /// Optionally the first message of a connection, asking to speak one of `encodings` in
+    /// order of preference instead of CBOR. Understood in any encoding, see `codec`
+    Hello {
+        version: u32,
+        encodings: Vec<Encoding>,
+        /// the sender's clock, see `NetworkClock`
+        #[serde(default)]
+        time: Option<DateTime<Utc>>,
+        /// for the node to sign with its identity key in `Welcome`, see `NodeIdentity`
+        #[serde(default)]
+        nonce: Option<Nonce>,
+        /// what the sender supports, see `Capabilities`
+        #[serde(default)]
+        capabilities: Capabilities,
+    },
+    /// Response to Hello, the connection uses `encoding` from this message on. `policy` is what
+    /// the node takes into its mempool
+    Welcome {
+        version: u32,
+        encoding: Encoding,
+        #[serde(default)]
+        policy: MempoolPolicy,
+        /// the node's clock, see `NetworkClock`
+        #[serde(default)]
+        time: Option<DateTime<Utc>>,
+        /// the node's signature over the `Hello`'s nonce, if it sent one
+        #[serde(default)]
+        identity: Option<NodeIdentity>,
+        /// what the node supports, see `Capabilities`
+        #[serde(default)]
+        capabilities: Capabilities,
+    },
+    /// FetchTemplate with the coinbase split between several keys, and FetchShareTemplate too
+    /// with a share target. Nodes from before it was added don't know it, so miners paying a
+    /// single key still ask with those
+    FetchPayoutTemplate(Payout, Option<U256>),
+    /// Send transactions to the network together, parents before the child spending from them,
+    /// see `Blockchain::add_package_to_mempool`. Answered with an `Ack` of the child
+    SubmitPackage(Vec<Transaction>),
+    /// Broadcast a package to other nodes, for peers that support `Capabilities::PACKAGES`
+    NewPackage(Vec<Transaction>),
+}
+
+/// A block's header in `BlockHeader::to_compact` form with the block's hash. That hash covers
+/// the transactions as well, so it can't be worked out from the header, but the next header has
+/// to name it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct CompactHeader {
+    pub hash: Hash,
+    #[serde(with = "serde_bytes")]
+    pub header: Vec<u8>,
+}
+
+impl CompactHeader {
+    pub fn new(block: &Block) -> Self {
+        CompactHeader {
+            hash: block.hash(),
+            header: block.header.to_compact().to_vec(),
+        }
+    }
+
+    pub fn header(&self) -> Result<BlockHeader, BtcError> {
+        BlockHeader::from_compact(&self.header)
+    }
+}
+
+/// Why a node refused a request, so clients can react without parsing `reason`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub enum ErrorCode {
+    /// spends an output the node doesn't know about
+    MissingInputs,
+    /// spends an output twice
+    DoubleSpend,
+    BadSignature,
+    /// creates more value than it spends, or values overflow
+    InsufficientFunds,
+    /// block doesn't build on the node's tip anymore
+    StaleBlock,
+    /// breaks any other consensus rule
+    Invalid,
+    /// the node doesn't allow this request, e.g. regtest only messages
+    NotAllowed,
+    /// the node doesn't know what was asked for
+    NotFound,
+    /// the node's mempool policy refuses it, see the one in its `Welcome`
+    Policy,
+    /// the node couldn't make out the message, e.g. one from a newer version than its own
+    Unsupported,
+}
+
+impl From<&BtcError> for ErrorCode {
+    fn from(error: &BtcError) -> Self {
+        match error {
+            BtcError::MissingUtxo(_) => ErrorCode::MissingInputs,
+            BtcError::DoubleSpend(_) => ErrorCode::DoubleSpend,
+            BtcError::BadSignature { .. } | BtcError::InvalidSignature => ErrorCode::BadSignature,
+            BtcError::InsufficientInputs { .. }
+            | BtcError::ValueOverflow(_)
+            | BtcError::AboveMaxMoney { .. } => ErrorCode::InsufficientFunds,
+            BtcError::PrevHashMismatch { .. } => ErrorCode::StaleBlock,
+            BtcError::TransactionTooLarge { .. }
+            | BtcError::FeeTooLow { .. }
+            | BtcError::MempoolFull { .. } => ErrorCode::Policy,
+            _ => ErrorCode::Invalid,
+        }
+    }
+}
+
+/// Why a node would refuse something, as an `Error` reply would say
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct Rejection {
+    pub code: ErrorCode,
+    pub reason: String,
+}
+
+impl From<BtcError> for Rejection {
+    fn from(error: BtcError) -> Self {
+        Rejection {
+            code: ErrorCode::from(&error),
+            reason: error.to_string(),
+        }
+    }
+}
+
+impl From<BtcError> for Message {
+    fn from(error: BtcError) -> Self {
+        Message::Error {
+            code: ErrorCode::from(&error),
+            reason: error.to_string(),
+        }
+    }
 }
 
 impl Message {
@@ -57,33 +329,37 @@ impl Message {
         ciborium::from_reader(data)
     }
 
+    /// the message in CBOR out of what a frame holds after the length, see `envelope`
+    fn from_frame(frame: &[u8]) -> Result<Self, ciborium::de::Error<IoError>> {
+        envelope::split(frame)
+            .and_then(|(kind, compression, payload)| {
+                envelope::open(kind, compression, payload, Encoding::Cbor)
+            })
+            .map_err(|e| match e {
+                CodecError::CborDecode(e) => e,
+                e => ciborium::de::Error::Semantic(None, e.to_string()),
+            })
+    }
+
     pub fn send(&self, stream: &mut impl Write) -> Result<(), ciborium::ser::Error<IoError>> {
-        let bytes = self.encode()?;
-        let len = bytes.len() as u64;
-        stream.write_all(&len.to_be_bytes())?;
-        // shouldn't we check if receiver received the len first?
-        stream.write_all(&bytes)?;
+        stream.write_all(&self.to_frame()?)?;
         Ok(())
     }
 
     pub fn receive(stream: &mut impl Read) -> Result<Self, ciborium::de::Error<IoError>> {
         let mut len_bytes = [0u8; 8];
         stream.read_exact(&mut len_bytes)?;
-        let len = u64::from_be_bytes(len_bytes) as usize;
+        let len = pipeline::checked_len(u64::from_be_bytes(len_bytes))?;
         let mut data = vec![0u8; len];
         stream.read_exact(&mut data)?;
-        Self::decode(&data)
+        Self::from_frame(&data)
     }
 
     pub async fn send_async(
         &self,
         stream: &mut (impl AsyncWrite + Unpin),
     ) -> Result<(), ciborium::ser::Error<IoError>> {
-        let bytes = self.encode()?;
-        let len = bytes.len() as u64;
-        stream.write_all(&len.to_be_bytes()).await?;
-        // shouldn't we check if receiver received the len first?
-        stream.write_all(&bytes).await?;
+        stream.write_all(&self.to_frame()?).await?;
         Ok(())
     }
 
@@ -92,9 +368,9 @@ impl Message {
     ) -> Result<Self, ciborium::de::Error<IoError>> {
         let mut len_bytes = [0u8; 8];
         stream.read_exact(&mut len_bytes).await?;
-        let len = u64::from_be_bytes(len_bytes) as usize;
+        let len = pipeline::checked_len(u64::from_be_bytes(len_bytes))?;
         let mut data = vec![0u8; len];
         stream.read_exact(&mut data).await?;
-        Self::decode(&data)
+        Self::from_frame(&data)
     }
 }
@@ -0,0 +1,170 @@
+//! BIP37 style bloom filters. A light wallet hands its node a filter of its keys and only hears
+//! about transactions that match, without telling the node exactly which keys are its own.
+
+use crate::{
+    crypto::PublicKey,
+    types::{Block, BlockHeader, Transaction},
+    util::{MerkleProof, MerkleRoot},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// largest filter a node accepts, in bytes
+pub const MAX_BLOOM_FILTER_SIZE: usize = 36_000;
+/// most hash functions a node accepts a filter with
+pub const MAX_BLOOM_HASH_FUNCS: u32 = 50;
+/// largest item that may be added with `FilterAdd`, in bytes
+pub const MAX_FILTER_ADD_SIZE: usize = 520;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct BloomFilter {
+    #[serde(with = "serde_bytes")]
+    bits: Vec<u8>,
+    hash_funcs: u32,
+    /// picked at random, so two wallets with the same keys don't send the same filter
+    tweak: u32,
+}
+
+impl BloomFilter {
+    /// A filter sized to hold `elements` items with a false positive rate of about `fp_rate`.
+    /// A higher rate hides the wallet's keys better but gets it more unrelated transactions
+    pub fn new(elements: usize, fp_rate: f64, tweak: u32) -> Self {
+        let elements = elements.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let size = (-elements * fp_rate.ln() / (ln2 * ln2) / 8.0) as usize;
+        let size = size.clamp(1, MAX_BLOOM_FILTER_SIZE);
+        let hash_funcs = ((size * 8) as f64 / elements * ln2) as u32;
+        BloomFilter {
+            bits: vec![0; size],
+            hash_funcs: hash_funcs.clamp(1, MAX_BLOOM_HASH_FUNCS),
+            tweak,
+        }
+    }
+
+    /// whether a node should accept the filter
+    pub fn is_valid(&self) -> bool {
+        !self.bits.is_empty()
+            && self.bits.len() <= MAX_BLOOM_FILTER_SIZE
+            && (1..=MAX_BLOOM_HASH_FUNCS).contains(&self.hash_funcs)
+    }
+
+    /// bytes the filter takes
+    pub fn size(&self) -> usize {
+        self.bits.len()
+    }
+
+    fn bit_index(&self, n: u32, data: &[u8]) -> usize {
+        let seed = n.wrapping_mul(0xFBA4_C795).wrapping_add(self.tweak);
+        murmur3(seed, data) as usize % (self.bits.len() * 8)
+    }
+
+    pub fn insert(&mut self, data: &[u8]) {
+        if self.bits.is_empty() {
+            return;
+        }
+        for n in 0..self.hash_funcs {
+            let index = self.bit_index(n, data);
+            self.bits[index / 8] |= 1 << (index % 8);
+        }
+    }
+
+    pub fn insert_pubkey(&mut self, pubkey: &PublicKey) {
+        self.insert(&pubkey.to_compact());
+    }
+
+    pub fn contains(&self, data: &[u8]) -> bool {
+        !self.bits.is_empty()
+            && (0..self.hash_funcs).all(|n| {
+                let index = self.bit_index(n, data);
+                self.bits[index / 8] & (1 << (index % 8)) != 0
+            })
+    }
+
+    /// Whether the transaction's hash, a key it pays or an output it spends is in the filter.
+    /// Outputs paying a key in the filter are added to it, so whatever spends them matches too
+    pub fn matches_transaction(&mut self, transaction: &Transaction) -> bool {
+        let mut matches = self.contains(&transaction.hash().as_bytes());
+        for output in &transaction.outputs {
+            if self.contains(&output.pubkey.to_compact()) {
+                self.insert(&output.hash().as_bytes());
+                matches = true;
+            }
+        }
+        matches
+            || transaction
+                .inputs
+                .iter()
+                .any(|input| self.contains(&input.prev_transaction_output_hash.as_bytes()))
+    }
+}
+
+/// A block's header with only the transactions matching a filter, each with the proof it's in
+/// the block
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MerkleBlock {
+    pub header: BlockHeader,
+    pub transaction_count: usize,
+    pub matches: Vec<(Transaction, MerkleProof)>,
+}
+
+impl MerkleBlock {
+    pub fn new(block: &Block, filter: &mut BloomFilter) -> Self {
+        let matches = block
+            .transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, transaction)| filter.matches_transaction(transaction))
+            .filter_map(|(index, transaction)| {
+                let proof = MerkleRoot::prove(&block.transactions, index)?;
+                Some((transaction.clone(), proof))
+            })
+            .collect();
+        MerkleBlock {
+            header: block.header.clone(),
+            transaction_count: block.transactions.len(),
+            matches,
+        }
+    }
+
+    /// whether every matched transaction is really in the block
+    pub fn verify(&self) -> bool {
+        self.matches
+            .iter()
+            .all(|(transaction, proof)| proof.verify(transaction, &self.header.merkle_root))
+    }
+}
+
+/// 32 bit murmur3, the hash BIP37 filters use
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut hash = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        hash ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let k = tail
+            .iter()
+            .rev()
+            .fold(0u32, |k, &byte| (k << 8) | byte as u32);
+        hash ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
@@ -0,0 +1,99 @@
+//! What a peer supports beyond the messages every version understands, exchanged in `Hello` and
+//! `Welcome`. Messages that need one of them, see `Message::needs`, are only sent to peers that
+//! said they have it, so a network can upgrade one node at a time without the old ones choking
+//! on messages they can't decode. A peer that sends none, e.g. one from before capabilities,
+//! has none.
+
+use super::Message;
+
+use std::fmt;
+use std::ops::BitOr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+#[serde(transparent)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    /// relaying blocks as short ids of transactions the peer has already, nothing speaks it yet
+    pub const COMPACT_BLOCKS: Capabilities = Capabilities(1);
+    /// bloom filters, see `SetFilter`
+    pub const FILTERS: Capabilities = Capabilities(1 << 1);
+    /// chain snapshots to start from over http, see the node's `--serve-snapshots`
+    pub const SNAPSHOTS: Capabilities = Capabilities(1 << 2);
+    /// checkpoints signed by the chain's authorities, see `SignedCheckpoint`
+    pub const CHECKPOINTS: Capabilities = Capabilities(1 << 3);
+    /// reading messages compressed with zstd, see `Compression`
+    pub const ZSTD: Capabilities = Capabilities(1 << 4);
+    /// reading messages compressed with lz4
+    pub const LZ4: Capabilities = Capabilities(1 << 5);
+    /// taking transactions in together as a package, see `NewPackage`
+    pub const PACKAGES: Capabilities = Capabilities(1 << 6);
+
+    const NAMES: &[(Capabilities, &str)] = &[
+        (Self::COMPACT_BLOCKS, "compact-blocks"),
+        (Self::FILTERS, "filters"),
+        (Self::SNAPSHOTS, "snapshots"),
+        (Self::CHECKPOINTS, "checkpoints"),
+        (Self::ZSTD, "zstd"),
+        (Self::LZ4, "lz4"),
+        (Self::PACKAGES, "packages"),
+    ];
+
+    pub const fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// what both have, e.g. what the node and a peer can use on their connection
+    pub const fn common(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & other.0)
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+}
+
+/// The names of the known ones, comma separated. Unknown ones, from newer peers, are left out
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = Self::NAMES
+            .iter()
+            .filter(|(capability, _)| self.contains(*capability))
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(","))
+        }
+    }
+}
+
+impl Message {
+    /// What a peer has to support to be sent this message, every peer understands the rest
+    pub fn needs(&self) -> Capabilities {
+        match self {
+            Message::SetFilter(_)
+            | Message::FilterAdd(_)
+            | Message::FilterClear
+            | Message::FilteredBlock(_)
+            | Message::Evicted { .. }
+            | Message::DoubleSpendAlert(_) => Capabilities::FILTERS,
+            Message::Checkpoint(_) => Capabilities::CHECKPOINTS,
+            Message::NewPackage(_) => Capabilities::PACKAGES,
+            _ => Capabilities::NONE,
+        }
+    }
+}
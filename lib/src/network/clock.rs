@@ -0,0 +1,72 @@
+//! What time the network thinks it is. Peers send their clock in `Hello` and `Welcome`, and the
+//! median of how far theirs are from ours is what a node adds to its own clock. Bitcoin does the
+//! same so a host with a wrong clock doesn't refuse blocks everybody else takes. A median further
+//! off than `MAX_TIME_ADJUSTMENT` is more likely a bunch of lying peers than a wrong clock, it's
+//! only warned about.
+
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+
+/// peers it takes before their clocks count
+pub const MIN_CLOCK_SAMPLES: usize = 5;
+/// peers whose clocks are kept, the oldest are dropped
+pub const MAX_CLOCK_SAMPLES: usize = 200;
+
+#[derive(Clone, Debug, Default)]
+pub struct NetworkClock {
+    /// seconds each peer's clock is ahead of ours
+    offsets: HashMap<String, i64>,
+    /// peers by when they were sampled, oldest first
+    order: VecDeque<String>,
+}
+
+impl NetworkClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note the time `peer` sent, read at `now` on the local clock. A peer is only counted once,
+    /// so name it by its address rather than its connection
+    pub fn add_sample(&mut self, peer: &str, their_time: DateTime<Utc>, now: DateTime<Utc>) {
+        let offset = (their_time - now).num_seconds();
+        if self.offsets.insert(peer.to_string(), offset).is_some() {
+            return;
+        }
+        self.order.push_back(peer.to_string());
+        if self.order.len() > MAX_CLOCK_SAMPLES
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.offsets.remove(&oldest);
+        }
+    }
+
+    pub fn samples(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// seconds the peers' clocks are ahead of ours going by the median, 0 until there are
+    /// `MIN_CLOCK_SAMPLES`
+    pub fn median_offset(&self) -> i64 {
+        if self.offsets.len() < MIN_CLOCK_SAMPLES {
+            return 0;
+        }
+        let mut offsets: Vec<i64> = self.offsets.values().copied().collect();
+        offsets.sort_unstable();
+        offsets[offsets.len() / 2]
+    }
+
+    /// what to add to the local clock, the median unless it's beyond `MAX_TIME_ADJUSTMENT`
+    pub fn adjustment(&self) -> Duration {
+        let median = self.median_offset();
+        if median.abs() > crate::MAX_TIME_ADJUSTMENT {
+            return Duration::zero();
+        }
+        Duration::seconds(median)
+    }
+
+    /// whether the local clock is further than `MAX_CLOCK_SKEW` from the network's
+    pub fn is_skewed(&self) -> bool {
+        self.median_offset().abs() > crate::MAX_CLOCK_SKEW
+    }
+}
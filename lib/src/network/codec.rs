@@ -0,0 +1,145 @@
+//! How messages are turned into bytes. Connections speak CBOR unless the peer opens with a
+//! `Hello` asking for something else, which it may write in any encoding the node understands.
+//! The node answers with a `Welcome` naming the encoding it picked, already in that encoding, and
+//! both sides use it from then on. Frames stay the same whatever the encoding: a u64 big endian
+//! length, the message's kind, then the encoded message, see `envelope`. E.g. a script speaking
+//! JSON sends a frame of kind 53 holding
+//!
+//! ```json
+//! {"Hello":{"version":3,"encodings":["Json"]}}
+//! ```
+//!
+//! and gets back `{"Welcome":{"version":3,"encoding":"Json","policy":{…},"time":"…",…}}` with
+//! the node's mempool policy, clock and `Capabilities`.
+
+use super::Message;
+
+use std::fmt;
+use std::io::{Error as IoError, Write};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Version of the protocol a node speaks, sent in `Hello` and `Welcome`. 2 added `Capabilities`,
+/// what else a peer supports is up to them rather than the version. 3 put messages in an
+/// `Envelope`, older peers can't read those
+pub const PROTOCOL_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub enum Encoding {
+    #[default]
+    Cbor,
+    Json,
+}
+
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("I/O error: {0}")]
+    Io(#[from] IoError),
+    #[error("Failed to encode CBOR: {0}")]
+    CborEncode(#[from] ciborium::ser::Error<IoError>),
+    #[error("Invalid CBOR: {0}")]
+    CborDecode(#[from] ciborium::de::Error<IoError>),
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Frame too short for a message kind")]
+    MissingKind,
+    #[error("Unknown message kind {0}")]
+    UnknownKind(u16),
+    #[error("Message of kind {actual} in an envelope of kind {kind}")]
+    KindMismatch { kind: u16, actual: u16 },
+    #[error("Unknown compression {0}")]
+    UnknownCompression(u16),
+    #[error("Failed to decompress: {0}")]
+    Decompress(String),
+}
+
+impl CodecError {
+    /// Whether a message came in whole but couldn't be made out, e.g. one a newer version added.
+    /// The connection is still fine after one
+    pub fn is_undecodable(&self) -> bool {
+        !matches!(self, CodecError::Io(_) | CodecError::CborEncode(_))
+    }
+}
+
+/// Turns messages into bytes and back, one per `Encoding`
+pub trait Codec: Send + Sync {
+    fn encode(&self, message: &Message, writer: &mut dyn Write) -> Result<(), CodecError>;
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError>;
+}
+
+pub struct Cbor;
+
+impl Codec for Cbor {
+    fn encode(&self, message: &Message, writer: &mut dyn Write) -> Result<(), CodecError> {
+        Ok(ciborium::into_writer(message, writer)?)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        Ok(ciborium::from_reader(bytes)?)
+    }
+}
+
+pub struct Json;
+
+impl Codec for Json {
+    fn encode(&self, message: &Message, writer: &mut dyn Write) -> Result<(), CodecError> {
+        Ok(serde_json::to_writer(writer, message)?)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Message, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+impl Encoding {
+    /// every encoding a node understands
+    pub const ALL: &[Encoding] = &[Encoding::Cbor, Encoding::Json];
+
+    pub fn codec(self) -> &'static dyn Codec {
+        match self {
+            Encoding::Cbor => &Cbor,
+            Encoding::Json => &Json,
+        }
+    }
+
+    /// the peer's favourite of its `offered` encodings, CBOR if it offered none
+    pub fn negotiate(offered: &[Encoding]) -> Encoding {
+        offered.first().copied().unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Cbor => write!(f, "cbor"),
+            Encoding::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cbor" => Ok(Encoding::Cbor),
+            "json" => Ok(Encoding::Json),
+            _ => Err(format!("unknown encoding {s}, expected cbor or json")),
+        }
+    }
+}
+
+impl Message {
+    pub fn encode_as(&self, encoding: Encoding) -> Result<Vec<u8>, CodecError> {
+        let mut bytes = Vec::new();
+        encoding.codec().encode(self, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn decode_as(encoding: Encoding, data: &[u8]) -> Result<Self, CodecError> {
+        encoding.codec().decode(data)
+    }
+}
@@ -0,0 +1,126 @@
+//! Compressing big messages, e.g. blocks while a node downloads the chain, for slow links. A peer
+//! that supports zstd or lz4 says so in its `Capabilities`, and the side answering it compresses
+//! messages over `COMPRESSION_THRESHOLD` bytes with the one both support, zstd if they both do.
+//! How a message is compressed is in its envelope, see `envelope`, so readers don't have to keep
+//! track and anyone can read a compressed message whether it asked for them or not.
+
+use super::{Capabilities, CodecError, MAX_MESSAGE_SIZE};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// messages smaller than this are sent as they are, they'd hardly get any smaller
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+/// what zstd is run at, its default
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub enum Compression {
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    /// the one to use with a peer supporting `theirs`, None if there's none both support
+    pub fn negotiate(ours: Capabilities, theirs: Capabilities) -> Option<Compression> {
+        let common = ours.common(theirs);
+        if common.contains(Capabilities::ZSTD) {
+            Some(Compression::Zstd)
+        } else if common.contains(Capabilities::LZ4) {
+            Some(Compression::Lz4)
+        } else {
+            None
+        }
+    }
+
+    /// how it's marked in an envelope's kind
+    pub(super) fn bits(compression: Option<Compression>) -> u16 {
+        match compression {
+            None => 0,
+            Some(Compression::Zstd) => 1,
+            Some(Compression::Lz4) => 2,
+        }
+    }
+
+    pub(super) fn from_bits(bits: u16) -> Result<Option<Compression>, CodecError> {
+        match bits {
+            0 => Ok(None),
+            1 => Ok(Some(Compression::Zstd)),
+            2 => Ok(Some(Compression::Lz4)),
+            _ => Err(CodecError::UnknownCompression(bits)),
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            // only fails for levels zstd doesn't have
+            Compression::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL).unwrap(),
+            Compression::Lz4 => lz4_flex::compress_prepend_size(data),
+        }
+    }
+
+    /// The data `compress` was given, refusing anything that would come out bigger than
+    /// `MAX_MESSAGE_SIZE` before it's all allocated
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>, CodecError> {
+        match self {
+            Compression::Zstd => zstd::bulk::decompress(data, MAX_MESSAGE_SIZE)
+                .map_err(|e| CodecError::Decompress(e.to_string())),
+            Compression::Lz4 => {
+                let (size, compressed) = lz4_flex::block::uncompressed_size(data)
+                    .map_err(|e| CodecError::Decompress(e.to_string()))?;
+                if size > MAX_MESSAGE_SIZE {
+                    return Err(CodecError::Decompress(format!(
+                        "{size} bytes is over the {MAX_MESSAGE_SIZE} bytes limit"
+                    )));
+                }
+                lz4_flex::block::decompress(compressed, size)
+                    .map_err(|e| CodecError::Decompress(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Counts what compression saved, shared by everything sending for a node
+#[derive(Default, Debug)]
+pub struct CompressionMeter {
+    messages: AtomicU64,
+    raw_bytes: AtomicU64,
+    compressed_bytes: AtomicU64,
+}
+
+impl CompressionMeter {
+    pub fn record(&self, raw: usize, compressed: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.raw_bytes.fetch_add(raw as u64, Ordering::Relaxed);
+        self.compressed_bytes
+            .fetch_add(compressed as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            messages: self.messages.load(Ordering::Relaxed),
+            raw_bytes: self.raw_bytes.load(Ordering::Relaxed),
+            compressed_bytes: self.compressed_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// How much smaller the messages a node compressed got
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct CompressionStats {
+    pub messages: u64,
+    /// what they'd have been
+    pub raw_bytes: u64,
+    /// what was sent instead
+    pub compressed_bytes: u64,
+}
+
+impl CompressionStats {
+    /// compressed bytes per raw byte, None until something was compressed
+    pub fn ratio(&self) -> Option<f64> {
+        (self.raw_bytes > 0).then(|| self.compressed_bytes as f64 / self.raw_bytes as f64)
+    }
+}
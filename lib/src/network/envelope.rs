@@ -0,0 +1,240 @@
+//! What a frame holds: after the u64 big endian length, a u16 big endian kind saying which message
+//! it is, then the message in the connection's encoding. The kind is read before the message, so
+//! one a newer version added, with a kind this one doesn't know, can be skipped and answered with
+//! an `ErrorCode::Unsupported` without decoding it and without losing the connection.
+//!
+//! Kinds are the position of the variant in `Message`, `Hello` is 53, and never change: new
+//! messages get the next one. The top two bits of the u16 aren't part of the kind, they say how
+//! the message is compressed, see `compression`.
+
+use super::{CodecError, Compression, Encoding, Message};
+
+use std::borrow::Cow;
+
+/// kinds from this one on are from newer versions
+pub const KNOWN_KINDS: u16 = 58;
+/// the name of the message of each kind, for logs and traffic stats
+const KIND_NAMES: [&str; KNOWN_KINDS as usize] = [
+    "FetchUTXOs",
+    "UTXOs",
+    "FetchUTXOsSince",
+    "UTXOsSince",
+    "SubmitTransaction",
+    "NewTransaction",
+    "TestTransaction",
+    "TransactionTested",
+    "FetchTemplate",
+    "Template",
+    "ValidateTemplate",
+    "TemplateValidity",
+    "SubmitTemplate",
+    "FetchShareTemplate",
+    "ShareAccepted",
+    "DiscoverNodes",
+    "NodeList",
+    "AskDifference",
+    "Difference",
+    "FetchBlock",
+    "NewBlock",
+    "Checkpoint",
+    "GenerateBlocks",
+    "GeneratedBlocks",
+    "FetchTransaction",
+    "TransactionInfo",
+    "FetchHistory",
+    "History",
+    "ScanAddress",
+    "AddressScanned",
+    "SetFilter",
+    "FilterAdd",
+    "FilterClear",
+    "FilteredBlock",
+    "Evicted",
+    "DoubleSpendAlert",
+    "FetchSupply",
+    "Supply",
+    "FetchHeaderRange",
+    "Headers",
+    "FetchMempool",
+    "MempoolTransactions",
+    "FetchMempoolTx",
+    "MempoolTx",
+    "GetChainStats",
+    "Stats",
+    "GetStatus",
+    "Status",
+    "DecodeRawTransaction",
+    "DecodedTransaction",
+    "SendRawTransaction",
+    "Ack",
+    "Error",
+    "Hello",
+    "Welcome",
+    "FetchPayoutTemplate",
+    "SubmitPackage",
+    "NewPackage",
+];
+/// where the compression bits start in the u16 on the wire
+const COMPRESSION_SHIFT: u16 = 14;
+const KIND_MASK: u16 = (1 << COMPRESSION_SHIFT) - 1;
+
+/// A message's kind and its encoded bytes, as they're in a frame after the length
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    pub kind: u16,
+    /// how `payload` is compressed
+    pub compression: Option<Compression>,
+    pub payload: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn seal(message: &Message, encoding: Encoding) -> Result<Self, CodecError> {
+        Ok(Envelope {
+            kind: message.kind(),
+            compression: None,
+            payload: message.encode_as(encoding)?,
+        })
+    }
+
+    /// Split what a frame holds after the length into its kind and message bytes
+    pub fn parse(frame: &[u8]) -> Result<Self, CodecError> {
+        let (kind, compression, payload) = split(frame)?;
+        Ok(Envelope {
+            kind,
+            compression,
+            payload: payload.to_vec(),
+        })
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.kind < KNOWN_KINDS
+    }
+
+    /// the message inside, refusing kinds this version doesn't know and messages that aren't of
+    /// the kind on the envelope
+    pub fn open(&self, encoding: Encoding) -> Result<Message, CodecError> {
+        open(self.kind, self.compression, &self.payload, encoding)
+    }
+
+    /// the kind with the compression bits and then the payload
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            &wire_kind(self.kind, self.compression).to_be_bytes()[..],
+            &self.payload,
+        ]
+        .concat()
+    }
+}
+
+/// the u16 before the payload for a message of `kind` compressed with `compression`
+pub(super) fn wire_kind(kind: u16, compression: Option<Compression>) -> u16 {
+    kind | (Compression::bits(compression) << COMPRESSION_SHIFT)
+}
+
+/// `Envelope::open` without copying the payload out of the frame first
+pub(super) fn open(
+    kind: u16,
+    compression: Option<Compression>,
+    payload: &[u8],
+    encoding: Encoding,
+) -> Result<Message, CodecError> {
+    if kind >= KNOWN_KINDS {
+        return Err(CodecError::UnknownKind(kind));
+    }
+    let payload = match compression {
+        Some(compression) => Cow::Owned(compression.decompress(payload)?),
+        None => Cow::Borrowed(payload),
+    };
+    let message = Message::decode_as(encoding, &payload)?;
+    if message.kind() != kind {
+        return Err(CodecError::KindMismatch {
+            kind,
+            actual: message.kind(),
+        });
+    }
+    Ok(message)
+}
+
+/// the kind, compression and payload of what a frame holds after the length, without copying
+pub(super) fn split(frame: &[u8]) -> Result<(u16, Option<Compression>, &[u8]), CodecError> {
+    match frame {
+        [high, low, payload @ ..] => {
+            let wire = u16::from_be_bytes([*high, *low]);
+            let compression = Compression::from_bits(wire >> COMPRESSION_SHIFT)?;
+            Ok((wire & KIND_MASK, compression, payload))
+        }
+        _ => Err(CodecError::MissingKind),
+    }
+}
+
+impl Message {
+    /// the name of the message of `kind`, None for kinds from newer versions
+    pub fn kind_name(kind: u16) -> Option<&'static str> {
+        KIND_NAMES.get(usize::from(kind)).copied()
+    }
+
+    /// the kind of this message in its envelope
+    pub fn kind(&self) -> u16 {
+        use Message::*;
+        match self {
+            FetchUTXOs(_) => 0,
+            UTXOs(_) => 1,
+            FetchUTXOsSince(..) => 2,
+            UTXOsSince(_) => 3,
+            SubmitTransaction(_) => 4,
+            NewTransaction(_) => 5,
+            TestTransaction(_) => 6,
+            TransactionTested { .. } => 7,
+            FetchTemplate(_) => 8,
+            Template(_) => 9,
+            ValidateTemplate(_) => 10,
+            TemplateValidity(_) => 11,
+            SubmitTemplate(_) => 12,
+            FetchShareTemplate(..) => 13,
+            ShareAccepted(_) => 14,
+            DiscoverNodes => 15,
+            NodeList(_) => 16,
+            AskDifference(_) => 17,
+            Difference(_) => 18,
+            FetchBlock(_) => 19,
+            NewBlock(_) => 20,
+            Checkpoint(_) => 21,
+            GenerateBlocks(..) => 22,
+            GeneratedBlocks(_) => 23,
+            FetchTransaction(_) => 24,
+            TransactionInfo { .. } => 25,
+            FetchHistory(_) => 26,
+            History(_) => 27,
+            ScanAddress(..) => 28,
+            AddressScanned(_) => 29,
+            SetFilter(_) => 30,
+            FilterAdd(_) => 31,
+            FilterClear => 32,
+            FilteredBlock(_) => 33,
+            Evicted { .. } => 34,
+            DoubleSpendAlert(_) => 35,
+            FetchSupply => 36,
+            Supply { .. } => 37,
+            FetchHeaderRange(..) => 38,
+            Headers(_) => 39,
+            FetchMempool => 40,
+            MempoolTransactions(_) => 41,
+            FetchMempoolTx(_) => 42,
+            MempoolTx { .. } => 43,
+            GetChainStats { .. } => 44,
+            Stats(_) => 45,
+            GetStatus => 46,
+            Status(_) => 47,
+            DecodeRawTransaction(_) => 48,
+            DecodedTransaction { .. } => 49,
+            SendRawTransaction(_) => 50,
+            Ack(_) => 51,
+            Error { .. } => 52,
+            Hello { .. } => 53,
+            Welcome { .. } => 54,
+            FetchPayoutTemplate(..) => 55,
+            SubmitPackage(_) => 56,
+            NewPackage(_) => 57,
+        }
+    }
+}
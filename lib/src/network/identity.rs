@@ -0,0 +1,90 @@
+//! Who a node is. Every node has a key pair of its own, kept across restarts, and answers a
+//! `Hello` carrying a nonce with a `NodeIdentity` signing it. Wallets and miners that pin the
+//! identities they trust refuse a node that can't sign for one of them, so nothing sitting on a
+//! plaintext link can pass itself off as their node. The link stays plaintext though, someone
+//! relaying the handshake to the real node can still read and change what comes after it.
+
+use crate::{
+    crypto::{PrivateKey, PublicKey, Signature},
+    error::{BtcError, Result},
+};
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// what `Hello` asks the node to sign, fresh for every connection
+pub type Nonce = [u8; 32];
+
+/// signed along with the nonce, so the signature proves nothing but the node's identity
+const IDENTITY_PREFIX: &[u8] = b"node identity:";
+
+pub fn new_nonce() -> Nonce {
+    let mut nonce = [0; 32];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+/// A node's identity key and its signature over the nonce of the `Hello` it answers
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct NodeIdentity {
+    pub key: PublicKey,
+    pub signature: Signature,
+}
+
+impl NodeIdentity {
+    pub fn prove(key: &PrivateKey, nonce: &Nonce) -> Self {
+        NodeIdentity {
+            key: key.public_key(),
+            signature: key.sign_message(&[IDENTITY_PREFIX, nonce].concat()),
+        }
+    }
+
+    pub fn verify(&self, nonce: &Nonce) -> bool {
+        self.key
+            .verify_message(&[IDENTITY_PREFIX, nonce].concat(), &self.signature)
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.key)
+    }
+}
+
+/// how identities are pinned and shown, the key's compact form in hex
+pub fn fingerprint(key: &PublicKey) -> String {
+    hex::encode(key.to_compact())
+}
+
+/// Check the identity a node answered a `Hello` with `nonce` with. With nothing `pinned` any node
+/// goes, as long as what it sent holds up
+pub fn check_identity(
+    identity: Option<&NodeIdentity>,
+    nonce: &Nonce,
+    pinned: &[String],
+) -> Result<()> {
+    let Some(identity) = identity else {
+        if pinned.is_empty() {
+            return Ok(());
+        }
+        return Err(BtcError::UntrustedNode(
+            "it didn't say who it is".to_string(),
+        ));
+    };
+    if !identity.verify(nonce) {
+        return Err(BtcError::UntrustedNode(format!(
+            "it can't sign for {}",
+            identity.fingerprint()
+        )));
+    }
+    let fingerprint = identity.fingerprint();
+    if !pinned.is_empty()
+        && !pinned
+            .iter()
+            .any(|pin| pin.trim().eq_ignore_ascii_case(&fingerprint))
+    {
+        return Err(BtcError::UntrustedNode(format!(
+            "{fingerprint} isn't one of the trusted ones"
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Keeping count of the memory a node holds for its peers, and a cap on it. Every frame a
+//! connection reads is `reserve`d before its bytes are, and every frame queued to be written
+//! until it is, so the buffers of a thousand slow or lying peers add up to at most the cap: past
+//! it, a frame read is refused and the connection closed, a frame to queue is refused and gossip
+//! the peer can do without is dropped. A frame queued for several peers counts once for each.
+
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// Bytes held for peers, shared by every connection of a node
+#[derive(Debug, Default)]
+pub struct MemoryMeter {
+    limit: Option<usize>,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    refused: AtomicU64,
+}
+
+/// Bytes taken from a `MemoryMeter`, given back when dropped
+#[derive(Debug)]
+pub struct Reservation {
+    meter: Arc<MemoryMeter>,
+    bytes: usize,
+}
+
+impl MemoryMeter {
+    /// at most `limit` bytes held at once, no limit when None
+    pub fn new(limit: Option<usize>) -> Self {
+        MemoryMeter {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Take `bytes`, refused with `OutOfMemory` when that would go over the limit
+    pub fn reserve(self: &Arc<Self>, bytes: usize) -> Result<Reservation, IoError> {
+        let fits = |used: usize| {
+            let after = used.saturating_add(bytes);
+            self.limit
+                .is_none_or(|limit| after <= limit)
+                .then_some(after)
+        };
+        match self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, fits)
+        {
+            Ok(used) => {
+                self.peak.fetch_max(used + bytes, Ordering::Relaxed);
+                Ok(Reservation {
+                    meter: self.clone(),
+                    bytes,
+                })
+            }
+            Err(used) => {
+                self.refused.fetch_add(1, Ordering::Relaxed);
+                Err(IoError::new(
+                    ErrorKind::OutOfMemory,
+                    format!(
+                        "{bytes} bytes more for peers would go over the {} bytes limit, {used} are taken",
+                        self.limit.unwrap_or_default()
+                    ),
+                ))
+            }
+        }
+    }
+
+    pub fn stats(&self) -> PeerMemory {
+        PeerMemory {
+            used: self.used.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            limit: self.limit,
+            refused: self.refused.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.meter.used.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// What a `MemoryMeter` counted
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct PeerMemory {
+    /// bytes held right now
+    pub used: usize,
+    /// the most held at once
+    pub peak: usize,
+    pub limit: Option<usize>,
+    /// frames refused for going over the limit
+    pub refused: u64,
+}
+
+/// What a node holds in memory, by what it holds it for. There's no orphan pool to count: blocks
+/// and transactions whose parents it doesn't know are refused, not kept
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MemoryUsage {
+    /// bytes of the transactions in the mempool, see `Transaction::size`
+    pub mempool: usize,
+    /// bytes the mempool holds before it evicts, see `MempoolPolicy`
+    pub mempool_limit: usize,
+    pub utxo_entries: usize,
+    /// about what the UTXO set takes, see `Blockchain::utxo_memory`. It holds what the chain
+    /// needs to check blocks, so it has no limit
+    pub utxo_bytes: usize,
+    /// bytes of the light wallets' filters
+    pub filters: usize,
+    /// frames being read from or waiting to be written to peers
+    pub peers: PeerMemory,
+}
+
+impl MemoryUsage {
+    /// everything counted, in bytes
+    pub fn total(&self) -> usize {
+        self.mempool + self.utxo_bytes + self.filters + self.peers.used
+    }
+}
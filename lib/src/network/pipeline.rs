@@ -0,0 +1,382 @@
+//! Buffered message handling for servers with many connections. Reading reuses one buffer per
+//! connection and writing goes through a bounded queue, so a slow peer only ever holds up its own
+//! queue and never the task answering it.
+
+use super::{
+    COMPRESSION_THRESHOLD, CodecError, Compression, CompressionMeter, Encoding, MemoryMeter,
+    Message, Reservation, Throttle, TrafficMeter, envelope,
+};
+
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::Arc;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+
+/// messages bigger than this are rejected before anything is allocated for them
+pub const MAX_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
+/// a connection's buffers are dropped after a message bigger than this, so one big block doesn't
+/// pin its memory forever
+const KEEP_BUFFER_SIZE: usize = 64 * 1024;
+
+/// the length of a message from its prefix, refusing anything over `MAX_MESSAGE_SIZE` before it
+/// gets allocated
+pub(super) fn checked_len(len: u64) -> Result<usize, IoError> {
+    match usize::try_from(len) {
+        Ok(len) if len <= MAX_MESSAGE_SIZE => Ok(len),
+        _ => Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("message of {len} bytes is over the {MAX_MESSAGE_SIZE} bytes limit"),
+        )),
+    }
+}
+
+impl Message {
+    /// Append the length prefixed frame `send` would write to `buffer` and split it off, the
+    /// buffer's allocation is reused once the returned frame is dropped
+    pub fn encode_frame(
+        &self,
+        buffer: &mut BytesMut,
+    ) -> Result<Bytes, ciborium::ser::Error<IoError>> {
+        buffer.clear();
+        buffer.put_u64(0);
+        buffer.put_u16(self.kind());
+        if let Err(e) = ciborium::into_writer(self, buffer.writer()) {
+            buffer.clear();
+            return Err(e);
+        }
+        Ok(finish_frame(buffer))
+    }
+
+    /// `encode_frame` in any encoding
+    pub fn encode_frame_as(
+        &self,
+        encoding: Encoding,
+        buffer: &mut BytesMut,
+    ) -> Result<Bytes, CodecError> {
+        buffer.clear();
+        buffer.put_u64(0);
+        buffer.put_u16(self.kind());
+        if let Err(e) = encoding.codec().encode(self, &mut buffer.writer()) {
+            buffer.clear();
+            return Err(e);
+        }
+        Ok(finish_frame(buffer))
+    }
+
+    /// `encode_frame` into a fresh buffer, for frames sent to many peers
+    pub fn to_frame(&self) -> Result<Bytes, ciborium::ser::Error<IoError>> {
+        self.encode_frame(&mut BytesMut::new())
+    }
+
+    pub fn to_frame_as(&self, encoding: Encoding) -> Result<Bytes, CodecError> {
+        self.encode_frame_as(encoding, &mut BytesMut::new())
+    }
+}
+
+/// fill in the length of the kind and message after the prefix and split the frame off
+fn finish_frame(buffer: &mut BytesMut) -> Bytes {
+    let len = (buffer.len() - 8) as u64;
+    buffer[..8].copy_from_slice(&len.to_be_bytes());
+    buffer.split().freeze()
+}
+
+/// The frame with its message compressed, if it's over `COMPRESSION_THRESHOLD` and comes out
+/// smaller. Everything that gets compressed is counted in `meter`, whether it helped or not
+fn compress_frame(
+    frame: Bytes,
+    compression: Compression,
+    meter: Option<&CompressionMeter>,
+) -> Bytes {
+    let payload = &frame[10..];
+    if payload.len() <= COMPRESSION_THRESHOLD {
+        return frame;
+    }
+    let compressed = compression.compress(payload);
+    if compressed.len() >= payload.len() {
+        if let Some(meter) = meter {
+            meter.record(payload.len(), payload.len());
+        }
+        return frame;
+    }
+    if let Some(meter) = meter {
+        meter.record(payload.len(), compressed.len());
+    }
+    let kind = u16::from_be_bytes([frame[8], frame[9]]);
+    let mut buffer = BytesMut::with_capacity(10 + compressed.len());
+    buffer.put_u64(0);
+    buffer.put_u16(envelope::wire_kind(kind, Some(compression)));
+    buffer.put_slice(&compressed);
+    finish_frame(&mut buffer)
+}
+
+/// Reads messages off a stream into the same buffer every time
+pub struct MessageReader<R> {
+    reader: R,
+    buffer: BytesMut,
+    encoding: Encoding,
+    traffic: Option<Arc<TrafficMeter>>,
+    throttle: Option<Arc<Throttle>>,
+    memory: Option<Arc<MemoryMeter>>,
+    /// what the frame being read took from `memory`
+    reservation: Option<Reservation>,
+}
+
+impl<R: AsyncRead + Unpin> MessageReader<R> {
+    pub fn new(reader: R) -> Self {
+        MessageReader {
+            reader,
+            buffer: BytesMut::new(),
+            encoding: Encoding::default(),
+            traffic: None,
+            throttle: None,
+            memory: None,
+            reservation: None,
+        }
+    }
+
+    /// where the frames read take their memory from, a frame it has no room for is an error
+    pub fn set_memory(&mut self, memory: Arc<MemoryMeter>) {
+        self.memory = Some(memory);
+    }
+
+    /// where the frames read are counted
+    pub fn set_traffic(&mut self, traffic: Arc<TrafficMeter>) {
+        self.traffic = Some(traffic);
+    }
+
+    /// how fast frames are read, the stream backs up to the other side when they come faster
+    pub fn set_throttle(&mut self, throttle: Arc<Throttle>) {
+        self.throttle = Some(throttle);
+    }
+
+    /// how the messages read from now on are encoded
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub async fn receive(&mut self) -> Result<Message, CodecError> {
+        self.read_frame().await?;
+        let message = envelope::split(&self.buffer).and_then(|(kind, compression, payload)| {
+            envelope::open(kind, compression, payload, self.encoding)
+        });
+        self.release_buffer();
+        message
+    }
+
+    /// Like `receive`, but a `Hello` is understood in any encoding, for the first message of a
+    /// connection. Anything else still has to be in the reader's encoding
+    pub async fn receive_hello(&mut self) -> Result<Message, CodecError> {
+        self.read_frame().await?;
+        let message = envelope::split(&self.buffer).and_then(|(kind, compression, payload)| {
+            envelope::open(kind, compression, payload, self.encoding).or_else(|e| {
+                Encoding::ALL
+                    .iter()
+                    .filter(|encoding| **encoding != self.encoding)
+                    .find_map(|encoding| {
+                        match envelope::open(kind, compression, payload, *encoding) {
+                            Ok(hello @ Message::Hello { .. }) => Some(hello),
+                            _ => None,
+                        }
+                    })
+                    .ok_or(e)
+            })
+        });
+        self.release_buffer();
+        message
+    }
+
+    async fn read_frame(&mut self) -> Result<(), IoError> {
+        let len = checked_len(self.reader.read_u64().await?)?;
+        if let Some(throttle) = &self.throttle {
+            throttle.take(8 + len).await;
+        }
+        self.reservation = None;
+        if let Some(memory) = &self.memory {
+            self.reservation = Some(memory.reserve(8 + len)?);
+        }
+        // the buffer grows with what arrives, so a peer announcing a big frame and sending little
+        // of it doesn't get the whole of it allocated
+        self.buffer.clear();
+        self.buffer.reserve(len.min(KEEP_BUFFER_SIZE));
+        let mut frame = (&mut self.reader).take(len as u64);
+        while self.buffer.len() < len {
+            if frame.read_buf(&mut self.buffer).await? == 0 {
+                return Err(IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("frame ended {} bytes short", len - self.buffer.len()),
+                ));
+            }
+        }
+        if let (Some(traffic), Ok((kind, ..))) = (&self.traffic, envelope::split(&self.buffer)) {
+            traffic.received(kind, 8 + len);
+        }
+        Ok(())
+    }
+
+    fn release_buffer(&mut self) {
+        self.reservation = None;
+        if self.buffer.capacity() > KEEP_BUFFER_SIZE {
+            self.buffer = BytesMut::new();
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// The sending half of a connection. Frames are queued and written by a background task which
+/// batches whatever is waiting into one flush. `send` waits once `capacity` frames are queued.
+/// Dropping the outbox, and all its clones, lets the task write out what's left and stop
+#[derive(Clone)]
+pub struct Outbox {
+    frames: mpsc::Sender<(Bytes, Option<Reservation>)>,
+    buffer: BytesMut,
+    encoding: Encoding,
+    compression: Option<Compression>,
+    meter: Option<Arc<CompressionMeter>>,
+    traffic: Option<Arc<TrafficMeter>>,
+    throttle: Option<Arc<Throttle>>,
+    memory: Option<Arc<MemoryMeter>>,
+}
+
+impl Outbox {
+    /// spawn the writer task on the current tokio runtime
+    pub fn spawn<W>(writer: W, capacity: usize) -> Self
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        // a frame's reservation is given back once it's written
+        let (frames, mut queue) = mpsc::channel::<(Bytes, Option<Reservation>)>(capacity);
+        tokio::spawn(async move {
+            let mut writer = BufWriter::new(writer);
+            while let Some((frame, _reservation)) = queue.recv().await {
+                writer.write_all(&frame).await?;
+                while let Ok((frame, _reservation)) = queue.try_recv() {
+                    writer.write_all(&frame).await?;
+                }
+                writer.flush().await?;
+            }
+            writer.shutdown().await
+        });
+        Outbox {
+            frames,
+            buffer: BytesMut::new(),
+            encoding: Encoding::default(),
+            compression: None,
+            meter: None,
+            traffic: None,
+            throttle: None,
+            memory: None,
+        }
+    }
+
+    /// where the frames queued take their memory from until they're written, by this outbox and
+    /// the clones made after. A frame there's no room for isn't queued
+    pub fn set_memory(&mut self, memory: Arc<MemoryMeter>) {
+        self.memory = Some(memory);
+    }
+
+    /// where the frames sent are counted, by this outbox and the clones made after
+    pub fn set_traffic(&mut self, traffic: Arc<TrafficMeter>) {
+        self.traffic = Some(traffic);
+    }
+
+    /// how fast frames are queued, shared with the clones made after so they're all held to it
+    pub fn set_throttle(&mut self, throttle: Arc<Throttle>) {
+        self.throttle = Some(throttle);
+    }
+
+    /// how big messages sent from now on are compressed, None to send them as they are
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.compression = compression;
+    }
+
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    /// where what compression saved is counted
+    pub fn set_meter(&mut self, meter: Arc<CompressionMeter>) {
+        self.meter = Some(meter);
+    }
+
+    /// how messages sent from now on are encoded
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub async fn send(&mut self, message: &Message) -> Result<(), CodecError> {
+        let mut frame = message.encode_frame_as(self.encoding, &mut self.buffer)?;
+        if let Some(compression) = self.compression {
+            frame = compress_frame(frame, compression, self.meter.as_deref());
+        }
+        if self.buffer.capacity() > KEEP_BUFFER_SIZE {
+            self.buffer = BytesMut::new();
+        }
+        Ok(self.send_frame(frame).await?)
+    }
+
+    /// queue an already encoded frame, e.g. one broadcast to every peer
+    pub async fn send_frame(&self, frame: Bytes) -> Result<(), IoError> {
+        if let Some(throttle) = &self.throttle {
+            throttle.take(frame.len()).await;
+        }
+        let reservation = self.reserve(&frame)?;
+        self.count(&frame);
+        self.frames
+            .send((frame, reservation))
+            .await
+            .map_err(|_| IoError::new(ErrorKind::BrokenPipe, "connection writer has stopped"))
+    }
+
+    /// queue a frame unless the queue is full or the throttle is over its rate, for messages the
+    /// peer can do without
+    pub fn try_send_frame(&self, frame: Bytes) -> Result<(), IoError> {
+        if let Some(throttle) = &self.throttle
+            && !throttle.try_take(frame.len())
+        {
+            return Err(IoError::new(
+                ErrorKind::WouldBlock,
+                "connection is over its rate",
+            ));
+        }
+        let reservation = self.reserve(&frame)?;
+        // a clone of a frame is just another handle on it
+        self.frames
+            .try_send((frame.clone(), reservation))
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => {
+                    IoError::new(ErrorKind::WouldBlock, "connection writer is busy")
+                }
+                mpsc::error::TrySendError::Closed(_) => {
+                    IoError::new(ErrorKind::BrokenPipe, "connection writer has stopped")
+                }
+            })?;
+        self.count(&frame);
+        Ok(())
+    }
+
+    fn reserve(&self, frame: &[u8]) -> Result<Option<Reservation>, IoError> {
+        self.memory
+            .as_ref()
+            .map(|memory| memory.reserve(frame.len()))
+            .transpose()
+    }
+
+    fn count(&self, frame: &[u8]) {
+        if let (Some(traffic), Ok((kind, ..))) = (&self.traffic, envelope::split(&frame[8..])) {
+            traffic.sent(kind, frame.len());
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use super::{CompressionStats, MemoryUsage, Traffic};
+use crate::{
+    U256,
+    crypto::PublicKey,
+    sha256::Hash,
+    types::{MempoolPolicy, MempoolStats},
+};
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What a node reports about itself and its chain, the answer to GetStatus
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct NodeStatus {
+    /// version of the node software
+    pub version: String,
+    pub protocol_version: u32,
+    /// name of the network from the chain spec
+    pub network: String,
+    pub regtest: bool,
+    pub height: u64,
+    /// hash of the last block, zero for an empty chain
+    pub best_hash: Hash,
+    /// target the next block has to meet
+    pub target: U256,
+    /// see `Blockchain::difficulty`
+    pub difficulty: f64,
+    pub mempool: MempoolStats,
+    /// what it takes into its mempool and for how long
+    #[serde(default)]
+    pub policy: MempoolPolicy,
+    /// other nodes it knows about
+    pub peers: usize,
+    /// light wallets with a filter set
+    pub light_wallets: usize,
+    /// seconds since the node started
+    pub uptime: u64,
+    /// whether it is still downloading the chain from its peers
+    pub syncing: bool,
+    /// seconds its peers' clocks are ahead of its own, going by the median
+    #[serde(default)]
+    pub clock_offset: i64,
+    /// the background jobs the node runs every so often
+    #[serde(default)]
+    pub tasks: Vec<TaskStatus>,
+    /// the key it proves who it is with, see `NodeIdentity`
+    #[serde(default)]
+    pub identity: Option<PublicKey>,
+    /// height of the latest block the checkpoint authorities signed, see `SignedCheckpoint`
+    #[serde(default)]
+    pub checkpoint: Option<u64>,
+    /// height of the highest block final by the node's policy, see `finality`
+    #[serde(default)]
+    pub finalized: Option<u64>,
+    /// blocks it mined or was handed to send out itself, rather than relayed from peers
+    #[serde(default)]
+    pub own_blocks: Propagation,
+    /// transactions wallets submitted to it
+    #[serde(default)]
+    pub own_transactions: Propagation,
+    /// what compressing big messages to peers saved
+    #[serde(default)]
+    pub compression: CompressionStats,
+    /// over every connection since it started
+    #[serde(default)]
+    pub traffic: Traffic,
+    /// bytes a second it sends to each peer at most
+    #[serde(default)]
+    pub upload_limit: Option<u64>,
+    /// bytes a second it reads from each peer at most
+    #[serde(default)]
+    pub download_limit: Option<u64>,
+    /// connections whose handler panicked, each a bug
+    #[serde(default)]
+    pub handler_panics: u64,
+    /// what it holds in memory and for what
+    #[serde(default)]
+    pub memory: MemoryUsage,
+}
+
+/// How long what a node sent out itself took from being accepted to having been written to every
+/// peer, in milliseconds
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct Propagation {
+    pub sent: u64,
+    pub total_ms: u64,
+    pub last_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Propagation {
+    pub fn record(&mut self, ms: u64) {
+        self.sent = self.sent.saturating_add(1);
+        self.total_ms = self.total_ms.saturating_add(ms);
+        self.last_ms = ms;
+        self.max_ms = self.max_ms.max(ms);
+    }
+
+    pub fn average_ms(&self) -> u64 {
+        self.total_ms.checked_div(self.sent).unwrap_or(0)
+    }
+}
+
+/// How one of a node's background jobs has been going
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct TaskStatus {
+    pub name: String,
+    /// seconds between runs
+    pub interval: u64,
+    pub runs: u64,
+    /// when the last run finished, whether it worked or not
+    pub last_run: Option<DateTime<Utc>>,
+    /// runs that failed or panicked
+    pub failures: u64,
+    /// what went wrong the last time a run failed, kept when later runs work
+    pub last_error: Option<String>,
+}
+
+/// one `name: value` per line, for people
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let network = if self.regtest {
+            format!("{} (regtest)", self.network)
+        } else {
+            self.network.clone()
+        };
+        writeln!(
+            f,
+            "version:    {} (protocol {})",
+            self.version, self.protocol_version
+        )?;
+        writeln!(f, "network:    {network}")?;
+        if let Some(identity) = &self.identity {
+            writeln!(f, "identity:   {}", super::fingerprint(identity))?;
+        }
+        writeln!(
+            f,
+            "state:      {}",
+            if self.syncing { "syncing" } else { "synced" }
+        )?;
+        writeln!(f, "height:     {}", self.height)?;
+        if let Some(checkpoint) = self.checkpoint {
+            writeln!(f, "checkpoint: block {checkpoint}")?;
+        }
+        if let Some(finalized) = self.finalized {
+            writeln!(f, "finalized:  block {finalized}")?;
+        }
+        writeln!(f, "best hash:  {}", self.best_hash)?;
+        let mut target = [0; 32];
+        self.target.to_big_endian(&mut target);
+        writeln!(f, "target:     {}", hex::encode(target))?;
+        writeln!(f, "difficulty: {:.2}", self.difficulty)?;
+        write!(
+            f,
+            "mempool:    {} transactions of {} bytes paying {}",
+            self.mempool.transactions, self.mempool.size, self.mempool.fees
+        )?;
+        if let Some(oldest) = self.mempool.oldest {
+            write!(f, ", oldest from {}", oldest.format("%Y-%m-%d %H:%M:%S"))?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "policy:     {} sats/byte at least, transactions expire after {}s",
+            self.policy.min_fee_rate, self.policy.max_transaction_age
+        )?;
+        writeln!(
+            f,
+            "peers:      {} nodes, {} light wallets",
+            self.peers, self.light_wallets
+        )?;
+        write!(f, "clock:      {:+}s from the network", -self.clock_offset)?;
+        if self.clock_offset.abs() > crate::MAX_CLOCK_SKEW {
+            write!(f, ", check the local clock!")?;
+        }
+        writeln!(f)?;
+        for (name, propagation) in [
+            ("own blocks", &self.own_blocks),
+            ("own txs", &self.own_transactions),
+        ] {
+            if propagation.sent > 0 {
+                writeln!(
+                    f,
+                    "{:<12}{} sent to peers in {}ms on average, {}ms the last time",
+                    format!("{name}:"),
+                    propagation.sent,
+                    propagation.average_ms(),
+                    propagation.last_ms
+                )?;
+            }
+        }
+        write!(
+            f,
+            "traffic:    {} bytes sent, {} received",
+            self.traffic.bytes_sent, self.traffic.bytes_received
+        )?;
+        match (self.upload_limit, self.download_limit) {
+            (Some(up), Some(down)) => write!(f, ", {up}B/s up and {down}B/s down per peer")?,
+            (Some(up), None) => write!(f, ", {up}B/s up per peer")?,
+            (None, Some(down)) => write!(f, ", {down}B/s down per peer")?,
+            (None, None) => {}
+        }
+        writeln!(f)?;
+        if let Some(ratio) = self.compression.ratio() {
+            writeln!(
+                f,
+                "compressed: {} messages to {:.0}% of their size",
+                self.compression.messages,
+                ratio * 100.0
+            )?;
+        }
+        if self.handler_panics > 0 {
+            writeln!(
+                f,
+                "panics:     {} connection handlers, see the log",
+                self.handler_panics
+            )?;
+        }
+        let memory = &self.memory;
+        write!(
+            f,
+            "memory:     {} bytes, mempool {}/{}, {} utxos {}, filters {}, peers {}",
+            memory.total(),
+            memory.mempool,
+            memory.mempool_limit,
+            memory.utxo_entries,
+            memory.utxo_bytes,
+            memory.filters,
+            memory.peers.used
+        )?;
+        if let Some(limit) = memory.peers.limit {
+            write!(f, "/{limit}")?;
+        }
+        if memory.peers.refused > 0 {
+            write!(f, ", {} frames refused", memory.peers.refused)?;
+        }
+        writeln!(f)?;
+        write!(f, "uptime:     {}s", self.uptime)?;
+        for task in &self.tasks {
+            write!(
+                f,
+                "\ntask:       {} every {}s, ran {} times",
+                task.name, task.interval, task.runs
+            )?;
+            if let Some(last_run) = task.last_run {
+                write!(f, ", last at {}", last_run.format("%Y-%m-%d %H:%M:%S"))?;
+            }
+            if let Some(error) = &task.last_error {
+                write!(f, ", {} failed, last with: {error}", task.failures)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! Counting the bytes that go over a connection and holding a connection to a rate. A
+//! `TrafficMeter` counts frames by the kind of message in them, as they are on the wire, i.e.
+//! with the length prefix and after compression. A `Throttle` lets a second's worth of bytes
+//! through at once and makes whoever goes over wait until the rate catches up, so a frame bigger
+//! than the rate still gets through, just followed by a longer pause.
+
+use super::Message;
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Bytes a second that go through, shared by everything reading or writing one side of a
+/// connection
+#[derive(Debug)]
+pub struct Throttle {
+    rate: u64,
+    /// bytes that can go through right away, negative while over the rate, as of when
+    budget: Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    /// `rate` bytes a second, at least 1
+    pub fn new(rate: u64) -> Self {
+        let rate = rate.max(1);
+        Throttle {
+            rate,
+            budget: Mutex::new((rate as f64, Instant::now())),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate
+    }
+
+    /// the budget after taking `bytes`, refilled up to a second's worth since it was last taken
+    fn spend(&self, bytes: usize, only_if_there: bool) -> Option<f64> {
+        let mut budget = self.budget.lock().unwrap();
+        let (left, since) = *budget;
+        let now = Instant::now();
+        let refilled = (left + now.duration_since(since).as_secs_f64() * self.rate as f64)
+            .min(self.rate as f64);
+        if only_if_there && refilled < bytes as f64 {
+            *budget = (refilled, now);
+            return None;
+        }
+        *budget = (refilled - bytes as f64, now);
+        Some(budget.0)
+    }
+
+    /// take `bytes` out of the budget, waiting for the rate to catch up if that goes over it
+    pub async fn take(&self, bytes: usize) {
+        if let Some(left) = self.spend(bytes, false)
+            && left < 0.0
+        {
+            tokio::time::sleep(Duration::from_secs_f64(-left / self.rate as f64)).await;
+        }
+    }
+
+    /// take `bytes` if they're in the budget right now, for what can be dropped instead
+    pub fn try_take(&self, bytes: usize) -> bool {
+        self.spend(bytes, true).is_some()
+    }
+}
+
+/// Counts the frames of one connection, or of a whole node
+#[derive(Default, Debug)]
+pub struct TrafficMeter {
+    kinds: Mutex<BTreeMap<u16, Traffic>>,
+}
+
+impl TrafficMeter {
+    /// a frame of `bytes` holding a message of `kind` was written
+    pub fn sent(&self, kind: u16, bytes: usize) {
+        let mut kinds = self.kinds.lock().unwrap();
+        let traffic = kinds.entry(kind).or_default();
+        traffic.messages_sent += 1;
+        traffic.bytes_sent += bytes as u64;
+    }
+
+    /// a frame of `bytes` holding a message of `kind` was read
+    pub fn received(&self, kind: u16, bytes: usize) {
+        let mut kinds = self.kinds.lock().unwrap();
+        let traffic = kinds.entry(kind).or_default();
+        traffic.messages_received += 1;
+        traffic.bytes_received += bytes as u64;
+    }
+
+    pub fn stats(&self) -> TrafficStats {
+        let mut stats = TrafficStats::default();
+        for (kind, traffic) in self.kinds.lock().unwrap().iter() {
+            stats.add(*kind, traffic);
+        }
+        stats
+    }
+}
+
+/// Frames and their bytes both ways
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct Traffic {
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+}
+
+impl Traffic {
+    pub fn merge(&mut self, other: &Traffic) {
+        self.messages_sent += other.messages_sent;
+        self.bytes_sent += other.bytes_sent;
+        self.messages_received += other.messages_received;
+        self.bytes_received += other.bytes_received;
+    }
+}
+
+/// What a `TrafficMeter` counted, in all and by the name of the message, see `Message::kind_name`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    pub total: Traffic,
+    /// kinds from newer versions are all counted as "unknown"
+    pub messages: BTreeMap<String, Traffic>,
+}
+
+impl TrafficStats {
+    fn add(&mut self, kind: u16, traffic: &Traffic) {
+        self.total.merge(traffic);
+        let name = Message::kind_name(kind).unwrap_or("unknown");
+        self.messages
+            .entry(name.to_string())
+            .or_default()
+            .merge(traffic);
+    }
+
+    /// both counted together, e.g. a connection that closed into the node's
+    pub fn merge(&mut self, other: &TrafficStats) {
+        self.total.merge(&other.total);
+        for (name, traffic) in &other.messages {
+            self.messages
+                .entry(name.clone())
+                .or_default()
+                .merge(traffic);
+        }
+    }
+}
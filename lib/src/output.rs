@@ -0,0 +1,248 @@
+//! What the node, miner and wallet print with `--json`, so scripts don't have to read the text
+//! meant for people. Fields only get added here, never renamed or removed. Hashes, keys and
+//! targets are lowercase hex, amounts satoshis and times RFC 3339.
+
+use crate::{
+    U256,
+    network::{
+        CompressionStats, MemoryUsage, NodeStatus, Propagation, TaskStatus, Traffic, fingerprint,
+    },
+    types::{AddressActivity, MempoolPolicy},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+fn target_hex(target: U256) -> String {
+    let mut bytes = [0; 32];
+    target.to_big_endian(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// `node --status` with `--json`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StatusOutput {
+    pub version: String,
+    pub protocol_version: u32,
+    pub network: String,
+    pub regtest: bool,
+    pub height: u64,
+    pub best_hash: String,
+    pub target: String,
+    pub difficulty: f64,
+    pub mempool: MempoolOutput,
+    /// fee rates in sats a byte, sizes in bytes, `max_transaction_age` in seconds
+    #[serde(default)]
+    pub policy: MempoolPolicy,
+    pub peers: usize,
+    pub light_wallets: usize,
+    /// seconds
+    pub uptime: u64,
+    pub syncing: bool,
+    /// seconds the network's clocks are ahead of the node's
+    pub clock_offset: i64,
+    pub tasks: Vec<TaskStatus>,
+    /// see `network::fingerprint`
+    #[serde(default)]
+    pub identity: Option<String>,
+    #[serde(default)]
+    pub checkpoint: Option<u64>,
+    #[serde(default)]
+    pub finalized: Option<u64>,
+    #[serde(default)]
+    pub own_blocks: Propagation,
+    #[serde(default)]
+    pub own_transactions: Propagation,
+    #[serde(default)]
+    pub compression: CompressionStats,
+    #[serde(default)]
+    pub traffic: Traffic,
+    /// bytes a second per peer
+    #[serde(default)]
+    pub upload_limit: Option<u64>,
+    #[serde(default)]
+    pub download_limit: Option<u64>,
+    #[serde(default)]
+    pub handler_panics: u64,
+    /// bytes, by what they're held for
+    #[serde(default)]
+    pub memory: MemoryUsage,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MempoolOutput {
+    pub transactions: usize,
+    pub size: usize,
+    pub fees: u64,
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+impl From<&NodeStatus> for StatusOutput {
+    fn from(status: &NodeStatus) -> Self {
+        Self {
+            version: status.version.clone(),
+            protocol_version: status.protocol_version,
+            network: status.network.clone(),
+            regtest: status.regtest,
+            height: status.height,
+            best_hash: status.best_hash.to_hex(),
+            target: target_hex(status.target),
+            difficulty: status.difficulty,
+            mempool: MempoolOutput {
+                transactions: status.mempool.transactions,
+                size: status.mempool.size,
+                fees: status.mempool.fees.to_sat(),
+                oldest: status.mempool.oldest,
+            },
+            policy: status.policy.clone(),
+            peers: status.peers,
+            light_wallets: status.light_wallets,
+            uptime: status.uptime,
+            syncing: status.syncing,
+            clock_offset: status.clock_offset,
+            tasks: status.tasks.clone(),
+            identity: status.identity.as_ref().map(fingerprint),
+            checkpoint: status.checkpoint,
+            finalized: status.finalized,
+            own_blocks: status.own_blocks.clone(),
+            own_transactions: status.own_transactions.clone(),
+            compression: status.compression.clone(),
+            traffic: status.traffic,
+            upload_limit: status.upload_limit,
+            download_limit: status.download_limit,
+            handler_panics: status.handler_panics,
+            memory: status.memory.clone(),
+        }
+    }
+}
+
+/// One line the miner prints with `--json`, e.g. `{"event":"accepted","hash":"00ab.."}`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MinerEvent {
+    /// a new template to mine on
+    Template {
+        target: String,
+    },
+    /// the template went stale, a new one gets fetched
+    Stale,
+    /// a hash meeting the block target, about to be submitted
+    Mined {
+        hash: String,
+    },
+    /// the node took the block
+    Accepted {
+        hash: String,
+    },
+    /// the node took a share, `shares` so far at about `hashrate` hashes a second
+    Share {
+        hash: String,
+        shares: u64,
+        hashrate: f64,
+    },
+    Rejected {
+        code: String,
+        reason: String,
+    },
+    /// a block the node took earlier isn't on its chain anymore
+    Orphaned {
+        hash: String,
+    },
+}
+
+impl MinerEvent {
+    pub fn template(target: U256) -> Self {
+        MinerEvent::Template {
+            target: target_hex(target),
+        }
+    }
+}
+
+/// `wallet --no-ui --json balance`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BalanceOutput {
+    pub balance: u64,
+    /// what of it can be spent right now
+    pub spendable: u64,
+    /// what of it is kept out of coin selection
+    #[serde(default)]
+    pub frozen: u64,
+}
+
+/// `wallet --no-ui --json send`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SentOutput {
+    pub txid: String,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+/// one entry of `wallet --no-ui --json history`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub height: u64,
+    pub txid: String,
+    pub received: u64,
+    pub sent: u64,
+    /// see `finality`
+    #[serde(default)]
+    pub finalized: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(activity: &AddressActivity, finalized: bool) -> Self {
+        Self {
+            height: activity.height,
+            txid: activity.txid.to_hex(),
+            received: activity.received.to_sat(),
+            sent: activity.sent.to_sat(),
+            finalized,
+        }
+    }
+}
+
+/// one entry of `wallet --no-ui --json receive`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct KeyOutput {
+    pub key_file: String,
+    pub pubkey: String,
+    /// for the keys `receive --new` handed out with one
+    pub label: Option<String>,
+}
+
+/// one entry of `wallet --no-ui --json contacts`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ContactOutput {
+    pub name: String,
+    pub key_file: String,
+    /// `None` if the key file is missing
+    pub pubkey: Option<String>,
+}
+
+/// one transaction of `wallet export-transactions`, a row of the CSV or an entry of the JSON
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TransactionRecord {
+    pub txid: String,
+    pub height: u64,
+    /// when the block holding it was mined
+    pub time: DateTime<Utc>,
+    pub confirmations: u64,
+    /// see `finality`
+    pub finalized: bool,
+    /// hashes of the outputs it spends
+    pub inputs: Vec<String>,
+    pub outputs: Vec<RecordOutput>,
+    /// paid to the wallet's keys
+    pub received: u64,
+    /// spent from the wallet's keys
+    pub sent: u64,
+    /// only known for transactions the wallet's keys signed every input of
+    pub fee: Option<u64>,
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecordOutput {
+    pub pubkey: String,
+    pub value: u64,
+}
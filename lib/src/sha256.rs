@@ -1,33 +1,80 @@
 use serde::{Deserialize, Serialize};
 use sha256::digest;
+use subtle::ConstantTimeEq;
+
+use std::cmp::Ordering;
 use std::fmt;
+use std::str::FromStr;
 
 use crate::U256;
+use crate::error::BtcError;
 
-#[derive(Clone, Copy, Serialize, Debug, Deserialize, PartialEq, Eq, Hash)]
+/// A sha256 digest. Numerically it is the digest bytes read as a big-endian 256-bit integer, which
+/// is what `matches_target` compares and what the hex form shows, so a hash with leading zeros
+/// in hex is a small number.
+#[derive(Clone, Copy, Serialize, Debug, Deserialize, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct Hash(U256);
 
 impl Hash {
     /// hash anything that can be serde Serialized via ciborium
     pub fn hash<T: serde::Serialize>(data: &T) -> Self {
+        Self::of_bytes(&Self::preimage(data))
+    }
+
+    /// the bytes `hash` puts through sha256, the CBOR of `data`
+    pub(crate) fn preimage<T: serde::Serialize>(data: &T) -> Vec<u8> {
         let mut serialized: Vec<u8> = vec![];
 
         if let Err(e) = ciborium::into_writer(data, &mut serialized) {
             panic!("Failed to serialize data: {:?}. This should not happen", e);
         }
-        let hash = digest(&serialized);
-        let hash_bytes = hex::decode(hash).unwrap();
+        serialized
+    }
+
+    /// sha256 of raw bytes, the hex form is what `sha256sum` prints for a file of them
+    pub fn of_bytes(data: &[u8]) -> Self {
+        Self::from_digest(&digest(data))
+    }
+
+    /// the hex digest the `sha256` crate gives back, read as a big-endian number
+    pub(crate) fn from_digest(hex_digest: &str) -> Self {
+        let hash_bytes = hex::decode(hex_digest).unwrap();
         let hash_array: [u8; 32] = hash_bytes.as_slice().try_into().unwrap();
         Hash(U256::from(&hash_array))
     }
 
+    /// little-endian bytes of the number, this is what signatures commit to
     pub fn as_bytes(&self) -> [u8; 32] {
         let mut bytes: Vec<u8> = vec![0; 32];
         self.0.to_little_endian(&mut bytes);
         bytes.as_slice().try_into().unwrap()
     }
 
-    /// if block hash is <= arg, returns true
+    /// the digest bytes as sha256 produced them, big-endian
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        self.0.to_big_endian(&mut bytes);
+        bytes
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        Hash(U256::from_big_endian(&bytes))
+    }
+
+    /// 64 lowercase hex characters of the big-endian digest
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_be_bytes())
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, BtcError> {
+        let bytes = hex::decode(s).map_err(|_| BtcError::InvalidHash)?;
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| BtcError::InvalidHash)?;
+        Ok(Self::from_be_bytes(bytes))
+    }
+
+    /// if block hash is <= arg, returns true. Both sides are plain numbers, so this works the same
+    /// whatever byte order the hash is later printed or sent in
     pub fn matches_target(&self, target: U256) -> bool {
         self.0 <= target
     }
@@ -38,8 +85,42 @@ impl Hash {
     }
 }
 
+/// Constant time, so comparing against a secret hash leaks nothing through timing
+impl PartialEq for Hash {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_be_bytes().ct_eq(&other.to_be_bytes()).into()
+    }
+}
+
+impl std::hash::Hash for Hash {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+/// Orders hashes as numbers, the same order targets use
+impl Ord for Hash {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl PartialOrd for Hash {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl fmt::Display for Hash {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:x}", self.0)
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+impl FromStr for Hash {
+    type Err = BtcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
     }
 }
@@ -0,0 +1,471 @@
+//! Deterministic simulation of a network of nodes in one process. Nodes talk through an in-memory
+//! queue ordered by arrival on a virtual clock, so a scenario plays out the same way every time
+//! and on every machine. Links can be slowed down or cut by partitions, and nodes can drop off the
+//! network and come back.
+//!
+//! Nodes gossip new blocks and transactions to their peers and catch up through
+//! `AskDifference`/`FetchBlock` like the real node does. The chain can't reorganize yet, so two
+//! sides of a partition that both mined stay on their own forks.
+
+use crate::{
+    chain_params::ChainParams,
+    consensus::{Consensus, ProofOfStake},
+    crypto::PrivateKey,
+    error::{BtcError, Result},
+    network::Message,
+    sha256::Hash,
+    types::{BlockBuilder, Blockchain, Transaction},
+};
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+pub type NodeId = usize;
+
+/// latency of links nobody set one for, in milliseconds
+pub const DEFAULT_LATENCY_MS: i64 = 100;
+/// a node stops waiting for a peer it's catching up with after this many seconds
+const SYNC_TIMEOUT_SECS: i64 = 30;
+
+/// One step of a scenario, see `Simulation::run`
+#[derive(Clone, Debug)]
+pub enum Step {
+    /// let time pass, delivering whatever arrives meanwhile
+    Advance(Duration),
+    /// deliver messages until none are in flight
+    Settle,
+    /// the node mines a block on its tip paying its own key, on a proof-of-stake chain it has to
+    /// lead the slot the clock is in
+    Mine(NodeId),
+    /// a wallet submits a transaction to the node
+    Submit(NodeId, Transaction),
+    /// Split the network, nodes only reach nodes of their own group. Nodes in none of the groups
+    /// form one more together
+    Partition(Vec<Vec<NodeId>>),
+    Heal,
+    /// latency of every link without one of its own
+    Latency(Duration),
+    LinkLatency(NodeId, NodeId, Duration),
+    Connect(NodeId, NodeId),
+    Disconnect(NodeId, NodeId),
+    /// the node drops off the network, whatever is sent to it meanwhile is lost
+    Offline(NodeId),
+    /// the node comes back and asks its peers what it missed
+    Online(NodeId),
+}
+
+pub struct SimNode {
+    blockchain: Blockchain,
+    key: PrivateKey,
+    peers: BTreeSet<NodeId>,
+    online: bool,
+    /// blocks and transactions already handled, so gossip stops going around
+    seen: HashSet<Hash>,
+    /// the peer being caught up with, since when and how many blocks are still to come
+    syncing: Option<(NodeId, DateTime<Utc>, u64)>,
+}
+
+impl SimNode {
+    fn new(params: Arc<ChainParams>) -> Result<Self> {
+        let mut blockchain = Blockchain::with_params(params.clone());
+        if let Some(genesis) = params.genesis_block() {
+            blockchain.add_block(genesis?)?;
+        }
+        Ok(SimNode {
+            blockchain,
+            key: PrivateKey::new_key(),
+            peers: BTreeSet::new(),
+            online: true,
+            seen: HashSet::new(),
+            syncing: None,
+        })
+    }
+
+    pub fn blockchain(&self) -> &Blockchain {
+        &self.blockchain
+    }
+
+    /// the key its blocks pay
+    pub fn key(&self) -> &PrivateKey {
+        &self.key
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.peers.iter().copied()
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.online
+    }
+
+    fn tip(&self) -> Option<Hash> {
+        self.blockchain.blocks().last().map(|block| block.hash())
+    }
+
+    fn is_syncing(&self, now: DateTime<Utc>) -> bool {
+        self.syncing
+            .is_some_and(|(_, since, _)| now - since < Duration::seconds(SYNC_TIMEOUT_SECS))
+    }
+
+    /// `message` for every peer but `except`
+    fn gossip(
+        &self,
+        except: Option<NodeId>,
+        message: impl Fn() -> Message,
+    ) -> Vec<(NodeId, Message)> {
+        self.peers
+            .iter()
+            .filter(|peer| Some(**peer) != except)
+            .map(|peer| (*peer, message()))
+            .collect()
+    }
+
+    /// handle a message from `from`, returns what to send to whom in reply
+    fn receive(
+        &mut self,
+        now: DateTime<Utc>,
+        from: NodeId,
+        message: Message,
+    ) -> Vec<(NodeId, Message)> {
+        use Message::*;
+        match message {
+            NewTransaction(transaction) => {
+                let hash = transaction.hash();
+                if self.seen.contains(&hash)
+                    || self
+                        .blockchain
+                        .add_to_mempool_at(transaction.clone(), now)
+                        .is_err()
+                {
+                    return vec![];
+                }
+                self.seen.insert(hash);
+                self.gossip(Some(from), || NewTransaction(transaction.clone()))
+            }
+            NewBlock(block) => {
+                if let Some((peer, since, pending)) = self.syncing
+                    && peer == from
+                {
+                    self.syncing = (pending > 1).then_some((peer, since, pending - 1));
+                }
+
+                let hash = block.hash();
+                if self.seen.contains(&hash) {
+                    return vec![];
+                }
+                match self.blockchain.add_block(block.clone()) {
+                    Ok(()) => {
+                        self.seen.insert(hash);
+                        self.gossip(Some(from), || NewBlock(block.clone()))
+                    }
+                    // it builds on blocks this node doesn't have
+                    Err(BtcError::PrevHashMismatch { .. }) if !self.is_syncing(now) => {
+                        self.syncing = Some((from, now, 0));
+                        let height = self.blockchain.block_height() as i32;
+                        vec![(from, AskDifference(height))]
+                    }
+                    Err(_) => vec![],
+                }
+            }
+            AskDifference(height) => {
+                let count = self.blockchain.block_height() as i32 - height;
+                vec![(from, Difference(count))]
+            }
+            Difference(count) => {
+                let syncing_with = self.syncing.map(|(peer, _, _)| peer);
+                if count <= 0 {
+                    if syncing_with == Some(from) {
+                        self.syncing = None;
+                    }
+                    return vec![];
+                }
+                if self.is_syncing(now) && syncing_with != Some(from) {
+                    return vec![];
+                }
+                self.syncing = Some((from, now, count as u64));
+                let height = self.blockchain.block_height() as usize;
+                (height..height + count as usize)
+                    .map(|height| (from, FetchBlock(height)))
+                    .collect()
+            }
+            FetchBlock(height) => match self.blockchain.blocks().nth(height) {
+                Some(block) => vec![(from, NewBlock(block.clone()))],
+                None => vec![],
+            },
+            // wallet and miner messages, nobody sends those here
+            _ => vec![],
+        }
+    }
+}
+
+/// a message on its way
+struct Envelope {
+    from: NodeId,
+    to: NodeId,
+    bytes: Vec<u8>,
+}
+
+pub struct Simulation {
+    nodes: Vec<SimNode>,
+    clock: DateTime<Utc>,
+    /// messages in flight by when they arrive, those arriving together in the order they were sent
+    queue: BTreeMap<(DateTime<Utc>, u64), Envelope>,
+    sent: u64,
+    latency: Duration,
+    /// by the lower id first
+    link_latency: BTreeMap<(NodeId, NodeId), Duration>,
+    /// which group every node is in while the network is split
+    partition: Option<BTreeMap<NodeId, usize>>,
+}
+
+impl Simulation {
+    /// `nodes` nodes all connected to each other. The clock starts at 2025-01-01 whenever it runs.
+    /// Work chains have to be regtest ones, their blocks are mined at `REGTEST_TARGET`
+    pub fn new(nodes: usize, params: Arc<ChainParams>) -> Result<Self> {
+        let mut nodes = (0..nodes)
+            .map(|_| SimNode::new(params.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let ids: Vec<NodeId> = (0..nodes.len()).collect();
+        for (id, node) in nodes.iter_mut().enumerate() {
+            node.peers = ids.iter().copied().filter(|peer| *peer != id).collect();
+        }
+        Ok(Simulation {
+            nodes,
+            clock: Utc.timestamp_opt(1_735_689_600, 0).unwrap(),
+            queue: BTreeMap::new(),
+            sent: 0,
+            latency: Duration::milliseconds(DEFAULT_LATENCY_MS),
+            link_latency: BTreeMap::new(),
+            partition: None,
+        })
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock
+    }
+
+    pub fn node(&self, id: NodeId) -> &SimNode {
+        &self.nodes[id]
+    }
+
+    pub fn nodes(&self) -> &[SimNode] {
+        &self.nodes
+    }
+
+    /// messages sent but not delivered or dropped yet
+    pub fn in_flight(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// whether every online node has the same tip
+    pub fn converged(&self) -> bool {
+        let mut tips = self
+            .nodes
+            .iter()
+            .filter(|node| node.online)
+            .map(SimNode::tip);
+        let Some(first) = tips.next() else {
+            return true;
+        };
+        tips.all(|tip| tip == first)
+    }
+
+    fn link(a: NodeId, b: NodeId) -> (NodeId, NodeId) {
+        (a.min(b), a.max(b))
+    }
+
+    fn send(&mut self, from: NodeId, to: NodeId, message: Message) {
+        if !self.nodes[from].online {
+            return;
+        }
+        let Ok(bytes) = message.encode() else {
+            return;
+        };
+        let latency = self
+            .link_latency
+            .get(&Self::link(from, to))
+            .copied()
+            .unwrap_or(self.latency);
+        self.queue.insert(
+            (self.clock + latency, self.sent),
+            Envelope { from, to, bytes },
+        );
+        self.sent += 1;
+    }
+
+    fn send_all(&mut self, from: NodeId, messages: Vec<(NodeId, Message)>) {
+        for (to, message) in messages {
+            self.send(from, to, message);
+        }
+    }
+
+    /// Whether a message gets through right now. Links are checked on arrival, so cutting one
+    /// loses what's on its way too
+    fn reachable(&self, from: NodeId, to: NodeId) -> bool {
+        let same_group = self.partition.as_ref().is_none_or(|groups| {
+            groups.get(&from).unwrap_or(&usize::MAX) == groups.get(&to).unwrap_or(&usize::MAX)
+        });
+        self.nodes[to].online && self.nodes[to].peers.contains(&from) && same_group
+    }
+
+    fn deliver(&mut self, envelope: Envelope) {
+        let Envelope { from, to, bytes } = envelope;
+        if !self.reachable(from, to) {
+            return;
+        }
+        let Ok(message) = Message::decode(&bytes) else {
+            return;
+        };
+        let replies = self.nodes[to].receive(self.clock, from, message);
+        self.send_all(to, replies);
+    }
+
+    fn expire_mempools(&mut self) {
+        for node in &mut self.nodes {
+            let max_age = node.blockchain.mempool_policy().max_age();
+            node.blockchain.expire_mempool(self.clock - max_age);
+        }
+    }
+
+    /// let `duration` pass, delivering everything arriving until then in order
+    pub fn advance(&mut self, duration: Duration) {
+        let until = self.clock + duration;
+        while let Some(entry) = self.queue.first_entry()
+            && entry.key().0 <= until
+        {
+            let ((arrival, _), envelope) = entry.remove_entry();
+            self.clock = arrival;
+            self.deliver(envelope);
+        }
+        self.clock = until;
+        self.expire_mempools();
+    }
+
+    /// deliver messages until none are in flight, the clock moves on to the last arrival
+    pub fn settle(&mut self) {
+        while let Some(((arrival, _), envelope)) = self.queue.pop_first() {
+            self.clock = self.clock.max(arrival);
+            self.deliver(envelope);
+        }
+        self.expire_mempools();
+    }
+
+    /// Mine a block on `id`'s tip with the best of its mempool, paying its key, and send it to
+    /// its peers
+    pub fn mine(&mut self, id: NodeId) -> Result<Hash> {
+        let node = &mut self.nodes[id];
+        // blocks mined in the same instant still need increasing timestamps
+        let timestamp = match node.blockchain.blocks().last() {
+            Some(tip) if tip.header.timestamp >= self.clock => {
+                tip.header.timestamp + Duration::seconds(1)
+            }
+            _ => self.clock,
+        };
+        let mut block = BlockBuilder::on_top_of(&node.blockchain)
+            .timestamp(timestamp)
+            .coinbase_to(
+                node.key.public_key(),
+                node.blockchain.calculate_block_reward(),
+            )
+            .add_txs(
+                node.blockchain
+                    .template_transactions(crate::BLOCK_TRANSACTION_CAP - 1),
+            )
+            .finalize_with_fees(node.blockchain.utxos())?;
+        match node.blockchain.params().consensus {
+            Consensus::ProofOfWork => block
+                .header
+                .mine_with_target_override(crate::REGTEST_TARGET),
+            Consensus::ProofOfStake => ProofOfStake::sign(&mut block, &node.key),
+        }
+
+        let hash = block.hash();
+        node.blockchain.add_block(block.clone())?;
+        node.seen.insert(hash);
+        let gossip = node.gossip(None, || Message::NewBlock(block.clone()));
+        self.send_all(id, gossip);
+        Ok(hash)
+    }
+
+    /// hand `id` a transaction like a wallet would, it passes it on if it accepts it
+    pub fn submit(&mut self, id: NodeId, transaction: Transaction) -> Result<()> {
+        let node = &mut self.nodes[id];
+        node.blockchain
+            .add_to_mempool_at(transaction.clone(), self.clock)?;
+        node.seen.insert(transaction.hash());
+        let gossip = node.gossip(None, || Message::NewTransaction(transaction.clone()));
+        self.send_all(id, gossip);
+        Ok(())
+    }
+
+    pub fn connect(&mut self, a: NodeId, b: NodeId) {
+        self.nodes[a].peers.insert(b);
+        self.nodes[b].peers.insert(a);
+    }
+
+    pub fn disconnect(&mut self, a: NodeId, b: NodeId) {
+        self.nodes[a].peers.remove(&b);
+        self.nodes[b].peers.remove(&a);
+    }
+
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = latency;
+    }
+
+    pub fn set_link_latency(&mut self, a: NodeId, b: NodeId, latency: Duration) {
+        self.link_latency.insert(Self::link(a, b), latency);
+    }
+
+    /// see `Step::Partition`
+    pub fn partition(&mut self, groups: &[Vec<NodeId>]) {
+        self.partition = Some(
+            groups
+                .iter()
+                .enumerate()
+                .flat_map(|(group, ids)| ids.iter().map(move |id| (*id, group)))
+                .collect(),
+        );
+    }
+
+    pub fn heal(&mut self) {
+        self.partition = None;
+    }
+
+    pub fn take_offline(&mut self, id: NodeId) {
+        self.nodes[id].online = false;
+        self.nodes[id].syncing = None;
+    }
+
+    /// `id` comes back and asks every peer how far ahead it is, like a node starting up
+    pub fn bring_online(&mut self, id: NodeId) {
+        self.nodes[id].online = true;
+        let height = self.nodes[id].blockchain.block_height() as i32;
+        let asks = self.nodes[id].gossip(None, || Message::AskDifference(height));
+        self.send_all(id, asks);
+    }
+
+    /// play a scenario, stops at the first block or transaction a node refuses
+    pub fn run(&mut self, scenario: impl IntoIterator<Item = Step>) -> Result<()> {
+        for step in scenario {
+            match step {
+                Step::Advance(duration) => self.advance(duration),
+                Step::Settle => self.settle(),
+                Step::Mine(id) => {
+                    self.mine(id)?;
+                }
+                Step::Submit(id, transaction) => self.submit(id, transaction)?,
+                Step::Partition(groups) => self.partition(&groups),
+                Step::Heal => self.heal(),
+                Step::Latency(latency) => self.set_latency(latency),
+                Step::LinkLatency(a, b, latency) => self.set_link_latency(a, b, latency),
+                Step::Connect(a, b) => self.connect(a, b),
+                Step::Disconnect(a, b) => self.disconnect(a, b),
+                Step::Offline(id) => self.take_offline(id),
+                Step::Online(id) => self.bring_online(id),
+            }
+        }
+        Ok(())
+    }
+}
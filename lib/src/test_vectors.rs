@@ -0,0 +1,147 @@
+//! Known answer vectors for hashing and signing. Every signature scheme signs deterministically, so
+//! the expected values below only change if hashing, key derivation or nonce generation does.
+//! They are checked by `tests/test_vectors.rs` and are public so other implementations of the
+//! protocol can check themselves against this one.
+//!
+//! With the `test-vectors` feature, `steps` also gives out how a hash and a merkle root are put
+//! together one step at a time, for checking each step against a reference implementation.
+
+use crate::crypto::Algorithm;
+
+#[cfg(feature = "test-vectors")]
+pub mod steps;
+
+pub struct HashVector {
+    /// string that gets hashed, as `Hash::hash(&data)`
+    pub data: &'static str,
+    /// expected hash, formatted with `Display`
+    pub hash: &'static str,
+}
+
+pub struct SigningVector {
+    pub algorithm: Algorithm,
+    /// hex encoded secret key, see `PrivateKey::from_bytes`
+    pub private_key: &'static str,
+    /// hex encoded public key, see `PublicKey::to_bytes`
+    pub public_key: &'static str,
+    /// string whose hash gets signed with `Signature::sign_output`
+    pub message: &'static str,
+    /// expected hash of `message`
+    pub output_hash: &'static str,
+    /// hex encoded signature, see `Signature::to_bytes`
+    pub signature: &'static str,
+}
+
+pub const HASH_VECTORS: &[HashVector] = &[
+    HashVector {
+        data: "",
+        hash: "8d33f520a3c4cef80d2453aef81b612bfe1cb44c8b2025630ad38662763f13d3",
+    },
+    HashVector {
+        data: "abc",
+        hash: "a6d89baf01ac02637da09835b28485b2db68576834d01869fc15e36b124c617c",
+    },
+    HashVector {
+        data: "kme-btcrs",
+        hash: "9ab693ac7343d5a891a9decb586b315ca481260d1496d332365ed367cfc46b03",
+    },
+];
+
+pub const SIGNING_VECTORS: &[SigningVector] = &[
+    SigningVector {
+        algorithm: Algorithm::Secp256k1,
+        private_key: "0000000000000000000000000000000000000000000000000000000000000001",
+        public_key: "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        message: "",
+        output_hash: "8d33f520a3c4cef80d2453aef81b612bfe1cb44c8b2025630ad38662763f13d3",
+        signature: "3e40b86ff2c9cd1ca079a0ed73ca52dfcc93a1e75f2d889781d19355fe6ecef1454108d2555d8f66ba1bf4281913d368c967aa183ef8f47de8e4330f8c8a24a8",
+    },
+    SigningVector {
+        algorithm: Algorithm::Secp256k1,
+        private_key: "0000000000000000000000000000000000000000000000000000000000000001",
+        public_key: "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        message: "kme-btcrs",
+        output_hash: "9ab693ac7343d5a891a9decb586b315ca481260d1496d332365ed367cfc46b03",
+        signature: "f9f19610fd7c5e1cf590e348c0cd95f25bc9f14df6f909829cdf9aa8aa85b0f10a07daab05075f2fb029f002266dd7a4e07e69ba99fcfb61f4c2372d46dbb58d",
+    },
+    SigningVector {
+        algorithm: Algorithm::Secp256k1,
+        private_key: "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721",
+        public_key: "032c8c31fc9f990c6b55e3865a184a4ce50e09481f2eaeb3e60ec1cea13a6ae645",
+        message: "",
+        output_hash: "8d33f520a3c4cef80d2453aef81b612bfe1cb44c8b2025630ad38662763f13d3",
+        signature: "825de92a4b5793c42de5835e1b277465f413e9a0e9a867d11ce70e56944db2d10f9b723f19c85ffb1c7c148ddedf3d083db36fb270a84d91eea907f7c5a02ce1",
+    },
+    SigningVector {
+        algorithm: Algorithm::Secp256k1,
+        private_key: "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721",
+        public_key: "032c8c31fc9f990c6b55e3865a184a4ce50e09481f2eaeb3e60ec1cea13a6ae645",
+        message: "kme-btcrs",
+        output_hash: "9ab693ac7343d5a891a9decb586b315ca481260d1496d332365ed367cfc46b03",
+        signature: "5e61d429c2137d64c264d3f510b365143003e30e5ff20c4efdaff120c0aa27122d8e0f497ed0b6ac53ea7e23ec574ee8695096cdb86aefd19f24c57cc0c42e91",
+    },
+    SigningVector {
+        algorithm: Algorithm::Ed25519,
+        private_key: "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        public_key: "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+        message: "",
+        output_hash: "8d33f520a3c4cef80d2453aef81b612bfe1cb44c8b2025630ad38662763f13d3",
+        signature: "ef04f95f34e62b9df71219025dafdccc82984925bd78f988cc5881255c6ab7d85a37e6cedc161c2ba360422a6e37b7a1c2b72f82066687bc6e7c5f5ff3ab100c",
+    },
+    SigningVector {
+        algorithm: Algorithm::Ed25519,
+        private_key: "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        public_key: "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+        message: "kme-btcrs",
+        output_hash: "9ab693ac7343d5a891a9decb586b315ca481260d1496d332365ed367cfc46b03",
+        signature: "9bc7c675e899dcae0c9c55f73a9a0f4f97975e255faffae85d378019ba061f2637365680aebf6f8c9bfcbb652f79c4007fbeeb4c4bdc58cc15e65734d2563600",
+    },
+    SigningVector {
+        algorithm: Algorithm::Ed25519,
+        private_key: "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+        public_key: "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+        message: "",
+        output_hash: "8d33f520a3c4cef80d2453aef81b612bfe1cb44c8b2025630ad38662763f13d3",
+        signature: "cd3a874e73cd14b56fd06ad89b1224662077a0e3fe8c17810b03b09323ec347ac27f23581fbf78293064d450a87faf41e31deeb52be22e607520b2dc77f90403",
+    },
+    SigningVector {
+        algorithm: Algorithm::Ed25519,
+        private_key: "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+        public_key: "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+        message: "kme-btcrs",
+        output_hash: "9ab693ac7343d5a891a9decb586b315ca481260d1496d332365ed367cfc46b03",
+        signature: "b5043d62c8cd15f8456a6348e5a3ab060d4d98faa4fbacbc9c3ea0e2db7ddfd0f2f1907a31ee1dd849d83499015c27f57042b7e5d93d6a217ecb5b356d4b8701",
+    },
+    SigningVector {
+        algorithm: Algorithm::Schnorr,
+        private_key: "0000000000000000000000000000000000000000000000000000000000000003",
+        public_key: "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+        message: "",
+        output_hash: "8d33f520a3c4cef80d2453aef81b612bfe1cb44c8b2025630ad38662763f13d3",
+        signature: "0306313626a7228875506600b7c12e66752d1fbdefa9b31bf0df3752071649cd6efd95534bd4dd4accf7c04b4e6078fca914bcea14151c10cd45948a767683a767",
+    },
+    SigningVector {
+        algorithm: Algorithm::Schnorr,
+        private_key: "0000000000000000000000000000000000000000000000000000000000000003",
+        public_key: "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+        message: "kme-btcrs",
+        output_hash: "9ab693ac7343d5a891a9decb586b315ca481260d1496d332365ed367cfc46b03",
+        signature: "03d27c2ae81223d60e1e519674976dc9fb1388706c6eb5399241b2e8e6b5603e226f3ff25b9f73bceca1ea5396abe7379dff9f82074450ed3acf7a448c2d11b891",
+    },
+    SigningVector {
+        algorithm: Algorithm::Schnorr,
+        private_key: "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef",
+        public_key: "02dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659",
+        message: "",
+        output_hash: "8d33f520a3c4cef80d2453aef81b612bfe1cb44c8b2025630ad38662763f13d3",
+        signature: "0347a0a1b13de5c2d7494621df14e708ec5b16fc5494c3909dcaf6eb282593c811b2177a5a9e2f02c1f9913656999cca6e3040e1b1bb96f484d0ec177d9ff4afcc",
+    },
+    SigningVector {
+        algorithm: Algorithm::Schnorr,
+        private_key: "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef",
+        public_key: "02dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659",
+        message: "kme-btcrs",
+        output_hash: "9ab693ac7343d5a891a9decb586b315ca481260d1496d332365ed367cfc46b03",
+        signature: "039c01453df482faf80f6a5850edbd000469c7d41692357dfa6f335a32082f6306d6be2629e75096a6d9dd0bcefd3ffff965601bd133156262c2b1fc571dcd4c22",
+    },
+];
@@ -0,0 +1,43 @@
+//! How a hash and a merkle root are put together, one step at a time. The sha256 compression
+//! itself is the `sha256` crate's, what's here is everything around it: the bytes that get
+//! hashed, how the digest becomes a `Hash`, and how hashes pair up into a tree.
+
+use crate::{sha256::Hash, types::Transaction, util};
+
+use serde::Serialize;
+
+/// the bytes `Hash::hash` puts through sha256
+pub fn preimage<T: Serialize>(data: &T) -> Vec<u8> {
+    Hash::preimage(data)
+}
+
+/// what sha256 makes of `data`, as hex, before it becomes a `Hash`
+pub fn hex_digest(data: &[u8]) -> String {
+    sha256::digest(data)
+}
+
+/// the `Hash` of a hex digest
+pub fn from_hex_digest(hex_digest: &str) -> Hash {
+    Hash::from_digest(hex_digest)
+}
+
+/// the tree's bottom layer, a hash for each transaction
+pub fn merkle_leaves(transactions: &[Transaction]) -> Vec<Hash> {
+    transactions.iter().map(Hash::hash).collect()
+}
+
+pub fn merkle_parent(left: Hash, right: Hash) -> Hash {
+    util::merkle_parent(left, right)
+}
+
+/// every layer of the tree bottom up, the last one is the root alone
+pub fn merkle_layers(transactions: &[Transaction]) -> Vec<Vec<Hash>> {
+    let mut layers = vec![merkle_leaves(transactions)];
+    while let Some(layer) = layers.last()
+        && layer.len() > 1
+    {
+        let above = util::merkle_layer(layer);
+        layers.push(above);
+    }
+    layers
+}
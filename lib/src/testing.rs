@@ -0,0 +1,65 @@
+//! `Arbitrary` implementations for property tests and fuzzing, the types of the chain and the
+//! network derive theirs with the `testing` feature. Keys are always valid keys and signatures
+//! real signatures, just not necessarily over anything that makes sense, so generated data gets
+//! past parsing and into the checks that come after it.
+
+use crate::{
+    U256,
+    crypto::{Algorithm, PrivateKey, PublicKey, Signature},
+    sha256::Hash,
+    types::{Payout, PayoutSplit},
+};
+
+use arbitrary::{Arbitrary, Error, Result, Unstructured};
+
+impl<'a> Arbitrary<'a> for U256 {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(U256(u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for PrivateKey {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let algorithm = Algorithm::arbitrary(u)?;
+        let secret: [u8; 32] = u.arbitrary()?;
+        // zero or above the curve order isn't a key, those few get a fixed one instead
+        PrivateKey::from_bytes(algorithm, &secret)
+            .or_else(|_| PrivateKey::from_bytes(algorithm, &[1; 32]))
+            .map_err(|_| Error::IncorrectFormat)
+    }
+}
+
+impl<'a> Arbitrary<'a> for PublicKey {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(PrivateKey::arbitrary(u)?.public_key())
+    }
+}
+
+impl<'a> Arbitrary<'a> for Payout {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let keys = u.int_in_range(1..=4)?;
+        let mut left = 100;
+        let mut splits = vec![];
+        for i in 1..=keys {
+            // every key after this one needs at least 1%
+            let percent = if i == keys {
+                left
+            } else {
+                u.int_in_range(1..=left - (keys - i))?
+            };
+            left -= percent;
+            splits.push(PayoutSplit {
+                pubkey: PublicKey::arbitrary(u)?,
+                percent,
+            });
+        }
+        Payout::new(splits).map_err(|_| Error::IncorrectFormat)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Signature {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let key = PrivateKey::arbitrary(u)?;
+        Ok(Signature::sign_output(&Hash::arbitrary(u)?, &key))
+    }
+}
@@ -1,7 +1,24 @@
+//! The chain's data types. The submodules are private, everything is used through the
+//! re-exports below, e.g. `btclib::types::Block`, so there's one path to each type.
+
+mod amount;
 mod block;
 mod blockchain;
+mod builder;
+mod invoice;
+mod payout;
 mod transaction;
 
-pub use block::{Block, BlockHeader};
-pub use blockchain::Blockchain;
+pub use amount::{Amount, ParseAmountError, SATS_PER_BTC};
+pub use block::{Block, BlockHeader, COMPACT_HEADER_SIZE};
+pub use blockchain::{
+    AddressActivity, AddressScan, BlockFile, BlockFileReport, BlockUndo, Blockchain, ChainStats,
+    CheckpointSignature, DoubleSpend, DoubleSpendOutcome, Eviction, EvictionReason, ExportFormat,
+    Federation, Holder, Mempool, MempoolAcceptance, MempoolPolicy, MempoolStats, MempoolTxInfo,
+    Recovery, SelfCheck, SignedCheckpoint, SupplyBucket, UtxoChecksum, UtxoDiff, UtxoSnapshot,
+    ValidatedBlock, Wal, WalRecord,
+};
+pub use builder::{BlockBuilder, TransactionBuilder};
+pub use invoice::{INVOICE_URI_SCHEME, Invoice};
+pub use payout::{Payout, PayoutSplit};
 pub use transaction::{Transaction, TransactionInput, TransactionOutput};
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// satoshis in one bitcoin
+pub const SATS_PER_BTC: u64 = 100_000_000;
+
+/// An amount of satoshis. There is deliberately no `+`/`-`, arithmetic goes through the checked
+/// methods so a bad fee calculation is an error instead of a wrapped or panicking `u64`.
+/// Serializes exactly like the `u64` it wraps.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const ONE_SAT: Amount = Amount(1);
+    pub const ONE_BTC: Amount = Amount(SATS_PER_BTC);
+    /// `crate::MAX_MONEY` as an amount
+    pub const MAX_MONEY: Amount = Amount(crate::MAX_MONEY);
+
+    pub const fn from_sat(sats: u64) -> Self {
+        Amount(sats)
+    }
+
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// Rounded to the nearest satoshi, since most decimal amounts aren't exact in an `f64`, e.g.
+    /// `0.29 * 1e8` is `28999999.999999996`. `None` if it is negative, not finite or above
+    /// `MAX_MONEY`
+    pub fn from_btc(btc: f64) -> Option<Self> {
+        let sats = btc * SATS_PER_BTC as f64;
+        if !(0.0..=Self::MAX_MONEY.0 as f64).contains(&sats) {
+            return None;
+        }
+        Some(Amount(sats.round() as u64))
+    }
+
+    pub fn to_btc(self) -> f64 {
+        self.0 as f64 / SATS_PER_BTC as f64
+    }
+
+    /// whether this amount could exist at all, i.e. is not above `MAX_MONEY`
+    pub fn is_valid(self) -> bool {
+        self <= Self::MAX_MONEY
+    }
+
+    /// `None` on overflow or when the sum goes above `MAX_MONEY`
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .filter(|sum| sum.is_valid())
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0
+            .checked_mul(factor)
+            .map(Amount)
+            .filter(|product| product.is_valid())
+    }
+
+    pub fn checked_div(self, divisor: u64) -> Option<Amount> {
+        self.0.checked_div(divisor).map(Amount)
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    /// add up amounts, `None` if the total is not a valid amount
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, |sum, amount| sum.checked_add(amount))
+    }
+}
+
+/// formatted in BTC with all 8 decimals, e.g. `0.00012000 BTC`
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:08} BTC",
+            self.0 / SATS_PER_BTC,
+            self.0 % SATS_PER_BTC
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAmountError(String);
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid amount: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseAmountError {}
+
+/// Parses a BTC amount with up to 8 decimals (`1.5`, `0.0001 BTC`) or satoshis with a `sat`
+/// suffix (`1500 sat`)
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseAmountError(s.to_string());
+        let s = s.trim();
+        let amount = if let Some(sats) = s.strip_suffix("sats").or(s.strip_suffix("sat")) {
+            Amount(sats.trim().parse().map_err(|_| error())?)
+        } else {
+            let btc = s.strip_suffix("BTC").unwrap_or(s).trim();
+            let (whole, fraction) = btc.split_once('.').unwrap_or((btc, ""));
+            if fraction.len() > 8 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+                return Err(error());
+            }
+            let whole: u64 = whole.parse().map_err(|_| error())?;
+            let fraction: u64 = format!("{fraction:0<8}").parse().map_err(|_| error())?;
+            whole
+                .checked_mul(SATS_PER_BTC)
+                .and_then(|sats| sats.checked_add(fraction))
+                .map(Amount)
+                .ok_or_else(error)?
+        };
+        if !amount.is_valid() {
+            return Err(error());
+        }
+        Ok(amount)
+    }
+}
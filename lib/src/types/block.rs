@@ -1,5 +1,7 @@
 use crate::{
     U256,
+    chain_params::ChainParams,
+    consensus::BlockSignature,
     error::{BtcError, Result},
     sha256::Hash,
     types::*,
@@ -8,18 +10,26 @@ use crate::{
 
 use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Write};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// bytes in `BlockHeader::to_compact`
+pub const COMPACT_HEADER_SIZE: usize = 116;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct Block {
     pub header: BlockHeader,
     pub transactions: Vec<Transaction>,
+    /// the slot leader's signature on proof-of-stake chains, see `consensus::ProofOfStake`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Box<BlockSignature>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct BlockHeader {
     /// the time when the block was created. This is and the `nonce` are the two fields that alter
     /// when mining blocks in our blockchain.
@@ -41,6 +51,7 @@ impl Block {
         Block {
             header,
             transactions,
+            signature: None,
         }
     }
 
@@ -51,136 +62,154 @@ impl Block {
     pub fn calculate_miner_fees(
         &self,
         utxos: &HashMap<Hash, (TransactionOutput, bool)>,
-    ) -> Result<u64> {
-        // TODO: is this correct? shouldn't it be a hashmap of inputs instead?
-        let mut inputs: HashMap<Hash, TransactionOutput> = HashMap::new();
-        let mut outputs: HashMap<Hash, TransactionOutput> = HashMap::new();
+    ) -> Result<Amount> {
+        let mut inputs: HashSet<Hash> = HashSet::new();
+        let mut outputs: HashMap<Hash, &TransactionOutput> = HashMap::new();
+        let mut fees = Amount::ZERO;
 
         // Skip coinbase transaction
         for transaction in self.transactions.iter().skip(1) {
+            let mut input_value = Amount::ZERO;
             for input in &transaction.inputs {
                 // inputs does not contain the values of the output so we need to match inputs to
                 // outputs
-                let prev_output = utxos
-                    .get(&input.prev_transaction_output_hash)
-                    .map(|(output, _)| output);
-                if prev_output.is_none() {
-                    return Err(BtcError::InvalidTransaction);
-                }
-                let prev_output = prev_output.unwrap();
+                let prev_output = spendable(utxos, &outputs, &input.prev_transaction_output_hash)?;
 
-                if inputs.contains_key(&input.prev_transaction_output_hash) {
-                    return Err(BtcError::InvalidTransaction);
+                if !inputs.insert(input.prev_transaction_output_hash) {
+                    return Err(BtcError::DoubleSpend(input.prev_transaction_output_hash));
                 }
-
-                inputs.insert(input.prev_transaction_output_hash, prev_output.clone());
+                input_value = input_value
+                    .checked_add(prev_output.value)
+                    .ok_or_else(|| BtcError::ValueOverflow(transaction.hash()))?;
             }
 
             for output in &transaction.outputs {
-                if outputs.contains_key(&output.hash()) {
-                    return Err(BtcError::InvalidTransaction);
+                if outputs.insert(output.hash(), output).is_some() {
+                    return Err(BtcError::DuplicateOutput(output.hash()));
                 }
-                outputs.insert(output.hash(), output.clone());
             }
-        }
 
-        let input_value: u64 = inputs.values().map(|output| output.value).sum();
-        let output_value: u64 = outputs.values().map(|output| output.value).sum();
-        // miner fees will be the difference of inputs transactins and outputs transactions in the
-        // block then?
-        Ok(input_value - output_value)
+            fees = input_value
+                .checked_sub(transaction.output_value()?)
+                .and_then(|fee| fees.checked_add(fee))
+                .ok_or_else(|| transaction.insufficient_inputs(input_value))?;
+        }
+        Ok(fees)
     }
 
     pub fn verify_coinbase_transaction(
         &self,
+        params: &ChainParams,
         predicted_block_height: u64,
         utxos: &HashMap<Hash, (TransactionOutput, bool)>,
     ) -> Result<()> {
         // coinbase tx is the first transaction in the block
-        let coinbase_transaction = &self.transactions[0];
+        let coinbase_transaction = self.transactions.first().ok_or(BtcError::EmptyBlock)?;
 
         if !coinbase_transaction.inputs.is_empty() {
-            return Err(BtcError::InvalidTransaction);
+            return Err(BtcError::CoinbaseHasInputs);
         }
         if coinbase_transaction.outputs.is_empty() {
-            return Err(BtcError::InvalidTransaction);
+            return Err(BtcError::CoinbaseWithoutOutputs);
         }
 
         let miner_fees = self.calculate_miner_fees(utxos)?;
-        let block_reward = crate::INITIAL_REWARD * 10u64.pow(8)
-            / 2u64.pow((predicted_block_height / crate::HALVING_INTERVAL) as u32);
-        let total_coinbase_outputs: u64 = coinbase_transaction
-            .outputs
-            .iter()
-            .map(|output| output.value)
-            .sum();
-
-        if total_coinbase_outputs != block_reward + miner_fees {
-            return Err(BtcError::InvalidTransaction);
+        let block_reward = params.block_reward(predicted_block_height);
+        let total_coinbase_outputs = coinbase_transaction.output_value()?;
+        let expected = block_reward
+            .checked_add(miner_fees)
+            .ok_or(BtcError::ValueOverflow(coinbase_transaction.hash()))?;
+
+        if total_coinbase_outputs != expected {
+            return Err(BtcError::CoinbaseValueMismatch {
+                expected,
+                actual: total_coinbase_outputs,
+            });
         }
         Ok(())
     }
 
     pub fn verify_transactions(
         &self,
+        params: &ChainParams,
         predicted_block_height: u64,
         utxos: &HashMap<Hash, (TransactionOutput, bool)>,
     ) -> Result<()> {
-        let mut inputs: HashMap<Hash, TransactionOutput> = HashMap::new();
+        let mut inputs: HashSet<Hash> = HashSet::new();
 
         // reject completely empty blocks
         if self.transactions.is_empty() {
-            return Err(BtcError::InvalidTransaction);
+            return Err(BtcError::EmptyBlock);
+        }
+        if self.transactions.len() > crate::BLOCK_TRANSACTION_CAP {
+            return Err(BtcError::OversizedBlock {
+                count: self.transactions.len(),
+                max: crate::BLOCK_TRANSACTION_CAP,
+            });
         }
 
-        self.verify_coinbase_transaction(predicted_block_height, utxos)?;
+        for transaction in &self.transactions {
+            transaction.verify_memo()?;
+            transaction.verify_output_values(params.max_money())?;
+        }
+        self.verify_coinbase_transaction(params, predicted_block_height, utxos)?;
 
         // skip coinbase transaction
+        let mut created: HashMap<Hash, &TransactionOutput> = HashMap::new();
         for transaction in self.transactions.iter().skip(1) {
-            let mut input_value = 0;
-            let mut output_value = 0;
-            for input in &transaction.inputs {
-                let prev_output = utxos
-                    .get(&input.prev_transaction_output_hash)
-                    .map(|(output, _)| output);
-
-                if prev_output.is_none() {
-                    return Err(BtcError::InvalidTransaction);
-                }
-
-                let prev_output = prev_output.unwrap();
+            let mut input_value = Amount::ZERO;
+            for (index, input) in transaction.inputs.iter().enumerate() {
+                let prev_output = spendable(utxos, &created, &input.prev_transaction_output_hash)?;
 
                 // prevents same-block double-spending but checking if inputs already have the
                 // previous hash outpu
-                if inputs.contains_key(&input.prev_transaction_output_hash) {
-                    return Err(BtcError::InvalidTransaction);
+                if !inputs.insert(input.prev_transaction_output_hash) {
+                    return Err(BtcError::DoubleSpend(input.prev_transaction_output_hash));
                 }
 
-                if !input
-                    .signature
-                    .verify(&input.prev_transaction_output_hash, &prev_output.pubkey)
-                {
-                    return Err(BtcError::InvalidSignature);
+                let signed = transaction.signature_hash(&input.prev_transaction_output_hash);
+                if !input.signature.verify(&signed, &prev_output.pubkey) {
+                    return Err(BtcError::BadSignature {
+                        transaction: transaction.hash(),
+                        input: index,
+                    });
                 }
 
-                input_value += prev_output.value;
-                inputs.insert(input.prev_transaction_output_hash, prev_output.clone());
-            }
-
-            for output in &transaction.outputs {
-                output_value += output.value;
+                input_value = input_value
+                    .checked_add(prev_output.value)
+                    .ok_or_else(|| BtcError::ValueOverflow(transaction.hash()))?;
             }
 
             // output_value can be less than input value beacuse of the fees for the miner, but
             // never the other way around
-            if input_value < output_value {
-                return Err(BtcError::InvalidTransaction);
+            if input_value < transaction.output_value()? {
+                return Err(transaction.insufficient_inputs(input_value));
             }
+            created.extend(
+                transaction
+                    .outputs
+                    .iter()
+                    .map(|output| (output.hash(), output)),
+            );
         }
         Ok(())
     }
 }
 
+/// The output an input spends: one of the chain's unspent outputs, or one created by an earlier
+/// transaction of the same block so a child can be mined together with its parent
+fn spendable<'a>(
+    utxos: &'a HashMap<Hash, (TransactionOutput, bool)>,
+    created: &HashMap<Hash, &'a TransactionOutput>,
+    hash: &Hash,
+) -> Result<&'a TransactionOutput> {
+    utxos
+        .get(hash)
+        .map(|(output, _)| output)
+        .or_else(|| created.get(hash).copied())
+        .ok_or(BtcError::MissingUtxo(*hash))
+}
+
 impl BlockHeader {
     pub fn new(
         timestamp: DateTime<Utc>,
@@ -203,8 +232,14 @@ impl BlockHeader {
     }
 
     pub fn mine(&mut self, steps: usize) -> bool {
+        self.mine_to(self.target, steps)
+    }
+
+    /// `mine` until the hash meets `target` instead of the header's target, e.g. a pool's share
+    /// target. The header keeps its own
+    pub fn mine_to(&mut self, target: U256, steps: usize) -> bool {
         // if the block already matches target, return early
-        if self.hash().matches_target(self.target) {
+        if self.hash().matches_target(target) {
             return true;
         }
 
@@ -216,12 +251,76 @@ impl BlockHeader {
                 self.timestamp = Utc::now()
             }
 
-            if self.hash().matches_target(self.target) {
+            if self.hash().matches_target(target) {
                 return true;
             }
         }
         false
     }
+
+    /// Fixed size big-endian form for sending many headers: timestamp seconds (i64) and
+    /// nanoseconds (u32), nonce, previous block hash, merkle root and target. It decodes to a
+    /// header with the same hash
+    pub fn to_compact(&self) -> [u8; COMPACT_HEADER_SIZE] {
+        let mut bytes = [0; COMPACT_HEADER_SIZE];
+        bytes[..8].copy_from_slice(&self.timestamp.timestamp().to_be_bytes());
+        bytes[8..12].copy_from_slice(&self.timestamp.timestamp_subsec_nanos().to_be_bytes());
+        bytes[12..20].copy_from_slice(&self.nonce.to_be_bytes());
+        bytes[20..52].copy_from_slice(&self.prev_block_hash.to_be_bytes());
+        bytes[52..84].copy_from_slice(&self.merkle_root.hash().to_be_bytes());
+        self.target.to_big_endian(&mut bytes[84..]);
+        bytes
+    }
+
+    pub fn from_compact(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != COMPACT_HEADER_SIZE {
+            return Err(BtcError::InvalidBlockHeader);
+        }
+        let hash =
+            |range: std::ops::Range<usize>| Hash::from_be_bytes(bytes[range].try_into().unwrap());
+        let seconds = i64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let nanos = u32::from_be_bytes(bytes[8..12].try_into().unwrap());
+        Ok(BlockHeader {
+            timestamp: DateTime::from_timestamp(seconds, nanos)
+                .ok_or(BtcError::InvalidBlockHeader)?,
+            nonce: u64::from_be_bytes(bytes[12..20].try_into().unwrap()),
+            prev_block_hash: hash(20..52),
+            merkle_root: MerkleRoot::from_hash(hash(52..84)),
+            target: U256::from_big_endian(&bytes[84..]),
+        })
+    }
+
+    /// The checks a block's header has to pass to follow the block hashing to `prev_hash` with
+    /// header `prev`, `None` for the first block. Enough for a light client keeping a header
+    /// chain of a proof-of-work chain, the transactions are up to the full nodes
+    pub fn verify_after(&self, prev_hash: Hash, prev: Option<&BlockHeader>) -> Result<()> {
+        if self.prev_block_hash != prev_hash {
+            return Err(BtcError::PrevHashMismatch {
+                expected: prev_hash,
+                actual: self.prev_block_hash,
+            });
+        }
+        if !self.hash().matches_target(self.target) {
+            return Err(BtcError::InsufficientWork(self.hash()));
+        }
+        if let Some(prev) = prev
+            && self.timestamp <= prev.timestamp
+        {
+            return Err(BtcError::TimestampTooOld {
+                timestamp: self.timestamp,
+                previous: prev.timestamp,
+            });
+        }
+        Ok(())
+    }
+
+    /// Mine against `target` instead of the header's own target, regtest only.
+    /// With `REGTEST_TARGET` the very first hash matches, so tests don't have to spin a real PoW
+    /// loop. The overridden target is kept in the header so the block validates against it.
+    pub fn mine_with_target_override(&mut self, target: U256) {
+        self.target = target;
+        while !self.mine(usize::MAX) {}
+    }
 }
 
 impl Saveable for Block {
@@ -1,5 +1,47 @@
+mod addrindex;
+mod blockfile;
+mod checkpoint;
+mod export;
+mod format;
+mod history;
+mod invalidate;
+mod mempool;
+mod package;
+mod policy;
+mod rebuild;
+mod richlist;
+mod scan;
+mod selfcheck;
+mod stats;
+mod txindex;
+mod undo;
+mod utxodiff;
+mod wal;
+
+pub use addrindex::AddressActivity;
+pub use blockfile::{BlockFile, BlockFileReport};
+pub use checkpoint::{CheckpointSignature, Federation, SignedCheckpoint};
+pub use export::ExportFormat;
+pub use mempool::{
+    DoubleSpend, DoubleSpendOutcome, Eviction, EvictionReason, Mempool, MempoolAcceptance,
+    MempoolStats, MempoolTxInfo,
+};
+pub use policy::MempoolPolicy;
+pub use richlist::{Holder, SupplyBucket};
+pub use scan::AddressScan;
+pub use selfcheck::{SelfCheck, UtxoChecksum};
+pub use stats::ChainStats;
+pub use undo::BlockUndo;
+pub use utxodiff::UtxoDiff;
+pub use wal::{Recovery, Wal, WalRecord};
+
+use addrindex::AddressIndex;
+use txindex::TxIndex;
+
 use crate::{
     U256,
+    chain_params::ChainParams,
+    crypto::PublicKey,
     error::{BtcError, Result},
     sha256::Hash,
     types::*,
@@ -7,21 +49,107 @@ use crate::{
 };
 
 use std::collections::{HashMap, HashSet};
-use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Write};
+use std::fs::File;
+use std::io::{Read, Result as IoResult, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::sync::Arc;
 
-use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Blockchain {
-    utxos: HashMap<Hash, (TransactionOutput, bool)>,
+    /// shared with the snapshots taken of it, see `utxo_snapshot`
+    utxos: Arc<HashMap<Hash, (TransactionOutput, bool)>>,
     blocks: Vec<Block>,
     target: U256,
+    /// blocks `invalidate_block` marked
+    invalid: HashSet<Hash>,
+    /// blocks taken off the chain, see `invalidate`
+    disconnected: Vec<Block>,
+    /// what the last blocks spent, one for each of them, see `undo`
+    undo: Vec<BlockUndo>,
     /// The mempool is a list of transactions that have been sent to the network and haven’t
     /// been processed yet.
-    #[serde(default, skip_serializing)]
-    mempool: Vec<(Transaction, DateTime<Utc>)>,
+    #[serde(skip)]
+    mempool: Mempool,
+    /// what the mempool takes beyond consensus, up to whoever runs the node
+    #[serde(skip)]
+    policy: MempoolPolicy,
+    /// not saved with the chain, whoever loads it decides which network it belongs to
+    #[serde(skip)]
+    params: Arc<ChainParams>,
+    /// added to the local clock to get the network's, see `network::NetworkClock`
+    #[serde(skip)]
+    time_offset: chrono::Duration,
+    /// only kept if `enable_txindex` was called
+    #[serde(skip)]
+    txindex: Option<TxIndex>,
+    /// only kept if `enable_addrindex` was called
+    #[serde(skip)]
+    addrindex: Option<AddressIndex>,
+    /// where changes to the chain are logged before they're made, see `wal`
+    #[serde(skip)]
+    wal: Option<Wal>,
+    /// not saved with the chain, the authorities sign a new one soon enough, see `checkpoint`
+    #[serde(skip)]
+    checkpoint: Option<SignedCheckpoint>,
+    /// what the UTXO set was when the chain was saved, if it was loaded from a file that says,
+    /// see `self_check`
+    #[serde(skip)]
+    saved_utxos: Option<UtxoChecksum>,
+}
+
+/// A block `Blockchain::validate_block` accepted on top of a given tip
+#[derive(Clone, Debug)]
+pub struct ValidatedBlock {
+    tip: Hash,
+    block: Block,
+}
+
+impl ValidatedBlock {
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    pub fn into_block(self) -> Block {
+        self.block
+    }
+}
+
+/// A consistent view of the UTXO set after a block, see `Blockchain::utxo_snapshot`
+#[derive(Clone, Debug)]
+pub struct UtxoSnapshot {
+    height: u64,
+    tip: Hash,
+    utxos: Arc<HashMap<Hash, (TransactionOutput, bool)>>,
+}
+
+impl UtxoSnapshot {
+    /// how many blocks the chain had
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// hash of the last block the set includes
+    pub fn tip(&self) -> Hash {
+        self.tip
+    }
+
+    pub fn utxos(&self) -> &HashMap<Hash, (TransactionOutput, bool)> {
+        &self.utxos
+    }
+
+    /// the outputs `pubkey` can spend, with whether a mempool transaction already does
+    pub fn owned_by<'a>(
+        &'a self,
+        pubkey: &'a PublicKey,
+    ) -> impl Iterator<Item = &'a (TransactionOutput, bool)> + 'a {
+        self.utxos
+            .values()
+            .filter(move |(output, _)| output.pubkey == *pubkey)
+    }
 }
 
 impl Default for Blockchain {
@@ -31,156 +159,158 @@ impl Default for Blockchain {
 }
 
 impl Blockchain {
+    /// version of the on-disk format `save` writes
+    pub const FORMAT_VERSION: u16 = format::FORMAT_VERSION;
+
     pub fn new() -> Self {
+        Self::with_params(Arc::new(ChainParams::default()))
+    }
+
+    /// an empty chain taking blocks mined at `REGTEST_TARGET`, see `ChainParams::regtest`
+    pub fn regtest() -> Self {
+        Self::with_params(Arc::new(ChainParams::regtest()))
+    }
+
+    pub fn with_params(params: Arc<ChainParams>) -> Self {
         Blockchain {
-            utxos: HashMap::new(),
+            utxos: Arc::default(),
             blocks: vec![],
-            target: crate::MIN_TARGET,
-            mempool: vec![],
+            target: params.min_target,
+            invalid: HashSet::new(),
+            disconnected: vec![],
+            undo: vec![],
+            mempool: Mempool::default(),
+            policy: MempoolPolicy::default(),
+            params,
+            time_offset: chrono::Duration::zero(),
+            txindex: None,
+            addrindex: None,
+            wal: None,
+            checkpoint: None,
+            saved_utxos: None,
         }
     }
 
+    pub fn params(&self) -> &Arc<ChainParams> {
+        &self.params
+    }
+
+    /// switch a loaded chain to the network it belongs to
+    pub fn set_params(&mut self, params: Arc<ChainParams>) {
+        self.params = params;
+    }
+
     // TODO: in two conficting transactions (what does that mean?), remove the one with smaller
     // fee.
     pub fn add_to_mempool(&mut self, transaction: Transaction) -> Result<()> {
-        // validate before inserting transaction to mempool, all inputs must match known UTXOs, and
-        // must be unique
-        let mut known_inputs = HashSet::new();
-        for input in &transaction.inputs {
-            if !self.utxos.contains_key(&input.prev_transaction_output_hash) {
-                return Err(BtcError::InvalidTransaction);
-            }
-
-            if known_inputs.contains(&input.prev_transaction_output_hash) {
-                return Err(BtcError::InvalidTransaction);
-            }
+        self.add_to_mempool_at(transaction, Utc::now())
+    }
 
-            known_inputs.insert(input.prev_transaction_output_hash);
+    /// `add_to_mempool` for a transaction that came in at `received`, expiry counts from there
+    pub fn add_to_mempool_at(
+        &mut self,
+        transaction: Transaction,
+        received: DateTime<Utc>,
+    ) -> Result<()> {
+        if self.mempool.contains(&transaction.hash()) {
+            // already have it
+            return Ok(());
         }
-
-        let mut to_remove: Vec<usize> = Vec::new();
-
-        // check if any of the utxos have the bool mark set to true and if so, find the transaction
-        // that references them in mempool, remove it and set all the utxos it references to false
-        for input in &transaction.inputs {
-            if let Some((_, true)) = self.utxos.get(&input.prev_transaction_output_hash) {
-                // find a mempool tx that outputs this UTXO
-                if let Some((idx, _referencing_idx)) =
-                    self.mempool
-                        .iter()
-                        .enumerate()
-                        .find(|(_idx, (tx, _txtime))| {
-                            tx.outputs
-                                .iter()
-                                .any(|output| output.hash() == input.prev_transaction_output_hash)
-                        })
-                {
-                    to_remove.push(idx);
-                } else {
-                    // if there is no matching transaction set this utxo to false
-                    self.utxos
-                        .entry(input.prev_transaction_output_hash)
-                        .and_modify(|(_transaction, marked)| *marked = false);
-                }
-            }
-        }
-
-        to_remove.sort_unstable();
-        to_remove.dedup();
-        for idx in to_remove.into_iter().rev() {
-            // remove returns the transaction so we can unmark its inputs
-            let (referencing_transaction, _txtime) = self.mempool.remove(idx);
-            for input in &referencing_transaction.inputs {
-                self.utxos
-                    .entry(input.prev_transaction_output_hash)
-                    .and_modify(|(_tx, marked)| *marked = false);
-            }
+        let acceptance = self.test_mempool_accept(&transaction)?;
+        let replaced = self
+            .mempool
+            .double_spends(&transaction, DoubleSpendOutcome::Replaced);
+        for double_spend in replaced {
+            let hash = double_spend.txid;
+            self.evict(&hash, EvictionReason::Replaced, Some(double_spend));
         }
+        self.make_mempool_room(&acceptance.evicts);
+        self.insert_into_mempool(transaction, received);
+        Ok(())
+    }
 
-        let all_inputs = transaction
-            .inputs
-            .iter()
-            .map(|input| {
-                self.utxos
-                    .get(&input.prev_transaction_output_hash)
-                    .expect("BUG: Impossible")
-                    .0
-                    .value
-            })
-            .sum::<u64>();
-
-        let all_outputs: u64 = transaction.outputs.iter().map(|output| output.value).sum();
-
-        // all inputs be lower than all outp[uts
-        if all_inputs < all_outputs {
-            println!("Inputs are lower than outputs");
-            return Err(BtcError::InvalidTransaction);
+    /// Everything `add_to_mempool` checks, without adding the transaction, e.g. for a wallet to
+    /// try one before broadcasting it. One already in the mempool passes as it is
+    pub fn test_mempool_accept(&self, transaction: &Transaction) -> Result<MempoolAcceptance> {
+        if self.mempool.contains(&transaction.hash()) {
+            return Ok(MempoolAcceptance {
+                fee: self.mempool_fee(transaction),
+                size: transaction.size(),
+                replaces: vec![],
+                evicts: vec![],
+            });
         }
+        let fee = self.mempool_input_fee(transaction, &HashMap::new())?;
+        self.check_mempool_policy(transaction, fee)?;
+        self.check_mempool_conflicts(transaction)?;
+        self.check_mempool_limits(transaction)?;
+        let evicts = self.mempool_room(transaction, fee)?;
+        let replaces = self
+            .mempool
+            .double_spends(transaction, DoubleSpendOutcome::Replaced)
+            .into_iter()
+            .map(|double_spend| double_spend.txid)
+            .collect();
+        Ok(MempoolAcceptance {
+            fee,
+            size: transaction.size(),
+            replaces,
+            evicts,
+        })
+    }
 
+    /// What `transaction` leaves to the miner. Checks its memo and values, and that its inputs
+    /// are different outputs of the chain, the mempool or `pending`
+    pub(super) fn mempool_input_fee(
+        &self,
+        transaction: &Transaction,
+        pending: &HashMap<Hash, &TransactionOutput>,
+    ) -> Result<Amount> {
+        // all inputs must match known UTXOs or outputs of other mempool transactions, and must be
+        // unique
+        transaction.verify_memo()?;
+        transaction.verify_output_values(self.params.max_money())?;
+        let mut known_inputs = HashSet::new();
+        let mut all_inputs = Amount::ZERO;
         for input in &transaction.inputs {
-            self.utxos
-                .entry(input.prev_transaction_output_hash)
-                .and_modify(|(_tx, marked)| {
-                    *marked = true;
-                });
-        }
-
-        self.mempool.push((transaction, Utc::now()));
-
-        // sort by miner fee
-        self.mempool.sort_by_key(|(transaction, _)| {
-            let all_inputs = transaction
-                .inputs
-                .iter()
-                .map(|input| {
-                    self.utxos
-                        .get(&input.prev_transaction_output_hash)
-                        .expect("BUG: Impossible")
-                        .0
-                        .value
-                })
-                .sum::<u64>();
-
-            let all_outputs: u64 = transaction.outputs.iter().map(|output| output.value).sum();
+            let hash = input.prev_transaction_output_hash;
+            let Some(prev_output) = self
+                .mempool_spendable(&hash)
+                .or_else(|| pending.get(&hash).copied())
+            else {
+                return Err(BtcError::MissingUtxo(hash));
+            };
+
+            if !known_inputs.insert(hash) {
+                return Err(BtcError::DoubleSpend(hash));
+            }
 
-            all_inputs - all_outputs
-        });
+            all_inputs = all_inputs
+                .checked_add(prev_output.value)
+                .ok_or_else(|| BtcError::ValueOverflow(transaction.hash()))?;
+        }
 
-        Ok(())
+        // all inputs must not be lower than all outputs
+        all_inputs
+            .checked_sub(transaction.output_value()?)
+            .ok_or_else(|| transaction.insufficient_inputs(all_inputs))
     }
 
-    /// remove transactions older than MAX_MEMPOOL_TRANSACTION_AGE
-    pub fn cleanup_mempool(&mut self) {
-        let now = Utc::now();
-        let mut utxo_hashes_to_unmark = vec![];
-        self.mempool().to_vec().retain(|(transaction, timestamp)| {
-            if now - *timestamp
-                > chrono::Duration::seconds(crate::MAX_MEMPOOL_TRANSACTION_AGE as i64)
-            {
-                utxo_hashes_to_unmark.extend(
-                    transaction
-                        .inputs
-                        .iter()
-                        .map(|input| input.prev_transaction_output_hash),
-                );
-                false
-            } else {
-                true
-            }
-        });
+    /// Evict transactions older than the policy's `max_transaction_age`, and whatever spends from
+    /// them. Returns how many transactions left
+    pub fn cleanup_mempool(&mut self) -> usize {
+        let max_age = self.policy.max_age();
+        self.expire_mempool(Utc::now() - max_age)
+    }
 
-        // unmark all of the UTXOs
-        for hash in utxo_hashes_to_unmark {
-            self.utxos
-                .entry(hash)
-                .and_modify(|(_tx, marked)| *marked = false);
-        }
+    /// what the peers' clocks say it is, blocks may only be `MAX_FUTURE_BLOCK_TIME` ahead of it
+    pub fn adjusted_time(&self) -> DateTime<Utc> {
+        Utc::now() + self.time_offset
     }
 
-    pub fn mempool(&self) -> &[(Transaction, DateTime<Utc>)] {
-        // later, we will also need to keep track
-        // of time
-        &self.mempool
+    /// move the clock `adjusted_time` goes by, see `NetworkClock::adjustment`
+    pub fn set_time_offset(&mut self, offset: chrono::Duration) {
+        self.time_offset = offset;
     }
 
     /// utxos
@@ -188,11 +318,24 @@ impl Blockchain {
         &self.utxos
     }
 
+    /// About how many bytes the UTXO set takes: what its table has room for, a byte of
+    /// bookkeeping each. What the keys inside outputs point to on the heap isn't counted
+    pub fn utxo_memory(&self) -> usize {
+        let entry = std::mem::size_of::<(Hash, (TransactionOutput, bool))>() + 1;
+        self.utxos.capacity() * entry
+    }
+
     /// target
     pub fn target(&self) -> U256 {
         self.target
     }
 
+    /// How many times harder the current target is to meet than the easiest one the network
+    /// allows, 1 on a fresh chain
+    pub fn difficulty(&self) -> f64 {
+        u256_as_f64(self.params.min_target) / u256_as_f64(self.target).max(1.0)
+    }
+
     /// blocks
     pub fn blocks(&self) -> impl Iterator<Item = &Block> {
         self.blocks.iter()
@@ -204,148 +347,366 @@ impl Blockchain {
         self.blocks.len() as u64
     }
 
-    /// Rebuild UTXO set from the blockchain
-    /// For every block in the blockchain, we go
-    /// through every transaction, and for every transaction, we go through every input
-    /// and output. We add all outputs we see and remove the outputs if we see an input
-    /// that spends it.
-    pub fn rebuild_utxos(&mut self) {
-        for block in &self.blocks {
-            for transaction in &block.transactions {
-                for input in &transaction.inputs {
-                    self.utxos.remove(&input.prev_transaction_output_hash);
+    pub fn add_block(&mut self, block: Block) -> Result<()> {
+        let validated = self.validate_block(block)?;
+        self.add_validated_block(validated)
+    }
+
+    /// Check `block` against the current tip without changing anything, so it can be done while
+    /// others keep reading the chain. `add_validated_block` appends the result
+    pub fn validate_block(&self, block: Block) -> Result<ValidatedBlock> {
+        self.check_block(&block, block.header.target)?;
+        Ok(ValidatedBlock {
+            tip: self.tip_hash(),
+            block,
+        })
+    }
+
+    /// Whether `block` would be a valid block on the tip if only its hash had to meet
+    /// `share_target` rather than the chain's target, e.g. to count a pool miner's work. A target
+    /// harder than the chain's doesn't count
+    pub fn validate_share(&self, block: &Block, share_target: U256) -> Result<()> {
+        self.check_block(block, share_target.max(self.target))
+    }
+
+    /// every rule `validate_block` checks, with the hash having to meet `work_target`
+    fn check_block(&self, block: &Block, work_target: U256) -> Result<()> {
+        if self.invalid.contains(&block.hash()) {
+            return Err(BtcError::InvalidatedBlock(block.hash()));
+        }
+        let now = self.adjusted_time();
+        if block.header.timestamp > now + chrono::Duration::seconds(crate::MAX_FUTURE_BLOCK_TIME) {
+            return Err(BtcError::TimestampTooFarAhead {
+                timestamp: block.header.timestamp,
+                now,
+            });
+        }
+        // the target is the chain's to set, not the block's
+        let target = block.header.target;
+        if target != self.target && !(self.params.regtest && target == crate::REGTEST_TARGET) {
+            return Err(BtcError::TargetMismatch {
+                expected: self.target,
+                actual: target,
+            });
+        }
+        match self.blocks.last() {
+            // the first block has nothing to build on, but it still can't issue more than its
+            // reward
+            None => {
+                if block.header.prev_block_hash != Hash::zero() {
+                    return Err(BtcError::PrevHashMismatch {
+                        expected: Hash::zero(),
+                        actual: block.header.prev_block_hash,
+                    });
+                }
+                block.verify_transactions(&self.params, 0, &HashMap::new())?;
+            }
+            Some(last_block) => {
+                // make sure the previous hash matches
+                if block.header.prev_block_hash != last_block.hash() {
+                    return Err(BtcError::PrevHashMismatch {
+                        expected: last_block.hash(),
+                        actual: block.header.prev_block_hash,
+                    });
+                }
+
+                // work or stake, whatever the chain runs with
+                self.params
+                    .consensus
+                    .engine()
+                    .verify_seal(self, block, work_target)?;
+
+                // check if block's merkel root hash is correct
+                if MerkleRoot::calculate(&block.transactions)? != block.header.merkle_root {
+                    return Err(BtcError::InvalidMerkleRoot);
                 }
 
-                for output in transaction.outputs.iter() {
-                    self.utxos
-                        .insert(transaction.hash(), (output.clone(), false));
+                // check if the timestamp of the last block is higher than current block
+                if block.header.timestamp <= last_block.header.timestamp {
+                    return Err(BtcError::TimestampTooOld {
+                        timestamp: block.header.timestamp,
+                        previous: last_block.header.timestamp,
+                    });
                 }
+
+                block.verify_transactions(&self.params, self.block_height(), self.utxos())?;
             }
         }
+        Ok(())
     }
 
-    pub fn add_block(&mut self, block: Block) -> Result<()> {
-        if self.blocks.is_empty() {
-            if block.header.prev_block_hash != Hash::zero() {
-                println!(
-                    "First block but previous block hash isn't zero, therefore block is invalid"
-                );
-                return Err(BtcError::InvalidBlock);
-            }
+    /// Append a block checked by `validate_block`. If another block made it in since, it's
+    /// validated again against the new tip
+    pub fn add_validated_block(&mut self, validated: ValidatedBlock) -> Result<()> {
+        let block = if validated.tip == self.tip_hash() {
+            validated.block
         } else {
-            // make sure the previous hash matches
-            let last_block = self.blocks.last().unwrap();
-            if block.header.prev_block_hash != last_block.hash() {
-                println!("Previous hash is wrong, block is invalid");
-            }
+            self.validate_block(validated.block)?.block
+        };
 
-            // check if hash is less than target
-            if !block.header.hash().matches_target(block.header.target) {
-                println!("Block hash is higher than network target, block is invalid!");
-                return Err(BtcError::InvalidBlock);
-            }
+        self.log(&WalRecord::Apply {
+            height: self.block_height(),
+            block: block.clone(),
+        })?;
+        if let Some(addrindex) = &mut self.addrindex {
+            addrindex.add_block(self.blocks.len() as u64, &block, &self.utxos);
+        }
+        self.undo.push(BlockUndo::of(&block, &self.utxos));
+        Self::apply_to_utxos(self.utxos_mut(), &block);
+        self.update_mempool(&block);
+        if let Some(txindex) = &mut self.txindex {
+            txindex.add_block(self.blocks.len() as u64, &block);
+        }
+        if !self.disconnected.is_empty() {
+            let hash = block.hash();
+            self.disconnected.retain(|kept| kept.hash() != hash);
+        }
+        self.blocks.push(block);
+        self.try_adjust_target();
+        self.log(&WalRecord::Done)
+    }
 
-            // check if block's merkel root hash is correct
-            let calculated_merkle_root = MerkleRoot::calculate(&block.transactions);
+    /// index every transaction of the chain, added blocks are indexed as they come in
+    pub fn enable_txindex(&mut self) {
+        self.txindex = Some(TxIndex::build(&self.blocks));
+    }
 
-            if calculated_merkle_root != block.header.merkle_root {
-                println!("Merkle root does not match, block is invalid!");
-                return Err(BtcError::InvalidMerkleRoot);
-            }
+    pub fn has_txindex(&self) -> bool {
+        self.txindex.is_some()
+    }
 
-            // check if the timestamp of the last block is higher than current block
-            if block.header.timestamp <= last_block.header.timestamp {
-                println!("Timestamp is incorrect, invalid block!");
-                return Err(BtcError::InvalidBlock);
+    /// A transaction in the chain and the height of its block. Goes through every block unless
+    /// the txindex is enabled
+    pub fn find_transaction(&self, hash: &Hash) -> Option<(u64, &Transaction)> {
+        match &self.txindex {
+            Some(txindex) => {
+                let (height, offset) = txindex.get(hash)?;
+                let transaction = self.blocks.get(height as usize)?.transactions.get(offset)?;
+                Some((height, transaction))
             }
+            None => self.blocks.iter().enumerate().find_map(|(height, block)| {
+                block
+                    .transactions
+                    .iter()
+                    .find(|transaction| transaction.hash() == *hash)
+                    .map(|transaction| (height as u64, transaction))
+            }),
+        }
+    }
+
+    /// index what every transaction did to each public key, kept up to date like the txindex
+    pub fn enable_addrindex(&mut self) {
+        self.addrindex = Some(AddressIndex::build(&self.blocks));
+    }
 
-            block
-                .verify_transactions(self.block_height(), self.utxos())
-                .unwrap();
+    pub fn has_addrindex(&self) -> bool {
+        self.addrindex.is_some()
+    }
+
+    /// Every confirmed transaction that paid or spent from `pubkey`, oldest first. Empty unless
+    /// the addrindex is enabled
+    pub fn address_history(&self, pubkey: &PublicKey) -> &[AddressActivity] {
+        self.addrindex
+            .as_ref()
+            .map_or(&[], |addrindex| addrindex.get(pubkey))
+    }
+
+    /// The UTXO set as it is now, it stays that way however the chain changes afterwards. Cheap
+    /// to take, the set is only copied if the chain changes while a snapshot is still around
+    pub fn utxo_snapshot(&self) -> UtxoSnapshot {
+        UtxoSnapshot {
+            height: self.block_height(),
+            tip: self.tip_hash(),
+            utxos: self.utxos.clone(),
         }
+    }
 
-        // Remove transactinos from the mempool that are now in the block
-        let block_transactions: HashSet<_> =
-            block.transactions.iter().map(|tx| tx.hash()).collect();
+    /// copy the UTXO set first if a snapshot still uses it. It no longer is what was saved
+    fn utxos_mut(&mut self) -> &mut HashMap<Hash, (TransactionOutput, bool)> {
+        self.saved_utxos = None;
+        Arc::make_mut(&mut self.utxos)
+    }
 
-        self.mempool
-            .retain(|tx| !block_transactions.contains(&tx.0.hash()));
-        self.blocks.push(block);
-        self.try_adjust_target();
-        Ok(())
+    /// spend the block's inputs and add its outputs
+    fn apply_to_utxos(utxos: &mut HashMap<Hash, (TransactionOutput, bool)>, block: &Block) {
+        for transaction in &block.transactions {
+            for input in &transaction.inputs {
+                utxos.remove(&input.prev_transaction_output_hash);
+            }
+            // inputs point at outputs by their own hash, not the transaction's
+            for output in &transaction.outputs {
+                utxos.insert(output.hash(), (output.clone(), false));
+            }
+        }
+    }
+
+    /// hash of the last block, zero for an empty chain
+    pub fn tip_hash(&self) -> Hash {
+        self.blocks
+            .last()
+            .map(|last_block| last_block.hash())
+            .unwrap_or(Hash::zero())
     }
 
     /// try to adjust the target of the blockchain
     pub fn try_adjust_target(&mut self) {
-        if self.blocks.is_empty() {
-            return;
-        }
+        self.target = self.next_target(self.target, &self.blocks);
+    }
+
+    /// what the target goes to from `target` once `blocks` are the chain, it only changes every
+    /// `difficulty_update_interval` blocks
+    fn next_target(&self, target: U256, blocks: &[Block]) -> U256 {
+        let Some(last_block) = blocks.last() else {
+            return target;
+        };
 
-        if !self
-            .blocks
-            .len()
-            .is_multiple_of(crate::DIFFICULTY_UPDATE_INTERVAL as usize)
+        let interval = self.params.difficulty_update_interval as usize;
+        if !self.params.consensus.engine().adjusts_target()
+            || !blocks.len().is_multiple_of(interval)
         {
-            return;
+            return target;
         }
 
         // measure the time it took to mine the last blocks
-        let start_time = self.blocks
-            [self.blocks.len() - crate::DIFFICULTY_UPDATE_INTERVAL as usize]
-            .header
-            .timestamp;
-        let end_time = self.blocks.last().unwrap().header.timestamp;
+        let start_time = blocks[blocks.len() - interval].header.timestamp;
+        let end_time = last_block.header.timestamp;
+
+        let target_seconds = self
+            .params
+            .ideal_block_time
+            .saturating_mul(self.params.difficulty_update_interval);
+        // clamp the actual time within 4x of the ideal time in either direction, which keeps the
+        // new target within 4x of the old one. It seems like bitcoin does not want to adjust the
+        // difficulty by more than a factor of 4x either, and it also means timestamps going
+        // backwards can't make the time negative
+        let time_diff_seconds = u64::try_from((end_time - start_time).num_seconds())
+            .unwrap_or(0)
+            .clamp(target_seconds / 4, target_seconds * 4);
 
-        let time_diff = end_time - start_time;
-        let time_diff_seconds = time_diff.num_seconds();
-
-        let target_seconds = crate::IDEAL_BLOCK_TIME * crate::DIFFICULTY_UPDATE_INTERVAL;
         // multiply the current target by actual time divided by
         // ideal time
         // NewTarget = OldTarget * (ActualTime / IdealTime)
-        let new_target = BigDecimal::parse_bytes(self.target.to_string().as_bytes(), 10)
-            .expect("BUG: Impossible")
-            * (BigDecimal::from(time_diff_seconds) / BigDecimal::from(target_seconds));
-
-        // cut of decimal point and everything after it from string repesentation of new_target
-        let new_target_str = new_target
-            .to_string()
-            .split('.')
-            .next()
-            .expect("BUG: Expected a decimal point")
-            .to_owned();
-
-        let new_target: U256 = U256::from_str_radix(&new_target_str, 10).expect("BUG: Impossible");
-
-        // clamp new_target to be within the range of the 4 * self.target and self.target / 4
-        // it seems like bitcoin does not want to adjust the difficulty by more than a factor of 4x
-        // in either direction, thats why the multiplicatoin or divisoin by 4
-        let new_target = if new_target < self.target / 4 {
-            self.target / 4
-        } else if new_target > self.target * 4 {
-            self.target * 4
-        } else {
-            new_target
+        let new_target = match target.checked_mul(U256::from(time_diff_seconds)) {
+            Some(scaled) => scaled / target_seconds,
+            // only huge targets overflow, for those the precision lost by dividing first is
+            // irrelevant
+            None => (target / target_seconds).saturating_mul(U256::from(time_diff_seconds)),
         };
 
         // if the new target is more than the minimum target, set it to the minimum target
-        self.target = new_target.min(crate::MIN_TARGET);
+        new_target.min(self.params.min_target)
     }
 
-    pub fn calculate_block_reward(&self) -> u64 {
-        let block_height = self.block_height();
-        let halvings = block_height / crate::HALVING_INTERVAL;
-        (crate::INITIAL_REWARD * 10u64.pow(8)) >> halvings
+    /// the target worked out again from the first block, what `target` should be
+    pub fn recomputed_target(&self) -> U256 {
+        (1..=self.blocks.len()).fold(self.params.min_target, |target, len| {
+            self.next_target(target, &self.blocks[..len])
+        })
     }
+
+    /// set the target to `recomputed_target`, `try_adjust_target` only goes forward
+    pub fn retarget(&mut self) {
+        self.target = self.recomputed_target();
+    }
+
+    /// Write the blocks in `heights` to `writer`, see `export` for the format. Heights past the
+    /// tip are ignored, returns how many blocks were written
+    pub fn export_blocks<W: Write>(
+        &self,
+        heights: impl RangeBounds<u64>,
+        format: ExportFormat,
+        writer: W,
+    ) -> Result<usize> {
+        let height = self.block_height();
+        let start = match heights.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        }
+        .min(height);
+        let end = match heights.end_bound() {
+            Bound::Included(end) => end.saturating_add(1),
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => height,
+        }
+        .clamp(start, height);
+        let blocks = &self.blocks[start as usize..end as usize];
+        export::write(blocks, start, format, writer)?;
+        Ok(blocks.len())
+    }
+
+    pub fn export_blocks_to_file<P: AsRef<Path>>(
+        &self,
+        heights: impl RangeBounds<u64>,
+        format: ExportFormat,
+        path: P,
+    ) -> Result<usize> {
+        self.export_blocks(heights, format, File::create(path)?)
+    }
+
+    /// Validate and append the blocks of an export, in either format. Blocks the chain already
+    /// has are skipped as long as they match. Stops at the first invalid block, the ones before
+    /// it stay imported. Returns how many blocks were added
+    pub fn import_blocks<R: Read>(&mut self, reader: R) -> Result<usize> {
+        let (start, blocks) = export::read(reader)?;
+        if start > self.block_height() {
+            return Err(BtcError::InvalidBlockFile(format!(
+                "blocks start at height {start} but the chain only has {}",
+                self.block_height()
+            )));
+        }
+
+        let mut imported = 0;
+        for (height, block) in (start..).zip(blocks) {
+            if let Some(existing) = self.blocks.get(height as usize) {
+                if existing.hash() != block.hash() {
+                    return Err(BtcError::InvalidBlockFile(format!(
+                        "block {height} differs from the one in the chain"
+                    )));
+                }
+                continue;
+            }
+            self.add_block(block)?;
+            imported += 1;
+        }
+        Ok(imported)
+    }
+
+    pub fn import_blocks_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        self.import_blocks(File::open(path)?)
+    }
+
+    /// Audit the UTXO set against the emission schedule: since every coinbase pays exactly its
+    /// reward plus the fees other transactions left over, the unspent outputs have to add up to
+    /// exactly the rewards issued so far. Expects the UTXO set to be rebuilt
+    pub fn total_supply(&self) -> Result<Amount> {
+        let actual = Amount::checked_sum(self.utxos.values().map(|(output, _)| output.value))
+            .ok_or(BtcError::SupplyOverflow)?;
+        let expected = self.params.issued_until(self.block_height());
+        if actual != expected || actual > self.params.max_money() {
+            return Err(BtcError::SupplyMismatch { expected, actual });
+        }
+        Ok(actual)
+    }
+
+    pub fn calculate_block_reward(&self) -> Amount {
+        self.params.block_reward(self.block_height())
+    }
+}
+
+/// close enough for ratios of targets, not for anything consensus
+fn u256_as_f64(n: U256) -> f64 {
+    n.0.iter()
+        .rev()
+        .fold(0.0, |acc, limb| acc * 2f64.powi(64) + *limb as f64)
 }
 
+/// Saved with a format version header, older files are migrated when loading. See `format`
 impl Saveable for Blockchain {
     fn load<I: Read>(reader: I) -> IoResult<Self> {
-        ciborium::de::from_reader(reader)
-            .map_err(|_| IoError::new(IoErrorKind::InvalidData, "Failed to deserialize Block"))
+        format::read(reader)
     }
     fn save<O: Write>(&self, writer: O) -> IoResult<()> {
-        ciborium::ser::into_writer(self, writer)
-            .map_err(|_| IoError::new(IoErrorKind::InvalidData, "Failed to serialize Block"))
+        format::write(self, writer)
     }
 }
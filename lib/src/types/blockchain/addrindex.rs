@@ -0,0 +1,97 @@
+//! Optional index of everything that happened to each public key, for wallets showing their
+//! history
+
+use super::{Blockchain, richlist::Balances};
+use crate::{
+    crypto::PublicKey,
+    sha256::Hash,
+    types::{Amount, Block, TransactionOutput},
+};
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// What one transaction did to a public key's balance, it changed by `received - sent`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct AddressActivity {
+    pub txid: Hash,
+    pub height: u64,
+    /// paid to the key by the transaction's outputs
+    pub received: Amount,
+    /// spent from the key by the transaction's inputs
+    pub sent: Amount,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct AddressIndex {
+    activity: HashMap<PublicKey, Vec<AddressActivity>>,
+    balances: Balances,
+}
+
+impl AddressIndex {
+    pub(super) fn build<'a>(blocks: impl IntoIterator<Item = &'a Block>) -> Self {
+        let mut index = AddressIndex::default();
+        let mut utxos = HashMap::new();
+        for (height, block) in blocks.into_iter().enumerate() {
+            index.add_block(height as u64, block, &utxos);
+            Blockchain::apply_to_utxos(&mut utxos, block);
+        }
+        index
+    }
+
+    /// index a block, with `utxos` the unspent outputs from before it
+    pub(super) fn add_block(
+        &mut self,
+        height: u64,
+        block: &Block,
+        utxos: &HashMap<Hash, (TransactionOutput, bool)>,
+    ) {
+        // outputs may be spent later in the same block
+        let mut created: HashMap<Hash, &TransactionOutput> = HashMap::new();
+        for transaction in &block.transactions {
+            let mut changes: HashMap<&PublicKey, (Amount, Amount)> = HashMap::new();
+            for input in &transaction.inputs {
+                let hash = input.prev_transaction_output_hash;
+                let spent = utxos
+                    .get(&hash)
+                    .map(|(output, _)| output)
+                    .or_else(|| created.get(&hash).copied());
+                if let Some(spent) = spent {
+                    let (_, sent) = changes.entry(&spent.pubkey).or_default();
+                    *sent = sent.checked_add(spent.value).unwrap_or(Amount::MAX_MONEY);
+                }
+            }
+            for output in &transaction.outputs {
+                let (received, _) = changes.entry(&output.pubkey).or_default();
+                *received = received
+                    .checked_add(output.value)
+                    .unwrap_or(Amount::MAX_MONEY);
+                created.insert(output.hash(), output);
+            }
+
+            let txid = transaction.hash();
+            for (pubkey, (received, sent)) in changes {
+                self.balances.apply(pubkey, received, sent);
+                self.activity
+                    .entry(pubkey.clone())
+                    .or_default()
+                    .push(AddressActivity {
+                        txid,
+                        height,
+                        received,
+                        sent,
+                    });
+            }
+        }
+    }
+
+    pub(super) fn get(&self, pubkey: &PublicKey) -> &[AddressActivity] {
+        self.activity.get(pubkey).map_or(&[], Vec::as_slice)
+    }
+
+    pub(super) fn balances(&self) -> &Balances {
+        &self.balances
+    }
+}
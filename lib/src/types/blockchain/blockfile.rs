@@ -0,0 +1,492 @@
+//! Append-only file of a chain's blocks with an index next to it, so any block can be read by its
+//! height or hash without going through the ones before it.
+//!
+//! The block file is `MAGIC`, the version as a big endian u16, then each block as a u64 big endian
+//! length and its CBOR, framed like in exports. The index, at the same path with `.idx` added, is
+//! `INDEX_MAGIC` and the version, then for each height the offset of its block's frame as a big
+//! endian u64 and its hash. The index can always be built again from the blocks, see `reindex`.
+//!
+//! Blocks are written and synced before their index entry. Opening the file after a crash in
+//! between indexes the blocks the index misses, a block cut off halfway is dropped.
+//!
+//! The blocks read last are kept in memory, up to `DEFAULT_CACHE_CAPACITY` of them unless
+//! `with_cache_capacity` says otherwise, so reading the same blocks again, e.g. for the tip while
+//! validating new blocks, doesn't go to the disk.
+
+use super::Blockchain;
+use super::export::{MAX_BLOCK_SIZE, decode, encode, invalid};
+use crate::{
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::{Block, BlockHeader},
+    util::MerkleRoot,
+};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const MAGIC: &[u8; 8] = b"BTCRSBKF";
+pub const INDEX_MAGIC: &[u8; 8] = b"BTCRSIDX";
+pub const BLOCK_FILE_VERSION: u16 = 1;
+/// blocks kept in memory when `with_cache_capacity` isn't called
+pub const DEFAULT_CACHE_CAPACITY: usize = 128;
+
+/// bytes before the first block or index entry
+const HEADER_SIZE: u64 = 10;
+/// bytes of an index entry, the offset and the hash
+const ENTRY_SIZE: u64 = 40;
+
+/// What `BlockFile::verify` found
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockFileReport {
+    /// blocks read back whole
+    pub blocks: u64,
+    /// bytes of the block file
+    pub bytes: u64,
+    /// everything wrong with the files, the first problem with a block ends the check
+    pub problems: Vec<String>,
+}
+
+impl BlockFileReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockFile {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    blocks: File,
+    index: File,
+    /// where each height's frame starts
+    offsets: Vec<u64>,
+    hashes: Vec<Hash>,
+    heights: HashMap<Hash, u64>,
+    /// where the next block goes
+    end: u64,
+    cache: Cache,
+}
+
+/// The blocks read last by their height, the one used longest ago is dropped to make room
+#[derive(Debug)]
+struct Cache {
+    capacity: usize,
+    /// each block and when it was last used
+    blocks: HashMap<u64, (Block, u64)>,
+    /// the heights by when they were last used
+    used: BTreeMap<u64, u64>,
+    clock: u64,
+}
+
+fn index_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".idx");
+    PathBuf::from(path)
+}
+
+fn open_rw(path: &Path) -> Result<File> {
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?)
+}
+
+/// write the header of an empty file, or check the one of an existing file
+fn header(file: &mut File, magic: &[u8; 8]) -> Result<()> {
+    if file.metadata()?.len() == 0 {
+        file.write_all(magic)?;
+        file.write_all(&BLOCK_FILE_VERSION.to_be_bytes())?;
+        file.sync_data()?;
+        return Ok(());
+    }
+    let mut header = [0u8; HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)
+        .map_err(|_| invalid("too short for its header"))?;
+    if header[..8] != magic[..] {
+        return Err(invalid("not a block file"));
+    }
+    let version = u16::from_be_bytes([header[8], header[9]]);
+    if version > BLOCK_FILE_VERSION {
+        return Err(BtcError::UnsupportedFormatVersion {
+            found: version,
+            supported: BLOCK_FILE_VERSION,
+        });
+    }
+    Ok(())
+}
+
+/// the frame at `offset`, None when it isn't there whole
+fn read_frame(file: &mut File, offset: u64) -> Result<Option<Vec<u8>>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut len = [0u8; 8];
+    if file.read_exact(&mut len).is_err() {
+        return Ok(None);
+    }
+    let len = u64::from_be_bytes(len);
+    if len > MAX_BLOCK_SIZE {
+        return Ok(None);
+    }
+    let mut bytes = vec![0u8; len as usize];
+    if file.read_exact(&mut bytes).is_err() {
+        return Ok(None);
+    }
+    Ok(Some(bytes))
+}
+
+impl BlockFile {
+    /// Open the block file at `path` and its index, creating them if there are none
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut blocks = open_rw(&path)?;
+        header(&mut blocks, MAGIC)?;
+        let mut index = open_rw(&index_path(&path))?;
+        header(&mut index, INDEX_MAGIC)?;
+
+        let mut entries = vec![];
+        index.seek(SeekFrom::Start(HEADER_SIZE))?;
+        index.read_to_end(&mut entries)?;
+        let mut inner = Inner {
+            blocks,
+            index,
+            offsets: vec![],
+            hashes: vec![],
+            heights: HashMap::new(),
+            end: HEADER_SIZE,
+            cache: Cache::new(DEFAULT_CACHE_CAPACITY),
+        };
+        for entry in entries.chunks_exact(ENTRY_SIZE as usize) {
+            let offset = u64::from_be_bytes(entry[..8].try_into().unwrap());
+            let hash = Hash::from_be_bytes(entry[8..].try_into().unwrap());
+            inner.push(offset, hash);
+        }
+        inner.catch_up()?;
+        Ok(BlockFile {
+            path,
+            inner: Mutex::new(inner),
+        })
+    }
+
+    /// Keep up to `capacity` blocks in memory instead of `DEFAULT_CACHE_CAPACITY`, 0 reads every
+    /// block from the disk
+    pub fn with_cache_capacity(self, capacity: usize) -> Self {
+        self.inner.lock().unwrap().cache.resize(capacity);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// how many blocks there are
+    pub fn len(&self) -> u64 {
+        self.inner.lock().unwrap().offsets.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn hash_at(&self, height: u64) -> Option<Hash> {
+        let inner = self.inner.lock().unwrap();
+        inner.hashes.get(height as usize).copied()
+    }
+
+    pub fn height_of(&self, hash: &Hash) -> Option<u64> {
+        self.inner.lock().unwrap().heights.get(hash).copied()
+    }
+
+    /// Append `block`, which has to follow the last one. Returns its height
+    pub fn append(&self, block: &Block) -> Result<u64> {
+        let mut inner = self.inner.lock().unwrap();
+        let height = inner.offsets.len() as u64;
+        if let Some(tip) = inner.hashes.last()
+            && block.header.prev_block_hash != *tip
+        {
+            return Err(invalid(format!("block {height} doesn't follow {tip}")));
+        }
+        let bytes = encode(block)?;
+        let offset = inner.end;
+        inner.blocks.seek(SeekFrom::Start(offset))?;
+        inner
+            .blocks
+            .write_all(&(bytes.len() as u64).to_be_bytes())?;
+        inner.blocks.write_all(&bytes)?;
+        inner.blocks.sync_data()?;
+        inner.end = offset + 8 + bytes.len() as u64;
+        let hash = block.hash();
+        inner.write_entry(height, offset, hash)?;
+        inner.index.sync_data()?;
+        inner.push(offset, hash);
+        Ok(height)
+    }
+
+    /// the block at `height`, None past the last one
+    pub fn read_block(&self, height: u64) -> Result<Option<Block>> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(&offset) = inner.offsets.get(height as usize) else {
+            return Ok(None);
+        };
+        if let Some(block) = inner.cache.get(height) {
+            return Ok(Some(block));
+        }
+        let bytes = read_frame(&mut inner.blocks, offset)?
+            .ok_or_else(|| invalid(format!("block {height} is cut off")))?;
+        let block = decode(height, &bytes)?;
+        if block.hash() != inner.hashes[height as usize] {
+            return Err(invalid(format!(
+                "block {height} doesn't match its index entry"
+            )));
+        }
+        inner.cache.insert(height, block.clone());
+        Ok(Some(block))
+    }
+
+    /// the header of the block at `height`, None past the last one
+    pub fn read_header(&self, height: u64) -> Result<Option<BlockHeader>> {
+        Ok(self.read_block(height)?.map(|block| block.header))
+    }
+
+    pub fn read_block_by_hash(&self, hash: &Hash) -> Result<Option<Block>> {
+        match self.height_of(hash) {
+            Some(height) => self.read_block(height),
+            None => Ok(None),
+        }
+    }
+
+    /// Drop the blocks from `height` on, e.g. once they were taken off the chain
+    pub fn truncate(&self, height: u64) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(&offset) = inner.offsets.get(height as usize) else {
+            return Ok(());
+        };
+        inner.blocks.set_len(offset)?;
+        inner.blocks.sync_data()?;
+        inner.index.set_len(HEADER_SIZE + height * ENTRY_SIZE)?;
+        inner.index.sync_data()?;
+        for hash in inner.hashes.drain(height as usize..).collect::<Vec<_>>() {
+            inner.heights.remove(&hash);
+        }
+        inner.offsets.truncate(height as usize);
+        inner.cache.truncate(height);
+        inner.end = offset;
+        Ok(())
+    }
+
+    /// Build the index again from the blocks, e.g. once `verify` found it wrong. Stops at the
+    /// first block that can't be read, it and the ones after are dropped
+    pub fn reindex(&self) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.index.set_len(HEADER_SIZE)?;
+        inner.offsets.clear();
+        inner.hashes.clear();
+        inner.heights.clear();
+        inner.cache.clear();
+        inner.end = HEADER_SIZE;
+        inner.catch_up()
+    }
+
+    /// Read every block back and check it against the index and the block before it, the way
+    /// `fsck` checks a file system. Changes nothing
+    pub fn verify(&self) -> Result<BlockFileReport> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut report = BlockFileReport {
+            bytes: inner.blocks.metadata()?.len(),
+            ..Default::default()
+        };
+        let mut offset = HEADER_SIZE;
+        let mut previous: Option<Hash> = None;
+        while offset < report.bytes {
+            let height = report.blocks;
+            let problem = |problem: &str| format!("block {height} at {offset}: {problem}");
+            let Some(bytes) = read_frame(&mut inner.blocks, offset)? else {
+                report.problems.push(problem("cut off"));
+                break;
+            };
+            let block = match decode(height, &bytes) {
+                Ok(block) => block,
+                Err(e) => {
+                    report.problems.push(problem(&e.to_string()));
+                    break;
+                }
+            };
+            let hash = block.hash();
+            match (
+                inner.offsets.get(height as usize),
+                inner.hashes.get(height as usize),
+            ) {
+                (None, _) => report.problems.push(problem("not indexed")),
+                (Some(&indexed), _) if indexed != offset => report
+                    .problems
+                    .push(problem(&format!("indexed at {indexed}"))),
+                (_, Some(indexed)) if *indexed != hash => report
+                    .problems
+                    .push(problem(&format!("indexed as {indexed}, is {hash}"))),
+                _ => {}
+            }
+            if let Some(previous) = previous
+                && block.header.prev_block_hash != previous
+            {
+                report
+                    .problems
+                    .push(problem("doesn't follow the block before"));
+            }
+            if MerkleRoot::calculate(&block.transactions).ok() != Some(block.header.merkle_root) {
+                report
+                    .problems
+                    .push(problem("merkle root doesn't match its transactions"));
+            }
+            previous = Some(hash);
+            report.blocks += 1;
+            offset += 8 + bytes.len() as u64;
+        }
+        if (inner.offsets.len() as u64) > report.blocks {
+            report.problems.push(format!(
+                "{} index entries for {} blocks",
+                inner.offsets.len(),
+                report.blocks
+            ));
+        }
+        Ok(report)
+    }
+}
+
+impl Inner {
+    fn push(&mut self, offset: u64, hash: Hash) {
+        self.heights.insert(hash, self.offsets.len() as u64);
+        self.offsets.push(offset);
+        self.hashes.push(hash);
+    }
+
+    fn write_entry(&mut self, height: u64, offset: u64, hash: Hash) -> Result<()> {
+        self.index
+            .seek(SeekFrom::Start(HEADER_SIZE + height * ENTRY_SIZE))?;
+        self.index.write_all(&offset.to_be_bytes())?;
+        self.index.write_all(&hash.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Drop index entries for blocks that aren't there whole, index the blocks after the last
+    /// entry and cut off what's left of a block after them
+    fn catch_up(&mut self) -> Result<()> {
+        let len = self.blocks.metadata()?.len();
+        while let Some(&offset) = self.offsets.last() {
+            if let Some(bytes) = read_frame(&mut self.blocks, offset)? {
+                self.end = offset + 8 + bytes.len() as u64;
+                break;
+            }
+            let hash = self.hashes.pop().unwrap();
+            self.heights.remove(&hash);
+            self.offsets.pop();
+        }
+        let indexed = self.offsets.len() as u64;
+        self.index.set_len(HEADER_SIZE + indexed * ENTRY_SIZE)?;
+
+        while self.end < len {
+            let height = self.offsets.len() as u64;
+            let Some(bytes) = read_frame(&mut self.blocks, self.end)? else {
+                break;
+            };
+            let Ok(block) = decode(height, &bytes) else {
+                break;
+            };
+            let (offset, hash) = (self.end, block.hash());
+            self.write_entry(height, offset, hash)?;
+            self.push(offset, hash);
+            self.end = offset + 8 + bytes.len() as u64;
+        }
+        if self.end < len {
+            self.blocks.set_len(self.end)?;
+            self.blocks.sync_data()?;
+        }
+        self.index.sync_data()?;
+        Ok(())
+    }
+}
+
+impl Cache {
+    fn new(capacity: usize) -> Self {
+        Cache {
+            capacity,
+            blocks: HashMap::new(),
+            used: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, height: u64) -> Option<Block> {
+        let (block, used) = self.blocks.get_mut(&height)?;
+        self.used.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.used.insert(self.clock, height);
+        Some(block.clone())
+    }
+
+    fn insert(&mut self, height: u64, block: Block) {
+        if self.capacity == 0 || self.blocks.contains_key(&height) {
+            return;
+        }
+        self.evict(self.capacity - 1);
+        self.clock += 1;
+        self.blocks.insert(height, (block, self.clock));
+        self.used.insert(self.clock, height);
+    }
+
+    fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict(capacity);
+    }
+
+    /// drop the blocks used longest ago until at most `keep` are left
+    fn evict(&mut self, keep: usize) {
+        while self.blocks.len() > keep {
+            let (_, height) = self.used.pop_first().unwrap();
+            self.blocks.remove(&height);
+        }
+    }
+
+    /// drop the blocks from `height` on
+    fn truncate(&mut self, height: u64) {
+        let used = &mut self.used;
+        self.blocks.retain(|&cached, (_, last)| {
+            cached < height || {
+                used.remove(last);
+                false
+            }
+        });
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+        self.used.clear();
+    }
+}
+
+impl Blockchain {
+    /// Bring `file` up to date with the chain: blocks the chain took off since are dropped from
+    /// it and the ones it's missing appended. Returns how many blocks were appended
+    pub fn write_block_file(&self, file: &BlockFile) -> Result<usize> {
+        let mut height = file.len().min(self.block_height());
+        while height > 0
+            && file.hash_at(height - 1) != Some(self.blocks[height as usize - 1].hash())
+        {
+            height -= 1;
+        }
+        file.truncate(height)?;
+        for block in &self.blocks[height as usize..] {
+            file.append(block)?;
+        }
+        Ok(self.blocks.len() - height as usize)
+    }
+}
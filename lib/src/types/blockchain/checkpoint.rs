@@ -0,0 +1,179 @@
+//! Checkpoints signed by a federation: on chains whose spec names checkpoint authorities, they
+//! sign the hash of a block now and then, and once enough of them have, nodes refuse to take that
+//! block or any before it off the chain again. Meant for networks with someone in charge, e.g. a
+//! classroom chain the teacher's nodes keep from being rewritten by a student with a fast miner.
+
+use super::Blockchain;
+use crate::{
+    crypto::{PrivateKey, PublicKey, Signature},
+    error::{BtcError, Result},
+    sha256::Hash,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// signed before the checkpoint, so the signature is good for nothing else
+const CHECKPOINT_SIGNING_PREFIX: &[u8] = b"kme-btcrs checkpoint:";
+
+/// The keys allowed to sign checkpoints, and how many of them have to, see `ChainParams`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Federation {
+    pub authorities: Vec<PublicKey>,
+    /// signatures a checkpoint needs, 1 if left out
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+}
+
+fn default_threshold() -> usize {
+    1
+}
+
+/// The block at `height` is `hash`, as signed by some of the authorities
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct SignedCheckpoint {
+    pub height: u64,
+    pub hash: Hash,
+    pub signatures: Vec<CheckpointSignature>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct CheckpointSignature {
+    pub key: PublicKey,
+    pub signature: Signature,
+}
+
+impl SignedCheckpoint {
+    /// a checkpoint nobody signed yet
+    pub fn new(height: u64, hash: Hash) -> Self {
+        SignedCheckpoint {
+            height,
+            hash,
+            signatures: vec![],
+        }
+    }
+
+    fn message(&self) -> Vec<u8> {
+        let mut message = CHECKPOINT_SIGNING_PREFIX.to_vec();
+        message.extend_from_slice(&self.height.to_be_bytes());
+        message.extend_from_slice(&self.hash.as_bytes());
+        message
+    }
+
+    /// add `key`'s signature, replacing one it made before
+    pub fn sign(&mut self, key: &PrivateKey) {
+        let public = key.public_key();
+        self.signatures.retain(|signed| signed.key != public);
+        self.signatures.push(CheckpointSignature {
+            key: public,
+            signature: key.sign_message(&self.message()),
+        });
+    }
+
+    pub fn is_signed_by(&self, key: &PublicKey) -> bool {
+        self.signatures.iter().any(|signed| signed.key == *key)
+    }
+
+    /// how many of the `federation`'s authorities signed it, each counted once
+    pub fn signers(&self, federation: &Federation) -> usize {
+        let message = self.message();
+        federation
+            .authorities
+            .iter()
+            .filter(|authority| {
+                self.signatures.iter().any(|signed| {
+                    signed.key == **authority
+                        && authority.verify_message(&message, &signed.signature)
+                })
+            })
+            .count()
+    }
+
+    /// whether enough of the `federation` signed it
+    pub fn is_final(&self, federation: &Federation) -> bool {
+        self.signers(federation) >= federation.threshold
+    }
+
+    /// Take the good signatures of `other` over the same block that this one lacks. Returns
+    /// whether there were any
+    pub fn merge(&mut self, other: &SignedCheckpoint) -> bool {
+        if other.height != self.height || other.hash != self.hash {
+            return false;
+        }
+        let message = self.message();
+        let mut merged = false;
+        for signed in &other.signatures {
+            if !self.is_signed_by(&signed.key)
+                && signed.key.verify_message(&message, &signed.signature)
+            {
+                self.signatures.push(signed.clone());
+                merged = true;
+            }
+        }
+        merged
+    }
+}
+
+impl Blockchain {
+    /// the latest checkpoint enough authorities signed, nothing up to it comes off the chain
+    pub fn checkpoint(&self) -> Option<&SignedCheckpoint> {
+        self.checkpoint.as_ref()
+    }
+
+    /// Hold the chain to `checkpoint` from now on. Errors if the chain has no federation, not
+    /// enough of it signed or the chain has another block at its height, or none. One no newer
+    /// than the last is left be, returns whether it was taken
+    pub fn add_checkpoint(&mut self, checkpoint: SignedCheckpoint) -> Result<bool> {
+        let Some(federation) = &self.params.checkpoints else {
+            return Err(BtcError::InvalidCheckpoint(
+                "the chain has no checkpoint authorities".to_string(),
+            ));
+        };
+        let signers = checkpoint.signers(federation);
+        if signers < federation.threshold {
+            return Err(BtcError::InvalidCheckpoint(format!(
+                "signed by {signers} of the {} authorities needed",
+                federation.threshold
+            )));
+        }
+        if self
+            .checkpoint
+            .as_ref()
+            .is_some_and(|last| last.height >= checkpoint.height)
+        {
+            return Ok(false);
+        }
+        match self.blocks.get(checkpoint.height as usize) {
+            Some(block) if block.hash() == checkpoint.hash => {}
+            Some(block) => {
+                return Err(BtcError::InvalidCheckpoint(format!(
+                    "block {} is {} here, not {}",
+                    checkpoint.height,
+                    block.hash(),
+                    checkpoint.hash
+                )));
+            }
+            None => {
+                return Err(BtcError::InvalidCheckpoint(format!(
+                    "the chain has no block {} yet",
+                    checkpoint.height
+                )));
+            }
+        }
+        self.checkpoint = Some(checkpoint);
+        Ok(true)
+    }
+
+    /// errors if taking the blocks from `height` on off the chain would take a checkpointed one
+    pub(super) fn check_checkpoint(&self, height: u64) -> Result<()> {
+        match &self.checkpoint {
+            Some(checkpoint) if height <= checkpoint.height => Err(BtcError::BelowCheckpoint {
+                height,
+                checkpoint: checkpoint.height,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Portable files holding a range of blocks, so chains can be handed around without the node.
+//!
+//! The binary format is
+//!
+//! | field         | size                                 |
+//! |---------------|--------------------------------------|
+//! | `MAGIC`       | 8 bytes                              |
+//! | version       | u16, big endian                      |
+//! | start height  | u64, big endian                      |
+//! | block count   | u64, big endian                      |
+//! | blocks        | per block: u64 big endian length, then the block as CBOR |
+//!
+//! which frames blocks the same way `Message`s are framed on the wire. The hex format is plain
+//! text for pasting around: a `btcrs-blocks <version> <start height>` line, then one line with
+//! the hex of each block's CBOR.
+
+use crate::error::{BtcError, Result};
+use crate::types::Block;
+
+use std::io::{BufRead, Read, Write};
+
+pub const MAGIC: &[u8; 8] = b"BTCRSBLK";
+pub const HEX_HEADER: &str = "btcrs-blocks";
+pub const EXPORT_VERSION: u16 = 1;
+
+/// a block bigger than this is certainly not one of ours
+pub(super) const MAX_BLOCK_SIZE: u64 = 32 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Binary,
+    Hex,
+}
+
+pub(super) fn invalid(reason: impl Into<String>) -> BtcError {
+    BtcError::InvalidBlockFile(reason.into())
+}
+
+pub(super) fn encode(block: &Block) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    ciborium::into_writer(block, &mut bytes)
+        .map_err(|e| invalid(format!("failed to encode block: {e}")))?;
+    Ok(bytes)
+}
+
+pub(super) fn decode(height: u64, bytes: &[u8]) -> Result<Block> {
+    ciborium::from_reader(bytes).map_err(|e| invalid(format!("block {height}: {e}")))
+}
+
+pub(super) fn write<W: Write>(
+    blocks: &[Block],
+    start: u64,
+    format: ExportFormat,
+    mut writer: W,
+) -> Result<()> {
+    match format {
+        ExportFormat::Binary => {
+            writer.write_all(MAGIC)?;
+            writer.write_all(&EXPORT_VERSION.to_be_bytes())?;
+            writer.write_all(&start.to_be_bytes())?;
+            writer.write_all(&(blocks.len() as u64).to_be_bytes())?;
+            for block in blocks {
+                let bytes = encode(block)?;
+                writer.write_all(&(bytes.len() as u64).to_be_bytes())?;
+                writer.write_all(&bytes)?;
+            }
+        }
+        ExportFormat::Hex => {
+            writeln!(writer, "{HEX_HEADER} {EXPORT_VERSION} {start}")?;
+            for block in blocks {
+                writeln!(writer, "{}", hex::encode(encode(block)?))?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// the start height and blocks of an export in either format
+pub(super) fn read<R: Read>(mut reader: R) -> Result<(u64, Vec<Block>)> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    if bytes.starts_with(MAGIC) {
+        read_binary(bytes.as_slice())
+    } else {
+        read_hex(bytes.as_slice())
+    }
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn check_version(version: u16) -> Result<()> {
+    if version > EXPORT_VERSION {
+        return Err(BtcError::UnsupportedFormatVersion {
+            found: version,
+            supported: EXPORT_VERSION,
+        });
+    }
+    Ok(())
+}
+
+fn read_binary(mut reader: impl Read) -> Result<(u64, Vec<Block>)> {
+    let mut header = [0u8; 10];
+    reader.read_exact(&mut header)?;
+    check_version(u16::from_be_bytes([header[8], header[9]]))?;
+    let start = read_u64(&mut reader)?;
+    let count = read_u64(&mut reader)?;
+
+    let mut blocks = vec![];
+    for height in start..start.saturating_add(count) {
+        let len = read_u64(&mut reader)?;
+        if len > MAX_BLOCK_SIZE {
+            return Err(invalid(format!("block {height} claims to be {len} bytes")));
+        }
+        let mut bytes = vec![0u8; len as usize];
+        reader.read_exact(&mut bytes)?;
+        blocks.push(decode(height, &bytes)?);
+    }
+    Ok((start, blocks))
+}
+
+fn read_hex(reader: impl BufRead) -> Result<(u64, Vec<Block>)> {
+    let mut lines = reader.lines();
+    let header = lines.next().ok_or_else(|| invalid("empty file"))??;
+    let (version, start) = match header.split_whitespace().collect::<Vec<_>>()[..] {
+        [HEX_HEADER, version, start] => (version.parse(), start.parse()),
+        _ => return Err(invalid("not a block export")),
+    };
+    let (Ok(version), Ok(start)) = (version, start) else {
+        return Err(invalid(format!("bad header: {header}")));
+    };
+    check_version(version)?;
+
+    let mut blocks = vec![];
+    for (height, line) in (start..).zip(lines) {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bytes =
+            hex::decode(line.trim()).map_err(|e| invalid(format!("block {height}: {e}")))?;
+        blocks.push(decode(height, &bytes)?);
+    }
+    Ok((start, blocks))
+}
@@ -0,0 +1,116 @@
+//! On-disk format of a saved `Blockchain`: `MAGIC`, the format version as a big endian u16, then
+//! the chain as CBOR followed by the `UtxoChecksum` of its UTXO set. Files from before the header
+//! are version 0, files from before the checksum have nothing after the chain.
+//!
+//! Changing the layout of `Blockchain` or anything inside it means bumping `FORMAT_VERSION` and
+//! adding a migration that turns a document of the previous version into the new one.
+
+use crate::error::BtcError;
+use crate::types::{Blockchain, UtxoChecksum};
+
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Write};
+
+use ciborium::Value;
+
+pub const MAGIC: &[u8; 8] = b"BTCRSCHN";
+pub const FORMAT_VERSION: u16 = 4;
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4];
+
+pub(super) fn write<O: Write>(blockchain: &Blockchain, mut writer: O) -> IoResult<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_be_bytes())?;
+    let failed = |_| IoError::new(IoErrorKind::InvalidData, "Failed to serialize Blockchain");
+    ciborium::ser::into_writer(blockchain, &mut writer).map_err(failed)?;
+    ciborium::ser::into_writer(&UtxoChecksum::of(blockchain.utxos()), writer).map_err(failed)
+}
+
+pub(super) fn read<I: Read>(mut reader: I) -> IoResult<Blockchain> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let (version, payload) = split_header(&bytes);
+    let invalid = |reason: String| IoError::new(IoErrorKind::InvalidData, reason);
+
+    if version > FORMAT_VERSION {
+        return Err(IoError::new(
+            IoErrorKind::InvalidData,
+            BtcError::UnsupportedFormatVersion {
+                found: version,
+                supported: FORMAT_VERSION,
+            },
+        ));
+    }
+    if version == FORMAT_VERSION {
+        let mut payload = payload;
+        let mut blockchain: Blockchain = ciborium::de::from_reader(&mut payload)
+            .map_err(|e| invalid(format!("Failed to deserialize Blockchain: {e}")))?;
+        let checksum = ciborium::de::from_reader(payload)
+            .map_err(|e| invalid(format!("Failed to deserialize the UTXO checksum: {e}")))?;
+        blockchain.saved_utxos = Some(checksum);
+        return Ok(blockchain);
+    }
+
+    let mut document: Value = ciborium::de::from_reader(payload)
+        .map_err(|e| invalid(format!("Failed to read version {version} Blockchain: {e}")))?;
+    for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migrate(&mut document).map_err(|e| {
+            invalid(format!(
+                "Failed to migrate Blockchain from version {from}: {e}"
+            ))
+        })?;
+    }
+    document
+        .deserialized()
+        .map_err(|e| invalid(format!("Failed to deserialize migrated Blockchain: {e}")))
+}
+
+/// the format version of a saved chain and its CBOR payload
+fn split_header(bytes: &[u8]) -> (u16, &[u8]) {
+    match bytes.strip_prefix(MAGIC.as_slice()) {
+        Some([high, low, payload @ ..]) => (u16::from_be_bytes([*high, *low]), payload),
+        _ => (0, bytes),
+    }
+}
+
+/// Version 0 keyed the UTXO set by transaction hash while inputs spend outputs by their own hash.
+/// Drop it, loading a chain always rebuilds it anyway
+fn v0_to_v1(document: &mut Value) -> Result<(), String> {
+    let Value::Map(fields) = document else {
+        return Err("expected a map".to_string());
+    };
+    for (key, value) in fields.iter_mut() {
+        if key.as_text() == Some("utxos") {
+            *value = Value::Map(vec![]);
+        }
+    }
+    Ok(())
+}
+
+/// Version 2 keeps the blocks marked invalid and the ones taken off the chain, see `invalidate`
+fn v1_to_v2(document: &mut Value) -> Result<(), String> {
+    let Value::Map(fields) = document else {
+        return Err("expected a map".to_string());
+    };
+    for field in ["invalid", "disconnected"] {
+        fields.push((Value::Text(field.to_string()), Value::Array(vec![])));
+    }
+    Ok(())
+}
+
+/// Version 3 keeps undo data for the blocks, see `undo`. Older chains start without any
+fn v2_to_v3(document: &mut Value) -> Result<(), String> {
+    let Value::Map(fields) = document else {
+        return Err("expected a map".to_string());
+    };
+    fields.push((Value::Text("undo".to_string()), Value::Array(vec![])));
+    Ok(())
+}
+
+/// Version 4 has the `UtxoChecksum` after the chain, the chain itself is as it was. Older chains
+/// are loaded without one
+fn v3_to_v4(_: &mut Value) -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! What a key had at some point in the past, e.g. to plot its balance over time without replaying
+//! the chain. Both come from the key's history, the addrindex if there is one and the blocks
+//! otherwise, see `walk_history`: its balance adds up what it received and sent, its outputs are
+//! the ones the transactions it was in paid it and nothing later spent.
+
+use super::{AddressActivity, Blockchain};
+use crate::{
+    crypto::PublicKey,
+    types::{Amount, TransactionOutput},
+};
+
+use std::collections::HashSet;
+
+impl Blockchain {
+    /// What `pubkey` had once the chain had `height` blocks. `None` if it never had that many
+    pub fn balance_at(&self, pubkey: &PublicKey, height: u64) -> Option<Amount> {
+        let history = self.history_until(pubkey, height)?;
+        let received = history.iter().map(|activity| activity.received);
+        let sent = history.iter().map(|activity| activity.sent);
+        let received = Amount::checked_sum(received).unwrap_or(Amount::MAX_MONEY);
+        let sent = Amount::checked_sum(sent).unwrap_or(Amount::MAX_MONEY);
+        Some(received.saturating_sub(sent))
+    }
+
+    /// The outputs `pubkey` could spend once the chain had `height` blocks, oldest first. `None`
+    /// if it never had that many
+    pub fn utxos_at(&self, pubkey: &PublicKey, height: u64) -> Option<Vec<TransactionOutput>> {
+        let history = self.history_until(pubkey, height)?;
+        let mut created = vec![];
+        let mut spent = HashSet::new();
+        for activity in &history {
+            let Some(transaction) = self.blocks[activity.height as usize]
+                .transactions
+                .iter()
+                .find(|transaction| transaction.hash() == activity.txid)
+            else {
+                continue;
+            };
+            spent.extend(
+                transaction
+                    .inputs
+                    .iter()
+                    .map(|input| input.prev_transaction_output_hash),
+            );
+            created.extend(
+                transaction
+                    .outputs
+                    .iter()
+                    .filter(|output| output.pubkey == *pubkey),
+            );
+        }
+        Some(
+            created
+                .into_iter()
+                .filter(|output| !spent.contains(&output.hash()))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// what happened to `pubkey` in the first `height` blocks
+    fn history_until(&self, pubkey: &PublicKey, height: u64) -> Option<Vec<AddressActivity>> {
+        if height > self.block_height() {
+            return None;
+        }
+        Some(match &self.addrindex {
+            Some(addrindex) => addrindex
+                .get(pubkey)
+                .iter()
+                .take_while(|activity| activity.height < height)
+                .cloned()
+                .collect(),
+            None => self.walk_history(pubkey, 0..height),
+        })
+    }
+}
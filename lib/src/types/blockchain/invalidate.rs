@@ -0,0 +1,202 @@
+//! Marking blocks invalid by hand, like bitcoind's `invalidateblock`, to see what a node does when
+//! the chain forks. The chain is one list of blocks, so an invalidated block is taken off with
+//! every block after it and they're kept aside. Reconsidering it puts back the longest run of
+//! kept blocks that builds on the chain, if that's longer than what the chain has there now.
+
+use super::{Blockchain, WalRecord};
+use crate::{
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::{Block, Transaction},
+};
+
+use std::collections::{HashMap, HashSet};
+
+impl Blockchain {
+    /// whether `invalidate_block` marked the block, and it wasn't reconsidered since
+    pub fn is_invalidated(&self, hash: &Hash) -> bool {
+        self.invalid.contains(hash)
+    }
+
+    /// blocks taken off the chain that may be put back, in the order they were taken off
+    pub fn disconnected_blocks(&self) -> impl Iterator<Item = &Block> {
+        self.disconnected.iter()
+    }
+
+    /// Mark a block invalid, it won't be added again until it's reconsidered. If it's in the
+    /// chain it's taken off with every block after it: the UTXO set goes back to before it and
+    /// the blocks' transactions go back to the mempool. Then a kept fork that's now longer takes
+    /// their place. Returns how many blocks were taken off
+    pub fn invalidate_block(&mut self, hash: &Hash) -> Result<usize> {
+        let Some(height) = self.height_of(hash) else {
+            if !self.is_disconnected(hash) {
+                return Err(BtcError::UnknownBlock(*hash));
+            }
+            self.invalid.insert(*hash);
+            return Ok(0);
+        };
+        self.check_checkpoint(height)?;
+        self.invalid.insert(*hash);
+        let removed = self.disconnect_from(height)?.len();
+        // a fork kept from before may be longer now, a bad block in it is marked and skipped
+        let _ = self.reconnect();
+        Ok(removed)
+    }
+
+    /// Clear the marks `invalidate_block` left on a block, the blocks before it and the ones
+    /// after it, then put back the longest run of kept blocks if it beats the chain. A block
+    /// failing validation on the way is marked invalid and the chain stays as it was. Returns how
+    /// many blocks were added
+    pub fn reconsider_block(&mut self, hash: &Hash) -> Result<usize> {
+        if self.height_of(hash).is_none() && !self.is_disconnected(hash) {
+            return Err(BtcError::UnknownBlock(*hash));
+        }
+        let related: Vec<Hash> = self
+            .disconnected
+            .iter()
+            .map(|block| block.hash())
+            .filter(|other| {
+                self.kept_ancestors(other).contains(hash)
+                    || self.kept_ancestors(hash).contains(other)
+            })
+            .collect();
+        self.invalid.remove(hash);
+        for other in related {
+            self.invalid.remove(&other);
+        }
+        self.reconnect()
+    }
+
+    /// position of a block in the chain
+    fn height_of(&self, hash: &Hash) -> Option<u64> {
+        self.blocks
+            .iter()
+            .position(|block| block.hash() == *hash)
+            .map(|height| height as u64)
+    }
+
+    fn is_disconnected(&self, hash: &Hash) -> bool {
+        self.disconnected.iter().any(|block| block.hash() == *hash)
+    }
+
+    /// `hash` and the kept blocks it builds on, back to where they leave the kept ones
+    fn kept_ancestors(&self, hash: &Hash) -> HashSet<Hash> {
+        let by_hash: HashMap<Hash, &Block> = self
+            .disconnected
+            .iter()
+            .map(|block| (block.hash(), block))
+            .collect();
+        let mut ancestors = HashSet::from([*hash]);
+        let mut current = by_hash.get(hash);
+        while let Some(block) = current {
+            let prev = block.header.prev_block_hash;
+            if !ancestors.insert(prev) {
+                break;
+            }
+            current = by_hash.get(&prev);
+        }
+        ancestors
+    }
+
+    /// Take the blocks from `height` on off the chain and keep them aside. Rolls back the UTXO
+    /// set, the target and the indexes, and refills the mempool. The UTXO set goes back with the
+    /// blocks' undo data, it's only rebuilt if some don't have any. Returns the blocks
+    pub(super) fn disconnect_from(&mut self, height: u64) -> Result<Vec<Block>> {
+        if height >= self.block_height() {
+            return Ok(vec![]);
+        }
+        self.check_checkpoint(height)?;
+        self.log(&WalRecord::Unapply {
+            height,
+            tip: self.tip_hash(),
+        })?;
+        let removed = self.blocks.split_off(height as usize);
+        self.disconnected.extend(removed.iter().cloned());
+        if self.unapply_from_utxos(&removed) {
+            self.clear_utxo_marks();
+        } else {
+            self.undo.clear();
+            self.rebuild_utxos();
+        }
+        self.retarget();
+        if self.txindex.is_some() {
+            self.enable_txindex();
+        }
+        if self.addrindex.is_some() {
+            self.enable_addrindex();
+        }
+        // coinbases can't go in the mempool
+        let returned: Vec<Transaction> = removed
+            .iter()
+            .flat_map(|block| block.transactions.iter().skip(1))
+            .cloned()
+            .collect();
+        self.refill_mempool(returned);
+        self.log(&WalRecord::Done)?;
+        Ok(removed)
+    }
+
+    /// The longest run of kept blocks, none of them marked, that builds on a block of the chain
+    /// or starts a new one. Returns the height it forks off at with the blocks
+    fn best_branch(&self) -> Option<(u64, Vec<Block>)> {
+        let mut children: HashMap<Hash, Vec<&Block>> = HashMap::new();
+        for block in &self.disconnected {
+            if !self.invalid.contains(&block.hash()) {
+                children
+                    .entry(block.header.prev_block_hash)
+                    .or_default()
+                    .push(block);
+            }
+        }
+        // what a block builds on, with the height it would get
+        let parents = std::iter::once(Hash::zero())
+            .chain(self.blocks.iter().map(Block::hash))
+            .zip(0..);
+        parents
+            .filter_map(|(parent, height)| {
+                let branch = longest_run(&children, &parent);
+                (!branch.is_empty()).then_some((height, branch))
+            })
+            .max_by_key(|(height, branch)| height + branch.len() as u64)
+    }
+
+    /// Switch to `best_branch` if it's longer than the chain, going back if one of its blocks
+    /// turns out to be invalid
+    fn reconnect(&mut self) -> Result<usize> {
+        let Some((fork, branch)) = self.best_branch() else {
+            return Ok(0);
+        };
+        if fork + branch.len() as u64 <= self.block_height() {
+            return Ok(0);
+        }
+        let replaced = self.disconnect_from(fork)?;
+        let added = branch.len();
+        for block in branch {
+            let hash = block.hash();
+            if let Err(e) = self.add_block(block) {
+                self.invalid.insert(hash);
+                self.disconnect_from(fork)?;
+                for block in replaced {
+                    self.add_block(block)?;
+                }
+                return Err(e);
+            }
+        }
+        Ok(added)
+    }
+}
+
+/// the longest chain of `children` starting from the ones of `parent`
+fn longest_run(children: &HashMap<Hash, Vec<&Block>>, parent: &Hash) -> Vec<Block> {
+    children
+        .get(parent)
+        .into_iter()
+        .flatten()
+        .map(|child| {
+            let mut run = vec![(*child).clone()];
+            run.extend(longest_run(children, &child.hash()));
+            run
+        })
+        .max_by_key(Vec::len)
+        .unwrap_or_default()
+}
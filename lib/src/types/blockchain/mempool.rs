@@ -0,0 +1,645 @@
+//! Transactions waiting to get mined. A transaction may spend outputs of another one still in the
+//! mempool, the miner then has to take both and can be paid for the parent by the child's fee
+//! (child pays for parent), so templates pick whole packages by their fees. Transactions are also
+//! kept in the order they came in, so expiring old ones only ever looks at those.
+
+use super::Blockchain;
+use crate::{
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::{Amount, Block, Transaction, TransactionOutput},
+};
+
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// evictions a subscriber may fall behind on before it misses some
+const EVICTION_BACKLOG: usize = 1024;
+
+/// Why a transaction left the mempool without getting mined. Whatever spends from it leaves
+/// with it, for the same reason
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub enum EvictionReason {
+    /// it waited longer than its node's `MempoolPolicy::max_transaction_age`
+    Expired,
+    /// a newer transaction spends one of its inputs
+    Replaced,
+    /// a block spent one of its inputs
+    Conflict,
+    /// the mempool was full and it paid the least per byte
+    Full,
+    /// an output it spent was taken off the chain with its block
+    Reorg,
+}
+
+#[derive(Clone, Debug)]
+pub struct Eviction {
+    pub transaction: Transaction,
+    pub reason: EvictionReason,
+    /// what replaced it or got mined instead, for the transaction itself but not for what spent
+    /// from it
+    pub double_spend: Option<DoubleSpend>,
+}
+
+/// Another transaction spending outputs a transaction spends too, e.g. a payer trying to take a
+/// payment back before it's mined
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct DoubleSpend {
+    pub txid: Hash,
+    pub conflicting: Hash,
+    /// the outputs both spend
+    pub outputs: Vec<Hash>,
+    pub outcome: DoubleSpendOutcome,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub enum DoubleSpendOutcome {
+    /// the node refused the conflicting transaction, the first one is still waiting to get mined
+    Rejected,
+    /// the conflicting transaction took the first one's place in the mempool
+    Replaced,
+    /// a block mined the conflicting transaction, the first one never will be
+    Confirmed,
+}
+
+/// A summary of the mempool for status reports
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MempoolStats {
+    pub transactions: usize,
+    /// bytes of all transactions, see `Transaction::size`
+    pub size: usize,
+    /// what mining every transaction would pay
+    pub fees: Amount,
+    /// when the oldest transaction came in
+    pub oldest: Option<DateTime<Utc>>,
+}
+
+/// What adding a transaction to the mempool would do, see `Blockchain::test_mempool_accept`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MempoolAcceptance {
+    /// what it leaves to the miner
+    pub fee: Amount,
+    /// bytes, see `Transaction::size`
+    pub size: usize,
+    /// mempool transactions spending the same outputs it would take the place of
+    pub replaces: Vec<Hash>,
+    /// mempool transactions paying less per byte it would push out of a full mempool
+    pub evicts: Vec<Hash>,
+}
+
+/// A mempool transaction with what it pays and where it stands in line, as `FetchMempool` lists
+/// it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MempoolTxInfo {
+    pub txid: Hash,
+    /// bytes, see `Transaction::size`
+    pub size: usize,
+    pub fee: Amount,
+    pub received: DateTime<Utc>,
+    /// how many transactions pay more per byte, or as much and came in first. Templates take
+    /// whole packages, so a child paying well can still get its parent mined sooner
+    pub position: usize,
+}
+
+impl MempoolTxInfo {
+    /// sats per byte
+    pub fn fee_rate(&self) -> f64 {
+        self.fee.to_sat() as f64 / self.size.max(1) as f64
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Mempool {
+    transactions: HashMap<Hash, (Transaction, DateTime<Utc>)>,
+    /// oldest first
+    by_time: BTreeSet<(DateTime<Utc>, Hash)>,
+    /// the mempool transaction that created an output
+    creators: HashMap<Hash, Hash>,
+    /// the mempool transaction spending an output
+    spenders: HashMap<Hash, Hash>,
+    /// bytes of all transactions
+    size: usize,
+    /// bumped whenever a transaction comes in or leaves
+    generation: u64,
+    evictions: broadcast::Sender<Eviction>,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        let (evictions, _) = broadcast::channel(EVICTION_BACKLOG);
+        Mempool {
+            transactions: HashMap::new(),
+            by_time: BTreeSet::new(),
+            creators: HashMap::new(),
+            spenders: HashMap::new(),
+            size: 0,
+            generation: 0,
+            evictions,
+        }
+    }
+}
+
+impl Mempool {
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// bytes of all transactions, see `Transaction::size`
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Changes whenever a transaction comes in or leaves, so whatever was worked out from the
+    /// mempool, like a block template, can tell it's out of date
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.transactions.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&Transaction> {
+        self.transactions.get(hash).map(|(tx, _)| tx)
+    }
+
+    /// transactions with when they came in, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = (&Transaction, DateTime<Utc>)> {
+        self.by_time
+            .iter()
+            .map(|(time, hash)| (&self.transactions[hash].0, *time))
+    }
+
+    /// the mempool transaction spending an output, if any
+    pub fn spender(&self, output: &Hash) -> Option<Hash> {
+        self.spenders.get(output).copied()
+    }
+
+    /// Every other mempool transaction spending outputs `transaction` spends too, one entry
+    /// per transaction
+    pub fn double_spends(
+        &self,
+        transaction: &Transaction,
+        outcome: DoubleSpendOutcome,
+    ) -> Vec<DoubleSpend> {
+        let txid = transaction.hash();
+        let mut double_spends: Vec<DoubleSpend> = vec![];
+        for input in &transaction.inputs {
+            let output = input.prev_transaction_output_hash;
+            let Some(spender) = self.spender(&output).filter(|spender| *spender != txid) else {
+                continue;
+            };
+            match double_spends.iter_mut().find(|known| known.txid == spender) {
+                Some(known) => known.outputs.push(output),
+                None => double_spends.push(DoubleSpend {
+                    txid: spender,
+                    conflicting: txid,
+                    outputs: vec![output],
+                    outcome,
+                }),
+            }
+        }
+        double_spends
+    }
+
+    /// Hear about every transaction leaving the mempool without getting mined. A receiver more
+    /// than `EVICTION_BACKLOG` evictions behind misses the oldest ones
+    pub fn subscribe(&self) -> broadcast::Receiver<Eviction> {
+        self.evictions.subscribe()
+    }
+
+    /// an output created by a mempool transaction
+    fn output(&self, hash: &Hash) -> Option<&TransactionOutput> {
+        let creator = self.creators.get(hash)?;
+        self.transactions[creator]
+            .0
+            .outputs
+            .iter()
+            .find(|output| output.hash() == *hash)
+    }
+
+    fn insert(&mut self, transaction: Transaction, time: DateTime<Utc>) {
+        let hash = transaction.hash();
+        for output in &transaction.outputs {
+            self.creators.insert(output.hash(), hash);
+        }
+        for input in &transaction.inputs {
+            self.spenders
+                .insert(input.prev_transaction_output_hash, hash);
+        }
+        self.by_time.insert((time, hash));
+        self.size += transaction.size();
+        self.generation += 1;
+        self.transactions.insert(hash, (transaction, time));
+    }
+
+    /// take out just this transaction, whatever spends from it stays
+    fn remove(&mut self, hash: &Hash) -> Option<Transaction> {
+        let (transaction, time) = self.transactions.remove(hash)?;
+        self.by_time.remove(&(time, *hash));
+        self.size -= transaction.size();
+        self.generation += 1;
+        for output in &transaction.outputs {
+            self.creators.remove(&output.hash());
+        }
+        for input in &transaction.inputs {
+            self.spenders.remove(&input.prev_transaction_output_hash);
+        }
+        Some(transaction)
+    }
+
+    /// take everything out, oldest first, subscribers keep listening
+    fn drain(&mut self) -> Vec<(Transaction, DateTime<Utc>)> {
+        self.creators.clear();
+        self.spenders.clear();
+        self.size = 0;
+        self.generation += 1;
+        std::mem::take(&mut self.by_time)
+            .into_iter()
+            .filter_map(|(time, hash)| Some((self.transactions.remove(&hash)?.0, time)))
+            .collect()
+    }
+
+    /// the mempool transactions `transaction` spends outputs of
+    fn parents<'a>(&'a self, transaction: &'a Transaction) -> impl Iterator<Item = Hash> + 'a {
+        transaction
+            .inputs
+            .iter()
+            .filter_map(|input| self.creators.get(&input.prev_transaction_output_hash))
+            .copied()
+    }
+
+    /// the mempool transactions spending outputs of `transaction`
+    fn children<'a>(&'a self, transaction: &'a Transaction) -> impl Iterator<Item = Hash> + 'a {
+        transaction
+            .outputs
+            .iter()
+            .filter_map(|output| self.spenders.get(&output.hash()))
+            .copied()
+    }
+
+    /// everything `transaction` spends from, directly or not, parents before their children
+    pub(super) fn ancestors_of(&self, transaction: &Transaction) -> Vec<Hash> {
+        let mut visited = HashSet::new();
+        let mut ancestors = vec![];
+        self.visit_parents(transaction, &mut visited, &mut ancestors);
+        ancestors
+    }
+
+    fn visit_parents(
+        &self,
+        transaction: &Transaction,
+        visited: &mut HashSet<Hash>,
+        out: &mut Vec<Hash>,
+    ) {
+        for parent in self.parents(transaction) {
+            if visited.insert(parent) {
+                self.visit_parents(&self.transactions[&parent].0, visited, out);
+                out.push(parent);
+            }
+        }
+    }
+
+    /// everything spending from `hash`, directly or not, parents before their children
+    fn descendants(&self, hash: &Hash) -> Vec<Hash> {
+        let Some((transaction, _)) = self.transactions.get(hash) else {
+            return vec![];
+        };
+        let mut visited = HashSet::new();
+        let mut descendants = vec![];
+        let mut stack: Vec<Hash> = self.children(transaction).collect();
+        while let Some(child) = stack.pop() {
+            if visited.insert(child) {
+                descendants.push(child);
+                stack.extend(self.children(&self.transactions[&child].0));
+            }
+        }
+        // a child spending from two of the others may have come up before one of them
+        descendants.sort_by_key(|hash| self.ancestors_of(&self.transactions[hash].0).len());
+        descendants
+    }
+}
+
+impl Blockchain {
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    /// An output a mempool transaction may spend: unspent on the chain, or created by a
+    /// transaction still in the mempool
+    pub(super) fn mempool_spendable(&self, hash: &Hash) -> Option<&TransactionOutput> {
+        self.utxos
+            .get(hash)
+            .map(|(output, _)| output)
+            .or_else(|| self.mempool.output(hash))
+    }
+
+    pub fn mempool_stats(&self) -> MempoolStats {
+        MempoolStats {
+            transactions: self.mempool.len(),
+            size: self.mempool.size(),
+            fees: Amount::checked_sum(
+                self.mempool
+                    .iter()
+                    .map(|(transaction, _)| self.mempool_fee(transaction)),
+            )
+            .unwrap_or(Amount::MAX_MONEY),
+            oldest: self.mempool.iter().next().map(|(_, time)| time),
+        }
+    }
+
+    /// every mempool transaction, the ones paying the most per byte first
+    pub fn mempool_info(&self) -> Vec<MempoolTxInfo> {
+        let mut info: Vec<MempoolTxInfo> = self
+            .mempool
+            .iter()
+            .map(|(transaction, received)| MempoolTxInfo {
+                txid: transaction.hash(),
+                size: transaction.size(),
+                fee: self.mempool_fee(transaction),
+                received,
+                position: 0,
+            })
+            .collect();
+        // compare fee per byte without dividing, the oldest first when they pay the same
+        info.sort_by(|a, b| {
+            let a_rate = a.fee.to_sat() as u128 * b.size as u128;
+            let b_rate = b.fee.to_sat() as u128 * a.size as u128;
+            b_rate
+                .cmp(&a_rate)
+                .then(a.received.cmp(&b.received))
+                .then(a.txid.cmp(&b.txid))
+        });
+        for (position, info) in info.iter_mut().enumerate() {
+            info.position = position;
+        }
+        info
+    }
+
+    /// `mempool_info` of one transaction, `None` if it's not in the mempool
+    pub fn mempool_tx_info(&self, hash: &Hash) -> Option<MempoolTxInfo> {
+        if !self.mempool.contains(hash) {
+            return None;
+        }
+        self.mempool_info()
+            .into_iter()
+            .find(|info| info.txid == *hash)
+    }
+
+    /// what a mempool transaction leaves to the miner
+    pub fn mempool_fee(&self, transaction: &Transaction) -> Amount {
+        let inputs = transaction
+            .inputs
+            .iter()
+            .filter_map(|input| self.mempool_spendable(&input.prev_transaction_output_hash))
+            .map(|output| output.value);
+        Amount::checked_sum(inputs)
+            .unwrap_or(Amount::MAX_MONEY)
+            .saturating_sub(transaction.output_value().unwrap_or(Amount::MAX_MONEY))
+    }
+
+    /// Reject a transaction that would make an unconfirmed chain in the mempool longer than
+    /// `MAX_MEMPOOL_ANCESTORS`/`MAX_MEMPOOL_DESCENDANTS`, counting itself
+    pub(super) fn check_mempool_limits(&self, transaction: &Transaction) -> Result<()> {
+        let ancestors = self.mempool.ancestors_of(transaction);
+        if ancestors.len() + 1 > crate::MAX_MEMPOOL_ANCESTORS {
+            return Err(BtcError::TooManyMempoolAncestors {
+                transaction: transaction.hash(),
+                max: crate::MAX_MEMPOOL_ANCESTORS,
+            });
+        }
+        for ancestor in ancestors {
+            // the new transaction would be one more descendant
+            if self.mempool.descendants(&ancestor).len() + 2 > crate::MAX_MEMPOOL_DESCENDANTS {
+                return Err(BtcError::TooManyMempoolDescendants {
+                    transaction: ancestor,
+                    max: crate::MAX_MEMPOOL_DESCENDANTS,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check `transaction` may replace the mempool transactions spending the same outputs.
+    /// Confirmed outputs can be taken over, unconfirmed ones can't, and nothing can replace a
+    /// transaction it spends from
+    pub(super) fn check_mempool_conflicts(&self, transaction: &Transaction) -> Result<()> {
+        let ancestors: HashSet<Hash> = self.mempool.ancestors_of(transaction).into_iter().collect();
+        for input in &transaction.inputs {
+            let hash = input.prev_transaction_output_hash;
+            let Some(spender) = self.mempool.spender(&hash) else {
+                continue;
+            };
+            if !self.utxos.contains_key(&hash) || ancestors.contains(&spender) {
+                return Err(BtcError::DoubleSpend(hash));
+            }
+        }
+        Ok(())
+    }
+
+    /// put a checked transaction in the mempool and mark the outputs it spends
+    pub(super) fn insert_into_mempool(&mut self, transaction: Transaction, time: DateTime<Utc>) {
+        for input in &transaction.inputs {
+            self.utxos_mut()
+                .entry(input.prev_transaction_output_hash)
+                .and_modify(|(_tx, marked)| *marked = true);
+        }
+        self.mempool.insert(transaction, time);
+    }
+
+    /// Take `hash` and everything spending from it out of the mempool, unmark the outputs they
+    /// spent and tell subscribers. Returns how many transactions left
+    pub(super) fn evict(
+        &mut self,
+        hash: &Hash,
+        reason: EvictionReason,
+        mut double_spend: Option<DoubleSpend>,
+    ) -> usize {
+        let mut hashes = self.mempool.descendants(hash);
+        hashes.insert(0, *hash);
+        let mut evicted = 0;
+        for hash in hashes {
+            let Some(transaction) = self.mempool.remove(&hash) else {
+                continue;
+            };
+            for input in &transaction.inputs {
+                self.utxos_mut()
+                    .entry(input.prev_transaction_output_hash)
+                    .and_modify(|(_tx, marked)| *marked = false);
+            }
+            // nobody listening is fine
+            let _ = self.mempool.evictions.send(Eviction {
+                transaction,
+                reason,
+                // descendants only lose their parent
+                double_spend: double_spend.take(),
+            });
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Catch the mempool up with a block just added to the chain: its transactions are mined,
+    /// other ones spending the same outputs can't be anymore, and outputs it created may already
+    /// be spent by mempool transactions
+    pub(super) fn update_mempool(&mut self, block: &Block) {
+        for transaction in &block.transactions {
+            self.mempool.remove(&transaction.hash());
+        }
+        for transaction in &block.transactions {
+            let double_spends = self
+                .mempool
+                .double_spends(transaction, DoubleSpendOutcome::Confirmed);
+            for double_spend in double_spends {
+                let hash = double_spend.txid;
+                self.evict(&hash, EvictionReason::Conflict, Some(double_spend));
+            }
+        }
+        for output in block.transactions.iter().flat_map(|tx| &tx.outputs) {
+            let hash = output.hash();
+            if self.mempool.spenders.contains_key(&hash) {
+                self.utxos_mut()
+                    .entry(hash)
+                    .and_modify(|(_tx, marked)| *marked = true);
+            }
+        }
+    }
+
+    /// Refill the mempool after blocks were taken off the chain and the UTXO set rebuilt: the
+    /// blocks' transactions go back in first, then what was waiting already. Waiting ones that
+    /// don't fit the shorter chain anymore are evicted
+    pub(super) fn refill_mempool(&mut self, returned: Vec<Transaction>) {
+        let waiting = self.mempool.drain();
+        let now = Utc::now();
+        for transaction in returned {
+            // gone for good if it doesn't make it, nobody was told it was waiting
+            let _ = self.add_to_mempool_at(transaction, now);
+        }
+        for (transaction, received) in waiting {
+            if self
+                .add_to_mempool_at(transaction.clone(), received)
+                .is_err()
+            {
+                let _ = self.mempool.evictions.send(Eviction {
+                    transaction,
+                    reason: EvictionReason::Reorg,
+                    double_spend: None,
+                });
+            }
+        }
+    }
+
+    /// Evict transactions that came in before `cutoff`, and whatever spends from them. Only
+    /// the expired transactions are looked at. Returns how many transactions left
+    pub fn expire_mempool(&mut self, cutoff: DateTime<Utc>) -> usize {
+        let expired: Vec<Hash> = self
+            .mempool
+            .by_time
+            .iter()
+            .take_while(|(time, _)| *time < cutoff)
+            .map(|(_, hash)| *hash)
+            .collect();
+        expired
+            .iter()
+            .map(|hash| self.evict(hash, EvictionReason::Expired, None))
+            .sum()
+    }
+
+    /// the unconfirmed transactions `hash` spends from, parents first
+    pub fn mempool_ancestors(&self, hash: &Hash) -> Vec<&Transaction> {
+        let Some(transaction) = self.mempool.get(hash) else {
+            return vec![];
+        };
+        self.mempool
+            .ancestors_of(transaction)
+            .iter()
+            .filter_map(|ancestor| self.mempool.get(ancestor))
+            .collect()
+    }
+
+    /// the mempool transactions spending from `hash`
+    pub fn mempool_descendants(&self, hash: &Hash) -> Vec<&Transaction> {
+        self.mempool
+            .descendants(hash)
+            .iter()
+            .filter_map(|descendant| self.mempool.get(descendant))
+            .collect()
+    }
+
+    /// Up to `max` mempool transactions for a block, parents before their children. Takes the
+    /// transaction whose missing ancestors pay the most per transaction, together with them,
+    /// until the block is full, so a child with a big fee gets its cheap parents mined. Ties go
+    /// to whatever came in first
+    pub fn template_transactions(&self, max: usize) -> Vec<Transaction> {
+        self.template_txids(max)
+            .iter()
+            .map(|hash| self.mempool.transactions[hash].0.clone())
+            .collect()
+    }
+
+    /// the hashes of what `template_transactions` picks, in the same order
+    pub fn template_txids(&self, max: usize) -> Vec<Hash> {
+        let fees: HashMap<Hash, Amount> = self
+            .mempool
+            .transactions
+            .iter()
+            .map(|(hash, (tx, _))| (*hash, self.mempool_fee(tx)))
+            .collect();
+
+        let mut selected = vec![];
+        let mut included = HashSet::new();
+        loop {
+            let mut best: Option<(Vec<Hash>, u128)> = None;
+            for (_, hash) in self
+                .mempool
+                .by_time
+                .iter()
+                .filter(|(_, hash)| !included.contains(hash))
+            {
+                let mut package: Vec<Hash> = self
+                    .mempool
+                    .ancestors_of(&self.mempool.transactions[hash].0)
+                    .into_iter()
+                    .filter(|ancestor| !included.contains(ancestor))
+                    .collect();
+                package.push(*hash);
+                if selected.len() + package.len() > max {
+                    continue;
+                }
+
+                let fee: u128 = package.iter().map(|hash| fees[hash].to_sat() as u128).sum();
+                // compare fee per transaction without dividing
+                let better = best.as_ref().is_none_or(|(best_package, best_fee)| {
+                    (fee * best_package.len() as u128).cmp(&(best_fee * package.len() as u128))
+                        == Ordering::Greater
+                });
+                if better {
+                    best = Some((package, fee));
+                }
+            }
+
+            let Some((package, _)) = best else {
+                break;
+            };
+            for hash in package {
+                selected.push(hash);
+                included.insert(hash);
+            }
+        }
+        selected
+    }
+}
@@ -0,0 +1,196 @@
+//! Transactions submitted together, parents before the child spending from them, and taken into
+//! the mempool all or none. The fee policy looks at the package as a whole, so a parent paying
+//! nothing gets in on the back of a child paying for both, which it couldn't on its own.
+//! Everything else is checked for each transaction like `test_mempool_accept` does.
+
+use super::{Blockchain, DoubleSpendOutcome, EvictionReason, MempoolAcceptance};
+use crate::{
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::{Amount, Transaction, TransactionOutput},
+};
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+
+impl Blockchain {
+    /// Everything `add_package_to_mempool` checks, without adding anything. The acceptance adds
+    /// up the transactions of the package not in the mempool yet
+    pub fn test_package_accept(&self, package: &[Transaction]) -> Result<MempoolAcceptance> {
+        check_package_shape(package)?;
+        let child = package[package.len() - 1].hash();
+        let (waiting, new): (Vec<&Transaction>, Vec<&Transaction>) = package
+            .iter()
+            .partition(|transaction| self.mempool.contains(&transaction.hash()));
+        if new.is_empty() {
+            return Ok(MempoolAcceptance {
+                fee: Amount::ZERO,
+                size: 0,
+                replaces: vec![],
+                evicts: vec![],
+            });
+        }
+
+        // the outputs of the new transactions, for the ones after them to spend
+        let mut pending: HashMap<Hash, &TransactionOutput> = HashMap::new();
+        let (mut fee, mut size) = (Amount::ZERO, 0);
+        for transaction in &new {
+            let transaction_fee = self.mempool_input_fee(transaction, &pending)?;
+            self.check_mempool_size(transaction)?;
+            fee = fee
+                .checked_add(transaction_fee)
+                .ok_or_else(|| BtcError::ValueOverflow(transaction.hash()))?;
+            size += transaction.size();
+            pending.extend(
+                transaction
+                    .outputs
+                    .iter()
+                    .map(|output| (output.hash(), output)),
+            );
+        }
+        let min = self.policy.min_fee(size);
+        if fee < min {
+            return Err(BtcError::FeeTooLow {
+                transaction: child,
+                fee,
+                min,
+            });
+        }
+
+        // what the package spends from in the mempool, with its transactions already there
+        let mut ancestors: HashSet<Hash> = waiting.iter().map(|tx| tx.hash()).collect();
+        for transaction in package {
+            ancestors.extend(self.mempool.ancestors_of(transaction));
+        }
+        if ancestors.len() + new.len() > crate::MAX_MEMPOOL_ANCESTORS {
+            return Err(BtcError::TooManyMempoolAncestors {
+                transaction: child,
+                max: crate::MAX_MEMPOOL_ANCESTORS,
+            });
+        }
+        // as if every new transaction spent from each of them, which is at most too strict
+        for ancestor in &ancestors {
+            let descendants = self.mempool_descendants(ancestor).len();
+            if descendants + 1 + new.len() > crate::MAX_MEMPOOL_DESCENDANTS {
+                return Err(BtcError::TooManyMempoolDescendants {
+                    transaction: *ancestor,
+                    max: crate::MAX_MEMPOOL_DESCENDANTS,
+                });
+            }
+        }
+
+        // like `check_mempool_conflicts`, but nothing the whole package spends from is replaced
+        let mut replaces = vec![];
+        for transaction in &new {
+            for input in &transaction.inputs {
+                let hash = input.prev_transaction_output_hash;
+                let Some(spender) = self.mempool.spender(&hash) else {
+                    continue;
+                };
+                if !self.utxos.contains_key(&hash) || ancestors.contains(&spender) {
+                    return Err(BtcError::DoubleSpend(hash));
+                }
+                if !replaces.contains(&spender) {
+                    replaces.push(spender);
+                }
+            }
+        }
+
+        let evicts = self.mempool_room_for(child, size, fee, &ancestors)?;
+        Ok(MempoolAcceptance {
+            fee,
+            size,
+            replaces,
+            evicts,
+        })
+    }
+
+    /// Add the transactions of `package` not in the mempool yet, all of them or none. Errors like
+    /// `test_package_accept`
+    pub fn add_package_to_mempool(
+        &mut self,
+        package: Vec<Transaction>,
+    ) -> Result<MempoolAcceptance> {
+        let acceptance = self.test_package_accept(&package)?;
+        let new: Vec<Transaction> = package
+            .into_iter()
+            .filter(|transaction| !self.mempool.contains(&transaction.hash()))
+            .collect();
+        for transaction in &new {
+            let replaced = self
+                .mempool
+                .double_spends(transaction, DoubleSpendOutcome::Replaced);
+            for double_spend in replaced {
+                let hash = double_spend.txid;
+                self.evict(&hash, EvictionReason::Replaced, Some(double_spend));
+            }
+        }
+        self.make_mempool_room(&acceptance.evicts);
+        let received = Utc::now();
+        for transaction in new {
+            self.insert_into_mempool(transaction, received);
+        }
+        Ok(acceptance)
+    }
+}
+
+/// Between 1 and `MAX_PACKAGE_TRANSACTIONS` different transactions, none spending what another
+/// one spends, parents before their children, and every one but the last spent from by one
+/// after it
+fn check_package_shape(package: &[Transaction]) -> Result<()> {
+    if package.is_empty() || package.len() > crate::MAX_PACKAGE_TRANSACTIONS {
+        return Err(BtcError::InvalidPackage(format!(
+            "{} transactions, 1 to {} can be submitted together",
+            package.len(),
+            crate::MAX_PACKAGE_TRANSACTIONS
+        )));
+    }
+    let hashes: Vec<Hash> = package.iter().map(Transaction::hash).collect();
+    if hashes.iter().collect::<HashSet<_>>().len() != hashes.len() {
+        return Err(BtcError::InvalidPackage(
+            "a transaction is in it twice".to_string(),
+        ));
+    }
+    let creators: HashMap<Hash, usize> = package
+        .iter()
+        .enumerate()
+        .flat_map(|(index, transaction)| {
+            transaction
+                .outputs
+                .iter()
+                .map(move |output| (output.hash(), index))
+        })
+        .collect();
+
+    let mut spent = HashSet::new();
+    let mut spent_from = vec![false; package.len()];
+    for (index, transaction) in package.iter().enumerate() {
+        for input in &transaction.inputs {
+            let hash = input.prev_transaction_output_hash;
+            if !spent.insert(hash) {
+                return Err(BtcError::DoubleSpend(hash));
+            }
+            let Some(&parent) = creators.get(&hash) else {
+                continue;
+            };
+            if parent >= index {
+                return Err(BtcError::InvalidPackage(format!(
+                    "{} spends from {}, which comes after it",
+                    hashes[index], hashes[parent]
+                )));
+            }
+            spent_from[parent] = true;
+        }
+    }
+    if let Some(index) = spent_from[..package.len() - 1]
+        .iter()
+        .position(|spent| !spent)
+    {
+        return Err(BtcError::InvalidPackage(format!(
+            "nothing after {} spends from it",
+            hashes[index]
+        )));
+    }
+    Ok(())
+}
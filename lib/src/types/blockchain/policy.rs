@@ -0,0 +1,173 @@
+//! What a node is willing to put in its mempool beyond what consensus requires. Every node picks
+//! its own, and tells peers in its `Welcome` so wallets know what it will take. When the mempool
+//! is full, the transactions paying the least per byte make room for ones paying more, and a
+//! transaction nobody mined within `max_transaction_age` expires.
+
+use super::{Blockchain, EvictionReason};
+use crate::{
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::{Amount, Transaction},
+};
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MempoolPolicy {
+    /// sats a transaction has to pay per byte, see `Transaction::size`
+    pub min_fee_rate: u64,
+    /// biggest transaction taken, in bytes
+    pub max_transaction_size: usize,
+    /// bytes of transactions kept
+    pub max_mempool_size: usize,
+    /// whether transactions leaving nothing to the miner are taken
+    pub allow_zero_fee: bool,
+    /// seconds a transaction waits to be mined before it expires. Older peers don't send it, so
+    /// theirs is taken to be the default
+    #[serde(default = "default_max_transaction_age")]
+    pub max_transaction_age: u64,
+}
+
+fn default_max_transaction_age() -> u64 {
+    crate::MAX_MEMPOOL_TRANSACTION_AGE
+}
+
+impl Default for MempoolPolicy {
+    fn default() -> Self {
+        MempoolPolicy {
+            min_fee_rate: 0,
+            max_transaction_size: crate::DEFAULT_MAX_TRANSACTION_SIZE,
+            max_mempool_size: crate::DEFAULT_MAX_MEMPOOL_SIZE,
+            allow_zero_fee: true,
+            max_transaction_age: crate::MAX_MEMPOOL_TRANSACTION_AGE,
+        }
+    }
+}
+
+impl MempoolPolicy {
+    /// least fee a transaction of `size` bytes has to pay
+    pub fn min_fee(&self, size: usize) -> Amount {
+        let fee = self.min_fee_rate.saturating_mul(size as u64);
+        Amount::from_sat(if self.allow_zero_fee { fee } else { fee.max(1) })
+    }
+
+    pub fn max_age(&self) -> Duration {
+        Duration::seconds(self.max_transaction_age.min(i64::MAX as u64 / 1000) as i64)
+    }
+
+    /// when a transaction that came in at `received` expires, if nothing mines it first
+    pub fn expires(&self, received: DateTime<Utc>) -> DateTime<Utc> {
+        received
+            .checked_add_signed(self.max_age())
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+impl Blockchain {
+    pub fn mempool_policy(&self) -> &MempoolPolicy {
+        &self.policy
+    }
+
+    /// Only applies to transactions added from now on, the ones already in the mempool stay. A
+    /// new `max_transaction_age` applies to all of them at the next `cleanup_mempool`
+    pub fn set_mempool_policy(&mut self, policy: MempoolPolicy) {
+        self.policy = policy;
+    }
+
+    /// check a transaction leaving `fee` to the miner is small enough and pays enough
+    pub(super) fn check_mempool_policy(
+        &self,
+        transaction: &Transaction,
+        fee: Amount,
+    ) -> Result<()> {
+        self.check_mempool_size(transaction)?;
+        let min = self.policy.min_fee(transaction.size());
+        if fee < min {
+            return Err(BtcError::FeeTooLow {
+                transaction: transaction.hash(),
+                fee,
+                min,
+            });
+        }
+        Ok(())
+    }
+
+    pub(super) fn check_mempool_size(&self, transaction: &Transaction) -> Result<()> {
+        let size = transaction.size();
+        if size > self.policy.max_transaction_size {
+            return Err(BtcError::TransactionTooLarge {
+                transaction: transaction.hash(),
+                size,
+                max: self.policy.max_transaction_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// The mempool transactions to drop, lowest fee rate first, so `transaction` fits. Refused if
+    /// that would take one paying as much per byte, or one it spends from
+    pub(super) fn mempool_room(&self, transaction: &Transaction, fee: Amount) -> Result<Vec<Hash>> {
+        let ancestors: HashSet<Hash> = self.mempool.ancestors_of(transaction).into_iter().collect();
+        self.mempool_room_for(transaction.hash(), transaction.size(), fee, &ancestors)
+    }
+
+    /// `mempool_room` for `size` bytes paying `fee`, e.g. a whole package, keeping `ancestors`
+    pub(super) fn mempool_room_for(
+        &self,
+        txid: Hash,
+        size: usize,
+        fee: Amount,
+        ancestors: &HashSet<Hash>,
+    ) -> Result<Vec<Hash>> {
+        let (fee, size) = (fee.to_sat() as u128, size as u128);
+        let max = self.policy.max_mempool_size;
+        let fits = |freed: u128| self.mempool.size() as u128 + size <= max as u128 + freed;
+        if fits(0) {
+            return Ok(vec![]);
+        }
+        let mut candidates: Vec<(Hash, u128, u128)> = self
+            .mempool
+            .iter()
+            .map(|(candidate, _)| {
+                let fee = self.mempool_fee(candidate).to_sat() as u128;
+                (candidate.hash(), fee, candidate.size() as u128)
+            })
+            .filter(|(hash, _, _)| !ancestors.contains(hash))
+            .collect();
+        // lowest fee rate first, compared without dividing
+        candidates.sort_by(|(_, a_fee, a_size), (_, b_fee, b_size)| {
+            (a_fee * b_size).cmp(&(b_fee * a_size))
+        });
+
+        // what spends from a dropped transaction goes with it, only its own size is counted
+        let mut freed = 0;
+        let mut dropped = vec![];
+        for (hash, candidate_fee, candidate_size) in candidates {
+            if fits(freed) || candidate_fee * size >= fee * candidate_size {
+                break;
+            }
+            freed += candidate_size;
+            dropped.push(hash);
+        }
+        if fits(freed) {
+            Ok(dropped)
+        } else {
+            Err(BtcError::MempoolFull {
+                transaction: txid,
+                max,
+            })
+        }
+    }
+
+    /// drop what `mempool_room` picked, returns how many transactions left
+    pub(super) fn make_mempool_room(&mut self, dropped: &[Hash]) -> usize {
+        dropped
+            .iter()
+            .map(|hash| self.evict(hash, EvictionReason::Full, None))
+            .sum()
+    }
+}
@@ -0,0 +1,116 @@
+//! Working out the UTXO set from the blocks alone. Long chains take a while, so the blocks are
+//! split in batches worked on in parallel: each batch collects the outputs it creates and leaves
+//! unspent, and the ones from before it that it spends. Applying the batches in order then gives
+//! the same set as going through the blocks one by one.
+
+use super::Blockchain;
+use crate::{
+    REBUILD_PROGRESS_STEP,
+    sha256::Hash,
+    types::{Block, TransactionOutput},
+};
+
+use std::collections::{HashMap, HashSet};
+use std::num::NonZero;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// fewer blocks than this aren't worth a thread of their own
+const MIN_REBUILD_BATCH: usize = 1000;
+
+/// what a run of blocks does to the UTXO set before it
+#[derive(Default)]
+struct BatchChanges {
+    created: HashMap<Hash, (TransactionOutput, bool)>,
+    spent: HashSet<Hash>,
+}
+
+impl BatchChanges {
+    fn of(blocks: &[Block], mut block_done: impl FnMut()) -> Self {
+        let mut changes = BatchChanges::default();
+        for block in blocks {
+            for transaction in &block.transactions {
+                for input in &transaction.inputs {
+                    let hash = input.prev_transaction_output_hash;
+                    if changes.created.remove(&hash).is_none() {
+                        changes.spent.insert(hash);
+                    }
+                }
+                for output in &transaction.outputs {
+                    changes
+                        .created
+                        .insert(output.hash(), (output.clone(), false));
+                }
+            }
+            block_done();
+        }
+        changes
+    }
+}
+
+impl Blockchain {
+    /// Rebuild UTXO set from the blockchain: every output of every block, less the ones an input
+    /// spends. `add_block` keeps the set up to date, this is for chains loaded without one and
+    /// clears the mempool marks
+    pub fn rebuild_utxos(&mut self) {
+        self.rebuild_utxos_with_progress(|_, _| {});
+    }
+
+    /// `rebuild_utxos`, telling `progress` how many blocks are done out of how many every
+    /// `REBUILD_PROGRESS_STEP` blocks and at the end. It's called from the threads the batches
+    /// run on, so not always in order
+    pub fn rebuild_utxos_with_progress(&mut self, progress: impl Fn(u64, u64) + Sync) {
+        let total = self.blocks.len() as u64;
+        let threads = std::thread::available_parallelism().map_or(1, NonZero::get);
+        let batch = self.blocks.len().div_ceil(threads).max(MIN_REBUILD_BATCH);
+        let done = AtomicU64::new(0);
+        let block_done = || {
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if done.is_multiple_of(REBUILD_PROGRESS_STEP) || done == total {
+                progress(done, total);
+            }
+        };
+
+        let batches: Vec<BatchChanges> = std::thread::scope(|scope| {
+            let running: Vec<_> = self
+                .blocks
+                .chunks(batch)
+                .map(|blocks| scope.spawn(|| BatchChanges::of(blocks, block_done)))
+                .collect();
+            running
+                .into_iter()
+                .map(|batch| batch.join().expect("a UTXO rebuild thread panicked"))
+                .collect()
+        });
+
+        self.saved_utxos = None;
+        let utxos = Arc::make_mut(&mut self.utxos);
+        utxos.clear();
+        for batch in batches {
+            for spent in &batch.spent {
+                utxos.remove(spent);
+            }
+            utxos.extend(batch.created);
+        }
+    }
+
+    /// Keep the UTXO set saved with the chain if it adds up to what was issued, see
+    /// `total_supply`, and rebuild it otherwise, e.g. for a file from before it was saved along.
+    /// Returns whether it was rebuilt
+    pub fn restore_utxos(&mut self, progress: impl Fn(u64, u64) + Sync) -> bool {
+        if self.total_supply().is_err() {
+            self.rebuild_utxos_with_progress(progress);
+            return true;
+        }
+        // a loaded chain has an empty mempool
+        self.clear_utxo_marks();
+        false
+    }
+
+    /// forget which outputs the mempool spends, for when it's about to be filled again
+    pub(super) fn clear_utxo_marks(&mut self) {
+        for (_, marked) in Arc::make_mut(&mut self.utxos).values_mut() {
+            *marked = false;
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Who holds how much, for chain analysis. The addrindex keeps every key's balance up to date as
+//! blocks come in, with the keys ordered by it and the supply split into buckets by balance, so
+//! neither needs a pass over the UTXO set.
+
+use super::Blockchain;
+use crate::{crypto::PublicKey, types::Amount};
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+/// buckets the supply is split into, by powers of ten from 1 sat up to 10^16 which is past
+/// `MAX_MONEY`
+const BUCKETS: usize = 16;
+
+/// a key and what its unspent outputs add up to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct Holder {
+    pub pubkey: PublicKey,
+    pub balance: Amount,
+}
+
+/// The keys holding at least `min` and less than `max`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct SupplyBucket {
+    pub min: Amount,
+    pub max: Amount,
+    pub holders: u64,
+    /// what the holders have together
+    pub total: Amount,
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Balances {
+    balances: HashMap<PublicKey, Amount>,
+    ranked: BTreeSet<(Amount, PublicKey)>,
+    buckets: [SupplyBucket; BUCKETS],
+}
+
+impl Default for Balances {
+    fn default() -> Self {
+        Balances {
+            balances: HashMap::new(),
+            ranked: BTreeSet::new(),
+            buckets: std::array::from_fn(|i| SupplyBucket {
+                min: Amount::from_sat(10u64.pow(i as u32)),
+                max: Amount::from_sat(10u64.pow(i as u32 + 1)),
+                holders: 0,
+                total: Amount::ZERO,
+            }),
+        }
+    }
+}
+
+impl Balances {
+    /// `pubkey` got `received` and spent `sent`
+    pub(super) fn apply(&mut self, pubkey: &PublicKey, received: Amount, sent: Amount) {
+        let old = self.balances.remove(pubkey).unwrap_or(Amount::ZERO);
+        let new = old
+            .checked_add(received)
+            .unwrap_or(Amount::MAX_MONEY)
+            .saturating_sub(sent);
+        if old != Amount::ZERO {
+            self.ranked.remove(&(old, pubkey.clone()));
+            let bucket = &mut self.buckets[bucket(old)];
+            bucket.holders -= 1;
+            bucket.total = bucket.total.saturating_sub(old);
+        }
+        if new != Amount::ZERO {
+            self.balances.insert(pubkey.clone(), new);
+            self.ranked.insert((new, pubkey.clone()));
+            let bucket = &mut self.buckets[bucket(new)];
+            bucket.holders += 1;
+            bucket.total = bucket.total.checked_add(new).unwrap_or(Amount::MAX_MONEY);
+        }
+    }
+
+    pub(super) fn get(&self, pubkey: &PublicKey) -> Amount {
+        self.balances.get(pubkey).copied().unwrap_or(Amount::ZERO)
+    }
+}
+
+/// which bucket a balance above zero goes in
+fn bucket(balance: Amount) -> usize {
+    (balance.to_sat().ilog10() as usize).min(BUCKETS - 1)
+}
+
+impl Blockchain {
+    /// The `limit` keys holding the most, richest first, ties broken by the key. Empty unless
+    /// the addrindex is enabled
+    pub fn rich_list(&self, limit: usize) -> Vec<Holder> {
+        let Some(addrindex) = &self.addrindex else {
+            return vec![];
+        };
+        addrindex
+            .balances()
+            .ranked
+            .iter()
+            .rev()
+            .take(limit)
+            .map(|(balance, pubkey)| Holder {
+                pubkey: pubkey.clone(),
+                balance: *balance,
+            })
+            .collect()
+    }
+
+    /// How many keys hold between each power of ten sats and the next, and how much they hold
+    /// together, from 1 sat up. Empty unless the addrindex is enabled
+    pub fn supply_distribution(&self) -> Vec<SupplyBucket> {
+        self.addrindex
+            .as_ref()
+            .map_or(vec![], |addrindex| addrindex.balances().buckets.to_vec())
+    }
+
+    /// what `pubkey`'s unspent outputs add up to, zero unless the addrindex is enabled
+    pub fn address_balance(&self, pubkey: &PublicKey) -> Amount {
+        self.addrindex
+            .as_ref()
+            .map_or(Amount::ZERO, |addrindex| addrindex.balances().get(pubkey))
+    }
+}
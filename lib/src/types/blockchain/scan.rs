@@ -0,0 +1,133 @@
+//! Scanning the chain for a key a wallet just imported, so it learns the key's outputs and history
+//! without having been around when they happened. The UTXO set has the outputs whatever their age,
+//! the history comes from the addrindex if there is one and from going through the blocks from
+//! the key's birth height on otherwise.
+
+use super::{AddressActivity, Blockchain};
+use crate::{
+    crypto::PublicKey,
+    sha256::Hash,
+    types::{Amount, TransactionOutput},
+};
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+/// What `Blockchain::scan_address` found
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct AddressScan {
+    /// blocks on the chain when it was scanned
+    pub height: u64,
+    /// hash of the last of them, zero for an empty chain
+    pub tip: Hash,
+    /// every unspent output of the key, and whether a mempool transaction spends it
+    pub utxos: Vec<(TransactionOutput, bool)>,
+    /// what happened to the key from the height scanned from on, oldest first
+    pub history: Vec<AddressActivity>,
+}
+
+impl Blockchain {
+    /// The outputs `pubkey` can spend and its history from `from` on. `None` if the chain never
+    /// had that many blocks
+    pub fn scan_address(&self, pubkey: &PublicKey, from: u64) -> Option<AddressScan> {
+        if from > self.block_height() {
+            return None;
+        }
+        let history = match &self.addrindex {
+            Some(addrindex) => addrindex
+                .get(pubkey)
+                .iter()
+                .filter(|activity| activity.height >= from)
+                .cloned()
+                .collect(),
+            None => self.walk_history(pubkey, from..self.block_height()),
+        };
+        Some(AddressScan {
+            height: self.block_height(),
+            tip: self.tip_hash(),
+            utxos: self
+                .utxos
+                .values()
+                .filter(|(output, _)| output.pubkey == *pubkey)
+                .cloned()
+                .collect(),
+            history,
+        })
+    }
+
+    /// The history `AddressIndex` would have for `pubkey`, over the blocks at `heights`. What
+    /// their inputs spent comes from their undo data, or for blocks without any, from the key's
+    /// outputs in the blocks before
+    pub(super) fn walk_history(
+        &self,
+        pubkey: &PublicKey,
+        heights: Range<u64>,
+    ) -> Vec<AddressActivity> {
+        let from = heights.start;
+        // the key's outputs seen so far, by hash
+        let mut owned: HashMap<Hash, Amount> = HashMap::new();
+        let mut looked_back = false;
+        let mut history = vec![];
+        for (height, block) in self.blocks[..heights.end as usize]
+            .iter()
+            .enumerate()
+            .skip(from as usize)
+        {
+            let height = height as u64;
+            let undo = self.block_undo(height);
+            if undo.is_none() && !looked_back {
+                looked_back = true;
+                owned.extend(self.blocks[..from as usize].iter().flat_map(|block| {
+                    block
+                        .transactions
+                        .iter()
+                        .flat_map(|transaction| &transaction.outputs)
+                        .filter(|output| output.pubkey == *pubkey)
+                        .map(|output| (output.hash(), output.value))
+                }));
+            }
+            let spent: HashMap<&Hash, &TransactionOutput> = undo
+                .into_iter()
+                .flat_map(|undo| undo.spent.iter().map(|(hash, output)| (hash, output)))
+                .collect();
+
+            for transaction in &block.transactions {
+                let mut involved = false;
+                let mut sent = Amount::ZERO;
+                for input in &transaction.inputs {
+                    let hash = &input.prev_transaction_output_hash;
+                    let value = match spent.get(hash) {
+                        Some(output) if output.pubkey == *pubkey => Some(output.value),
+                        _ => owned.get(hash).copied(),
+                    };
+                    if let Some(value) = value {
+                        involved = true;
+                        sent = sent.checked_add(value).unwrap_or(Amount::MAX_MONEY);
+                    }
+                }
+                let mut received = Amount::ZERO;
+                for output in &transaction.outputs {
+                    if output.pubkey == *pubkey {
+                        involved = true;
+                        received = received
+                            .checked_add(output.value)
+                            .unwrap_or(Amount::MAX_MONEY);
+                        owned.insert(output.hash(), output.value);
+                    }
+                }
+                if involved {
+                    history.push(AddressActivity {
+                        txid: transaction.hash(),
+                        height,
+                        received,
+                        sent,
+                    });
+                }
+            }
+        }
+        history
+    }
+}
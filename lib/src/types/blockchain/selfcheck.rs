@@ -0,0 +1,132 @@
+//! What a node checks of a chain it loads before serving it: that the last blocks link up, hold
+//! the transactions their merkle roots say and, on proof-of-work, meet their targets, that the
+//! UTXO set is the one saved with them, and that the target is what the blocks work out to.
+//!
+//! The blocks can't be fixed without getting them again. The UTXO set and the target are worked
+//! out from the blocks, so those can be, see `rebuild_utxos` and `retarget`.
+
+use super::Blockchain;
+use crate::{
+    consensus::Consensus,
+    sha256::Hash,
+    types::{Block, TransactionOutput},
+    util::MerkleRoot,
+};
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// How many outputs a UTXO set holds and a hash of them all, saved after the chain, see `format`
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UtxoChecksum {
+    pub count: u64,
+    /// sha256 of the outputs' hashes, sorted
+    pub hash: Hash,
+}
+
+impl UtxoChecksum {
+    pub fn of(utxos: &HashMap<Hash, (TransactionOutput, bool)>) -> Self {
+        let mut hashes: Vec<[u8; 32]> = utxos.keys().map(Hash::to_be_bytes).collect();
+        hashes.sort_unstable();
+        let mut hasher = Sha256::new();
+        for hash in hashes {
+            hasher.update(hash);
+        }
+        UtxoChecksum {
+            count: utxos.len() as u64,
+            hash: Hash::from_be_bytes(hasher.finalize().into()),
+        }
+    }
+}
+
+/// What `Blockchain::self_check` found
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SelfCheck {
+    /// blocks at the tip that were checked
+    pub blocks: u64,
+    /// what's wrong with those blocks, nothing short of getting them again fixes it
+    pub broken: Vec<String>,
+    /// what's wrong with the UTXO set or the target, working them out again fixes it
+    pub stale: Vec<String>,
+}
+
+impl SelfCheck {
+    pub fn is_ok(&self) -> bool {
+        self.broken.is_empty() && self.stale.is_empty()
+    }
+}
+
+impl Blockchain {
+    /// Check the last `depth` blocks and what was worked out from the chain, see `selfcheck`. The
+    /// UTXO set is only compared with a checksum if the chain was loaded with one and the set
+    /// hasn't changed since
+    pub fn self_check(&self, depth: u64) -> SelfCheck {
+        let start = self.blocks.len().saturating_sub(depth as usize);
+        let mut check = SelfCheck {
+            blocks: (self.blocks.len() - start) as u64,
+            ..SelfCheck::default()
+        };
+        for (height, block) in self.blocks.iter().enumerate().skip(start) {
+            let prev = match height {
+                0 => Hash::zero(),
+                _ => self.blocks[height - 1].hash(),
+            };
+            if let Err(problem) = check_block(block, prev, self.params.consensus) {
+                check.broken.push(format!("block {height}: {problem}"));
+            }
+        }
+
+        if let Some(saved) = self.saved_utxos {
+            for (hash, (output, _)) in self.utxos.iter() {
+                if output.hash() != *hash {
+                    check.stale.push(format!(
+                        "the output under {hash} hashes to {}",
+                        output.hash()
+                    ));
+                }
+            }
+            let found = UtxoChecksum::of(&self.utxos);
+            if found != saved {
+                check.stale.push(format!(
+                    "{} outputs hashing to {}, {} hashing to {} were saved",
+                    found.count, found.hash, saved.count, saved.hash
+                ));
+            }
+        }
+
+        let expected = self.recomputed_target();
+        if self.target != expected {
+            check.stale.push(format!(
+                "the target is {}, the blocks work out to {expected}",
+                self.target
+            ));
+        }
+        check
+    }
+}
+
+/// what's wrong with `block` on top of `prev`, if anything
+fn check_block(block: &Block, prev: Hash, consensus: Consensus) -> Result<(), String> {
+    if block.header.prev_block_hash != prev {
+        return Err(format!(
+            "builds on {}, not on {prev}",
+            block.header.prev_block_hash
+        ));
+    }
+    match MerkleRoot::calculate(&block.transactions) {
+        Ok(root) if root == block.header.merkle_root => {}
+        _ => return Err("its transactions don't match its merkle root".to_string()),
+    }
+    // a stake signature is checked against the chain as it was, work only against the block
+    if consensus == Consensus::ProofOfWork
+        && !block.header.hash().matches_target(block.header.target)
+    {
+        return Err(format!(
+            "hash {} doesn't meet its target",
+            block.header.hash()
+        ));
+    }
+    Ok(())
+}
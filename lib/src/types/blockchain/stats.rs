@@ -0,0 +1,110 @@
+//! Numbers about a stretch of the chain for dashboards: how fast blocks came, how much work went
+//! into them and what they carried. Everything is worked out from the blocks themselves, so a
+//! miner's clock being off shows up in the intervals and the hashrate.
+
+use super::{Blockchain, u256_as_f64};
+use crate::{U256, types::Amount};
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct ChainStats {
+    /// height of the first block counted
+    pub start: u64,
+    /// height after the last block counted
+    pub end: u64,
+    /// seconds between blocks on average, by their timestamps. None for less than two blocks
+    pub average_interval: Option<f64>,
+    /// hashes per second it would have taken to find the blocks that fast, None like
+    /// `average_interval` or when the timestamps don't move forward
+    pub hashrate: Option<f64>,
+    /// average difficulty of the blocks, see `Blockchain::difficulty`
+    pub difficulty: f64,
+    /// what the coinbases claimed beyond the block reward
+    pub fees: Amount,
+    /// transactions besides the coinbases
+    pub transactions: u64,
+}
+
+impl ChainStats {
+    /// how many blocks were counted
+    pub fn blocks(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+impl Blockchain {
+    /// Stats for the blocks in `range`, cut down to the blocks the chain has
+    pub fn stats(&self, range: Range<u64>) -> ChainStats {
+        let end = range.end.min(self.block_height());
+        let start = range.start.min(end);
+        let blocks = &self.blocks[start as usize..end as usize];
+
+        let min_target = u256_as_f64(self.params.min_target);
+        let mut difficulty = 0.0;
+        let mut work = 0.0;
+        let mut fees = Amount::ZERO;
+        let mut transactions = 0;
+        for (height, block) in (start..).zip(blocks) {
+            let target = u256_as_f64(block.header.target);
+            difficulty += min_target / target.max(1.0);
+            // the first block's work went in before its timestamp, the span starts there
+            if height > start {
+                work += u256_as_f64(U256::MAX) / (target + 1.0);
+            }
+            let claimed = block
+                .transactions
+                .first()
+                .and_then(|coinbase| coinbase.output_value().ok())
+                .unwrap_or(Amount::ZERO);
+            fees = fees
+                .checked_add(claimed.saturating_sub(self.params.block_reward(height)))
+                .unwrap_or(fees);
+            transactions += block.transactions.len().saturating_sub(1) as u64;
+        }
+
+        let span = match (blocks.first(), blocks.last()) {
+            (Some(first), Some(last)) if blocks.len() > 1 => Some(
+                (last.header.timestamp - first.header.timestamp).num_milliseconds() as f64 / 1000.0,
+            ),
+            _ => None,
+        };
+        ChainStats {
+            start,
+            end,
+            average_interval: span.map(|span| span / (blocks.len() - 1) as f64),
+            hashrate: span.filter(|span| *span > 0.0).map(|span| work / span),
+            difficulty: if blocks.is_empty() {
+                0.0
+            } else {
+                difficulty / blocks.len() as f64
+            },
+            fees,
+            transactions,
+        }
+    }
+
+    /// `stats` for each `window` blocks of `range`, the last window can be shorter. Windows are
+    /// made longer when there'd be more than `MAX_STATS_WINDOWS`, a `window` of 0 is the whole
+    /// range at once
+    pub fn stats_windows(&self, range: Range<u64>, window: u64) -> Vec<ChainStats> {
+        let end = range.end.min(self.block_height());
+        let start = range.start.min(end);
+        let length = end - start;
+        if length == 0 {
+            return vec![self.stats(start..end)];
+        }
+        let window = if window == 0 {
+            length
+        } else {
+            window.max(length.div_ceil(crate::MAX_STATS_WINDOWS))
+        };
+        (start..end)
+            .step_by(window as usize)
+            .map(|from| self.stats(from..(from + window).min(end)))
+            .collect()
+    }
+}
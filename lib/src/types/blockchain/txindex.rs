@@ -0,0 +1,32 @@
+//! Optional index of every transaction in the chain, so looking one up doesn't mean going
+//! through all blocks
+
+use crate::{sha256::Hash, types::Block};
+
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct TxIndex {
+    /// height of the block and position of the transaction in it
+    locations: HashMap<Hash, (u64, usize)>,
+}
+
+impl TxIndex {
+    pub(super) fn build<'a>(blocks: impl IntoIterator<Item = &'a Block>) -> Self {
+        let mut index = TxIndex::default();
+        for (height, block) in blocks.into_iter().enumerate() {
+            index.add_block(height as u64, block);
+        }
+        index
+    }
+
+    pub(super) fn add_block(&mut self, height: u64, block: &Block) {
+        for (offset, transaction) in block.transactions.iter().enumerate() {
+            self.locations.insert(transaction.hash(), (height, offset));
+        }
+    }
+
+    pub(super) fn get(&self, hash: &Hash) -> Option<(u64, usize)> {
+        self.locations.get(hash).copied()
+    }
+}
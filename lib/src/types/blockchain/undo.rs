@@ -0,0 +1,68 @@
+//! Undo data: the outputs each block spent, saved as the block is added. Taking blocks off the tip
+//! then puts those back and drops what the blocks created, instead of working the whole UTXO set
+//! out again from the first block. Chains saved before undo data was kept only have it for the
+//! blocks added since, going back further than that still rebuilds the set.
+
+use super::Blockchain;
+use crate::{
+    sha256::Hash,
+    types::{Block, TransactionOutput},
+};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The outputs a block spent from before it, enough to take it off the UTXO set again
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BlockUndo {
+    /// by the hash inputs spend them with, in the order the block spent them
+    pub spent: Vec<(Hash, TransactionOutput)>,
+}
+
+impl BlockUndo {
+    /// what `block` would spend from `utxos`, outputs it creates and spends itself aren't in it
+    pub(super) fn of(block: &Block, utxos: &HashMap<Hash, (TransactionOutput, bool)>) -> Self {
+        let spent = block
+            .transactions
+            .iter()
+            .flat_map(|transaction| &transaction.inputs)
+            .filter_map(|input| {
+                let hash = input.prev_transaction_output_hash;
+                utxos.get(&hash).map(|(output, _)| (hash, output.clone()))
+            })
+            .collect();
+        BlockUndo { spent }
+    }
+}
+
+impl Blockchain {
+    /// Undo data of the block at `height`, if it was added while undo data was kept
+    pub fn block_undo(&self, height: u64) -> Option<&BlockUndo> {
+        let first = self.blocks.len() - self.undo.len();
+        let offset = (height as usize).checked_sub(first)?;
+        self.undo.get(offset)
+    }
+
+    /// Take `removed`, the blocks that were on the tip, off the UTXO set with their undo data.
+    /// Returns false, leaving the set alone, if some of them don't have any
+    pub(super) fn unapply_from_utxos(&mut self, removed: &[Block]) -> bool {
+        if removed.len() > self.undo.len() {
+            return false;
+        }
+        let undo = self.undo.split_off(self.undo.len() - removed.len());
+        let utxos = self.utxos_mut();
+        for (block, undo) in removed.iter().zip(undo).rev() {
+            for transaction in &block.transactions {
+                for output in &transaction.outputs {
+                    utxos.remove(&output.hash());
+                }
+            }
+            utxos.extend(
+                undo.spent
+                    .into_iter()
+                    .map(|(hash, output)| (hash, (output, false))),
+            );
+        }
+        true
+    }
+}
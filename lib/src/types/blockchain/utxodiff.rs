@@ -0,0 +1,116 @@
+//! What changed about a key's unspent outputs since some height, so wallets don't have to fetch
+//! all of them every time. Spent outputs are gone from the set, whether one belonged to the key is
+//! told by the input's signature verifying against it.
+
+use super::{Blockchain, UtxoSnapshot};
+use crate::{crypto::PublicKey, sha256::Hash, types::TransactionOutput};
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct UtxoDiff {
+    /// the height the wallet is synced to once it applied the diff
+    pub height: u64,
+    /// Hash of the block at `height`, zero for an empty chain. If the node's block at that height
+    /// has another hash later, the chain reorganized below the wallet
+    #[serde(default = "Hash::zero")]
+    pub tip: Hash,
+    /// `added` lists every unspent output of the key, it replaces what the wallet had instead of
+    /// being applied to it
+    pub full: bool,
+    /// outputs created since, still unspent
+    pub added: Vec<TransactionOutput>,
+    /// outputs spent since
+    pub removed: Vec<Hash>,
+    /// every output of the key a mempool transaction spends, not just the new ones
+    pub marked: Vec<Hash>,
+}
+
+impl UtxoDiff {
+    /// everything the key can spend, for a wallet too far behind to be sent a diff
+    pub fn full(snapshot: &UtxoSnapshot, pubkey: &PublicKey) -> Self {
+        UtxoDiff {
+            height: snapshot.height(),
+            tip: snapshot.tip(),
+            full: true,
+            added: snapshot
+                .owned_by(pubkey)
+                .map(|(output, _)| output.clone())
+                .collect(),
+            removed: vec![],
+            marked: snapshot
+                .owned_by(pubkey)
+                .filter(|(_, marked)| *marked)
+                .map(|(output, _)| output.hash())
+                .collect(),
+        }
+    }
+}
+
+impl Blockchain {
+    /// How the outputs `pubkey` can spend changed since the chain had `since` blocks. `None` if
+    /// it never had that many or it's more than `MAX_UTXO_DIFF_BLOCKS` ago, see `UtxoDiff::full`
+    pub fn utxo_diff(&self, pubkey: &PublicKey, since: u64) -> Option<UtxoDiff> {
+        let height = self.block_height();
+        if since > height || height - since > crate::MAX_UTXO_DIFF_BLOCKS {
+            return None;
+        }
+
+        let mut created = vec![];
+        let mut created_since = HashSet::new();
+        let mut removed = vec![];
+        for transaction in self.blocks[since as usize..]
+            .iter()
+            .flat_map(|block| &block.transactions)
+        {
+            for input in &transaction.inputs {
+                let hash = input.prev_transaction_output_hash;
+                // spent again before the wallet ever heard of it
+                if created_since.remove(&hash) {
+                    continue;
+                }
+                let signed = transaction.signature_hash(&hash);
+                if input.signature.verify(&signed, pubkey) {
+                    removed.push(hash);
+                }
+            }
+            for output in transaction
+                .outputs
+                .iter()
+                .filter(|output| output.pubkey == *pubkey)
+            {
+                created.push(output.hash());
+                created_since.insert(output.hash());
+            }
+        }
+
+        let marked = self
+            .mempool
+            .iter()
+            .flat_map(|(transaction, _)| &transaction.inputs)
+            .map(|input| input.prev_transaction_output_hash)
+            .filter(|hash| {
+                self.utxos
+                    .get(hash)
+                    .is_some_and(|(output, _)| output.pubkey == *pubkey)
+            })
+            .collect();
+
+        Some(UtxoDiff {
+            height,
+            tip: self.tip_hash(),
+            full: false,
+            added: created
+                .iter()
+                .filter(|hash| created_since.contains(hash))
+                .filter_map(|hash| self.utxos.get(hash))
+                .map(|(output, _)| output.clone())
+                .collect(),
+            removed,
+            marked,
+        })
+    }
+}
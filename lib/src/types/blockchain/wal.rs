@@ -0,0 +1,212 @@
+//! Write-ahead log of what was done to the chain since it was last saved. Before a block is put
+//! on the chain or blocks are taken off it the operation is written down and synced, and once
+//! it's done a `Done` record follows. The store only has to be written every so often then:
+//! loading it and replaying the log gets back to where the chain was, and an operation without
+//! its `Done` was cut short by a crash and is left out.
+//!
+//! Each record is its length as a big endian u32, the first four bytes of its hash, then the
+//! record as CBOR. A record cut off or not matching its hash ends the log, it was being written
+//! when the node stopped.
+//!
+//! Only the blocks are logged. What `invalidate_block` marked is saved with the store alone.
+
+use super::Blockchain;
+use crate::{error::Result, sha256::Hash, types::Block};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// bytes before each record's CBOR
+const FRAME_HEADER: usize = 8;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum WalRecord {
+    /// `block` is about to go on the chain at `height`
+    Apply { height: u64, block: Block },
+    /// the blocks from `height` on are about to be taken off the chain, `tip` is its tip before
+    Unapply { height: u64, tip: Hash },
+    /// the operation logged last finished
+    Done,
+}
+
+/// What `Blockchain::recover` did with a log
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recovery {
+    /// finished operations the chain didn't have yet
+    pub replayed: usize,
+    /// the operation a crash cut short, rolled back
+    pub rolled_back: Option<Hash>,
+}
+
+/// The log file, shared by the clones of the chain it was set on
+#[derive(Clone, Debug)]
+pub struct Wal {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+impl Wal {
+    /// Open the log at `path`, creating it if there's none. Returns it with the records in it,
+    /// anything after the last whole record is cut off so new ones follow straight after
+    pub fn open(path: impl AsRef<Path>) -> IoResult<(Self, Vec<WalRecord>)> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        let (records, end) = parse(&bytes);
+        if end < bytes.len() {
+            file.set_len(end as u64)?;
+            file.sync_data()?;
+        }
+        let wal = Wal {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        };
+        Ok((wal, records))
+    }
+
+    /// write `record` and wait for it to be on disk
+    pub fn append(&self, record: &WalRecord) -> IoResult<()> {
+        let mut payload = vec![];
+        ciborium::ser::into_writer(record, &mut payload)
+            .map_err(|e| IoError::new(IoErrorKind::InvalidData, e.to_string()))?;
+        let len = u32::try_from(payload.len())
+            .map_err(|_| IoError::new(IoErrorKind::InvalidData, "WAL record too large"))?;
+        let mut frame = Vec::with_capacity(FRAME_HEADER + payload.len());
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.extend_from_slice(&checksum(&payload));
+        frame.extend_from_slice(&payload);
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&frame)?;
+        file.sync_data()
+    }
+
+    /// how many bytes were logged, see `checkpoint`
+    pub fn len(&self) -> IoResult<u64> {
+        Ok(self.file.lock().unwrap().metadata()?.len())
+    }
+
+    pub fn is_empty(&self) -> IoResult<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Drop the first `len` bytes of the log, once a chain saved when it was that long is safely
+    /// in the store. Whatever was logged since stays
+    pub fn checkpoint(&self, len: u64) -> IoResult<()> {
+        let mut file = self.file.lock().unwrap();
+        let mut bytes = vec![];
+        File::open(&self.path)?.read_to_end(&mut bytes)?;
+        let rest = bytes.get(len as usize..).unwrap_or_default();
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        let mut new = File::create(&temp)?;
+        new.write_all(rest)?;
+        new.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+        *file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        Ok(())
+    }
+}
+
+/// the whole records at the start of `bytes`, with where they end
+fn parse(bytes: &[u8]) -> (Vec<WalRecord>, usize) {
+    let mut records = vec![];
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + FRAME_HEADER) {
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let start = offset + FRAME_HEADER;
+        let Some(payload) = bytes.get(start..start + len) else {
+            break;
+        };
+        if header[4..] != checksum(payload) {
+            break;
+        }
+        let Ok(record) = ciborium::de::from_reader(payload) else {
+            break;
+        };
+        records.push(record);
+        offset = start + len;
+    }
+    (records, offset)
+}
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let hash = Hash::hash(&payload).as_bytes();
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+impl Blockchain {
+    /// Log every block put on or taken off the chain from now on to `wal`, see `wal`
+    pub fn set_wal(&mut self, wal: Option<Wal>) {
+        self.wal = wal;
+    }
+
+    pub fn wal(&self) -> Option<&Wal> {
+        self.wal.as_ref()
+    }
+
+    /// write `record` to the log, if there is one
+    pub(super) fn log(&self, record: &WalRecord) -> Result<()> {
+        if let Some(wal) = &self.wal {
+            wal.append(record)?;
+        }
+        Ok(())
+    }
+
+    /// Catch the chain up with a log written after it was saved. Finished operations it doesn't
+    /// have yet are done again, ones that don't fit it, e.g. because it was saved after them, are
+    /// skipped. One a crash cut short is rolled back, it never made it to the store
+    pub fn recover(&mut self, records: Vec<WalRecord>) -> Result<Recovery> {
+        // doing it all again isn't logged again
+        let wal = self.wal.take();
+        let result = self.replay(records);
+        self.wal = wal;
+        result
+    }
+
+    fn replay(&mut self, records: Vec<WalRecord>) -> Result<Recovery> {
+        let mut recovery = Recovery::default();
+        let mut records = records.into_iter().peekable();
+        while let Some(record) = records.next() {
+            let finished = records
+                .next_if(|next| matches!(next, WalRecord::Done))
+                .is_some();
+            if !finished {
+                recovery.rolled_back = match record {
+                    WalRecord::Apply { block, .. } => Some(block.hash()),
+                    WalRecord::Unapply { tip, .. } => Some(tip),
+                    WalRecord::Done => None,
+                };
+                continue;
+            }
+            match record {
+                WalRecord::Apply { height, block }
+                    if height == self.block_height()
+                        && block.header.prev_block_hash == self.tip_hash() =>
+                {
+                    self.add_block(block)?;
+                    recovery.replayed += 1;
+                }
+                WalRecord::Unapply { height, tip }
+                    if tip == self.tip_hash() && height < self.block_height() =>
+                {
+                    self.disconnect_from(height)?;
+                    recovery.replayed += 1;
+                }
+                _ => {}
+            }
+        }
+        Ok(recovery)
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{
+    U256,
+    crypto::{PrivateKey, PublicKey, Signature},
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::*,
+    util::{MerkleRoot, MerkleTree},
+};
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Builds a block with its coinbase in front and the merkle root filled in, so nobody has to
+/// remember the order things need to happen in.
+#[derive(Clone, Debug)]
+pub struct BlockBuilder {
+    timestamp: DateTime<Utc>,
+    nonce: u64,
+    prev_block_hash: Hash,
+    target: U256,
+    coinbase: Vec<TransactionOutput>,
+    transactions: Vec<Transaction>,
+    tree: Option<MerkleTree>,
+}
+
+impl Default for BlockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockBuilder {
+    /// a genesis block at the minimum difficulty, timestamped now
+    pub fn new() -> Self {
+        BlockBuilder {
+            timestamp: Utc::now(),
+            nonce: 0,
+            prev_block_hash: Hash::zero(),
+            target: crate::MIN_TARGET,
+            coinbase: vec![],
+            transactions: vec![],
+            tree: None,
+        }
+    }
+
+    /// a block extending the chain's tip at its current target
+    pub fn on_top_of(blockchain: &Blockchain) -> Self {
+        let prev_block_hash = blockchain
+            .blocks()
+            .last()
+            .map(|last_block| last_block.hash())
+            .unwrap_or(Hash::zero());
+        Self::new()
+            .prev_block_hash(prev_block_hash)
+            .set_target(blockchain.target())
+    }
+
+    pub fn prev_block_hash(mut self, prev_block_hash: Hash) -> Self {
+        self.prev_block_hash = prev_block_hash;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn set_target(mut self, target: U256) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// add an output to the coinbase, fees collected by `finalize_with_fees` go to the first one
+    pub fn coinbase_to(self, pubkey: PublicKey, value: Amount) -> Self {
+        self.coinbase_output(TransactionOutput {
+            value,
+            unique_id: Uuid::new_v4(),
+            pubkey,
+        })
+    }
+
+    pub fn coinbase_output(mut self, output: TransactionOutput) -> Self {
+        self.coinbase.push(output);
+        self
+    }
+
+    pub fn add_tx(mut self, transaction: Transaction) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    pub fn add_txs(mut self, transactions: impl IntoIterator<Item = Transaction>) -> Self {
+        self.transactions.extend(transactions);
+        self
+    }
+
+    /// `tree` already has the hashes of the transactions added after the coinbase, from its
+    /// second leaf on, so finalizing only hashes the coinbase's branch
+    pub fn merkle_tree(mut self, tree: MerkleTree) -> Self {
+        self.tree = Some(tree);
+        self
+    }
+
+    /// the root of `transactions`, through `tree` if there is one
+    fn merkle_root(tree: Option<MerkleTree>, transactions: &[Transaction]) -> Result<MerkleRoot> {
+        match tree {
+            Some(mut tree) => {
+                tree.set(0, transactions[0].hash());
+                tree.root().ok_or(BtcError::EmptyBlock)
+            }
+            None => MerkleRoot::calculate(transactions),
+        }
+    }
+
+    /// every transaction of the block, coinbase first
+    fn all_transactions(&self) -> Result<Vec<Transaction>> {
+        if self.coinbase.is_empty() {
+            return Err(BtcError::MissingCoinbase);
+        }
+        let coinbase = Transaction::new(vec![], self.coinbase.clone());
+        Ok(std::iter::once(coinbase)
+            .chain(self.transactions.iter().cloned())
+            .collect())
+    }
+
+    pub fn compute_merkle(&self) -> Result<MerkleRoot> {
+        MerkleRoot::calculate(&self.all_transactions()?)
+    }
+
+    /// The unmined block. Does not validate the transactions, that's the chain's job
+    pub fn finalize(self) -> Result<Block> {
+        let transactions = self.all_transactions()?;
+        let merkle_root = Self::merkle_root(self.tree, &transactions)?;
+        Ok(Block::new(
+            BlockHeader::new(
+                self.timestamp,
+                self.nonce,
+                self.prev_block_hash,
+                merkle_root,
+                self.target,
+            ),
+            transactions,
+        ))
+    }
+
+    /// Like `finalize`, but the first coinbase output also collects the fees the block's
+    /// transactions pay, looked up in `utxos`
+    pub fn finalize_with_fees(
+        self,
+        utxos: &HashMap<Hash, (TransactionOutput, bool)>,
+    ) -> Result<Block> {
+        let tree = self.tree.clone();
+        let mut block = self.finalize()?;
+        let fees = block.calculate_miner_fees(utxos)?;
+        let coinbase = &mut block.transactions[0];
+        let hash = coinbase.hash();
+        let output = &mut coinbase.outputs[0];
+        output.value = output
+            .value
+            .checked_add(fees)
+            .ok_or(BtcError::ValueOverflow(hash))?;
+        block.header.merkle_root = Self::merkle_root(tree, &block.transactions)?;
+        Ok(block)
+    }
+
+    /// Like `finalize_with_fees`, but the coinbase pays `payout` its shares of `reward` plus the
+    /// fees, after any outputs added to it before
+    pub fn finalize_paying(
+        self,
+        payout: &Payout,
+        reward: Amount,
+        utxos: &HashMap<Hash, (TransactionOutput, bool)>,
+    ) -> Result<Block> {
+        let start = self.coinbase.len();
+        let tree = self.tree.clone();
+        // the fees are only known once there's a block, the real outputs replace this one
+        let first = payout.splits()[0].pubkey.clone();
+        let mut block = self.coinbase_to(first, reward).finalize()?;
+        let fees = block.calculate_miner_fees(utxos)?;
+        let coinbase = &mut block.transactions[0];
+        let total = reward
+            .checked_add(fees)
+            .ok_or(BtcError::ValueOverflow(coinbase.hash()))?;
+        coinbase.outputs.truncate(start);
+        coinbase
+            .outputs
+            .extend(
+                payout
+                    .split(total)
+                    .into_iter()
+                    .map(|(pubkey, value)| TransactionOutput {
+                        value,
+                        unique_id: Uuid::new_v4(),
+                        pubkey,
+                    }),
+            );
+        block.header.merkle_root = Self::merkle_root(tree, &block.transactions)?;
+        Ok(block)
+    }
+}
+
+/// Builds and signs a transaction. Inputs added with `spend` are signed by `finalize`, once the
+/// memo is known, and count towards the change. Ones added with `add_input` are taken as they are.
+#[derive(Clone, Default)]
+pub struct TransactionBuilder {
+    inputs: Vec<PendingInput>,
+    outputs: Vec<TransactionOutput>,
+    spent: Vec<Amount>,
+    change: Option<(PublicKey, Amount)>,
+    memo: Option<String>,
+}
+
+#[derive(Clone)]
+enum PendingInput {
+    Signed(TransactionInput),
+    Unsigned(Hash, PrivateKey),
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// spend `output`, signing for it with `key`
+    pub fn spend(mut self, output: &TransactionOutput, key: &PrivateKey) -> Self {
+        self.inputs
+            .push(PendingInput::Unsigned(output.hash(), key.clone()));
+        self.spent.push(output.value);
+        self
+    }
+
+    pub fn add_input(mut self, input: TransactionInput) -> Self {
+        self.inputs.push(PendingInput::Signed(input));
+        self
+    }
+
+    pub fn memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    pub fn pay_to(mut self, pubkey: PublicKey, value: Amount) -> Self {
+        self.outputs.push(TransactionOutput {
+            value,
+            unique_id: Uuid::new_v4(),
+            pubkey,
+        });
+        self
+    }
+
+    /// pay `invoice` what it asks, with the output it names so the payee can match it
+    pub fn pay_invoice(self, invoice: &Invoice) -> Self {
+        self.add_output(invoice.output())
+    }
+
+    pub fn add_output(mut self, output: TransactionOutput) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// send whatever the spent outputs have left after the payments and `fee` back to `pubkey`
+    pub fn change_to(mut self, pubkey: PublicKey, fee: Amount) -> Self {
+        self.change = Some((pubkey, fee));
+        self
+    }
+
+    /// Errors if the values overflow, the memo is too long, or if the spent outputs don't cover
+    /// the payments plus fee when there is change to compute
+    pub fn finalize(self) -> Result<Transaction> {
+        let mut transaction = Transaction::new(vec![], self.outputs);
+        transaction.memo = self.memo;
+        transaction.verify_memo()?;
+        transaction.inputs = self
+            .inputs
+            .into_iter()
+            .map(|input| match input {
+                PendingInput::Signed(input) => input,
+                PendingInput::Unsigned(hash, key) => TransactionInput {
+                    prev_transaction_output_hash: hash,
+                    signature: Signature::sign_output(&transaction.signature_hash(&hash), &key),
+                },
+            })
+            .collect();
+        let outputs = transaction.output_value()?;
+        let Some((pubkey, fee)) = self.change else {
+            return Ok(transaction);
+        };
+
+        let spent = Amount::checked_sum(self.spent)
+            .ok_or_else(|| BtcError::ValueOverflow(transaction.hash()))?;
+        let change = outputs
+            .checked_add(fee)
+            .and_then(|needed| spent.checked_sub(needed))
+            .ok_or_else(|| transaction.insufficient_inputs(spent))?;
+        if change > Amount::ZERO {
+            transaction.outputs.push(TransactionOutput {
+                value: change,
+                unique_id: Uuid::new_v4(),
+                pubkey,
+            });
+        }
+        Ok(transaction)
+    }
+}
@@ -0,0 +1,155 @@
+//! Invoices: a payment request signed by the key it asks to be paid to, so the paying wallet can
+//! tell nobody changed the amount or the key on the way. The invoice id goes into the `unique_id`
+//! of the output paying it, which is how the receiving wallet tells which invoice an output pays.
+
+use crate::{
+    crypto::{PrivateKey, PublicKey, Signature},
+    error::{BtcError, Result},
+    types::{Amount, TransactionOutput},
+    util::Saveable,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Write};
+
+/// what invoice URIs start with, the CBOR of the invoice as hex follows
+pub const INVOICE_URI_SCHEME: &str = "btcrs-invoice:";
+/// signed before the invoice terms, so an invoice signature is good for nothing else
+const INVOICE_SIGNING_PREFIX: &[u8] = b"kme-btcrs invoice:";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Invoice {
+    /// the `unique_id` of the output paying it
+    pub id: Uuid,
+    /// where to pay, and the key that signed it
+    pub pubkey: PublicKey,
+    pub amount: Amount,
+    pub memo: String,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub signature: Signature,
+}
+
+/// the terms of an invoice, what its signature is over
+#[derive(Serialize)]
+struct Terms<'a> {
+    id: &'a Uuid,
+    pubkey: &'a PublicKey,
+    amount: Amount,
+    memo: &'a str,
+    created: &'a DateTime<Utc>,
+    expires: &'a DateTime<Utc>,
+}
+
+impl Terms<'_> {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = INVOICE_SIGNING_PREFIX.to_vec();
+        ciborium::into_writer(self, &mut bytes).expect("writing to a Vec can't fail");
+        bytes
+    }
+}
+
+impl Invoice {
+    /// An invoice for `amount` to `key`, good for `expiry` from now
+    pub fn new(
+        key: &PrivateKey,
+        amount: Amount,
+        memo: impl Into<String>,
+        expiry: Duration,
+    ) -> Self {
+        let id = Uuid::new_v4();
+        let pubkey = key.public_key();
+        let memo = memo.into();
+        let created = Utc::now();
+        let expires = created + expiry;
+        let signature = key.sign_message(
+            &Terms {
+                id: &id,
+                pubkey: &pubkey,
+                amount,
+                memo: &memo,
+                created: &created,
+                expires: &expires,
+            }
+            .to_bytes(),
+        );
+        Invoice {
+            id,
+            pubkey,
+            amount,
+            memo,
+            created,
+            expires,
+            signature,
+        }
+    }
+
+    fn terms(&self) -> Terms<'_> {
+        Terms {
+            id: &self.id,
+            pubkey: &self.pubkey,
+            amount: self.amount,
+            memo: &self.memo,
+            created: &self.created,
+            expires: &self.expires,
+        }
+    }
+
+    /// whether the key it asks to be paid to signed it as it is
+    pub fn verify(&self) -> bool {
+        self.pubkey
+            .verify_message(&self.terms().to_bytes(), &self.signature)
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires
+    }
+
+    /// the output that pays it
+    pub fn output(&self) -> TransactionOutput {
+        TransactionOutput {
+            value: self.amount,
+            unique_id: self.id,
+            pubkey: self.pubkey.clone(),
+        }
+    }
+
+    /// whether `output` pays it, paying more than asked counts
+    pub fn is_paid_by(&self, output: &TransactionOutput) -> bool {
+        output.unique_id == self.id && output.pubkey == self.pubkey && output.value >= self.amount
+    }
+
+    pub fn to_uri(&self) -> String {
+        let mut bytes = vec![];
+        ciborium::into_writer(self, &mut bytes).expect("writing to a Vec can't fail");
+        format!("{INVOICE_URI_SCHEME}{}", hex::encode(bytes))
+    }
+
+    /// Parse what `to_uri` produces, in either case so it survives an uppercase QR code. The
+    /// signature isn't checked, see `verify`
+    pub fn from_uri(uri: &str) -> Result<Self> {
+        let invalid = |reason: &dyn std::fmt::Display| BtcError::InvalidInvoice(reason.to_string());
+        let uri = uri.trim();
+        let data = uri
+            .get(..INVOICE_URI_SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(INVOICE_URI_SCHEME))
+            .map(|_| &uri[INVOICE_URI_SCHEME.len()..])
+            .ok_or_else(|| invalid(&format!("doesn't start with {INVOICE_URI_SCHEME}")))?;
+        let bytes = hex::decode(data).map_err(|e| invalid(&e))?;
+        ciborium::from_reader(bytes.as_slice()).map_err(|e| invalid(&e))
+    }
+}
+
+impl Saveable for Invoice {
+    fn load<I: Read>(reader: I) -> IoResult<Self> {
+        ciborium::de::from_reader(reader)
+            .map_err(|_| IoError::new(IoErrorKind::InvalidData, "Failed to deserialize Invoice"))
+    }
+    fn save<O: Write>(&self, writer: O) -> IoResult<()> {
+        ciborium::ser::into_writer(self, writer)
+            .map_err(|_| IoError::new(IoErrorKind::InvalidData, "Failed to serialize Invoice"))
+    }
+}
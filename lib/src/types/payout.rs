@@ -0,0 +1,116 @@
+//! Splitting a block's reward between several keys, e.g. a classroom team sharing what its miner
+//! finds without running a pool. Each key gets a whole percentage of the reward plus fees, what
+//! rounding leaves over goes to the first key, so the coinbase pays out exactly what it may.
+
+use crate::{
+    crypto::PublicKey,
+    error::{BtcError, Result},
+    types::Amount,
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+
+/// One key of a `Payout` and its share of the reward
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PayoutSplit {
+    pub pubkey: PublicKey,
+    /// of the reward plus fees, 1 to 100
+    pub percent: u8,
+}
+
+/// Who a block's coinbase pays and how much of it, the percents add up to 100
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(try_from = "Vec<PayoutSplit>", into = "Vec<PayoutSplit>")]
+pub struct Payout {
+    splits: Vec<PayoutSplit>,
+}
+
+impl Payout {
+    /// Errors unless there are between 1 and `MAX_PAYOUT_SPLITS` different keys, each getting
+    /// something, and the percents add up to 100
+    pub fn new(splits: Vec<PayoutSplit>) -> Result<Self> {
+        if splits.is_empty() || splits.len() > crate::MAX_PAYOUT_SPLITS {
+            return Err(BtcError::InvalidPayout(format!(
+                "{} keys, 1 to {} can be paid",
+                splits.len(),
+                crate::MAX_PAYOUT_SPLITS
+            )));
+        }
+        let mut keys = HashSet::new();
+        for split in &splits {
+            if split.percent == 0 {
+                return Err(BtcError::InvalidPayout("a key gets 0%".to_string()));
+            }
+            if !keys.insert(&split.pubkey) {
+                return Err(BtcError::InvalidPayout("a key is paid twice".to_string()));
+            }
+        }
+        let total: u32 = splits.iter().map(|split| u32::from(split.percent)).sum();
+        if total != 100 {
+            return Err(BtcError::InvalidPayout(format!(
+                "the percents add up to {total}, not 100"
+            )));
+        }
+        Ok(Payout { splits })
+    }
+
+    /// everything to `pubkey`
+    pub fn single(pubkey: PublicKey) -> Self {
+        Payout {
+            splits: vec![PayoutSplit {
+                pubkey,
+                percent: 100,
+            }],
+        }
+    }
+
+    pub fn splits(&self) -> &[PayoutSplit] {
+        &self.splits
+    }
+
+    /// the key everything goes to, if it's just the one
+    pub fn single_key(&self) -> Option<&PublicKey> {
+        match self.splits.as_slice() {
+            [split] => Some(&split.pubkey),
+            _ => None,
+        }
+    }
+
+    /// `amount` shared out by percent, in the payout's order. Shares round down and the first key
+    /// gets what's left over, so they add up to `amount`
+    pub fn split(&self, amount: Amount) -> Vec<(PublicKey, Amount)> {
+        let mut shares: Vec<(PublicKey, Amount)> = self
+            .splits
+            .iter()
+            .map(|split| {
+                let share = amount.to_sat() as u128 * u128::from(split.percent) / 100;
+                (split.pubkey.clone(), Amount::from_sat(share as u64))
+            })
+            .collect();
+        let paid: u64 = shares.iter().map(|(_, share)| share.to_sat()).sum();
+        shares[0].1 = Amount::from_sat(shares[0].1.to_sat() + (amount.to_sat() - paid));
+        shares
+    }
+}
+
+impl From<PublicKey> for Payout {
+    fn from(pubkey: PublicKey) -> Self {
+        Payout::single(pubkey)
+    }
+}
+
+impl TryFrom<Vec<PayoutSplit>> for Payout {
+    type Error = BtcError;
+
+    fn try_from(splits: Vec<PayoutSplit>) -> Result<Self> {
+        Payout::new(splits)
+    }
+}
+
+impl From<Payout> for Vec<PayoutSplit> {
+    fn from(payout: Payout) -> Self {
+        payout.splits
+    }
+}
@@ -1,4 +1,10 @@
-use crate::{crypto::PublicKey, sha256::Hash, util::Saveable};
+use crate::{
+    crypto::PublicKey,
+    error::{BtcError, Result},
+    sha256::Hash,
+    types::Amount,
+    util::Saveable,
+};
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -7,12 +13,19 @@ use crate::crypto::Signature;
 use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Write};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct Transaction {
     pub inputs: Vec<TransactionInput>,
     pub outputs: Vec<TransactionOutput>,
+    /// Free text for the recipient, e.g. an invoice number. At most `MAX_MEMO_LEN` bytes, and
+    /// signed by every input so nobody can swap it out. Left out of the encoding when there is
+    /// none, so transactions from before memos keep their hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct TransactionInput {
     /// the previous transaction output hash; Bitcoin uses the index of the output as well, we are
     /// gonna keep it simple for now.
@@ -23,9 +36,10 @@ pub struct TransactionInput {
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct TransactionOutput {
     /// amount of currency being transferred in this output;
-    pub value: u64,
+    pub value: Amount,
     /// generated indentifier to help us ensure the transaction hash is unique.
     pub unique_id: Uuid,
     /// valid signature created with the private key
@@ -34,12 +48,106 @@ pub struct TransactionOutput {
 
 impl Transaction {
     pub fn new(inputs: Vec<TransactionInput>, outputs: Vec<TransactionOutput>) -> Self {
-        Transaction { inputs, outputs }
+        Transaction {
+            inputs,
+            outputs,
+            memo: None,
+        }
+    }
+
+    pub fn with_memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    /// What the input spending `output_hash` signs: just the output hash, or the output hash
+    /// together with the memo when there is one
+    pub fn signature_hash(&self, output_hash: &Hash) -> Hash {
+        match &self.memo {
+            None => *output_hash,
+            Some(memo) => Hash::hash(&(output_hash, memo)),
+        }
+    }
+
+    pub fn verify_memo(&self) -> Result<()> {
+        match &self.memo {
+            Some(memo) if memo.len() > crate::MAX_MEMO_LEN => Err(BtcError::MemoTooLong {
+                transaction: self.hash(),
+                len: memo.len(),
+                max: crate::MAX_MEMO_LEN,
+            }),
+            _ => Ok(()),
+        }
     }
 
     pub fn hash(&self) -> Hash {
         Hash::hash(self)
     }
+
+    /// the CBOR the transaction's hash is taken over
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        if let Err(e) = ciborium::into_writer(self, &mut bytes) {
+            panic!("Failed to serialize transaction: {e:?}. This should not happen");
+        }
+        bytes
+    }
+
+    /// bytes of the transaction's CBOR, what fee rates are per
+    pub fn size(&self) -> usize {
+        self.to_bytes().len()
+    }
+
+    /// hex of the CBOR the transaction's hash is taken over, to pass it around as text
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    /// Parse what `to_hex` produces. Anything that doesn't encode back to the same bytes, e.g.
+    /// with data after the transaction, is refused so the hex always stands for one txid
+    pub fn from_hex(s: &str) -> Result<Self> {
+        let invalid =
+            |reason: &dyn std::fmt::Display| BtcError::InvalidRawTransaction(reason.to_string());
+        let bytes = hex::decode(s.trim()).map_err(|e| invalid(&e))?;
+        let transaction: Transaction =
+            ciborium::from_reader(bytes.as_slice()).map_err(|e| invalid(&e))?;
+        if transaction.to_hex() != hex::encode(&bytes) {
+            return Err(invalid(&"not in canonical encoding"));
+        }
+        Ok(transaction)
+    }
+
+    /// sum of all output values, an error if it is not a valid amount
+    pub fn output_value(&self) -> Result<Amount> {
+        Amount::checked_sum(self.outputs.iter().map(|output| output.value))
+            .ok_or_else(|| BtcError::ValueOverflow(self.hash()))
+    }
+
+    /// Sum of all output values, an error if it is more than `max_money`. Values are never
+    /// negative so this keeps every single output under it as well
+    pub fn verify_output_values(&self, max_money: Amount) -> Result<Amount> {
+        let value = self.output_value()?;
+        if value > max_money {
+            return Err(BtcError::AboveMaxMoney {
+                transaction: self.hash(),
+                value,
+                max: max_money,
+            });
+        }
+        Ok(value)
+    }
+
+    /// the error for this transaction spending less than it creates
+    pub(crate) fn insufficient_inputs(&self, inputs: Amount) -> BtcError {
+        match self.output_value() {
+            Ok(outputs) => BtcError::InsufficientInputs {
+                transaction: self.hash(),
+                inputs,
+                outputs,
+            },
+            Err(e) => e,
+        }
+    }
 }
 
 impl TransactionOutput {
@@ -1,52 +1,160 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 use std::{
     fs::File,
-    io::{Read, Result, Write},
+    io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result, Write},
     path::Path,
 };
 
+mod footer;
+mod merkle_tree;
+
+pub use footer::{CHUNK_SIZE, ChunkedWriter, FOOTER_MAGIC, FOOTER_SIZE, read_checked};
+pub use merkle_tree::MerkleTree;
+
 use crate::sha256::Hash;
 use crate::types::Transaction;
 
 #[derive(Debug, Clone, Deserialize, Serialize, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct MerkleRoot(Hash);
 
 impl MerkleRoot {
-    pub fn calculate(transactions: &[Transaction]) -> Self {
-        let mut layer: Vec<Hash> = vec![];
-
-        for transaction in transactions {
-            layer.push(Hash::hash(transaction));
+    /// an empty list has no root, every block needs at least its coinbase transaction
+    pub fn calculate(transactions: &[Transaction]) -> crate::error::Result<Self> {
+        let mut layer: Vec<Hash> = transactions.iter().map(Hash::hash).collect();
+        while layer.len() > 1 {
+            layer = merkle_layer(&layer);
         }
+        layer
+            .first()
+            .copied()
+            .map(MerkleRoot)
+            .ok_or(crate::error::BtcError::EmptyBlock)
+    }
+
+    pub fn from_hash(hash: Hash) -> Self {
+        MerkleRoot(hash)
+    }
+
+    pub fn hash(&self) -> Hash {
+        self.0
+    }
+}
+
+/// The hashes needed to get from one transaction to the merkle root, so a light client can check
+/// a transaction is in a block knowing only its header
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MerkleProof {
+    /// sibling hashes from the bottom up, with whether the sibling is the right one of the pair
+    path: Vec<(Hash, bool)>,
+}
 
+impl MerkleRoot {
+    /// proof for the transaction at `index`, `None` if there is none
+    pub fn prove(transactions: &[Transaction], index: usize) -> Option<MerkleProof> {
+        if index >= transactions.len() {
+            return None;
+        }
+        let mut layer: Vec<Hash> = transactions.iter().map(Hash::hash).collect();
+        let mut index = index;
+        let mut path = vec![];
         while layer.len() > 1 {
-            let mut new_layer = vec![];
-            for pair in layer.chunks(2) {
-                let left = pair[0];
-                let right = pair.get(1).unwrap_or(&pair[0]);
-                new_layer.push(Hash::hash(&[left, *right]));
-            }
-            layer = new_layer
+            // the last hash of an odd layer is paired with itself
+            let sibling = layer.get(index ^ 1).unwrap_or(&layer[index]);
+            path.push((*sibling, index.is_multiple_of(2)));
+            layer = merkle_layer(&layer);
+            index /= 2;
         }
-        MerkleRoot(layer[0])
+        Some(MerkleProof { path })
+    }
+}
+
+impl MerkleProof {
+    pub fn verify(&self, transaction: &Transaction, root: &MerkleRoot) -> bool {
+        let hash = self
+            .path
+            .iter()
+            .fold(Hash::hash(transaction), |hash, (sibling, on_right)| {
+                if *on_right {
+                    merkle_parent(hash, *sibling)
+                } else {
+                    merkle_parent(*sibling, hash)
+                }
+            });
+        hash == root.0
     }
 }
 
+/// the hash of two neighbours in the tree, the CBOR of the pair hashed
+pub(crate) fn merkle_parent(left: Hash, right: Hash) -> Hash {
+    Hash::hash(&[left, right])
+}
+
+/// the layer above `layer`, the last hash of an odd layer is paired with itself
+pub(crate) fn merkle_layer(layer: &[Hash]) -> Vec<Hash> {
+    layer
+        .chunks(2)
+        .map(|pair| merkle_parent(pair[0], *pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
 pub trait Saveable
 where
     Self: Sized,
 {
+    /// whether files get an integrity footer, see `footer`. Off for formats other programs read
+    const FOOTER: bool = true;
+
     fn load<I: Read>(reader: I) -> Result<Self>;
     fn save<O: Write>(&self, writer: O) -> Result<()>;
 
+    /// `save` followed by the integrity footer, streamed out in chunks
+    fn save_checked<O: Write>(&self, writer: O) -> Result<()> {
+        let mut writer = ChunkedWriter::new(writer);
+        self.save(&mut writer)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// `load` what `save_checked` wrote, an `InvalidData` error if it doesn't match its footer.
+    /// What was saved without a footer loads as it is
+    fn load_checked<I: Read>(reader: I) -> Result<Self> {
+        Self::load(read_checked(reader)?.as_slice())
+    }
+
+    /// as JSON, for reading or editing by hand
+    fn save_json<O: Write>(&self, writer: O) -> Result<()>
+    where
+        Self: Serialize,
+    {
+        serde_json::to_writer_pretty(writer, self)
+            .map_err(|e| IoError::new(IoErrorKind::InvalidData, e))
+    }
+
+    fn load_json<I: Read>(reader: I) -> Result<Self>
+    where
+        Self: DeserializeOwned,
+    {
+        serde_json::from_reader(reader).map_err(|e| IoError::new(IoErrorKind::InvalidData, e))
+    }
+
     fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let file = File::create(&path)?;
-        self.save(file)
+        if Self::FOOTER {
+            self.save_checked(file)
+        } else {
+            self.save(file)
+        }
     }
 
     fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let file = File::open(&path)?;
-        Self::load(file)
+        if Self::FOOTER {
+            Self::load_checked(file)
+        } else {
+            Self::load(file)
+        }
     }
 }
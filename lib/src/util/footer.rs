@@ -0,0 +1,106 @@
+//! Integrity footer of saved files: `FOOTER_MAGIC`, the length of what comes before it as a big
+//! endian u64, then its sha256. A file with bytes flipped or missing no longer matches its
+//! footer and is refused, instead of loading as some other, wrong state.
+//!
+//! Files saved before the footer have none and load as they are. A file cut short loses its
+//! footer along with its end, that's left to the format inside to notice.
+
+use crate::error::BtcError;
+use crate::sha256::Hash;
+
+use sha2::{Digest, Sha256};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Write};
+
+pub const FOOTER_MAGIC: &[u8; 8] = b"BTCRSSUM";
+/// bytes of the footer at the end of a file
+pub const FOOTER_SIZE: usize = FOOTER_MAGIC.len() + 8 + 32;
+/// bytes `ChunkedWriter` gathers before writing them out
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Writes what it's given in chunks of `CHUNK_SIZE`, hashing them on the way, and the footer once
+/// `finish`ed. Nothing bigger than a chunk is held in memory, so a whole chain can be saved
+/// without serializing it to a buffer first
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+    chunk: Vec<u8>,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChunkedWriter {
+            inner,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            hasher: Sha256::new(),
+            written: 0,
+        }
+    }
+
+    fn write_chunk(&mut self) -> IoResult<()> {
+        self.hasher.update(&self.chunk);
+        self.inner.write_all(&self.chunk)?;
+        self.written += self.chunk.len() as u64;
+        self.chunk.clear();
+        Ok(())
+    }
+
+    /// write out the last chunk and the footer, giving the writer back
+    pub fn finish(mut self) -> IoResult<W> {
+        self.write_chunk()?;
+        let hash: [u8; 32] = self.hasher.finalize().into();
+        self.inner.write_all(FOOTER_MAGIC)?;
+        self.inner.write_all(&self.written.to_be_bytes())?;
+        self.inner.write_all(&hash)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let taken = buf.len().min(CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..taken]);
+        if self.chunk.len() == CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(taken)
+    }
+
+    /// only flushes what was written out already, a chunk is written out when full or finished
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// Everything in `reader` without its footer, once it matches what's before it. Without a footer
+/// it's all returned as it is
+pub fn read_checked<I: Read>(mut reader: I) -> IoResult<Vec<u8>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    let Some(start) = bytes.len().checked_sub(FOOTER_SIZE) else {
+        return Ok(bytes);
+    };
+    let footer = &bytes[start..];
+    if !footer.starts_with(FOOTER_MAGIC) {
+        return Ok(bytes);
+    }
+    let corrupt =
+        |reason: String| IoError::new(IoErrorKind::InvalidData, BtcError::CorruptFile(reason));
+    let (len, hash) = footer[FOOTER_MAGIC.len()..].split_at(8);
+    let len = u64::from_be_bytes(len.try_into().unwrap());
+    if len != start as u64 {
+        return Err(corrupt(format!(
+            "{start} bytes before the footer, it says {len}"
+        )));
+    }
+    let expected = Hash::from_be_bytes(hash.try_into().unwrap());
+    let found = Hash::of_bytes(&bytes[..start]);
+    if found != expected {
+        return Err(corrupt(format!(
+            "hashes to {found}, the footer says {expected}"
+        )));
+    }
+    bytes.truncate(start);
+    Ok(bytes)
+}
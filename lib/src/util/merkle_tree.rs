@@ -0,0 +1,101 @@
+//! A merkle tree that keeps its layers, for the node's templates. Templates change a little at a
+//! time: another coinbase for each miner, a few transactions in or out when the mempool changes.
+//! Only the hashes above the leaves that changed are worked out again, a new coinbase costs one
+//! hash a layer instead of the whole tree.
+
+use super::{MerkleRoot, merkle_parent};
+use crate::sha256::Hash;
+use crate::types::Transaction;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleTree {
+    /// bottom up, the leaves first and the root alone last. Empty with no leaves
+    layers: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(transactions: &[Transaction]) -> Self {
+        Self::from_leaves(transactions.iter().map(Hash::hash).collect())
+    }
+
+    pub fn from_leaves(leaves: Vec<Hash>) -> Self {
+        let len = leaves.len();
+        let mut tree = MerkleTree {
+            layers: vec![leaves],
+        };
+        tree.rehash(0, len);
+        tree
+    }
+
+    /// the same as `MerkleRoot::calculate` of the leaves, `None` without any
+    pub fn root(&self) -> Option<MerkleRoot> {
+        self.layers
+            .last()
+            .and_then(|layer| layer.first())
+            .copied()
+            .map(MerkleRoot::from_hash)
+    }
+
+    pub fn leaves(&self) -> &[Hash] {
+        &self.layers[0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers[0].is_empty()
+    }
+
+    /// put `leaf` at `index`, or after the last leaf if that's where `index` is
+    pub fn set(&mut self, index: usize, leaf: Hash) {
+        let leaves = &mut self.layers[0];
+        if index == leaves.len() {
+            leaves.push(leaf);
+        } else {
+            leaves[index] = leaf;
+        }
+        self.rehash(index, index + 1);
+    }
+
+    /// every leaf from `from` on swapped for `leaves`, which may be more or fewer
+    pub fn splice(&mut self, from: usize, leaves: impl IntoIterator<Item = Hash>) {
+        let layer = &mut self.layers[0];
+        let from = from.min(layer.len());
+        layer.truncate(from);
+        layer.extend(leaves);
+        let len = layer.len();
+        self.rehash(from, len);
+    }
+
+    /// Work out the hashes above the leaves in `from..to` again, and fit the layers to how many
+    /// leaves there are now. Above a layer, the pairs starting at `from / 2` up to the one
+    /// holding `to - 1` changed
+    fn rehash(&mut self, mut from: usize, mut to: usize) {
+        let mut level = 0;
+        while self.layers[level].len() > 1 {
+            let below = &self.layers[level];
+            let width = below.len().div_ceil(2);
+            let changed: Vec<(usize, Hash)> = (from / 2..to.div_ceil(2).min(width))
+                .map(|index| {
+                    let left = below[2 * index];
+                    let right = below.get(2 * index + 1).copied().unwrap_or(left);
+                    (index, merkle_parent(left, right))
+                })
+                .collect();
+            if level + 1 == self.layers.len() {
+                self.layers.push(vec![]);
+            }
+            let above = &mut self.layers[level + 1];
+            above.resize(width, Hash::zero());
+            for (index, hash) in changed {
+                above[index] = hash;
+            }
+            from /= 2;
+            to = to.div_ceil(2);
+            level += 1;
+        }
+        self.layers.truncate(level + 1);
+    }
+}
@@ -0,0 +1,114 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{Amount, BlockBuilder, Blockchain, Transaction, TransactionBuilder},
+    util::Saveable,
+};
+
+use chrono::{Duration, Utc};
+use common::{mine, unspent};
+
+mod common;
+
+/// Alice mines the first block, pays bob from it in the second and bob pays her back from that
+/// in the third, along with something he spends in the same block
+fn chain(alice: &PrivateKey, bob: &PrivateKey) -> Blockchain {
+    let start = Utc::now() - Duration::minutes(10);
+    let mut blockchain = Blockchain::regtest();
+    let mut transactions: Vec<Transaction> = vec![];
+    for i in 0..4 {
+        let block = mine(
+            BlockBuilder::on_top_of(&blockchain)
+                .timestamp(start + Duration::minutes(i))
+                .coinbase_to(alice.public_key(), blockchain.calculate_block_reward())
+                .add_txs(transactions),
+            &blockchain,
+        );
+        transactions = match i {
+            0 => vec![
+                TransactionBuilder::new()
+                    .spend(&block.transactions[0].outputs[0], alice)
+                    .pay_to(bob.public_key(), Amount::ONE_BTC)
+                    .change_to(alice.public_key(), Amount::from_sat(1_000))
+                    .finalize()
+                    .unwrap(),
+            ],
+            1 => {
+                let back = TransactionBuilder::new()
+                    .spend(&block.transactions[1].outputs[0], bob)
+                    .pay_to(alice.public_key(), Amount::from_sat(50_000_000))
+                    .change_to(bob.public_key(), Amount::from_sat(1_000))
+                    .finalize()
+                    .unwrap();
+                let change = back
+                    .outputs
+                    .iter()
+                    .find(|output| output.pubkey == bob.public_key())
+                    .unwrap()
+                    .clone();
+                let again = TransactionBuilder::new()
+                    .spend(&change, bob)
+                    .pay_to(bob.public_key(), Amount::from_sat(10_000_000))
+                    .change_to(bob.public_key(), Amount::from_sat(1_000))
+                    .finalize()
+                    .unwrap();
+                vec![back, again]
+            }
+            _ => vec![],
+        };
+        blockchain.add_block(block).unwrap();
+    }
+    blockchain
+}
+
+#[test]
+fn scans_match_the_addrindex() {
+    let alice = PrivateKey::new_key();
+    let bob = PrivateKey::new_key();
+    let blockchain = chain(&alice, &bob);
+    let mut indexed = blockchain.clone();
+    indexed.enable_addrindex();
+
+    for key in [&alice, &bob] {
+        for from in 0..=blockchain.block_height() {
+            let walked = blockchain.scan_address(&key.public_key(), from).unwrap();
+            let looked_up = indexed.scan_address(&key.public_key(), from).unwrap();
+            assert_eq!(walked.history, looked_up.history);
+            assert!(
+                walked
+                    .history
+                    .iter()
+                    .all(|activity| activity.height >= from)
+            );
+            assert_eq!(unspent(&walked.utxos), unspent(&looked_up.utxos));
+        }
+    }
+    let scan = blockchain.scan_address(&bob.public_key(), 0).unwrap();
+    assert_eq!(scan.history.len(), 3);
+    assert_eq!(
+        Amount::checked_sum(scan.utxos.iter().map(|(output, _)| output.value)),
+        Some(indexed.address_balance(&bob.public_key()))
+    );
+    assert_eq!((scan.height, scan.tip), (4, blockchain.tip_hash()));
+    assert!(blockchain.scan_address(&bob.public_key(), 5).is_none());
+}
+
+#[test]
+fn scans_without_undo_data_look_back() {
+    // saved before undo data was kept
+    let bytes = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../blockchain.cbor")).unwrap();
+    let mut blockchain = Blockchain::load(bytes.as_slice()).unwrap();
+    blockchain.rebuild_utxos();
+    let mut indexed = blockchain.clone();
+    indexed.enable_addrindex();
+
+    let height = blockchain.block_height();
+    let pubkey = blockchain.blocks().last().unwrap().transactions[0].outputs[0]
+        .pubkey
+        .clone();
+    for from in [0, height / 2, height] {
+        assert_eq!(
+            blockchain.scan_address(&pubkey, from).unwrap().history,
+            indexed.scan_address(&pubkey, from).unwrap().history
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{Amount, BlockBuilder, Blockchain, TransactionBuilder},
+};
+
+use chrono::{Duration, Utc};
+use common::mine;
+
+mod common;
+
+#[test]
+fn history_follows_payments() {
+    let alice = PrivateKey::new_key();
+    let bob = PrivateKey::new_key().public_key();
+    let mut blockchain = Blockchain::regtest();
+    blockchain.enable_addrindex();
+
+    let start = Utc::now() - Duration::minutes(10);
+    let reward = blockchain.calculate_block_reward();
+    let genesis = mine(
+        BlockBuilder::new()
+            .timestamp(start)
+            .coinbase_to(alice.public_key(), reward),
+        &blockchain,
+    );
+    let coinbase = genesis.transactions[0].outputs[0].clone();
+    blockchain.add_block(genesis).unwrap();
+
+    let fee = Amount::from_sat(1_000);
+    let payment = TransactionBuilder::new()
+        .spend(&coinbase, &alice)
+        .pay_to(bob.clone(), Amount::ONE_BTC)
+        .change_to(alice.public_key(), fee)
+        .finalize()
+        .unwrap();
+    let block = mine(
+        BlockBuilder::on_top_of(&blockchain)
+            .timestamp(start + Duration::minutes(1))
+            .coinbase_to(bob.clone(), blockchain.calculate_block_reward())
+            .add_tx(payment.clone()),
+        &blockchain,
+    );
+    blockchain.add_block(block).unwrap();
+
+    let history = blockchain.address_history(&alice.public_key());
+    assert_eq!(history.len(), 2);
+    assert_eq!(
+        (history[0].received, history[0].sent),
+        (reward, Amount::ZERO)
+    );
+    let change = reward
+        .checked_sub(Amount::ONE_BTC)
+        .and_then(|rest| rest.checked_sub(fee))
+        .unwrap();
+    assert_eq!(history[1].txid, payment.hash());
+    assert_eq!((history[1].received, history[1].sent), (change, reward));
+
+    // bob got paid and mined the block
+    let history = blockchain.address_history(&bob);
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().all(|activity| activity.height == 1));
+
+    // building the index from scratch gives the same history
+    let mut rebuilt = blockchain.clone();
+    rebuilt.enable_addrindex();
+    assert_eq!(
+        rebuilt.address_history(&alice.public_key()),
+        blockchain.address_history(&alice.public_key())
+    );
+}
+
+#[test]
+fn balances_add_up_to_the_utxos() {
+    let alice = PrivateKey::new_key();
+    let bob = PrivateKey::new_key();
+    let mut blockchain = Blockchain::regtest();
+    blockchain.enable_addrindex();
+
+    let start = Utc::now() - Duration::minutes(10);
+    let genesis = mine(
+        BlockBuilder::new()
+            .timestamp(start)
+            .coinbase_to(alice.public_key(), blockchain.calculate_block_reward()),
+        &blockchain,
+    );
+    let coinbase = genesis.transactions[0].outputs[0].clone();
+    blockchain.add_block(genesis).unwrap();
+    let payment = TransactionBuilder::new()
+        .spend(&coinbase, &alice)
+        .pay_to(bob.public_key(), Amount::ONE_BTC)
+        .change_to(alice.public_key(), Amount::from_sat(1_000))
+        .finalize()
+        .unwrap();
+    let block = mine(
+        BlockBuilder::on_top_of(&blockchain)
+            .timestamp(start + Duration::minutes(1))
+            .coinbase_to(bob.public_key(), blockchain.calculate_block_reward())
+            .add_tx(payment),
+        &blockchain,
+    );
+    blockchain.add_block(block).unwrap();
+
+    let unspent = |key: &PrivateKey| {
+        Amount::checked_sum(
+            blockchain
+                .utxos()
+                .values()
+                .filter(|(output, _)| output.pubkey == key.public_key())
+                .map(|(output, _)| output.value),
+        )
+        .unwrap()
+    };
+    let rich_list = blockchain.rich_list(10);
+    let holders: Vec<_> = rich_list
+        .iter()
+        .map(|holder| (holder.pubkey.clone(), holder.balance))
+        .collect();
+    assert_eq!(
+        holders,
+        [
+            (bob.public_key(), unspent(&bob)),
+            (alice.public_key(), unspent(&alice)),
+        ]
+    );
+    assert_eq!(blockchain.rich_list(1), rich_list[..1]);
+    assert_eq!(blockchain.address_balance(&bob.public_key()), unspent(&bob));
+
+    // both hold tens of coins
+    let distribution = blockchain.supply_distribution();
+    let full: Vec<_> = distribution
+        .iter()
+        .filter(|bucket| bucket.holders > 0)
+        .collect();
+    assert_eq!(full.len(), 1);
+    assert_eq!(full[0].min, Amount::from_sat(1_000_000_000));
+    assert_eq!(full[0].holders, 2);
+    assert_eq!(
+        Some(full[0].total),
+        unspent(&alice).checked_add(unspent(&bob))
+    );
+
+    let mut rebuilt = blockchain.clone();
+    rebuilt.enable_addrindex();
+    assert_eq!(rebuilt.rich_list(10), rich_list);
+    assert_eq!(rebuilt.supply_distribution(), distribution);
+    assert!(Blockchain::regtest().rich_list(10).is_empty());
+}
@@ -0,0 +1,52 @@
+use btclib::types::Amount;
+
+#[test]
+fn decimal_btc_round_to_the_nearest_sat() {
+    // none of these are exact as an f64
+    for (btc, sats) in [
+        (0.29, 29_000_000),
+        (0.57, 57_000_000),
+        (1.1, 110_000_000),
+        (0.000_000_01, 1),
+        (20_999_999.999_999_99, 2_099_999_999_999_999),
+    ] {
+        assert_eq!(Amount::from_btc(btc), Some(Amount::from_sat(sats)), "{btc}");
+        assert_eq!(Amount::from_sat(sats).to_btc(), btc);
+    }
+    assert_eq!(Amount::from_btc(0.0), Some(Amount::ZERO));
+    assert_eq!(Amount::from_btc(21_000_000.0), Some(Amount::MAX_MONEY));
+}
+
+#[test]
+fn fractions_of_a_sat_round_too() {
+    for (btc, sats) in [
+        (0.000_000_016, 2),
+        (0.000_000_004, 0),
+        (0.123_456_789, 12_345_679),
+    ] {
+        assert_eq!(Amount::from_btc(btc), Some(Amount::from_sat(sats)), "{btc}");
+    }
+}
+
+#[test]
+fn bad_amounts_are_refused() {
+    for btc in [
+        -0.000_000_01,
+        -0.000_000_004,
+        21_000_000.000_000_01,
+        f64::NAN,
+        f64::INFINITY,
+    ] {
+        assert_eq!(Amount::from_btc(btc), None, "{btc}");
+    }
+}
+
+#[test]
+fn parsed_amounts_match_from_btc() {
+    for text in ["0.29", "0.57", "1.1", "0.00000001"] {
+        let parsed: Amount = text.parse().unwrap();
+        assert_eq!(Amount::from_btc(text.parse().unwrap()), Some(parsed));
+    }
+    assert_eq!("1500 sat".parse(), Ok(Amount::from_sat(1500)));
+    assert!("0.000000001".parse::<Amount>().is_err());
+}
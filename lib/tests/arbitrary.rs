@@ -0,0 +1,46 @@
+use btclib::{
+    network::Message,
+    types::{Block, Blockchain},
+    util::Saveable,
+};
+
+use std::io::Cursor;
+
+use arbitrary::{Arbitrary, Unstructured};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+/// the same pseudo random inputs on every run
+fn inputs(seed: u64) -> impl Iterator<Item = Vec<u8>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..200).map(move |_| {
+        let len = rng.gen_range(0..4096);
+        (0..len).map(|_| rng.r#gen()).collect()
+    })
+}
+
+#[test]
+fn arbitrary_messages_survive_the_wire() {
+    for data in inputs(1) {
+        let Ok(message) = Message::arbitrary(&mut Unstructured::new(&data)) else {
+            continue;
+        };
+        let encoded = message.encode().unwrap();
+        let decoded = Message::decode(&encoded).unwrap();
+        assert_eq!(decoded.encode().unwrap(), encoded, "{message:?}");
+    }
+}
+
+#[test]
+fn garbage_is_refused_without_panicking() {
+    for data in inputs(2) {
+        let _ = Message::decode(&data);
+        let _ = Block::load(Cursor::new(&data));
+        let _ = Blockchain::load(Cursor::new([b"BTCRSCHN\x00\x01", &data[..]].concat()));
+    }
+}
+
+#[test]
+fn huge_length_prefixes_are_refused_before_allocating() {
+    let mut stream = Cursor::new(u64::MAX.to_be_bytes().to_vec());
+    assert!(Message::receive(&mut stream).is_err());
+}
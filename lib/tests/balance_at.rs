@@ -0,0 +1,119 @@
+use btclib::{
+    crypto::PrivateKey,
+    sha256::Hash,
+    types::{Amount, BlockBuilder, Blockchain, Transaction, TransactionBuilder, TransactionOutput},
+};
+
+use chrono::{Duration, Utc};
+use common::mine;
+use std::collections::HashSet;
+
+mod common;
+
+/// Alice mines every block, pays bob from the first in the second and bob pays her back in the
+/// third, spending his change again in the same block
+fn chain(alice: &PrivateKey, bob: &PrivateKey) -> Blockchain {
+    let start = Utc::now() - Duration::minutes(10);
+    let mut blockchain = Blockchain::regtest();
+    let mut transactions: Vec<Transaction> = vec![];
+    for i in 0..4 {
+        let block = mine(
+            BlockBuilder::on_top_of(&blockchain)
+                .timestamp(start + Duration::minutes(i))
+                .coinbase_to(alice.public_key(), blockchain.calculate_block_reward())
+                .add_txs(transactions),
+            &blockchain,
+        );
+        transactions = match i {
+            0 => vec![
+                TransactionBuilder::new()
+                    .spend(&block.transactions[0].outputs[0], alice)
+                    .pay_to(bob.public_key(), Amount::ONE_BTC)
+                    .change_to(alice.public_key(), Amount::from_sat(1_000))
+                    .finalize()
+                    .unwrap(),
+            ],
+            1 => {
+                let back = TransactionBuilder::new()
+                    .spend(&block.transactions[1].outputs[0], bob)
+                    .pay_to(alice.public_key(), Amount::from_sat(50_000_000))
+                    .change_to(bob.public_key(), Amount::from_sat(1_000))
+                    .finalize()
+                    .unwrap();
+                let change = back
+                    .outputs
+                    .iter()
+                    .find(|output| output.pubkey == bob.public_key())
+                    .unwrap()
+                    .clone();
+                let again = TransactionBuilder::new()
+                    .spend(&change, bob)
+                    .pay_to(bob.public_key(), Amount::from_sat(10_000_000))
+                    .change_to(bob.public_key(), Amount::from_sat(1_000))
+                    .finalize()
+                    .unwrap();
+                vec![back, again]
+            }
+            _ => vec![],
+        };
+        blockchain.add_block(block).unwrap();
+    }
+    blockchain
+}
+
+#[test]
+fn balances_add_up_to_the_outputs_at_every_height() {
+    let alice = PrivateKey::new_key();
+    let bob = PrivateKey::new_key();
+    let blockchain = chain(&alice, &bob);
+    let mut indexed = blockchain.clone();
+    indexed.enable_addrindex();
+
+    for key in [&alice, &bob] {
+        let pubkey = key.public_key();
+        for height in 0..=blockchain.block_height() {
+            let balance = blockchain.balance_at(&pubkey, height).unwrap();
+            let utxos = blockchain.utxos_at(&pubkey, height).unwrap();
+            assert_eq!(
+                Amount::checked_sum(utxos.iter().map(|output| output.value)),
+                Some(balance)
+            );
+            assert_eq!(indexed.balance_at(&pubkey, height), Some(balance));
+            let hashes = |outputs: Vec<TransactionOutput>| -> Vec<Hash> {
+                outputs.iter().map(TransactionOutput::hash).collect()
+            };
+            assert_eq!(
+                hashes(indexed.utxos_at(&pubkey, height).unwrap()),
+                hashes(utxos)
+            );
+        }
+
+        // at the tip it's what the UTXO set has
+        let height = blockchain.block_height();
+        let now: HashSet<Hash> = blockchain
+            .utxos()
+            .values()
+            .filter(|(output, _)| output.pubkey == pubkey)
+            .map(|(output, _)| output.hash())
+            .collect();
+        let then: HashSet<Hash> = blockchain
+            .utxos_at(&pubkey, height)
+            .unwrap()
+            .iter()
+            .map(|output| output.hash())
+            .collect();
+        assert_eq!(now, then);
+        assert_eq!(
+            blockchain.balance_at(&pubkey, height),
+            Some(indexed.address_balance(&pubkey))
+        );
+        assert!(blockchain.balance_at(&pubkey, height + 1).is_none());
+    }
+
+    let bob = bob.public_key();
+    assert_eq!(blockchain.balance_at(&bob, 0), Some(Amount::ZERO));
+    assert_eq!(blockchain.balance_at(&bob, 1), Some(Amount::ZERO));
+    assert_eq!(blockchain.balance_at(&bob, 2), Some(Amount::ONE_BTC));
+    // his change was spent again in the block it was made in
+    assert_eq!(blockchain.utxos_at(&bob, 3).unwrap().len(), 2);
+}
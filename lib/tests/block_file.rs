@@ -0,0 +1,213 @@
+use btclib::{error::BtcError, types::BlockFile};
+
+use common::mined_chain;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+mod common;
+
+fn scratch() -> PathBuf {
+    std::env::temp_dir().join(format!("btclib-{}.blocks", Uuid::new_v4()))
+}
+
+fn index(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.idx", path.display()))
+}
+
+fn remove(path: &Path) {
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(index(path)).unwrap();
+}
+
+#[test]
+fn blocks_are_read_by_height_and_hash() {
+    let blockchain = mined_chain(5, 0);
+    let path = scratch();
+    let file = BlockFile::open(&path).unwrap();
+    assert_eq!(blockchain.write_block_file(&file).unwrap(), 5);
+    assert_eq!(blockchain.write_block_file(&file).unwrap(), 0);
+    assert_eq!(file.len(), 5);
+
+    for (height, block) in blockchain.blocks().enumerate() {
+        let read = file.read_block(height as u64).unwrap().unwrap();
+        assert_eq!(read.hash(), block.hash());
+        let read = file.read_block_by_hash(&block.hash()).unwrap().unwrap();
+        assert_eq!(read.hash(), block.hash());
+    }
+    assert!(file.read_block(5).unwrap().is_none());
+
+    // a block that doesn't go on top is refused
+    let first = blockchain.blocks().next().unwrap();
+    assert!(matches!(
+        file.append(first),
+        Err(BtcError::InvalidBlockFile(_))
+    ));
+    drop(file);
+    let file = BlockFile::open(&path).unwrap();
+    assert_eq!(file.len(), 5);
+    assert_eq!(file.hash_at(4), Some(blockchain.tip_hash()));
+    assert!(file.verify().unwrap().is_ok());
+    remove(&path);
+}
+
+#[test]
+fn the_index_catches_up_after_a_crash() {
+    let blockchain = mined_chain(4, 0);
+    let path = scratch();
+    let file = BlockFile::open(&path).unwrap();
+    blockchain.write_block_file(&file).unwrap();
+    drop(file);
+
+    // the last block made it, its index entry didn't, and the next block was cut off halfway
+    let idx = index(&path);
+    let len = std::fs::metadata(&idx).unwrap().len();
+    OpenOptions::new()
+        .write(true)
+        .open(&idx)
+        .unwrap()
+        .set_len(len - 40)
+        .unwrap();
+    let blocks_len = std::fs::metadata(&path).unwrap().len();
+    let mut blocks = OpenOptions::new().append(true).open(&path).unwrap();
+    blocks.write_all(&1000u64.to_be_bytes()).unwrap();
+    blocks.write_all(&[0; 10]).unwrap();
+    drop(blocks);
+
+    let file = BlockFile::open(&path).unwrap();
+    assert_eq!(file.len(), 4);
+    assert_eq!(file.hash_at(3), Some(blockchain.tip_hash()));
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), blocks_len);
+    assert!(file.verify().unwrap().is_ok());
+
+    // without any index it's built from scratch
+    drop(file);
+    std::fs::remove_file(&idx).unwrap();
+    let file = BlockFile::open(&path).unwrap();
+    assert_eq!(file.len(), 4);
+    assert!(file.verify().unwrap().is_ok());
+    remove(&path);
+}
+
+#[test]
+fn blocks_taken_off_the_chain_are_dropped() {
+    let blockchain = mined_chain(5, 0);
+    let path = scratch();
+    let file = BlockFile::open(&path).unwrap();
+    blockchain.write_block_file(&file).unwrap();
+
+    // as if the chain had another block 3
+    file.truncate(3).unwrap();
+    let other = mined_chain(4, 1);
+    assert!(file.append(other.blocks().nth(3).unwrap()).is_err());
+    assert_eq!(file.len(), 3);
+    assert_eq!(blockchain.write_block_file(&file).unwrap(), 2);
+    assert_eq!(file.hash_at(4), Some(blockchain.tip_hash()));
+
+    let shorter = mined_chain(2, 2);
+    assert_eq!(shorter.write_block_file(&file).unwrap(), 2);
+    assert_eq!(file.len(), 2);
+    assert_eq!(file.height_of(&blockchain.tip_hash()), None);
+    remove(&path);
+}
+
+#[test]
+fn verify_finds_damage() {
+    let blockchain = mined_chain(3, 0);
+    let path = scratch();
+    let file = BlockFile::open(&path).unwrap();
+    blockchain.write_block_file(&file).unwrap();
+    let report = file.verify().unwrap();
+    assert_eq!(report.blocks, 3);
+    assert_eq!(report.bytes, std::fs::metadata(&path).unwrap().len());
+    drop(file);
+
+    // flip a byte in the last block
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 20;
+    bytes[last] ^= 0xff;
+    std::fs::write(&path, &bytes).unwrap();
+    let file = BlockFile::open(&path).unwrap();
+    let report = file.verify().unwrap();
+    assert!(!report.is_ok(), "{report:?}");
+    assert!(report.problems[0].starts_with("block 2"), "{report:?}");
+    assert!(file.read_block(2).is_err());
+    assert!(file.read_block(1).unwrap().is_some());
+    remove(&path);
+}
+
+/// wipe the blocks on disk behind `file`'s back, leaving only what it has in memory
+fn wipe_blocks(path: &Path) {
+    let len = std::fs::metadata(path).unwrap().len();
+    let mut blocks = OpenOptions::new().write(true).open(path).unwrap();
+    blocks.seek(SeekFrom::Start(10)).unwrap();
+    blocks.write_all(&vec![0; len as usize - 10]).unwrap();
+}
+
+#[test]
+fn blocks_used_last_are_kept_in_memory() {
+    let blockchain = mined_chain(3, 0);
+    let path = scratch();
+    let file = BlockFile::open(&path).unwrap().with_cache_capacity(2);
+    blockchain.write_block_file(&file).unwrap();
+    for height in [1, 2, 1, 0] {
+        file.read_block(height).unwrap().unwrap();
+    }
+
+    // 2 was used longest ago so it made room for 0
+    wipe_blocks(&path);
+    let tip = file.read_block(1).unwrap().unwrap();
+    assert_eq!(tip.hash(), blockchain.blocks().nth(1).unwrap().hash());
+    let header = file.read_header(0).unwrap().unwrap();
+    assert_eq!(
+        header.hash(),
+        blockchain.blocks().next().unwrap().header.hash()
+    );
+    assert!(file.read_block(2).is_err());
+    remove(&path);
+
+    // nothing is kept without room for it
+    let path = scratch();
+    let file = BlockFile::open(&path).unwrap().with_cache_capacity(0);
+    blockchain.write_block_file(&file).unwrap();
+    file.read_block(0).unwrap().unwrap();
+    wipe_blocks(&path);
+    assert!(file.read_block(0).is_err());
+    remove(&path);
+}
+
+#[test]
+fn dropped_blocks_leave_the_cache() {
+    let blockchain = mined_chain(3, 0);
+    let path = scratch();
+    let file = BlockFile::open(&path).unwrap();
+    blockchain.write_block_file(&file).unwrap();
+    for height in 0..3 {
+        file.read_block(height).unwrap().unwrap();
+    }
+
+    let other = mined_chain(2, 1);
+    other.write_block_file(&file).unwrap();
+    assert!(file.read_block(2).unwrap().is_none());
+    for (height, block) in other.blocks().enumerate() {
+        let read = file.read_block(height as u64).unwrap().unwrap();
+        assert_eq!(read.hash(), block.hash());
+    }
+
+    file.reindex().unwrap();
+    wipe_blocks(&path);
+    assert!(file.read_block(0).is_err());
+    remove(&path);
+}
+
+#[test]
+fn other_files_are_refused() {
+    let path = scratch();
+    std::fs::write(&path, b"not blocks at all").unwrap();
+    assert!(matches!(
+        BlockFile::open(&path),
+        Err(BtcError::InvalidBlockFile(_))
+    ));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,197 @@
+use btclib::{
+    U256,
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, BlockBuilder, Blockchain, TransactionBuilder},
+};
+
+use chrono::{Duration, Utc};
+use common::mine;
+
+mod common;
+
+#[test]
+fn validated_block_is_checked_again_on_a_new_tip() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::regtest();
+    let start = Utc::now() - Duration::minutes(10);
+    let genesis = BlockBuilder::new()
+        .timestamp(start)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    blockchain.add_block(genesis).unwrap();
+
+    // two miners build on the same tip, the first one to get the write lock wins
+    let next = |minutes| {
+        BlockBuilder::on_top_of(&blockchain)
+            .timestamp(start + Duration::minutes(minutes))
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+    };
+    let first = mine(next(1), &blockchain);
+    let second = mine(next(2), &blockchain);
+    let first = blockchain.validate_block(first).unwrap();
+    let second = blockchain.validate_block(second).unwrap();
+
+    blockchain.add_validated_block(first).unwrap();
+    assert!(matches!(
+        blockchain.add_validated_block(second),
+        Err(BtcError::PrevHashMismatch { .. })
+    ));
+    assert_eq!(blockchain.block_height(), 2);
+}
+
+#[test]
+fn utxos_follow_added_blocks() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::regtest();
+    let start = Utc::now() - Duration::minutes(10);
+    let genesis = BlockBuilder::new()
+        .timestamp(start)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    blockchain.add_block(genesis).unwrap();
+
+    let (coinbase, _) = blockchain.utxos().values().next().unwrap().clone();
+    let transaction = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(PrivateKey::new_key().public_key(), Amount::ONE_BTC)
+        .change_to(key.public_key(), Amount::ZERO)
+        .finalize()
+        .unwrap();
+    let block = mine(
+        BlockBuilder::on_top_of(&blockchain)
+            .timestamp(start + Duration::minutes(1))
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .add_tx(transaction),
+        &blockchain,
+    );
+    blockchain.add_block(block).unwrap();
+
+    // the coinbase is spent, the payment, its change and the new coinbase are not
+    assert!(!blockchain.utxos().contains_key(&coinbase.hash()));
+    assert_eq!(blockchain.utxos().len(), 3);
+    let mut rebuilt = blockchain.clone();
+    rebuilt.rebuild_utxos();
+    assert_eq!(
+        rebuilt
+            .utxos()
+            .keys()
+            .collect::<std::collections::HashSet<_>>(),
+        blockchain.utxos().keys().collect()
+    );
+}
+
+#[test]
+fn shares_only_need_the_share_target() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::new();
+    let start = Utc::now() - Duration::minutes(10);
+    let genesis = BlockBuilder::new()
+        .timestamp(start)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    blockchain.add_block(genesis).unwrap();
+
+    // one hash in 16 meets it
+    let share_target = U256::MAX >> 4;
+    let mut share = BlockBuilder::on_top_of(&blockchain)
+        .timestamp(start + Duration::minutes(1))
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    assert!(share.header.mine_to(share_target, usize::MAX));
+    assert!(!share.hash().matches_target(share.header.target));
+
+    blockchain.validate_share(&share, share_target).unwrap();
+    // a harder target than the block's own is no use
+    assert!(matches!(
+        blockchain.validate_share(&share, U256::zero()),
+        Err(BtcError::InsufficientWork(_))
+    ));
+    assert!(matches!(
+        blockchain.validate_block(share.clone()),
+        Err(BtcError::InsufficientWork(_))
+    ));
+
+    // everything else still has to be right
+    share.header.prev_block_hash = btclib::sha256::Hash::zero();
+    assert!(share.header.mine_to(share_target, usize::MAX));
+    assert!(matches!(
+        blockchain.validate_share(&share, share_target),
+        Err(BtcError::PrevHashMismatch { .. })
+    ));
+}
+
+#[test]
+fn shares_with_a_forged_target_are_refused() {
+    let key = PrivateKey::new_key();
+    let start = Utc::now() - Duration::minutes(10);
+    let share_target = U256::MAX >> 4;
+    for mut blockchain in [Blockchain::new(), Blockchain::regtest()] {
+        let genesis = BlockBuilder::new()
+            .timestamp(start)
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .finalize()
+            .unwrap();
+        blockchain.add_block(genesis).unwrap();
+
+        // a header claiming a target any hash meets, with a hash that misses the share target
+        let mut share = BlockBuilder::on_top_of(&blockchain)
+            .timestamp(start + Duration::minutes(1))
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .set_target(btclib::REGTEST_TARGET)
+            .finalize()
+            .unwrap();
+        while share.hash().matches_target(share_target) {
+            share.header.nonce += 1;
+        }
+        let err = blockchain.validate_share(&share, share_target).unwrap_err();
+        if blockchain.params().regtest {
+            assert!(matches!(err, BtcError::InsufficientWork(_)), "{err}");
+        } else {
+            assert!(matches!(err, BtcError::TargetMismatch { .. }), "{err}");
+        }
+    }
+}
+
+#[test]
+fn blocks_carry_the_chain_target() {
+    let key = PrivateKey::new_key();
+    let start = Utc::now() - Duration::minutes(10);
+    let genesis = BlockBuilder::new()
+        .timestamp(start)
+        .coinbase_to(key.public_key(), Blockchain::new().calculate_block_reward())
+        .finalize()
+        .unwrap();
+    let mut easy = genesis.clone();
+    easy.header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    assert!(matches!(
+        Blockchain::new().validate_block(easy.clone()),
+        Err(BtcError::TargetMismatch { .. })
+    ));
+    // only regtest chains take blocks mined at the regtest target
+    Blockchain::regtest().validate_block(easy).unwrap();
+
+    let mut blockchain = Blockchain::new();
+    blockchain.add_block(genesis).unwrap();
+    let next = || {
+        BlockBuilder::on_top_of(&blockchain)
+            .timestamp(start + Duration::minutes(1))
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+    };
+    assert!(matches!(
+        blockchain.validate_block(mine(next(), &blockchain)),
+        Err(BtcError::TargetMismatch { actual, .. }) if actual == U256::MAX
+    ));
+    // nor any other target the block picks for itself
+    let mut block = next().set_target(U256::MAX >> 1).finalize().unwrap();
+    assert!(block.header.mine(usize::MAX));
+    assert!(matches!(
+        blockchain.validate_block(block),
+        Err(BtcError::TargetMismatch { expected, .. }) if expected == blockchain.target()
+    ));
+}
@@ -0,0 +1,96 @@
+use btclib::{
+    crypto::PrivateKey,
+    network::{BloomFilter, MerkleBlock},
+    types::{Amount, BlockBuilder, TransactionBuilder},
+    util::MerkleRoot,
+};
+
+#[test]
+fn filter_matches_bip37_vector() {
+    let mut filter = BloomFilter::new(3, 0.01, 0);
+    for item in [
+        "99108ad8ed9bb6274d3980bab5a85c048f0950c8",
+        "b5a2c786d9ef4658287ced5914b37a1b4aa32eee",
+        "b9300670b4c5366e95b2699e8b18bc75e5f729c5",
+    ] {
+        filter.insert(&hex::decode(item).unwrap());
+    }
+    assert!(filter.contains(&hex::decode("99108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap()));
+    assert!(!filter.contains(&hex::decode("19108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap()));
+
+    // the filter's bits are 614e9b, a three byte CBOR byte string
+    let mut encoded = vec![];
+    ciborium::into_writer(&filter, &mut encoded).unwrap();
+    assert!(encoded.windows(4).any(|w| w == [0x43, 0x61, 0x4e, 0x9b]));
+}
+
+#[test]
+fn filter_follows_payments_to_its_keys() {
+    let key = PrivateKey::new_key();
+    let stranger = PrivateKey::new_key();
+    // room for the outputs it picks up too, or unrelated transactions may match it by chance
+    let mut filter = BloomFilter::new(10, 0.000_001, 7);
+    filter.insert_pubkey(&key.public_key());
+
+    let paid = TransactionBuilder::new()
+        .pay_to(key.public_key(), Amount::ONE_BTC)
+        .finalize()
+        .unwrap();
+    assert!(filter.matches_transaction(&paid));
+
+    // spending the output matches too, even though it pays someone else
+    let spent = TransactionBuilder::new()
+        .spend(&paid.outputs[0], &key)
+        .pay_to(stranger.public_key(), Amount::ONE_BTC)
+        .finalize()
+        .unwrap();
+    assert!(filter.matches_transaction(&spent));
+
+    let unrelated = TransactionBuilder::new()
+        .pay_to(stranger.public_key(), Amount::ONE_BTC)
+        .finalize()
+        .unwrap();
+    assert!(!filter.matches_transaction(&unrelated));
+}
+
+#[test]
+fn merkle_block_proves_matching_transactions() {
+    let key = PrivateKey::new_key();
+    let stranger = PrivateKey::new_key().public_key();
+    let payments: Vec<_> = (0..4)
+        .map(|i| {
+            let pubkey = if i == 2 {
+                key.public_key()
+            } else {
+                stranger.clone()
+            };
+            TransactionBuilder::new()
+                .pay_to(pubkey, Amount::ONE_SAT)
+                .finalize()
+                .unwrap()
+        })
+        .collect();
+    let block = BlockBuilder::new()
+        .coinbase_to(stranger.clone(), Amount::ONE_BTC)
+        .add_txs(payments.clone())
+        .finalize()
+        .unwrap();
+
+    // every transaction of an odd sized block has a proof
+    for (index, transaction) in block.transactions.iter().enumerate() {
+        let proof = MerkleRoot::prove(&block.transactions, index).unwrap();
+        assert!(proof.verify(transaction, &block.header.merkle_root));
+    }
+
+    let mut filter = BloomFilter::new(10, 0.000_001, 7);
+    filter.insert_pubkey(&key.public_key());
+    let merkle_block = MerkleBlock::new(&block, &mut filter);
+    assert_eq!(merkle_block.transaction_count, 5);
+    assert_eq!(merkle_block.matches.len(), 1);
+    assert_eq!(merkle_block.matches[0].0.hash(), payments[2].hash());
+    assert!(merkle_block.verify());
+
+    let mut forged = merkle_block.clone();
+    forged.matches[0].0 = payments[1].clone();
+    assert!(!forged.verify());
+}
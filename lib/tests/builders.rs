@@ -0,0 +1,135 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, BlockBuilder, TransactionBuilder},
+};
+
+use common::chain_paying;
+
+mod common;
+
+#[test]
+fn built_block_spending_coinbase_is_accepted() {
+    let key = PrivateKey::new_key();
+    let recipient = PrivateKey::new_key().public_key();
+    let (mut blockchain, [coinbase]) = chain_paying(&key);
+
+    let fee = Amount::from_sat(1_000);
+    let payment = Amount::ONE_BTC;
+    let transaction = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(recipient, payment)
+        .change_to(key.public_key(), fee)
+        .finalize()
+        .unwrap();
+    let change = coinbase
+        .value
+        .checked_sub(payment)
+        .and_then(|rest| rest.checked_sub(fee))
+        .unwrap();
+    assert_eq!(transaction.outputs.len(), 2);
+    assert_eq!(transaction.outputs[1].value, change);
+
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .add_tx(transaction)
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    assert_eq!(
+        block.transactions[0].outputs[0].value,
+        blockchain
+            .calculate_block_reward()
+            .checked_add(fee)
+            .unwrap()
+    );
+
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.block_height(), 2);
+}
+
+#[test]
+fn exact_spend_has_no_change_output() {
+    let key = PrivateKey::new_key();
+    let (_, [coinbase]) = chain_paying(&key);
+
+    let transaction = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(
+            key.public_key(),
+            coinbase.value.checked_sub(Amount::ONE_SAT).unwrap(),
+        )
+        .change_to(key.public_key(), Amount::ONE_SAT)
+        .finalize()
+        .unwrap();
+    assert_eq!(transaction.outputs.len(), 1);
+}
+
+#[test]
+fn overspending_transaction_is_rejected() {
+    let key = PrivateKey::new_key();
+    let (_, [coinbase]) = chain_paying(&key);
+
+    let result = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(key.public_key(), coinbase.value)
+        .change_to(key.public_key(), Amount::ONE_SAT)
+        .finalize();
+    assert!(matches!(result, Err(BtcError::InsufficientInputs { .. })));
+}
+
+#[test]
+fn block_without_coinbase_is_rejected() {
+    let result = BlockBuilder::new().finalize();
+    assert!(matches!(result, Err(BtcError::MissingCoinbase)));
+}
+
+#[test]
+fn memo_is_signed_and_bounded() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [coinbase]) = chain_paying(&key);
+
+    let long_memo = "x".repeat(btclib::MAX_MEMO_LEN + 1);
+    let result = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(key.public_key(), Amount::ONE_BTC)
+        .memo(long_memo)
+        .finalize();
+    assert!(matches!(result, Err(BtcError::MemoTooLong { .. })));
+
+    let transaction = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(key.public_key(), Amount::ONE_BTC)
+        .change_to(key.public_key(), Amount::ZERO)
+        .memo("invoice 42")
+        .finalize()
+        .unwrap();
+
+    // swapping the memo out breaks the input's signature
+    let mut tampered = transaction.clone();
+    tampered.memo = Some("invoice 43".to_string());
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .add_tx(tampered)
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    assert!(matches!(
+        blockchain.add_block(block),
+        Err(BtcError::BadSignature { .. })
+    ));
+
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .add_tx(transaction)
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    blockchain.add_block(block).unwrap();
+}
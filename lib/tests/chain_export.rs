@@ -0,0 +1,79 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{BlockBuilder, Blockchain, ExportFormat},
+};
+
+use common::mined_chain;
+
+mod common;
+
+#[test]
+fn export_import_round_trip() {
+    let source = mined_chain(5, 0);
+    for format in [ExportFormat::Binary, ExportFormat::Hex] {
+        let mut file = vec![];
+        let exported = source.export_blocks(.., format, &mut file).unwrap();
+        assert_eq!(exported as u64, source.block_height());
+
+        let mut imported = Blockchain::regtest();
+        assert_eq!(imported.import_blocks(file.as_slice()).unwrap(), exported);
+        assert_eq!(
+            imported.blocks().last().unwrap().hash(),
+            source.blocks().last().unwrap().hash()
+        );
+    }
+}
+
+#[test]
+fn import_appends_and_skips_known_blocks() {
+    let source = mined_chain(5, 0);
+    let mut first = vec![];
+    source
+        .export_blocks(..2, ExportFormat::Binary, &mut first)
+        .unwrap();
+    let mut rest = vec![];
+    source
+        .export_blocks(1.., ExportFormat::Hex, &mut rest)
+        .unwrap();
+
+    let mut blockchain = Blockchain::regtest();
+    assert_eq!(blockchain.import_blocks(first.as_slice()).unwrap(), 2);
+    // block 1 is in both files
+    assert_eq!(
+        blockchain.import_blocks(rest.as_slice()).unwrap() as u64,
+        source.block_height() - 2
+    );
+    assert_eq!(blockchain.block_height(), source.block_height());
+}
+
+#[test]
+fn import_refuses_gaps_and_forks() {
+    let source = mined_chain(5, 0);
+    let mut tail = vec![];
+    source
+        .export_blocks(3.., ExportFormat::Binary, &mut tail)
+        .unwrap();
+    assert!(matches!(
+        Blockchain::regtest().import_blocks(tail.as_slice()),
+        Err(BtcError::InvalidBlockFile(_))
+    ));
+
+    let mut other = Blockchain::regtest();
+    let genesis = BlockBuilder::new()
+        .coinbase_to(
+            PrivateKey::new_key().public_key(),
+            other.calculate_block_reward(),
+        )
+        .finalize()
+        .unwrap();
+    other.add_block(genesis).unwrap();
+    let mut all = vec![];
+    source
+        .export_blocks(.., ExportFormat::Binary, &mut all)
+        .unwrap();
+    assert!(matches!(
+        other.import_blocks(all.as_slice()),
+        Err(BtcError::InvalidBlockFile(_))
+    ));
+}
@@ -0,0 +1,62 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{BlockBuilder, Blockchain},
+    util::Saveable,
+};
+
+use std::io::ErrorKind;
+
+fn legacy_chain() -> Vec<u8> {
+    std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../blockchain.cbor")).unwrap()
+}
+
+#[test]
+fn saved_chain_has_version_header() {
+    let mut blockchain = Blockchain::new();
+    let genesis = BlockBuilder::new()
+        .coinbase_to(
+            PrivateKey::new_key().public_key(),
+            blockchain.calculate_block_reward(),
+        )
+        .finalize()
+        .unwrap();
+    blockchain.add_block(genesis).unwrap();
+
+    let mut bytes = vec![];
+    blockchain.save(&mut bytes).unwrap();
+    assert!(bytes.starts_with(b"BTCRSCHN"));
+    assert_eq!(bytes[8..10], Blockchain::FORMAT_VERSION.to_be_bytes());
+
+    let loaded = Blockchain::load(bytes.as_slice()).unwrap();
+    assert_eq!(loaded.block_height(), 1);
+}
+
+#[test]
+fn legacy_chain_is_migrated() {
+    let bytes = legacy_chain();
+    assert!(!bytes.starts_with(b"BTCRSCHN"));
+
+    let mut blockchain = Blockchain::load(bytes.as_slice()).unwrap();
+    // version 0 utxos were keyed by transaction hash, the migration drops them
+    assert!(blockchain.utxos().is_empty());
+    blockchain.rebuild_utxos();
+    blockchain.total_supply().unwrap();
+    let check = blockchain.self_check(u64::MAX);
+    assert!(check.is_ok(), "{check:?}");
+
+    let mut resaved = vec![];
+    blockchain.save(&mut resaved).unwrap();
+    let reloaded = Blockchain::load(resaved.as_slice()).unwrap();
+    assert_eq!(reloaded.block_height(), blockchain.block_height());
+}
+
+#[test]
+fn newer_format_is_refused() {
+    let mut bytes = b"BTCRSCHN".to_vec();
+    bytes.extend((Blockchain::FORMAT_VERSION + 1).to_be_bytes());
+    bytes.extend(&legacy_chain());
+
+    let error = Blockchain::load(bytes.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert!(error.to_string().contains("format version"), "{error}");
+}
@@ -0,0 +1,111 @@
+use btclib::{
+    chain_params::ChainParams,
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, BlockBuilder, Blockchain},
+};
+
+use std::sync::Arc;
+
+#[test]
+fn default_params_match_constants() {
+    let params = ChainParams::default();
+    assert_eq!(params.min_target, btclib::MIN_TARGET);
+    assert_eq!(
+        params.block_reward(0),
+        Amount::ONE_BTC.checked_mul(btclib::INITIAL_REWARD).unwrap()
+    );
+    assert_eq!(ChainParams::from_toml("").unwrap(), params);
+    assert_eq!(ChainParams::from_toml(&params.to_toml()).unwrap(), params);
+}
+
+#[test]
+fn custom_spec_changes_reward_and_genesis() {
+    let pubkey = PrivateKey::new_key().public_key();
+    let spec = format!(
+        r#"
+name = "classroom"
+initial_reward = 10
+halving_interval = 5
+
+[genesis]
+timestamp = 1735689600
+pubkey = "{}"
+"#,
+        hex::encode(pubkey.to_compact())
+    );
+    let params = ChainParams::from_toml(&spec).unwrap();
+    assert_eq!(params.block_reward(4), Amount::from_sat(10 * 100_000_000));
+    assert_eq!(params.block_reward(5), Amount::from_sat(5 * 100_000_000));
+
+    // every node builds the same genesis block from the same spec
+    let genesis = params.genesis_block().unwrap().unwrap();
+    assert_eq!(
+        genesis.hash(),
+        params.genesis_block().unwrap().unwrap().hash()
+    );
+    assert_eq!(genesis.transactions[0].outputs[0].pubkey, pubkey);
+
+    let mut blockchain = Blockchain::with_params(Arc::new(params));
+    blockchain.add_block(genesis).unwrap();
+    blockchain.rebuild_utxos();
+    assert_eq!(
+        blockchain.calculate_block_reward(),
+        Amount::from_sat(10 * 100_000_000)
+    );
+}
+
+#[test]
+fn invalid_specs_are_rejected() {
+    for spec in [
+        "halving_interval = 0",
+        "min_target = \"00\"",
+        "initial_reward = 1000000000",
+        "unknown_field = 1",
+    ] {
+        assert!(
+            matches!(
+                ChainParams::from_toml(spec),
+                Err(BtcError::InvalidChainSpec(_))
+            ),
+            "{spec}"
+        );
+    }
+}
+
+#[test]
+fn issuance_follows_halvings() {
+    let params = ChainParams::from_toml("initial_reward = 8\nhalving_interval = 2").unwrap();
+    let btc = |n| Amount::ONE_BTC.checked_mul(n).unwrap();
+    assert_eq!(params.issued_until(0), Amount::ZERO);
+    assert_eq!(params.issued_until(3), btc(8 + 8 + 4));
+    // rewards keep halving down to single satoshis, approaching twice the first era's
+    let total = params.issued_until(u64::MAX);
+    assert!(total > btc(31) && total < btc(32));
+    assert!(total <= params.max_money());
+}
+
+#[test]
+fn supply_audit_and_genesis_issuance() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::new();
+    let greedy = BlockBuilder::new()
+        .coinbase_to(key.public_key(), Amount::ONE_BTC.checked_mul(51).unwrap())
+        .finalize()
+        .unwrap();
+    assert!(matches!(
+        blockchain.add_block(greedy),
+        Err(BtcError::CoinbaseValueMismatch { .. })
+    ));
+
+    let genesis = BlockBuilder::new()
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    blockchain.add_block(genesis).unwrap();
+    blockchain.rebuild_utxos();
+    assert_eq!(
+        blockchain.total_supply().unwrap(),
+        blockchain.params().issued_until(1)
+    );
+}
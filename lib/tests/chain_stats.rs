@@ -0,0 +1,85 @@
+use btclib::{
+    MAX_STATS_WINDOWS,
+    crypto::PrivateKey,
+    types::{Amount, Blockchain},
+};
+
+use chrono::{Duration, Utc};
+use common::{block, mined_chain, spend};
+
+mod common;
+
+const FEE: Amount = Amount::from_sat(1000);
+
+/// `count` blocks a minute apart, the second mining a transaction paying `FEE`
+fn chain(key: &PrivateKey, count: i64) -> Blockchain {
+    let start = Utc::now() - Duration::minutes(count + 1);
+    let mut blockchain = Blockchain::regtest();
+    for i in 0..count {
+        let transactions = match blockchain.blocks().next() {
+            Some(genesis) if i == 1 => vec![spend(&genesis.transactions[0].outputs[0], key, FEE)],
+            _ => vec![],
+        };
+        let block = block(&blockchain, key, start + Duration::minutes(i), transactions);
+        blockchain.add_block(block).unwrap();
+    }
+    blockchain
+}
+
+#[test]
+fn stats_add_up_the_blocks() {
+    let key = PrivateKey::new_key();
+    let blockchain = chain(&key, 4);
+
+    let stats = blockchain.stats(0..4);
+    assert_eq!((stats.start, stats.end, stats.blocks()), (0, 4, 4));
+    assert_eq!(stats.average_interval, Some(60.0));
+    assert_eq!(stats.fees, FEE);
+    assert_eq!(stats.transactions, 1);
+    // mined at the regtest target, easier than the network's easiest
+    assert!(stats.difficulty > 0.0 && stats.difficulty < 1.0);
+    assert_eq!(stats.difficulty, blockchain.stats(3..4).difficulty);
+    // the three blocks after the first took three minutes of hashing
+    let hashrate = stats.hashrate.unwrap();
+    let later = blockchain.stats(2..4).hashrate.unwrap();
+    assert!((hashrate - later).abs() / later < 1e-9);
+    assert!(hashrate > 0.0);
+
+    // past the tip is cut off
+    assert_eq!(blockchain.stats(2..100), blockchain.stats(2..4));
+    let stats = blockchain.stats(3..4);
+    assert_eq!((stats.average_interval, stats.hashrate), (None, None));
+    let stats = blockchain.stats(10..20);
+    assert_eq!((stats.start, stats.end, stats.blocks()), (4, 4, 0));
+    assert_eq!(stats.difficulty, 0.0);
+}
+
+#[test]
+fn windows_split_the_range() {
+    let key = PrivateKey::new_key();
+    let blockchain = chain(&key, 5);
+
+    let windows = blockchain.stats_windows(0..u64::MAX, 2);
+    let bounds: Vec<_> = windows.iter().map(|w| (w.start, w.end)).collect();
+    assert_eq!(bounds, [(0, 2), (2, 4), (4, 5)]);
+    assert_eq!(windows[0].fees, FEE);
+    assert_eq!(windows[1].fees, Amount::ZERO);
+
+    assert_eq!(
+        blockchain.stats_windows(1..5, 0),
+        vec![blockchain.stats(1..5)]
+    );
+    assert_eq!(
+        blockchain.stats_windows(7..9, 3),
+        vec![blockchain.stats(5..5)]
+    );
+}
+
+#[test]
+fn windows_are_capped() {
+    let blockchain = mined_chain(MAX_STATS_WINDOWS + 1, 0);
+    let windows = blockchain.stats_windows(0..u64::MAX, 1);
+    assert!(windows.len() as u64 <= MAX_STATS_WINDOWS);
+    assert_eq!(windows[0].blocks(), 2);
+    assert_eq!(windows.last().unwrap().end, MAX_STATS_WINDOWS + 1);
+}
@@ -0,0 +1,124 @@
+use btclib::{
+    chaingen::{ChainGen, Corruption},
+    error::BtcError,
+    types::{Blockchain, ExportFormat},
+};
+
+/// a chain of the first `height` blocks of `blockchain`
+fn prefix(blockchain: &Blockchain, height: u64) -> Blockchain {
+    let mut prefix = Blockchain::regtest();
+    for block in blockchain.blocks().take(height as usize) {
+        prefix.add_block(block.clone()).unwrap();
+    }
+    prefix
+}
+
+fn hashes(blockchain: &Blockchain) -> Vec<String> {
+    blockchain
+        .blocks()
+        .map(|block| block.hash().to_string())
+        .collect()
+}
+
+#[test]
+fn same_seed_same_chain() {
+    let config = ChainGen {
+        blocks: 30,
+        forks: vec![10],
+        invalid: vec![20],
+        ..ChainGen::default()
+    };
+    let first = config.generate().unwrap();
+    let second = config.generate().unwrap();
+    assert_eq!(first.blockchain.block_height(), 30);
+    assert_eq!(hashes(&first.blockchain), hashes(&second.blockchain));
+    assert_eq!(
+        hashes(&first.forks[0].blockchain),
+        hashes(&second.forks[0].blockchain)
+    );
+    assert_eq!(
+        first.invalid[0].block.hash(),
+        second.invalid[0].block.hash()
+    );
+    // keys get paid and spend
+    assert!(
+        first
+            .blockchain
+            .blocks()
+            .any(|block| block.transactions.len() > 1)
+    );
+
+    // asking for branches leaves the chain alone
+    let plain = ChainGen {
+        forks: vec![],
+        invalid: vec![],
+        ..config.clone()
+    };
+    assert_eq!(
+        hashes(&plain.generate().unwrap().blockchain),
+        hashes(&first.blockchain)
+    );
+
+    let other = ChainGen { seed: 1, ..config };
+    assert_ne!(
+        hashes(&other.generate().unwrap().blockchain),
+        hashes(&first.blockchain)
+    );
+}
+
+#[test]
+fn invalid_blocks_are_rejected() {
+    let generated = ChainGen {
+        blocks: 24,
+        invalid: (1..=24).collect(),
+        ..ChainGen::default()
+    }
+    .generate()
+    .unwrap();
+
+    for invalid in generated.invalid {
+        let blockchain = prefix(&generated.blockchain, invalid.height);
+        let err = blockchain.validate_block(invalid.block).unwrap_err();
+        let expected = match invalid.corruption {
+            Corruption::WrongParent => matches!(err, BtcError::PrevHashMismatch { .. }),
+            Corruption::InsufficientWork => matches!(err, BtcError::InsufficientWork(_)),
+            Corruption::BadMerkleRoot => matches!(err, BtcError::InvalidMerkleRoot),
+            Corruption::StaleTimestamp => matches!(err, BtcError::TimestampTooOld { .. }),
+            Corruption::Overpay => matches!(err, BtcError::CoinbaseValueMismatch { .. }),
+            Corruption::DoubleSpend => matches!(err, BtcError::DoubleSpend(_)),
+        };
+        assert!(
+            expected,
+            "block {} with a {}: {err}",
+            invalid.height, invalid.corruption
+        );
+    }
+}
+
+#[test]
+fn forks_overtake_the_chain() {
+    let generated = ChainGen {
+        blocks: 20,
+        forks: vec![0, 7, 20],
+        ..ChainGen::default()
+    }
+    .generate()
+    .unwrap();
+    let chain = hashes(&generated.blockchain);
+
+    for fork in generated.forks {
+        let height = fork.height as usize;
+        let branch = hashes(&fork.blockchain);
+        assert_eq!(branch.len(), chain.len() + 1);
+        assert_eq!(branch[..height], chain[..height]);
+        assert_ne!(branch[height], chain[height.min(chain.len() - 1)]);
+
+        let mut file = vec![];
+        fork.blockchain
+            .export_blocks(fork.height.., ExportFormat::Binary, &mut file)
+            .unwrap();
+        let mut blockchain = prefix(&generated.blockchain, fork.height);
+        blockchain.import_blocks(file.as_slice()).unwrap();
+        assert_eq!(hashes(&blockchain), branch);
+    }
+}
@@ -0,0 +1,90 @@
+use btclib::{
+    chain_params::ChainParams,
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{BlockBuilder, Blockchain, Federation, SignedCheckpoint},
+};
+
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+
+/// three blocks on a chain two of the `authorities` have to sign checkpoints of
+fn chain(authorities: &[PrivateKey]) -> Blockchain {
+    let params = ChainParams {
+        checkpoints: Some(Federation {
+            authorities: authorities.iter().map(PrivateKey::public_key).collect(),
+            threshold: 2,
+        }),
+        ..ChainParams::regtest()
+    };
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::with_params(Arc::new(params));
+    for i in 0..3 {
+        let mut block = BlockBuilder::on_top_of(&blockchain)
+            .timestamp(Utc::now() - Duration::minutes(10 - i))
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .finalize()
+            .unwrap();
+        block
+            .header
+            .mine_with_target_override(btclib::REGTEST_TARGET);
+        blockchain.add_block(block).unwrap();
+    }
+    blockchain
+}
+
+#[test]
+fn checkpoints_need_enough_authorities() {
+    let authorities = [PrivateKey::new_key(), PrivateKey::new_key()];
+    let mut blockchain = chain(&authorities);
+    let hash = blockchain.blocks().nth(1).unwrap().hash();
+
+    let mut checkpoint = SignedCheckpoint::new(1, hash);
+    checkpoint.sign(&authorities[0]);
+    // signing twice still counts once, someone else not at all
+    checkpoint.sign(&authorities[0]);
+    checkpoint.sign(&PrivateKey::new_key());
+    assert!(matches!(
+        blockchain.add_checkpoint(checkpoint.clone()),
+        Err(BtcError::InvalidCheckpoint(_))
+    ));
+
+    let mut other = SignedCheckpoint::new(1, hash);
+    other.sign(&authorities[1]);
+    assert!(checkpoint.merge(&other));
+    assert!(!checkpoint.merge(&other));
+    assert!(blockchain.add_checkpoint(checkpoint.clone()).unwrap());
+    assert_eq!(blockchain.checkpoint().unwrap().height, 1);
+    // old news
+    assert!(!blockchain.add_checkpoint(checkpoint).unwrap());
+
+    // a block the chain doesn't have there
+    let mut wrong = SignedCheckpoint::new(2, hash);
+    wrong.sign(&authorities[0]);
+    wrong.sign(&authorities[1]);
+    assert!(blockchain.add_checkpoint(wrong).is_err());
+}
+
+#[test]
+fn checkpointed_blocks_stay() {
+    let authorities = [PrivateKey::new_key(), PrivateKey::new_key()];
+    let mut blockchain = chain(&authorities);
+    let blocks: Vec<_> = blockchain.blocks().map(|block| block.hash()).collect();
+    let mut checkpoint = SignedCheckpoint::new(1, blocks[1]);
+    for authority in &authorities {
+        checkpoint.sign(authority);
+    }
+    blockchain.add_checkpoint(checkpoint).unwrap();
+
+    assert!(matches!(
+        blockchain.invalidate_block(&blocks[1]),
+        Err(BtcError::BelowCheckpoint {
+            height: 1,
+            checkpoint: 1
+        })
+    ));
+    assert!(!blockchain.is_invalidated(&blocks[1]));
+    assert_eq!(blockchain.block_height(), 3);
+    // what came after it can still go
+    assert_eq!(blockchain.invalidate_block(&blocks[2]).unwrap(), 1);
+}
@@ -0,0 +1,303 @@
+use btclib::{
+    crypto::PrivateKey,
+    network::{
+        COMPRESSION_THRESHOLD, Capabilities, CodecError, Compression, CompressionMeter, Encoding,
+        Envelope, KNOWN_KINDS, MAX_MESSAGE_SIZE, Message, MessageReader, Outbox, PROTOCOL_VERSION,
+        Throttle, TrafficMeter,
+    },
+    types::{Amount, BlockBuilder},
+};
+
+use std::sync::Arc;
+
+use tokio::io::{AsyncWriteExt, duplex};
+
+fn messages() -> Vec<Message> {
+    let key = PrivateKey::new_key().public_key();
+    let block = BlockBuilder::new()
+        .coinbase_to(key.clone(), Amount::ONE_BTC)
+        .finalize()
+        .unwrap();
+    vec![
+        Message::FetchUTXOs(key),
+        Message::NewTransaction(block.transactions[0].clone()),
+        Message::NewBlock(block),
+        Message::Hello {
+            version: PROTOCOL_VERSION,
+            encodings: Encoding::ALL.to_vec(),
+            time: Some(chrono::Utc::now()),
+            nonce: Some([7; 32]),
+            capabilities: Capabilities::FILTERS | Capabilities::SNAPSHOTS,
+        },
+    ]
+}
+
+#[test]
+fn every_encoding_round_trips() {
+    for encoding in Encoding::ALL {
+        for message in messages() {
+            let bytes = message.encode_as(*encoding).unwrap();
+            let decoded = Message::decode_as(*encoding, &bytes).unwrap();
+            // messages aren't comparable, their CBOR is
+            assert_eq!(decoded.encode().unwrap(), message.encode().unwrap());
+        }
+    }
+}
+
+#[test]
+fn encodings_parse_by_name() {
+    for encoding in Encoding::ALL {
+        assert_eq!(encoding.to_string().parse(), Ok(*encoding));
+    }
+    assert_eq!("JSON".parse(), Ok(Encoding::Json));
+    assert!("protobuf".parse::<Encoding>().is_err());
+    assert_eq!(Encoding::negotiate(&[]), Encoding::Cbor);
+    assert_eq!(
+        Encoding::negotiate(&[Encoding::Json, Encoding::Cbor]),
+        Encoding::Json
+    );
+}
+
+#[tokio::test]
+async fn reader_takes_a_hello_in_any_encoding() {
+    let (mut client, server) = duplex(1024);
+    let hello = Envelope {
+        kind: 53,
+        compression: None,
+        payload: br#"{"Hello":{"version":3,"encodings":["Json"]}}"#.to_vec(),
+    }
+    .to_bytes();
+    client.write_u64(hello.len() as u64).await.unwrap();
+    client.write_all(&hello).await.unwrap();
+    let frame = Message::AskDifference(3)
+        .to_frame_as(Encoding::Json)
+        .unwrap();
+    client.write_all(&frame).await.unwrap();
+    client.write_all(&frame).await.unwrap();
+
+    let mut reader = MessageReader::new(server);
+    let Message::Hello { encodings, .. } = reader.receive_hello().await.unwrap() else {
+        panic!("expected a hello");
+    };
+    assert_eq!(encodings, vec![Encoding::Json]);
+    // still CBOR until told otherwise
+    assert!(reader.receive().await.is_err());
+    reader.set_encoding(Encoding::Json);
+    assert!(matches!(
+        reader.receive().await.unwrap(),
+        Message::AskDifference(3)
+    ));
+}
+
+#[tokio::test]
+async fn reader_only_takes_a_hello_in_another_encoding() {
+    let (mut client, server) = duplex(1024);
+    let frame = Message::AskDifference(3)
+        .to_frame_as(Encoding::Json)
+        .unwrap();
+    client.write_all(&frame).await.unwrap();
+
+    let mut reader = MessageReader::new(server);
+    assert!(reader.receive_hello().await.is_err());
+}
+
+#[tokio::test]
+async fn outbox_writes_its_encoding() {
+    let (client, server) = duplex(1024);
+    let (_, writer) = tokio::io::split(server);
+    let mut outbox = Outbox::spawn(writer, 4);
+    outbox.set_encoding(Encoding::Json);
+    outbox.send(&Message::Difference(-2)).await.unwrap();
+
+    let mut reader = MessageReader::new(client);
+    reader.set_encoding(Encoding::Json);
+    assert!(matches!(
+        reader.receive().await.unwrap(),
+        Message::Difference(-2)
+    ));
+}
+
+#[test]
+fn capabilities_default_to_none() {
+    // a hello from before capabilities
+    let hello = br#"{"Hello":{"version":1,"encodings":["Json"]}}"#;
+    let Message::Hello { capabilities, .. } = Message::decode_as(Encoding::Json, hello).unwrap()
+    else {
+        panic!("expected a hello");
+    };
+    assert_eq!(capabilities, Capabilities::NONE);
+    assert_eq!(capabilities.to_string(), "none");
+
+    // ones only newer peers know are kept but not shown
+    let hello = br#"{"Hello":{"version":3,"encodings":[],"capabilities":1026}}"#;
+    let Message::Hello { capabilities, .. } = Message::decode_as(Encoding::Json, hello).unwrap()
+    else {
+        panic!("expected a hello");
+    };
+    assert!(capabilities.contains(Capabilities::FILTERS));
+    assert!(!capabilities.contains(Capabilities::FILTERS | Capabilities::SNAPSHOTS));
+    assert_eq!(capabilities.to_string(), "filters");
+    assert_eq!(
+        capabilities.common(Capabilities::FILTERS | Capabilities::CHECKPOINTS),
+        Capabilities::FILTERS
+    );
+    assert_eq!(Message::FilterClear.needs(), Capabilities::FILTERS);
+    assert!(Message::AskDifference(0).needs().is_empty());
+}
+
+#[tokio::test]
+async fn unknown_kinds_are_skipped() {
+    let (mut client, server) = duplex(1024);
+    let unknown = Envelope {
+        kind: KNOWN_KINDS,
+        compression: None,
+        payload: vec![0xff; 16],
+    }
+    .to_bytes();
+    client.write_u64(unknown.len() as u64).await.unwrap();
+    client.write_all(&unknown).await.unwrap();
+    client
+        .write_all(&Message::AskDifference(3).to_frame().unwrap())
+        .await
+        .unwrap();
+
+    let mut reader = MessageReader::new(server);
+    let error = reader.receive().await.unwrap_err();
+    assert!(matches!(error, CodecError::UnknownKind(kind) if kind == KNOWN_KINDS));
+    assert!(error.is_undecodable());
+    // the next message is read as usual
+    assert!(matches!(
+        reader.receive().await.unwrap(),
+        Message::AskDifference(3)
+    ));
+}
+
+#[test]
+fn every_message_has_its_own_kind() {
+    for message in messages() {
+        let envelope = Envelope::seal(&message, Encoding::Cbor).unwrap();
+        assert!(envelope.is_known());
+        assert_eq!(
+            Envelope::parse(&envelope.to_bytes()).unwrap(),
+            envelope,
+            "{message:?}"
+        );
+        assert_eq!(
+            envelope.open(Encoding::Cbor).unwrap().kind(),
+            message.kind()
+        );
+    }
+    assert_eq!(
+        Message::FetchUTXOs(PrivateKey::new_key().public_key()).kind(),
+        0
+    );
+    assert_eq!(Message::GetStatus.kind(), 46);
+    assert_eq!(
+        Message::Error {
+            code: btclib::network::ErrorCode::Unsupported,
+            reason: String::new()
+        }
+        .kind(),
+        52
+    );
+}
+
+#[tokio::test]
+async fn big_messages_are_compressed() {
+    let messages = vec![
+        Message::AskDifference(3),
+        Message::GeneratedBlocks(vec![btclib::sha256::Hash::zero(); 1000]),
+    ];
+    for compression in [Compression::Zstd, Compression::Lz4] {
+        let (client, server) = duplex(64 * 1024);
+        let (_, writer) = tokio::io::split(server);
+        let meter = Arc::new(CompressionMeter::default());
+        let mut outbox = Outbox::spawn(writer, 4);
+        outbox.set_compression(Some(compression));
+        outbox.set_meter(meter.clone());
+        for message in &messages {
+            outbox.send(message).await.unwrap();
+        }
+
+        let mut reader = MessageReader::new(client);
+        for message in &messages {
+            let received = reader.receive().await.unwrap();
+            assert_eq!(received.encode().unwrap(), message.encode().unwrap());
+        }
+        // only the one over the threshold
+        let stats = meter.stats();
+        assert_eq!(stats.messages, 1);
+        assert!(stats.raw_bytes > COMPRESSION_THRESHOLD as u64);
+        assert!(stats.ratio().unwrap() < 0.5, "{compression:?}: {stats:?}");
+    }
+}
+
+#[test]
+fn compression_is_negotiated() {
+    let both = Capabilities::ZSTD | Capabilities::LZ4;
+    assert_eq!(Compression::negotiate(both, both), Some(Compression::Zstd));
+    assert_eq!(
+        Compression::negotiate(both, Capabilities::LZ4 | Capabilities::FILTERS),
+        Some(Compression::Lz4)
+    );
+    assert_eq!(Compression::negotiate(both, Capabilities::NONE), None);
+}
+
+#[test]
+fn decompression_bombs_are_refused() {
+    let bomb = vec![0; MAX_MESSAGE_SIZE + 1];
+    for compression in [Compression::Zstd, Compression::Lz4] {
+        let compressed = compression.compress(&bomb);
+        assert!(compressed.len() < MAX_MESSAGE_SIZE / 100);
+        assert!(matches!(
+            compression.decompress(&compressed),
+            Err(CodecError::Decompress(_))
+        ));
+        let envelope = Envelope {
+            kind: Message::AskDifference(0).kind(),
+            compression: Some(compression),
+            payload: compressed,
+        };
+        let parsed = Envelope::parse(&envelope.to_bytes()).unwrap();
+        assert_eq!(parsed.compression, Some(compression));
+        assert!(parsed.open(Encoding::Cbor).unwrap_err().is_undecodable());
+    }
+}
+
+#[tokio::test]
+async fn traffic_is_counted_by_message() {
+    let (client, server) = duplex(1024);
+    let (_, writer) = tokio::io::split(server);
+    let sent = Arc::new(TrafficMeter::default());
+    let mut outbox = Outbox::spawn(writer, 4);
+    outbox.set_traffic(sent.clone());
+    outbox.send(&Message::AskDifference(3)).await.unwrap();
+    outbox.send(&Message::Difference(-2)).await.unwrap();
+    outbox.send(&Message::Difference(5)).await.unwrap();
+
+    let received = Arc::new(TrafficMeter::default());
+    let mut reader = MessageReader::new(client);
+    reader.set_traffic(received.clone());
+    for _ in 0..3 {
+        reader.receive().await.unwrap();
+    }
+    let sent = sent.stats();
+    let received = received.stats();
+    assert_eq!(sent.total.messages_sent, 3);
+    assert_eq!(sent.messages["Difference"].messages_sent, 2);
+    assert_eq!(received.total.bytes_received, sent.total.bytes_sent);
+    assert_eq!(received.messages["AskDifference"].messages_received, 1);
+    assert_eq!(
+        Message::kind_name(Message::GetStatus.kind()),
+        Some("GetStatus")
+    );
+    assert_eq!(Message::kind_name(KNOWN_KINDS), None);
+}
+
+#[test]
+fn throttles_drop_what_is_over_the_rate() {
+    let throttle = Throttle::new(1000);
+    assert!(throttle.try_take(600));
+    assert!(!throttle.try_take(600));
+    assert!(throttle.try_take(400));
+}
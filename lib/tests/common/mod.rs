@@ -0,0 +1,93 @@
+//! Helpers the integration tests share, each test file pulls them in with `mod common;`
+#![allow(dead_code)]
+
+use btclib::chaingen::ChainGen;
+use btclib::crypto::PrivateKey;
+use btclib::sha256::Hash;
+use btclib::types::{
+    Amount, Block, BlockBuilder, Blockchain, Transaction, TransactionBuilder, TransactionOutput,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashSet;
+
+/// a regtest mined chain of `height` blocks a second apart, coinbases only. Chains with another
+/// `seed` share no blocks
+pub fn mined_chain(height: u64, seed: u64) -> Blockchain {
+    let mut blockchain = ChainGen {
+        blocks: height,
+        transactions_per_block: 0,
+        keys: 1,
+        seed,
+        block_time: Some(1),
+        ..ChainGen::default()
+    }
+    .generate()
+    .unwrap()
+    .blockchain;
+    blockchain.rebuild_utxos();
+    blockchain
+}
+
+/// a chain whose genesis pays `key` `N` times, the reward split evenly with the last output
+/// taking what doesn't divide
+pub fn chain_paying<const N: usize>(key: &PrivateKey) -> (Blockchain, [TransactionOutput; N]) {
+    let mut blockchain = Blockchain::regtest();
+    let reward = blockchain.calculate_block_reward().to_sat();
+    let share = reward / N as u64;
+    let mut genesis = BlockBuilder::new().timestamp(Utc::now() - Duration::minutes(1));
+    for i in 0..N {
+        let value = if i == N - 1 {
+            reward - share * (N as u64 - 1)
+        } else {
+            share
+        };
+        genesis = genesis.coinbase_to(key.public_key(), Amount::from_sat(value));
+    }
+    let genesis = genesis.finalize().unwrap();
+    let outputs = genesis.transactions[0].outputs.clone().try_into().unwrap();
+    blockchain.add_block(genesis).unwrap();
+    (blockchain, outputs)
+}
+
+/// spend `output` back to `key`, leaving `fee`
+pub fn spend(output: &TransactionOutput, key: &PrivateKey, fee: Amount) -> Transaction {
+    TransactionBuilder::new()
+        .spend(output, key)
+        .pay_to(key.public_key(), output.value.checked_sub(fee).unwrap())
+        .finalize()
+        .unwrap()
+}
+
+/// `builder` finished on top of `blockchain` and mined at the regtest target
+pub fn mine(builder: BlockBuilder, blockchain: &Blockchain) -> Block {
+    let mut block = builder.finalize_with_fees(blockchain.utxos()).unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    block
+}
+
+/// a block on the tip of `blockchain` mining `transactions` and paying `key` the reward and
+/// their fees, at `timestamp` so forks get their own hashes
+pub fn block(
+    blockchain: &Blockchain,
+    key: &PrivateKey,
+    timestamp: DateTime<Utc>,
+    transactions: Vec<Transaction>,
+) -> Block {
+    mine(
+        BlockBuilder::on_top_of(blockchain)
+            .timestamp(timestamp)
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .add_txs(transactions),
+        blockchain,
+    )
+}
+
+/// the hashes of `utxos`, to compare UTXO sets whatever order they come in
+pub fn unspent<'a>(
+    utxos: impl IntoIterator<Item = &'a (TransactionOutput, bool)>,
+) -> HashSet<Hash> {
+    utxos.into_iter().map(|(output, _)| output.hash()).collect()
+}
@@ -0,0 +1,128 @@
+use btclib::{
+    chain_params::ChainParams,
+    consensus::{Consensus, ProofOfStake},
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, Block, BlockBuilder, Blockchain, TransactionBuilder},
+};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::sync::Arc;
+
+fn stake_chain() -> Blockchain {
+    let params = ChainParams {
+        consensus: Consensus::ProofOfStake,
+        ..ChainParams::default()
+    };
+    Blockchain::with_params(Arc::new(params))
+}
+
+/// an unsealed block on the tip paying `key`
+fn block(blockchain: &Blockchain, key: &PrivateKey, timestamp: DateTime<Utc>) -> Block {
+    BlockBuilder::on_top_of(blockchain)
+        .timestamp(timestamp)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap()
+}
+
+fn start() -> DateTime<Utc> {
+    Utc.timestamp_opt(1_735_689_600, 0).unwrap()
+}
+
+#[test]
+fn only_the_slot_leader_signs_blocks() {
+    let (alice, bob) = (PrivateKey::new_key(), PrivateKey::new_key());
+    let mut blockchain = stake_chain();
+    // nobody has a stake before the first block
+    blockchain
+        .add_block(block(&blockchain, &alice, start()))
+        .unwrap();
+    let next = start() + Duration::seconds(10);
+    let slot = ProofOfStake::slot(blockchain.params(), next);
+    assert_eq!(
+        ProofOfStake::slot_leader(&blockchain, slot),
+        Some(alice.public_key())
+    );
+
+    let unsigned = block(&blockchain, &alice, next);
+    assert!(matches!(
+        blockchain.validate_block(unsigned.clone()),
+        Err(BtcError::InvalidBlockSignature(_))
+    ));
+    let mut by_bob = unsigned.clone();
+    ProofOfStake::sign(&mut by_bob, &bob);
+    assert!(matches!(
+        blockchain.validate_block(by_bob),
+        Err(BtcError::NotSlotLeader(s)) if s == slot
+    ));
+    // no work needed, the signature is the proof
+    let mut signed = unsigned;
+    ProofOfStake::sign(&mut signed, &alice);
+    assert!(!signed.header.hash().matches_target(btclib::MIN_TARGET));
+    blockchain.add_block(signed).unwrap();
+
+    let mut same_slot = block(&blockchain, &alice, next + Duration::seconds(1));
+    ProofOfStake::sign(&mut same_slot, &alice);
+    assert!(matches!(
+        blockchain.validate_block(same_slot),
+        Err(BtcError::SlotTaken(s)) if s == slot
+    ));
+    assert_eq!(blockchain.target(), btclib::MIN_TARGET);
+}
+
+#[test]
+fn leaders_are_drawn_by_stake() {
+    let (alice, bob) = (PrivateKey::new_key(), PrivateKey::new_key());
+    let mut blockchain = stake_chain();
+    let first = block(&blockchain, &alice, start());
+    let coinbase = first.transactions[0].outputs[0].clone();
+    blockchain.add_block(first).unwrap();
+
+    // alice keeps a quarter, bob gets the rest
+    let quarter = Amount::from_sat(coinbase.value.to_sat() / 4);
+    let payment = TransactionBuilder::new()
+        .spend(&coinbase, &alice)
+        .pay_to(
+            bob.public_key(),
+            coinbase.value.checked_sub(quarter).unwrap(),
+        )
+        .pay_to(alice.public_key(), quarter)
+        .finalize()
+        .unwrap();
+    // and the next reward too
+    let mut paying = BlockBuilder::on_top_of(&blockchain)
+        .timestamp(start() + Duration::seconds(10))
+        .coinbase_to(bob.public_key(), blockchain.calculate_block_reward())
+        .add_txs(vec![payment])
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    ProofOfStake::sign(&mut paying, &alice);
+    blockchain.add_block(paying).unwrap();
+
+    let stakes = ProofOfStake::stakes(&blockchain);
+    assert_eq!(stakes[&alice.public_key()], quarter);
+    let leaders: Vec<_> = (100..500)
+        .map(|slot| ProofOfStake::slot_leader(&blockchain, slot).unwrap())
+        .collect();
+    let led_by_alice = leaders
+        .iter()
+        .filter(|leader| **leader == alice.public_key())
+        .count();
+    // one in eight on average
+    assert!((10..150).contains(&led_by_alice), "{led_by_alice}");
+
+    let slot = ProofOfStake::next_slot(&blockchain, &alice.public_key(), start(), 1000).unwrap();
+    assert!(slot > ProofOfStake::slot(blockchain.params(), start()) + 1);
+    assert_eq!(
+        ProofOfStake::slot_leader(&blockchain, slot),
+        Some(alice.public_key())
+    );
+    let mut next = block(
+        &blockchain,
+        &alice,
+        ProofOfStake::slot_start(blockchain.params(), slot),
+    );
+    ProofOfStake::sign(&mut next, &alice);
+    blockchain.add_block(next).unwrap();
+}
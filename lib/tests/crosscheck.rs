@@ -0,0 +1,173 @@
+//! The steps hashes and merkle roots are built in, checked against the `sha2` crate and a merkle
+//! tree worked out another way, on random inputs. Needs the `test-vectors` feature
+
+use btclib::{
+    crypto::PrivateKey,
+    sha256::Hash,
+    test_vectors::steps,
+    types::{Amount, Transaction, TransactionOutput},
+    util::MerkleRoot,
+};
+
+use ciborium::Value;
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+fn sha2(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+fn cbor(value: &Value) -> Vec<u8> {
+    let mut bytes = vec![];
+    ciborium::into_writer(value, &mut bytes).unwrap();
+    bytes
+}
+
+/// a hash the way it's serialized, its number as 4 u64 limbs, least significant first
+fn limbs(digest: &[u8; 32]) -> Value {
+    Value::Array(
+        digest
+            .rchunks(8)
+            .map(|limb| Value::Integer(u64::from_be_bytes(limb.try_into().unwrap()).into()))
+            .collect(),
+    )
+}
+
+fn reference_parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    sha2(&cbor(&Value::Array(vec![limbs(left), limbs(right)])))
+}
+
+/// The root of the tree over `leaves`, worked out top down: the node `index` at `level` covers
+/// the leaves from `index << level`, one without a right child pairs its left child with itself
+fn reference_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    fn width(leaves: usize, level: u32) -> usize {
+        leaves.div_ceil(1 << level)
+    }
+    fn node(leaves: &[[u8; 32]], level: u32, index: usize) -> [u8; 32] {
+        if level == 0 {
+            return leaves[index];
+        }
+        let left = node(leaves, level - 1, 2 * index);
+        let right = if 2 * index + 1 < width(leaves.len(), level - 1) {
+            node(leaves, level - 1, 2 * index + 1)
+        } else {
+            left
+        };
+        reference_parent(&left, &right)
+    }
+    let mut levels = 0;
+    while width(leaves.len(), levels) > 1 {
+        levels += 1;
+    }
+    node(leaves, levels, 0)
+}
+
+fn random_transactions(count: usize) -> Vec<Transaction> {
+    let mut rng = rand::thread_rng();
+    let keys: Vec<PrivateKey> = (0..3).map(|_| PrivateKey::new_key()).collect();
+    (0..count)
+        .map(|_| {
+            let outputs = (0..rng.gen_range(1..4))
+                .map(|_| TransactionOutput {
+                    value: Amount::from_sat(rng.gen_range(0..btclib::MAX_MONEY)),
+                    unique_id: Uuid::new_v4(),
+                    pubkey: keys[rng.gen_range(0..keys.len())].public_key(),
+                })
+                .collect();
+            Transaction::new(vec![], outputs)
+        })
+        .collect()
+}
+
+#[test]
+fn digests_match_sha2() {
+    let mut rng = rand::thread_rng();
+    // every length up to past two blocks, for where the padding goes, then random ones
+    let random: Vec<usize> = (0..200).map(|_| rng.gen_range(0..4096)).collect();
+    let lengths = (0..=130).chain(random);
+    for len in lengths {
+        let mut data = vec![0; len];
+        rng.fill_bytes(&mut data);
+        let expected = sha2(&data);
+
+        let hex_digest = steps::hex_digest(&data);
+        assert_eq!(hex_digest, hex::encode(expected), "{len} bytes");
+        let hash = steps::from_hex_digest(&hex_digest);
+        assert_eq!(hash, Hash::of_bytes(&data));
+        assert_eq!(hash.to_be_bytes(), expected);
+        assert_eq!(hash.to_hex(), hex_digest);
+
+        let mut reversed = expected;
+        reversed.reverse();
+        assert_eq!(hash.as_bytes(), reversed);
+    }
+}
+
+#[test]
+fn hashes_are_sha2_of_the_cbor() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..200 {
+        let number: u64 = rng.r#gen();
+        let mut bytes = vec![0; rng.gen_range(0..100)];
+        rng.fill_bytes(&mut bytes);
+        let text: String = (0..rng.gen_range(0..40))
+            .map(|_| rng.gen_range('a'..='z'))
+            .collect();
+        let data = (number, text.clone(), bytes.clone());
+
+        let expected = cbor(&Value::Array(vec![
+            Value::Integer(number.into()),
+            Value::Text(text),
+            Value::Array(
+                bytes
+                    .into_iter()
+                    .map(|byte| Value::Integer(byte.into()))
+                    .collect(),
+            ),
+        ]));
+        assert_eq!(steps::preimage(&data), expected);
+        assert_eq!(Hash::hash(&data).to_be_bytes(), sha2(&expected));
+    }
+}
+
+#[test]
+fn merkle_parents_match_the_reference() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..200 {
+        let (left, right): ([u8; 32], [u8; 32]) = (rng.r#gen(), rng.r#gen());
+        let parent = steps::merkle_parent(Hash::from_be_bytes(left), Hash::from_be_bytes(right));
+        assert_eq!(parent.to_be_bytes(), reference_parent(&left, &right));
+    }
+}
+
+#[test]
+fn merkle_roots_match_the_reference() {
+    for count in (1..=17).chain([32, 33, 100]) {
+        let transactions = random_transactions(count);
+        let leaves = steps::merkle_leaves(&transactions);
+        for (leaf, transaction) in leaves.iter().zip(&transactions) {
+            assert_eq!(
+                leaf.to_be_bytes(),
+                sha2(&steps::preimage(transaction)),
+                "leaf of {count}"
+            );
+        }
+
+        let leaves: Vec<[u8; 32]> = leaves.iter().map(Hash::to_be_bytes).collect();
+        let expected = reference_root(&leaves);
+        let root = MerkleRoot::calculate(&transactions).unwrap();
+        assert_eq!(root.hash().to_be_bytes(), expected, "{count} transactions");
+
+        let layers = steps::merkle_layers(&transactions);
+        assert_eq!(layers.last().unwrap(), &vec![root.hash()]);
+        for pair in layers.windows(2) {
+            assert_eq!(pair[1].len(), pair[0].len().div_ceil(2));
+        }
+
+        for index in 0..count {
+            let proof = MerkleRoot::prove(&transactions, index).unwrap();
+            assert!(proof.verify(&transactions[index], &root));
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use btclib::crypto::{Algorithm, PrivateKey, keyfile};
+
+fn sealed(real: &PrivateKey, decoy: &PrivateKey) -> Vec<u8> {
+    let mut data = vec![];
+    real.save_encrypted_with_decoy(&mut data, "real", decoy, "duress")
+        .unwrap();
+    data
+}
+
+#[test]
+fn each_passphrase_opens_its_own_key() {
+    let real = PrivateKey::new_key();
+    let decoy = PrivateKey::generate(Algorithm::Ed25519);
+    let data = sealed(&real, &decoy);
+    assert!(keyfile::is_encrypted(&data));
+
+    let opened = PrivateKey::load_with_passphrase(data.as_slice(), "real").unwrap();
+    assert_eq!(opened.public_key(), real.public_key());
+    let opened = PrivateKey::load_with_passphrase(data.as_slice(), "duress").unwrap();
+    assert_eq!(opened.public_key(), decoy.public_key());
+    assert!(PrivateKey::load_with_passphrase(data.as_slice(), "wrong").is_err());
+    assert!(PrivateKey::load_with_passphrase(&data[..data.len() - 1], "real").is_err());
+}
+
+#[test]
+fn files_with_a_decoy_look_alike() {
+    let real = PrivateKey::new_key();
+    // keys of any scheme take up the same room
+    let schnorr = PrivateKey::generate(Algorithm::Schnorr);
+    let ed25519 = PrivateKey::generate(Algorithm::Ed25519);
+    assert_eq!(sealed(&real, &schnorr).len(), sealed(&ed25519, &real).len());
+
+    let mut same = vec![];
+    let err = real
+        .save_encrypted_with_decoy(&mut same, "same", &schnorr, "same")
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,99 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{Amount, BlockBuilder, DoubleSpend, DoubleSpendOutcome, EvictionReason},
+};
+
+use common::{chain_paying, spend};
+
+mod common;
+
+#[test]
+fn replacements_are_double_spends() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [coinbase]) = chain_paying(&key);
+    let mut evictions = blockchain.mempool().subscribe();
+
+    let payment = spend(&coinbase, &key, Amount::ONE_SAT);
+    let child = spend(&payment.outputs[0], &key, Amount::ONE_SAT);
+    let refund = spend(&coinbase, &key, Amount::from_sat(2));
+    blockchain.add_to_mempool(payment.clone()).unwrap();
+    blockchain.add_to_mempool(child.clone()).unwrap();
+    blockchain.add_to_mempool(refund.clone()).unwrap();
+
+    let eviction = evictions.try_recv().unwrap();
+    assert_eq!(eviction.transaction.hash(), payment.hash());
+    assert_eq!(
+        eviction.double_spend,
+        Some(DoubleSpend {
+            txid: payment.hash(),
+            conflicting: refund.hash(),
+            outputs: vec![coinbase.hash()],
+            outcome: DoubleSpendOutcome::Replaced,
+        })
+    );
+    // the child only lost its parent
+    let eviction = evictions.try_recv().unwrap();
+    assert_eq!(eviction.transaction.hash(), child.hash());
+    assert_eq!(eviction.double_spend, None);
+}
+
+#[test]
+fn mined_conflicts_are_double_spends() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [coinbase]) = chain_paying(&key);
+    let payment = spend(&coinbase, &key, Amount::ONE_SAT);
+    blockchain.add_to_mempool(payment.clone()).unwrap();
+    let mut evictions = blockchain.mempool().subscribe();
+
+    // mined without ever going through this mempool
+    let refund = spend(&coinbase, &key, Amount::from_sat(2));
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .add_txs(vec![refund.clone()])
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    blockchain.add_block(block).unwrap();
+
+    let eviction = evictions.try_recv().unwrap();
+    assert_eq!(eviction.reason, EvictionReason::Conflict);
+    let double_spend = eviction.double_spend.unwrap();
+    assert_eq!(double_spend.txid, payment.hash());
+    assert_eq!(double_spend.conflicting, refund.hash());
+    assert_eq!(double_spend.outcome, DoubleSpendOutcome::Confirmed);
+    assert!(blockchain.mempool().is_empty());
+}
+
+#[test]
+fn refused_transactions_name_what_they_double_spend() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [coinbase]) = chain_paying(&key);
+    let parent = spend(&coinbase, &key, Amount::ZERO);
+    let payment = spend(&parent.outputs[0], &key, Amount::ONE_SAT);
+    blockchain.add_to_mempool(parent.clone()).unwrap();
+    blockchain.add_to_mempool(payment.clone()).unwrap();
+
+    // unconfirmed outputs can't be taken over
+    let refund = spend(&parent.outputs[0], &key, Amount::from_sat(2));
+    assert!(blockchain.add_to_mempool(refund.clone()).is_err());
+    assert_eq!(
+        blockchain
+            .mempool()
+            .double_spends(&refund, DoubleSpendOutcome::Rejected),
+        vec![DoubleSpend {
+            txid: payment.hash(),
+            conflicting: refund.hash(),
+            outputs: vec![parent.outputs[0].hash()],
+            outcome: DoubleSpendOutcome::Rejected,
+        }]
+    );
+    // a transaction doesn't double spend itself
+    assert!(
+        blockchain
+            .mempool()
+            .double_spends(&payment, DoubleSpendOutcome::Rejected)
+            .is_empty()
+    );
+}
@@ -0,0 +1,66 @@
+use btclib::finality::{ChainTip, Checkpointed, Confirmations, Finality, FinalityPolicy};
+
+use serde::Deserialize;
+
+#[test]
+fn blocks_are_final_with_enough_confirmations() {
+    let tip = ChainTip {
+        height: 10,
+        checkpoint: None,
+    };
+    let policy = Confirmations(6);
+    assert_eq!(policy.finalized_height(&tip), Some(4));
+    assert!(policy.is_final(4, &tip));
+    assert!(!policy.is_final(5, &tip));
+    assert_eq!(policy.finalized_height(&ChainTip::default()), None);
+    // the block itself is always needed
+    assert_eq!(Confirmations(0).finalized_height(&tip), Some(9));
+    assert_eq!(Confirmations::default(), Confirmations(6));
+}
+
+#[test]
+fn checkpoints_finalize_at_once() {
+    let policy = Checkpointed(Confirmations(6));
+    let tip = ChainTip {
+        height: 10,
+        checkpoint: Some(8),
+    };
+    assert!(policy.is_final(8, &tip));
+    assert!(!policy.is_final(9, &tip));
+    assert!(!Confirmations(6).is_final(8, &tip));
+    // an old checkpoint doesn't hold back what's deep enough anyway
+    let tip = ChainTip {
+        height: 10,
+        checkpoint: Some(1),
+    };
+    assert_eq!(policy.finalized_height(&tip), Some(4));
+}
+
+#[test]
+fn finality_is_configurable() {
+    #[derive(Deserialize)]
+    struct Config {
+        #[serde(default)]
+        finality: Finality,
+    }
+    let parse = |toml| toml::from_str::<Config>(toml).unwrap().finality;
+    assert_eq!(parse(""), Finality::Confirmations(6));
+    assert_eq!(
+        parse("finality = { checkpoints = 2 }"),
+        Finality::Checkpoints(2)
+    );
+    let tip = ChainTip {
+        height: 10,
+        checkpoint: Some(9),
+    };
+    assert_eq!(
+        parse("finality = { confirmations = 3 }")
+            .policy()
+            .finalized_height(&tip),
+        Some(7)
+    );
+    assert_eq!(
+        Finality::Checkpoints(3).policy().finalized_height(&tip),
+        Some(9)
+    );
+}
@@ -0,0 +1,174 @@
+use btclib::crypto::Signature;
+use btclib::crypto::frost::{
+    DkgCommitment, DkgSecret, DkgShare, FrostSession, KeyShare, SigningCommitment, dkg_round1,
+    frost_commit,
+};
+use btclib::error::BtcError;
+use btclib::sha256::Hash;
+use ciborium::Value;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+fn round1(threshold: u16, participants: u16) -> (Vec<DkgSecret>, Vec<DkgCommitment>) {
+    (1..=participants)
+        .map(|id| dkg_round1(id, threshold, participants).unwrap())
+        .unzip()
+}
+
+fn round2(secrets: &[DkgSecret], commitments: &[DkgCommitment]) -> Vec<DkgShare> {
+    secrets
+        .iter()
+        .flat_map(|secret| secret.round2(commitments).unwrap())
+        .collect()
+}
+
+/// everyone's key share, by id
+fn dkg(threshold: u16, participants: u16) -> Vec<KeyShare> {
+    let (secrets, commitments) = round1(threshold, participants);
+    let shares = round2(&secrets, &commitments);
+    secrets
+        .into_iter()
+        .map(|secret| secret.finish(&commitments, &shares).unwrap())
+        .collect()
+}
+
+fn sign(shares: &[KeyShare], signers: &[u16], output_hash: &Hash) -> Signature {
+    let share = |id: &u16| &shares[*id as usize - 1];
+    let (nonces, commitments): (Vec<_>, Vec<_>) =
+        signers.iter().map(|id| frost_commit(share(id))).unzip();
+    let session = FrostSession::new(shares[0].group().clone(), commitments, output_hash).unwrap();
+    let signature_shares: Vec<_> = signers
+        .iter()
+        .zip(nonces)
+        .map(|(id, nonces)| session.sign(share(id), nonces).unwrap())
+        .collect();
+    assert!(signature_shares.iter().all(|s| session.verify_share(s)));
+    session.aggregate(&signature_shares).unwrap()
+}
+
+#[test]
+fn any_threshold_of_signers_make_a_valid_signature() {
+    let output_hash = Hash::of_bytes(b"treasury payout");
+    for (threshold, participants) in [(1, 1), (2, 3), (3, 5)] {
+        let shares = dkg(threshold, participants);
+        let key = shares[0].group().public_key();
+        assert!(shares.iter().all(|s| s.group().public_key() == key));
+
+        let first: Vec<u16> = (1..=threshold).collect();
+        let last: Vec<u16> = (participants - threshold + 1..=participants).collect();
+        for signers in [first, last] {
+            let signature = sign(&shares, &signers, &output_hash);
+            assert!(signature.verify(&output_hash, &key));
+            assert!(!signature.verify(&Hash::of_bytes(b"something else"), &key));
+        }
+    }
+}
+
+#[test]
+fn fewer_than_threshold_signers_are_refused() {
+    let shares = dkg(2, 3);
+    let (_, commitment) = frost_commit(&shares[0]);
+    let output_hash = Hash::of_bytes(b"treasury payout");
+    // the same signer twice still counts once
+    assert!(
+        FrostSession::new(
+            shares[0].group().clone(),
+            vec![commitment, commitment],
+            &output_hash
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn signature_shares_for_another_message_are_refused() {
+    let shares = dkg(2, 3);
+    let signed = |output_hash: &Hash| {
+        let (nonces, commitments): (Vec<_>, Vec<_>) = shares[..2].iter().map(frost_commit).unzip();
+        let session =
+            FrostSession::new(shares[0].group().clone(), commitments, output_hash).unwrap();
+        let signature_shares: Vec<_> = shares[..2]
+            .iter()
+            .zip(nonces)
+            .map(|(share, nonces)| session.sign(share, nonces).unwrap())
+            .collect();
+        (session, signature_shares)
+    };
+    let (session, mut signature_shares) = signed(&Hash::of_bytes(b"treasury payout"));
+    let (_, other) = signed(&Hash::of_bytes(b"something else"));
+    signature_shares[1] = other[1];
+    assert!(!session.verify_share(&signature_shares[1]));
+    assert!(session.aggregate(&signature_shares).is_err());
+}
+
+#[test]
+fn bad_dkg_shares_are_refused() {
+    let (mut secrets, commitments) = round1(2, 3);
+    let mut shares = round2(&secrets, &commitments);
+    // participant 3 is handed the share 1 made for 2
+    let mut forged = shares
+        .iter()
+        .find(|s| s.from == 1 && s.to == 2)
+        .unwrap()
+        .clone();
+    forged.to = 3;
+    let meant_for_3 = shares
+        .iter()
+        .position(|s| s.from == 1 && s.to == 3)
+        .unwrap();
+    shares[meant_for_3] = forged;
+
+    let third = secrets.pop().unwrap();
+    assert!(matches!(
+        third.finish(&commitments, &shares),
+        Err(BtcError::InvalidKeyShare)
+    ));
+    let second = secrets.pop().unwrap();
+    assert!(second.finish(&commitments, &shares).is_ok());
+}
+
+#[test]
+fn bad_proofs_of_knowledge_are_refused() {
+    let (secrets, mut commitments) = round1(2, 3);
+    // each proof is bound to the id it was made for
+    commitments[0].id = 2;
+    commitments[1].id = 1;
+    assert!(matches!(
+        secrets[2].round2(&commitments),
+        Err(BtcError::InvalidKeyShare)
+    ));
+}
+
+#[test]
+fn only_compressed_points_other_than_the_identity_decode() {
+    let shares = dkg(1, 1);
+    let (_, commitment) = frost_commit(&shares[0]);
+    let mut encoded = vec![];
+    ciborium::into_writer(&commitment, &mut encoded).unwrap();
+    let document: Value = ciborium::from_reader(encoded.as_slice()).unwrap();
+
+    let with_hiding = |bytes: Vec<u8>| {
+        let mut document = document.clone();
+        let hiding = document
+            .as_map_mut()
+            .unwrap()
+            .iter_mut()
+            .find(|(k, _)| k.as_text() == Some("hiding"))
+            .map(|(_, v)| v)
+            .unwrap();
+        let compressed = hiding.as_bytes().unwrap().clone();
+        *hiding = Value::Bytes(bytes);
+        (compressed, document.deserialized::<SigningCommitment>())
+    };
+
+    let (compressed, decoded) = with_hiding(vec![]);
+    assert!(decoded.is_err());
+    let (_, decoded) = with_hiding(compressed.clone());
+    assert_eq!(decoded.unwrap(), commitment);
+    let (_, decoded) = with_hiding(vec![0]);
+    assert!(decoded.is_err());
+    let uncompressed = k256::PublicKey::from_sec1_bytes(&compressed)
+        .unwrap()
+        .to_encoded_point(false);
+    let (_, decoded) = with_hiding(uncompressed.as_bytes().to_vec());
+    assert!(decoded.is_err());
+}
@@ -0,0 +1,229 @@
+//! Byte for byte encodings of blocks, transactions, headers and messages, checked in under
+//! `tests/golden/`. A change to how any of them serializes changes block and transaction hashes,
+//! forking the chain, or leaves old data files and peers unreadable, so it has to show up here
+//! first. Everything below is built from fixed keys, ids and times, and signing is deterministic,
+//! so the same values always encode the same. When a change is meant to, regenerate the files with
+//! `UPDATE_GOLDEN=1 cargo test -p btclib --test golden` and check in the difference.
+
+use btclib::{
+    MIN_TARGET,
+    crypto::{Algorithm, PrivateKey, Signature},
+    network::{Capabilities, CompactHeader, Encoding, ErrorCode, Message, PROTOCOL_VERSION},
+    sha256::Hash,
+    types::{
+        Amount, Block, BlockHeader, MempoolPolicy, Transaction, TransactionInput, TransactionOutput,
+    },
+    util::{MerkleRoot, Saveable},
+};
+use chrono::DateTime;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+fn golden(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+/// Assert `bytes` are what's in the golden file `name`, or overwrite it with them when
+/// regenerating
+fn check(name: &str, bytes: &[u8]) {
+    let path = golden(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, bytes).unwrap();
+        return;
+    }
+    let expected =
+        std::fs::read(&path).unwrap_or_else(|e| panic!("can't read {}: {e}", path.display()));
+    assert!(
+        bytes == expected,
+        "{name} no longer encodes the same:\n  got      {}\n  expected {}",
+        hex::encode(bytes),
+        hex::encode(&expected)
+    );
+}
+
+fn read(name: &str) -> Vec<u8> {
+    std::fs::read(golden(name)).unwrap()
+}
+
+fn key(algorithm: Algorithm, seed: u8) -> PrivateKey {
+    PrivateKey::from_bytes(algorithm, &[seed; 32]).unwrap()
+}
+
+fn output(value: u64, id: u128, key: &PrivateKey) -> TransactionOutput {
+    TransactionOutput {
+        value: Amount::from_sat(value),
+        unique_id: Uuid::from_u128(id),
+        pubkey: key.public_key(),
+    }
+}
+
+fn coinbase() -> Transaction {
+    let miner = key(Algorithm::Secp256k1, 1);
+    Transaction::new(vec![], vec![output(50 * 100_000_000, 1, &miner)])
+}
+
+/// spends one output for each signature algorithm, with a memo
+fn payment() -> Transaction {
+    let memo = "invoice 42";
+    let signing_hash = |prev: &Hash| {
+        Transaction::new(vec![], vec![])
+            .with_memo(memo)
+            .signature_hash(prev)
+    };
+    let inputs = [Algorithm::Secp256k1, Algorithm::Ed25519, Algorithm::Schnorr]
+        .into_iter()
+        .enumerate()
+        .map(|(i, algorithm)| {
+            let prev = Hash::hash(&i);
+            TransactionInput {
+                prev_transaction_output_hash: prev,
+                signature: Signature::sign_output(&signing_hash(&prev), &key(algorithm, 2)),
+            }
+        })
+        .collect();
+    let outputs = vec![
+        output(100_000_000, 2, &key(Algorithm::Ed25519, 3)),
+        output(49_999_000, 3, &key(Algorithm::Schnorr, 4)),
+    ];
+    Transaction::new(inputs, outputs).with_memo(memo)
+}
+
+fn block() -> Block {
+    let transactions = vec![coinbase(), payment()];
+    let header = BlockHeader::new(
+        DateTime::from_timestamp(1_700_000_000, 123_456_789).unwrap(),
+        42,
+        Hash::hash(&"previous block"),
+        MerkleRoot::calculate(&transactions).unwrap(),
+        MIN_TARGET,
+    );
+    Block::new(header, transactions)
+}
+
+fn messages() -> Vec<(&'static str, Message)> {
+    let block = block();
+    vec![
+        (
+            "hello",
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+                encodings: vec![Encoding::Cbor],
+                time: Some(DateTime::from_timestamp(1_700_000_000, 0).unwrap()),
+                nonce: None,
+                capabilities: Capabilities::FILTERS | Capabilities::ZSTD,
+            },
+        ),
+        (
+            "welcome",
+            Message::Welcome {
+                version: PROTOCOL_VERSION,
+                encoding: Encoding::Cbor,
+                policy: MempoolPolicy::default(),
+                time: None,
+                identity: None,
+                capabilities: Capabilities::LZ4,
+            },
+        ),
+        (
+            "fetch_utxos",
+            Message::FetchUTXOs(key(Algorithm::Schnorr, 4).public_key()),
+        ),
+        (
+            "utxos",
+            Message::UTXOs(
+                block.transactions[1]
+                    .outputs
+                    .iter()
+                    .map(|output| (true, output.clone()))
+                    .collect(),
+            ),
+        ),
+        ("new_transaction", Message::NewTransaction(payment())),
+        (
+            "headers",
+            Message::Headers(vec![CompactHeader::new(&block)]),
+        ),
+        ("new_block", Message::NewBlock(block)),
+        (
+            "error",
+            Message::Error {
+                code: ErrorCode::DoubleSpend,
+                reason: "spent twice".to_string(),
+            },
+        ),
+    ]
+}
+
+fn saved<T: Saveable>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![];
+    value.save(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn transactions() {
+    for (name, transaction) in [("coinbase.cbor", coinbase()), ("payment.cbor", payment())] {
+        let bytes = saved(&transaction);
+        check(name, &bytes);
+        let loaded = Transaction::load(read(name).as_slice()).unwrap();
+        assert_eq!(saved(&loaded), read(name), "{name} doesn't round trip");
+        assert_eq!(loaded.hash(), transaction.hash());
+    }
+}
+
+#[test]
+fn blocks() {
+    let block = block();
+    check("block.cbor", &saved(&block));
+    let loaded = Block::load(read("block.cbor").as_slice()).unwrap();
+    assert_eq!(saved(&loaded), read("block.cbor"));
+    assert_eq!(loaded.hash(), block.hash());
+}
+
+#[test]
+fn headers() {
+    let header = block().header;
+    let mut bytes = vec![];
+    ciborium::into_writer(&header, &mut bytes).unwrap();
+    check("header.cbor", &bytes);
+    check("header.compact", &header.to_compact());
+
+    let loaded: BlockHeader = ciborium::from_reader(read("header.cbor").as_slice()).unwrap();
+    let mut again = vec![];
+    ciborium::into_writer(&loaded, &mut again).unwrap();
+    assert_eq!(again, read("header.cbor"));
+    let compact = BlockHeader::from_compact(&read("header.compact")).unwrap();
+    assert_eq!(compact.to_compact().as_slice(), read("header.compact"));
+}
+
+/// the whole frame, length and envelope included, as a peer sends it
+#[test]
+fn message_frames() {
+    for (name, message) in messages() {
+        let name = format!("{name}.frame");
+        check(&name, &message.to_frame().unwrap());
+        let frame = read(&name);
+        let received = Message::receive(&mut frame.as_slice()).unwrap();
+        assert_eq!(
+            received.to_frame().unwrap().as_ref(),
+            frame,
+            "{name} doesn't round trip"
+        );
+    }
+}
+
+/// the hashes blocks are chained by and transactions are spent by
+#[test]
+fn hashes() {
+    let block = block();
+    let hashes = format!(
+        "block {}\nheader {}\ncoinbase {}\npayment {}\n",
+        block.hash(),
+        block.header.hash(),
+        block.transactions[0].hash(),
+        block.transactions[1].hash()
+    );
+    check("hashes.txt", hashes.as_bytes());
+}
@@ -0,0 +1,89 @@
+use btclib::{
+    chaingen::{ChainGen, Corruption},
+    error::BtcError,
+    network::CompactHeader,
+    sha256::Hash,
+    types::{BlockHeader, COMPACT_HEADER_SIZE},
+};
+
+#[test]
+fn compact_headers_keep_their_hash() {
+    let chain = ChainGen {
+        blocks: 10,
+        ..ChainGen::default()
+    }
+    .generate()
+    .unwrap();
+    for block in chain.blockchain.blocks() {
+        let compact = CompactHeader::new(block);
+        assert_eq!(compact.header.len(), COMPACT_HEADER_SIZE);
+        assert_eq!(compact.hash, block.hash());
+        assert_eq!(compact.header().unwrap().hash(), block.header.hash());
+    }
+
+    let bytes = chain
+        .blockchain
+        .blocks()
+        .next()
+        .unwrap()
+        .header
+        .to_compact();
+    assert!(matches!(
+        BlockHeader::from_compact(&bytes[1..]),
+        Err(BtcError::InvalidBlockHeader)
+    ));
+    let mut bad_nanos = bytes;
+    bad_nanos[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(BlockHeader::from_compact(&bad_nanos).is_err());
+}
+
+#[test]
+fn header_chains_verify_without_transactions() {
+    let chain = ChainGen {
+        blocks: 12,
+        invalid: (1..12).collect(),
+        ..ChainGen::default()
+    }
+    .generate()
+    .unwrap();
+
+    let headers: Vec<_> = chain.blockchain.blocks().map(CompactHeader::new).collect();
+    let mut prev: Option<(Hash, BlockHeader)> = None;
+    for compact in &headers {
+        let header = compact.header().unwrap();
+        let prev_hash = prev.as_ref().map_or(Hash::zero(), |(hash, _)| *hash);
+        header
+            .verify_after(prev_hash, prev.as_ref().map(|(_, header)| header))
+            .unwrap();
+        prev = Some((compact.hash, header));
+    }
+
+    // out of order
+    let second = headers[2].header().unwrap();
+    assert!(matches!(
+        second.verify_after(headers[0].hash, None),
+        Err(BtcError::PrevHashMismatch { .. })
+    ));
+
+    for invalid in &chain.invalid {
+        let parent = &headers[invalid.height as usize - 1];
+        let parent_header = parent.header().unwrap();
+        let result = invalid
+            .block
+            .header
+            .verify_after(parent.hash, Some(&parent_header));
+        match invalid.corruption {
+            Corruption::WrongParent => {
+                assert!(matches!(result, Err(BtcError::PrevHashMismatch { .. })))
+            }
+            Corruption::InsufficientWork => {
+                assert!(matches!(result, Err(BtcError::InsufficientWork(_))))
+            }
+            Corruption::StaleTimestamp => {
+                assert!(matches!(result, Err(BtcError::TimestampTooOld { .. })))
+            }
+            // only the transactions are off, which headers can't tell
+            _ => assert!(result.is_ok()),
+        }
+    }
+}
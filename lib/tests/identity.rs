@@ -0,0 +1,45 @@
+use btclib::crypto::{Algorithm, PrivateKey};
+use btclib::error::BtcError;
+use btclib::network::{NodeIdentity, check_identity, fingerprint, new_nonce};
+
+#[test]
+fn identities_only_hold_for_their_nonce() {
+    let key = PrivateKey::new_key();
+    let nonce = new_nonce();
+    let identity = NodeIdentity::prove(&key, &nonce);
+    assert!(identity.verify(&nonce));
+    assert!(!identity.verify(&new_nonce()));
+
+    // nor can a signed message be passed off as one
+    let forged = NodeIdentity {
+        key: key.public_key(),
+        signature: key.sign_message(&nonce),
+    };
+    assert!(!forged.verify(&nonce));
+}
+
+#[test]
+fn pinned_identities_are_enforced() {
+    let node = PrivateKey::generate(Algorithm::Ed25519);
+    let impostor = PrivateKey::new_key();
+    let nonce = new_nonce();
+    let pinned = vec![fingerprint(&node.public_key()).to_uppercase()];
+
+    let honest = NodeIdentity::prove(&node, &nonce);
+    assert!(check_identity(Some(&honest), &nonce, &pinned).is_ok());
+    let other = NodeIdentity::prove(&impostor, &nonce);
+    assert!(check_identity(Some(&other), &nonce, &[]).is_ok());
+    assert!(matches!(
+        check_identity(Some(&other), &nonce, &pinned),
+        Err(BtcError::UntrustedNode(_))
+    ));
+    // claiming the pinned key without being able to sign for it
+    let claimed = NodeIdentity {
+        key: node.public_key(),
+        signature: other.signature,
+    };
+    assert!(check_identity(Some(&claimed), &nonce, &pinned).is_err());
+    // older nodes say nothing, which only goes when nothing is pinned
+    assert!(check_identity(None, &nonce, &[]).is_ok());
+    assert!(check_identity(None, &nonce, &pinned).is_err());
+}
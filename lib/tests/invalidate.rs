@@ -0,0 +1,127 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    sha256::Hash,
+    types::{Amount, Block, Blockchain, EvictionReason, Transaction},
+};
+
+use chrono::{Duration, Utc};
+use common::{block, spend};
+
+mod common;
+
+/// Three blocks, the second mining `payment` of the genesis coinbase
+fn chain(key: &PrivateKey) -> (Blockchain, Vec<Block>, Transaction) {
+    let start = Utc::now() - Duration::hours(1);
+    let mut blockchain = Blockchain::regtest();
+    let genesis = block(&blockchain, key, start, vec![]);
+    blockchain.add_block(genesis.clone()).unwrap();
+    let payment = spend(&genesis.transactions[0].outputs[0], key, Amount::ZERO);
+    let first = block(
+        &blockchain,
+        key,
+        start + Duration::minutes(1),
+        vec![payment.clone()],
+    );
+    blockchain.add_block(first.clone()).unwrap();
+    let second = block(&blockchain, key, start + Duration::minutes(2), vec![]);
+    blockchain.add_block(second.clone()).unwrap();
+    (blockchain, vec![genesis, first, second], payment)
+}
+
+#[test]
+fn invalidating_rolls_the_chain_back() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, blocks, payment) = chain(&key);
+    blockchain.enable_txindex();
+    // spends an output only the last block has
+    let waiting = spend(&blocks[2].transactions[0].outputs[0], &key, Amount::ZERO);
+    blockchain.add_to_mempool(waiting.clone()).unwrap();
+    let mut evictions = blockchain.mempool().subscribe();
+
+    assert_eq!(blockchain.invalidate_block(&blocks[1].hash()).unwrap(), 2);
+    assert_eq!(blockchain.block_height(), 1);
+    assert_eq!(blockchain.tip_hash(), blocks[0].hash());
+    assert!(blockchain.is_invalidated(&blocks[1].hash()));
+    assert!(!blockchain.is_invalidated(&blocks[2].hash()));
+    assert_eq!(blockchain.disconnected_blocks().count(), 2);
+    blockchain.total_supply().unwrap();
+    assert!(blockchain.find_transaction(&payment.hash()).is_none());
+
+    // the payment is waiting again, spending an output of the genesis
+    assert!(blockchain.mempool().contains(&payment.hash()));
+    assert!(blockchain.utxos()[&blocks[0].transactions[0].outputs[0].hash()].1);
+    let eviction = evictions.try_recv().unwrap();
+    assert_eq!(eviction.transaction.hash(), waiting.hash());
+    assert_eq!(eviction.reason, EvictionReason::Reorg);
+    assert_eq!(blockchain.mempool().len(), 1);
+
+    assert!(matches!(
+        blockchain.add_block(blocks[1].clone()),
+        Err(BtcError::InvalidatedBlock(hash)) if hash == blocks[1].hash()
+    ));
+}
+
+#[test]
+fn reconsidering_puts_the_blocks_back() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, blocks, payment) = chain(&key);
+    blockchain.invalidate_block(&blocks[1].hash()).unwrap();
+
+    assert_eq!(blockchain.reconsider_block(&blocks[1].hash()).unwrap(), 2);
+    assert_eq!(blockchain.block_height(), 3);
+    assert_eq!(blockchain.tip_hash(), blocks[2].hash());
+    assert!(!blockchain.is_invalidated(&blocks[1].hash()));
+    assert_eq!(blockchain.disconnected_blocks().count(), 0);
+    assert!(blockchain.mempool().is_empty());
+    assert!(blockchain.find_transaction(&payment.hash()).is_some());
+    blockchain.total_supply().unwrap();
+}
+
+#[test]
+fn only_longer_forks_come_back() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, blocks, payment) = chain(&key);
+    blockchain.invalidate_block(&blocks[1].hash()).unwrap();
+    // the fork mines the payment too
+    let fork = block(&blockchain, &key, Utc::now(), vec![payment]);
+    blockchain.add_block(fork.clone()).unwrap();
+    assert!(blockchain.mempool().is_empty());
+
+    // two blocks beat one
+    assert_eq!(blockchain.reconsider_block(&blocks[1].hash()).unwrap(), 2);
+    assert_eq!(blockchain.tip_hash(), blocks[2].hash());
+    assert_eq!(
+        blockchain
+            .disconnected_blocks()
+            .map(Block::hash)
+            .collect::<Vec<_>>(),
+        vec![fork.hash()]
+    );
+
+    blockchain.invalidate_block(&blocks[1].hash()).unwrap();
+    assert_eq!(blockchain.tip_hash(), fork.hash());
+    let longer = block(&blockchain, &key, Utc::now() + Duration::seconds(1), vec![]);
+    blockchain.add_block(longer.clone()).unwrap();
+    // as long as the chain is not enough
+    assert_eq!(blockchain.reconsider_block(&blocks[2].hash()).unwrap(), 0);
+    assert!(!blockchain.is_invalidated(&blocks[1].hash()));
+    assert_eq!(blockchain.tip_hash(), longer.hash());
+}
+
+#[test]
+fn unknown_blocks_are_refused() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, _, payment) = chain(&key);
+    for hash in [payment.hash(), Hash::zero()] {
+        assert!(matches!(
+            blockchain.invalidate_block(&hash),
+            Err(BtcError::UnknownBlock(_))
+        ));
+        assert!(matches!(
+            blockchain.reconsider_block(&hash),
+            Err(BtcError::UnknownBlock(_))
+        ));
+    }
+    assert_eq!(blockchain.block_height(), 3);
+}
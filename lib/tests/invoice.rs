@@ -0,0 +1,96 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, BlockBuilder, Blockchain, Invoice, TransactionBuilder},
+    util::Saveable,
+};
+
+use chrono::{Duration, Utc};
+
+fn invoice(key: &PrivateKey) -> Invoice {
+    Invoice::new(key, Amount::ONE_BTC, "two coffees", Duration::hours(1))
+}
+
+#[test]
+fn only_untouched_invoices_verify() {
+    let merchant = PrivateKey::new_key();
+    let invoice = invoice(&merchant);
+    assert!(invoice.verify());
+    assert!(!invoice.is_expired(Utc::now()));
+    assert!(invoice.is_expired(Utc::now() + Duration::hours(2)));
+
+    let mut more = invoice.clone();
+    more.amount = Amount::from_sat(2);
+    assert!(!more.verify());
+    let mut elsewhere = invoice.clone();
+    elsewhere.pubkey = PrivateKey::new_key().public_key();
+    assert!(!elsewhere.verify());
+    let mut longer = invoice.clone();
+    longer.expires += Duration::days(1);
+    assert!(!longer.verify());
+}
+
+#[test]
+fn invoices_travel_as_uris_and_files() {
+    let invoice = invoice(&PrivateKey::new_key());
+    let uri = invoice.to_uri();
+    assert!(uri.starts_with(btclib::types::INVOICE_URI_SCHEME));
+    for uri in [uri.clone(), uri.to_uppercase()] {
+        let parsed = Invoice::from_uri(&uri).unwrap();
+        assert!(parsed.verify());
+        assert_eq!((parsed.id, parsed.amount), (invoice.id, invoice.amount));
+    }
+    assert!(matches!(
+        Invoice::from_uri("btcrs-invoice:zz"),
+        Err(BtcError::InvalidInvoice(_))
+    ));
+    assert!(matches!(
+        Invoice::from_uri(&uri.replacen("btcrs", "bitcoin", 1)),
+        Err(BtcError::InvalidInvoice(_))
+    ));
+
+    let mut bytes = vec![];
+    invoice.save(&mut bytes).unwrap();
+    let loaded = Invoice::load(bytes.as_slice()).unwrap();
+    assert!(loaded.verify());
+    assert_eq!(loaded.to_uri(), uri);
+}
+
+#[test]
+fn payments_name_their_invoice() {
+    let payer = PrivateKey::new_key();
+    let merchant = PrivateKey::new_key();
+    let invoice = invoice(&merchant);
+
+    let mut blockchain = Blockchain::new();
+    let genesis = BlockBuilder::new()
+        .timestamp(Utc::now() - Duration::minutes(1))
+        .coinbase_to(payer.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    let coinbase = genesis.transactions[0].outputs[0].clone();
+    blockchain.add_block(genesis).unwrap();
+    blockchain.rebuild_utxos();
+
+    let payment = TransactionBuilder::new()
+        .spend(&coinbase, &payer)
+        .pay_invoice(&invoice)
+        .change_to(payer.public_key(), Amount::from_sat(1_000))
+        .finalize()
+        .unwrap();
+    let paid: Vec<bool> = payment
+        .outputs
+        .iter()
+        .map(|output| invoice.is_paid_by(output))
+        .collect();
+    assert_eq!(paid, [true, false]);
+    blockchain.add_to_mempool(payment).unwrap();
+
+    // the right id but too little, or to another key
+    let mut short = invoice.output();
+    short.value = Amount::from_sat(1);
+    assert!(!invoice.is_paid_by(&short));
+    let mut elsewhere = invoice.output();
+    elsewhere.pubkey = payer.public_key();
+    assert!(!invoice.is_paid_by(&elsewhere));
+}
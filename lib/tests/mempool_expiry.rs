@@ -0,0 +1,80 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{Amount, BlockBuilder, Blockchain, EvictionReason},
+};
+
+use chrono::{Duration, Utc};
+use common::spend;
+
+mod common;
+
+#[test]
+fn expired_transactions_are_evicted_with_their_children() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::new();
+    let genesis = BlockBuilder::new()
+        .timestamp(Utc::now() - Duration::minutes(1))
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    let coinbase = genesis.transactions[0].outputs[0].clone();
+    blockchain.add_block(genesis).unwrap();
+    let mut evictions = blockchain.mempool().subscribe();
+
+    let parent = spend(&coinbase, &key, Amount::ONE_SAT);
+    let child = spend(&parent.outputs[0], &key, Amount::ONE_SAT);
+    blockchain.add_to_mempool(parent.clone()).unwrap();
+    blockchain.add_to_mempool(child.clone()).unwrap();
+    assert!(blockchain.utxos()[&coinbase.hash()].1);
+
+    // nothing is old enough yet
+    assert_eq!(
+        blockchain.expire_mempool(Utc::now() - Duration::minutes(1)),
+        0
+    );
+    assert_eq!(blockchain.mempool().len(), 2);
+
+    assert_eq!(
+        blockchain.expire_mempool(Utc::now() + Duration::seconds(1)),
+        2
+    );
+    assert!(blockchain.mempool().is_empty());
+    assert!(!blockchain.utxos()[&coinbase.hash()].1);
+
+    let evicted: Vec<_> = std::iter::from_fn(|| evictions.try_recv().ok())
+        .map(|eviction| (eviction.transaction.hash(), eviction.reason))
+        .collect();
+    assert_eq!(
+        evicted,
+        vec![
+            (parent.hash(), EvictionReason::Expired),
+            (child.hash(), EvictionReason::Expired)
+        ]
+    );
+}
+
+#[test]
+fn replaced_transactions_are_announced() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::new();
+    let genesis = BlockBuilder::new()
+        .timestamp(Utc::now() - Duration::minutes(1))
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    let coinbase = genesis.transactions[0].outputs[0].clone();
+    blockchain.add_block(genesis).unwrap();
+    let mut evictions = blockchain.mempool().subscribe();
+
+    let first = spend(&coinbase, &key, Amount::ONE_SAT);
+    blockchain.add_to_mempool(first.clone()).unwrap();
+    blockchain
+        .add_to_mempool(spend(&coinbase, &key, Amount::from_sat(2)))
+        .unwrap();
+
+    let eviction = evictions.try_recv().unwrap();
+    assert_eq!(eviction.transaction.hash(), first.hash());
+    assert_eq!(eviction.reason, EvictionReason::Replaced);
+    assert_eq!(blockchain.mempool().len(), 1);
+    assert!(blockchain.utxos()[&coinbase.hash()].1);
+}
@@ -0,0 +1,208 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, BlockBuilder, Blockchain, MempoolPolicy, TransactionBuilder},
+};
+
+use common::{chain_paying, spend};
+
+mod common;
+
+#[test]
+fn child_pays_for_parent() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [first, second]) = chain_paying(&key);
+
+    let parent = spend(&first, &key, Amount::ZERO);
+    let child = spend(&parent.outputs[0], &key, Amount::from_sat(10_000));
+    let other = spend(&second, &key, Amount::from_sat(3_000));
+    blockchain.add_to_mempool(parent.clone()).unwrap();
+    blockchain.add_to_mempool(child.clone()).unwrap();
+    blockchain.add_to_mempool(other).unwrap();
+    assert_eq!(blockchain.mempool_ancestors(&child.hash()).len(), 1);
+    assert_eq!(blockchain.mempool_descendants(&parent.hash()).len(), 1);
+
+    // 5000 sats per transaction for the package beats the 3000 of the other one
+    let selected = blockchain.template_transactions(2);
+    let hashes: Vec<_> = selected.iter().map(|tx| tx.hash()).collect();
+    assert_eq!(hashes, vec![parent.hash(), child.hash()]);
+
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .add_txs(selected)
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.mempool().len(), 1);
+}
+
+#[test]
+fn the_generation_changes_with_the_mempool() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [first, _]) = chain_paying(&key);
+    let start = blockchain.mempool().generation();
+
+    let payment = spend(&first, &key, Amount::ZERO);
+    blockchain.add_to_mempool(payment.clone()).unwrap();
+    let added = blockchain.mempool().generation();
+    assert_ne!(added, start);
+
+    // already there, nothing changed
+    blockchain.add_to_mempool(payment).unwrap();
+    assert_eq!(blockchain.mempool().generation(), added);
+
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .add_txs(blockchain.template_transactions(1))
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    blockchain.add_block(block).unwrap();
+    assert!(blockchain.mempool().is_empty());
+    assert_ne!(blockchain.mempool().generation(), added);
+}
+
+#[test]
+fn unconfirmed_outputs_cannot_be_double_spent() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [first, _]) = chain_paying(&key);
+
+    let parent = spend(&first, &key, Amount::ZERO);
+    blockchain.add_to_mempool(parent.clone()).unwrap();
+    blockchain
+        .add_to_mempool(spend(&parent.outputs[0], &key, Amount::ONE_SAT))
+        .unwrap();
+    assert!(matches!(
+        blockchain.add_to_mempool(spend(&parent.outputs[0], &key, Amount::from_sat(2))),
+        Err(BtcError::DoubleSpend(_))
+    ));
+
+    // replacing the parent takes its child along
+    blockchain
+        .add_to_mempool(spend(&first, &key, Amount::from_sat(5)))
+        .unwrap();
+    assert_eq!(blockchain.mempool().len(), 1);
+}
+
+#[test]
+fn unconfirmed_chains_are_limited() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [first, _]) = chain_paying(&key);
+
+    let mut output = first;
+    for _ in 0..btclib::MAX_MEMPOOL_ANCESTORS {
+        let tx = spend(&output, &key, Amount::ONE_SAT);
+        output = tx.outputs[0].clone();
+        blockchain.add_to_mempool(tx).unwrap();
+    }
+    assert!(matches!(
+        blockchain.add_to_mempool(spend(&output, &key, Amount::ONE_SAT)),
+        Err(BtcError::TooManyMempoolAncestors { .. })
+    ));
+}
+
+/// 1 sat a byte at least, nothing for free
+fn strict(blockchain: &mut Blockchain) {
+    blockchain.set_mempool_policy(MempoolPolicy {
+        min_fee_rate: 1,
+        allow_zero_fee: false,
+        ..MempoolPolicy::default()
+    });
+}
+
+#[test]
+fn packages_carry_parents_paying_nothing() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [first, _]) = chain_paying(&key);
+    strict(&mut blockchain);
+
+    let parent = spend(&first, &key, Amount::ZERO);
+    let child = spend(&parent.outputs[0], &key, Amount::from_sat(10_000));
+    assert!(matches!(
+        blockchain.add_to_mempool(parent.clone()),
+        Err(BtcError::FeeTooLow { .. })
+    ));
+
+    let package = vec![parent.clone(), child.clone()];
+    let tested = blockchain.test_package_accept(&package).unwrap();
+    assert!(blockchain.mempool().is_empty());
+    let accepted = blockchain.add_package_to_mempool(package.clone()).unwrap();
+    assert_eq!(accepted, tested);
+    assert_eq!(accepted.fee, Amount::from_sat(10_000));
+    assert_eq!(accepted.size, parent.size() + child.size());
+    assert!(blockchain.mempool().contains(&parent.hash()));
+    assert!(blockchain.mempool().contains(&child.hash()));
+
+    // already there, nothing more to pay for
+    let again = blockchain.add_package_to_mempool(package).unwrap();
+    assert_eq!((again.fee, again.size), (Amount::ZERO, 0));
+    assert_eq!(blockchain.mempool().len(), 2);
+}
+
+#[test]
+fn packages_pay_for_all_of_themselves() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [first, _]) = chain_paying(&key);
+    strict(&mut blockchain);
+
+    let parent = spend(&first, &key, Amount::ZERO);
+    let child = spend(&parent.outputs[0], &key, Amount::from_sat(10));
+    assert!(matches!(
+        blockchain.add_package_to_mempool(vec![parent, child]),
+        Err(BtcError::FeeTooLow { .. })
+    ));
+    assert!(blockchain.mempool().is_empty());
+}
+
+#[test]
+fn packages_go_in_whole_or_not_at_all() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, [first, second]) = chain_paying(&key);
+
+    let parent = spend(&first, &key, Amount::ZERO);
+    let child = spend(&parent.outputs[0], &key, Amount::ONE_SAT);
+    let unrelated = spend(&second, &key, Amount::ONE_SAT);
+    for (package, what) in [
+        (vec![], "empty"),
+        (vec![child.clone(), parent.clone()], "child first"),
+        (vec![parent.clone(), parent.clone()], "twice"),
+        (vec![unrelated.clone(), child.clone()], "unrelated"),
+        (vec![parent.clone(), unrelated.clone()], "not spent from"),
+    ] {
+        assert!(
+            matches!(
+                blockchain.add_package_to_mempool(package),
+                Err(BtcError::InvalidPackage(_))
+            ),
+            "{what}"
+        );
+    }
+
+    // the parent is fine, the child spends an output nobody made
+    let made_up =
+        spend(&parent.outputs[0], &PrivateKey::new_key(), Amount::ZERO).outputs[0].clone();
+    let orphan = TransactionBuilder::new()
+        .spend(&parent.outputs[0], &key)
+        .spend(&made_up, &key)
+        .pay_to(key.public_key(), parent.outputs[0].value)
+        .finalize()
+        .unwrap();
+    assert!(matches!(
+        blockchain.add_package_to_mempool(vec![parent.clone(), orphan]),
+        Err(BtcError::MissingUtxo(_))
+    ));
+    assert!(blockchain.mempool().is_empty());
+
+    // a parent waiting already is left as it is
+    blockchain.add_to_mempool(parent.clone()).unwrap();
+    let accepted = blockchain
+        .add_package_to_mempool(vec![parent, child.clone()])
+        .unwrap();
+    assert_eq!(accepted.size, child.size());
+    assert_eq!(blockchain.mempool().len(), 2);
+}
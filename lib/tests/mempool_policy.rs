@@ -0,0 +1,193 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, EvictionReason, MempoolPolicy},
+};
+
+use chrono::{Duration, Utc};
+use common::{chain_paying, spend};
+
+mod common;
+
+#[test]
+fn default_policy_takes_zero_fees() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, outputs) = chain_paying::<3>(&key);
+    assert_eq!(blockchain.mempool_policy(), &MempoolPolicy::default());
+    let transaction = spend(&outputs[0], &key, Amount::from_sat(0));
+    blockchain.add_to_mempool(transaction.clone()).unwrap();
+    assert_eq!(blockchain.mempool().size(), transaction.size());
+    assert_eq!(blockchain.mempool_stats().size, transaction.size());
+}
+
+#[test]
+fn fees_and_sizes_are_checked() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, outputs) = chain_paying::<3>(&key);
+    blockchain.set_mempool_policy(MempoolPolicy {
+        allow_zero_fee: false,
+        ..MempoolPolicy::default()
+    });
+    assert!(matches!(
+        blockchain.add_to_mempool(spend(&outputs[0], &key, Amount::from_sat(0))),
+        Err(BtcError::FeeTooLow { .. })
+    ));
+    blockchain
+        .add_to_mempool(spend(&outputs[0], &key, Amount::from_sat(1)))
+        .unwrap();
+
+    let transaction = spend(&outputs[1], &key, Amount::from_sat(100));
+    let size = transaction.size();
+    blockchain.set_mempool_policy(MempoolPolicy {
+        min_fee_rate: 1,
+        max_transaction_size: size - 1,
+        ..MempoolPolicy::default()
+    });
+    assert!(matches!(
+        blockchain.add_to_mempool(transaction.clone()),
+        Err(BtcError::TransactionTooLarge { max, .. }) if max == size - 1
+    ));
+    blockchain.set_mempool_policy(MempoolPolicy {
+        min_fee_rate: 1,
+        ..MempoolPolicy::default()
+    });
+    // 100 sats don't make 1 per byte
+    assert!(size > 100);
+    assert!(matches!(
+        blockchain.add_to_mempool(transaction),
+        Err(BtcError::FeeTooLow { .. })
+    ));
+    blockchain
+        .add_to_mempool(spend(&outputs[1], &key, Amount::from_sat(size as u64)))
+        .unwrap();
+}
+
+#[test]
+fn full_mempools_drop_the_lowest_fee_rates() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, outputs) = chain_paying::<3>(&key);
+    let low = spend(&outputs[0], &key, Amount::from_sat(100));
+    let high = spend(&outputs[1], &key, Amount::from_sat(200));
+    blockchain.set_mempool_policy(MempoolPolicy {
+        max_mempool_size: low.size() + high.size() + 10,
+        ..MempoolPolicy::default()
+    });
+    blockchain.add_to_mempool(low.clone()).unwrap();
+    blockchain.add_to_mempool(high.clone()).unwrap();
+    let mut evictions = blockchain.mempool().subscribe();
+
+    let middle = spend(&outputs[2], &key, Amount::from_sat(150));
+    blockchain.add_to_mempool(middle.clone()).unwrap();
+    let eviction = evictions.try_recv().unwrap();
+    assert_eq!(eviction.transaction.hash(), low.hash());
+    assert_eq!(eviction.reason, EvictionReason::Full);
+    assert!(!blockchain.utxos()[&outputs[0].hash()].1);
+
+    // everything left pays more
+    assert!(matches!(
+        blockchain.add_to_mempool(spend(&outputs[0], &key, Amount::from_sat(50))),
+        Err(BtcError::MempoolFull { .. })
+    ));
+    assert_eq!(blockchain.mempool().len(), 2);
+    assert!(blockchain.mempool().contains(&middle.hash()));
+}
+
+#[test]
+fn mempool_info_lines_transactions_up_by_fee_rate() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, outputs) = chain_paying::<3>(&key);
+    let start = Utc::now() - Duration::seconds(30);
+    let cheap = spend(&outputs[0], &key, Amount::from_sat(100));
+    let generous = spend(&outputs[1], &key, Amount::from_sat(9_000));
+    let middle = spend(&outputs[2], &key, Amount::from_sat(3_000));
+    for (i, transaction) in [&cheap, &generous, &middle].into_iter().enumerate() {
+        blockchain
+            .add_to_mempool_at(transaction.clone(), start + Duration::seconds(i as i64))
+            .unwrap();
+    }
+
+    let info = blockchain.mempool_info();
+    let txids: Vec<_> = info.iter().map(|info| info.txid).collect();
+    assert_eq!(txids, vec![generous.hash(), middle.hash(), cheap.hash()]);
+    assert!(info[0].fee_rate() > info[1].fee_rate());
+
+    let cheap_info = blockchain.mempool_tx_info(&cheap.hash()).unwrap();
+    assert_eq!(cheap_info.position, 2);
+    assert_eq!(cheap_info.fee, Amount::from_sat(100));
+    assert_eq!(cheap_info.size, cheap.size());
+    assert_eq!(cheap_info.received, start);
+    assert!(blockchain.mempool_tx_info(&outputs[0].hash()).is_none());
+}
+
+#[test]
+fn testing_leaves_the_mempool_alone() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, outputs) = chain_paying::<3>(&key);
+    blockchain.set_mempool_policy(MempoolPolicy {
+        min_fee_rate: 1,
+        ..MempoolPolicy::default()
+    });
+    let generation = blockchain.mempool().generation();
+
+    let transaction = spend(&outputs[0], &key, Amount::from_sat(10_000));
+    let acceptance = blockchain.test_mempool_accept(&transaction).unwrap();
+    assert_eq!(acceptance.fee, Amount::from_sat(10_000));
+    assert_eq!(acceptance.size, transaction.size());
+    assert!(acceptance.replaces.is_empty() && acceptance.evicts.is_empty());
+    assert!(blockchain.mempool().is_empty());
+    assert_eq!(blockchain.mempool().generation(), generation);
+
+    // refused for the same reason adding it would be
+    let cheap = spend(&outputs[1], &key, Amount::from_sat(0));
+    assert!(matches!(
+        blockchain.test_mempool_accept(&cheap),
+        Err(BtcError::FeeTooLow { .. })
+    ));
+
+    blockchain.add_to_mempool(transaction.clone()).unwrap();
+    assert_eq!(
+        blockchain.test_mempool_accept(&transaction).unwrap(),
+        acceptance
+    );
+}
+
+#[test]
+fn transactions_expire_by_the_policy() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, outputs) = chain_paying::<3>(&key);
+    let transaction = spend(&outputs[0], &key, Amount::from_sat(0));
+    blockchain.add_to_mempool(transaction.clone()).unwrap();
+    assert_eq!(blockchain.cleanup_mempool(), 0);
+
+    let received = blockchain.mempool_info()[0].received;
+    let policy = MempoolPolicy {
+        max_transaction_age: 0,
+        ..MempoolPolicy::default()
+    };
+    assert_eq!(policy.expires(received), received);
+    assert_eq!(
+        MempoolPolicy::default().expires(received),
+        received + Duration::seconds(btclib::MAX_MEMPOOL_TRANSACTION_AGE as i64)
+    );
+
+    // applies to what's already in there
+    blockchain.set_mempool_policy(policy);
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert_eq!(blockchain.cleanup_mempool(), 1);
+    assert!(blockchain.mempool().is_empty());
+}
+
+#[test]
+fn policies_from_older_peers_expire_by_default() {
+    let policy: MempoolPolicy = serde_json::from_value(serde_json::json!({
+        "min_fee_rate": 1,
+        "max_transaction_size": 1000,
+        "max_mempool_size": 100000,
+        "allow_zero_fee": false,
+    }))
+    .unwrap();
+    assert_eq!(
+        policy.max_transaction_age,
+        btclib::MAX_MEMPOOL_TRANSACTION_AGE
+    );
+}
@@ -0,0 +1,97 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{Amount, BlockBuilder, Transaction, TransactionOutput},
+    util::{MerkleRoot, MerkleTree},
+};
+
+use rand::Rng;
+use uuid::Uuid;
+
+fn transaction(key: &PrivateKey) -> Transaction {
+    Transaction::new(
+        vec![],
+        vec![TransactionOutput {
+            value: Amount::from_sat(rand::thread_rng().gen_range(0..btclib::MAX_MONEY)),
+            unique_id: Uuid::new_v4(),
+            pubkey: key.public_key(),
+        }],
+    )
+}
+
+fn hashes(transactions: &[Transaction]) -> Vec<btclib::sha256::Hash> {
+    transactions.iter().map(Transaction::hash).collect()
+}
+
+#[test]
+fn changes_end_up_where_rebuilding_does() {
+    let key = PrivateKey::new_key();
+    let mut rng = rand::thread_rng();
+    let mut transactions: Vec<Transaction> = (0..5).map(|_| transaction(&key)).collect();
+    let mut tree = MerkleTree::new(&transactions);
+
+    for _ in 0..300 {
+        match rng.gen_range(0..3) {
+            0 if !transactions.is_empty() => {
+                let index = rng.gen_range(0..transactions.len());
+                transactions[index] = transaction(&key);
+                tree.set(index, transactions[index].hash());
+            }
+            1 => {
+                transactions.push(transaction(&key));
+                tree.set(transactions.len() - 1, transactions.last().unwrap().hash());
+            }
+            _ => {
+                let from = rng.gen_range(0..=transactions.len());
+                let added: Vec<Transaction> = (0..rng.gen_range(0..4))
+                    .map(|_| transaction(&key))
+                    .collect();
+                transactions.truncate(from);
+                transactions.extend(added.iter().cloned());
+                tree.splice(from, hashes(&added));
+            }
+        }
+        // every layer, not just the root
+        assert_eq!(
+            tree,
+            MerkleTree::new(&transactions),
+            "{} leaves",
+            tree.len()
+        );
+        assert_eq!(tree.leaves(), hashes(&transactions).as_slice());
+        assert_eq!(tree.root(), MerkleRoot::calculate(&transactions).ok());
+    }
+}
+
+#[test]
+fn empty_and_single_trees() {
+    let key = PrivateKey::new_key();
+    let mut tree = MerkleTree::new(&[]);
+    assert!(tree.is_empty());
+    assert_eq!(tree.root(), None);
+
+    let only = transaction(&key);
+    tree.set(0, only.hash());
+    assert_eq!(tree.root(), Some(MerkleRoot::from_hash(only.hash())));
+    tree.splice(0, []);
+    assert_eq!(tree.root(), None);
+}
+
+#[test]
+fn blocks_built_on_a_tree_have_the_right_root() {
+    let key = PrivateKey::new_key();
+    let transactions: Vec<Transaction> = (0..7).map(|_| transaction(&key)).collect();
+    // the coinbase's leaf is filled in when the block is finalized
+    let mut leaves = vec![btclib::sha256::Hash::zero()];
+    leaves.extend(hashes(&transactions));
+
+    let block = BlockBuilder::new()
+        .coinbase_to(key.public_key(), Amount::ONE_BTC)
+        .add_txs(transactions)
+        .merkle_tree(MerkleTree::from_leaves(leaves))
+        .finalize()
+        .unwrap();
+    assert_eq!(
+        block.header.merkle_root,
+        MerkleRoot::calculate(&block.transactions).unwrap()
+    );
+}
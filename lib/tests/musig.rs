@@ -0,0 +1,109 @@
+use btclib::crypto::{
+    Algorithm, KeyAggregation, MuSigSession, PartialSignature, PrivateKey, PublicNonce, Signature,
+    musig_nonce,
+};
+use btclib::sha256::Hash;
+use ciborium::Value;
+
+fn signers(n: usize) -> (Vec<PrivateKey>, KeyAggregation) {
+    let keys: Vec<_> = (0..n)
+        .map(|_| PrivateKey::generate(Algorithm::Schnorr))
+        .collect();
+    let public_keys: Vec<_> = keys.iter().map(PrivateKey::public_key).collect();
+    let aggregation = KeyAggregation::new(&public_keys).unwrap();
+    // signers go in the order the aggregation wants their nonces
+    let keys = aggregation
+        .keys()
+        .iter()
+        .map(|public| {
+            keys.iter()
+                .find(|key| key.public_key() == *public)
+                .unwrap()
+                .clone()
+        })
+        .collect();
+    (keys, aggregation)
+}
+
+fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+    let mut encoded = vec![];
+    ciborium::into_writer(value, &mut encoded).unwrap();
+    ciborium::from_reader(encoded.as_slice()).unwrap()
+}
+
+/// every signer only ever sees the others' nonces and partial signatures over the wire
+fn sign(keys: &[PrivateKey], aggregation: &KeyAggregation, output_hash: &Hash) -> Signature {
+    let (secrets, nonces): (Vec<_>, Vec<_>) = keys.iter().map(|_| musig_nonce()).unzip();
+    let nonces: Vec<PublicNonce> = nonces.iter().map(round_trip).collect();
+    let session = MuSigSession::new(aggregation.clone(), nonces, output_hash).unwrap();
+    let partials: Vec<PartialSignature> = keys
+        .iter()
+        .zip(secrets)
+        .map(|(key, secret)| round_trip(&session.partial_sign(key, secret).unwrap()))
+        .collect();
+    for (key, partial) in keys.iter().zip(&partials) {
+        assert!(session.verify_partial(&key.public_key(), partial));
+    }
+    session.aggregate(&partials).unwrap()
+}
+
+#[test]
+fn aggregate_signatures_verify_as_plain_schnorr() {
+    let output_hash = Hash::of_bytes(b"joint payout");
+    for n in [1, 2, 3] {
+        let (keys, aggregation) = signers(n);
+        let key = aggregation.public_key();
+        let signature = sign(&keys, &aggregation, &output_hash);
+        assert!(signature.verify(&output_hash, &key));
+        assert!(!signature.verify(&Hash::of_bytes(b"something else"), &key));
+        // nor does it pass for any one of the signers
+        assert!(
+            keys.iter()
+                .all(|k| !signature.verify(&output_hash, &k.public_key()))
+        );
+    }
+}
+
+#[test]
+fn every_signer_is_needed() {
+    let output_hash = Hash::of_bytes(b"joint payout");
+    let (keys, aggregation) = signers(3);
+    let (secrets, nonces): (Vec<_>, Vec<_>) = keys.iter().map(|_| musig_nonce()).unzip();
+    assert!(MuSigSession::new(aggregation.clone(), nonces[..2].to_vec(), &output_hash).is_err());
+
+    let session = MuSigSession::new(aggregation, nonces, &output_hash).unwrap();
+    let mut partials: Vec<_> = keys
+        .iter()
+        .zip(secrets)
+        .map(|(key, secret)| session.partial_sign(key, secret).unwrap())
+        .collect();
+    assert!(session.aggregate(&partials[..2]).is_err());
+
+    // a partial signature under someone else's nonce is blamed on its signer
+    partials.swap(0, 1);
+    assert!(!session.verify_partial(&keys[0].public_key(), &partials[0]));
+    assert!(!session.verify_partial(&keys[1].public_key(), &partials[1]));
+}
+
+#[test]
+fn nonces_cancelling_out_are_refused() {
+    let (_, aggregation) = signers(2);
+    let (_, honest) = musig_nonce();
+    // the same points negated, which a signer could send after seeing the other nonce
+    let mut document = Value::serialized(&honest).unwrap();
+    for point in document.as_array_mut().unwrap() {
+        let Value::Bytes(bytes) = point else {
+            panic!("points encode as bytes");
+        };
+        bytes[0] ^= 1;
+    }
+    let cancelling: PublicNonce = document.deserialized().unwrap();
+    assert!(
+        MuSigSession::new(
+            aggregation,
+            vec![honest, cancelling],
+            &Hash::of_bytes(b"joint payout")
+        )
+        .is_err()
+    );
+}
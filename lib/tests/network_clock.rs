@@ -0,0 +1,82 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    network::{MAX_CLOCK_SAMPLES, MIN_CLOCK_SAMPLES, NetworkClock},
+    types::{BlockBuilder, Blockchain},
+};
+
+use chrono::{Duration, Utc};
+
+/// a clock with one peer per offset, in seconds
+fn clock_with(offsets: &[i64]) -> NetworkClock {
+    let now = Utc::now();
+    let mut clock = NetworkClock::new();
+    for (i, offset) in offsets.iter().enumerate() {
+        clock.add_sample(
+            &format!("10.0.0.{i}"),
+            now + Duration::seconds(*offset),
+            now,
+        );
+    }
+    clock
+}
+
+#[test]
+fn the_median_peer_sets_the_time() {
+    let clock = clock_with(&[-30, 0, 10, 600, 700]);
+    assert_eq!(clock.median_offset(), 10);
+    assert_eq!(clock.adjustment(), Duration::seconds(10));
+    assert!(!clock.is_skewed());
+
+    // a few peers don't count yet
+    let clock = clock_with(&[600; MIN_CLOCK_SAMPLES - 1]);
+    assert_eq!(clock.median_offset(), 0);
+    assert_eq!(clock.adjustment(), Duration::zero());
+}
+
+#[test]
+fn far_off_clocks_only_warn() {
+    let clock = clock_with(&[600; MIN_CLOCK_SAMPLES]);
+    assert_eq!(clock.adjustment(), Duration::seconds(600));
+    assert!(clock.is_skewed());
+
+    let hours = 2 * 60 * 60;
+    let clock = clock_with(&[hours; MIN_CLOCK_SAMPLES]);
+    assert_eq!(clock.median_offset(), hours);
+    assert_eq!(clock.adjustment(), Duration::zero());
+    assert!(clock.is_skewed());
+}
+
+#[test]
+fn peers_count_once() {
+    let now = Utc::now();
+    let mut clock = NetworkClock::new();
+    for _ in 0..MIN_CLOCK_SAMPLES {
+        clock.add_sample("10.0.0.1", now + Duration::hours(1), now);
+    }
+    assert_eq!(clock.samples(), 1);
+    assert_eq!(clock.median_offset(), 0);
+
+    let clock = clock_with(&[0; MAX_CLOCK_SAMPLES + 10]);
+    assert_eq!(clock.samples(), MAX_CLOCK_SAMPLES);
+}
+
+#[test]
+fn blocks_from_the_future_wait_for_the_network() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::new();
+    let ahead = BlockBuilder::new()
+        .timestamp(Utc::now() + Duration::hours(3))
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    assert!(matches!(
+        blockchain.validate_block(ahead.clone()),
+        Err(BtcError::TimestampTooFarAhead { .. })
+    ));
+
+    // the peers say it's an hour and a bit later than the local clock does
+    blockchain.set_time_offset(Duration::minutes(65));
+    assert!(blockchain.adjusted_time() > Utc::now() + Duration::hours(1));
+    blockchain.add_block(ahead).unwrap();
+}
@@ -0,0 +1,92 @@
+use btclib::{
+    U256,
+    network::{CompressionStats, MemoryUsage, NodeStatus, Propagation, Traffic},
+    output::{MinerEvent, StatusOutput},
+    sha256::Hash,
+    types::{Amount, MempoolPolicy, MempoolStats},
+};
+
+use serde_json::json;
+
+fn status() -> NodeStatus {
+    NodeStatus {
+        version: "0.1.0".to_string(),
+        protocol_version: 1,
+        network: "main".to_string(),
+        regtest: false,
+        height: 12,
+        best_hash: Hash::from_be_bytes([0xab; 32]),
+        target: U256::from(0xffff),
+        difficulty: 1.0,
+        mempool: MempoolStats {
+            transactions: 2,
+            size: 300,
+            fees: Amount::from_sat(3_500),
+            oldest: None,
+        },
+        policy: MempoolPolicy {
+            max_transaction_age: 3_600,
+            ..Default::default()
+        },
+        peers: 3,
+        light_wallets: 0,
+        uptime: 60,
+        syncing: false,
+        clock_offset: -2,
+        tasks: vec![],
+        identity: None,
+        checkpoint: None,
+        finalized: Some(6),
+        own_blocks: Propagation::default(),
+        own_transactions: Propagation::default(),
+        compression: CompressionStats::default(),
+        traffic: Traffic::default(),
+        upload_limit: Some(64_000),
+        download_limit: None,
+        handler_panics: 0,
+        memory: MemoryUsage {
+            mempool: 300,
+            utxo_entries: 12,
+            ..Default::default()
+        },
+    }
+}
+
+#[test]
+fn status_output_has_hex_hashes_and_sats() {
+    let output = serde_json::to_value(StatusOutput::from(&status())).unwrap();
+    assert_eq!(output["best_hash"], json!("ab".repeat(32)));
+    assert_eq!(output["target"], json!(format!("{}ffff", "0".repeat(60))));
+    assert_eq!(output["height"], json!(12));
+    assert_eq!(
+        output["mempool"],
+        json!({"transactions": 2, "size": 300, "fees": 3500, "oldest": null})
+    );
+    assert_eq!(output["policy"]["max_transaction_age"], json!(3_600));
+    assert_eq!(output["clock_offset"], json!(-2));
+    assert_eq!(output["finalized"], json!(6));
+    assert_eq!(output["upload_limit"], json!(64_000));
+    assert_eq!(output["traffic"]["bytes_sent"], json!(0));
+    assert_eq!(output["memory"]["utxo_entries"], json!(12));
+    assert_eq!(output["memory"]["peers"]["limit"], json!(null));
+
+    let back: StatusOutput = serde_json::from_value(output).unwrap();
+    assert_eq!(back, StatusOutput::from(&status()));
+}
+
+#[test]
+fn miner_events_are_tagged() {
+    let event = MinerEvent::Share {
+        hash: "00".repeat(32),
+        shares: 4,
+        hashrate: 1000.0,
+    };
+    assert_eq!(
+        serde_json::to_value(&event).unwrap(),
+        json!({"event": "share", "hash": "00".repeat(32), "shares": 4, "hashrate": 1000.0})
+    );
+    assert_eq!(
+        serde_json::to_value(MinerEvent::Stale).unwrap(),
+        json!({"event": "stale"})
+    );
+}
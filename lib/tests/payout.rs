@@ -0,0 +1,107 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, BlockBuilder, Blockchain, Payout, PayoutSplit, TransactionBuilder},
+};
+
+use chrono::{Duration, Utc};
+
+fn split(key: &PrivateKey, percent: u8) -> PayoutSplit {
+    PayoutSplit {
+        pubkey: key.public_key(),
+        percent,
+    }
+}
+
+#[test]
+fn payouts_have_to_add_up() {
+    let (alice, bob) = (PrivateKey::new_key(), PrivateKey::new_key());
+    assert!(Payout::new(vec![split(&alice, 60), split(&bob, 40)]).is_ok());
+    for splits in [
+        vec![],
+        vec![split(&alice, 60), split(&bob, 30)],
+        vec![split(&alice, 100), split(&bob, 0)],
+        vec![split(&alice, 50), split(&alice, 50)],
+    ] {
+        assert!(matches!(
+            Payout::new(splits),
+            Err(BtcError::InvalidPayout(_))
+        ));
+    }
+
+    // nor does one that doesn't get past the wire
+    let bytes = serde_json::to_vec(&vec![split(&alice, 99)]).unwrap();
+    assert!(serde_json::from_slice::<Payout>(&bytes).is_err());
+    let payout = Payout::new(vec![split(&alice, 99), split(&bob, 1)]).unwrap();
+    let bytes = serde_json::to_vec(&payout).unwrap();
+    assert_eq!(serde_json::from_slice::<Payout>(&bytes).unwrap(), payout);
+}
+
+#[test]
+fn shares_add_up_to_the_amount() {
+    let keys: Vec<PrivateKey> = (0..3).map(|_| PrivateKey::new_key()).collect();
+    let payout = Payout::new(vec![
+        split(&keys[0], 34),
+        split(&keys[1], 33),
+        split(&keys[2], 33),
+    ])
+    .unwrap();
+    let shares = payout.split(Amount::from_sat(1_000));
+    let values: Vec<u64> = shares.iter().map(|(_, value)| value.to_sat()).collect();
+    // what rounding leaves goes to the first key
+    assert_eq!(values, vec![340, 330, 330]);
+    let values: Vec<u64> = payout
+        .split(Amount::from_sat(101))
+        .iter()
+        .map(|(_, value)| value.to_sat())
+        .collect();
+    assert_eq!(values, vec![35, 33, 33]);
+    assert_eq!(shares[1].0, keys[1].public_key());
+
+    let single = Payout::single(keys[0].public_key());
+    assert_eq!(single.single_key(), Some(&keys[0].public_key()));
+    assert_eq!(payout.single_key(), None);
+}
+
+#[test]
+fn a_split_coinbase_shares_the_fees_and_is_accepted() {
+    let (alice, bob) = (PrivateKey::new_key(), PrivateKey::new_key());
+    let mut blockchain = Blockchain::regtest();
+    let genesis = BlockBuilder::new()
+        .timestamp(Utc::now() - Duration::minutes(1))
+        .coinbase_to(alice.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    let output = genesis.transactions[0].outputs[0].clone();
+    blockchain.add_block(genesis).unwrap();
+
+    let fee = Amount::from_sat(1_000);
+    let transaction = TransactionBuilder::new()
+        .spend(&output, &alice)
+        .pay_to(bob.public_key(), Amount::ONE_BTC)
+        .change_to(alice.public_key(), fee)
+        .finalize()
+        .unwrap();
+    let payout = Payout::new(vec![split(&alice, 75), split(&bob, 25)]).unwrap();
+    let reward = blockchain.calculate_block_reward();
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .add_tx(transaction)
+        .finalize_paying(&payout, reward, blockchain.utxos())
+        .unwrap();
+
+    let total = reward.checked_add(fee).unwrap().to_sat();
+    let coinbase = &block.transactions[0].outputs;
+    assert_eq!(coinbase.len(), 2);
+    assert_eq!(coinbase[0].pubkey, alice.public_key());
+    assert_eq!(coinbase[1].value.to_sat(), total / 4);
+    assert_eq!(
+        coinbase[0].value.to_sat() + coinbase[1].value.to_sat(),
+        total
+    );
+
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    blockchain.add_block(block).unwrap();
+    assert_eq!(blockchain.block_height(), 2);
+}
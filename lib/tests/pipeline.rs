@@ -0,0 +1,117 @@
+use btclib::{
+    crypto::PrivateKey,
+    network::{MAX_MESSAGE_SIZE, MemoryMeter, Message, MessageReader, Outbox},
+};
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+use tokio::io::{AsyncWriteExt, duplex};
+
+#[tokio::test]
+async fn outbox_frames_match_plain_messages() {
+    let key = PrivateKey::new_key().public_key();
+    let (client, server) = duplex(64);
+    let (_, writer) = tokio::io::split(server);
+    let mut outbox = Outbox::spawn(writer, 4);
+
+    // more messages than the queue holds, so the outbox has to wait for the reader
+    let sender = {
+        let key = key.clone();
+        tokio::spawn(async move {
+            for height in 0..16 {
+                outbox.send(&Message::FetchBlock(height)).await.unwrap();
+            }
+            outbox.send(&Message::FetchUTXOs(key)).await.unwrap();
+        })
+    };
+
+    let (mut reader, _) = tokio::io::split(client);
+    for height in 0..16 {
+        let message = Message::receive_async(&mut reader).await.unwrap();
+        assert!(matches!(message, Message::FetchBlock(h) if h == height));
+    }
+    let message = Message::receive_async(&mut reader).await.unwrap();
+    assert!(matches!(message, Message::FetchUTXOs(k) if k == key));
+    sender.await.unwrap();
+}
+
+#[tokio::test]
+async fn reader_reads_plain_messages() {
+    let (mut client, server) = duplex(1024);
+    let frame = Message::AskDifference(7).to_frame().unwrap();
+    client.write_all(&frame).await.unwrap();
+    Message::DiscoverNodes
+        .send_async(&mut client)
+        .await
+        .unwrap();
+
+    let mut reader = MessageReader::new(server);
+    assert!(matches!(
+        reader.receive().await.unwrap(),
+        Message::AskDifference(7)
+    ));
+    assert!(matches!(
+        reader.receive().await.unwrap(),
+        Message::DiscoverNodes
+    ));
+}
+
+#[tokio::test]
+async fn oversized_message_is_rejected() {
+    let (mut client, server) = duplex(64);
+    client
+        .write_all(&(MAX_MESSAGE_SIZE as u64 + 1).to_be_bytes())
+        .await
+        .unwrap();
+
+    let mut reader = MessageReader::new(server);
+    assert!(reader.receive().await.is_err());
+}
+
+#[test]
+fn memory_is_given_back_once_released() {
+    let memory = Arc::new(MemoryMeter::new(Some(100)));
+    let first = memory.reserve(60).unwrap();
+    let error = memory.reserve(60).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::OutOfMemory);
+    let second = memory.reserve(40).unwrap();
+    assert_eq!(memory.stats().used, 100);
+    drop(first);
+    drop(second);
+
+    let stats = memory.stats();
+    assert_eq!((stats.used, stats.peak, stats.refused), (0, 100, 1));
+    assert!(Arc::new(MemoryMeter::new(None)).reserve(usize::MAX).is_ok());
+}
+
+#[tokio::test]
+async fn frames_past_the_memory_limit_are_refused() {
+    let (mut client, server) = duplex(1024);
+    let memory = Arc::new(MemoryMeter::new(Some(64)));
+    let mut reader = MessageReader::new(server);
+    reader.set_memory(memory.clone());
+
+    Message::AskDifference(7)
+        .send_async(&mut client)
+        .await
+        .unwrap();
+    assert!(matches!(
+        reader.receive().await.unwrap(),
+        Message::AskDifference(7)
+    ));
+    assert_eq!(memory.stats().used, 0);
+
+    client.write_all(&1000u64.to_be_bytes()).await.unwrap();
+    assert!(reader.receive().await.is_err());
+    assert_eq!(memory.stats().refused, 1);
+
+    // and an outbox doesn't queue what it has no room for
+    let (_, writer) = tokio::io::split(client);
+    let mut outbox = Outbox::spawn(writer, 4);
+    outbox.set_memory(memory.clone());
+    let frame = Message::DiscoverNodes.to_frame().unwrap();
+    assert!(outbox.send_frame(frame).await.is_ok());
+    let big = vec![0; 100];
+    assert!(outbox.try_send_frame(big.into()).is_err());
+    assert_eq!(memory.stats().refused, 2);
+}
@@ -0,0 +1,58 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, Transaction, TransactionBuilder, TransactionOutput},
+};
+
+use uuid::Uuid;
+
+fn transaction() -> Transaction {
+    let key = PrivateKey::new_key();
+    let output = TransactionOutput {
+        value: Amount::from_btc(2.0).unwrap(),
+        unique_id: Uuid::new_v4(),
+        pubkey: key.public_key(),
+    };
+    TransactionBuilder::new()
+        .spend(&output, &key)
+        .pay_to(PrivateKey::new_key().public_key(), Amount::ONE_BTC)
+        .change_to(key.public_key(), Amount::ONE_SAT)
+        .memo("invoice 42")
+        .finalize()
+        .unwrap()
+}
+
+#[test]
+fn hex_round_trips() {
+    let transaction = transaction();
+    let hex = transaction.to_hex();
+    assert!(hex.chars().all(|c| c.is_ascii_hexdigit()));
+
+    let decoded = Transaction::from_hex(&hex).unwrap();
+    assert_eq!(decoded.hash(), transaction.hash());
+    assert_eq!(decoded.memo.as_deref(), Some("invoice 42"));
+    // pasted with a newline or in upper case
+    assert_eq!(
+        Transaction::from_hex(&format!("{}\n", hex.to_uppercase()))
+            .unwrap()
+            .hash(),
+        transaction.hash()
+    );
+}
+
+#[test]
+fn malformed_hex_is_refused() {
+    let hex = transaction().to_hex();
+    for bad in [
+        "zz".to_string(),
+        hex[..hex.len() - 1].to_string(),
+        hex[..hex.len() - 2].to_string(),
+        format!("{hex}00"),
+        String::new(),
+    ] {
+        assert!(matches!(
+            Transaction::from_hex(&bad),
+            Err(BtcError::InvalidRawTransaction(_))
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use btclib::{
+    crypto::PrivateKey,
+    error::BtcError,
+    types::{Amount, BlockBuilder, Blockchain, Transaction, TransactionBuilder},
+    util::{CHUNK_SIZE, ChunkedWriter, FOOTER_MAGIC, FOOTER_SIZE, Saveable, read_checked},
+};
+use std::io::{ErrorKind, Write};
+use uuid::Uuid;
+
+fn transaction() -> Transaction {
+    TransactionBuilder::new()
+        .pay_to(PrivateKey::new_key().public_key(), Amount::ONE_BTC)
+        .finalize()
+        .unwrap()
+}
+
+fn is_corrupt(error: std::io::Error) -> bool {
+    error.kind() == ErrorKind::InvalidData
+        && matches!(
+            error.get_ref().and_then(|e| e.downcast_ref::<BtcError>()),
+            Some(BtcError::CorruptFile(_))
+        )
+}
+
+#[test]
+fn checked_files_end_with_a_footer() {
+    let transaction = transaction();
+    let mut plain = vec![];
+    transaction.save(&mut plain).unwrap();
+    let mut checked = vec![];
+    transaction.save_checked(&mut checked).unwrap();
+
+    assert_eq!(checked.len(), plain.len() + FOOTER_SIZE);
+    assert!(checked.starts_with(&plain));
+    assert!(checked[plain.len()..].starts_with(FOOTER_MAGIC));
+    let loaded = Transaction::load_checked(checked.as_slice()).unwrap();
+    assert_eq!(loaded.hash(), transaction.hash());
+    // and files from before the footer still load
+    let loaded = Transaction::load_checked(plain.as_slice()).unwrap();
+    assert_eq!(loaded.hash(), transaction.hash());
+}
+
+#[test]
+fn corruption_is_detected() {
+    let mut checked = vec![];
+    transaction().save_checked(&mut checked).unwrap();
+
+    let mut flipped = checked.clone();
+    flipped[10] ^= 1;
+    assert!(is_corrupt(
+        Transaction::load_checked(flipped.as_slice()).unwrap_err()
+    ));
+    let mut missing = checked.clone();
+    missing.remove(10);
+    assert!(is_corrupt(
+        Transaction::load_checked(missing.as_slice()).unwrap_err()
+    ));
+}
+
+#[test]
+fn big_files_are_written_in_chunks() {
+    let data: Vec<u8> = (0..3 * CHUNK_SIZE + 17).map(|i| i as u8).collect();
+    let mut writer = ChunkedWriter::new(vec![]);
+    for piece in data.chunks(1000) {
+        writer.write_all(piece).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    assert_eq!(bytes.len(), data.len() + FOOTER_SIZE);
+    assert_eq!(read_checked(bytes.as_slice()).unwrap(), data);
+}
+
+#[test]
+fn chains_are_saved_with_a_footer() {
+    let mut blockchain = Blockchain::new();
+    let block = BlockBuilder::new()
+        .coinbase_to(
+            PrivateKey::new_key().public_key(),
+            blockchain.calculate_block_reward(),
+        )
+        .finalize()
+        .unwrap();
+    blockchain.add_block(block).unwrap();
+
+    let path = std::env::temp_dir().join(format!("btclib-{}.cbor", Uuid::new_v4()));
+    blockchain.save_to_file(&path).unwrap();
+    let loaded = Blockchain::load_from_file(&path).unwrap();
+    assert_eq!(loaded.tip_hash(), blockchain.tip_hash());
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - FOOTER_SIZE - 1;
+    bytes[last] ^= 0xff;
+    std::fs::write(&path, &bytes).unwrap();
+    assert!(is_corrupt(Blockchain::load_from_file(&path).unwrap_err()));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn json_round_trips() {
+    let transaction = transaction();
+    let mut json = vec![];
+    transaction.save_json(&mut json).unwrap();
+    let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert!(value["outputs"].is_array());
+
+    let loaded = Transaction::load_json(json.as_slice()).unwrap();
+    assert_eq!(loaded.hash(), transaction.hash());
+    let error = Transaction::load_json(&b"{\"inputs\": 3}"[..]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
@@ -0,0 +1,104 @@
+use btclib::{types::Blockchain, util::Saveable};
+
+use ciborium::Value;
+use common::mined_chain;
+
+mod common;
+
+/// `blockchain` saved, with `change` made to the chain's document on the way, and loaded again
+fn tampered(blockchain: &Blockchain, change: impl FnOnce(&mut Value)) -> Blockchain {
+    let mut bytes = vec![];
+    blockchain.save(&mut bytes).unwrap();
+    let (header, mut payload) = bytes.split_at(10);
+    let mut document: Value = ciborium::de::from_reader(&mut payload).unwrap();
+    change(&mut document);
+
+    let mut bytes = header.to_vec();
+    ciborium::ser::into_writer(&document, &mut bytes).unwrap();
+    // the UTXO checksum, as it was saved
+    bytes.extend_from_slice(payload);
+    Blockchain::load(bytes.as_slice()).unwrap()
+}
+
+fn field<'a>(value: &'a mut Value, name: &str) -> &'a mut Value {
+    let Value::Map(fields) = value else {
+        panic!("not a map");
+    };
+    fields
+        .iter_mut()
+        .find(|(key, _)| key.as_text() == Some(name))
+        .map(|(_, value)| value)
+        .unwrap()
+}
+
+#[test]
+fn a_saved_chain_checks_out() {
+    let blockchain = mined_chain(5, 0);
+    let mut bytes = vec![];
+    blockchain.save(&mut bytes).unwrap();
+    let loaded = Blockchain::load(bytes.as_slice()).unwrap();
+
+    let check = loaded.self_check(3);
+    assert!(check.is_ok(), "{check:?}");
+    assert_eq!(check.blocks, 3);
+    assert_eq!(loaded.self_check(100).blocks, 5);
+    assert!(blockchain.self_check(100).is_ok());
+}
+
+#[test]
+fn a_changed_block_is_broken() {
+    let blockchain = mined_chain(5, 0);
+    let loaded = tampered(&blockchain, |document| {
+        let Value::Array(blocks) = field(document, "blocks") else {
+            panic!("no blocks");
+        };
+        let Value::Array(transactions) = field(&mut blocks[1], "transactions") else {
+            panic!("no transactions");
+        };
+        let Value::Array(outputs) = field(&mut transactions[0], "outputs") else {
+            panic!("no outputs");
+        };
+        *field(&mut outputs[0], "value") = Value::Integer(1.into());
+    });
+
+    let check = loaded.self_check(100);
+    assert_eq!(check.broken.len(), 2, "{check:?}");
+    assert!(check.broken[0].starts_with("block 1"), "{check:?}");
+    // the block after it builds on a hash it no longer has
+    assert!(check.broken[1].starts_with("block 2"), "{check:?}");
+    // too deep to be checked
+    assert!(loaded.self_check(2).broken.is_empty());
+}
+
+#[test]
+fn a_changed_utxo_set_is_stale() {
+    let blockchain = mined_chain(5, 0);
+    let mut loaded = tampered(&blockchain, |document| {
+        let Value::Map(utxos) = field(document, "utxos") else {
+            panic!("no utxos");
+        };
+        utxos.pop();
+    });
+    let check = loaded.self_check(100);
+    assert!(check.broken.is_empty(), "{check:?}");
+    assert_eq!(check.stale.len(), 1, "{check:?}");
+    assert!(check.stale[0].starts_with("4 outputs"), "{check:?}");
+
+    loaded.rebuild_utxos();
+    assert!(loaded.self_check(100).is_ok());
+    assert_eq!(loaded.utxos().len(), 5);
+}
+
+#[test]
+fn a_target_adjusted_twice_is_stale() {
+    let interval = btclib::DIFFICULTY_UPDATE_INTERVAL;
+    let mut blockchain = mined_chain(interval, 0);
+    assert!(blockchain.self_check(1).is_ok());
+
+    // what loading a chain saved right after an adjustment used to do
+    blockchain.try_adjust_target();
+    let check = blockchain.self_check(1);
+    assert!(check.stale[0].starts_with("the target is"), "{check:?}");
+    blockchain.retarget();
+    assert!(blockchain.self_check(1).is_ok());
+}
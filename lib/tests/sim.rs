@@ -0,0 +1,126 @@
+use btclib::{
+    chain_params::ChainParams,
+    consensus::Consensus,
+    error::BtcError,
+    sim::{Simulation, Step},
+    types::{Amount, Transaction, TransactionBuilder},
+};
+
+use std::sync::Arc;
+
+use chrono::Duration;
+
+fn heights(sim: &Simulation) -> Vec<u64> {
+    sim.nodes()
+        .iter()
+        .map(|node| node.blockchain().block_height())
+        .collect()
+}
+
+/// spend the coinbase of `node`'s last block back to it
+fn spend_coinbase(sim: &Simulation, node: usize) -> Transaction {
+    let node = sim.node(node);
+    let tip = node.blockchain().blocks().last().unwrap();
+    TransactionBuilder::new()
+        .spend(&tip.transactions[0].outputs[0], node.key())
+        .change_to(node.key().public_key(), Amount::ONE_SAT)
+        .finalize()
+        .unwrap()
+}
+
+#[test]
+fn transactions_travel_hop_by_hop() {
+    let mut sim = Simulation::new(4, Arc::new(ChainParams::regtest())).unwrap();
+    // a line: 0 - 1 - 2 - 3
+    sim.run([
+        Step::Disconnect(0, 2),
+        Step::Disconnect(0, 3),
+        Step::Disconnect(1, 3),
+        Step::Mine(0),
+        Step::Settle,
+    ])
+    .unwrap();
+    assert_eq!(heights(&sim), vec![1, 1, 1, 1]);
+
+    let transaction = spend_coinbase(&sim, 0);
+    let hash = transaction.hash();
+    sim.run([
+        Step::Submit(0, transaction),
+        Step::Advance(Duration::milliseconds(250)),
+    ])
+    .unwrap();
+    assert!(sim.node(2).blockchain().mempool().contains(&hash));
+    assert!(!sim.node(3).blockchain().mempool().contains(&hash));
+    sim.advance(Duration::milliseconds(50));
+    assert!(sim.node(3).blockchain().mempool().contains(&hash));
+
+    // and leaves every mempool once it's too old, on the virtual clock
+    sim.advance(Duration::seconds(
+        btclib::MAX_MEMPOOL_TRANSACTION_AGE as i64 + 1,
+    ));
+    assert!(
+        sim.nodes()
+            .iter()
+            .all(|node| node.blockchain().mempool().is_empty())
+    );
+}
+
+#[test]
+fn partitioned_nodes_catch_up_after_healing() {
+    let mut sim = Simulation::new(4, Arc::new(ChainParams::regtest())).unwrap();
+    sim.run([
+        Step::Mine(0),
+        Step::Settle,
+        Step::Partition(vec![vec![0, 1], vec![2, 3]]),
+        Step::Mine(0),
+        Step::Settle,
+        Step::Mine(1),
+        Step::Settle,
+    ])
+    .unwrap();
+    assert_eq!(heights(&sim), vec![3, 3, 1, 1]);
+    assert!(!sim.converged());
+
+    sim.run([Step::Heal, Step::Mine(1), Step::Settle]).unwrap();
+    assert_eq!(heights(&sim), vec![4, 4, 4, 4]);
+    assert!(sim.converged());
+}
+
+#[test]
+fn nodes_coming_back_ask_what_they_missed() {
+    let mut sim = Simulation::new(3, Arc::new(ChainParams::regtest())).unwrap();
+    sim.run([
+        Step::Mine(0),
+        Step::Settle,
+        Step::Offline(2),
+        Step::Mine(0),
+        Step::Settle,
+        Step::Mine(1),
+        Step::Settle,
+    ])
+    .unwrap();
+    assert_eq!(heights(&sim), vec![3, 3, 1]);
+
+    sim.run([Step::Online(2), Step::Settle]).unwrap();
+    assert!(sim.converged());
+    assert_eq!(sim.node(2).blockchain().block_height(), 3);
+}
+
+#[test]
+fn stake_chains_take_blocks_from_slot_leaders() {
+    let params = ChainParams {
+        consensus: Consensus::ProofOfStake,
+        ..ChainParams::regtest()
+    };
+    let mut sim = Simulation::new(3, Arc::new(params)).unwrap();
+    sim.run([Step::Mine(1), Step::Settle]).unwrap();
+    assert_eq!(heights(&sim), vec![1, 1, 1]);
+
+    // node 1 holds everything there is, it leads every slot
+    sim.advance(Duration::seconds(10));
+    assert!(matches!(sim.mine(0), Err(BtcError::NotSlotLeader(_))));
+    sim.run([Step::Mine(1), Step::Settle]).unwrap();
+    assert_eq!(heights(&sim), vec![2, 2, 2]);
+    assert!(sim.converged());
+    assert!(matches!(sim.mine(1), Err(BtcError::SlotTaken(_))));
+}
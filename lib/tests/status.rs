@@ -0,0 +1,46 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{Amount, BlockBuilder, Blockchain, MempoolStats, TransactionBuilder},
+};
+
+use chrono::{Duration, Utc};
+
+#[test]
+fn mempool_stats_add_up_fees() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::new();
+    assert_eq!(blockchain.mempool_stats(), MempoolStats::default());
+    assert_eq!(blockchain.difficulty(), 1.0);
+
+    let reward = blockchain.calculate_block_reward();
+    let half = Amount::from_sat(reward.to_sat() / 2);
+    let genesis = BlockBuilder::new()
+        .timestamp(Utc::now() - Duration::minutes(1))
+        .coinbase_to(key.public_key(), half)
+        .coinbase_to(key.public_key(), half)
+        .finalize()
+        .unwrap();
+    let outputs = genesis.transactions[0].outputs.clone();
+    blockchain.add_block(genesis).unwrap();
+
+    let first = Utc::now() - Duration::seconds(30);
+    for (output, fee, time) in [
+        (&outputs[0], 1_000, first),
+        (&outputs[1], 2_500, Utc::now()),
+    ] {
+        let transaction = TransactionBuilder::new()
+            .spend(output, &key)
+            .pay_to(
+                key.public_key(),
+                Amount::from_sat(output.value.to_sat() - fee),
+            )
+            .finalize()
+            .unwrap();
+        blockchain.add_to_mempool_at(transaction, time).unwrap();
+    }
+
+    let stats = blockchain.mempool_stats();
+    assert_eq!(stats.transactions, 2);
+    assert_eq!(stats.fees, Amount::from_sat(3_500));
+    assert_eq!(stats.oldest, Some(first));
+}
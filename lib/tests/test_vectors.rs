@@ -0,0 +1,53 @@
+use btclib::{
+    crypto::{PrivateKey, Signature},
+    sha256::Hash,
+    test_vectors::{HASH_VECTORS, SIGNING_VECTORS},
+};
+
+#[test]
+fn hash_vectors() {
+    for vector in HASH_VECTORS {
+        assert_eq!(
+            Hash::hash(&vector.data).to_string(),
+            vector.hash,
+            "hash of {:?}",
+            vector.data
+        );
+    }
+}
+
+#[test]
+fn signing_vectors() {
+    for vector in SIGNING_VECTORS {
+        let secret = hex::decode(vector.private_key).unwrap();
+        let private_key = PrivateKey::from_bytes(vector.algorithm, &secret).unwrap();
+        let public_key = private_key.public_key();
+        assert_eq!(hex::encode(public_key.to_bytes()), vector.public_key);
+
+        let output_hash = Hash::hash(&vector.message);
+        assert_eq!(output_hash.to_string(), vector.output_hash);
+
+        let signature = Signature::sign_output(&output_hash, &private_key);
+        assert_eq!(
+            hex::encode(signature.to_bytes()),
+            vector.signature,
+            "{} signature of {:?}",
+            vector.algorithm,
+            vector.message
+        );
+        assert!(signature.verify(&output_hash, &public_key));
+        assert!(!signature.verify(&Hash::zero(), &public_key));
+    }
+}
+
+#[test]
+fn signing_is_deterministic() {
+    for vector in SIGNING_VECTORS {
+        let secret = hex::decode(vector.private_key).unwrap();
+        let private_key = PrivateKey::from_bytes(vector.algorithm, &secret).unwrap();
+        let output_hash = Hash::hash(&vector.message);
+        let first = Signature::sign_output(&output_hash, &private_key);
+        let second = Signature::sign_output(&output_hash, &private_key);
+        assert_eq!(first.to_bytes(), second.to_bytes());
+    }
+}
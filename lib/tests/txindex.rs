@@ -0,0 +1,58 @@
+use btclib::{
+    crypto::PrivateKey,
+    sha256::Hash,
+    types::{Amount, BlockBuilder, Blockchain, TransactionBuilder},
+};
+
+use chrono::{Duration, Utc};
+use common::mine;
+
+mod common;
+
+#[test]
+fn txindex_finds_old_and_new_transactions() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::regtest();
+    let start = Utc::now() - Duration::minutes(10);
+    let genesis = mine(
+        BlockBuilder::new()
+            .timestamp(start)
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward()),
+        &blockchain,
+    );
+    let coinbase = genesis.transactions[0].clone();
+    blockchain.add_block(genesis).unwrap();
+    blockchain.enable_txindex();
+
+    let payment = TransactionBuilder::new()
+        .spend(&coinbase.outputs[0], &key)
+        .pay_to(key.public_key(), Amount::ONE_BTC)
+        .change_to(key.public_key(), Amount::ZERO)
+        .finalize()
+        .unwrap();
+    let block = mine(
+        BlockBuilder::on_top_of(&blockchain)
+            .timestamp(start + Duration::minutes(1))
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .add_tx(payment.clone()),
+        &blockchain,
+    );
+    blockchain.add_block(block).unwrap();
+
+    let (height, found) = blockchain.find_transaction(&coinbase.hash()).unwrap();
+    assert_eq!((height, found.hash()), (0, coinbase.hash()));
+    let (height, found) = blockchain.find_transaction(&payment.hash()).unwrap();
+    assert_eq!((height, found.hash()), (1, payment.hash()));
+    assert!(blockchain.find_transaction(&Hash::zero()).is_none());
+
+    // scanning without the index gives the same answers
+    let mut unindexed = Blockchain::regtest();
+    for block in blockchain.blocks() {
+        unindexed.add_block(block.clone()).unwrap();
+    }
+    assert!(!unindexed.has_txindex());
+    assert_eq!(
+        unindexed.find_transaction(&payment.hash()).unwrap().0,
+        height
+    );
+}
@@ -0,0 +1,104 @@
+use btclib::{
+    crypto::PrivateKey,
+    sha256::Hash,
+    types::{Amount, Block, Blockchain},
+    util::Saveable,
+};
+
+use chrono::{Duration, Utc};
+use common::{block, spend, unspent};
+
+mod common;
+
+/// Four blocks: the second spends the genesis coinbase, the third spends that payment and what
+/// it pays in the same block
+fn chain(key: &PrivateKey) -> (Blockchain, Vec<Block>) {
+    let start = Utc::now() - Duration::hours(1);
+    let mut blockchain = Blockchain::regtest();
+    let mut blocks: Vec<Block> = vec![];
+    for i in 0..4 {
+        let transactions = match i {
+            1 => vec![spend(
+                &blocks[0].transactions[0].outputs[0],
+                key,
+                Amount::ZERO,
+            )],
+            2 => {
+                let first = spend(&blocks[1].transactions[1].outputs[0], key, Amount::ZERO);
+                let second = spend(&first.outputs[0], key, Amount::ZERO);
+                vec![first, second]
+            }
+            _ => vec![],
+        };
+        let block = block(&blockchain, key, start + Duration::minutes(i), transactions);
+        blockchain.add_block(block.clone()).unwrap();
+        blocks.push(block);
+    }
+    (blockchain, blocks)
+}
+
+#[test]
+fn undo_data_lists_what_came_from_before_the_block() {
+    let key = PrivateKey::new_key();
+    let (blockchain, blocks) = chain(&key);
+    assert!(blockchain.block_undo(0).unwrap().spent.is_empty());
+    let spent: Vec<Hash> = blockchain
+        .block_undo(1)
+        .unwrap()
+        .spent
+        .iter()
+        .map(|(hash, _)| *hash)
+        .collect();
+    assert_eq!(spent, vec![blocks[0].transactions[0].outputs[0].hash()]);
+    // the output paid and spent in the third block isn't there
+    assert_eq!(blockchain.block_undo(2).unwrap().spent.len(), 1);
+    assert!(blockchain.block_undo(4).is_none());
+
+    let mut bytes = vec![];
+    blockchain.save(&mut bytes).unwrap();
+    let loaded = Blockchain::load(bytes.as_slice()).unwrap();
+    assert_eq!(loaded.block_undo(2).unwrap().spent.len(), 1);
+}
+
+#[test]
+fn disconnecting_puts_the_spent_outputs_back() {
+    let key = PrivateKey::new_key();
+    let (mut blockchain, blocks) = chain(&key);
+    let mut before = Blockchain::regtest();
+    before.add_block(blocks[0].clone()).unwrap();
+
+    blockchain.invalidate_block(&blocks[1].hash()).unwrap();
+    assert_eq!(
+        unspent(blockchain.utxos().values()),
+        unspent(before.utxos().values())
+    );
+    blockchain.total_supply().unwrap();
+    assert!(blockchain.block_undo(0).is_some());
+    assert!(blockchain.block_undo(1).is_none());
+
+    // and blocks added afterwards get their own
+    blockchain.reconsider_block(&blocks[1].hash()).unwrap();
+    assert_eq!(blockchain.block_height(), 4);
+    assert_eq!(blockchain.block_undo(1).unwrap().spent.len(), 1);
+    let mut rebuilt = blockchain.clone();
+    rebuilt.rebuild_utxos();
+    assert_eq!(
+        unspent(blockchain.utxos().values()),
+        unspent(rebuilt.utxos().values())
+    );
+}
+
+#[test]
+fn blocks_without_undo_data_rebuild_the_set() {
+    // saved before undo data was kept
+    let bytes = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../blockchain.cbor")).unwrap();
+    let mut blockchain = Blockchain::load(bytes.as_slice()).unwrap();
+    blockchain.rebuild_utxos();
+    let height = blockchain.block_height();
+    assert!(blockchain.block_undo(height - 1).is_none());
+
+    let tip = blockchain.blocks().last().unwrap().hash();
+    blockchain.invalidate_block(&tip).unwrap();
+    assert_eq!(blockchain.block_height(), height - 1);
+    blockchain.total_supply().unwrap();
+}
@@ -0,0 +1,72 @@
+use btclib::{
+    crypto::PrivateKey,
+    sha256::Hash,
+    types::{Amount, BlockBuilder, Blockchain, TransactionBuilder, TransactionOutput},
+};
+
+use chrono::{Duration, Utc};
+
+fn hashes(outputs: &[TransactionOutput]) -> Vec<Hash> {
+    outputs.iter().map(|output| output.hash()).collect()
+}
+
+#[test]
+fn diffs_list_new_and_spent_outputs() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let mut blockchain = Blockchain::regtest();
+    let start = Utc::now() - Duration::minutes(10);
+    let genesis = BlockBuilder::new()
+        .timestamp(start)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    let coinbase = genesis.transactions[0].outputs[0].clone();
+    blockchain.add_block(genesis).unwrap();
+
+    let payment = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(other.public_key(), Amount::ONE_BTC)
+        .change_to(key.public_key(), Amount::ZERO)
+        .finalize()
+        .unwrap();
+    let change = payment
+        .outputs
+        .iter()
+        .find(|output| output.pubkey == key.public_key())
+        .unwrap()
+        .clone();
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .timestamp(start + Duration::minutes(1))
+        .coinbase_to(other.public_key(), blockchain.calculate_block_reward())
+        .add_tx(payment)
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    blockchain.add_block(block).unwrap();
+
+    let diff = blockchain.utxo_diff(&key.public_key(), 1).unwrap();
+    assert_eq!(diff.height, 2);
+    assert_eq!(diff.tip, blockchain.tip_hash());
+    assert!(!diff.full);
+    assert_eq!(diff.removed, vec![coinbase.hash()]);
+    assert_eq!(hashes(&diff.added), vec![change.hash()]);
+
+    // from the start, the coinbase came and went
+    let diff = blockchain.utxo_diff(&key.public_key(), 0).unwrap();
+    assert!(diff.removed.is_empty());
+    assert_eq!(hashes(&diff.added), vec![change.hash()]);
+    assert!(blockchain.utxo_diff(&key.public_key(), 3).is_none());
+
+    let spend = TransactionBuilder::new()
+        .spend(&change, &key)
+        .pay_to(other.public_key(), change.value)
+        .finalize()
+        .unwrap();
+    blockchain.add_to_mempool(spend).unwrap();
+    let diff = blockchain.utxo_diff(&key.public_key(), 2).unwrap();
+    assert!(diff.added.is_empty() && diff.removed.is_empty());
+    assert_eq!(diff.marked, vec![change.hash()]);
+}
@@ -0,0 +1,99 @@
+use btclib::{
+    chain_params::ChainParams,
+    crypto::PrivateKey,
+    types::{Blockchain, Transaction, TransactionBuilder, TransactionOutput},
+    util::Saveable,
+};
+
+use chrono::{Duration, Utc};
+use common::{block, unspent};
+use std::sync::{Arc, Mutex};
+
+mod common;
+
+const BLOCKS: i64 = 2500;
+
+fn pay(output: &TransactionOutput, key: &PrivateKey, to: &PrivateKey) -> Transaction {
+    TransactionBuilder::new()
+        .spend(output, key)
+        .pay_to(to.public_key(), output.value)
+        .finalize()
+        .unwrap()
+}
+
+/// long enough for a few batches, with outputs spent batches after they were made
+fn chain() -> Blockchain {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let start = Utc::now() - Duration::minutes(BLOCKS + 1);
+    let mut blockchain = Blockchain::regtest();
+    let mut moved = None;
+    for i in 0..BLOCKS {
+        let transactions = match i {
+            // the genesis coinbase, then what it paid
+            1500 => {
+                let genesis = blockchain.blocks().next().unwrap();
+                let payment = pay(&genesis.transactions[0].outputs[0], &key, &other);
+                moved = Some(payment.outputs[0].clone());
+                vec![payment]
+            }
+            2200 => vec![pay(moved.as_ref().unwrap(), &other, &key)],
+            // and one spent in the batch it was made in
+            11 => {
+                let tenth = blockchain.blocks().nth(10).unwrap();
+                vec![pay(&tenth.transactions[0].outputs[0], &key, &other)]
+            }
+            _ => vec![],
+        };
+        let block = block(
+            &blockchain,
+            &key,
+            start + Duration::minutes(i),
+            transactions,
+        );
+        blockchain.add_block(block).unwrap();
+    }
+    blockchain
+}
+
+#[test]
+fn rebuilt_set_matches_the_incremental_one() {
+    let mut blockchain = chain();
+    let expected = unspent(blockchain.utxos().values());
+    assert_eq!(expected.len(), BLOCKS as usize);
+
+    let calls = Mutex::new(vec![]);
+    blockchain.rebuild_utxos_with_progress(|done, total| calls.lock().unwrap().push((done, total)));
+    assert_eq!(unspent(blockchain.utxos().values()), expected);
+    blockchain.total_supply().unwrap();
+
+    let calls = calls.into_inner().unwrap();
+    let total = BLOCKS as u64;
+    assert!(calls.iter().all(|&(done, of)| done <= of && of == total));
+    assert!(calls.contains(&(total, total)));
+}
+
+#[test]
+fn saved_set_is_kept_when_it_adds_up() {
+    let blockchain = chain();
+    let mut bytes = vec![];
+    blockchain.save(&mut bytes).unwrap();
+
+    let mut loaded = Blockchain::load(bytes.as_slice()).unwrap();
+    let rebuilt = loaded.restore_utxos(|_, _| panic!("nothing to rebuild"));
+    assert!(!rebuilt);
+    assert_eq!(
+        unspent(loaded.utxos().values()),
+        unspent(blockchain.utxos().values())
+    );
+
+    // under other rules the rewards don't add up anymore
+    let mut loaded = Blockchain::load(bytes.as_slice()).unwrap();
+    let params = ChainParams::from_toml("initial_reward = 10").unwrap();
+    loaded.set_params(Arc::new(params));
+    assert!(loaded.restore_utxos(|_, _| {}));
+    assert_eq!(
+        unspent(loaded.utxos().values()),
+        unspent(blockchain.utxos().values())
+    );
+}
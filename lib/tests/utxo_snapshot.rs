@@ -0,0 +1,51 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{Amount, BlockBuilder, Blockchain, TransactionBuilder},
+};
+
+use chrono::{Duration, Utc};
+
+#[test]
+fn snapshot_keeps_its_view_while_the_chain_moves_on() {
+    let key = PrivateKey::new_key();
+    let mut blockchain = Blockchain::regtest();
+    let start = Utc::now() - Duration::minutes(10);
+    let genesis = BlockBuilder::new()
+        .timestamp(start)
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .finalize()
+        .unwrap();
+    blockchain.add_block(genesis).unwrap();
+
+    let snapshot = blockchain.utxo_snapshot();
+    let (coinbase, _) = snapshot.owned_by(&key.public_key()).next().unwrap().clone();
+
+    // the mempool marks the coinbase as spent and a block spends it for good
+    let transaction = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(PrivateKey::new_key().public_key(), Amount::ONE_BTC)
+        .change_to(key.public_key(), Amount::ZERO)
+        .finalize()
+        .unwrap();
+    blockchain.add_to_mempool(transaction.clone()).unwrap();
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .timestamp(start + Duration::minutes(1))
+        .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+        .add_tx(transaction)
+        .finalize_with_fees(blockchain.utxos())
+        .unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    blockchain.add_block(block).unwrap();
+
+    assert_eq!(snapshot.height(), 1);
+    assert_eq!(snapshot.utxos().len(), 1);
+    assert!(!snapshot.utxos()[&coinbase.hash()].1);
+    assert!(!blockchain.utxos().contains_key(&coinbase.hash()));
+
+    let latest = blockchain.utxo_snapshot();
+    assert_eq!(latest.height(), 2);
+    assert_eq!(latest.tip(), blockchain.blocks().last().unwrap().hash());
+    assert_eq!(latest.owned_by(&key.public_key()).count(), 2);
+}
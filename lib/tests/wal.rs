@@ -0,0 +1,138 @@
+use btclib::{
+    crypto::PrivateKey,
+    types::{Blockchain, Recovery, Wal, WalRecord},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use common::{block, unspent};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+mod common;
+
+fn log_path() -> PathBuf {
+    std::env::temp_dir().join(format!("btclib-{}.wal", Uuid::new_v4()))
+}
+
+/// a block timestamp, `minutes` after an hour ago
+fn at(minutes: i64) -> DateTime<Utc> {
+    Utc::now() - Duration::hours(1) + Duration::minutes(minutes)
+}
+
+#[test]
+fn the_log_catches_a_saved_chain_up() {
+    let key = PrivateKey::new_key();
+    let path = log_path();
+    let mut blockchain = Blockchain::regtest();
+    blockchain.set_wal(Some(Wal::open(&path).unwrap().0));
+    for minutes in 0..2 {
+        blockchain
+            .add_block(block(&blockchain, &key, at(minutes), vec![]))
+            .unwrap();
+    }
+    // saved here, the log keeps going
+    let mut saved = blockchain.clone();
+    saved.set_wal(None);
+    for minutes in 2..4 {
+        blockchain
+            .add_block(block(&blockchain, &key, at(minutes), vec![]))
+            .unwrap();
+    }
+    let third = blockchain.blocks().nth(2).unwrap().hash();
+    blockchain.invalidate_block(&third).unwrap();
+
+    let (_, records) = Wal::open(&path).unwrap();
+    // four blocks added, two taken off, each followed by `Done`
+    assert_eq!(records.len(), 10);
+    let recovery = saved.recover(records).unwrap();
+    assert_eq!(
+        recovery,
+        Recovery {
+            replayed: 3,
+            rolled_back: None
+        }
+    );
+    assert_eq!(saved.block_height(), 2);
+    assert_eq!(saved.tip_hash(), blockchain.tip_hash());
+    assert_eq!(
+        unspent(saved.utxos().values()),
+        unspent(blockchain.utxos().values())
+    );
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn a_block_cut_short_is_rolled_back() {
+    let key = PrivateKey::new_key();
+    let path = log_path();
+    let mut blockchain = Blockchain::regtest();
+    let (wal, _) = Wal::open(&path).unwrap();
+    blockchain.set_wal(Some(wal.clone()));
+    blockchain
+        .add_block(block(&blockchain, &key, at(0), vec![]))
+        .unwrap();
+    let mut saved = blockchain.clone();
+    saved.set_wal(None);
+
+    // the node died between logging the block and finishing it, halfway through the next record
+    let cut_short = block(&blockchain, &key, at(1), vec![]);
+    wal.append(&WalRecord::Apply {
+        height: 1,
+        block: cut_short.clone(),
+    })
+    .unwrap();
+    let whole = wal.len().unwrap();
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[0, 0, 1, 0, 42])
+        .unwrap();
+
+    let (wal, records) = Wal::open(&path).unwrap();
+    assert_eq!(wal.len().unwrap(), whole);
+    assert_eq!(records.len(), 3);
+    let recovery = saved.recover(records).unwrap();
+    assert_eq!(recovery.replayed, 0);
+    assert_eq!(recovery.rolled_back, Some(cut_short.hash()));
+    assert_eq!(saved.block_height(), 1);
+    assert_eq!(
+        unspent(saved.utxos().values()),
+        unspent(blockchain.utxos().values())
+    );
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn checkpoints_keep_what_was_logged_since() {
+    let key = PrivateKey::new_key();
+    let path = log_path();
+    let mut blockchain = Blockchain::regtest();
+    let (wal, _) = Wal::open(&path).unwrap();
+    blockchain.set_wal(Some(wal.clone()));
+    blockchain
+        .add_block(block(&blockchain, &key, at(0), vec![]))
+        .unwrap();
+    let saved_at = wal.len().unwrap();
+    blockchain
+        .add_block(block(&blockchain, &key, at(1), vec![]))
+        .unwrap();
+
+    wal.checkpoint(saved_at).unwrap();
+    let (_, records) = Wal::open(&path).unwrap();
+    assert!(matches!(
+        &records[..],
+        [WalRecord::Apply { height: 1, .. }, WalRecord::Done]
+    ));
+    // logging goes on after the checkpoint
+    blockchain
+        .add_block(block(&blockchain, &key, at(2), vec![]))
+        .unwrap();
+    assert_eq!(Wal::open(&path).unwrap().1.len(), 4);
+
+    wal.checkpoint(wal.len().unwrap()).unwrap();
+    assert!(wal.is_empty().unwrap());
+    fs::remove_file(path).unwrap();
+}
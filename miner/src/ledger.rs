@@ -0,0 +1,200 @@
+//! What the miner earned: every block it found with the reward and the fees its coinbase paid,
+//! kept in a CBOR file, `--ledger`. A block counts once its coinbase is on the node's chain, with
+//! as many confirmations as blocks were built on it, and stops counting if a reorg takes it off
+//! again. Blocks are checked against the chain until they're `SETTLED` blocks deep.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use btclib::sha256::Hash;
+use btclib::types::Amount;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// confirmations after which a block isn't checked anymore, no reorg goes that deep
+pub const SETTLED: u64 = 100;
+
+/// Where a block the miner found stands on the node's chain
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockStatus {
+    /// the coinbase is on the chain, the tip has 1 confirmation
+    Confirmed { confirmations: u64 },
+    /// reorged out, or never made it onto the chain
+    Orphaned,
+}
+
+/// A block the node took from the miner
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FoundBlock {
+    pub hash: Hash,
+    /// where on the chain it was mined, as `FetchBlock` goes
+    pub height: u64,
+    pub found: DateTime<Utc>,
+    /// the hash of its coinbase transaction
+    pub coinbase: Hash,
+    /// the new coins the coinbase paid
+    pub reward: Amount,
+    /// what the coinbase paid on top of the reward
+    pub fees: Amount,
+    pub status: BlockStatus,
+}
+
+impl FoundBlock {
+    /// whether it's deep enough not to be checked anymore
+    pub fn is_settled(&self) -> bool {
+        matches!(self.status, BlockStatus::Confirmed { confirmations } if confirmations >= SETTLED)
+    }
+}
+
+/// What the ledger adds up to, orphaned blocks earn nothing
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Summary {
+    pub found: usize,
+    pub orphaned: usize,
+    pub rewards: Amount,
+    pub fees: Amount,
+}
+
+/// The blocks the miner found, oldest first
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Ledger {
+    pub blocks: Vec<FoundBlock>,
+}
+
+impl Ledger {
+    /// an empty one if there's no file yet
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(data) => Ok(ciborium::from_reader(data.as_slice())?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Ledger::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut data = vec![];
+        ciborium::into_writer(self, &mut data)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        for block in &self.blocks {
+            summary.found += 1;
+            if block.status == BlockStatus::Orphaned {
+                summary.orphaned += 1;
+                continue;
+            }
+            summary.rewards = summary
+                .rewards
+                .checked_add(block.reward)
+                .unwrap_or(Amount::MAX_MONEY);
+            summary.fees = summary
+                .fees
+                .checked_add(block.fees)
+                .unwrap_or(Amount::MAX_MONEY);
+        }
+        summary
+    }
+
+    /// One line per block after a header, amounts in satoshis
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("hash,height,found,coinbase,reward,fees,status,confirmations\n");
+        for block in &self.blocks {
+            let (status, confirmations) = match block.status {
+                BlockStatus::Confirmed { confirmations } => ("confirmed", confirmations),
+                BlockStatus::Orphaned => ("orphaned", 0),
+            };
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{status},{confirmations}\n",
+                block.hash,
+                block.height,
+                block.found.to_rfc3339(),
+                block.coinbase,
+                block.reward.to_sat(),
+                block.fees.to_sat(),
+            ));
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(height: u64, fees: u64, status: BlockStatus) -> FoundBlock {
+        FoundBlock {
+            hash: Hash::of_bytes(&height.to_be_bytes()),
+            height,
+            found: DateTime::from_timestamp(1_700_000_000 + height as i64, 0).unwrap(),
+            coinbase: Hash::of_bytes(&[&height.to_be_bytes()[..], b"coinbase"].concat()),
+            reward: Amount::ONE_BTC,
+            fees: Amount::from_sat(fees),
+            status,
+        }
+    }
+
+    fn ledger() -> Ledger {
+        Ledger {
+            blocks: vec![
+                block(10, 500, BlockStatus::Confirmed { confirmations: 3 }),
+                block(11, 700, BlockStatus::Orphaned),
+                block(12, 0, BlockStatus::Confirmed { confirmations: 1 }),
+            ],
+        }
+    }
+
+    #[test]
+    fn orphaned_blocks_earn_nothing() {
+        let summary = ledger().summary();
+        assert_eq!((summary.found, summary.orphaned), (3, 1));
+        assert_eq!(
+            summary.rewards,
+            Amount::from_sat(2 * Amount::ONE_BTC.to_sat())
+        );
+        assert_eq!(summary.fees, Amount::from_sat(500));
+        assert_eq!(Ledger::default().summary().found, 0);
+    }
+
+    #[test]
+    fn blocks_settle_once_deep_enough() {
+        let at = |confirmations| block(1, 0, BlockStatus::Confirmed { confirmations });
+        assert!(!at(SETTLED - 1).is_settled());
+        assert!(at(SETTLED).is_settled());
+        assert!(!block(1, 0, BlockStatus::Orphaned).is_settled());
+    }
+
+    #[test]
+    fn csv_has_a_line_per_block() {
+        let csv = ledger().to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "hash,height,found,coinbase,reward,fees,status,confirmations"
+        );
+        let first = block(10, 500, BlockStatus::Confirmed { confirmations: 3 });
+        assert_eq!(
+            lines[1],
+            format!(
+                "{},10,2023-11-14T22:13:30+00:00,{},100000000,500,confirmed,3",
+                first.hash, first.coinbase
+            )
+        );
+        assert!(lines[2].ends_with(",100000000,700,orphaned,0"));
+    }
+
+    #[test]
+    fn saved_ledgers_load_again() {
+        let path = std::env::temp_dir().join(format!("miner-ledger-{}.cbor", std::process::id()));
+        assert!(Ledger::load(&path).unwrap().blocks.is_empty());
+
+        ledger().save(&path).unwrap();
+        let loaded = Ledger::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.to_csv(), ledger().to_csv());
+    }
+}
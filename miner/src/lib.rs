@@ -0,0 +1,461 @@
+//! A toy miner, as a library so tests and other programs can mine for a node in-process instead
+//! of starting the binary:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use btclib::{chain_params::ChainParams, crypto::PrivateKey, types::Payout};
+//! let payout = Payout::single(PrivateKey::new_key().public_key());
+//! let ledger = "miner_ledger.cbor".into();
+//! let miner = miner::Miner::new(
+//!     "127.0.0.1:9000".to_string(),
+//!     payout,
+//!     ChainParams::default(),
+//!     None,
+//!     false,
+//!     &[],
+//!     ledger,
+//! )
+//! .await?;
+//! miner.run().await
+//! # }
+//! ```
+
+use anyhow::{Result, anyhow};
+use btclib::{
+    REGTEST_TARGET, U256,
+    chain_params::ChainParams,
+    network::{
+        Capabilities, Encoding, Message, NodeStatus, PROTOCOL_VERSION, check_identity, new_nonce,
+    },
+    output::MinerEvent,
+    types::{Block, Payout, Transaction},
+};
+use chrono::Utc;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::{net::*, sync::Mutex, time::interval};
+
+use crate::ledger::{BlockStatus, FoundBlock, Ledger};
+
+mod ledger;
+
+/// blocks below the tip searched for one the node just took, in case others came right after it
+const FIND_DEPTH: u64 = 6;
+
+/// Say hello to the node and check it's one of the `trusted` ones before mining for it
+async fn handshake(stream: &mut TcpStream, trusted: &[String]) -> Result<()> {
+    let nonce = new_nonce();
+    let hello = Message::Hello {
+        version: PROTOCOL_VERSION,
+        encodings: vec![Encoding::Cbor],
+        time: None,
+        nonce: Some(nonce),
+        capabilities: Capabilities::ZSTD | Capabilities::LZ4,
+    };
+    hello.send_async(stream).await?;
+    match Message::receive_async(stream).await? {
+        Message::Welcome { identity, .. } => {
+            Ok(check_identity(identity.as_ref(), &nonce, trusted)?)
+        }
+        _ => Err(anyhow!("unexpected response from node")),
+    }
+}
+
+/// Mines the templates of one node, paying `payout`, and keeps a ledger of the blocks it found.
+/// `run` is the whole loop, the steps it's made of are there to drive one block at a time
+pub struct Miner {
+    payout: Payout,
+    params: ChainParams,
+    share_target: Option<U256>,
+    json: bool,
+    /// shares accepted since `started`
+    shares: AtomicU64,
+    started: Instant,
+    stream: Mutex<TcpStream>,
+    ledger: Mutex<Ledger>,
+    ledger_path: PathBuf,
+    current_template: Arc<std::sync::Mutex<Option<Block>>>,
+    mining: Arc<AtomicBool>,
+    mined_block_sender: flume::Sender<Block>,
+    mined_block_receiver: flume::Receiver<Block>,
+}
+
+impl Miner {
+    pub async fn new(
+        address: String,
+        payout: Payout,
+        params: ChainParams,
+        share_target: Option<U256>,
+        json: bool,
+        trusted: &[String],
+        ledger_path: PathBuf,
+    ) -> Result<Self> {
+        let mut stream = TcpStream::connect(&address).await?;
+        if !trusted.is_empty() {
+            handshake(&mut stream, trusted).await?;
+        }
+        let ledger = Ledger::load(&ledger_path)
+            .map_err(|e| anyhow!("Error reading ledger {}: {e}", ledger_path.display()))?;
+        let (mined_block_sender, mined_block_receiver) = flume::unbounded();
+        Ok(Self {
+            payout,
+            params,
+            share_target,
+            json,
+            shares: AtomicU64::new(0),
+            started: Instant::now(),
+            stream: Mutex::new(stream),
+            ledger: Mutex::new(ledger),
+            ledger_path,
+            current_template: Arc::new(std::sync::Mutex::new(None)),
+            mining: Arc::new(AtomicBool::new(false)),
+            mined_block_sender,
+            mined_block_receiver,
+        })
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        self.spawn_mining_thread();
+        let mut template_interval = interval(Duration::from_secs(5));
+        loop {
+            tokio::select! {
+                _ = template_interval.tick() => {
+                    self.fetch_and_validate_template().await?;
+                }
+
+                Ok(mined_block) = self.found() => {
+                    self.submit_block(mined_block).await?;
+                }
+            }
+        }
+    }
+
+    /// Mine the current template, if there is one, until a new one comes in
+    pub fn spawn_mining_thread(&self) -> thread::JoinHandle<()> {
+        let template = self.current_template.clone();
+        let mining = self.mining.clone();
+        let sender = self.mined_block_sender.clone();
+        let share_target = self.share_target;
+        let json = self.json;
+
+        thread::spawn(move || {
+            loop {
+                if mining.load(Ordering::Relaxed) {
+                    if let Some(mut block) = template.lock().unwrap().clone() {
+                        let target = share_target.unwrap_or(block.header.target);
+                        let found = block.header.mine_to(target, 2_000_000);
+                        if found && block.hash().matches_target(block.header.target) {
+                            let text = format!(
+                                "Block mined: {}\nTarget was: {}",
+                                block.hash(),
+                                block.header.target
+                            );
+                            let hash = block.hash().to_hex();
+                            report(json, MinerEvent::Mined { hash }, text);
+                            sender.send(block).expect("Failed to send mined block");
+                            mining.store(false, Ordering::Relaxed);
+                            continue;
+                        }
+                        if found {
+                            sender.send(block.clone()).expect("Failed to send share");
+                            block.header.nonce = block.header.nonce.wrapping_add(1);
+                        }
+                        // carry on from here next time, unless a new template came in
+                        if let Some(current) = template.lock().unwrap().as_mut()
+                            && current.header.merkle_root == block.header.merkle_root
+                        {
+                            current.header = block.header;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// the next block or share the mining thread found
+    pub async fn found(&self) -> Result<Block> {
+        Ok(self.mined_block_receiver.recv_async().await?)
+    }
+
+    async fn fetch_and_validate_template(&self) -> Result<()> {
+        if !self.mining.load(Ordering::Relaxed) {
+            self.fetch_template().await?;
+        } else {
+            self.validate_template().await?;
+        }
+        Ok(())
+    }
+
+    /// Get a new template from the node and start mining it. A regtest node takes blocks mined
+    /// at `REGTEST_TARGET` as well, so its templates are mined at that
+    pub async fn fetch_template(&self) -> Result<()> {
+        if !self.json {
+            println!("Fetching new template");
+        }
+        // older nodes only know the messages for a single key
+        let message = match (self.payout.single_key(), self.share_target) {
+            (Some(pubkey), Some(target)) => Message::FetchShareTemplate(pubkey.clone(), target),
+            (Some(pubkey), None) => Message::FetchTemplate(pubkey.clone()),
+            (None, target) => Message::FetchPayoutTemplate(self.payout.clone(), target),
+        };
+        let mut stream_lock = self.stream.lock().await;
+        message.send_async(&mut *stream_lock).await?;
+        // On the highlighted lines, you will see that I am quite paranoid about dropping the
+        // lock as soon as possible. This is a personal choice, I like to not have to worry about
+        // deadlocking my process, but it is likely that you can survive with just one lock for
+        // the entire function. Regardless, it is a good practice to not lock shared resources
+        // longer than absolutely necessary - you are preventing others from working.
+        drop(stream_lock);
+        let mut stream_lock = self.stream.lock().await;
+        match Message::receive_async(&mut *stream_lock).await? {
+            Message::Template(mut template) => {
+                drop(stream_lock);
+                if self.params.regtest {
+                    template.header.target = REGTEST_TARGET;
+                }
+                self.report(
+                    MinerEvent::template(template.header.target),
+                    format!(
+                        "Received new template with target: {}",
+                        template.header.target
+                    ),
+                );
+                *self.current_template.lock().unwrap() = Some(template);
+                self.mining.store(true, Ordering::Relaxed);
+                // a new template means a new tip, which may have reorged blocks out
+                self.reconcile().await
+            }
+            _ => Err(anyhow!(
+                "Unexpected message received then fetching template"
+            )),
+        }
+    }
+
+    async fn validate_template(&self) -> Result<()> {
+        if let Some(template) = self.current_template.lock().unwrap().clone() {
+            let msg = Message::ValidateTemplate(template);
+            let mut stream_lock = self.stream.lock().await;
+            msg.send_async(&mut *stream_lock).await?;
+
+            drop(stream_lock);
+            let mut stream_lock = self.stream.lock().await;
+            match Message::receive_async(&mut *stream_lock).await? {
+                Message::TemplateValidity(valid) => {
+                    drop(stream_lock);
+                    if !valid {
+                        self.report(
+                            MinerEvent::Stale,
+                            "Current template is no longer valid".to_string(),
+                        );
+                        self.mining.store(false, Ordering::Relaxed);
+                    } else if !self.json {
+                        println!("Current template is still valid");
+                    }
+                    Ok(())
+                }
+                _ => Err(anyhow!(
+                    "Unexpected message received then validating template"
+                )),
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Hand a block or share the mining thread found to the node, and record an accepted block in
+    /// the ledger
+    pub async fn submit_block(&self, block: Block) -> Result<()> {
+        let is_block = block.hash().matches_target(block.header.target);
+        if is_block && !self.json {
+            println!("Submitting mined block");
+        }
+        let message = Message::SubmitTemplate(block.clone());
+        let mut stream_lock = self.stream.lock().await;
+        message.send_async(&mut *stream_lock).await?;
+        if is_block {
+            self.mining.store(false, Ordering::Relaxed);
+        }
+        match Message::receive_async(&mut *stream_lock).await? {
+            Message::Ack(hash) => {
+                drop(stream_lock);
+                let text = format!("Block {hash} accepted");
+                let hash = hash.to_hex();
+                self.report(MinerEvent::Accepted { hash }, text);
+                self.record_found(&block).await
+            }
+            Message::ShareAccepted(hash) => {
+                let shares = self.shares.fetch_add(1, Ordering::Relaxed) + 1;
+                let hashrate = self.hashrate(shares);
+                let text =
+                    format!("Share {hash} accepted, {shares} so far, about {hashrate:.0} H/s");
+                let hash = hash.to_hex();
+                self.report(
+                    MinerEvent::Share {
+                        hash,
+                        shares,
+                        hashrate,
+                    },
+                    text,
+                );
+                Ok(())
+            }
+            // a rejected block is not fatal, the next template will build on whatever won
+            Message::Error { code, reason } => {
+                let text = format!("Block rejected ({code:?}): {reason}");
+                let code = format!("{code:?}");
+                self.report(MinerEvent::Rejected { code, reason }, text);
+                Ok(())
+            }
+            _ => Err(anyhow!("Unexpected message received then submitting block")),
+        }
+    }
+
+    fn report(&self, event: MinerEvent, text: String) {
+        report(self.json, event, text)
+    }
+
+    async fn ask(&self, message: Message) -> Result<Message> {
+        let mut stream = self.stream.lock().await;
+        message.send_async(&mut *stream).await?;
+        Ok(Message::receive_async(&mut *stream).await?)
+    }
+
+    async fn status(&self) -> Result<NodeStatus> {
+        match self.ask(Message::GetStatus).await? {
+            Message::Status(status) => Ok(*status),
+            _ => Err(anyhow!("Unexpected message received then fetching status")),
+        }
+    }
+
+    async fn fetch_block(&self, height: u64) -> Result<Block> {
+        match self.ask(Message::FetchBlock(height as usize)).await? {
+            Message::NewBlock(block) => Ok(block),
+            _ => Err(anyhow!("Unexpected message received then fetching block")),
+        }
+    }
+
+    /// Add a block the node just took to the ledger, at the height it ended up at
+    async fn record_found(&self, block: &Block) -> Result<()> {
+        let hash = block.hash();
+        let status = self.status().await?;
+        let mut height = None;
+        for index in (status.height.saturating_sub(FIND_DEPTH)..status.height).rev() {
+            if status.best_hash == hash || self.fetch_block(index).await?.hash() == hash {
+                height = Some(index);
+                break;
+            }
+        }
+        let (height, block_status) = match height {
+            Some(height) => (
+                height,
+                BlockStatus::Confirmed {
+                    confirmations: status.height - height,
+                },
+            ),
+            // taken and beaten to it already, it was built on the tip it got the template at
+            None => (status.height.saturating_sub(1), BlockStatus::Orphaned),
+        };
+        let coinbase = &block.transactions[0];
+        let reward = self.params.block_reward(height);
+        let found = FoundBlock {
+            hash,
+            height,
+            found: Utc::now(),
+            coinbase: coinbase.hash(),
+            reward,
+            fees: coinbase.output_value()?.saturating_sub(reward),
+            status: block_status,
+        };
+        let mut ledger = self.ledger.lock().await;
+        ledger.blocks.push(found);
+        ledger.save(&self.ledger_path)
+    }
+
+    /// Check the blocks of the ledger that aren't settled against the node's chain: confirmed
+    /// while the block at their height still has their coinbase, orphaned once it doesn't
+    pub async fn reconcile(&self) -> Result<()> {
+        let status = self.status().await?;
+        let mut ledger = self.ledger.lock().await;
+        let mut orphaned = vec![];
+        let mut changed = false;
+        for found in ledger.blocks.iter_mut().filter(|found| !found.is_settled()) {
+            let on_chain = found.height < status.height && {
+                let block = self.fetch_block(found.height).await?;
+                block.hash() == found.hash
+                    && block.transactions.first().map(Transaction::hash) == Some(found.coinbase)
+            };
+            let block_status = if on_chain {
+                BlockStatus::Confirmed {
+                    confirmations: status.height - found.height,
+                }
+            } else {
+                BlockStatus::Orphaned
+            };
+            if block_status == BlockStatus::Orphaned && found.status != BlockStatus::Orphaned {
+                orphaned.push(found.hash);
+            }
+            changed |= block_status != found.status;
+            found.status = block_status;
+        }
+        if changed {
+            ledger.save(&self.ledger_path)?;
+        }
+        drop(ledger);
+        for hash in orphaned {
+            let text = format!("Block {hash} was reorged out, its reward is lost");
+            let hash = hash.to_hex();
+            self.report(MinerEvent::Orphaned { hash }, text);
+        }
+        Ok(())
+    }
+
+    /// a line on what the ledger adds up to
+    pub async fn ledger_summary(&self) -> String {
+        let summary = self.ledger.lock().await.summary();
+        format!(
+            "{} blocks found, {} orphaned, earned {} in rewards and {} in fees",
+            summary.found, summary.orphaned, summary.rewards, summary.fees
+        )
+    }
+
+    /// the ledger as CSV, see `Ledger::to_csv`
+    pub async fn ledger_csv(&self) -> String {
+        self.ledger.lock().await.to_csv()
+    }
+
+    /// hashes per second it takes to find `shares` shares since starting, on average
+    fn hashrate(&self, shares: u64) -> f64 {
+        let Some(target) = self.share_target else {
+            return 0.0;
+        };
+        let as_f64 = |n: U256| {
+            n.0.iter()
+                .rev()
+                .fold(0.0, |acc, limb| acc * 2f64.powi(64) + *limb as f64)
+        };
+        // a hash meets the target once in 2^256 / (target + 1) tries
+        let hashes_per_share = as_f64(U256::MAX) / (as_f64(target) + 1.0);
+        shares as f64 * hashes_per_share / self.started.elapsed().as_secs_f64()
+    }
+}
+
+/// print `event` as JSON with --json, `text` otherwise
+fn report(json: bool, event: MinerEvent, text: String) {
+    if json {
+        match serde_json::to_string(&event) {
+            Ok(line) => println!("{line}"),
+            Err(e) => eprintln!("Failed to print {event:?}: {e}"),
+        }
+    } else {
+        println!("{text}");
+    }
+}
@@ -1,174 +1,129 @@
 use anyhow::{Result, anyhow};
-use btclib::{crypto::PublicKey, network::Message, types::Block, util::Saveable};
-use std::sync::atomic::Ordering;
-use std::{
-    sync::{Arc, atomic::AtomicBool},
-    thread,
-    time::Duration,
+use btclib::{
+    U256,
+    chain_params::ChainParams,
+    crypto::PublicKey,
+    types::{Payout, PayoutSplit},
+    util::Saveable,
 };
+use miner::Miner;
+use std::path::PathBuf;
 
 use clap::Parser;
 
-use tokio::{net::*, sync::Mutex, time::interval};
-
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[arg(short, long)]
     address: String,
-    #[arg(short, long)]
-    public_key_file: String,
+    #[arg(
+        short,
+        long,
+        required_unless_present = "payout",
+        conflicts_with = "payout"
+    )]
+    public_key_file: Option<String>,
+    /// Split the reward between keys instead, as KEY_FILE=PERCENT with the percents adding up to
+    /// 100, e.g. `--payout alice.pub.pem=60 --payout bob.pub.pem=40`
+    #[arg(long, value_name = "KEY_FILE=PERCENT", value_parser = parse_split)]
+    payout: Vec<PayoutSplit>,
+    /// Also submit hashes meeting this easier target, in hex, as shares to measure the hashrate
+    #[arg(short, long, value_parser = parse_target)]
+    share_target: Option<U256>,
+    /// print one line of JSON per event instead of text, see `btclib::output::MinerEvent`
+    #[arg(long)]
+    json: bool,
+    /// only mine for a node that proves it has one of these identities, as `node --status`
+    /// shows them. Can be given more than once
+    #[arg(long, value_name = "IDENTITY")]
+    trusted_node: Vec<String>,
+    /// where the blocks found and what they earned are kept, see `ledger`
+    #[arg(long, value_name = "FILE", default_value = "miner_ledger.cbor")]
+    ledger: PathBuf,
+    /// check the ledger against the node's chain, write it to this CSV file and exit
+    #[arg(long, value_name = "FILE")]
+    export_ledger: Option<PathBuf>,
+    /// chain spec file of the node's network, to tell the reward from the fees
+    #[arg(long, value_name = "FILE")]
+    chainspec: Option<PathBuf>,
 }
 
-struct Miner {
-    public_key: PublicKey,
-    stream: Mutex<TcpStream>,
-    current_template: Arc<std::sync::Mutex<Option<Block>>>,
-    mining: Arc<AtomicBool>,
-    mined_block_sender: flume::Sender<Block>,
-    mined_block_receiver: flume::Receiver<Block>,
+fn parse_split(split: &str) -> Result<PayoutSplit> {
+    let (file, percent) = split
+        .rsplit_once('=')
+        .ok_or_else(|| anyhow!("expected KEY_FILE=PERCENT, got {split}"))?;
+    let percent = percent
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|e| anyhow!("invalid percent {percent}: {e}"))?;
+    let pubkey = PublicKey::load_from_file(file)
+        .map_err(|e| anyhow!("Error reading public key {file}: {e}"))?;
+    Ok(PayoutSplit { pubkey, percent })
 }
 
-impl Miner {
-    async fn new(address: String, public_key: PublicKey) -> Result<Self> {
-        let stream = TcpStream::connect(&address).await?;
-        let (mined_block_sender, mined_block_receiver) = flume::unbounded();
-        Ok(Self {
-            public_key,
-            stream: Mutex::new(stream),
-            current_template: Arc::new(std::sync::Mutex::new(None)),
-            mining: Arc::new(AtomicBool::new(false)),
-            mined_block_sender,
-            mined_block_receiver,
-        })
-    }
-
-    async fn run(&self) -> Result<()> {
-        self.spawn_mining_thread();
-        let mut template_interval = interval(Duration::from_secs(5));
-        loop {
-            let receiver_clone = self.mined_block_receiver.clone();
-            tokio::select! {
-                _ = template_interval.tick() => {
-                    self.fetch_and_validate_template().await?;
-                }
-
-                Ok(mined_block) = receiver_clone.recv_async() => {
-                    self.submit_block(mined_block).await?;
-                }
-            }
-        }
-    }
+fn parse_target(hex: &str) -> Result<U256> {
+    U256::from_str_radix(hex, 16).map_err(|e| anyhow!("invalid target: {e:?}"))
+}
 
-    fn spawn_mining_thread(&self) -> thread::JoinHandle<()> {
-        let template = self.current_template.clone();
-        let mining = self.mining.clone();
-        let sender = self.mined_block_sender.clone();
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
 
-        thread::spawn(move || {
-            loop {
-                if mining.load(Ordering::Relaxed) {
-                    if let Some(mut block) = template.lock().unwrap().clone() {
-                        if block.header.mine(2_000_000) {
-                            println!("Block mined: {}", block.hash());
-                            println!("Target was: {}", block.header.target);
-                            sender.send(block).expect("Failed to send mined block");
-                            mining.store(false, Ordering::Relaxed);
-                        }
-                    }
-                }
+    let payout = match cli.public_key_file {
+        Some(file) => {
+            let public_key = PublicKey::load_from_file(&file)
+                .map_err(|e| anyhow!("Error reading public key: {}", e))?;
+            if !cli.json {
+                println!("Connecting to {} to mine with {file}", cli.address);
             }
-        })
-    }
-
-    async fn fetch_and_validate_template(&self) -> Result<()> {
-        if !self.mining.load(Ordering::Relaxed) {
-            self.fetch_template().await?;
-        } else {
-            self.validate_template().await?;
+            Payout::single(public_key)
         }
-        Ok(())
-    }
-
-    async fn fetch_template(&self) -> Result<()> {
-        println!("Fetching new template");
-        let message = Message::FetchTemplate(self.public_key.clone());
-        let mut stream_lock = self.stream.lock().await;
-        message.send_async(&mut *stream_lock).await?;
-        // On the highlighted lines, you will see that I am quite paranoid about dropping the
-        // lock as soon as possible. This is a personal choice, I like to not have to worry about
-        // deadlocking my process, but it is likely that you can survive with just one lock for
-        // the entire function. Regardless, it is a good practice to not lock shared resources
-        // longer than absolutely necessary - you are preventing others from working.
-        drop(stream_lock);
-        let mut stream_lock = self.stream.lock().await;
-        match Message::receive_async(&mut *stream_lock).await? {
-            Message::Template(template) => {
-                drop(stream_lock);
+        None => {
+            let payout = Payout::new(cli.payout)?;
+            if !cli.json {
+                let percents: Vec<String> = payout
+                    .splits()
+                    .iter()
+                    .map(|split| format!("{}%", split.percent))
+                    .collect();
                 println!(
-                    "Received new template with target: {}",
-                    template.header.target
+                    "Connecting to {} to mine for {} keys, sharing {}",
+                    cli.address,
+                    percents.len(),
+                    percents.join("/")
                 );
-                *self.current_template.lock().unwrap() = Some(template);
-                self.mining.store(true, Ordering::Relaxed);
-                Ok(())
             }
-            _ => Err(anyhow!(
-                "Unexpected message received then fetching template"
-            )),
+            payout
         }
+    };
+
+    let params = match &cli.chainspec {
+        Some(path) => ChainParams::load_from_file(path)?,
+        None => ChainParams::default(),
+    };
+    let miner = Miner::new(
+        cli.address,
+        payout,
+        params,
+        cli.share_target,
+        cli.json,
+        &cli.trusted_node,
+        cli.ledger,
+    )
+    .await?;
+    if let Some(path) = cli.export_ledger {
+        miner.reconcile().await?;
+        std::fs::write(&path, miner.ledger_csv().await)?;
+        println!(
+            "{}, written to {}",
+            miner.ledger_summary().await,
+            path.display()
+        );
+        return Ok(());
     }
-
-    async fn validate_template(&self) -> Result<()> {
-        if let Some(template) = self.current_template.lock().unwrap().clone() {
-            let msg = Message::ValidateTemplate(template);
-            let mut stream_lock = self.stream.lock().await;
-            msg.send_async(&mut *stream_lock).await?;
-
-            drop(stream_lock);
-            let mut stream_lock = self.stream.lock().await;
-            match Message::receive_async(&mut *stream_lock).await? {
-                Message::TemplateValidity(valid) => {
-                    drop(stream_lock);
-                    if !valid {
-                        println!("Current template is no longer valid");
-                        self.mining.store(false, Ordering::Relaxed);
-                    } else {
-                        println!("Current template is still valid");
-                    }
-                    Ok(())
-                }
-                _ => Err(anyhow!(
-                    "Unexpected message received then validating template"
-                )),
-            }
-        } else {
-            Ok(())
-        }
-    }
-
-    async fn submit_block(&self, block: Block) -> Result<()> {
-        println!("Submitting mined block");
-        let message = Message::SubmitTemplate(block);
-        let mut stream_lock = self.stream.lock().await;
-        message.send_async(&mut *stream_lock).await?;
-        self.mining.store(false, Ordering::Relaxed);
-        Ok(())
+    if !cli.json {
+        println!("Ledger: {}", miner.ledger_summary().await);
     }
-}
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
-
-    println!(
-        "Connecting to {} to mine with {}",
-        cli.address, cli.public_key_file
-    );
-
-    let public_key = PublicKey::load_from_file(&cli.public_key_file)
-        .map_err(|e| anyhow!("Error reading public key: {}", e))?;
-    let miner = Miner::new(cli.address, public_key).await?;
     miner.run().await
 }
 
@@ -0,0 +1,350 @@
+//! A local channel for whoever runs the node, over a Unix socket only they can open. Next to the
+//! socket the node writes a cookie file with a random secret. A client sends the cookie as its
+//! first line, then one command per line, e.g.
+//!
+//! ```text
+//! $ (cat node.sock.cookie; echo; echo dumpmempool) | socat - UNIX-CONNECT:node.sock
+//! ```
+//!
+//! Each command gets one line of JSON back, see `AdminReply`.
+
+use anyhow::{Context, Result, anyhow, bail};
+use btclib::sha256::Hash;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::{ErrorKind, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream, unix::OwnedWriteHalf},
+    task::JoinHandle,
+};
+
+use crate::NodeContext;
+use crate::banlist::{Ban, DEFAULT_BAN_DURATION};
+use crate::log::LogLevel;
+
+/// lines `logs` sends without being told how many
+const DEFAULT_LOG_LINES: usize = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdminCommand {
+    /// disconnect an IP address, a port is ignored, and refuse it for `DEFAULT_BAN_DURATION`
+    Ban(IpAddr),
+    Unban(IpAddr),
+    ListBans,
+    /// lift every ban
+    ClearBans,
+    /// take a block and everything after it off the chain, and refuse it from now on
+    InvalidateBlock(Hash),
+    /// undo `InvalidateBlock`, the chain switches back if that's longer
+    ReconsiderBlock(Hash),
+    SetLogLevel(LogLevel),
+    /// write the chain to the store now
+    Save,
+    DumpMempool,
+    /// the nodes in the pool and how quickly they answer
+    Peers,
+    /// the last lines the node logged, `LOG_TAIL` at most
+    Logs(usize),
+    /// save the chain and stop listening
+    Shutdown,
+}
+
+/// The answer to an `AdminCommand`, e.g. `"Done"` or `{"Error":"the node has no store"}`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum AdminReply {
+    Done,
+    Mempool(Vec<MempoolEntry>),
+    Bans(Vec<Ban>),
+    Peers(Vec<PeerEntry>),
+    Logs(Vec<String>),
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PeerEntry {
+    pub addr: String,
+    /// how long it last took to answer, none until it has
+    pub latency_ms: Option<u64>,
+    /// what it said it supports, e.g. "filters,checkpoints"
+    pub capabilities: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MempoolEntry {
+    /// hex, like everywhere people read it
+    pub txid: String,
+    pub size: usize,
+    /// in sats
+    pub fee: u64,
+    pub received: DateTime<Utc>,
+}
+
+/// where the cookie for the socket at `socket` is written
+pub fn cookie_path(socket: &Path) -> PathBuf {
+    let mut path = socket.as_os_str().to_owned();
+    path.push(".cookie");
+    PathBuf::from(path)
+}
+
+fn parse_ip(s: &str) -> Result<IpAddr, String> {
+    s.parse::<SocketAddr>()
+        .map(|addr| addr.ip())
+        .or_else(|_| s.parse())
+        .map_err(|_| format!("{s} is not an IP address"))
+}
+
+impl FromStr for AdminCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or_default().to_lowercase();
+        let argument = words.next();
+        if words.next().is_some() {
+            return Err(format!("too many arguments to {command}"));
+        }
+        let hash = |s: &str| Hash::from_hex(s).map_err(|e| e.to_string());
+        match (command.as_str(), argument) {
+            ("ban", Some(peer)) => Ok(AdminCommand::Ban(parse_ip(peer)?)),
+            ("unban", Some(peer)) => Ok(AdminCommand::Unban(parse_ip(peer)?)),
+            ("invalidateblock", Some(block)) => Ok(AdminCommand::InvalidateBlock(hash(block)?)),
+            ("reconsiderblock", Some(block)) => Ok(AdminCommand::ReconsiderBlock(hash(block)?)),
+            ("setloglevel", Some(level)) => Ok(AdminCommand::SetLogLevel(level.parse()?)),
+            ("listbans", None) => Ok(AdminCommand::ListBans),
+            ("clearbans", None) => Ok(AdminCommand::ClearBans),
+            ("save", None) => Ok(AdminCommand::Save),
+            ("dumpmempool", None) => Ok(AdminCommand::DumpMempool),
+            ("peers", None) => Ok(AdminCommand::Peers),
+            ("logs", None) => Ok(AdminCommand::Logs(DEFAULT_LOG_LINES)),
+            ("logs", Some(count)) => count
+                .parse()
+                .map(AdminCommand::Logs)
+                .map_err(|_| format!("{count} is not a number of lines")),
+            ("shutdown", None) => Ok(AdminCommand::Shutdown),
+            ("ban" | "unban" | "invalidateblock" | "reconsiderblock" | "setloglevel", None) => {
+                Err(format!("{command} needs an argument"))
+            }
+            ("listbans" | "clearbans" | "save" | "dumpmempool" | "peers" | "shutdown", Some(_)) => {
+                Err(format!("{command} takes no argument"))
+            }
+            _ => Err(format!("unknown command {command}")),
+        }
+    }
+}
+
+impl fmt::Display for AdminCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminCommand::Ban(ip) => write!(f, "ban {ip}"),
+            AdminCommand::Unban(ip) => write!(f, "unban {ip}"),
+            AdminCommand::ListBans => write!(f, "listbans"),
+            AdminCommand::ClearBans => write!(f, "clearbans"),
+            AdminCommand::InvalidateBlock(hash) => write!(f, "invalidateblock {hash}"),
+            AdminCommand::ReconsiderBlock(hash) => write!(f, "reconsiderblock {hash}"),
+            AdminCommand::SetLogLevel(level) => write!(f, "setloglevel {level}"),
+            AdminCommand::Save => write!(f, "save"),
+            AdminCommand::DumpMempool => write!(f, "dumpmempool"),
+            AdminCommand::Peers => write!(f, "peers"),
+            AdminCommand::Logs(count) => write!(f, "logs {count}"),
+            AdminCommand::Shutdown => write!(f, "shutdown"),
+        }
+    }
+}
+
+/// Listen on `socket`, replacing whatever a previous run left there, and write a new cookie
+pub(crate) fn listen(ctx: Arc<NodeContext>, socket: &Path) -> Result<JoinHandle<()>> {
+    match fs::remove_file(socket) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let listener = UnixListener::bind(socket)
+        .with_context(|| format!("failed to bind the admin socket {}", socket.display()))?;
+    fs::set_permissions(socket, Permissions::from_mode(0o600))?;
+
+    let cookie = uuid::Uuid::new_v4().simple().to_string();
+    let cookie_path = cookie_path(socket);
+    let _ = fs::remove_file(&cookie_path);
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&cookie_path)?
+        .write_all(cookie.as_bytes())?;
+    info!(ctx, "admin socket at {}", socket.display());
+
+    let cookie = Arc::new(cookie);
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(ctx.clone(), stream, cookie.clone()));
+                }
+                Err(e) => warn!(ctx, "admin socket failed to accept: {e}"),
+            }
+        }
+    }))
+}
+
+async fn serve(ctx: Arc<NodeContext>, stream: UnixStream, cookie: Arc<String>) {
+    if let Err(e) = serve_commands(&ctx, stream, &cookie).await {
+        warn!(ctx, "admin connection failed: {e:#}");
+    }
+}
+
+async fn serve_commands(ctx: &NodeContext, stream: UnixStream, cookie: &str) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    if lines.next_line().await?.as_deref().map(str::trim) != Some(cookie) {
+        warn!(ctx, "admin connection with a wrong cookie");
+        let wrong = AdminReply::Error("wrong cookie".to_string());
+        return send_reply(&mut writer, &wrong).await;
+    }
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match line.parse() {
+            Ok(command) => command,
+            Err(e) => {
+                send_reply(&mut writer, &AdminReply::Error(e)).await?;
+                continue;
+            }
+        };
+        info!(ctx, "admin: {command}");
+        send_reply(&mut writer, &run(ctx, &command).await).await?;
+        if command == AdminCommand::Shutdown {
+            ctx.shutdown.notify_one();
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn send_reply(writer: &mut OwnedWriteHalf, reply: &AdminReply) -> Result<()> {
+    let mut line = serde_json::to_vec(reply)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+async fn run(ctx: &NodeContext, command: &AdminCommand) -> AdminReply {
+    match command {
+        AdminCommand::Ban(ip) => {
+            let until = Utc::now() + DEFAULT_BAN_DURATION;
+            let closed = ctx.ban(*ip, until, "banned by the operator");
+            info!(ctx, "banned {ip}, closed {closed} connections");
+            AdminReply::Done
+        }
+        AdminCommand::Unban(ip) => {
+            if ctx.bans.unban(ip) {
+                ctx.save_bans();
+                AdminReply::Done
+            } else {
+                AdminReply::Error(format!("{ip} is not banned"))
+            }
+        }
+        AdminCommand::ListBans => AdminReply::Bans(ctx.bans.list()),
+        AdminCommand::ClearBans => {
+            let cleared = ctx.bans.clear();
+            ctx.save_bans();
+            info!(ctx, "lifted {cleared} bans");
+            AdminReply::Done
+        }
+        AdminCommand::InvalidateBlock(hash) => {
+            match ctx.blockchain.write().await.invalidate_block(hash) {
+                Ok(removed) => {
+                    info!(
+                        ctx,
+                        "invalidated block {hash}, took {removed} blocks off the chain"
+                    );
+                    AdminReply::Done
+                }
+                Err(e) => AdminReply::Error(e.to_string()),
+            }
+        }
+        AdminCommand::ReconsiderBlock(hash) => {
+            match ctx.blockchain.write().await.reconsider_block(hash) {
+                Ok(added) => {
+                    info!(
+                        ctx,
+                        "reconsidered block {hash}, put {added} blocks back on the chain"
+                    );
+                    AdminReply::Done
+                }
+                Err(e) => AdminReply::Error(e.to_string()),
+            }
+        }
+        AdminCommand::SetLogLevel(level) => {
+            ctx.log.set_level(*level);
+            AdminReply::Done
+        }
+        AdminCommand::Save | AdminCommand::Shutdown => match &ctx.store {
+            Some(path) => match crate::util::save_blockchain(ctx, path).await {
+                Ok(()) => AdminReply::Done,
+                Err(e) => AdminReply::Error(format!("{e:#}")),
+            },
+            None if *command == AdminCommand::Shutdown => AdminReply::Done,
+            None => AdminReply::Error("the node has no store".to_string()),
+        },
+        AdminCommand::DumpMempool => {
+            let blockchain = ctx.blockchain.read().await;
+            let entries = blockchain
+                .mempool()
+                .iter()
+                .map(|(transaction, received)| MempoolEntry {
+                    txid: transaction.hash().to_hex(),
+                    size: transaction.size(),
+                    fee: blockchain.mempool_fee(transaction).to_sat(),
+                    received,
+                })
+                .collect();
+            AdminReply::Mempool(entries)
+        }
+        AdminCommand::Peers => {
+            let mut nodes: Vec<String> = ctx.nodes.iter().map(|node| node.key().clone()).collect();
+            nodes.sort();
+            let peers = nodes
+                .into_iter()
+                .map(|addr| PeerEntry {
+                    latency_ms: ctx
+                        .peer_latency
+                        .get(&addr)
+                        .map(|latency| latency.as_millis() as u64),
+                    capabilities: ctx
+                        .peer_capabilities
+                        .get(&addr)
+                        .map(|capabilities| capabilities.to_string())
+                        .unwrap_or_default(),
+                    addr,
+                })
+                .collect();
+            AdminReply::Peers(peers)
+        }
+        AdminCommand::Logs(count) => AdminReply::Logs(ctx.log.recent(*count)),
+    }
+}
+
+/// Send one command to the node listening on `socket`, with the cookie next to it
+pub async fn request(socket: &Path, command: &AdminCommand) -> Result<AdminReply> {
+    let cookie = fs::read_to_string(cookie_path(socket))
+        .with_context(|| format!("failed to read the cookie of {}", socket.display()))?;
+    let stream = UnixStream::connect(socket)
+        .await
+        .with_context(|| format!("failed to connect to {}", socket.display()))?;
+    let (reader, mut writer) = stream.into_split();
+    writer
+        .write_all(format!("{}\n{command}\n", cookie.trim()).as_bytes())
+        .await?;
+    let Some(line) = BufReader::new(reader).lines().next_line().await? else {
+        bail!("the node hung up without replying");
+    };
+    serde_json::from_str(&line).map_err(|e| anyhow!("invalid reply {line}: {e}"))
+}
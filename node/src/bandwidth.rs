@@ -0,0 +1,88 @@
+//! What goes over each connection, and how fast it may. Every connection the node serves counts
+//! its frames by message, and with `RateLimits` each is held to its own upload and download rate,
+//! so a peer downloading the whole chain can't take a home connection's whole uplink. What the
+//! node relays to the nodes in its pool is counted for them too, but never held back. Connections
+//! that closed are added into the node's totals.
+
+use btclib::network::{Throttle, Traffic, TrafficMeter, TrafficStats};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Bytes a second each connection may send or receive, no limit when None
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RateLimits {
+    /// what the node sends to one peer
+    pub upload: Option<u64>,
+    /// what it reads from one peer
+    pub download: Option<u64>,
+}
+
+/// The node's traffic as `GET /traffic` serves it
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct TrafficReport {
+    /// every connection there has been
+    pub total: TrafficStats,
+    /// the ones still open, by address
+    pub peers: BTreeMap<String, TrafficStats>,
+}
+
+#[derive(Default)]
+pub(crate) struct Bandwidth {
+    pub limits: RateLimits,
+    peers: DashMap<String, Arc<TrafficMeter>>,
+    /// what connections counted before they closed
+    closed: Mutex<TrafficStats>,
+}
+
+impl Bandwidth {
+    pub fn new(limits: RateLimits) -> Self {
+        Bandwidth {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    /// where `peer`'s frames are counted, kept until it `disconnect`s
+    pub fn meter(&self, peer: &str) -> Arc<TrafficMeter> {
+        self.peers.entry(peer.to_string()).or_default().clone()
+    }
+
+    /// a throttle for a new connection's uploads, if they're limited
+    pub fn upload_throttle(&self) -> Option<Arc<Throttle>> {
+        self.limits.upload.map(|rate| Arc::new(Throttle::new(rate)))
+    }
+
+    /// a throttle for a new connection's downloads, if they're limited
+    pub fn download_throttle(&self) -> Option<Arc<Throttle>> {
+        self.limits
+            .download
+            .map(|rate| Arc::new(Throttle::new(rate)))
+    }
+
+    /// add what `peer` counted to the totals and forget it
+    pub fn disconnect(&self, peer: &str) {
+        if let Some((_, meter)) = self.peers.remove(peer) {
+            self.closed.lock().unwrap().merge(&meter.stats());
+        }
+    }
+
+    pub fn report(&self) -> TrafficReport {
+        let peers: BTreeMap<_, _> = self
+            .peers
+            .iter()
+            .map(|meter| (meter.key().clone(), meter.stats()))
+            .collect();
+        let mut total = self.closed.lock().unwrap().clone();
+        for stats in peers.values() {
+            total.merge(stats);
+        }
+        TrafficReport { total, peers }
+    }
+
+    /// both ways over every connection there has been
+    pub fn total(&self) -> Traffic {
+        self.report().total.total
+    }
+}
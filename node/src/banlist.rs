@@ -0,0 +1,159 @@
+//! Peers the node refuses, saved as JSON so a restart doesn't let them right back in. Bans
+//! expire, after `DEFAULT_BAN_DURATION` unless they were made for longer.
+//!
+//! Peers relaying invalid blocks or transactions also collect misbehavior points. Points halve
+//! every `SCORE_HALF_LIFE`, so a peer relaying something invalid once in a while is fine, and one
+//! reaching `BAN_THRESHOLD` gets banned. Scores are only kept in memory.
+
+use anyhow::{Context, Result};
+use btclib::error::BtcError;
+use btclib::network::ErrorCode;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// how long a ban lasts
+pub const DEFAULT_BAN_DURATION: Duration = Duration::hours(24);
+/// misbehavior points a peer is banned at
+pub const BAN_THRESHOLD: f64 = 100.0;
+/// how long it takes a peer's points to halve
+pub const SCORE_HALF_LIFE: Duration = Duration::hours(1);
+/// points for relaying a block breaking consensus rules
+pub const INVALID_BLOCK_POINTS: f64 = 60.0;
+/// points for relaying a transaction breaking consensus rules
+pub const INVALID_TRANSACTION_POINTS: f64 = 20.0;
+/// points for relaying a checkpoint none of the checkpoint authorities signed
+pub const UNSIGNED_CHECKPOINT_POINTS: f64 = 20.0;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Ban {
+    pub ip: IpAddr,
+    pub until: DateTime<Utc>,
+    pub reason: String,
+}
+
+pub(crate) struct BanList {
+    bans: DashMap<IpAddr, Ban>,
+    /// points with when they were last added to
+    scores: DashMap<IpAddr, (f64, DateTime<Utc>)>,
+    /// where the bans are saved, nowhere if `None`
+    path: Option<PathBuf>,
+}
+
+impl BanList {
+    /// The bans saved at `path` that haven't expired yet, none if there's no file there
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let banlist = BanList {
+            bans: DashMap::new(),
+            scores: DashMap::new(),
+            path,
+        };
+        let Some(path) = &banlist.path else {
+            return Ok(banlist);
+        };
+        let bans: Vec<Ban> = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        let now = Utc::now();
+        for ban in bans.into_iter().filter(|ban| ban.until > now) {
+            banlist.bans.insert(ban.ip, ban);
+        }
+        Ok(banlist)
+    }
+
+    pub fn ban(&self, ip: IpAddr, until: DateTime<Utc>, reason: impl Into<String>) {
+        let reason = reason.into();
+        self.bans.insert(ip, Ban { ip, until, reason });
+        self.scores.remove(&ip);
+    }
+
+    /// whether `ip` was banned
+    pub fn unban(&self, ip: &IpAddr) -> bool {
+        self.scores.remove(ip);
+        self.bans.remove(ip).is_some()
+    }
+
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        self.bans.get(ip).is_some_and(|ban| ban.until > Utc::now())
+    }
+
+    /// the bans still in force, by address
+    pub fn list(&self) -> Vec<Ban> {
+        let now = Utc::now();
+        self.bans.retain(|_, ban| ban.until > now);
+        let mut bans: Vec<Ban> = self.bans.iter().map(|ban| ban.clone()).collect();
+        bans.sort_by_key(|ban| ban.ip);
+        bans
+    }
+
+    /// Forget expired bans, and scores that faded under a point. Returns how many bans expired
+    pub fn prune(&self) -> usize {
+        let now = Utc::now();
+        let before = self.bans.len();
+        self.bans.retain(|_, ban| ban.until > now);
+        self.scores
+            .retain(|_, (points, since)| decayed(*points, *since, now) >= 1.0);
+        before - self.bans.len()
+    }
+
+    /// lift every ban, returns how many there were
+    pub fn clear(&self) -> usize {
+        let cleared = self.bans.len();
+        self.bans.clear();
+        self.scores.clear();
+        cleared
+    }
+
+    /// Add `points` to what `ip` has left, returns whether that's enough to ban it
+    pub fn misbehaving(&self, ip: IpAddr, points: f64) -> bool {
+        let now = Utc::now();
+        let mut score = self.scores.entry(ip).or_insert((0.0, now));
+        let (points_left, since) = *score;
+        *score = (decayed(points_left, since, now) + points, now);
+        score.0 >= BAN_THRESHOLD
+    }
+
+    /// Write the bans to the file. The changes above leave that to the caller, see
+    /// `NodeContext::save_bans`
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut bans: Vec<Ban> = self.bans.iter().map(|ban| ban.clone()).collect();
+        bans.sort_by_key(|ban| ban.ip);
+        let write = || -> Result<()> {
+            // replace the file at once, a crash halfway through would lose every ban
+            let temp = path.with_extension("json.tmp");
+            fs::write(&temp, serde_json::to_vec_pretty(&bans)?)?;
+            fs::rename(temp, path)?;
+            Ok(())
+        };
+        write().with_context(|| format!("failed to save the ban list to {}", path.display()))
+    }
+}
+
+/// Whether a peer relaying something refused with `error` broke the rules, rather than being
+/// behind or having a different mempool
+pub(crate) fn is_misbehavior(error: &BtcError) -> bool {
+    match error {
+        // only this node thinks so
+        BtcError::InvalidatedBlock(_) => false,
+        _ => matches!(
+            ErrorCode::from(error),
+            ErrorCode::BadSignature | ErrorCode::InsufficientFunds | ErrorCode::Invalid
+        ),
+    }
+}
+
+/// what's left `now` of `points` added `since`
+fn decayed(points: f64, since: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+    let half_lives =
+        (now - since).num_milliseconds().max(0) as f64 / SCORE_HALF_LIFE.num_milliseconds() as f64;
+    points * 0.5f64.powf(half_lives)
+}
@@ -0,0 +1,134 @@
+//! Federated checkpoints on the node's side, see `btclib::types::SignedCheckpoint`. A checkpoint
+//! short of signatures is kept until more come in, only the highest one seen so far. Every time
+//! the node learns a signature it didn't have, it passes the checkpoint on, so authorities that
+//! peer with each other co-sign what the others sign. A node run with an authority's key signs
+//! its tip every so often, and signs the checkpoints it's sent for blocks of its chain.
+
+use anyhow::{Context, Result};
+use btclib::chain_params::ChainParams;
+use btclib::crypto::PrivateKey;
+use btclib::network::Message;
+use btclib::types::SignedCheckpoint;
+use btclib::util::Saveable;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::NodeContext;
+use crate::banlist::UNSIGNED_CHECKPOINT_POINTS;
+use crate::relay::broadcast;
+
+/// The authority key in `path`, once it's checked to be one of the chain's
+pub(crate) fn load_key(path: &Path, params: &ChainParams) -> Result<PrivateKey> {
+    let key = PrivateKey::load_from_file(path)
+        .with_context(|| format!("failed to load the checkpoint key: {}", path.display()))?;
+    let is_authority = params
+        .checkpoints
+        .as_ref()
+        .is_some_and(|federation| federation.authorities.contains(&key.public_key()));
+    if !is_authority {
+        anyhow::bail!("the checkpoint key isn't one of the chain spec's authorities");
+    }
+    Ok(key)
+}
+
+/// Take in a checkpoint sent by `peer`, or made by the node itself without one
+pub(crate) async fn receive(
+    ctx: &NodeContext,
+    peer: Option<&str>,
+    checkpoint: SignedCheckpoint,
+) -> Result<()> {
+    let mut blockchain = ctx.blockchain.write().await;
+    let Some(federation) = blockchain.params().checkpoints.clone() else {
+        debug!(ctx, "no checkpoint authorities, ignoring a checkpoint");
+        return Ok(());
+    };
+    if checkpoint.signers(&federation) == 0 {
+        if let Some(peer) = peer {
+            let reason = "relayed a checkpoint no authority signed";
+            ctx.misbehaving(peer, UNSIGNED_CHECKPOINT_POINTS, reason);
+        }
+        return Ok(());
+    }
+    if blockchain
+        .checkpoint()
+        .is_some_and(|last| last.height >= checkpoint.height)
+    {
+        return Ok(());
+    }
+
+    let (mut merged, known) = {
+        let mut pending = ctx.pending_checkpoint.lock().unwrap();
+        match pending.take() {
+            Some(mut held) if held.height == checkpoint.height && held.hash == checkpoint.hash => {
+                let known = held.signers(&federation);
+                held.merge(&checkpoint);
+                (held, known)
+            }
+            // a lower one or a rival for the same height only goes if it's final already
+            Some(held) if held.height >= checkpoint.height && !checkpoint.is_final(&federation) => {
+                *pending = Some(held);
+                return Ok(());
+            }
+            _ => (checkpoint, 0),
+        }
+    };
+    if let Some(key) = &ctx.checkpoint_key
+        && !merged.is_signed_by(&key.public_key())
+        && blockchain
+            .blocks()
+            .nth(merged.height as usize)
+            .is_some_and(|block| block.hash() == merged.hash)
+    {
+        merged.sign(key);
+    }
+    let signers = merged.signers(&federation);
+
+    if merged.is_final(&federation) {
+        match blockchain.add_checkpoint(merged.clone()) {
+            Ok(_) => info!(
+                ctx,
+                "checkpoint at block {} signed by {signers} authorities", merged.height
+            ),
+            Err(e) => {
+                warn!(ctx, "checkpoint refused: {e}");
+                return Ok(());
+            }
+        }
+    } else {
+        debug!(
+            ctx,
+            "checkpoint at block {} has {signers} of {} signatures",
+            merged.height,
+            federation.threshold
+        );
+        *ctx.pending_checkpoint.lock().unwrap() = Some(merged.clone());
+    }
+    drop(blockchain);
+    if signers > known {
+        broadcast(ctx, &Message::Checkpoint(merged)).await?;
+    }
+    Ok(())
+}
+
+/// Sign the tip as one of the checkpoint authorities, unless it's checkpointed already
+pub(crate) async fn sign_tip(ctx: Arc<NodeContext>) -> Result<()> {
+    let Some(key) = &ctx.checkpoint_key else {
+        return Ok(());
+    };
+    let checkpoint = {
+        let blockchain = ctx.blockchain.read().await;
+        let Some(height) = blockchain.block_height().checked_sub(1) else {
+            return Ok(());
+        };
+        if blockchain
+            .checkpoint()
+            .is_some_and(|last| last.height >= height)
+        {
+            return Ok(());
+        }
+        let mut checkpoint = SignedCheckpoint::new(height, blockchain.tip_hash());
+        checkpoint.sign(key);
+        checkpoint
+    };
+    receive(&ctx, None, checkpoint).await
+}
@@ -0,0 +1,229 @@
+//! A live view of a running node for whoever runs it, `node --tui`. The chain and the mempool come
+//! from what the node answers anyone, GetStatus, FetchMempool and FetchBlock, the peers and the
+//! log from its admin socket if there's one. Drawn with plain ANSI escapes, the whole screen again
+//! every refresh.
+
+use anyhow::{Result, bail};
+use btclib::network::{Message, NodeStatus};
+use btclib::types::{Block, MempoolTxInfo};
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+use crate::admin::{AdminCommand, AdminReply, PeerEntry, request};
+
+/// how often the dashboard asks the node again, unless told otherwise
+pub const DEFAULT_REFRESH: Duration = Duration::from_secs(2);
+/// blocks shown at the tip
+const RECENT_BLOCKS: u64 = 5;
+const LOG_LINES: usize = 10;
+/// upper ends of the fee rate histogram's buckets in sats per byte, the last one is open
+const FEE_BUCKETS: &[u64] = &[1, 2, 5, 10, 20, 50];
+/// characters of the histogram's biggest bar
+const BAR_WIDTH: usize = 40;
+
+/// Everything the dashboard shows, as the node answered it at `taken`
+#[derive(Clone, Debug)]
+pub struct Dashboard {
+    pub addr: String,
+    pub status: NodeStatus,
+    /// the best paying first, `MAX_MEMPOOL_LISTING` at most
+    pub mempool: Vec<MempoolTxInfo>,
+    /// the tip first
+    pub blocks: Vec<Block>,
+    /// with the admin socket only
+    pub peers: Option<Vec<PeerEntry>>,
+    /// with the admin socket only
+    pub logs: Option<Vec<String>>,
+    pub taken: DateTime<Utc>,
+}
+
+impl Dashboard {
+    /// Ask the node at `addr`, and the admin socket at `admin_socket` if given, for all of it
+    pub async fn fetch(addr: &str, admin_socket: Option<&Path>) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr).await?;
+        let status = match ask(&mut stream, Message::GetStatus).await? {
+            Message::Status(status) => *status,
+            message => bail!("unexpected reply from {addr}: {message:?}"),
+        };
+        let mempool = match ask(&mut stream, Message::FetchMempool).await? {
+            Message::MempoolTransactions(mempool) => mempool,
+            message => bail!("unexpected reply from {addr}: {message:?}"),
+        };
+        let mut blocks = vec![];
+        for height in (status.height.saturating_sub(RECENT_BLOCKS)..status.height).rev() {
+            match ask(&mut stream, Message::FetchBlock(height as usize)).await? {
+                Message::NewBlock(block) => blocks.push(block),
+                message => bail!("unexpected reply from {addr}: {message:?}"),
+            }
+        }
+
+        let (peers, logs) = match admin_socket {
+            Some(socket) => {
+                let peers = match request(socket, &AdminCommand::Peers).await? {
+                    AdminReply::Peers(peers) => peers,
+                    reply => bail!("unexpected reply from the admin socket: {reply:?}"),
+                };
+                let logs = match request(socket, &AdminCommand::Logs(LOG_LINES)).await? {
+                    AdminReply::Logs(logs) => logs,
+                    reply => bail!("unexpected reply from the admin socket: {reply:?}"),
+                };
+                (Some(peers), Some(logs))
+            }
+            None => (None, None),
+        };
+
+        Ok(Dashboard {
+            addr: addr.to_string(),
+            status,
+            mempool,
+            blocks,
+            peers,
+            logs,
+            taken: Utc::now(),
+        })
+    }
+
+    /// How many mempool transactions pay each fee rate, a bucket for up to each of `FEE_BUCKETS`
+    /// and one for more
+    pub fn fee_histogram(&self) -> Vec<(String, usize)> {
+        let mut counts = vec![0; FEE_BUCKETS.len() + 1];
+        for transaction in &self.mempool {
+            let bucket = FEE_BUCKETS
+                .iter()
+                .position(|&limit| transaction.fee_rate() < limit as f64)
+                .unwrap_or(FEE_BUCKETS.len());
+            counts[bucket] += 1;
+        }
+        let mut lower = 0;
+        let mut labels: Vec<String> = FEE_BUCKETS
+            .iter()
+            .map(|&upper| {
+                let label = format!("{lower}-{upper}");
+                lower = upper;
+                label
+            })
+            .collect();
+        labels.push(format!("{lower}+"));
+        labels.into_iter().zip(counts).collect()
+    }
+}
+
+async fn ask(stream: &mut TcpStream, message: Message) -> Result<Message> {
+    message.send_async(stream).await?;
+    Ok(Message::receive_async(stream).await?)
+}
+
+/// e.g. "3h 12m", the two biggest units
+fn duration(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        3600..86400 => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d {}h", seconds / 86400, seconds % 86400 / 3600),
+    }
+}
+
+impl fmt::Display for Dashboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = &self.status;
+        let regtest = if status.regtest { " (regtest)" } else { "" };
+        writeln!(
+            f,
+            "{} on {}{regtest}, up {}, updated {}",
+            self.addr,
+            status.network,
+            duration(status.uptime),
+            self.taken.format("%H:%M:%S")
+        )?;
+        writeln!(
+            f,
+            "height {} ({}), tip {}, difficulty {:.2}",
+            status.height,
+            if status.syncing { "syncing" } else { "synced" },
+            status.best_hash,
+            status.difficulty
+        )?;
+
+        writeln!(f)?;
+        match &self.peers {
+            Some(peers) => {
+                writeln!(f, "peers: {}", peers.len())?;
+                for peer in peers {
+                    let latency = peer
+                        .latency_ms
+                        .map_or_else(|| "?".to_string(), |ms| format!("{ms} ms"));
+                    writeln!(f, "  {:<24} {latency:>8}  {}", peer.addr, peer.capabilities)?;
+                }
+            }
+            None => writeln!(f, "peers: {}, --admin-socket lists them", status.peers)?,
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "mempool: {} transactions of {} bytes paying {}",
+            status.mempool.transactions, status.mempool.size, status.mempool.fees
+        )?;
+        let histogram = self.fee_histogram();
+        let most = histogram.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        for (label, count) in histogram {
+            let bar = (count * BAR_WIDTH).div_ceil(most.max(1));
+            writeln!(
+                f,
+                "  {label:>6} sat/B {:<BAR_WIDTH$} {count}",
+                "#".repeat(bar)
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "recent blocks:")?;
+        for (block, height) in self.blocks.iter().zip((0..status.height).rev()) {
+            let age = (self.taken - block.header.timestamp).num_seconds().max(0) as u64;
+            writeln!(
+                f,
+                "  {height:>6}  {}  {} transactions, {} ago",
+                block.hash(),
+                block.transactions.len(),
+                duration(age)
+            )?;
+        }
+
+        if let Some(logs) = &self.logs {
+            writeln!(f)?;
+            writeln!(f, "log:")?;
+            for line in logs {
+                writeln!(f, "  {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Redraw the dashboard of the node at `addr` every `refresh` until ctrl-c. The node going away
+/// doesn't stop it, it says so and keeps asking
+pub async fn run(addr: &str, admin_socket: Option<&Path>, refresh: Duration) -> Result<()> {
+    // hide the cursor while drawing
+    print!("\x1b[?25l");
+    loop {
+        let screen = match Dashboard::fetch(addr, admin_socket).await {
+            Ok(dashboard) => dashboard.to_string(),
+            Err(e) => format!(
+                "{addr}: {e:#}, trying again at {}\n",
+                (Utc::now() + refresh).format("%H:%M:%S")
+            ),
+        };
+        // clear the screen and start at the top
+        print!("\x1b[2J\x1b[H{screen}");
+        std::io::stdout().flush()?;
+        tokio::select! {
+            _ = tokio::time::sleep(refresh) => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    println!("\x1b[?25h");
+    Ok(())
+}
@@ -1,93 +1,412 @@
+use btclib::U256;
+use btclib::error::{BtcError, Result};
 use btclib::sha256::Hash;
+
 use chrono::Utc;
-use uuid::Uuid;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
 
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
+use tokio::sync::Notify;
+
+use btclib::network::{
+    CompactHeader, Compression, Encoding, ErrorCode, MAX_FILTER_ADD_SIZE, MerkleBlock, Message,
+    MessageReader, NodeIdentity, Outbox, PROTOCOL_VERSION, Rejection,
+};
+use btclib::types::{
+    Block, Blockchain, DoubleSpend, DoubleSpendOutcome, Eviction, Payout, Transaction, UtxoDiff,
+};
+
+use crate::banlist::{INVALID_BLOCK_POINTS, INVALID_TRANSACTION_POINTS, is_misbehavior};
+use crate::relay::{broadcast, broadcast_own};
+use crate::supervisor::{self, Activity};
+use crate::template::create_template;
+use crate::{NodeContext, NodeEvent};
+
+/// replies queued for a peer before its handler stops reading from it
+const OUTBOX_CAPACITY: usize = 32;
+
+pub async fn handle_connection(ctx: Arc<NodeContext>, socket: TcpStream) {
+    let peer = socket
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|_| "unknown peer".to_string());
+    handle_stream(ctx, peer, socket).await;
+}
 
-use btclib::network::Message;
-use btclib::types::{Block, BlockHeader, Transaction, TransactionOutput};
-use btclib::util::MerkleRoot;
+/// Serve a peer over any stream, e.g. one end of an in-memory pipe. `peer` names it in the logs
+/// and among the light wallets
+pub async fn handle_stream<S>(ctx: Arc<NodeContext>, peer: String, stream: S)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    if ctx.is_banned(&peer) {
+        info!(ctx, "refusing banned peer {peer}");
+        return;
+    }
+    let kick = Arc::new(Notify::new());
+    ctx.connections.insert(peer.clone(), kick.clone());
+    let activity = Arc::new(Activity::new());
+    let task = tokio::spawn(serve_stream(
+        ctx.clone(),
+        peer.clone(),
+        stream,
+        kick,
+        activity.clone(),
+    ));
+    supervisor::watch(&ctx, &peer, &activity, task).await;
+    ctx.connections.remove(&peer);
+    ctx.subscribers.remove(&peer);
+    ctx.bandwidth.disconnect(&peer);
+}
+
+/// `handle_stream`'s task, which may panic without taking the peer's entries with it
+async fn serve_stream<S>(
+    ctx: Arc<NodeContext>,
+    peer: String,
+    stream: S,
+    kick: Arc<Notify>,
+    activity: Arc<Activity>,
+) where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, writer) = tokio::io::split(stream);
+    let mut reader = MessageReader::new(reader);
+    let mut outbox = Outbox::spawn(writer, OUTBOX_CAPACITY);
+    outbox.set_meter(ctx.compression.clone());
+    reader.set_memory(ctx.memory.clone());
+    outbox.set_memory(ctx.memory.clone());
+    let traffic = ctx.bandwidth.meter(&peer);
+    reader.set_traffic(traffic.clone());
+    outbox.set_traffic(traffic);
+    if let Some(throttle) = ctx.bandwidth.download_throttle() {
+        reader.set_throttle(throttle);
+    }
+    if let Some(throttle) = ctx.bandwidth.upload_throttle() {
+        outbox.set_throttle(throttle);
+    }
+    let served = tokio::select! {
+        served = serve(&ctx, &peer, reader, outbox, &activity) => served,
+        _ = kick.notified() => Err(anyhow::anyhow!("banned")),
+    };
+    if let Err(e) = served {
+        warn!(ctx, "closing connection to {peer}: {e:#}");
+    }
+}
 
-pub async fn handle_connection(mut socket: TcpStream) {
+async fn serve<R: AsyncRead + Unpin>(
+    ctx: &NodeContext,
+    peer: &str,
+    mut reader: MessageReader<R>,
+    mut outbox: Outbox,
+    activity: &Activity,
+) -> anyhow::Result<()> {
+    let mut first = true;
+    // set by FetchShareTemplate and FetchPayoutTemplate
+    let mut share_target: Option<U256> = None;
     loop {
-        // read a message from the socket
-        let message = match Message::receive_async(&mut socket).await {
+        // read a message from the socket, the first may be a Hello in any encoding
+        let received = if first {
+            reader.receive_hello().await
+        } else {
+            reader.receive().await
+        };
+        first = false;
+        let message = match received {
             Ok(message) => message,
-            Err(e) => {
-                println!(
-                    "invalid message from peer: {}, error: {e}",
-                    socket.peer_addr().unwrap()
-                );
-                return;
+            // most likely from a newer version, the peer hears so and can do without it
+            Err(e) if e.is_undecodable() => {
+                debug!(ctx, "can't make out a message from {peer}: {e}");
+                let message = Message::Error {
+                    code: ErrorCode::Unsupported,
+                    reason: e.to_string(),
+                };
+                outbox.send(&message).await?;
+                continue;
             }
+            Err(e) => return Err(e.into()),
         };
+        activity.handling(&message);
 
         use btclib::network::Message::*;
         match message {
-            UTXOs(_) | Template(_) | Difference(_) | TemplateValidity(_) | NodeList(_) => {
-                println!(
+            UTXOs(_)
+            | UTXOsSince(_)
+            | Template(_)
+            | Difference(_)
+            | TemplateValidity(_)
+            | NodeList(_)
+            | GeneratedBlocks(_)
+            | Supply { .. }
+            | TransactionInfo { .. }
+            | History(_)
+            | AddressScanned(_)
+            | FilteredBlock(_)
+            | Evicted { .. }
+            | DoubleSpendAlert(_)
+            | Headers(_)
+            | Status(_)
+            | MempoolTransactions(_)
+            | MempoolTx { .. }
+            | Stats(_)
+            | TransactionTested { .. }
+            | DecodedTransaction { .. }
+            | Ack(_)
+            | ShareAccepted(_)
+            | Welcome { .. }
+            | Error { .. } => {
+                warn!(
+                    ctx,
                     "I am neither a miner nor a \
                           wallet! Goodbye"
                 );
-                return;
+                return Ok(());
+            }
+            Hello {
+                version,
+                encodings,
+                time,
+                nonce,
+                capabilities,
+            } => {
+                let encoding = Encoding::negotiate(&encodings);
+                debug!(
+                    ctx,
+                    "{peer} speaks version {version} with {capabilities}, switching to {encoding}"
+                );
+                // answer in the new encoding already
+                reader.set_encoding(encoding);
+                outbox.set_encoding(encoding);
+                if let Some(mut subscriber) = ctx.subscribers.get_mut(peer) {
+                    subscriber.1.set_encoding(encoding);
+                }
+                if let Some(time) = time {
+                    ctx.clock_sample(peer, time).await;
+                }
+                let policy = ctx.blockchain.read().await.mempool_policy().clone();
+                let message = Welcome {
+                    version: PROTOCOL_VERSION,
+                    encoding,
+                    policy,
+                    time: Some(Utc::now()),
+                    identity: nonce.map(|nonce| NodeIdentity::prove(&ctx.identity, &nonce)),
+                    capabilities: ctx.capabilities(),
+                };
+                outbox.send(&message).await?;
+                // only after the welcome, so it's readable whatever the peer makes of it
+                let compression = Compression::negotiate(ctx.capabilities(), capabilities);
+                outbox.set_compression(compression);
+                if let Some(mut subscriber) = ctx.subscribers.get_mut(peer) {
+                    subscriber.1.set_compression(compression);
+                }
             }
             FetchBlock(height) => {
-                let blockchain = crate::BLOCKCHAIN.read().await;
-                let Some(block) = blockchain.blocks().nth(height as usize).cloned() else {
-                    return;
+                let block = ctx
+                    .blockchain
+                    .read()
+                    .await
+                    .blocks()
+                    .nth(height as usize)
+                    .cloned();
+                let Some(block) = block else {
+                    return Ok(());
                 };
 
                 let message = NewBlock(block);
-                message.send_async(&mut socket).await.unwrap();
+                outbox.send(&message).await?;
             }
             DiscoverNodes => {
-                let nodes = crate::NODES
+                let nodes = ctx
+                    .nodes
                     .iter()
                     .map(|x| x.key().clone())
                     .collect::<Vec<_>>();
                 let message = NodeList(nodes);
-                message.send_async(&mut socket).await.unwrap();
+                outbox.send(&message).await?;
             }
             AskDifference(height) => {
-                let blockchain = crate::BLOCKCHAIN.read().await;
-                let count = blockchain.block_height() as i32 - height as i32;
+                let count = ctx.blockchain.read().await.block_height() as i32 - height as i32;
                 let message = Difference(count);
-                message.send_async(&mut socket).await.unwrap();
+                outbox.send(&message).await?;
             }
             FetchUTXOs(key) => {
-                let blockchain = crate::BLOCKCHAIN.read().await;
-                let utxos = blockchain
-                    .utxos()
-                    .iter()
-                    .filter(|(_, (txout, _))| txout.pubkey == key)
-                    .map(|(_, (txout, marked))| (*marked, txout.clone()))
+                // a snapshot is all that's needed from the lock, filtering happens without it
+                let snapshot = ctx.blockchain.read().await.utxo_snapshot();
+                let utxos = snapshot
+                    .owned_by(&key)
+                    .map(|(txout, marked)| (*marked, txout.clone()))
                     .collect::<Vec<_>>();
 
                 let message = UTXOs(utxos);
-                message.send_async(&mut socket).await.unwrap();
-                println!("Message with utxo sent back!");
+                outbox.send(&message).await?;
+                debug!(ctx, "Message with utxo sent back!");
+            }
+            FetchUTXOsSince(key, since) => {
+                let blockchain = ctx.blockchain.read().await;
+                let diff = match blockchain.utxo_diff(&key, since) {
+                    Some(diff) => diff,
+                    None => {
+                        // too far behind for a diff, everything is listed without the lock
+                        let snapshot = blockchain.utxo_snapshot();
+                        drop(blockchain);
+                        UtxoDiff::full(&snapshot, &key)
+                    }
+                };
+                outbox.send(&UTXOsSince(diff)).await?;
+            }
+
+            FetchTransaction(hash) => {
+                let blockchain = ctx.blockchain.read().await;
+                let message = if !blockchain.has_txindex() {
+                    Error {
+                        code: ErrorCode::NotAllowed,
+                        reason: "transaction lookups need a node running with --txindex"
+                            .to_string(),
+                    }
+                } else {
+                    match blockchain.find_transaction(&hash) {
+                        Some((height, transaction)) => TransactionInfo {
+                            height,
+                            transaction: transaction.clone(),
+                        },
+                        None => Error {
+                            code: ErrorCode::NotFound,
+                            reason: format!("no transaction {hash} in the chain"),
+                        },
+                    }
+                };
+                drop(blockchain);
+                outbox.send(&message).await?;
+            }
+
+            FetchHistory(pubkey) => {
+                let blockchain = ctx.blockchain.read().await;
+                let message = if blockchain.has_addrindex() {
+                    History(blockchain.address_history(&pubkey).to_vec())
+                } else {
+                    Error {
+                        code: ErrorCode::NotAllowed,
+                        reason: "history lookups need a node running with --addrindex".to_string(),
+                    }
+                };
+                drop(blockchain);
+                outbox.send(&message).await?;
+            }
+
+            ScanAddress(pubkey, from) => {
+                let blockchain = ctx.blockchain.read().await;
+                let message = match blockchain.scan_address(&pubkey, from) {
+                    Some(scan) => AddressScanned(scan),
+                    None => Error {
+                        code: ErrorCode::NotFound,
+                        reason: format!(
+                            "the chain has {} blocks, can't scan from {from}",
+                            blockchain.block_height()
+                        ),
+                    },
+                };
+                drop(blockchain);
+                outbox.send(&message).await?;
+            }
+
+            FetchSupply => {
+                let blockchain = ctx.blockchain.read().await;
+                let message = match blockchain.total_supply() {
+                    Ok(total) => Supply {
+                        height: blockchain.block_height(),
+                        total,
+                        max: blockchain.params().max_money(),
+                    },
+                    Err(e) => {
+                        warn!(ctx, "supply audit failed: {e}");
+                        Message::from(e)
+                    }
+                };
+                drop(blockchain);
+                outbox.send(&message).await?;
             }
 
             NewBlock(block) => {
-                let mut blockchain = crate::BLOCKCHAIN.write().await;
-                println!("received new block");
+                debug!(ctx, "received new block");
 
-                if blockchain.add_block(block).is_err() {
-                    println!("block rejected");
+                match crate::util::add_block(ctx, block.clone()).await {
+                    Ok(()) => relay_block(ctx, &block),
+                    Err(e) => {
+                        warn!(ctx, "block rejected: {e}");
+                        if is_misbehavior(&e) {
+                            ctx.misbehaving(peer, INVALID_BLOCK_POINTS, "relayed an invalid block");
+                        }
+                    }
                 }
             }
+            Checkpoint(checkpoint) => {
+                crate::checkpoint::receive(ctx, Some(peer), checkpoint).await?;
+            }
             NewTransaction(tx) => {
-                let mut blockchain = crate::BLOCKCHAIN.write().await;
+                debug!(ctx, "received transaction from friend");
 
-                println!("received transaction from friend");
+                if let Err(e) = add_to_mempool(ctx, &tx).await {
+                    warn!(ctx, "transaction rejected, closing connection: {e}");
+                    if is_misbehavior(&e) {
+                        let reason = "relayed an invalid transaction";
+                        ctx.misbehaving(peer, INVALID_TRANSACTION_POINTS, reason);
+                    }
+                    return Ok(());
+                }
+                relay_transaction(ctx, &tx);
+            }
+            NewPackage(package) => {
+                debug!(ctx, "received package of {} from friend", package.len());
 
-                if blockchain.add_to_mempool(tx).is_err() {
-                    println!("transaction rejected, closing connection");
-                    return;
+                if let Err(e) = add_package_to_mempool(ctx, &package).await {
+                    warn!(ctx, "package rejected: {e}");
+                    if is_misbehavior(&e) {
+                        let reason = "relayed an invalid package";
+                        ctx.misbehaving(peer, INVALID_TRANSACTION_POINTS, reason);
+                    }
+                    continue;
+                }
+                for tx in &package {
+                    relay_transaction(ctx, tx);
                 }
             }
+            SetFilter(filter) => {
+                if !filter.is_valid() {
+                    let message = Error {
+                        code: ErrorCode::Invalid,
+                        reason: "filter is empty or too big".to_string(),
+                    };
+                    outbox.send(&message).await?;
+                    continue;
+                }
+                ctx.subscribers
+                    .insert(peer.to_string(), (filter, outbox.clone()));
+            }
+            FilterAdd(data) => {
+                let added = match ctx.subscribers.get_mut(peer) {
+                    Some(mut subscriber) if data.len() <= MAX_FILTER_ADD_SIZE => {
+                        subscriber.0.insert(&data);
+                        true
+                    }
+                    _ => false,
+                };
+                if !added {
+                    let message = Error {
+                        code: ErrorCode::NotAllowed,
+                        reason: format!(
+                            "FilterAdd needs a filter and at most {MAX_FILTER_ADD_SIZE} bytes"
+                        ),
+                    };
+                    outbox.send(&message).await?;
+                }
+            }
+            FilterClear => {
+                ctx.subscribers.remove(peer);
+            }
             ValidateTemplate(block_template) => {
-                let blockchain = crate::BLOCKCHAIN.read().await;
+                let blockchain = ctx.blockchain.read().await;
 
                 let status = block_template.header.prev_block_hash
                     == blockchain
@@ -95,128 +414,395 @@ pub async fn handle_connection(mut socket: TcpStream) {
                         .last()
                         .map(|last_block| last_block.hash())
                         .unwrap_or(Hash::zero());
+                drop(blockchain);
 
                 let message = TemplateValidity(status);
-                message.send_async(&mut socket).await.unwrap();
+                outbox.send(&message).await?;
             }
             SubmitTemplate(block) => {
-                println!("received allegedly mined template");
-                let mut blockchain = crate::BLOCKCHAIN.write().await;
-                if let Err(e) = blockchain.add_block(block.clone()) {
-                    println!("block rejected: {e}, closing connection");
-                    return;
-                }
-
-                blockchain.rebuild_utxos();
-
-                println!("block looks good, broadcasting");
-
-                // send block to all friend nodes
-                let nodes = crate::NODES
-                    .iter()
-                    .map(|x| x.key().clone())
-                    .collect::<Vec<_>>();
-
-                for node in nodes {
-                    if let Some(mut stream) = crate::NODES.get_mut(&node) {
-                        let message = Message::NewBlock(block.clone());
-                        if message.send_async(&mut *stream).await.is_err() {
-                            println!("failed to send block to {}", node);
+                debug!(ctx, "received allegedly mined template");
+                let hash = block.hash();
+                if let Some(share_target) = share_target
+                    && !hash.matches_target(block.header.target)
+                {
+                    let checked = ctx
+                        .blockchain
+                        .read()
+                        .await
+                        .validate_share(&block, share_target);
+                    let message = match checked {
+                        Ok(()) => {
+                            debug!(ctx, "{peer} found share {hash}");
+                            ShareAccepted(hash)
                         }
-                    }
+                        Err(e) => {
+                            warn!(ctx, "share rejected: {e}");
+                            Message::from(e)
+                        }
+                    };
+                    outbox.send(&message).await?;
+                    continue;
+                }
+                if let Err(e) = submit_block(ctx, block).await {
+                    warn!(ctx, "block rejected: {e}");
+                    outbox.send(&Message::from(e)).await?;
+                    continue;
                 }
+                outbox.send(&Ack(hash)).await?;
             }
             SubmitTransaction(tx) => {
-                println!("submmit tx");
-                let mut blockchain = crate::BLOCKCHAIN.write().await;
-                if let Err(e) = blockchain.add_to_mempool(tx.clone()) {
-                    println!("transaction rejected, closing connection: {e}");
-                    return;
+                debug!(ctx, "submmit tx");
+                let hash = tx.hash();
+                if let Err(e) = submit_transaction(ctx, tx).await {
+                    warn!(ctx, "transaction rejected: {e}");
+                    outbox.send(&Message::from(e)).await?;
+                    continue;
+                }
+                outbox.send(&Ack(hash)).await?;
+            }
+            SubmitPackage(package) => {
+                let Some(hash) = package.last().map(Transaction::hash) else {
+                    outbox
+                        .send(&Message::from(BtcError::InvalidPackage(
+                            "it's empty".to_string(),
+                        )))
+                        .await?;
+                    continue;
+                };
+                if let Err(e) = submit_package(ctx, package).await {
+                    warn!(ctx, "package rejected: {e}");
+                    outbox.send(&Message::from(e)).await?;
+                    continue;
+                }
+                outbox.send(&Ack(hash)).await?;
+            }
+            FetchMempool => {
+                let mut info = ctx.blockchain.read().await.mempool_info();
+                info.truncate(btclib::MAX_MEMPOOL_LISTING);
+                outbox.send(&MempoolTransactions(info)).await?;
+            }
+            FetchMempoolTx(hash) => {
+                let blockchain = ctx.blockchain.read().await;
+                let found = blockchain.mempool().get(&hash).cloned();
+                let message = match (blockchain.mempool_tx_info(&hash), found) {
+                    (Some(info), Some(transaction)) => MempoolTx { info, transaction },
+                    _ => Error {
+                        code: ErrorCode::NotFound,
+                        reason: format!("no transaction {hash} in the mempool"),
+                    },
+                };
+                drop(blockchain);
+                outbox.send(&message).await?;
+            }
+            GetChainStats { start, end, window } => {
+                let stats = ctx
+                    .blockchain
+                    .read()
+                    .await
+                    .stats_windows(start..end, window);
+                outbox.send(&Stats(stats)).await?;
+            }
+            TestTransaction(tx) => {
+                let txid = tx.hash();
+                let result = ctx
+                    .blockchain
+                    .read()
+                    .await
+                    .test_mempool_accept(&tx)
+                    .map_err(Rejection::from);
+                debug!(ctx, "tested {txid}: {result:?}");
+                outbox.send(&TransactionTested { txid, result }).await?;
+            }
+            GetStatus => {
+                let message = Status(Box::new(ctx.status().await));
+                outbox.send(&message).await?;
+            }
+            FetchHeaderRange(start, count) => {
+                let count = count.min(btclib::MAX_HEADER_RANGE);
+                let headers = ctx
+                    .blockchain
+                    .read()
+                    .await
+                    .blocks()
+                    .skip(start as usize)
+                    .take(count as usize)
+                    .map(CompactHeader::new)
+                    .collect();
+                let message = Headers(headers);
+                outbox.send(&message).await?;
+            }
+            DecodeRawTransaction(hex) => {
+                let message = match Transaction::from_hex(&hex) {
+                    Ok(transaction) => DecodedTransaction {
+                        txid: transaction.hash(),
+                        transaction,
+                    },
+                    Err(e) => Message::from(e),
+                };
+                outbox.send(&message).await?;
+            }
+            SendRawTransaction(hex) => {
+                let tx = match Transaction::from_hex(&hex) {
+                    Ok(tx) => tx,
+                    Err(e) => {
+                        outbox.send(&Message::from(e)).await?;
+                        continue;
+                    }
+                };
+                let hash = tx.hash();
+                if let Err(e) = submit_transaction(ctx, tx).await {
+                    warn!(ctx, "transaction rejected: {e}");
+                    outbox.send(&Message::from(e)).await?;
+                    continue;
+                }
+                outbox.send(&Ack(hash)).await?;
+            }
+            GenerateBlocks(pubkey, count) => {
+                if !ctx.regtest {
+                    warn!(ctx, "GenerateBlocks is only available on regtest");
+                    let message = Error {
+                        code: ErrorCode::NotAllowed,
+                        reason: "GenerateBlocks is only available on regtest".to_string(),
+                    };
+                    outbox.send(&message).await?;
+                    continue;
                 }
 
-                println!("added transaction to mempool");
-
-                // send transaction to all friend nodes
-                let nodes = crate::NODES
-                    .iter()
-                    .map(|x| x.key().clone())
-                    .collect::<Vec<_>>();
-
-                for node in nodes {
-                    println!("sending to friend: {node}");
-                    if let Some(mut stream) = crate::NODES.get_mut(&node) {
-                        let message = Message::NewTransaction(tx.clone());
-                        if message.send_async(&mut *stream).await.is_err() {
-                            println!("failed to send transaction to {}", node);
+                let count = count.min(btclib::MAX_GENERATED_BLOCKS);
+                let mut blockchain = ctx.blockchain.write().await;
+                let mut generated = vec![];
+                for _ in 0..count {
+                    let mut block = match create_template(&blockchain, pubkey.clone()) {
+                        Ok(block) => block,
+                        Err(e) => {
+                            error!(ctx, "{e}");
+                            return Ok(());
                         }
+                    };
+                    block
+                        .header
+                        .mine_with_target_override(btclib::REGTEST_TARGET);
+                    if let Err(e) = blockchain.add_block(block.clone()) {
+                        warn!(ctx, "generated block rejected: {e}");
+                        break;
                     }
+                    generated.push((block, Instant::now()));
                 }
+                drop(blockchain);
 
-                println!("transaction sent to friends");
+                info!(ctx, "generated {} blocks", generated.len());
+                for (block, accepted) in &generated {
+                    send_own_block(ctx, block, *accepted).await;
+                }
+                let hashes = generated.iter().map(|(block, _)| block.hash()).collect();
+                let message = GeneratedBlocks(hashes);
+                outbox.send(&message).await?;
             }
             FetchTemplate(pubkey) => {
-                let blockchain = crate::BLOCKCHAIN.read().await;
-
-                let mut transactions = vec![];
-                // insert transactions from mempool
-                transactions.extend(
-                    blockchain
-                        .mempool()
-                        .iter()
-                        .take(btclib::BLOCK_TRANSACTION_CAP)
-                        .map(|(tx, _)| tx)
-                        .cloned()
-                        .collect::<Vec<_>>(),
-                );
-                // insert coinbase tx with pubkey
-                transactions.insert(
-                    0,
-                    Transaction {
-                        inputs: vec![],
-                        outputs: vec![TransactionOutput {
-                            pubkey,
-                            unique_id: Uuid::new_v4(),
-                            value: 0,
-                        }],
-                    },
-                );
+                let Some(block) = template(ctx, &Payout::single(pubkey)).await else {
+                    return Ok(());
+                };
+                outbox.send(&Template(block)).await?;
+            }
+            FetchShareTemplate(pubkey, target) => {
+                share_target = Some(target);
+                let Some(block) = template(ctx, &Payout::single(pubkey)).await else {
+                    return Ok(());
+                };
+                outbox.send(&Template(block)).await?;
+            }
+            FetchPayoutTemplate(payout, target) => {
+                if target.is_some() {
+                    share_target = target;
+                }
+                let Some(block) = template(ctx, &payout).await else {
+                    return Ok(());
+                };
+                outbox.send(&Template(block)).await?;
+            }
+        }
+    }
+}
 
-                let merkle_root = MerkleRoot::calculate(&transactions);
-
-                let mut block = Block::new(
-                    BlockHeader {
-                        timestamp: Utc::now(),
-                        prev_block_hash: blockchain
-                            .blocks()
-                            .last()
-                            .map(|last_block| last_block.hash())
-                            .unwrap_or(Hash::zero()),
-                        nonce: 0,
-                        target: blockchain.target(),
-                        merkle_root,
-                    },
-                    transactions,
-                );
+/// the template paying `payout`, None if it can't be made
+async fn template(ctx: &NodeContext, payout: &Payout) -> Option<Block> {
+    let blockchain = ctx.blockchain.read().await;
+    ctx.templates
+        .get(&blockchain, payout)
+        .inspect_err(|e| error!(ctx, "{e}"))
+        .ok()
+}
 
-                let miner_fees = match block.calculate_miner_fees(blockchain.utxos()) {
-                    Ok(fees) => fees,
-                    Err(e) => {
-                        eprintln!("{e}");
-                        return;
-                    }
-                };
+/// Add a block mined for this node, then pass it on to every known node ahead of gossip, and to
+/// light wallets
+pub async fn submit_block(ctx: &NodeContext, block: Block) -> Result<()> {
+    crate::util::add_block(ctx, block.clone()).await?;
+    info!(ctx, "block looks good, broadcasting");
+    send_own_block(ctx, &block, Instant::now()).await;
+    Ok(())
+}
+
+async fn send_own_block(ctx: &NodeContext, block: &Block, accepted: Instant) {
+    // send block to all friend nodes
+    if let Err(e) = broadcast_own(ctx, &Message::NewBlock(block.clone()), accepted).await {
+        warn!(ctx, "failed to broadcast block: {e}");
+    }
+    relay_block(ctx, block);
+}
 
-                let reward = blockchain.calculate_block_reward();
+/// Add a transaction sent to this node to the mempool, then pass it on to every known node ahead
+/// of gossip, and to light wallets
+pub async fn submit_transaction(ctx: &NodeContext, tx: Transaction) -> Result<()> {
+    add_to_mempool(ctx, &tx).await?;
+    let accepted = Instant::now();
+    debug!(ctx, "added transaction to mempool");
 
-                // update coinbase tx with reward
-                block.transactions[0].outputs[0].value = reward + miner_fees;
+    // send transaction to all friend nodes
+    if let Err(e) = broadcast_own(ctx, &Message::NewTransaction(tx.clone()), accepted).await {
+        warn!(ctx, "failed to broadcast transaction: {e}");
+    }
+    debug!(ctx, "transaction sent to friends");
+    relay_transaction(ctx, &tx);
+    Ok(())
+}
 
-                // recalculate merkle root
-                block.header.merkle_root = MerkleRoot::calculate(&block.transactions);
+/// Add transactions sent to this node together to the mempool, then pass them on to every known
+/// node that takes packages ahead of gossip, and to light wallets
+pub async fn submit_package(ctx: &NodeContext, package: Vec<Transaction>) -> Result<()> {
+    add_package_to_mempool(ctx, &package).await?;
+    let accepted = Instant::now();
+    debug!(ctx, "added package of {} to mempool", package.len());
 
-                let message = Template(block);
-                message.send_async(&mut socket).await.unwrap();
-            }
+    // peers without packages take the transactions alone, or not, when they're rebroadcast
+    if let Err(e) = broadcast_own(ctx, &Message::NewPackage(package.clone()), accepted).await {
+        warn!(ctx, "failed to broadcast package: {e}");
+    }
+    for tx in &package {
+        relay_transaction(ctx, tx);
+    }
+    Ok(())
+}
+
+/// Send every mempool transaction to the known nodes again, for ones that missed them while
+/// they were down or not connected yet
+pub async fn rebroadcast(ctx: Arc<NodeContext>) -> anyhow::Result<()> {
+    if ctx.nodes.is_empty() {
+        return Ok(());
+    }
+    let transactions: Vec<Transaction> = {
+        let blockchain = ctx.blockchain.read().await;
+        blockchain
+            .mempool()
+            .iter()
+            .map(|(transaction, _)| transaction.clone())
+            .collect()
+    };
+    debug!(ctx, "rebroadcasting {} transactions", transactions.len());
+    for transaction in transactions {
+        broadcast(&ctx, &Message::NewTransaction(transaction)).await?;
+    }
+    Ok(())
+}
+
+/// Add a transaction to the mempool. If it's refused for spending what a mempool transaction
+/// already spends, whoever waits for that one gets alerted
+async fn add_to_mempool(ctx: &NodeContext, tx: &Transaction) -> Result<()> {
+    let mut blockchain = ctx.blockchain.write().await;
+    let result = blockchain.add_to_mempool(tx.clone());
+    if let Err(BtcError::DoubleSpend(_)) = result {
+        alert_double_spends(ctx, &blockchain, tx);
+    }
+    result
+}
+
+/// `add_to_mempool` for a package, all of it or none
+async fn add_package_to_mempool(ctx: &NodeContext, package: &[Transaction]) -> Result<()> {
+    let mut blockchain = ctx.blockchain.write().await;
+    let result = blockchain.add_package_to_mempool(package.to_vec());
+    if let Err(BtcError::DoubleSpend(_)) = result {
+        for tx in package {
+            alert_double_spends(ctx, &blockchain, tx);
         }
     }
+    result.map(|_| ())
+}
+
+/// tell whoever waits for the mempool transactions `tx` would double spend
+fn alert_double_spends(ctx: &NodeContext, blockchain: &Blockchain, tx: &Transaction) {
+    let mempool = blockchain.mempool();
+    for double_spend in mempool.double_spends(tx, DoubleSpendOutcome::Rejected) {
+        if let Some(original) = mempool.get(&double_spend.txid) {
+            relay_double_spend(ctx, original, &double_spend);
+        }
+    }
+}
+
+/// Pass a transaction on to the light wallets whose filter it matches and to subscribers
+fn relay_transaction(ctx: &NodeContext, transaction: &Transaction) {
+    ctx.notify(|| NodeEvent::Transaction(transaction.clone()));
+    let message = Message::NewTransaction(transaction.clone());
+    send_to_matching(ctx, transaction, &message);
+}
+
+/// Send `message` to the light wallets whose filter matches `transaction`, encoding it once per
+/// encoding. Wallets too slow to take it miss it instead of holding up the node
+fn send_to_matching(ctx: &NodeContext, transaction: &Transaction, message: &Message) {
+    let mut frames = HashMap::new();
+    for mut subscriber in ctx.subscribers.iter_mut() {
+        let (filter, outbox) = subscriber.value_mut();
+        if !filter.matches_transaction(transaction) {
+            continue;
+        }
+        let encoding = outbox.encoding();
+        let frame = frames
+            .entry(encoding)
+            .or_insert_with(|| message.to_frame_as(encoding).ok());
+        if let Some(frame) = frame {
+            let _ = outbox.try_send_frame(frame.clone());
+        }
+    }
+}
+
+/// Send every light wallet the block's header with the transactions matching its filter, and
+/// subscribers the whole block
+fn relay_block(ctx: &NodeContext, block: &Block) {
+    ctx.notify(|| NodeEvent::Block(block.clone()));
+    for mut subscriber in ctx.subscribers.iter_mut() {
+        let (filter, outbox) = subscriber.value_mut();
+        let message = Message::FilteredBlock(MerkleBlock::new(block, filter));
+        if let Ok(frame) = message.to_frame_as(outbox.encoding()) {
+            let _ = outbox.try_send_frame(frame);
+        }
+    }
+}
+
+/// Tell every light wallet whose filter matches an evicted transaction that it's gone, and every
+/// subscriber
+pub fn relay_eviction(ctx: &NodeContext, eviction: &Eviction) {
+    ctx.notify(|| NodeEvent::Evicted(eviction.clone()));
+    let message = Message::Evicted {
+        txid: eviction.transaction.hash(),
+        reason: eviction.reason,
+    };
+    send_to_matching(ctx, &eviction.transaction, &message);
+    if let Some(double_spend) = &eviction.double_spend {
+        relay_double_spend(ctx, &eviction.transaction, double_spend);
+    }
+}
+
+/// Alert the light wallets whose filter matches the double spent `original`, and subscribers
+fn relay_double_spend(ctx: &NodeContext, original: &Transaction, double_spend: &DoubleSpend) {
+    info!(
+        ctx,
+        "{} double spends {} ({:?})",
+        double_spend.conflicting,
+        double_spend.txid,
+        double_spend.outcome
+    );
+    ctx.notify(|| NodeEvent::DoubleSpend(double_spend.clone()));
+    send_to_matching(
+        ctx,
+        original,
+        &Message::DoubleSpendAlert(double_spend.clone()),
+    );
 }
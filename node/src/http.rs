@@ -0,0 +1,253 @@
+//! A read-only HTTP endpoint for dashboards and explorers. `GET /stats` answers with a JSON
+//! array of `ChainStats`, like GetChainStats does. `start`, `end` and `window` go in the query,
+//! e.g.
+//!
+//! ```text
+//! $ curl 'http://127.0.0.1:8080/stats?start=1000&window=144'
+//! ```
+//!
+//! `start` is 0 and `end` the tip unless given, the default `window` of 0 is all blocks at once.
+//! With the addrindex there's also `GET /richlist`, the `limit` keys holding the most, and
+//! `GET /distribution`, how many keys hold how much, see `Blockchain::supply_distribution`.
+//! With `--serve-snapshots` it's `GET /snapshot` and the files under `/snapshots/` as well, see
+//! `snapshot`. `GET /traffic` is what went over the node's connections, see `bandwidth`,
+//! and `GET /memory` what it holds in memory, see `MemoryUsage`.
+//!
+//! `GET /balance/<key>` and `GET /utxos/<key>` are what a key, in the compact hex form
+//! `network::fingerprint` shows, had once the chain had `height` blocks, the tip unless given.
+//! Without the addrindex they go through the blocks, see `Blockchain::balance_at`.
+//!
+//! Plain http:// only, like the webhook, put a proxy in front for anything else.
+
+use anyhow::{Context, Result};
+use btclib::crypto::PublicKey;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use crate::NodeContext;
+use crate::snapshot::Snapshots;
+
+/// longest request line and headers read before the request is refused
+const MAX_REQUEST_HEAD: u64 = 8 * 1024;
+/// keys `/richlist` lists unless asked for a different `limit`
+const DEFAULT_RICH_LIST: usize = 100;
+/// how long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// status code, content type and body
+type Response = (u16, &'static str, Vec<u8>);
+
+/// Listen on `addr`, returning the address it ended up bound to
+pub(crate) async fn listen(
+    ctx: Arc<NodeContext>,
+    addr: &str,
+) -> Result<(JoinHandle<()>, SocketAddr)> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind the http endpoint {addr}"))?;
+    let local_addr = listener.local_addr()?;
+    info!(ctx, "serving chain stats on http://{local_addr}");
+    let task = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(ctx.clone(), stream));
+                }
+                Err(e) => warn!(ctx, "http endpoint failed to accept: {e}"),
+            }
+        }
+    });
+    Ok((task, local_addr))
+}
+
+async fn serve(ctx: Arc<NodeContext>, mut stream: TcpStream) {
+    let (status, content_type, body) =
+        match tokio::time::timeout(REQUEST_TIMEOUT, read_target(&mut stream)).await {
+            Ok(Ok(Some(target))) => respond(&ctx, &target).await,
+            Ok(Ok(None)) => error(400, "bad request"),
+            Ok(Err(e)) => {
+                debug!(ctx, "http request failed: {e}");
+                return;
+            }
+            Err(_) => error(408, "request timed out"),
+        };
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        _ => "Internal Server Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    let written = async {
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&body).await?;
+        stream.shutdown().await
+    };
+    if let Err(e) = written.await {
+        debug!(ctx, "http response failed: {e}");
+    }
+}
+
+/// The target of a GET, None for anything else or a request that doesn't fit `MAX_REQUEST_HEAD`
+async fn read_target(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_HEAD));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // the headers don't matter, but they're read so the client isn't reset mid-request
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        if header.trim_end().is_empty() {
+            break;
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    Ok(match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Some(target.to_string()),
+        (Some(_), Some(_)) => Some(String::new()),
+        _ => None,
+    })
+}
+
+async fn respond(ctx: &NodeContext, target: &str) -> Response {
+    if target.is_empty() {
+        return error(405, "only GET is served");
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if let Some(name) = path.strip_prefix("/snapshots/") {
+        return snapshot_file(ctx, name).await;
+    }
+    if let Some((kind @ ("balance" | "utxos"), key)) =
+        path.strip_prefix('/').and_then(|path| path.split_once('/'))
+    {
+        return match parse_query(query, &["height"]) {
+            Ok(params) => key_at(ctx, kind, key, params.get("height").copied()).await,
+            Err(reason) => error(400, &reason),
+        };
+    }
+    let known: &[&str] = match path {
+        "/stats" => &["start", "end", "window"],
+        "/richlist" => &["limit"],
+        "/distribution" | "/snapshot" | "/traffic" | "/memory" => &[],
+        _ => return error(404, "not found"),
+    };
+    let params = match parse_query(query, known) {
+        Ok(params) => params,
+        Err(reason) => return error(400, &reason),
+    };
+    let param = |name, default| params.get(name).copied().unwrap_or(default);
+
+    if path == "/snapshot" {
+        return match ctx.snapshots.as_ref().map(Snapshots::latest) {
+            Some(Some(snapshot)) => json(serde_json::to_string(&snapshot)),
+            Some(None) => error(404, "no snapshot written yet"),
+            None => error(404, "snapshots are only served with --serve-snapshots"),
+        };
+    }
+    if path == "/traffic" {
+        return json(serde_json::to_string(&ctx.bandwidth.report()));
+    }
+    let blockchain = ctx.blockchain.read().await;
+    if path == "/memory" {
+        return json(serde_json::to_string(&ctx.memory_usage(&blockchain)));
+    }
+    if path != "/stats" && !blockchain.has_addrindex() {
+        return error(
+            404,
+            "holders are only known to a node running with --addrindex",
+        );
+    }
+    let body = match path {
+        "/stats" => serde_json::to_string(&blockchain.stats_windows(
+            param("start", 0)..param("end", u64::MAX),
+            param("window", 0),
+        )),
+        "/richlist" => {
+            let limit = param("limit", DEFAULT_RICH_LIST as u64).min(btclib::MAX_RICH_LIST as u64);
+            serde_json::to_string(&blockchain.rich_list(limit as usize))
+        }
+        _ => serde_json::to_string(&blockchain.supply_distribution()),
+    };
+    drop(blockchain);
+    json(body)
+}
+
+/// `GET /balance/<key>` or `GET /utxos/<key>`, at `height` or the tip
+async fn key_at(ctx: &NodeContext, kind: &str, key: &str, height: Option<u64>) -> Response {
+    let Ok(pubkey) = PublicKey::from_hex(key) else {
+        return error(400, &format!("{key} is not a public key"));
+    };
+    let blockchain = ctx.blockchain.read().await;
+    let height = height.unwrap_or(blockchain.block_height());
+    let body = match kind {
+        "balance" => blockchain.balance_at(&pubkey, height).map(|balance| {
+            serde_json::to_string(&serde_json::json!({ "height": height, "balance": balance }))
+        }),
+        _ => blockchain
+            .utxos_at(&pubkey, height)
+            .map(|utxos| serde_json::to_string(&utxos)),
+    };
+    drop(blockchain);
+    match body {
+        Some(body) => json(body),
+        None => error(404, &format!("the chain doesn't have {height} blocks")),
+    }
+}
+
+/// the snapshot file called `name`, as it is on disk
+async fn snapshot_file(ctx: &NodeContext, name: &str) -> Response {
+    let Some(path) = ctx
+        .snapshots
+        .as_ref()
+        .and_then(|snapshots| snapshots.file(name))
+    else {
+        return error(404, "not found");
+    };
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => (200, "application/octet-stream", bytes),
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+/// the query's parameters, all numbers, refusing any not in `known`
+fn parse_query<'a>(query: &'a str, known: &[&str]) -> Result<HashMap<&'a str, u64>, String> {
+    let mut params = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if !known.contains(&name) {
+            return Err(format!("unknown parameter {name}"));
+        }
+        let value = value
+            .parse()
+            .map_err(|_| format!("{pair} is not a number"))?;
+        params.insert(name, value);
+    }
+    Ok(params)
+}
+
+fn json(body: serde_json::Result<String>) -> Response {
+    match body {
+        Ok(body) => (200, "application/json", body.into_bytes()),
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+fn error(status: u16, reason: &str) -> Response {
+    let body = serde_json::json!({ "error": reason }).to_string();
+    (status, "application/json", body.into_bytes())
+}
@@ -0,0 +1,866 @@
+//! A toy blockchain node, as a library so tests and other programs can run one in-process instead
+//! of starting the binary:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let node = node::Node::builder().port(0).regtest(true).spawn().await?;
+//! let mut events = node.subscribe();
+//! while let Ok(event) = events.recv().await {
+//!     println!("{event:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::Result;
+use bandwidth::Bandwidth;
+use banlist::BanList;
+use btclib::chain_params::ChainParams;
+use btclib::crypto::{PrivateKey, PublicKey};
+use btclib::finality::{ChainTip, Finality};
+use btclib::network::{
+    BloomFilter, Capabilities, CompressionMeter, MemoryMeter, MemoryUsage, NetworkClock,
+    NodeStatus, Outbox, PROTOCOL_VERSION,
+};
+use btclib::types::{
+    Block, Blockchain, DoubleSpend, Eviction, MempoolPolicy, SignedCheckpoint, Transaction,
+};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use log::Log;
+use relay::Relay;
+use scheduler::Scheduler;
+use snapshot::Snapshots;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use template::TemplateCache;
+use tokio::{
+    io::DuplexStream,
+    net::{TcpListener, TcpStream},
+    sync::{Notify, RwLock, RwLockReadGuard, broadcast},
+    task::JoinHandle,
+};
+
+#[macro_use]
+mod log;
+#[cfg(unix)]
+pub mod admin;
+mod bandwidth;
+mod banlist;
+mod checkpoint;
+#[cfg(unix)]
+pub mod dashboard;
+mod handler;
+mod http;
+mod relay;
+mod scheduler;
+mod snapshot;
+mod supervisor;
+mod template;
+mod util;
+mod webhook;
+
+pub use bandwidth::{RateLimits, TrafficReport};
+pub use banlist::{BAN_THRESHOLD, Ban, DEFAULT_BAN_DURATION};
+pub use log::{LOG_TAIL, LogLevel};
+pub use scheduler::Schedule;
+pub use snapshot::Snapshot;
+
+/// bytes an in-memory connection buffers each way before writes wait for the other end
+const DUPLEX_BUFFER: usize = 64 * 1024;
+/// events kept for subscribers before the slowest of them starts missing some
+const EVENT_BACKLOG: usize = 1024;
+/// bytes of frames the node buffers for all its peers together, unless told otherwise
+pub const DEFAULT_PEER_MEMORY: usize = 256 * 1024 * 1024;
+
+/// Something that changed the node's chain or mempool
+#[derive(Clone, Debug)]
+pub enum NodeEvent {
+    Block(Block),
+    Transaction(Transaction),
+    Evicted(Eviction),
+    /// a transaction refused, replaced or mined instead of one in the mempool
+    DoubleSpend(DoubleSpend),
+}
+
+/// Everything a node's connections and background tasks share, one per node so several can run
+/// in a process
+pub(crate) struct NodeContext {
+    pub blockchain: RwLock<Blockchain>,
+    /// Node pool
+    pub nodes: DashMap<String, TcpStream>,
+    /// what the nodes in the pool said they support in their `Welcome`
+    pub peer_capabilities: DashMap<String, Capabilities>,
+    /// how long the nodes in the pool last took to answer, see `util::handshake`
+    pub peer_latency: DashMap<String, Duration>,
+    /// Light wallets that set a filter, by their address, with where to relay matches
+    pub subscribers: DashMap<String, (BloomFilter, Outbox)>,
+    /// every connection being served, notified to close it
+    pub connections: DashMap<String, Arc<Notify>>,
+    /// addresses whose connections are refused
+    pub bans: BanList,
+    /// the clocks peers sent in their handshakes
+    pub clock: Mutex<NetworkClock>,
+    /// what miners asking for templates get until the tip or the mempool changes
+    pub templates: TemplateCache,
+    /// where `NodeHandle::subscribe` listens
+    pub events: broadcast::Sender<NodeEvent>,
+    /// Whether the node runs on regtest, enabling instant block generation
+    pub regtest: bool,
+    pub started: Instant,
+    /// set while the chain is downloaded from peers
+    pub syncing: AtomicBool,
+    /// file the chain is saved to, if any
+    pub store: Option<PathBuf>,
+    /// notified to stop accepting connections
+    pub shutdown: Notify,
+    /// how the background jobs have been going
+    pub scheduler: Scheduler,
+    /// the key the node signs `Hello` nonces with, see `NodeIdentity`
+    pub identity: PrivateKey,
+    /// the checkpoint authority key the node signs with, if it's one of them
+    pub checkpoint_key: Option<PrivateKey>,
+    /// the highest checkpoint still short of signatures, see `checkpoint`
+    pub pending_checkpoint: Mutex<Option<SignedCheckpoint>>,
+    /// what the status reports as finalized, see `finality`
+    pub finality: Finality,
+    /// puts the node's own blocks and transactions ahead of gossip, see `relay`
+    pub relay: Relay,
+    /// where snapshots are written to and served from, if the node serves them, see `snapshot`
+    pub snapshots: Option<Snapshots>,
+    /// what compressing messages to peers saved, shared by every connection's outbox
+    pub compression: Arc<CompressionMeter>,
+    /// what goes over each connection and how fast it may, see `bandwidth`
+    pub bandwidth: Bandwidth,
+    /// connection handlers that panicked, see `supervisor`
+    pub handler_panics: AtomicU64,
+    /// frames buffered for peers, shared by every connection's reader and outbox
+    pub memory: Arc<MemoryMeter>,
+    /// which messages the node prints and the last of them, see `log`
+    pub log: Log,
+}
+
+impl NodeContext {
+    fn new(
+        params: Arc<ChainParams>,
+        regtest: bool,
+        store: Option<PathBuf>,
+        bans: BanList,
+        identity: PrivateKey,
+        checkpoint_key: Option<PrivateKey>,
+        finality: Finality,
+    ) -> Self {
+        NodeContext {
+            blockchain: RwLock::new(Blockchain::with_params(params)),
+            nodes: DashMap::new(),
+            peer_capabilities: DashMap::new(),
+            peer_latency: DashMap::new(),
+            subscribers: DashMap::new(),
+            connections: DashMap::new(),
+            bans,
+            clock: Mutex::new(NetworkClock::new()),
+            templates: TemplateCache::default(),
+            events: broadcast::channel(EVENT_BACKLOG).0,
+            regtest,
+            started: Instant::now(),
+            syncing: AtomicBool::new(false),
+            store,
+            shutdown: Notify::new(),
+            scheduler: Scheduler::default(),
+            identity,
+            checkpoint_key,
+            pending_checkpoint: Mutex::new(None),
+            finality,
+            relay: Relay::default(),
+            snapshots: None,
+            compression: Arc::default(),
+            bandwidth: Bandwidth::default(),
+            handler_panics: AtomicU64::new(0),
+            memory: Arc::new(MemoryMeter::new(Some(DEFAULT_PEER_MEMORY))),
+            log: Log::default(),
+        }
+    }
+
+    /// what the node holds in memory, see `MemoryUsage`
+    pub fn memory_usage(&self, blockchain: &Blockchain) -> MemoryUsage {
+        MemoryUsage {
+            mempool: blockchain.mempool_stats().size,
+            mempool_limit: blockchain.mempool_policy().max_mempool_size,
+            utxo_entries: blockchain.utxos().len(),
+            utxo_bytes: blockchain.utxo_memory(),
+            filters: self
+                .subscribers
+                .iter()
+                .map(|subscriber| subscriber.0.size())
+                .sum(),
+            peers: self.memory.stats(),
+        }
+    }
+
+    pub async fn status(&self) -> NodeStatus {
+        let blockchain = self.blockchain.read().await;
+        NodeStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            network: blockchain.params().name.clone(),
+            regtest: self.regtest,
+            height: blockchain.block_height(),
+            best_hash: blockchain.tip_hash(),
+            target: blockchain.target(),
+            difficulty: blockchain.difficulty(),
+            mempool: blockchain.mempool_stats(),
+            policy: blockchain.mempool_policy().clone(),
+            peers: self.nodes.len(),
+            light_wallets: self.subscribers.len(),
+            uptime: self.started.elapsed().as_secs(),
+            syncing: self.syncing.load(Ordering::Relaxed),
+            clock_offset: self.clock.lock().unwrap().median_offset(),
+            tasks: self.scheduler.status(),
+            identity: Some(self.identity.public_key()),
+            checkpoint: blockchain.checkpoint().map(|checkpoint| checkpoint.height),
+            finalized: self
+                .finality
+                .policy()
+                .finalized_height(&ChainTip::from(&*blockchain)),
+            own_blocks: self.relay.blocks(),
+            own_transactions: self.relay.transactions(),
+            compression: self.compression.stats(),
+            traffic: self.bandwidth.total(),
+            upload_limit: self.bandwidth.limits.upload,
+            download_limit: self.bandwidth.limits.download,
+            handler_panics: self.handler_panics.load(Ordering::Relaxed),
+            memory: self.memory_usage(&blockchain),
+        }
+    }
+
+    /// what the node tells peers it supports in `Hello` and `Welcome`
+    pub fn capabilities(&self) -> Capabilities {
+        let capabilities = Capabilities::FILTERS
+            | Capabilities::CHECKPOINTS
+            | Capabilities::ZSTD
+            | Capabilities::LZ4
+            | Capabilities::PACKAGES;
+        if self.snapshots.is_some() {
+            capabilities | Capabilities::SNAPSHOTS
+        } else {
+            capabilities
+        }
+    }
+
+    /// whether `node` in the pool can be sent messages that need `capabilities`
+    pub fn peer_supports(&self, node: &str, capabilities: Capabilities) -> bool {
+        self.peer_capabilities
+            .get(node)
+            .map_or(capabilities.is_empty(), |supported| {
+                supported.contains(capabilities)
+            })
+    }
+
+    /// Note the clock a peer sent, and move the chain's to the network's if that changes it.
+    /// Peers are told apart by address, so one can't stuff the samples with many connections
+    pub async fn clock_sample(&self, peer: &str, time: DateTime<Utc>) {
+        let peer = peer_ip(peer).map_or_else(|| peer.to_string(), |ip| ip.to_string());
+        let (old, adjustment, newly_skewed, offset) = {
+            let mut clock = self.clock.lock().unwrap();
+            let (old, was_skewed) = (clock.adjustment(), clock.is_skewed());
+            clock.add_sample(&peer, time, Utc::now());
+            let newly_skewed = clock.is_skewed() && !was_skewed;
+            (old, clock.adjustment(), newly_skewed, clock.median_offset())
+        };
+        if newly_skewed {
+            warn!(
+                self,
+                "the local clock is {}s off from the network's, check it", -offset
+            );
+        }
+        if adjustment != old {
+            info!(self, "adjusting the clock by {}s", adjustment.num_seconds());
+            self.blockchain.write().await.set_time_offset(adjustment);
+        }
+    }
+
+    /// Refuse `ip` until `until`, and close the connections from and to it now. Returns how many
+    /// connections it had open to the node
+    pub fn ban(&self, ip: IpAddr, until: DateTime<Utc>, reason: &str) -> usize {
+        self.bans.ban(ip, until, reason);
+        self.save_bans();
+        self.nodes.retain(|node, _| peer_ip(node) != Some(ip));
+        let mut closed = 0;
+        for connection in self.connections.iter() {
+            if peer_ip(connection.key()) == Some(ip) {
+                connection.value().notify_one();
+                closed += 1;
+            }
+        }
+        closed
+    }
+
+    /// write the bans to their file, a failure is only logged
+    pub fn save_bans(&self) {
+        if let Err(e) = self.bans.save() {
+            warn!(self, "{e:#}");
+        }
+    }
+
+    pub fn is_banned(&self, peer: &str) -> bool {
+        peer_ip(peer).is_some_and(|ip| self.bans.is_banned(&ip))
+    }
+
+    /// Give `peer` misbehavior points for `reason`, and ban it if it has enough, see `banlist`
+    pub fn misbehaving(&self, peer: &str, points: f64, reason: &str) {
+        let Some(ip) = peer_ip(peer) else {
+            return;
+        };
+        if self.bans.misbehaving(ip, points) {
+            warn!(self, "banning {ip}: {reason}");
+            self.ban(ip, Utc::now() + DEFAULT_BAN_DURATION, reason);
+        }
+    }
+
+    /// send `event` to the subscribers, only built if there are any
+    pub fn notify(&self, event: impl FnOnce() -> NodeEvent) {
+        if self.events.receiver_count() > 0 {
+            let _ = self.events.send(event());
+        }
+    }
+}
+
+/// the address a peer connects from, in-memory peers have none
+fn peer_ip(peer: &str) -> Option<IpAddr> {
+    peer.parse::<SocketAddr>().map(|addr| addr.ip()).ok()
+}
+
+pub struct Node;
+
+impl Node {
+    pub fn builder() -> NodeBuilder {
+        NodeBuilder::default()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NodeBuilder {
+    port: u16,
+    store: Option<PathBuf>,
+    params: Arc<ChainParams>,
+    txindex: bool,
+    addrindex: bool,
+    regtest: bool,
+    peers: Vec<String>,
+    webhook: Option<String>,
+    policy: MempoolPolicy,
+    admin_socket: Option<PathBuf>,
+    banlist: Option<PathBuf>,
+    http: Option<String>,
+    schedule: Schedule,
+    identity: Option<PathBuf>,
+    checkpoint_key: Option<PathBuf>,
+    finality: Finality,
+    snapshots: Option<PathBuf>,
+    rate_limits: RateLimits,
+    peer_memory: Option<usize>,
+    check_depth: u64,
+    ephemeral: bool,
+    log_level: LogLevel,
+}
+
+impl Default for NodeBuilder {
+    fn default() -> Self {
+        NodeBuilder {
+            port: 9000,
+            store: None,
+            params: Arc::default(),
+            txindex: false,
+            addrindex: false,
+            regtest: false,
+            peers: vec![],
+            webhook: None,
+            policy: MempoolPolicy::default(),
+            admin_socket: None,
+            banlist: None,
+            http: None,
+            schedule: Schedule::default(),
+            identity: None,
+            checkpoint_key: None,
+            finality: Finality::default(),
+            snapshots: None,
+            rate_limits: RateLimits::default(),
+            peer_memory: Some(DEFAULT_PEER_MEMORY),
+            check_depth: btclib::SELF_CHECK_DEPTH,
+            ephemeral: false,
+            log_level: LogLevel::Info,
+        }
+    }
+}
+
+impl NodeBuilder {
+    /// port to listen on, 0 picks a free one, see `NodeHandle::local_addr`
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// File the chain is loaded from and periodically saved to. Without one the node starts
+    /// from scratch and keeps the chain in memory only
+    pub fn store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.store = Some(path.into());
+        self
+    }
+
+    /// the network to run, the default one otherwise
+    pub fn params(mut self, params: Arc<ChainParams>) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// index all transactions of the chain so peers can look them up with FetchTransaction
+    pub fn txindex(mut self, txindex: bool) -> Self {
+        self.txindex = txindex;
+        self
+    }
+
+    /// index the history of every public key so wallets can fetch it with FetchHistory
+    pub fn addrindex(mut self, addrindex: bool) -> Self {
+        self.addrindex = addrindex;
+        self
+    }
+
+    /// run on regtest, allowing GenerateBlocks to mine blocks instantly. The chain takes blocks
+    /// mined at `REGTEST_TARGET` then, see `ChainParams::regtest`
+    pub fn regtest(mut self, regtest: bool) -> Self {
+        self.regtest = regtest;
+        self
+    }
+
+    /// Nodes to connect to and download the chain from, or the blocks past the one in the store
+    /// if there's one already
+    pub fn peers(mut self, peers: impl IntoIterator<Item = String>) -> Self {
+        self.peers.extend(peers);
+        self
+    }
+
+    /// what the mempool takes beyond consensus, the defaults otherwise
+    pub fn mempool_policy(mut self, policy: MempoolPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Unix socket to take operator commands on, see `admin`. Only the user running the node can
+    /// use it
+    pub fn admin_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.admin_socket = Some(path.into());
+        self
+    }
+
+    /// File banned peers are loaded from and saved to, see `banlist`. Without one bans only last
+    /// until the node stops
+    pub fn banlist(mut self, path: impl Into<PathBuf>) -> Self {
+        self.banlist = Some(path.into());
+        self
+    }
+
+    /// http:// url double spends get POSTed to as JSON
+    pub fn webhook(mut self, url: impl Into<String>) -> Self {
+        self.webhook = Some(url.into());
+        self
+    }
+
+    /// address to serve the `http` endpoint on, a port of 0 picks a free one, see
+    /// `NodeHandle::http_addr`
+    pub fn http(mut self, addr: impl Into<String>) -> Self {
+        self.http = Some(addr.into());
+        self
+    }
+
+    /// File the node's identity key is kept in, made on the first run. Without one the node is
+    /// someone else every time it starts
+    pub fn identity(mut self, path: impl Into<PathBuf>) -> Self {
+        self.identity = Some(path.into());
+        self
+    }
+
+    /// File with the private key of one of the chain's checkpoint authorities, for the node to
+    /// sign checkpoints with, see `checkpoint`
+    pub fn checkpoint_key(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint_key = Some(path.into());
+        self
+    }
+
+    /// when blocks count as final in the status, `DEFAULT_FINAL_CONFIRMATIONS` otherwise
+    pub fn finality(mut self, finality: Finality) -> Self {
+        self.finality = finality;
+        self
+    }
+
+    /// Directory to write snapshots of the chain to for new nodes to start from, served on the
+    /// `http` endpoint, see `snapshot`
+    pub fn serve_snapshots(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.snapshots = Some(dir.into());
+        self
+    }
+
+    /// which messages the node prints, `LogLevel::Info` and up otherwise. The admin socket's
+    /// `setloglevel` changes it while the node runs
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    /// how fast each connection may send and receive, unlimited otherwise
+    pub fn rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// bytes of frames buffered for all peers together, `DEFAULT_PEER_MEMORY` otherwise, None
+    /// for no limit. Past it, frames peers send are refused and their connections closed
+    pub fn peer_memory(mut self, limit: Option<usize>) -> Self {
+        self.peer_memory = limit;
+        self
+    }
+
+    /// blocks at the tip checked when the chain is loaded from the store, `SELF_CHECK_DEPTH`
+    /// otherwise, see `Blockchain::self_check`
+    pub fn check_depth(mut self, depth: u64) -> Self {
+        self.check_depth = depth;
+        self
+    }
+
+    /// how often the background jobs run, the defaults otherwise
+    pub fn schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Keep nothing on disk, for demos: no store, banlist, identity file, snapshots or admin
+    /// socket even if given, on regtest, and a seed without a genesis in its chain spec mines one
+    /// right away
+    pub fn ephemeral(mut self, ephemeral: bool) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    /// load or download the chain, start listening and start the background tasks
+    pub async fn spawn(mut self) -> Result<NodeHandle> {
+        if self.ephemeral {
+            self.store = None;
+            self.banlist = None;
+            self.identity = None;
+            self.snapshots = None;
+            self.admin_socket = None;
+            self.regtest = true;
+        }
+        // regtest nodes take the blocks GenerateBlocks mines
+        if self.regtest && !self.params.regtest {
+            self.params = Arc::new(ChainParams {
+                regtest: true,
+                ..(*self.params).clone()
+            });
+        }
+        let webhook = self
+            .webhook
+            .as_deref()
+            .map(webhook::Webhook::parse)
+            .transpose()?;
+        let params = self.params;
+        let bans = BanList::load(self.banlist.clone())?;
+        let (identity, made_identity) = util::load_identity(self.identity.as_deref())?;
+        let checkpoint_key = match &self.checkpoint_key {
+            Some(path) => Some(checkpoint::load_key(path, &params)?),
+            None => None,
+        };
+        if self.snapshots.is_some() && self.http.is_none() {
+            anyhow::bail!("snapshots are served on the http endpoint, the node needs one");
+        }
+        let mut ctx = NodeContext::new(
+            params.clone(),
+            self.regtest,
+            self.store.clone(),
+            bans,
+            identity,
+            checkpoint_key,
+            self.finality,
+        );
+        ctx.snapshots = self.snapshots.map(Snapshots::new).transpose()?;
+        ctx.bandwidth = Bandwidth::new(self.rate_limits);
+        ctx.memory = Arc::new(MemoryMeter::new(self.peer_memory));
+        ctx.log.set_level(self.log_level);
+        let ctx = Arc::new(ctx);
+        info!(ctx, "running on the {} network", params.name);
+        if let Some(path) = self.identity.as_deref().filter(|_| made_identity) {
+            info!(ctx, "made a new node identity in {}", path.display());
+        }
+        info!(
+            ctx,
+            "node identity: {}",
+            btclib::network::fingerprint(&ctx.identity.public_key())
+        );
+
+        match &self.store {
+            Some(path) if Path::new(path).exists() => {
+                util::load_blockchain(&ctx, path, params, self.check_depth).await?;
+                if !self.peers.is_empty()
+                    && let Err(e) = util::catch_up(&ctx, &self.peers).await
+                {
+                    warn!(
+                        ctx,
+                        "failed to catch up with peers, carrying on with the stored chain: {e:#}"
+                    );
+                }
+            }
+            _ => {
+                util::populate_connections(&ctx, &self.peers).await?;
+                info!(ctx, "total amount of known nodes: {}", ctx.nodes.len());
+
+                if self.peers.is_empty() {
+                    info!(ctx, "no initial nodes provided, starting as a seed node");
+                    if let Some(genesis) = params.genesis_block() {
+                        let mut blockchain = ctx.blockchain.write().await;
+                        blockchain.add_block(genesis?)?;
+                        blockchain.rebuild_utxos();
+                        info!(ctx, "added the chain spec's genesis block");
+                    } else if self.ephemeral {
+                        let mut blockchain = ctx.blockchain.write().await;
+                        let genesis = template::instant_genesis(&blockchain)?;
+                        info!(ctx, "mined genesis block {}", genesis.hash());
+                        blockchain.add_block(genesis)?;
+                        blockchain.rebuild_utxos();
+                    }
+                } else {
+                    ctx.syncing.store(true, Ordering::Relaxed);
+                    let (longest_name, longest_count) =
+                        util::find_longest_chain_node(&ctx, 0).await?;
+                    // download blockchain from the node with the longest blockchain
+                    util::download_blockchain(&ctx, &longest_name, 0, longest_count).await?;
+                    info!(ctx, "blockchain downloaded from: {longest_name}");
+                    ctx.syncing.store(false, Ordering::Relaxed);
+                    //recalculate utxos
+                    let mut blockchain = ctx.blockchain.write().await;
+                    blockchain.rebuild_utxos();
+                    blockchain.retarget();
+                }
+            }
+        }
+
+        if let Some(path) = &self.store {
+            util::recover_blockchain(&ctx, path).await?;
+        }
+        ctx.blockchain.write().await.set_mempool_policy(self.policy);
+        if self.txindex {
+            info!(ctx, "building the transaction index...");
+            ctx.blockchain.write().await.enable_txindex();
+        }
+        if self.addrindex {
+            info!(ctx, "building the address index...");
+            ctx.blockchain.write().await.enable_addrindex();
+        }
+
+        // tasks, subscribed right away so nothing is missed before they first run
+        let evictions = ctx.blockchain.read().await.mempool().subscribe();
+        let mut tasks = vec![tokio::spawn(util::relay_evictions(ctx.clone(), evictions))];
+        if let Some(webhook) = webhook {
+            let events = ctx.events.subscribe();
+            tasks.push(tokio::spawn(webhook::post_double_spends(
+                ctx.clone(),
+                events,
+                webhook,
+            )));
+        }
+        let schedule = &self.schedule;
+        tasks.extend(scheduler::every(
+            &ctx,
+            "cleanup",
+            schedule.cleanup,
+            util::cleanup,
+        ));
+        if let Some(path) = self.store {
+            tasks.extend(scheduler::every(&ctx, "save", schedule.save, move |ctx| {
+                let path = path.clone();
+                async move { util::save_blockchain(&ctx, &path).await }
+            }));
+        }
+        tasks.extend(scheduler::every(
+            &ctx,
+            "peers",
+            schedule.peers,
+            util::maintain_peers,
+        ));
+        tasks.extend(scheduler::every(
+            &ctx,
+            "rebroadcast",
+            schedule.rebroadcast,
+            handler::rebroadcast,
+        ));
+        if ctx.checkpoint_key.is_some() {
+            tasks.extend(scheduler::every(
+                &ctx,
+                "checkpoint",
+                schedule.checkpoint,
+                checkpoint::sign_tip,
+            ));
+        }
+        if ctx.snapshots.is_some() {
+            tasks.extend(scheduler::every(
+                &ctx,
+                "snapshot",
+                schedule.snapshot,
+                snapshot::export,
+            ));
+        }
+        if let Some(path) = &self.admin_socket {
+            #[cfg(unix)]
+            tasks.push(admin::listen(ctx.clone(), path)?);
+            #[cfg(not(unix))]
+            anyhow::bail!("no admin socket at {}, it needs unix", path.display());
+        }
+        let http_addr = match &self.http {
+            Some(addr) => {
+                let (task, addr) = http::listen(ctx.clone(), addr).await?;
+                tasks.push(task);
+                Some(addr)
+            }
+            None => None,
+        };
+
+        let addr = format!("0.0.0.0:{}", self.port);
+        let listener = TcpListener::bind(&addr).await?;
+        let local_addr = listener.local_addr()?;
+        info!(ctx, "Listening on {local_addr}");
+        let listener = tokio::spawn({
+            let ctx = ctx.clone();
+            async move {
+                loop {
+                    tokio::select! {
+                        accepted = listener.accept() => {
+                            let (socket, _) = accepted?;
+                            tokio::spawn(handler::handle_connection(ctx.clone(), socket));
+                        }
+                        _ = ctx.shutdown.notified() => {
+                            info!(ctx, "shutting down");
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(NodeHandle {
+            ctx,
+            local_addr,
+            http_addr,
+            tasks,
+            listener,
+        })
+    }
+}
+
+/// A running node. Dropping it stops the node, connections already open are served until the
+/// peer closes them
+pub struct NodeHandle {
+    ctx: Arc<NodeContext>,
+    local_addr: SocketAddr,
+    http_addr: Option<SocketAddr>,
+    tasks: Vec<JoinHandle<()>>,
+    listener: JoinHandle<Result<()>>,
+}
+
+impl NodeHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// where the `http` endpoint is served, if the node was built with `NodeBuilder::http`
+    pub fn http_addr(&self) -> Option<SocketAddr> {
+        self.http_addr
+    }
+
+    /// which messages the node prints, see `NodeBuilder::log_level`
+    pub fn log_level(&self) -> LogLevel {
+        self.ctx.log.level()
+    }
+
+    pub fn set_log_level(&self, level: LogLevel) {
+        self.ctx.log.set_level(level);
+    }
+
+    /// the last `count` lines the node logged, oldest first, the way the admin socket's `logs`
+    /// shows them
+    pub fn recent_logs(&self, count: usize) -> Vec<String> {
+        self.ctx.log.recent(count)
+    }
+
+    /// An in-memory connection to the node, served just like a peer connecting over TCP. `peer`
+    /// names it the way an address would
+    pub fn connect(&self, peer: impl Into<String>) -> DuplexStream {
+        let (client, server) = tokio::io::duplex(DUPLEX_BUFFER);
+        tokio::spawn(handler::handle_stream(
+            self.ctx.clone(),
+            peer.into(),
+            server,
+        ));
+        client
+    }
+
+    /// Add a block as if a miner submitted it, and pass it on to wallets and other nodes
+    pub async fn submit_block(&self, block: Block) -> btclib::error::Result<()> {
+        handler::submit_block(&self.ctx, block).await
+    }
+
+    /// Add a transaction to the mempool as if a wallet submitted it, and pass it on to wallets
+    /// and other nodes
+    pub async fn submit_transaction(&self, transaction: Transaction) -> btclib::error::Result<()> {
+        handler::submit_transaction(&self.ctx, transaction).await
+    }
+
+    /// Every block and transaction the node accepts from now on, wherever it came from, and
+    /// every transaction leaving the mempool unmined
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.ctx.events.subscribe()
+    }
+
+    /// the key the node proves who it is with
+    pub fn identity(&self) -> PublicKey {
+        self.ctx.identity.public_key()
+    }
+
+    /// the latest snapshot the node wrote, if it serves them
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        self.ctx.snapshots.as_ref().and_then(Snapshots::latest)
+    }
+
+    /// what the node answers to GetStatus
+    pub async fn status(&self) -> NodeStatus {
+        self.ctx.status().await
+    }
+
+    /// what went over the node's connections, by peer and message
+    pub fn traffic(&self) -> TrafficReport {
+        self.ctx.bandwidth.report()
+    }
+
+    pub async fn memory(&self) -> MemoryUsage {
+        let blockchain = self.ctx.blockchain.read().await;
+        self.ctx.memory_usage(&blockchain)
+    }
+
+    pub async fn blockchain(&self) -> RwLockReadGuard<'_, Blockchain> {
+        self.ctx.blockchain.read().await
+    }
+
+    /// run until the node stops accepting connections
+    pub async fn wait(mut self) -> Result<()> {
+        (&mut self.listener).await?
+    }
+}
+
+impl Drop for NodeHandle {
+    fn drop(&mut self) {
+        self.listener.abort();
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
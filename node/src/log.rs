@@ -0,0 +1,137 @@
+//! What the node prints and how much of it. Messages above the node's level are skipped. The last
+//! `LOG_TAIL` lines printed are kept too, for the admin socket's `logs` and the dashboard
+
+use chrono::Utc;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// lines of the log kept in memory
+pub const LOG_TAIL: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    /// every message the node handles
+    Debug,
+}
+
+/// how much one node prints and what it printed last, kept in its `NodeContext`
+#[derive(Debug)]
+pub(crate) struct Log {
+    level: AtomicU8,
+    recent: Mutex<VecDeque<String>>,
+}
+
+impl LogLevel {
+    pub const ALL: &[LogLevel] = &[
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+    ];
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Log {
+            level: AtomicU8::new(LogLevel::Info as u8),
+            recent: Mutex::new(VecDeque::with_capacity(LOG_TAIL)),
+        }
+    }
+}
+
+impl Log {
+    pub fn level(&self) -> LogLevel {
+        LogLevel::ALL[self.level.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set_level(&self, level: LogLevel) {
+        self.level.store(level as u8, Ordering::Relaxed);
+    }
+
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level <= self.level()
+    }
+
+    /// keep `line` for `recent`, the oldest goes past `LOG_TAIL`
+    pub fn record(&self, level: LogLevel, line: &str) {
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == LOG_TAIL {
+            recent.pop_front();
+        }
+        let time = Utc::now().format("%H:%M:%S");
+        recent.push_back(format!("{time} {:<5} {line}", level.to_string()));
+    }
+
+    /// the last `count` lines logged, oldest first, with the time and level in front
+    pub fn recent(&self, count: usize) -> Vec<String> {
+        let recent = self.recent.lock().unwrap();
+        recent
+            .iter()
+            .skip(recent.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLevel::Error => write!(f, "error"),
+            LogLevel::Warn => write!(f, "warn"),
+            LogLevel::Info => write!(f, "info"),
+            LogLevel::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LogLevel::ALL
+            .iter()
+            .find(|level| level.to_string() == s.to_lowercase())
+            .copied()
+            .ok_or_else(|| format!("unknown log level {s}, expected error, warn, info or debug"))
+    }
+}
+
+/// `$ctx` is the `NodeContext` of the node logging, whose level decides
+macro_rules! log {
+    ($ctx:expr, $level:expr, $($arg:tt)*) => {
+        if $ctx.log.enabled($level) {
+            let line = format!($($arg)*);
+            $ctx.log.record($level, &line);
+            println!("{line}");
+        }
+    };
+}
+
+/// to stderr, unlike the others
+macro_rules! error {
+    ($ctx:expr, $($arg:tt)*) => {
+        if $ctx.log.enabled($crate::log::LogLevel::Error) {
+            let line = format!($($arg)*);
+            $ctx.log.record($crate::log::LogLevel::Error, &line);
+            eprintln!("{line}");
+        }
+    };
+}
+
+macro_rules! warn {
+    ($ctx:expr, $($arg:tt)*) => { log!($ctx, $crate::log::LogLevel::Warn, $($arg)*) };
+}
+
+macro_rules! info {
+    ($ctx:expr, $($arg:tt)*) => { log!($ctx, $crate::log::LogLevel::Info, $($arg)*) };
+}
+
+macro_rules! debug {
+    ($ctx:expr, $($arg:tt)*) => { log!($ctx, $crate::log::LogLevel::Debug, $($arg)*) };
+}
@@ -1,23 +1,14 @@
 use anyhow::Result;
 use argh::*;
-use btclib::types::Blockchain;
-use dashmap::DashMap;
-use static_init::dynamic;
-use std::path::Path;
-use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::RwLock,
-};
-
-mod handler;
-mod util;
-
-#[dynamic]
-pub static BLOCKCHAIN: RwLock<Blockchain> = RwLock::new(Blockchain::new());
-
-#[dynamic]
-/// Node pool
-pub static NODES: DashMap<String, TcpStream> = DashMap::new();
+use btclib::chain_params::ChainParams;
+use btclib::finality::Finality;
+use btclib::network::Message;
+use btclib::output::StatusOutput;
+use btclib::types::MempoolPolicy;
+use node::{Node, RateLimits, Schedule};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
 
 #[derive(FromArgs, Debug)]
 /// A toy blockchain node :D
@@ -28,6 +19,113 @@ struct Args {
     #[argh(option, default = "String::from(\"./blockchain.cbor\")")]
     /// blockchain file location
     blockchain_file: String,
+    #[argh(option)]
+    /// chain spec file for a custom network, the default network otherwise
+    chainspec: Option<String>,
+    #[argh(switch)]
+    /// index all transactions of the chain so peers can look them up with FetchTransaction
+    txindex: bool,
+    #[argh(switch)]
+    /// index the history of every public key so wallets can fetch it with FetchHistory
+    addrindex: bool,
+    #[argh(switch)]
+    /// run on regtest, allowing GenerateBlocks to mine blocks instantly
+    regtest: bool,
+    #[argh(switch)]
+    /// keep the chain in memory only and write no files, on regtest with a genesis block mined
+    /// right away. For demos
+    ephemeral: bool,
+    #[argh(option)]
+    /// sats per byte a transaction has to pay to get into the mempool, 0 by default
+    min_fee_rate: Option<u64>,
+    #[argh(option)]
+    /// biggest transaction taken into the mempool in bytes
+    max_tx_size: Option<usize>,
+    #[argh(option)]
+    /// megabytes of transactions the mempool keeps
+    max_mempool_mb: Option<usize>,
+    #[argh(switch)]
+    /// refuse transactions that pay no fee at all
+    no_zero_fee: bool,
+    #[argh(option)]
+    /// seconds a transaction waits in the mempool to be mined before it expires
+    mempool_expiry: Option<u64>,
+    #[argh(option, default = "String::from(\"./banlist.json\")")]
+    /// file banned peers are kept in across restarts
+    banlist: String,
+    #[argh(option, default = "String::from(\"./node_identity.cbor\")")]
+    /// file the node's identity key is kept in, made on the first run
+    identity_file: String,
+    #[argh(option)]
+    /// http:// url to POST double spends to as JSON
+    webhook: Option<String>,
+    #[argh(option)]
+    /// unix socket to take operator commands on, a cookie to use it is written next to it
+    admin_socket: Option<String>,
+    #[argh(option)]
+    /// address to serve chain stats, and with --addrindex the rich list, on over http for
+    /// dashboards, e.g. 127.0.0.1:8080
+    http: Option<String>,
+    #[argh(option)]
+    /// directory to write chain snapshots to and serve them from with --http, for new nodes to
+    /// start from
+    serve_snapshots: Option<String>,
+    #[argh(option)]
+    /// seconds between writing snapshots with --serve-snapshots
+    snapshot_interval: Option<u64>,
+    #[argh(option)]
+    /// kilobytes a second the node sends each peer at most
+    max_upload: Option<u64>,
+    #[argh(option)]
+    /// kilobytes a second the node reads from each peer at most
+    max_download: Option<u64>,
+    #[argh(option)]
+    /// megabytes of frames the node buffers for all its peers together, 256 by default, 0 for
+    /// no limit
+    max_peer_memory: Option<usize>,
+    #[argh(option, default = "btclib::SELF_CHECK_DEPTH")]
+    /// blocks at the tip checked when the stored chain is loaded
+    check_depth: u64,
+    #[argh(option)]
+    /// seconds between evicting old mempool transactions, 0 turns it off
+    cleanup_interval: Option<u64>,
+    #[argh(option)]
+    /// seconds between saving the chain, 0 turns it off
+    save_interval: Option<u64>,
+    #[argh(option)]
+    /// seconds between dropping banned peers and forgetting expired bans, 0 turns it off
+    peer_interval: Option<u64>,
+    #[argh(option)]
+    /// seconds between sending the mempool to peers again, 0 turns it off
+    rebroadcast_interval: Option<u64>,
+    #[argh(option)]
+    /// private key of one of the chain spec's checkpoint authorities, to sign checkpoints with
+    checkpoint_key: Option<String>,
+    #[argh(option)]
+    /// seconds between signing the tip as a checkpoint with --checkpoint-key
+    checkpoint_interval: Option<u64>,
+    #[argh(option, default = "btclib::DEFAULT_FINAL_CONFIRMATIONS")]
+    /// confirmations before a block counts as final in the status
+    final_confirmations: u64,
+    #[argh(switch)]
+    /// count checkpointed blocks as final right away
+    checkpoint_finality: bool,
+    #[argh(option)]
+    /// send a command, e.g. "ban 10.0.0.1", to the node with the admin socket and exit
+    admin: Option<String>,
+    #[argh(option)]
+    /// print the status of the node at this address and exit
+    status: Option<String>,
+    #[argh(option)]
+    /// show a live dashboard of the node at this address, with --admin-socket its peers and log
+    /// too
+    tui: Option<String>,
+    #[argh(option)]
+    /// seconds between redrawing the --tui dashboard, 2 by default
+    refresh: Option<u64>,
+    #[argh(switch)]
+    /// print what --status and --admin answer as JSON
+    json: bool,
     #[argh(positional)]
     /// addresses of inital nodes
     nodes: Vec<String>,
@@ -36,42 +134,188 @@ struct Args {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args: Args = argh::from_env();
-    let port = args.port;
-    let blockchain_path = args.blockchain_file;
-    let nodes = args.nodes;
 
-    if Path::new(&blockchain_path).exists() {
-        util::load_blockchain(&blockchain_path).await?;
+    if let Some(addr) = &args.status {
+        return print_status(addr, args.json).await;
+    }
+    if let Some(addr) = &args.tui {
+        return run_dashboard(addr, args.admin_socket.as_deref(), args.refresh).await;
+    }
+    if let Some(command) = &args.admin {
+        let Some(socket) = &args.admin_socket else {
+            anyhow::bail!("--admin needs the node's --admin-socket");
+        };
+        return send_admin_command(socket, command, args.json).await;
+    }
+
+    let params = match &args.chainspec {
+        Some(path) => Arc::new(ChainParams::load_from_file(path)?),
+        None => Arc::new(ChainParams::default()),
+    };
+
+    let defaults = MempoolPolicy::default();
+    let policy = MempoolPolicy {
+        min_fee_rate: args.min_fee_rate.unwrap_or(defaults.min_fee_rate),
+        max_transaction_size: args.max_tx_size.unwrap_or(defaults.max_transaction_size),
+        max_mempool_size: args
+            .max_mempool_mb
+            .map_or(defaults.max_mempool_size, |mb| mb.saturating_mul(1_000_000)),
+        allow_zero_fee: !args.no_zero_fee,
+        max_transaction_age: args.mempool_expiry.unwrap_or(defaults.max_transaction_age),
+    };
+
+    let defaults = Schedule::default();
+    let seconds = |interval: Option<u64>, default| interval.map_or(default, Duration::from_secs);
+    let schedule = Schedule {
+        cleanup: seconds(args.cleanup_interval, defaults.cleanup),
+        save: seconds(args.save_interval, defaults.save),
+        peers: seconds(args.peer_interval, defaults.peers),
+        rebroadcast: seconds(args.rebroadcast_interval, defaults.rebroadcast),
+        checkpoint: seconds(args.checkpoint_interval, defaults.checkpoint),
+        snapshot: seconds(args.snapshot_interval, defaults.snapshot),
+    };
+
+    let finality = if args.checkpoint_finality {
+        Finality::Checkpoints(args.final_confirmations)
     } else {
-        util::populate_connections(&nodes).await?;
-        println!("total amount of known nodes: {}", NODES.len());
-
-        if nodes.is_empty() {
-            println!("no initial nodes provided, starting as a seed node");
-        } else {
-            let (longest_name, longest_count) = util::find_longest_chain_node().await?;
-            // download blockchain from the node with the longest blockchain
-            util::download_blockchain(&longest_name, longest_count).await?;
-            println!("blockchain downloaded from: {longest_name}");
-            //recalculate utxos
-            let mut blockchain = BLOCKCHAIN.write().await;
-            blockchain.rebuild_utxos();
-            drop(blockchain);
-            let mut blockchain = BLOCKCHAIN.write().await;
-            blockchain.try_adjust_target();
-            drop(blockchain);
+        Finality::Confirmations(args.final_confirmations)
+    };
+
+    let kilobytes = |rate: Option<u64>| rate.map(|kb| kb.saturating_mul(1000));
+    let rate_limits = RateLimits {
+        upload: kilobytes(args.max_upload),
+        download: kilobytes(args.max_download),
+    };
+
+    let mut builder = Node::builder()
+        .mempool_policy(policy)
+        .schedule(schedule)
+        .finality(finality)
+        .rate_limits(rate_limits)
+        .check_depth(args.check_depth);
+    match args.max_peer_memory {
+        Some(0) => builder = builder.peer_memory(None),
+        Some(mb) => builder = builder.peer_memory(Some(mb.saturating_mul(1_000_000))),
+        None => {}
+    }
+    if let Some(url) = args.webhook {
+        builder = builder.webhook(url);
+    }
+    if let Some(path) = args.admin_socket {
+        builder = builder.admin_socket(path);
+    }
+    if let Some(addr) = args.http {
+        builder = builder.http(addr);
+    }
+    if let Some(dir) = args.serve_snapshots {
+        builder = builder.serve_snapshots(dir);
+    }
+    if let Some(path) = args.checkpoint_key {
+        builder = builder.checkpoint_key(path);
+    }
+    builder
+        .port(args.port)
+        .store(args.blockchain_file)
+        .banlist(args.banlist)
+        .identity(args.identity_file)
+        .params(params)
+        .txindex(args.txindex)
+        .addrindex(args.addrindex)
+        .regtest(args.regtest)
+        .ephemeral(args.ephemeral)
+        .peers(args.nodes)
+        .spawn()
+        .await?
+        .wait()
+        .await
+}
+
+async fn print_status(addr: &str, json: bool) -> Result<()> {
+    let mut stream = TcpStream::connect(addr).await?;
+    Message::GetStatus.send_async(&mut stream).await?;
+    match Message::receive_async(&mut stream).await? {
+        Message::Status(status) if json => {
+            println!("{}", serde_json::to_string(&StatusOutput::from(&*status))?)
         }
+        Message::Status(status) => println!("{status}"),
+        message => anyhow::bail!("unexpected reply from {addr}: {message:?}"),
     }
+    Ok(())
+}
 
-    // tasks
-    tokio::spawn(util::cleanup());
-    tokio::spawn(util::save(blockchain_path.clone()));
+#[cfg(unix)]
+async fn send_admin_command(socket: &str, command: &str, json: bool) -> Result<()> {
+    use node::admin::{AdminReply, request};
 
-    let addr = format!("0.0.0.0:{port}");
-    let listener = TcpListener::bind(&addr).await?;
-    println!("Listening on {addr}");
-    loop {
-        let (socket, _) = listener.accept().await?;
-        tokio::spawn(handler::handle_connection(socket));
+    let command = command.parse().map_err(anyhow::Error::msg)?;
+    let reply = request(socket.as_ref(), &command).await?;
+    // the socket answers in JSON already, see `AdminReply`
+    if json {
+        println!("{}", serde_json::to_string(&reply)?);
+        if let AdminReply::Error(e) = reply {
+            anyhow::bail!(e);
+        }
+        return Ok(());
     }
+    match reply {
+        AdminReply::Done => println!("done"),
+        AdminReply::Mempool(entries) => {
+            for entry in entries {
+                println!(
+                    "{} {} bytes, {} sats, since {}",
+                    entry.txid,
+                    entry.size,
+                    entry.fee,
+                    entry.received.format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+        }
+        AdminReply::Bans(bans) => {
+            for ban in bans {
+                println!(
+                    "{} until {}: {}",
+                    ban.ip,
+                    ban.until.format("%Y-%m-%d %H:%M:%S"),
+                    ban.reason
+                );
+            }
+        }
+        AdminReply::Peers(peers) => {
+            for peer in peers {
+                let latency = peer
+                    .latency_ms
+                    .map_or_else(|| "?".to_string(), |ms| format!("{ms} ms"));
+                println!("{} {latency}, {}", peer.addr, peer.capabilities);
+            }
+        }
+        AdminReply::Logs(lines) => {
+            for line in lines {
+                println!("{line}");
+            }
+        }
+        AdminReply::Error(e) => anyhow::bail!(e),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn run_dashboard(addr: &str, admin_socket: Option<&str>, refresh: Option<u64>) -> Result<()> {
+    use node::dashboard::{DEFAULT_REFRESH, run};
+
+    let refresh = refresh.map_or(DEFAULT_REFRESH, Duration::from_secs);
+    run(addr, admin_socket.map(std::path::Path::new), refresh).await
+}
+
+#[cfg(not(unix))]
+async fn run_dashboard(
+    _addr: &str,
+    _admin_socket: Option<&str>,
+    _refresh: Option<u64>,
+) -> Result<()> {
+    anyhow::bail!("the dashboard needs unix")
+}
+
+#[cfg(not(unix))]
+async fn send_admin_command(_socket: &str, _command: &str, _json: bool) -> Result<()> {
+    anyhow::bail!("the admin socket needs unix")
 }
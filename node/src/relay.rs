@@ -0,0 +1,88 @@
+//! Sending blocks and transactions to peers. What the node mined or a wallet handed it goes out
+//! first: it's written to the peers before light wallets and subscribers hear of it, and gossip,
+//! i.e. the mempool sent again and checkpoints passed on, waits while it's being sent. Gossip
+//! takes turns message by message, so a rebroadcast of a big mempool holds it up by one
+//! transaction at most. How long the node's own blocks and transactions take to get out is part
+//! of its status. Peers only get messages they said they support, see `Capabilities`.
+
+use btclib::network::{Message, Propagation};
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+
+use crate::NodeContext;
+
+#[derive(Default)]
+pub(crate) struct Relay {
+    /// gossip reads, the node's own sends write, so they go ahead of any gossip waiting
+    gate: RwLock<()>,
+    blocks: Mutex<Propagation>,
+    transactions: Mutex<Propagation>,
+}
+
+impl Relay {
+    pub fn blocks(&self) -> Propagation {
+        self.blocks.lock().unwrap().clone()
+    }
+
+    pub fn transactions(&self) -> Propagation {
+        self.transactions.lock().unwrap().clone()
+    }
+}
+
+/// Send `message` to every known node, encoding it only once
+pub(crate) async fn broadcast(ctx: &NodeContext, message: &Message) -> anyhow::Result<()> {
+    let frame = message.to_frame()?;
+    let _turn = ctx.relay.gate.read().await;
+    send_to_peers(ctx, &frame, message).await;
+    Ok(())
+}
+
+/// Send a block or transaction of the node's own, accepted at `accepted`, to every known node
+/// ahead of gossip
+pub(crate) async fn broadcast_own(
+    ctx: &NodeContext,
+    message: &Message,
+    accepted: Instant,
+) -> anyhow::Result<()> {
+    let frame = message.to_frame()?;
+    {
+        let _turn = ctx.relay.gate.write().await;
+        send_to_peers(ctx, &frame, message).await;
+    }
+    let ms = u64::try_from(accepted.elapsed().as_millis()).unwrap_or(u64::MAX);
+    let propagation = match message {
+        Message::NewBlock(_) => &ctx.relay.blocks,
+        _ => &ctx.relay.transactions,
+    };
+    propagation.lock().unwrap().record(ms);
+    debug!(ctx, "sent to {} peers in {ms}ms", ctx.nodes.len());
+    Ok(())
+}
+
+/// `frame` holding `message` to every known node that supports it
+async fn send_to_peers(ctx: &NodeContext, frame: &[u8], message: &Message) {
+    let needs = message.needs();
+    let nodes = ctx
+        .nodes
+        .iter()
+        .map(|x| x.key().clone())
+        .collect::<Vec<_>>();
+
+    for node in nodes {
+        if !ctx.peer_supports(&node, needs) {
+            debug!(ctx, "{node} doesn't support {needs}, not sending it");
+            continue;
+        }
+        debug!(ctx, "sending to friend: {node}");
+        let Some(mut stream) = ctx.nodes.get_mut(&node) else {
+            continue;
+        };
+        if stream.write_all(frame).await.is_err() {
+            warn!(ctx, "failed to send to {}", node);
+        } else {
+            ctx.bandwidth.meter(&node).sent(message.kind(), frame.len());
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Runs the node's periodic jobs: mempool cleanup, saving the chain, peer upkeep, rebroadcasting
+//! the mempool, for checkpoint authorities signing the tip and for nodes serving snapshots writing
+//! them. How often each runs is up to whoever runs the node, see `Schedule`.
+//! Every run is a task of its own, so one panicking is counted as a failure and the job runs
+//! again on time. How each job has been going is part of the node's status.
+
+use anyhow::Result;
+use btclib::network::TaskStatus;
+use chrono::Utc;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::{task::JoinHandle, time};
+
+use crate::NodeContext;
+use crate::supervisor::panic_message;
+
+/// How often the background jobs run, a zero interval turns a job off
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    /// evicting transactions too old for the mempool
+    pub cleanup: Duration,
+    /// writing the chain to the store, if the node has one
+    pub save: Duration,
+    /// dropping banned peers and forgetting expired bans and old misbehavior
+    pub peers: Duration,
+    /// sending the mempool to peers again, in case they missed some of it
+    pub rebroadcast: Duration,
+    /// signing the tip as a checkpoint, only for nodes with a checkpoint key
+    pub checkpoint: Duration,
+    /// writing a snapshot of the chain, only for nodes serving them
+    pub snapshot: Duration,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            cleanup: Duration::from_secs(30),
+            save: Duration::from_secs(15),
+            peers: Duration::from_secs(60),
+            rebroadcast: Duration::from_secs(10 * 60),
+            checkpoint: Duration::from_secs(60),
+            snapshot: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+/// what the jobs reported, in the order they were started
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    tasks: Mutex<Vec<TaskStatus>>,
+}
+
+impl Scheduler {
+    pub fn status(&self) -> Vec<TaskStatus> {
+        self.tasks.lock().unwrap().clone()
+    }
+
+    fn update(&self, name: &str, update: impl FnOnce(&mut TaskStatus)) {
+        if let Some(task) = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|t| t.name == name)
+        {
+            update(task);
+        }
+    }
+}
+
+/// Run `job` every `interval`, the first time right away. None if the interval is zero
+pub(crate) fn every<F, Fut>(
+    ctx: &Arc<NodeContext>,
+    name: &'static str,
+    interval: Duration,
+    job: F,
+) -> Option<JoinHandle<()>>
+where
+    F: Fn(Arc<NodeContext>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    if interval.is_zero() {
+        info!(ctx, "not running the {name} task");
+        return None;
+    }
+    ctx.scheduler.tasks.lock().unwrap().push(TaskStatus {
+        name: name.to_string(),
+        interval: interval.as_secs(),
+        runs: 0,
+        last_run: None,
+        failures: 0,
+        last_error: None,
+    });
+    let ctx = ctx.clone();
+    Some(tokio::spawn(async move {
+        let mut ticks = time::interval(interval);
+        ticks.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let error = match tokio::spawn(job(ctx.clone())).await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => {
+                    error!(ctx, "the {name} task failed: {e:#}");
+                    Some(format!("{e:#}"))
+                }
+                Err(e) => {
+                    let message = panic_message(e);
+                    error!(
+                        ctx,
+                        "the {name} task panicked, it runs again next time: {message}"
+                    );
+                    Some(format!("panicked: {message}"))
+                }
+            };
+            ctx.scheduler.update(name, |task| {
+                task.runs += 1;
+                task.last_run = Some(Utc::now());
+                if error.is_some() {
+                    task.failures += 1;
+                    task.last_error = error;
+                }
+            });
+        }
+    }))
+}
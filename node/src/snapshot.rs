@@ -0,0 +1,155 @@
+//! Snapshots of the chain for new nodes to start from, so they don't have to fetch every block
+//! from peers one by one. A node run with `--serve-snapshots DIR` writes its chain, unspent
+//! outputs included, to a file in `DIR` every so often and serves the latest on its `http`
+//! endpoint, with `GET /snapshot` saying which one it is and its sha256:
+//!
+//! ```text
+//! $ curl http://seed:8080/snapshot
+//! {"height":1200,"tip":"00a1…","utxos":830,"file":"chain-1200.cbor","sha256":"5f0e…",…}
+//! $ curl -o blockchain.cbor http://seed:8080/snapshots/chain-1200.cbor
+//! $ sha256sum blockchain.cbor
+//! $ node --blockchain-file blockchain.cbor seed:9000
+//! ```
+//!
+//! The new node loads it like a chain it saved itself and gets the blocks since from its peers.
+//! `DIR` ends up holding what's served, `snapshot.json` included, so any web server can serve
+//! it as well.
+
+use anyhow::{Context, Result};
+use btclib::{sha256::Hash, util::Saveable};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::NodeContext;
+
+/// snapshot files kept, the one before the latest stays for whoever's still downloading it
+const KEPT_SNAPSHOTS: usize = 2;
+/// what describes the latest snapshot, next to it
+const MANIFEST: &str = "snapshot.json";
+
+/// What `GET /snapshot` answers, the latest snapshot
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// blocks in it
+    pub height: u64,
+    /// hex hash of its last block
+    pub tip: String,
+    /// unspent outputs in it
+    pub utxos: usize,
+    /// name of the file, under `/snapshots/`
+    pub file: String,
+    /// bytes in the file
+    pub size: u64,
+    /// hex sha256 of the file, as `sha256sum` prints it
+    pub sha256: String,
+    pub created: DateTime<Utc>,
+}
+
+/// where the node writes snapshots to and the latest it wrote
+pub(crate) struct Snapshots {
+    dir: PathBuf,
+    latest: Mutex<Option<Snapshot>>,
+}
+
+impl Snapshots {
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create the snapshot dir {}", dir.display()))?;
+        Ok(Snapshots {
+            dir,
+            latest: Mutex::new(None),
+        })
+    }
+
+    pub fn latest(&self) -> Option<Snapshot> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    /// The snapshot file called `name`, None for anything that's not one, so no other file can
+    /// be asked for
+    pub fn file(&self, name: &str) -> Option<PathBuf> {
+        height_of(name)?;
+        let path = self.dir.join(name);
+        path.is_file().then_some(path)
+    }
+}
+
+fn file_name(height: u64) -> String {
+    format!("chain-{height}.cbor")
+}
+
+/// the height a snapshot file called `name` is at
+fn height_of(name: &str) -> Option<u64> {
+    name.strip_prefix("chain-")?
+        .strip_suffix(".cbor")?
+        .parse()
+        .ok()
+}
+
+/// Write a snapshot of the chain unless the latest is at the tip already
+pub(crate) async fn export(ctx: Arc<NodeContext>) -> Result<()> {
+    let Some(snapshots) = &ctx.snapshots else {
+        return Ok(());
+    };
+    // writing takes a while, a copy keeps the lock free for everyone else meanwhile
+    let blockchain = {
+        let blockchain = ctx.blockchain.read().await;
+        let latest = snapshots.latest();
+        if latest.is_some_and(|latest| latest.tip == blockchain.tip_hash().to_hex()) {
+            return Ok(());
+        }
+        blockchain.clone()
+    };
+    let mut bytes = vec![];
+    blockchain.save(&mut bytes)?;
+    let snapshot = Snapshot {
+        height: blockchain.block_height(),
+        tip: blockchain.tip_hash().to_hex(),
+        utxos: blockchain.utxos().len(),
+        file: file_name(blockchain.block_height()),
+        size: bytes.len() as u64,
+        sha256: Hash::of_bytes(&bytes).to_hex(),
+        created: Utc::now(),
+    };
+    let manifest = serde_json::to_vec(&snapshot)?;
+    write_atomically(&snapshots.dir.join(&snapshot.file), &bytes)?;
+    write_atomically(&snapshots.dir.join(MANIFEST), &manifest)?;
+    info!(
+        ctx,
+        "wrote a snapshot of {} blocks to {}", snapshot.height, snapshot.file
+    );
+    let height = snapshot.height;
+    *snapshots.latest.lock().unwrap() = Some(snapshot);
+    prune(&ctx, &snapshots.dir, height)
+}
+
+/// a crash while writing leaves the old file, not half of the new one
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, bytes)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Remove all but `KEPT_SNAPSHOTS` snapshot files, the one at `latest` and the highest others.
+/// After a reorg the latest can be lower than the ones before it
+fn prune(ctx: &NodeContext, dir: &Path, latest: u64) -> Result<()> {
+    let mut heights = vec![];
+    for entry in fs::read_dir(dir)? {
+        if let Some(height) = entry?.file_name().to_str().and_then(height_of)
+            && height != latest
+        {
+            heights.push(height);
+        }
+    }
+    heights.sort_unstable_by(|a, b| b.cmp(a));
+    for height in heights.into_iter().skip(KEPT_SNAPSHOTS - 1) {
+        debug!(ctx, "removing the snapshot at {height}");
+        fs::remove_file(dir.join(file_name(height)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,80 @@
+//! Keeps a bug in serving one peer from going unnoticed. Every connection is served in a task of
+//! its own, watched by the one that accepted it: when it panics, the panic is logged with what
+//! the connection had been doing and counted in the node's status, and the peer's entries are
+//! dropped like after any other disconnect, see `handler::handle_stream`.
+
+use btclib::network::Message;
+use std::sync::atomic::{AtomicU16, AtomicU64, Ordering};
+use std::time::Instant;
+use tokio::task::{JoinError, JoinHandle};
+
+use crate::NodeContext;
+
+/// no message read yet
+const NOTHING: u16 = u16::MAX;
+
+/// What a connection has been doing, for when it panics
+pub(crate) struct Activity {
+    started: Instant,
+    messages: AtomicU64,
+    /// kind of the message read last
+    last: AtomicU16,
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Activity {
+            started: Instant::now(),
+            messages: AtomicU64::new(0),
+            last: AtomicU16::new(NOTHING),
+        }
+    }
+
+    /// `message` was read and is being handled
+    pub fn handling(&self, message: &Message) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.last.store(message.kind(), Ordering::Relaxed);
+    }
+}
+
+/// Wait for the task serving `peer` to end, reporting it if it panicked
+pub(crate) async fn watch(
+    ctx: &NodeContext,
+    peer: &str,
+    activity: &Activity,
+    task: JoinHandle<()>,
+) {
+    let Err(e) = task.await else {
+        return;
+    };
+    if e.is_cancelled() {
+        return;
+    }
+    let panics = ctx.handler_panics.fetch_add(1, Ordering::Relaxed) + 1;
+    let last = match activity.last.load(Ordering::Relaxed) {
+        NOTHING => "none",
+        kind => Message::kind_name(kind).unwrap_or("unknown"),
+    };
+    error!(
+        ctx,
+        "connection handler panicked: peer={peer} last_message={last} messages={} \
+         connected_ms={} panics={panics} panic={:?}",
+        activity.messages.load(Ordering::Relaxed),
+        activity.started.elapsed().as_millis(),
+        panic_message(e)
+    );
+}
+
+/// what a task that panicked panicked with
+pub(crate) fn panic_message(e: JoinError) -> String {
+    let panic = e.try_into_panic().ok();
+    panic
+        .as_ref()
+        .and_then(|panic| {
+            panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+        })
+        .unwrap_or_else(|| "cancelled".to_string())
+}
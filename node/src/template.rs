@@ -0,0 +1,137 @@
+//! Block templates for miners. Picking the mempool transactions is what takes the time and miners
+//! keep polling for templates, so the pick is kept until the tip or the mempool changes, and so is
+//! each miner's template. The pick's merkle tree is kept too, see `MerkleTree`: a miner asking
+//! with another payout only costs a coinbase and the hashes on its branch, and when the mempool
+//! changes only the part of the tree from the first transaction picked differently is rehashed.
+
+use btclib::crypto::{PrivateKey, PublicKey};
+use btclib::error::Result;
+use btclib::sha256::Hash;
+use btclib::types::{Block, BlockBuilder, Blockchain, Payout, Transaction};
+use btclib::util::MerkleTree;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// templates kept for different keys before they're all dropped
+const MAX_CACHED_TEMPLATES: usize = 256;
+
+#[derive(Default)]
+pub(crate) struct TemplateCache {
+    cached: Mutex<Option<Cached>>,
+}
+
+/// what was worked out for one tip and mempool
+struct Cached {
+    tip: Hash,
+    /// see `Mempool::generation`
+    generation: u64,
+    txids: Vec<Hash>,
+    transactions: Vec<Transaction>,
+    /// of the coinbase, a stand-in until there is one, and `transactions`
+    tree: MerkleTree,
+    templates: HashMap<Payout, Block>,
+}
+
+impl Cached {
+    fn new(blockchain: &Blockchain) -> Self {
+        let txids = template_txids(blockchain);
+        let leaves = std::iter::once(Hash::zero()).chain(txids.iter().copied());
+        Cached {
+            tip: blockchain.tip_hash(),
+            generation: blockchain.mempool().generation(),
+            transactions: transactions(blockchain, &txids),
+            tree: MerkleTree::from_leaves(leaves.collect()),
+            txids,
+            templates: HashMap::new(),
+        }
+    }
+
+    /// pick again for the same tip, keeping what the pick starts with like before
+    fn update(&mut self, blockchain: &Blockchain) {
+        let txids = template_txids(blockchain);
+        let same = self
+            .txids
+            .iter()
+            .zip(&txids)
+            .take_while(|(old, new)| old == new)
+            .count();
+        // the coinbase comes first
+        self.tree.splice(same + 1, txids[same..].iter().copied());
+        self.transactions.truncate(same);
+        self.transactions
+            .extend(transactions(blockchain, &txids[same..]));
+        self.txids = txids;
+        self.generation = blockchain.mempool().generation();
+        self.templates.clear();
+    }
+}
+
+impl TemplateCache {
+    /// The template paying `payout` on top of the chain, the one handed out before if neither the
+    /// tip nor the mempool changed since
+    pub fn get(&self, blockchain: &Blockchain, payout: &Payout) -> Result<Block> {
+        let tip = blockchain.tip_hash();
+        let generation = blockchain.mempool().generation();
+        let mut cached = self.cached.lock().unwrap();
+        match cached.as_mut() {
+            Some(cached) if cached.tip == tip => {
+                if cached.generation != generation {
+                    cached.update(blockchain);
+                }
+            }
+            _ => *cached = Some(Cached::new(blockchain)),
+        }
+        let cached = cached.as_mut().expect("cached above");
+
+        if let Some(block) = cached.templates.get(payout) {
+            return Ok(block.clone());
+        }
+        let block = BlockBuilder::on_top_of(blockchain)
+            .add_txs(cached.transactions.clone())
+            .merkle_tree(cached.tree.clone())
+            .finalize_paying(
+                payout,
+                blockchain.calculate_block_reward(),
+                blockchain.utxos(),
+            )?;
+        if cached.templates.len() >= MAX_CACHED_TEMPLATES {
+            cached.templates.clear();
+        }
+        cached.templates.insert(payout.clone(), block.clone());
+        Ok(block)
+    }
+}
+
+/// A first block at `REGTEST_TARGET` paying a key nobody keeps, for ephemeral nodes to start
+/// with, see `NodeBuilder::ephemeral`
+pub(crate) fn instant_genesis(blockchain: &Blockchain) -> Result<Block> {
+    let mut block = create_template(blockchain, PrivateKey::new_key().public_key())?;
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    Ok(block)
+}
+
+/// Assemble a block template from the mempool with a coinbase paying `pubkey`, without the cache
+pub(crate) fn create_template(blockchain: &Blockchain, pubkey: PublicKey) -> Result<Block> {
+    let txids = template_txids(blockchain);
+    BlockBuilder::on_top_of(blockchain)
+        .add_txs(transactions(blockchain, &txids))
+        .finalize_paying(
+            &Payout::single(pubkey),
+            blockchain.calculate_block_reward(),
+            blockchain.utxos(),
+        )
+}
+
+fn template_txids(blockchain: &Blockchain) -> Vec<Hash> {
+    // the coinbase takes up one of the slots
+    blockchain.template_txids(btclib::BLOCK_TRANSACTION_CAP - 1)
+}
+
+fn transactions(blockchain: &Blockchain, txids: &[Hash]) -> Vec<Transaction> {
+    txids
+        .iter()
+        .filter_map(|txid| blockchain.mempool().get(txid).cloned())
+        .collect()
+}
@@ -1,72 +1,186 @@
+use crate::NodeContext;
 use anyhow::{Context, Result};
-use btclib::{network::Message, types::Blockchain, util::Saveable};
-use tokio::{net::TcpStream, time};
-
-pub async fn load_blockchain(blockchain_path: &str) -> Result<()> {
-    println!("blockchain file exists, loading...");
-    let new_blockchain = Blockchain::load_from_file(blockchain_path)?;
-    println!("blockchain loaded");
-    let mut blockchain = crate::BLOCKCHAIN.write().await;
+use btclib::{
+    chain_params::ChainParams,
+    crypto::PrivateKey,
+    network::{Capabilities, Encoding, Message, PROTOCOL_VERSION, check_identity, new_nonce},
+    types::{Block, Blockchain, Eviction, Recovery, Wal},
+    util::Saveable,
+};
+use chrono::Utc;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use tokio::{net::TcpStream, sync::broadcast};
+
+/// Load the chain saved at `blockchain_path` and check it before it's served, see
+/// `Blockchain::self_check`. Broken blocks refuse the start, a UTXO set or target that doesn't
+/// match the blocks is worked out again
+pub async fn load_blockchain(
+    ctx: &NodeContext,
+    blockchain_path: &Path,
+    params: Arc<ChainParams>,
+    check_depth: u64,
+) -> Result<()> {
+    info!(ctx, "blockchain file exists, loading...");
+    let mut new_blockchain = Blockchain::load_from_file(blockchain_path)?;
+    new_blockchain.set_params(params);
+    info!(ctx, "blockchain loaded");
+
+    let check = new_blockchain.self_check(check_depth);
+    if !check.broken.is_empty() {
+        for problem in &check.broken {
+            error!(ctx, "{problem}");
+        }
+        anyhow::bail!(
+            "{} failed its self-check, move it away to download the chain from peers again",
+            blockchain_path.display()
+        );
+    }
+    let progress = |done, total| info!(ctx, "rebuilding utxos: {done} of {total} blocks");
+    if !check.stale.is_empty() {
+        for problem in &check.stale {
+            warn!(ctx, "{problem}");
+        }
+        warn!(
+            ctx,
+            "the saved utxos or target don't match the blocks, working them out again"
+        );
+        new_blockchain.rebuild_utxos_with_progress(progress);
+        new_blockchain.retarget();
+    }
+    info!(ctx, "checked the last {} blocks", check.blocks);
+
+    let mut blockchain = ctx.blockchain.write().await;
     *blockchain = new_blockchain;
-    println!("rebuilding utxos...");
-    blockchain.rebuild_utxos();
-    println!("utxos rebuilt");
-    println!("checking if target needs to be adjusted...");
-    println!("current target {}", blockchain.target());
-    blockchain.try_adjust_target();
-    println!("new target: {}", blockchain.target());
-    println!("initialization complete");
+    if blockchain.restore_utxos(progress) {
+        info!(ctx, "utxos rebuilt, the saved ones didn't add up");
+    } else {
+        info!(ctx, "utxos loaded");
+    }
+    info!(ctx, "target: {}", blockchain.target());
+    info!(ctx, "initialization complete");
     Ok(())
 }
 
-pub async fn populate_connections(nodes: &[String]) -> Result<()> {
-    println!("trying to connect to other nodes...");
+pub async fn populate_connections(ctx: &NodeContext, nodes: &[String]) -> Result<()> {
+    info!(ctx, "trying to connect to other nodes...");
     for node in nodes {
-        println!("connecting to {}", node);
+        debug!(ctx, "connecting to {}", node);
         let mut stream = TcpStream::connect(&node).await?;
+        let capabilities = handshake(ctx, node, &mut stream).await?;
         let message = Message::DiscoverNodes;
         message.send_async(&mut stream).await?;
-        println!("sent message to discover nodes to: {}", node);
+        debug!(ctx, "sent message to discover nodes to: {}", node);
         let message = Message::receive_async(&mut stream).await?;
         match message {
             Message::NodeList(child_nodes) => {
-                println!("received nodek list from: {}", node);
+                debug!(ctx, "received nodek list from: {}", node);
                 for child_node in child_nodes {
-                    println!("adding node {}", child_node);
-                    let new_stream = TcpStream::connect(&child_node).await?;
-                    crate::NODES.insert(child_node, new_stream);
+                    debug!(ctx, "adding node {}", child_node);
+                    let mut new_stream = TcpStream::connect(&child_node).await?;
+                    let capabilities = handshake(ctx, &child_node, &mut new_stream).await?;
+                    ctx.peer_capabilities
+                        .insert(child_node.clone(), capabilities);
+                    ctx.nodes.insert(child_node, new_stream);
                 }
             }
             _ => {
-                println!("unexpected message from: {}", node);
+                warn!(ctx, "unexpected message from: {}", node);
             }
         }
-        crate::NODES.insert(node.clone(), stream);
+        ctx.peer_capabilities.insert(node.clone(), capabilities);
+        ctx.nodes.insert(node.clone(), stream);
     }
     Ok(())
 }
 
-pub async fn find_longest_chain_node() -> Result<(String, u32)> {
-    println!("finding longest chain");
+/// The identity key kept in `path`, made and saved there if there's none yet, and whether it was
+/// just made. Without a path the node gets a new one
+pub fn load_identity(path: Option<&Path>) -> Result<(PrivateKey, bool)> {
+    let Some(path) = path else {
+        return Ok((PrivateKey::new_key(), false));
+    };
+    if path.exists() {
+        let key = PrivateKey::load_from_file(path)
+            .with_context(|| format!("failed to load the node identity: {}", path.display()))?;
+        return Ok((key, false));
+    }
+    let key = PrivateKey::new_key();
+    key.save_to_file(path)
+        .with_context(|| format!("failed to save the node identity: {}", path.display()))?;
+    Ok((key, true))
+}
+
+/// Say hello to a node just connected to, so both learn the other's clock and capabilities, and
+/// check it can sign for the identity it claims. How long it takes to answer is noted as its
+/// latency. The connection stays CBOR
+async fn handshake(ctx: &NodeContext, node: &str, stream: &mut TcpStream) -> Result<Capabilities> {
+    let nonce = new_nonce();
+    let sent = Instant::now();
+    let hello = Message::Hello {
+        version: PROTOCOL_VERSION,
+        encodings: vec![Encoding::Cbor],
+        time: Some(Utc::now()),
+        nonce: Some(nonce),
+        capabilities: ctx.capabilities(),
+    };
+    hello.send_async(stream).await?;
+    let reply = Message::receive_async(stream).await?;
+    ctx.peer_latency.insert(node.to_string(), sent.elapsed());
+    match reply {
+        Message::Welcome {
+            time,
+            identity,
+            capabilities,
+            ..
+        } => {
+            check_identity(identity.as_ref(), &nonce, &[])
+                .with_context(|| format!("handshake with {node}"))?;
+            if let Some(identity) = identity {
+                debug!(ctx, "{node} is {}", identity.fingerprint());
+            }
+            if let Some(time) = time {
+                ctx.clock_sample(node, time).await;
+            }
+            debug!(ctx, "{node} supports {capabilities}");
+            Ok(capabilities)
+        }
+        _ => {
+            warn!(ctx, "unexpected message from: {node}");
+            Ok(Capabilities::NONE)
+        }
+    }
+}
+
+/// The node with the most blocks past `height`, and how many more it has
+pub async fn find_longest_chain_node(ctx: &NodeContext, height: u64) -> Result<(String, u32)> {
+    info!(ctx, "finding longest chain");
 
     let mut longest_name = String::new();
     let mut longest_count = 0;
-    let all_nodes = crate::NODES
+    let all_nodes = ctx
+        .nodes
         .iter()
         .map(|x| x.key().clone())
         .collect::<Vec<_>>();
     for node in all_nodes {
-        println!("asking blockchain length to node: {}", node);
-        let mut stream = crate::NODES.get_mut(&node).context("no node somehow")?;
-        let message = Message::AskDifference(0);
+        debug!(ctx, "asking blockchain length to node: {}", node);
+        let mut stream = ctx.nodes.get_mut(&node).context("no node somehow")?;
+        let message = Message::AskDifference(i32::try_from(height)?);
+        let sent = Instant::now();
         message.send_async(&mut *stream).await.unwrap();
-        println!("sent askDifference to {}", node);
+        debug!(ctx, "sent askDifference to {}", node);
         let message = Message::receive_async(&mut *stream).await?;
+        ctx.peer_latency.insert(node.clone(), sent.elapsed());
         match message {
             Message::Difference(count) => {
-                println!("received difference from {}", node);
+                debug!(ctx, "received difference from {}", node);
                 if count > longest_count {
-                    println!(
+                    info!(
+                        ctx,
                         "new longest blockchain: \
  {} blocks from {node}",
                         count
@@ -76,48 +190,162 @@ pub async fn find_longest_chain_node() -> Result<(String, u32)> {
                 }
             }
             _ => {
-                println!("unexpected message from node: {node}");
+                warn!(ctx, "unexpected message from node: {node}");
             }
         }
     }
     Ok((longest_name, longest_count as u32))
 }
 
-pub async fn download_blockchain(node: &str, count: u32) -> Result<()> {
-    let mut stream = crate::NODES.get_mut(node).unwrap();
-    for i in 0..count as usize {
+/// fetch `count` blocks from `node`, starting at `height`
+pub async fn download_blockchain(
+    ctx: &NodeContext,
+    node: &str,
+    height: u64,
+    count: u32,
+) -> Result<()> {
+    let mut stream = ctx.nodes.get_mut(node).unwrap();
+    let start = height as usize;
+    for i in start..start + count as usize {
         let message = Message::FetchBlock(i);
         message.send_async(&mut *stream).await?;
         let message = Message::receive_async(&mut *stream).await?;
         match message {
             Message::NewBlock(block) => {
-                let mut blockchain = crate::BLOCKCHAIN.write().await;
+                let mut blockchain = ctx.blockchain.write().await;
                 blockchain.add_block(block)?;
             }
             _ => {
-                println!("unexpected message from node: {node}");
+                warn!(ctx, "unexpected message from node: {node}");
             }
         }
     }
     Ok(())
 }
 
-pub async fn cleanup() {
-    let mut interval = time::interval(time::Duration::from_secs(30));
-    loop {
-        interval.tick().await;
-        println!("cleaning the mempool from old transactions");
-        let mut blockchain = crate::BLOCKCHAIN.write().await;
-        blockchain.cleanup_mempool();
+/// Connect to `peers` and fetch the blocks they have past the chain loaded from the store, e.g.
+/// one a snapshot started it from
+pub async fn catch_up(ctx: &NodeContext, peers: &[String]) -> Result<()> {
+    populate_connections(ctx, peers).await?;
+    let height = ctx.blockchain.read().await.block_height();
+    let (node, count) = find_longest_chain_node(ctx, height).await?;
+    if count == 0 {
+        return Ok(());
     }
+    ctx.syncing.store(true, Ordering::Relaxed);
+    let downloaded = download_blockchain(ctx, &node, height, count).await;
+    ctx.syncing.store(false, Ordering::Relaxed);
+    downloaded?;
+    ctx.blockchain.write().await.retarget();
+    info!(ctx, "caught up {count} blocks from {node}");
+    Ok(())
 }
 
-pub async fn save(name: String) {
-    let mut interval = time::interval(time::Duration::from_secs(15));
+/// Validate `block` under a read lock so wallets keep being answered meanwhile, the write lock is
+/// only taken to append it
+pub async fn add_block(ctx: &NodeContext, block: Block) -> btclib::error::Result<()> {
+    let validated = ctx.blockchain.read().await.validate_block(block)?;
+    ctx.blockchain.write().await.add_validated_block(validated)
+}
+
+/// Evict the transactions that waited too long in the mempool
+pub async fn cleanup(ctx: Arc<NodeContext>) -> Result<()> {
+    debug!(ctx, "cleaning the mempool from old transactions");
+    let evicted = ctx.blockchain.write().await.cleanup_mempool();
+    if evicted > 0 {
+        info!(ctx, "evicted {evicted} transactions");
+    }
+    Ok(())
+}
+
+/// Forget expired bans and faded misbehavior, and stop talking to known nodes banned since
+pub async fn maintain_peers(ctx: Arc<NodeContext>) -> Result<()> {
+    let expired = ctx.bans.prune();
+    if expired > 0 {
+        ctx.save_bans();
+        info!(ctx, "{expired} bans expired");
+    }
+    ctx.nodes.retain(|node, _| {
+        let banned = crate::peer_ip(node).is_some_and(|ip| ctx.bans.is_banned(&ip));
+        if banned {
+            info!(ctx, "dropping banned node {node}");
+            ctx.bandwidth.disconnect(node);
+        }
+        !banned
+    });
+    ctx.peer_capabilities
+        .retain(|node, _| ctx.nodes.contains_key(node));
+    ctx.peer_latency
+        .retain(|node, _| ctx.nodes.contains_key(node));
+    Ok(())
+}
+
+/// Tell light wallets and subscribers about transactions leaving the mempool unmined
+pub async fn relay_evictions(ctx: Arc<NodeContext>, mut evictions: broadcast::Receiver<Eviction>) {
     loop {
-        interval.tick().await;
-        println!("saving blockchain to drive...");
-        let blockchain = crate::BLOCKCHAIN.read().await;
-        blockchain.save_to_file(name.clone()).unwrap();
+        match evictions.recv().await {
+            Ok(eviction) => crate::handler::relay_eviction(&ctx, &eviction),
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!(ctx, "missed {missed} mempool evictions");
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+pub async fn save_blockchain(ctx: &NodeContext, path: &Path) -> Result<()> {
+    info!(ctx, "saving blockchain to drive...");
+    // writing takes a while, a copy keeps the lock free for everyone else meanwhile
+    let (blockchain, logged) = {
+        let blockchain = ctx.blockchain.read().await;
+        let logged = blockchain.wal().map(Wal::len).transpose()?;
+        (blockchain.clone(), logged)
+    };
+    // a crash while writing leaves the old file, not half of the new one
+    let temp = with_suffix(path, ".tmp");
+    let file = File::create(&temp)?;
+    blockchain.save_checked(&file)?;
+    file.sync_all()?;
+    fs::rename(&temp, path)?;
+    if let (Some(wal), Some(logged)) = (blockchain.wal(), logged) {
+        wal.checkpoint(logged)?;
+    }
+    Ok(())
+}
+
+/// Open the write-ahead log next to the store and catch the chain up with it, then log to it
+/// from now on. A chain that needed catching up is saved right away
+pub async fn recover_blockchain(ctx: &NodeContext, path: &Path) -> Result<()> {
+    let wal_path = with_suffix(path, ".wal");
+    let (wal, records) = Wal::open(&wal_path)
+        .with_context(|| format!("failed to open the log {}", wal_path.display()))?;
+    let recovery = {
+        let mut blockchain = ctx.blockchain.write().await;
+        let recovery = blockchain.recover(records)?;
+        blockchain.set_wal(Some(wal));
+        recovery
+    };
+    if let Some(hash) = recovery.rolled_back {
+        warn!(
+            ctx,
+            "the node stopped in the middle of changing the chain at {hash}, rolled it back"
+        );
     }
+    if recovery.replayed > 0 {
+        info!(
+            ctx,
+            "replayed {} changes to the chain from the log", recovery.replayed
+        );
+    }
+    if recovery != Recovery::default() {
+        save_blockchain(ctx, path).await?;
+    }
+    Ok(())
+}
+
+/// `path` with `suffix` after its extension
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
 }
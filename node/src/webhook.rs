@@ -0,0 +1,115 @@
+//! POSTs the double spends the node notices to an HTTP endpoint, e.g. a shop's backend taking
+//! unconfirmed payments. Each one is a JSON object like
+//!
+//! ```json
+//! {"event":"double_spend","txid":"…","conflicting":"…","outputs":["…"],"outcome":"Replaced"}
+//! ```
+//!
+//! Plain http:// only, put a proxy in front for anything else.
+
+use anyhow::{Context, Result, anyhow, bail};
+use btclib::types::DoubleSpend;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::broadcast,
+};
+
+use crate::{NodeContext, NodeEvent};
+
+#[derive(Clone, Debug)]
+pub(crate) struct Webhook {
+    /// host and port as given, for the Host header
+    authority: String,
+    /// what to connect to
+    addr: String,
+    path: String,
+}
+
+impl Webhook {
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| anyhow!("webhook {url} is not an http:// url"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() {
+            bail!("webhook {url} has no host");
+        }
+        let addr = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok(Webhook {
+            authority: authority.to_string(),
+            addr,
+            path: path.to_string(),
+        })
+    }
+
+    async fn post(&self, body: &[u8]) -> Result<()> {
+        let mut stream = TcpStream::connect(&self.addr).await?;
+        let head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.path,
+            self.authority,
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body).await?;
+
+        let mut status = String::new();
+        BufReader::new(stream).read_line(&mut status).await?;
+        let code = status
+            .split_whitespace()
+            .nth(1)
+            .context("no status line in the response")?;
+        if !code.starts_with('2') {
+            bail!("endpoint answered {}", status.trim_end());
+        }
+        Ok(())
+    }
+}
+
+fn payload(double_spend: &DoubleSpend) -> serde_json::Value {
+    json!({
+        "event": "double_spend",
+        "txid": double_spend.txid.to_hex(),
+        "conflicting": double_spend.conflicting.to_hex(),
+        "outputs": double_spend.outputs.iter().map(|hash| hash.to_hex()).collect::<Vec<_>>(),
+        "outcome": double_spend.outcome,
+    })
+}
+
+/// Post every double spend in `events` to the webhook, one at a time. A failed post is only
+/// logged, the endpoint has to ask the node if it missed something
+pub(crate) async fn post_double_spends(
+    ctx: Arc<NodeContext>,
+    mut events: broadcast::Receiver<NodeEvent>,
+    webhook: Webhook,
+) {
+    loop {
+        match events.recv().await {
+            Ok(NodeEvent::DoubleSpend(double_spend)) => {
+                let body = payload(&double_spend).to_string();
+                if let Err(e) = webhook.post(body.as_bytes()).await {
+                    warn!(
+                        ctx,
+                        "failed to post double spend to {}: {e}", webhook.authority
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!(ctx, "webhook missed {missed} events");
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
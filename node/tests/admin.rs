@@ -0,0 +1,411 @@
+#![cfg(unix)]
+
+use btclib::{
+    crypto::PrivateKey,
+    network::Message,
+    types::{Amount, BlockBuilder, TransactionBuilder},
+};
+use node::admin::{AdminCommand, AdminReply, cookie_path, request};
+use node::dashboard::Dashboard;
+use node::{Ban, DEFAULT_BAN_DURATION, LogLevel, Node, NodeHandle};
+
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::time::timeout;
+
+/// a directory of its own for a test's files
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("node-admin-{name}-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn spawn_with_admin(dir: &std::path::Path) -> (NodeHandle, PathBuf) {
+    let socket = dir.join("node.sock");
+    let node = Node::builder()
+        .port(0)
+        .regtest(true)
+        .store(dir.join("blockchain.cbor"))
+        .banlist(dir.join("banlist.json"))
+        .admin_socket(&socket)
+        .spawn()
+        .await
+        .unwrap();
+    (node, socket)
+}
+
+async fn command(socket: &std::path::Path, command: &str) -> AdminReply {
+    request(socket, &command.parse().unwrap()).await.unwrap()
+}
+
+#[test]
+fn commands_parse() {
+    for command in [
+        "ban 10.0.0.1",
+        "unban ::1",
+        "listbans",
+        "clearbans",
+        "setloglevel debug",
+        "save",
+        "dumpmempool",
+        "peers",
+        "logs 5",
+        "shutdown",
+    ] {
+        let parsed: AdminCommand = command.parse().unwrap();
+        assert_eq!(parsed.to_string(), command);
+    }
+    assert_eq!(
+        "BAN 10.0.0.1:9000".parse(),
+        Ok(AdminCommand::Ban("10.0.0.1".parse().unwrap()))
+    );
+    assert_eq!(
+        "setloglevel Warn".parse(),
+        Ok(AdminCommand::SetLogLevel(LogLevel::Warn))
+    );
+    assert!(matches!("logs".parse(), Ok(AdminCommand::Logs(_))));
+    for wrong in [
+        "ban",
+        "ban somewhere",
+        "save now",
+        "logs many",
+        "peers all",
+        "invalidateblock 12",
+        "reboot",
+        "",
+    ] {
+        assert!(wrong.parse::<AdminCommand>().is_err(), "{wrong}");
+    }
+}
+
+#[tokio::test]
+async fn only_the_cookie_gets_in() {
+    let dir = scratch_dir("cookie");
+    let (_node, socket) = spawn_with_admin(&dir).await;
+    let cookie = std::fs::read_to_string(cookie_path(&socket)).unwrap();
+    assert_eq!(cookie.len(), 32);
+
+    let mut stream = UnixStream::connect(&socket).await.unwrap();
+    stream.write_all(b"not the cookie\nsave\n").await.unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    let reply: AdminReply =
+        serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+    assert_eq!(reply, AdminReply::Error("wrong cookie".to_string()));
+    // and nothing else is run
+    assert_eq!(lines.next_line().await.unwrap(), None);
+
+    assert_eq!(command(&socket, "save").await, AdminReply::Done);
+    assert!(dir.join("blockchain.cbor").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn banning_peers() {
+    let dir = scratch_dir("ban");
+    let (node, socket) = spawn_with_admin(&dir).await;
+    let addr = format!("127.0.0.1:{}", node.local_addr().port());
+
+    let mut peer = TcpStream::connect(&addr).await.unwrap();
+    Message::AskDifference(0)
+        .send_async(&mut peer)
+        .await
+        .unwrap();
+    Message::receive_async(&mut peer).await.unwrap();
+
+    assert_eq!(command(&socket, "ban 127.0.0.1").await, AdminReply::Done);
+    let AdminReply::Bans(bans) = command(&socket, "listbans").await else {
+        panic!("expected the bans");
+    };
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+    assert!(bans[0].until > Utc::now() + DEFAULT_BAN_DURATION - chrono::Duration::minutes(1));
+    let closed = timeout(Duration::from_secs(5), Message::receive_async(&mut peer))
+        .await
+        .expect("the banned peer wasn't disconnected");
+    assert!(closed.is_err());
+    // and can't come back
+    let mut peer = TcpStream::connect(&addr).await.unwrap();
+    let _ = Message::AskDifference(0).send_async(&mut peer).await;
+    assert!(Message::receive_async(&mut peer).await.is_err());
+
+    assert_eq!(command(&socket, "unban 127.0.0.1").await, AdminReply::Done);
+    assert!(matches!(
+        command(&socket, "unban 127.0.0.1").await,
+        AdminReply::Error(_)
+    ));
+    let mut peer = TcpStream::connect(&addr).await.unwrap();
+    Message::AskDifference(0)
+        .send_async(&mut peer)
+        .await
+        .unwrap();
+    assert!(Message::receive_async(&mut peer).await.is_ok());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn bans_survive_restarts() {
+    let dir = scratch_dir("restart");
+    let (node, socket) = spawn_with_admin(&dir).await;
+    assert_eq!(command(&socket, "ban 10.0.0.1").await, AdminReply::Done);
+    drop(node);
+
+    let (_node, socket) = spawn_with_admin(&dir).await;
+    let AdminReply::Bans(bans) = command(&socket, "listbans").await else {
+        panic!("expected the bans");
+    };
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].ip, "10.0.0.1".parse::<IpAddr>().unwrap());
+    assert_eq!(bans[0].reason, "banned by the operator");
+
+    assert_eq!(command(&socket, "clearbans").await, AdminReply::Done);
+    assert_eq!(command(&socket, "listbans").await, AdminReply::Bans(vec![]));
+    let saved: Vec<Ban> =
+        serde_json::from_slice(&std::fs::read(dir.join("banlist.json")).unwrap()).unwrap();
+    assert!(saved.is_empty());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn misbehaving_peers_get_banned() {
+    let dir = scratch_dir("misbehaving");
+    let (node, socket) = spawn_with_admin(&dir).await;
+    let addr = format!("127.0.0.1:{}", node.local_addr().port());
+    // pays itself more than the reward
+    let invalid = {
+        let blockchain = node.blockchain().await;
+        let reward = blockchain.calculate_block_reward();
+        let mut block = BlockBuilder::on_top_of(&blockchain)
+            .coinbase_to(
+                PrivateKey::new_key().public_key(),
+                reward.checked_add(Amount::ONE_SAT).unwrap(),
+            )
+            .finalize()
+            .unwrap();
+        block
+            .header
+            .mine_with_target_override(btclib::REGTEST_TARGET);
+        block
+    };
+
+    let mut peer = TcpStream::connect(&addr).await.unwrap();
+    // one invalid block is forgiven
+    Message::NewBlock(invalid.clone())
+        .send_async(&mut peer)
+        .await
+        .unwrap();
+    Message::GetStatus.send_async(&mut peer).await.unwrap();
+    Message::receive_async(&mut peer).await.unwrap();
+    assert_eq!(command(&socket, "listbans").await, AdminReply::Bans(vec![]));
+
+    Message::NewBlock(invalid)
+        .send_async(&mut peer)
+        .await
+        .unwrap();
+    let closed = timeout(Duration::from_secs(5), Message::receive_async(&mut peer))
+        .await
+        .expect("the misbehaving peer wasn't disconnected");
+    assert!(closed.is_err());
+    let AdminReply::Bans(bans) = command(&socket, "listbans").await else {
+        panic!("expected the bans");
+    };
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].reason, "relayed an invalid block");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn invalidating_blocks() {
+    let dir = scratch_dir("invalidate");
+    let (node, socket) = spawn_with_admin(&dir).await;
+    let mut miner = node.connect("miner");
+    Message::GenerateBlocks(PrivateKey::new_key().public_key(), 3)
+        .send_async(&mut miner)
+        .await
+        .unwrap();
+    Message::receive_async(&mut miner).await.unwrap();
+    let (second, tip) = {
+        let blockchain = node.blockchain().await;
+        let hashes: Vec<_> = blockchain.blocks().map(|block| block.hash()).collect();
+        (hashes[1], hashes[2])
+    };
+
+    let invalidate = format!("invalidateblock {}", second.to_hex());
+    assert_eq!(command(&socket, &invalidate).await, AdminReply::Done);
+    assert_eq!(node.blockchain().await.block_height(), 1);
+    let reconsider = format!("reconsiderblock {}", second.to_hex());
+    assert_eq!(command(&socket, &reconsider).await, AdminReply::Done);
+    assert_eq!(node.blockchain().await.tip_hash(), tip);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn inspecting_and_stopping_the_node() {
+    let dir = scratch_dir("stop");
+    let (node, socket) = spawn_with_admin(&dir).await;
+    let key = PrivateKey::new_key();
+    let mut miner = node.connect("miner");
+    Message::GenerateBlocks(key.public_key(), 1)
+        .send_async(&mut miner)
+        .await
+        .unwrap();
+    Message::receive_async(&mut miner).await.unwrap();
+    let coinbase = node
+        .blockchain()
+        .await
+        .blocks()
+        .next()
+        .unwrap()
+        .transactions[0]
+        .outputs[0]
+        .clone();
+    let transaction = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .pay_to(key.public_key(), Amount::ONE_BTC)
+        .change_to(key.public_key(), Amount::from_sat(500))
+        .finalize()
+        .unwrap();
+    node.submit_transaction(transaction.clone()).await.unwrap();
+
+    let AdminReply::Mempool(entries) = command(&socket, "dumpmempool").await else {
+        panic!("expected the mempool");
+    };
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].txid, transaction.hash().to_hex());
+    assert_eq!(entries[0].fee, 500);
+    assert_eq!(entries[0].size, transaction.size());
+
+    assert!(matches!(
+        command(
+            &socket,
+            &format!("invalidateblock {}", transaction.hash().to_hex())
+        )
+        .await,
+        AdminReply::Error(_)
+    ));
+
+    assert_eq!(command(&socket, "shutdown").await, AdminReply::Done);
+    timeout(Duration::from_secs(5), node.wait())
+        .await
+        .expect("the node didn't stop")
+        .unwrap();
+    assert!(dir.join("blockchain.cbor").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn each_node_has_its_own_log_level() {
+    let dir = scratch_dir("loglevel");
+    let (node, socket) = spawn_with_admin(&dir).await;
+    let other = Node::builder()
+        .port(0)
+        .regtest(true)
+        .log_level(LogLevel::Error)
+        .spawn()
+        .await
+        .unwrap();
+    assert_eq!(node.log_level(), LogLevel::Info);
+    assert_eq!(other.log_level(), LogLevel::Error);
+
+    assert_eq!(
+        command(&socket, "setloglevel debug").await,
+        AdminReply::Done
+    );
+    assert_eq!(node.log_level(), LogLevel::Debug);
+    assert_eq!(other.log_level(), LogLevel::Error);
+    other.set_log_level(LogLevel::Warn);
+    assert_eq!(node.log_level(), LogLevel::Debug);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn each_node_keeps_its_own_log() {
+    let dir = scratch_dir("logs");
+    let (node, socket) = spawn_with_admin(&dir).await;
+    let identity = dir.join("identity.pem");
+    let other = Node::builder()
+        .port(0)
+        .regtest(true)
+        .identity(&identity)
+        .spawn()
+        .await
+        .unwrap();
+    let made = format!("made a new node identity in {}", identity.display());
+    assert!(
+        other
+            .recent_logs(10)
+            .iter()
+            .any(|line| line.ends_with(&made))
+    );
+
+    let AdminReply::Logs(lines) = command(&socket, "logs 10").await else {
+        panic!("expected the log");
+    };
+    assert!(!lines.is_empty());
+    assert!(!lines.iter().any(|line| line.ends_with(&made)));
+    assert_eq!(lines, node.recent_logs(10));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn peers_logs_and_the_dashboard() {
+    let dir = scratch_dir("dashboard");
+    let peer = Node::builder().port(0).regtest(true).spawn().await.unwrap();
+    let mut miner = peer.connect("miner");
+    Message::GenerateBlocks(PrivateKey::new_key().public_key(), 1)
+        .send_async(&mut miner)
+        .await
+        .unwrap();
+    Message::receive_async(&mut miner).await.unwrap();
+    let peer_addr = format!("127.0.0.1:{}", peer.local_addr().port());
+    let socket = dir.join("node.sock");
+    let node = Node::builder()
+        .port(0)
+        .regtest(true)
+        .admin_socket(&socket)
+        .peers([peer_addr.clone()])
+        .spawn()
+        .await
+        .unwrap();
+
+    let AdminReply::Peers(peers) = command(&socket, "peers").await else {
+        panic!("expected the peers");
+    };
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].addr, peer_addr);
+    assert!(peers[0].latency_ms.is_some());
+
+    let AdminReply::Logs(lines) = command(&socket, "logs 3").await else {
+        panic!("expected the log");
+    };
+    assert!(!lines.is_empty() && lines.len() <= 3);
+
+    let mut miner = node.connect("miner");
+    Message::GenerateBlocks(PrivateKey::new_key().public_key(), 2)
+        .send_async(&mut miner)
+        .await
+        .unwrap();
+    Message::receive_async(&mut miner).await.unwrap();
+    let addr = format!("127.0.0.1:{}", node.local_addr().port());
+    let dashboard = Dashboard::fetch(&addr, Some(&socket)).await.unwrap();
+    assert_eq!(dashboard.status.height, 3);
+    assert_eq!(dashboard.blocks.len(), 3);
+    assert_eq!(dashboard.blocks[0].hash(), dashboard.status.best_hash);
+    assert_eq!(dashboard.peers.as_deref(), Some(&peers[..]));
+    assert_eq!(
+        dashboard
+            .fee_histogram()
+            .iter()
+            .map(|(_, count)| count)
+            .sum::<usize>(),
+        0
+    );
+    let screen = dashboard.to_string();
+    assert!(screen.contains(&peer_addr));
+    assert!(screen.contains(&dashboard.status.best_hash.to_string()));
+    std::fs::remove_dir_all(dir).unwrap();
+}
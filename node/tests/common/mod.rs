@@ -0,0 +1,30 @@
+//! Helpers the node's tests share, each test file pulls them in with `mod common;`
+#![allow(dead_code)]
+
+use btclib::chaingen::ChainGen;
+use btclib::crypto::PrivateKey;
+use btclib::types::{Amount, Blockchain, Transaction, TransactionBuilder, TransactionOutput};
+
+/// a regtest mined chain of `height` blocks a second apart, coinbases only
+pub fn mined_chain(height: u64) -> Blockchain {
+    ChainGen {
+        blocks: height,
+        transactions_per_block: 0,
+        keys: 1,
+        block_time: Some(1),
+        ..ChainGen::default()
+    }
+    .generate()
+    .unwrap()
+    .blockchain
+}
+
+/// pay `to` a coin out of `output`, the change goes back to `key` less a sat in fees
+pub fn spend(output: &TransactionOutput, key: &PrivateKey, to: &PrivateKey) -> Transaction {
+    TransactionBuilder::new()
+        .spend(output, key)
+        .pay_to(to.public_key(), Amount::ONE_BTC)
+        .change_to(key.public_key(), Amount::ONE_SAT)
+        .finalize()
+        .unwrap()
+}
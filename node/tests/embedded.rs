@@ -0,0 +1,192 @@
+use btclib::{
+    crypto::PrivateKey,
+    network::Message,
+    types::{Amount, BlockBuilder, TransactionBuilder},
+};
+use node::{Node, NodeEvent};
+
+use chrono::{Duration, Utc};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn embedded_node() {
+    let node = Node::builder().port(0).regtest(true).spawn().await.unwrap();
+    let mut events = node.subscribe();
+    let key = PrivateKey::new_key();
+
+    let reward = node.blockchain().await.calculate_block_reward();
+    let mut genesis = BlockBuilder::new()
+        .timestamp(Utc::now() - Duration::minutes(1))
+        .coinbase_to(key.public_key(), reward)
+        .finalize()
+        .unwrap();
+    genesis
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    node.submit_block(genesis.clone()).await.unwrap();
+    match events.recv().await.unwrap() {
+        NodeEvent::Block(block) => assert_eq!(block.hash(), genesis.hash()),
+        event => panic!("unexpected event {event:?}"),
+    }
+    assert!(node.submit_block(genesis.clone()).await.is_err());
+
+    let transaction = TransactionBuilder::new()
+        .spend(&genesis.transactions[0].outputs[0], &key)
+        .change_to(key.public_key(), Amount::ONE_SAT)
+        .finalize()
+        .unwrap();
+    node.submit_transaction(transaction.clone()).await.unwrap();
+    match events.recv().await.unwrap() {
+        NodeEvent::Transaction(tx) => assert_eq!(tx.hash(), transaction.hash()),
+        event => panic!("unexpected event {event:?}"),
+    }
+
+    // peers see the same chain
+    let mut stream = TcpStream::connect(("127.0.0.1", node.local_addr().port()))
+        .await
+        .unwrap();
+    Message::GenerateBlocks(key.public_key(), 2)
+        .send_async(&mut stream)
+        .await
+        .unwrap();
+    let Message::GeneratedBlocks(hashes) = Message::receive_async(&mut stream).await.unwrap()
+    else {
+        panic!("expected the generated blocks");
+    };
+    assert_eq!(hashes.len(), 2);
+    for hash in &hashes {
+        match events.recv().await.unwrap() {
+            NodeEvent::Block(block) => assert_eq!(block.hash(), *hash),
+            event => panic!("unexpected event {event:?}"),
+        }
+    }
+
+    let blockchain = node.blockchain().await;
+    assert_eq!(blockchain.block_height(), 3);
+    assert!(blockchain.mempool().is_empty());
+    assert_eq!(blockchain.blocks().nth(1).unwrap().transactions.len(), 2);
+}
+
+#[tokio::test]
+async fn nodes_in_one_process_keep_their_own_state() {
+    let seed = Node::builder().port(0).regtest(true).spawn().await.unwrap();
+    let key = PrivateKey::new_key();
+    let mut stream = TcpStream::connect(("127.0.0.1", seed.local_addr().port()))
+        .await
+        .unwrap();
+    Message::GenerateBlocks(key.public_key(), 3)
+        .send_async(&mut stream)
+        .await
+        .unwrap();
+    Message::receive_async(&mut stream).await.unwrap();
+
+    let peer = format!("127.0.0.1:{}", seed.local_addr().port());
+    let synced = Node::builder()
+        .port(0)
+        .regtest(true)
+        .peers([peer])
+        .spawn()
+        .await
+        .unwrap();
+    let fresh = Node::builder().port(0).spawn().await.unwrap();
+
+    assert_eq!(synced.blockchain().await.block_height(), 3);
+    assert_eq!(
+        synced.blockchain().await.blocks().last().unwrap().hash(),
+        seed.blockchain().await.blocks().last().unwrap().hash()
+    );
+    assert_eq!(fresh.blockchain().await.block_height(), 0);
+}
+
+#[tokio::test]
+async fn own_transactions_go_straight_to_peers() {
+    let seed = Node::builder().port(0).regtest(true).spawn().await.unwrap();
+    let key = PrivateKey::new_key();
+    let mut stream = TcpStream::connect(("127.0.0.1", seed.local_addr().port()))
+        .await
+        .unwrap();
+    Message::GenerateBlocks(key.public_key(), 1)
+        .send_async(&mut stream)
+        .await
+        .unwrap();
+    Message::receive_async(&mut stream).await.unwrap();
+
+    let peer = format!("127.0.0.1:{}", seed.local_addr().port());
+    let node = Node::builder()
+        .port(0)
+        .regtest(true)
+        .peers([peer])
+        .spawn()
+        .await
+        .unwrap();
+    let coinbase = node
+        .blockchain()
+        .await
+        .blocks()
+        .next()
+        .unwrap()
+        .transactions[0]
+        .outputs[0]
+        .clone();
+    let transaction = TransactionBuilder::new()
+        .spend(&coinbase, &key)
+        .change_to(key.public_key(), Amount::ONE_SAT)
+        .finalize()
+        .unwrap();
+    node.submit_transaction(transaction.clone()).await.unwrap();
+
+    let status = node.status().await;
+    assert_eq!(status.own_transactions.sent, 1);
+    assert_eq!(status.own_blocks.sent, 0);
+    assert!(status.to_string().contains("own txs:    1 sent to peers"));
+    for _ in 0..100 {
+        if seed
+            .blockchain()
+            .await
+            .mempool()
+            .contains(&transaction.hash())
+        {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("the peer never got the transaction");
+}
+
+#[tokio::test]
+async fn ephemeral_nodes_write_nothing() {
+    let dir = std::env::temp_dir().join(format!("node-ephemeral-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let node = Node::builder()
+        .port(0)
+        .store(dir.join("blockchain.cbor"))
+        .banlist(dir.join("banlist.json"))
+        .identity(dir.join("node_identity.cbor"))
+        .serve_snapshots(dir.join("snapshots"))
+        .admin_socket(dir.join("admin.sock"))
+        .ephemeral(true)
+        .spawn()
+        .await
+        .unwrap();
+    assert_eq!(node.blockchain().await.block_height(), 1);
+
+    // on regtest without asking
+    let key = PrivateKey::new_key();
+    let mut stream = TcpStream::connect(("127.0.0.1", node.local_addr().port()))
+        .await
+        .unwrap();
+    Message::GenerateBlocks(key.public_key(), 2)
+        .send_async(&mut stream)
+        .await
+        .unwrap();
+    let Message::GeneratedBlocks(hashes) = Message::receive_async(&mut stream).await.unwrap()
+    else {
+        panic!("expected the generated blocks");
+    };
+    assert_eq!(hashes.len(), 2);
+    assert_eq!(node.blockchain().await.block_height(), 3);
+
+    drop(node);
+    assert!(std::fs::read_dir(&dir).unwrap().next().is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
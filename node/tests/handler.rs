@@ -0,0 +1,1347 @@
+//! Scripted conversations with a node over in-memory connections, one request and the reply it
+//! gets at a time
+
+use btclib::{
+    U256,
+    crypto::PrivateKey,
+    finality::Finality,
+    network::{
+        BloomFilter, Capabilities, CodecError, Compression, Encoding, Envelope, ErrorCode,
+        KNOWN_KINDS, MAX_FILTER_ADD_SIZE, MIN_CLOCK_SAMPLES, MerkleBlock, Message,
+        PROTOCOL_VERSION, Rejection, check_identity, fingerprint, new_nonce,
+    },
+    sha256::Hash,
+    types::{
+        AddressScan, Amount, Block, BlockBuilder, DoubleSpend, DoubleSpendOutcome, EvictionReason,
+        MempoolPolicy, Payout, PayoutSplit, TransactionBuilder, TransactionOutput, UtxoDiff,
+    },
+};
+use node::{Node, NodeBuilder, NodeEvent, NodeHandle, RateLimits};
+
+use chrono::Utc;
+use common::spend;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::time::timeout;
+
+mod common;
+
+/// how long a reply may take before the test gives up on it
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// how long a request that gets no reply is given to take effect
+const SILENCE: Duration = Duration::from_millis(100);
+
+/// A peer talking to the node
+struct Peer {
+    stream: DuplexStream,
+    encoding: Encoding,
+}
+
+impl Peer {
+    fn connect(node: &NodeHandle, name: &str) -> Self {
+        Peer {
+            stream: node.connect(name),
+            encoding: Encoding::Cbor,
+        }
+    }
+
+    async fn send(&mut self, message: Message) {
+        let frame = message.to_frame_as(self.encoding).unwrap();
+        self.stream.write_all(&frame).await.unwrap();
+    }
+
+    /// write a frame holding `payload` as is, as a message of `kind`
+    async fn send_raw(&mut self, kind: u16, payload: &[u8]) {
+        let envelope = Envelope {
+            kind,
+            compression: None,
+            payload: payload.to_vec(),
+        }
+        .to_bytes();
+        self.stream.write_u64(envelope.len() as u64).await.unwrap();
+        self.stream.write_all(&envelope).await.unwrap();
+    }
+
+    /// the next frame as it came
+    async fn receive_envelope(&mut self) -> std::io::Result<Envelope> {
+        let mut frame = vec![0; self.stream.read_u64().await? as usize];
+        self.stream.read_exact(&mut frame).await?;
+        Envelope::parse(&frame).map_err(std::io::Error::other)
+    }
+
+    /// the payload of the next frame
+    async fn receive_raw(&mut self) -> std::io::Result<Vec<u8>> {
+        Ok(self.receive_envelope().await?.payload)
+    }
+
+    async fn read(&mut self) -> Result<Message, CodecError> {
+        self.receive_envelope().await?.open(self.encoding)
+    }
+
+    async fn receive(&mut self) -> Message {
+        timeout(REPLY_TIMEOUT, self.read())
+            .await
+            .expect("no reply from the node")
+            .expect("the node closed the connection")
+    }
+
+    async fn ask(&mut self, message: Message) -> Message {
+        self.send(message).await;
+        self.receive().await
+    }
+
+    /// whether the node hangs up after `message`
+    async fn hangs_up_after(&mut self, message: Message) -> bool {
+        self.send(message).await;
+        timeout(REPLY_TIMEOUT, self.read())
+            .await
+            .expect("the node neither replied nor hung up")
+            .is_err()
+    }
+
+    /// nothing arrives for a while
+    async fn is_quiet(&mut self) -> bool {
+        timeout(SILENCE, self.read()).await.is_err()
+    }
+}
+
+async fn spawn(builder: NodeBuilder) -> NodeHandle {
+    builder.port(0).spawn().await.unwrap()
+}
+
+/// a regtest node with `blocks` blocks paying `key`
+async fn funded(builder: NodeBuilder, key: &PrivateKey, blocks: u32) -> NodeHandle {
+    let node = spawn(builder.regtest(true)).await;
+    let reply = Peer::connect(&node, "miner")
+        .ask(Message::GenerateBlocks(key.public_key(), blocks))
+        .await;
+    assert!(matches!(reply, Message::GeneratedBlocks(hashes) if hashes.len() == blocks as usize));
+    node
+}
+
+async fn coinbase(node: &NodeHandle, height: usize) -> TransactionOutput {
+    node.blockchain()
+        .await
+        .blocks()
+        .nth(height)
+        .unwrap()
+        .transactions[0]
+        .outputs[0]
+        .clone()
+}
+
+fn error_code(message: &Message) -> Option<ErrorCode> {
+    match message {
+        Message::Error { code, .. } => Some(*code),
+        _ => None,
+    }
+}
+
+fn mined(mut block: Block) -> Block {
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    block
+}
+
+/// the template `miner` gets paying `key`
+async fn template(miner: &mut Peer, key: &PrivateKey) -> Block {
+    let Message::Template(template) = miner.ask(Message::FetchTemplate(key.public_key())).await
+    else {
+        panic!("expected a template");
+    };
+    template
+}
+
+#[tokio::test]
+async fn chain_queries() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 2).await;
+    let mut peer = Peer::connect(&node, "peer");
+
+    assert!(matches!(
+        peer.ask(Message::AskDifference(0)).await,
+        Message::Difference(2)
+    ));
+    assert!(matches!(
+        peer.ask(Message::AskDifference(1)).await,
+        Message::Difference(1)
+    ));
+    let tip = node.blockchain().await.blocks().last().unwrap().hash();
+    assert!(matches!(
+        peer.ask(Message::FetchBlock(1)).await,
+        Message::NewBlock(block) if block.hash() == tip
+    ));
+    assert!(matches!(
+        peer.ask(Message::DiscoverNodes).await,
+        Message::NodeList(nodes) if nodes.is_empty()
+    ));
+    assert!(matches!(
+        peer.ask(Message::FetchSupply).await,
+        Message::Supply { height: 2, .. }
+    ));
+    // there's nothing to send for blocks the node doesn't have
+    assert!(peer.hangs_up_after(Message::FetchBlock(2)).await);
+}
+
+#[tokio::test]
+async fn wallet_queries() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 2).await;
+    let mut wallet = Peer::connect(&node, "wallet");
+
+    let Message::UTXOs(utxos) = wallet.ask(Message::FetchUTXOs(key.public_key())).await else {
+        panic!("expected UTXOs");
+    };
+    assert_eq!(utxos.len(), 2);
+    assert!(utxos.iter().all(|(marked, _)| !marked));
+
+    let Message::UTXOsSince(diff) = wallet
+        .ask(Message::FetchUTXOsSince(key.public_key(), 1))
+        .await
+    else {
+        panic!("expected a diff");
+    };
+    assert_eq!((diff.height, diff.full, diff.added.len()), (2, false, 1));
+    // what a wallet checks later to notice a reorg below it
+    let Message::Headers(headers) = wallet.ask(Message::FetchHeaderRange(1, 1)).await else {
+        panic!("expected a header");
+    };
+    assert_eq!(headers[0].hash, diff.tip);
+    // too far ahead for a diff, so everything
+    let Message::UTXOsSince(diff) = wallet
+        .ask(Message::FetchUTXOsSince(key.public_key(), 5))
+        .await
+    else {
+        panic!("expected a full listing");
+    };
+    assert_eq!((diff.full, diff.added.len()), (true, 2));
+}
+
+#[tokio::test]
+async fn index_lookups() {
+    let key = PrivateKey::new_key();
+    let plain = funded(Node::builder(), &key, 1).await;
+    let mut peer = Peer::connect(&plain, "peer");
+    let coinbase_hash = plain
+        .blockchain()
+        .await
+        .blocks()
+        .next()
+        .unwrap()
+        .transactions[0]
+        .hash();
+    let reply = peer.ask(Message::FetchTransaction(coinbase_hash)).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::NotAllowed));
+    let reply = peer.ask(Message::FetchHistory(key.public_key())).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::NotAllowed));
+
+    let indexed = funded(Node::builder().txindex(true).addrindex(true), &key, 1).await;
+    let mut peer = Peer::connect(&indexed, "peer");
+    let coinbase_hash = indexed
+        .blockchain()
+        .await
+        .blocks()
+        .next()
+        .unwrap()
+        .transactions[0]
+        .hash();
+    assert!(matches!(
+        peer.ask(Message::FetchTransaction(coinbase_hash)).await,
+        Message::TransactionInfo { height: 0, transaction } if transaction.hash() == coinbase_hash
+    ));
+    let reply = peer.ask(Message::FetchTransaction(Hash::zero())).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::NotFound));
+    assert!(matches!(
+        peer.ask(Message::FetchHistory(key.public_key())).await,
+        Message::History(history) if history.len() == 1
+    ));
+}
+
+#[tokio::test]
+async fn address_scans() {
+    let key = PrivateKey::new_key();
+    // no addrindex, the node goes through the blocks
+    let node = funded(Node::builder(), &key, 3).await;
+    let mut peer = Peer::connect(&node, "wallet");
+    let Message::AddressScanned(scan) = peer.ask(Message::ScanAddress(key.public_key(), 1)).await
+    else {
+        panic!("expected a scan");
+    };
+    assert_eq!((scan.height, scan.utxos.len()), (3, 3));
+    assert_eq!(scan.tip, node.blockchain().await.tip_hash());
+    let heights: Vec<u64> = scan
+        .history
+        .iter()
+        .map(|activity| activity.height)
+        .collect();
+    assert_eq!(heights, [1, 2]);
+
+    let reply = peer.ask(Message::ScanAddress(key.public_key(), 4)).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::NotFound));
+}
+
+#[tokio::test]
+async fn mining_a_template() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut miner = Peer::connect(&node, "miner");
+
+    let Message::Template(template) = miner.ask(Message::FetchTemplate(key.public_key())).await
+    else {
+        panic!("expected a template");
+    };
+    assert!(matches!(
+        miner.ask(Message::ValidateTemplate(template.clone())).await,
+        Message::TemplateValidity(true)
+    ));
+
+    let block = mined(template.clone());
+    assert!(matches!(
+        miner.ask(Message::SubmitTemplate(block.clone())).await,
+        Message::Ack(hash) if hash == block.hash()
+    ));
+    assert_eq!(node.blockchain().await.block_height(), 2);
+
+    // the template doesn't build on the tip anymore
+    assert!(matches!(
+        miner.ask(Message::ValidateTemplate(template)).await,
+        Message::TemplateValidity(false)
+    ));
+    let reply = miner.ask(Message::SubmitTemplate(block)).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::StaleBlock));
+}
+
+#[tokio::test]
+async fn templates_are_cached() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut miner = Peer::connect(&node, "miner");
+
+    // the same block, timestamp and all, until something changes
+    let first = template(&mut miner, &key).await;
+    assert_eq!(template(&mut miner, &key).await.hash(), first.hash());
+    let theirs = template(&mut miner, &other).await;
+    assert_ne!(theirs.hash(), first.hash());
+    assert_eq!(template(&mut miner, &other).await.hash(), theirs.hash());
+
+    let payment = spend(&coinbase(&node, 0).await, &key, &other);
+    node.submit_transaction(payment.clone()).await.unwrap();
+    let second = template(&mut miner, &key).await;
+    assert_eq!(second.transactions.len(), 2);
+    assert_eq!(second.transactions[1].hash(), payment.hash());
+
+    let block = mined(second);
+    node.submit_block(block.clone()).await.unwrap();
+    let third = template(&mut miner, &key).await;
+    assert_eq!(third.header.prev_block_hash, block.hash());
+    assert_eq!(third.transactions.len(), 1);
+}
+
+#[tokio::test]
+async fn mining_shares() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut miner = Peer::connect(&node, "miner");
+    let share_target = U256::MAX >> 4;
+
+    let reply = miner
+        .ask(Message::FetchShareTemplate(key.public_key(), share_target))
+        .await;
+    let Message::Template(mut share) = reply else {
+        panic!("expected a template");
+    };
+    assert!(share.header.mine_to(share_target, usize::MAX));
+    assert!(!share.hash().matches_target(share.header.target));
+    assert!(matches!(
+        miner.ask(Message::SubmitTemplate(share.clone())).await,
+        Message::ShareAccepted(hash) if hash == share.hash()
+    ));
+    assert_eq!(node.blockchain().await.block_height(), 1);
+
+    // only on the connection that asked for it
+    let reply = Peer::connect(&node, "other miner")
+        .ask(Message::SubmitTemplate(share.clone()))
+        .await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::Invalid));
+
+    // a block is still a block
+    let block = mined(share);
+    assert!(matches!(
+        miner.ask(Message::SubmitTemplate(block.clone())).await,
+        Message::Ack(hash) if hash == block.hash()
+    ));
+    assert_eq!(node.blockchain().await.block_height(), 2);
+}
+
+#[tokio::test]
+async fn mining_for_a_team() {
+    let (key, other) = (PrivateKey::new_key(), PrivateKey::new_key());
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut miner = Peer::connect(&node, "miner");
+    let payout = Payout::new(vec![
+        PayoutSplit {
+            pubkey: key.public_key(),
+            percent: 70,
+        },
+        PayoutSplit {
+            pubkey: other.public_key(),
+            percent: 30,
+        },
+    ])
+    .unwrap();
+
+    let reply = miner
+        .ask(Message::FetchPayoutTemplate(payout.clone(), None))
+        .await;
+    let Message::Template(template) = reply else {
+        panic!("expected a template");
+    };
+    let outputs = &template.transactions[0].outputs;
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs[1].pubkey, other.public_key());
+    let reward = node.blockchain().await.calculate_block_reward().to_sat();
+    assert_eq!(outputs[1].value.to_sat(), reward * 3 / 10);
+
+    let block = mined(template);
+    assert!(matches!(
+        miner.ask(Message::SubmitTemplate(block.clone())).await,
+        Message::Ack(hash) if hash == block.hash()
+    ));
+    let blockchain = node.blockchain().await;
+    assert_eq!(blockchain.block_height(), 2);
+    let balance = blockchain.balance_at(&other.public_key(), 2).unwrap();
+    assert_eq!(balance.to_sat(), reward * 3 / 10);
+}
+
+#[tokio::test]
+async fn generating_blocks_needs_regtest() {
+    let node = spawn(Node::builder()).await;
+    let key = PrivateKey::new_key();
+    let reply = Peer::connect(&node, "miner")
+        .ask(Message::GenerateBlocks(key.public_key(), 1))
+        .await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::NotAllowed));
+    assert_eq!(node.blockchain().await.block_height(), 0);
+}
+
+#[tokio::test]
+async fn submitting_transactions() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 2).await;
+    let mut wallet = Peer::connect(&node, "wallet");
+
+    let output = coinbase(&node, 0).await;
+    let transaction = spend(&output, &key, &other);
+    assert!(matches!(
+        wallet.ask(Message::SubmitTransaction(transaction.clone())).await,
+        Message::Ack(hash) if hash == transaction.hash()
+    ));
+    let output = coinbase(&node, 1).await;
+    let overspending = TransactionBuilder::new()
+        .spend(&output, &key)
+        .pay_to(
+            other.public_key(),
+            Amount::from_sat(output.value.to_sat() + 1),
+        )
+        .finalize()
+        .unwrap();
+    let reply = wallet.ask(Message::SubmitTransaction(overspending)).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::InsufficientFunds));
+    let reply = wallet
+        .ask(Message::SubmitTransaction(spend(
+            &TransactionBuilder::new()
+                .pay_to(
+                    key.public_key(),
+                    Amount::from_sat(2 * Amount::ONE_BTC.to_sat()),
+                )
+                .finalize()
+                .unwrap()
+                .outputs[0],
+            &key,
+            &other,
+        )))
+        .await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::MissingInputs));
+    assert!(
+        node.blockchain()
+            .await
+            .mempool()
+            .contains(&transaction.hash())
+    );
+}
+
+#[tokio::test]
+async fn mempool_policy() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let policy = MempoolPolicy {
+        min_fee_rate: 2,
+        allow_zero_fee: false,
+        max_transaction_age: 60,
+        ..MempoolPolicy::default()
+    };
+    let node = funded(Node::builder().mempool_policy(policy.clone()), &key, 1).await;
+    assert_eq!(node.status().await.policy, policy);
+    let mut wallet = Peer::connect(&node, "wallet");
+
+    let reply = wallet
+        .ask(Message::Hello {
+            version: PROTOCOL_VERSION,
+            encodings: vec![Encoding::Cbor],
+            time: None,
+            nonce: None,
+            capabilities: Capabilities::NONE,
+        })
+        .await;
+    assert!(matches!(reply, Message::Welcome { policy: told, .. } if told == policy));
+
+    let output = coinbase(&node, 0).await;
+    let paying = |fee: Amount| {
+        TransactionBuilder::new()
+            .spend(&output, &key)
+            .pay_to(other.public_key(), Amount::ONE_BTC)
+            .change_to(key.public_key(), fee)
+            .finalize()
+            .unwrap()
+    };
+    let cheap = paying(Amount::ONE_SAT);
+    let reply = wallet.ask(Message::SubmitTransaction(cheap)).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::Policy));
+    let transaction = paying(Amount::from_sat(10_000));
+    assert!(policy.min_fee(transaction.size()) <= Amount::from_sat(10_000));
+    assert!(matches!(
+        wallet.ask(Message::SubmitTransaction(transaction.clone())).await,
+        Message::Ack(hash) if hash == transaction.hash()
+    ));
+}
+
+#[tokio::test]
+async fn submitting_packages() {
+    let key = PrivateKey::new_key();
+    let policy = MempoolPolicy {
+        min_fee_rate: 1,
+        allow_zero_fee: false,
+        ..MempoolPolicy::default()
+    };
+    let node = funded(Node::builder().mempool_policy(policy), &key, 2).await;
+    let mut events = node.subscribe();
+    let mut wallet = Peer::connect(&node, "wallet");
+    // a parent paying nothing, and a child paying for both
+    let package = |output: &TransactionOutput| {
+        let parent = TransactionBuilder::new()
+            .spend(output, &key)
+            .pay_to(key.public_key(), output.value)
+            .finalize()
+            .unwrap();
+        let change = &parent.outputs[0];
+        let child = TransactionBuilder::new()
+            .spend(change, &key)
+            .pay_to(
+                key.public_key(),
+                change.value.checked_sub(Amount::from_sat(10_000)).unwrap(),
+            )
+            .finalize()
+            .unwrap();
+        vec![parent, child]
+    };
+
+    let submitted = package(&coinbase(&node, 0).await);
+    let reply = wallet
+        .ask(Message::SubmitTransaction(submitted[0].clone()))
+        .await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::Policy));
+    assert!(matches!(
+        wallet.ask(Message::SubmitPackage(submitted.clone())).await,
+        Message::Ack(hash) if hash == submitted[1].hash()
+    ));
+    assert_eq!(node.blockchain().await.mempool().len(), 2);
+    let reply = wallet.ask(Message::SubmitPackage(vec![])).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::Invalid));
+
+    // other nodes relay them the same way, without a reply
+    let relayed = package(&coinbase(&node, 1).await);
+    let mut peer = Peer::connect(&node, "peer");
+    peer.send(Message::NewPackage(relayed.clone())).await;
+    let mut heard = vec![];
+    while heard.len() < 4 {
+        if let NodeEvent::Transaction(tx) = timeout(REPLY_TIMEOUT, events.recv())
+            .await
+            .unwrap()
+            .unwrap()
+        {
+            heard.push(tx.hash());
+        }
+    }
+    assert!(relayed.iter().all(|tx| heard.contains(&tx.hash())));
+    assert!(peer.is_quiet().await);
+    assert_eq!(node.blockchain().await.mempool().len(), 4);
+}
+
+#[tokio::test]
+async fn testing_transactions() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut wallet = Peer::connect(&node, "wallet");
+
+    let transaction = spend(&coinbase(&node, 0).await, &key, &other);
+    let reply = wallet
+        .ask(Message::TestTransaction(transaction.clone()))
+        .await;
+    assert!(matches!(
+        reply,
+        Message::TransactionTested { txid, result: Ok(acceptance) }
+            if txid == transaction.hash() && acceptance.size == transaction.size()
+    ));
+    // nothing was added or relayed
+    assert!(node.blockchain().await.mempool().is_empty());
+
+    // spends what the first one made, which never got anywhere
+    let child = TransactionBuilder::new()
+        .spend(&transaction.outputs[0], &other)
+        .pay_to(key.public_key(), transaction.outputs[0].value)
+        .finalize()
+        .unwrap();
+    let reply = wallet.ask(Message::TestTransaction(child)).await;
+    assert!(matches!(
+        reply,
+        Message::TransactionTested {
+            result: Err(Rejection {
+                code: ErrorCode::MissingInputs,
+                ..
+            }),
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn mempool_queries() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut wallet = Peer::connect(&node, "wallet");
+    assert!(matches!(
+        wallet.ask(Message::FetchMempool).await,
+        Message::MempoolTransactions(info) if info.is_empty()
+    ));
+
+    let transaction = spend(&coinbase(&node, 0).await, &key, &other);
+    wallet
+        .ask(Message::SubmitTransaction(transaction.clone()))
+        .await;
+    let Message::MempoolTransactions(info) = wallet.ask(Message::FetchMempool).await else {
+        panic!("expected the mempool");
+    };
+    assert_eq!(info.len(), 1);
+    assert_eq!(info[0].txid, transaction.hash());
+    assert_eq!(info[0].position, 0);
+
+    let reply = wallet
+        .ask(Message::FetchMempoolTx(transaction.hash()))
+        .await;
+    let Message::MempoolTx {
+        info: found,
+        transaction: queued,
+    } = reply
+    else {
+        panic!("expected the transaction");
+    };
+    assert_eq!(found, info[0]);
+    assert_eq!(queued.hash(), transaction.hash());
+
+    let reply = wallet.ask(Message::FetchMempoolTx(Hash::zero())).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::NotFound));
+}
+
+#[tokio::test]
+async fn chain_stats() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 5).await;
+    let mut dashboard = Peer::connect(&node, "dashboard");
+    let reply = dashboard
+        .ask(Message::GetChainStats {
+            start: 1,
+            end: u64::MAX,
+            window: 2,
+        })
+        .await;
+    let Message::Stats(stats) = reply else {
+        panic!("expected stats");
+    };
+    let blockchain = node.blockchain().await;
+    assert_eq!(stats, vec![blockchain.stats(1..3), blockchain.stats(3..5)]);
+    assert_eq!(stats[0].transactions, 0);
+}
+
+#[tokio::test]
+async fn node_status() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let builder = Node::builder().finality(Finality::Confirmations(2));
+    let node = funded(builder, &key, 3).await;
+    let mut light = Peer::connect(&node, "light wallet");
+    light
+        .send(Message::SetFilter(BloomFilter::new(1, 0.01, 0)))
+        .await;
+    let mut wallet = Peer::connect(&node, "wallet");
+    let transaction = spend(&coinbase(&node, 0).await, &key, &other);
+    wallet
+        .ask(Message::SubmitTransaction(transaction.clone()))
+        .await;
+
+    let Message::Status(status) = wallet.ask(Message::GetStatus).await else {
+        panic!("expected the status");
+    };
+    let blockchain = node.blockchain().await;
+    assert_eq!(status.height, 3);
+    assert_eq!(status.finalized, Some(1));
+    assert_eq!(status.best_hash, blockchain.blocks().last().unwrap().hash());
+    assert_eq!(status.target, blockchain.target());
+    assert!(status.regtest);
+    assert!(!status.syncing);
+    assert_eq!(status.peers, 0);
+    assert_eq!(status.light_wallets, 1);
+    assert_eq!(status.mempool.transactions, 1);
+    assert_eq!(status.mempool.fees, Amount::ONE_SAT);
+    assert!(status.mempool.oldest.is_some());
+    drop(blockchain);
+    assert_eq!(node.status().await.height, status.height);
+    assert!(status.to_string().contains("height:     3"));
+    assert!(status.to_string().contains("finalized:  block 1"));
+    assert_eq!(status.handler_panics, 0);
+    assert!(!status.to_string().contains("panics:"));
+}
+
+#[tokio::test]
+async fn node_identity() {
+    let dir = std::env::temp_dir().join(format!("node-identity-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("identity.cbor");
+    let node = Node::builder()
+        .port(0)
+        .identity(&path)
+        .spawn()
+        .await
+        .unwrap();
+    let identity = node.identity();
+    assert_eq!(node.status().await.identity, Some(identity.clone()));
+
+    let nonce = new_nonce();
+    let reply = Peer::connect(&node, "wallet")
+        .ask(Message::Hello {
+            version: PROTOCOL_VERSION,
+            encodings: vec![Encoding::Cbor],
+            time: None,
+            nonce: Some(nonce),
+            capabilities: Capabilities::NONE,
+        })
+        .await;
+    let Message::Welcome {
+        identity: Some(proof),
+        ..
+    } = reply
+    else {
+        panic!("expected the node's identity");
+    };
+    assert_eq!(proof.key, identity);
+    check_identity(Some(&proof), &nonce, &[fingerprint(&identity)]).unwrap();
+
+    // it's the same node after a restart
+    drop(node);
+    let node = Node::builder()
+        .port(0)
+        .identity(&path)
+        .spawn()
+        .await
+        .unwrap();
+    assert_eq!(node.identity(), identity);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn clock_skew() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    // enough peers agree the local clock is 20 minutes behind
+    for i in 0..MIN_CLOCK_SAMPLES {
+        let reply = Peer::connect(&node, &format!("peer {i}"))
+            .ask(Message::Hello {
+                version: PROTOCOL_VERSION,
+                encodings: vec![Encoding::Cbor],
+                time: Some(Utc::now() + chrono::Duration::minutes(20)),
+                nonce: None,
+                capabilities: Capabilities::NONE,
+            })
+            .await;
+        let Message::Welcome {
+            time: Some(time), ..
+        } = reply
+        else {
+            panic!("expected the node's clock");
+        };
+        assert!((time - Utc::now()).num_seconds().abs() < 5);
+    }
+    let status = node.status().await;
+    assert!((1195..=1200).contains(&status.clock_offset));
+    assert!(status.to_string().contains("check the local clock"));
+
+    // a block 2 hours ahead of the network is still more than 2 hours ahead of the local clock
+    let block = {
+        let blockchain = node.blockchain().await;
+        mined(
+            BlockBuilder::on_top_of(&blockchain)
+                .timestamp(Utc::now() + chrono::Duration::minutes(135))
+                .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+                .finalize()
+                .unwrap(),
+        )
+    };
+    node.submit_block(block).await.unwrap();
+}
+
+#[tokio::test]
+async fn header_ranges() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 5).await;
+    let mut client = Peer::connect(&node, "verifier");
+
+    let Message::Headers(headers) = client.ask(Message::FetchHeaderRange(1, 3)).await else {
+        panic!("expected headers");
+    };
+    let blockchain = node.blockchain().await;
+    let blocks: Vec<_> = blockchain.blocks().skip(1).take(3).collect();
+    assert_eq!(headers.len(), 3);
+    for (compact, block) in headers.iter().zip(blocks) {
+        assert_eq!(compact.hash, block.hash());
+        assert_eq!(compact.header().unwrap().hash(), block.header.hash());
+    }
+    drop(blockchain);
+
+    // cut off at the tip, and nothing past it
+    let reply = client.ask(Message::FetchHeaderRange(3, 100)).await;
+    assert!(matches!(reply, Message::Headers(headers) if headers.len() == 2));
+    let reply = client.ask(Message::FetchHeaderRange(5, 1)).await;
+    assert!(matches!(reply, Message::Headers(headers) if headers.is_empty()));
+}
+
+#[tokio::test]
+async fn raw_transactions() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut wallet = Peer::connect(&node, "wallet");
+
+    let transaction = spend(&coinbase(&node, 0).await, &key, &other);
+    let reply = wallet
+        .ask(Message::DecodeRawTransaction(transaction.to_hex()))
+        .await;
+    let Message::DecodedTransaction {
+        txid,
+        transaction: decoded,
+    } = reply
+    else {
+        panic!("expected the decoded transaction, got {reply:?}");
+    };
+    assert_eq!(txid, transaction.hash());
+    assert_eq!(decoded.hash(), transaction.hash());
+    // decoding doesn't submit it
+    assert!(node.blockchain().await.mempool().is_empty());
+
+    let reply = wallet
+        .ask(Message::DecodeRawTransaction("not hex".to_string()))
+        .await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::Invalid));
+    let reply = wallet
+        .ask(Message::SendRawTransaction(transaction.to_hex() + "00"))
+        .await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::Invalid));
+
+    let reply = wallet
+        .ask(Message::SendRawTransaction(transaction.to_hex()))
+        .await;
+    assert!(matches!(reply, Message::Ack(hash) if hash == transaction.hash()));
+    assert!(
+        node.blockchain()
+            .await
+            .mempool()
+            .contains(&transaction.hash())
+    );
+}
+
+#[tokio::test]
+async fn relayed_transactions_and_blocks() {
+    let key = PrivateKey::new_key();
+    let other = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut events = node.subscribe();
+    let mut peer = Peer::connect(&node, "peer");
+
+    // other nodes get no reply, just whatever they sent is taken in
+    let transaction = spend(&coinbase(&node, 0).await, &key, &other);
+    peer.send(Message::NewTransaction(transaction.clone()))
+        .await;
+    assert!(matches!(
+        timeout(REPLY_TIMEOUT, events.recv()).await.unwrap().unwrap(),
+        NodeEvent::Transaction(tx) if tx.hash() == transaction.hash()
+    ));
+
+    let reward = node.blockchain().await.calculate_block_reward();
+    let block = mined({
+        let blockchain = node.blockchain().await;
+        BlockBuilder::on_top_of(&blockchain)
+            .coinbase_to(other.public_key(), reward)
+            .add_tx(transaction)
+            .finalize_with_fees(blockchain.utxos())
+            .unwrap()
+    });
+    peer.send(Message::NewBlock(block.clone())).await;
+    assert!(matches!(
+        timeout(REPLY_TIMEOUT, events.recv()).await.unwrap().unwrap(),
+        NodeEvent::Block(added) if added.hash() == block.hash()
+    ));
+    assert!(peer.is_quiet().await);
+
+    // a rejected block is only ignored
+    peer.send(Message::NewBlock(block)).await;
+    assert!(peer.is_quiet().await);
+    assert_eq!(node.blockchain().await.block_height(), 2);
+
+    // a rejected transaction costs the connection
+    let unfunded = spend(&coinbase(&node, 0).await, &key, &other);
+    assert!(peer.hangs_up_after(Message::NewTransaction(unfunded)).await);
+}
+
+#[tokio::test]
+async fn light_wallet_filters() {
+    let key = PrivateKey::new_key();
+    let watched = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 2).await;
+    let mut light = Peer::connect(&node, "light wallet");
+    let mut wallet = Peer::connect(&node, "wallet");
+
+    // no filter to add to yet
+    let reply = light.ask(Message::FilterAdd(vec![1])).await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::NotAllowed));
+
+    let mut filter = BloomFilter::new(2, 0.000_001, 0);
+    filter.insert(b"something else");
+    light.send(Message::SetFilter(filter)).await;
+    light
+        .send(Message::FilterAdd(
+            watched.public_key().to_compact().to_vec(),
+        ))
+        .await;
+    let reply = light
+        .ask(Message::FilterAdd(vec![0; MAX_FILTER_ADD_SIZE + 1]))
+        .await;
+    assert_eq!(error_code(&reply), Some(ErrorCode::NotAllowed));
+
+    let transaction = spend(&coinbase(&node, 0).await, &key, &watched);
+    wallet
+        .ask(Message::SubmitTransaction(transaction.clone()))
+        .await;
+    assert!(matches!(
+        light.receive().await,
+        Message::NewTransaction(tx) if tx.hash() == transaction.hash()
+    ));
+
+    wallet
+        .ask(Message::GenerateBlocks(key.public_key(), 1))
+        .await;
+    let Message::FilteredBlock(block) = light.receive().await else {
+        panic!("expected the new block");
+    };
+    assert!(block.verify());
+    assert_eq!(block.matches.len(), 1);
+
+    light.send(Message::FilterClear).await;
+    // once there's a reply the filter is gone
+    light.ask(Message::AskDifference(0)).await;
+    let transaction = spend(&coinbase(&node, 1).await, &key, &watched);
+    wallet.ask(Message::SubmitTransaction(transaction)).await;
+    assert!(light.is_quiet().await);
+}
+
+#[tokio::test]
+async fn double_spend_alerts() {
+    let key = PrivateKey::new_key();
+    let merchant = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 2).await;
+    let mut events = node.subscribe();
+    let mut light = Peer::connect(&node, "merchant");
+    let mut wallet = Peer::connect(&node, "wallet");
+    // room for the outputs it picks up too, or the refund may match it by chance
+    let mut filter = BloomFilter::new(10, 0.000_001, 0);
+    filter.insert_pubkey(&merchant.public_key());
+    light.send(Message::SetFilter(filter)).await;
+    // once there's a reply the filter is set
+    light.ask(Message::AskDifference(0)).await;
+
+    // paying the merchant, then taking it back
+    let output = coinbase(&node, 0).await;
+    let payment = spend(&output, &key, &merchant);
+    wallet
+        .ask(Message::SubmitTransaction(payment.clone()))
+        .await;
+    assert!(matches!(light.receive().await, Message::NewTransaction(_)));
+    let refund = spend(&output, &key, &key);
+    wallet.ask(Message::SubmitTransaction(refund.clone())).await;
+    assert!(matches!(
+        light.receive().await,
+        Message::Evicted {
+            reason: EvictionReason::Replaced,
+            ..
+        }
+    ));
+    let alert = DoubleSpend {
+        txid: payment.hash(),
+        conflicting: refund.hash(),
+        outputs: vec![output.hash()],
+        outcome: DoubleSpendOutcome::Replaced,
+    };
+    assert!(matches!(
+        light.receive().await,
+        Message::DoubleSpendAlert(received) if received == alert
+    ));
+    let mut alerted = false;
+    while let Ok(Ok(event)) = timeout(SILENCE, events.recv()).await {
+        alerted |= matches!(event, NodeEvent::DoubleSpend(received) if received == alert);
+    }
+    assert!(alerted);
+
+    // unconfirmed outputs can't be taken back, trying to is still worth an alert
+    let parent = spend(&coinbase(&node, 1).await, &key, &key);
+    let pay = |to: &PrivateKey| {
+        TransactionBuilder::new()
+            .spend(&parent.outputs[0], &key)
+            .pay_to(
+                to.public_key(),
+                Amount::from_sat(Amount::ONE_BTC.to_sat() - 1),
+            )
+            .finalize()
+            .unwrap()
+    };
+    let payment = pay(&merchant);
+    wallet.ask(Message::SubmitTransaction(parent.clone())).await;
+    wallet
+        .ask(Message::SubmitTransaction(payment.clone()))
+        .await;
+    assert!(matches!(light.receive().await, Message::NewTransaction(_)));
+    let refund = pay(&key);
+    let reply = wallet.ask(Message::SubmitTransaction(refund.clone())).await;
+    assert!(error_code(&reply).is_some());
+    assert!(matches!(
+        light.receive().await,
+        Message::DoubleSpendAlert(DoubleSpend {
+            txid,
+            conflicting,
+            outcome: DoubleSpendOutcome::Rejected,
+            ..
+        }) if txid == payment.hash() && conflicting == refund.hash()
+    ));
+}
+
+#[tokio::test]
+async fn replies_sent_to_the_node_end_the_conversation() {
+    let node = spawn(Node::builder()).await;
+    let key = PrivateKey::new_key();
+    let block = mined(
+        BlockBuilder::new()
+            .coinbase_to(key.public_key(), Amount::ONE_BTC)
+            .finalize()
+            .unwrap(),
+    );
+    let replies = [
+        Message::UTXOs(vec![]),
+        Message::UTXOsSince(UtxoDiff {
+            height: 0,
+            tip: Hash::zero(),
+            full: true,
+            added: vec![],
+            removed: vec![],
+            marked: vec![],
+        }),
+        Message::Template(block.clone()),
+        Message::TemplateValidity(true),
+        Message::NodeList(vec![]),
+        Message::Difference(0),
+        Message::GeneratedBlocks(vec![]),
+        Message::TransactionInfo {
+            height: 0,
+            transaction: block.transactions[0].clone(),
+        },
+        Message::History(vec![]),
+        Message::AddressScanned(AddressScan {
+            height: 0,
+            tip: Hash::zero(),
+            utxos: vec![],
+            history: vec![],
+        }),
+        Message::FilteredBlock(MerkleBlock::new(&block, &mut BloomFilter::new(1, 0.01, 0))),
+        Message::Evicted {
+            txid: Hash::zero(),
+            reason: EvictionReason::Expired,
+        },
+        Message::DoubleSpendAlert(DoubleSpend {
+            txid: Hash::zero(),
+            conflicting: Hash::zero(),
+            outputs: vec![],
+            outcome: DoubleSpendOutcome::Rejected,
+        }),
+        Message::Supply {
+            height: 0,
+            total: Amount::ZERO,
+            max: Amount::ZERO,
+        },
+        Message::Headers(vec![]),
+        Message::Status(Box::new(node.status().await)),
+        Message::DecodedTransaction {
+            txid: block.transactions[0].hash(),
+            transaction: block.transactions[0].clone(),
+        },
+        Message::Ack(Hash::zero()),
+        Message::ShareAccepted(Hash::zero()),
+        Message::MempoolTransactions(vec![]),
+        Message::Stats(vec![]),
+        Message::TransactionTested {
+            txid: Hash::zero(),
+            result: Err(Rejection {
+                code: ErrorCode::Invalid,
+                reason: String::new(),
+            }),
+        },
+        Message::Welcome {
+            version: PROTOCOL_VERSION,
+            encoding: Encoding::Json,
+            policy: MempoolPolicy::default(),
+            time: None,
+            identity: None,
+            capabilities: Capabilities::NONE,
+        },
+        Message::Error {
+            code: ErrorCode::Invalid,
+            reason: String::new(),
+        },
+    ];
+    for (i, reply) in replies.into_iter().enumerate() {
+        let mut peer = Peer::connect(&node, &format!("peer {i}"));
+        assert!(peer.hangs_up_after(reply).await);
+    }
+}
+
+#[tokio::test]
+async fn newer_peers_are_understood_as_far_as_possible() {
+    let node = spawn(Node::builder()).await;
+    let mut peer = Peer::connect(&node, "newer node");
+    peer.encoding = Encoding::Json;
+    let reply = peer
+        .ask(Message::Hello {
+            version: PROTOCOL_VERSION + 1,
+            encodings: vec![Encoding::Json],
+            time: None,
+            nonce: None,
+            capabilities: Capabilities::FILTERS | Capabilities::COMPACT_BLOCKS,
+        })
+        .await;
+    let Message::Welcome { capabilities, .. } = reply else {
+        panic!("expected a welcome, got {reply:?}");
+    };
+    assert!(capabilities.contains(Capabilities::FILTERS | Capabilities::CHECKPOINTS));
+    assert!(!capabilities.contains(Capabilities::SNAPSHOTS));
+
+    // a message this version doesn't have is refused, the connection carries on
+    peer.send_raw(KNOWN_KINDS, br#"{"CompactBlock":{"short_ids":[]}}"#)
+        .await;
+    assert!(matches!(
+        peer.receive().await,
+        Message::Error {
+            code: ErrorCode::Unsupported,
+            ..
+        }
+    ));
+    assert!(matches!(
+        peer.ask(Message::AskDifference(0)).await,
+        Message::Difference(0)
+    ));
+}
+
+#[tokio::test]
+async fn big_replies_are_compressed_for_peers_that_read_them() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 30).await;
+    let mut peer = Peer::connect(&node, "peer on a slow link");
+    peer.ask(Message::Hello {
+        version: PROTOCOL_VERSION,
+        encodings: vec![Encoding::Cbor],
+        time: None,
+        nonce: None,
+        capabilities: Capabilities::LZ4,
+    })
+    .await;
+
+    peer.send(Message::FetchHeaderRange(0, 30)).await;
+    let envelope = peer.receive_envelope().await.unwrap();
+    assert_eq!(envelope.compression, Some(Compression::Lz4));
+    let Message::Headers(headers) = envelope.open(Encoding::Cbor).unwrap() else {
+        panic!("expected headers");
+    };
+    assert_eq!(headers.len(), 30);
+    // small ones aren't worth it
+    peer.send(Message::AskDifference(0)).await;
+    assert_eq!(peer.receive_envelope().await.unwrap().compression, None);
+
+    // nor for peers that didn't say they read them
+    let mut old = Peer::connect(&node, "older peer");
+    old.send(Message::FetchHeaderRange(0, 30)).await;
+    assert_eq!(old.receive_envelope().await.unwrap().compression, None);
+
+    let status = node.status().await;
+    assert_eq!(status.compression.messages, 1);
+    assert!(status.compression.ratio().unwrap() < 1.0);
+    assert!(status.to_string().contains("compressed: 1 messages to"));
+}
+
+#[tokio::test]
+async fn traffic_is_counted_and_capped_per_peer() {
+    let key = PrivateKey::new_key();
+    let limits = RateLimits {
+        upload: Some(4_000),
+        download: None,
+    };
+    let node = funded(Node::builder().rate_limits(limits), &key, 30).await;
+    let mut syncing = Peer::connect(&node, "syncing peer");
+    let started = std::time::Instant::now();
+    for _ in 0..4 {
+        let reply = syncing.ask(Message::FetchHeaderRange(0, 30)).await;
+        assert!(matches!(reply, Message::Headers(headers) if headers.len() == 30));
+    }
+    // the first second's worth goes at once, the rest at the rate
+    assert!(started.elapsed() > Duration::from_secs(1));
+
+    let traffic = node.traffic();
+    let peer = &traffic.peers["syncing peer"];
+    assert_eq!(peer.messages["Headers"].messages_sent, 4);
+    assert_eq!(peer.messages["FetchHeaderRange"].messages_received, 4);
+    assert!(peer.total.bytes_sent > 8_000);
+    let status = node.status().await;
+    assert_eq!(status.upload_limit, Some(4_000));
+    assert!(status.traffic.bytes_sent >= peer.total.bytes_sent);
+    assert!(status.to_string().contains("4000B/s up per peer"));
+
+    // closed connections stay in the totals
+    drop(syncing);
+    tokio::time::sleep(SILENCE).await;
+    let traffic = node.traffic();
+    assert!(!traffic.peers.contains_key("syncing peer"));
+    assert_eq!(traffic.total.messages["Headers"].messages_sent, 4);
+}
+
+#[tokio::test]
+async fn peers_cannot_make_the_node_buffer_past_its_cap() {
+    let key = PrivateKey::new_key();
+    let node = funded(Node::builder().peer_memory(Some(64 * 1024)), &key, 3).await;
+
+    // a frame bigger than the whole cap gets the connection closed before it's read
+    let mut greedy = Peer::connect(&node, "greedy peer");
+    greedy.stream.write_u64(1_000_000).await.unwrap();
+    let closed = timeout(REPLY_TIMEOUT, greedy.read())
+        .await
+        .expect("the node neither read the frame nor hung up");
+    assert!(closed.is_err());
+
+    // while everyone else is served as before
+    let mut peer = Peer::connect(&node, "peer");
+    let reply = peer.ask(Message::FetchHeaderRange(0, 3)).await;
+    assert!(matches!(reply, Message::Headers(headers) if headers.len() == 3));
+
+    let status = node.status().await;
+    assert_eq!(status.memory.peers.limit, Some(64 * 1024));
+    assert_eq!(status.memory.peers.refused, 1);
+    assert!(status.memory.peers.peak > 0);
+    assert_eq!(status.memory.utxo_entries, 3);
+    assert!(status.memory.utxo_bytes > 0);
+    assert_eq!(status.memory, node.memory().await);
+    assert!(status.to_string().contains("1 frames refused"));
+}
+
+#[tokio::test]
+async fn speaking_json() {
+    let key = PrivateKey::new_key();
+    let watched = PrivateKey::new_key();
+    let node = funded(Node::builder(), &key, 1).await;
+    let mut script = Peer::connect(&node, "script");
+    let mut light = Peer::connect(&node, "light wallet");
+    let mut wallet = Peer::connect(&node, "wallet");
+
+    // the hello is understood in JSON right away, and answered in JSON
+    script
+        .send_raw(
+            53,
+            br#"{"Hello":{"version":3,"encodings":["Json","Cbor"]}}"#,
+        )
+        .await;
+    let welcome: serde_json::Value =
+        serde_json::from_slice(&script.receive_raw().await.unwrap()).unwrap();
+    assert_eq!(welcome["Welcome"]["version"], PROTOCOL_VERSION);
+    assert_eq!(welcome["Welcome"]["encoding"], "Json");
+    assert_eq!(welcome["Welcome"]["policy"]["allow_zero_fee"], true);
+    script.send_raw(17, br#"{"AskDifference":0}"#).await;
+    assert_eq!(script.receive_raw().await.unwrap(), br#"{"Difference":1}"#);
+    // the kind has to be the message's
+    script.send_raw(18, br#"{"AskDifference":0}"#).await;
+    let error: serde_json::Value =
+        serde_json::from_slice(&script.receive_raw().await.unwrap()).unwrap();
+    assert_eq!(error["Error"]["code"], "Unsupported");
+
+    // light wallets in either encoding get relays in their own
+    light.encoding = Encoding::Json;
+    let reply = light
+        .ask(Message::Hello {
+            version: PROTOCOL_VERSION,
+            encodings: vec![Encoding::Json],
+            time: None,
+            nonce: None,
+            capabilities: Capabilities::NONE,
+        })
+        .await;
+    assert!(matches!(
+        reply,
+        Message::Welcome {
+            encoding: Encoding::Json,
+            ..
+        }
+    ));
+    let mut filter = BloomFilter::new(1, 0.000_001, 0);
+    filter.insert_pubkey(&watched.public_key());
+    light.send(Message::SetFilter(filter.clone())).await;
+    wallet.send(Message::SetFilter(filter)).await;
+    // once there's a reply the filters are set
+    light.ask(Message::AskDifference(0)).await;
+    wallet.ask(Message::AskDifference(0)).await;
+
+    let transaction = spend(&coinbase(&node, 0).await, &key, &watched);
+    wallet
+        .send(Message::SubmitTransaction(transaction.clone()))
+        .await;
+    for peer in [&mut light, &mut wallet] {
+        let mut relayed = false;
+        // the wallet also gets its Ack
+        for _ in 0..2 {
+            if let Message::NewTransaction(tx) = peer.receive().await {
+                relayed = tx.hash() == transaction.hash();
+                break;
+            }
+        }
+        assert!(relayed);
+    }
+
+    // anything but a hello in another encoding is refused
+    let mut peer = Peer::connect(&node, "confused");
+    peer.encoding = Encoding::Json;
+    assert!(peer.hangs_up_after(Message::AskDifference(0)).await);
+}
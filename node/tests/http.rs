@@ -0,0 +1,298 @@
+use btclib::{
+    crypto::PrivateKey,
+    network::{MemoryUsage, fingerprint},
+    sha256::Hash,
+    types::{
+        Amount, Block, BlockBuilder, Blockchain, ChainStats, Holder, SupplyBucket,
+        TransactionOutput,
+    },
+    util::Saveable,
+};
+use node::{Node, NodeHandle, Schedule, Snapshot};
+
+use chrono::{Duration, Utc};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// GET `target` from the node's http endpoint, the status code and body
+async fn get(node: &NodeHandle, target: &str) -> (u16, String) {
+    let (status, body) = get_bytes(node, target).await;
+    (status, String::from_utf8(body).unwrap())
+}
+
+async fn get_bytes(node: &NodeHandle, target: &str) -> (u16, Vec<u8>) {
+    let mut stream = TcpStream::connect(node.http_addr().unwrap()).await.unwrap();
+    stream
+        .write_all(format!("GET {target} HTTP/1.1\r\nHost: node\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await.unwrap();
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8(response[..split].to_vec()).unwrap();
+    let body = response[split + 4..].to_vec();
+    assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, body)
+}
+
+/// a block on the node's tip, `minutes` ago
+async fn next_block(node: &NodeHandle, key: &PrivateKey, minutes: i64) -> Block {
+    let mut block = {
+        let blockchain = node.blockchain().await;
+        BlockBuilder::on_top_of(&blockchain)
+            .timestamp(Utc::now() - Duration::minutes(minutes))
+            .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+            .finalize()
+            .unwrap()
+    };
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    block
+}
+
+#[tokio::test]
+async fn stats_are_served() {
+    let node = Node::builder()
+        .port(0)
+        .regtest(true)
+        .http("127.0.0.1:0")
+        .spawn()
+        .await
+        .unwrap();
+    let key = PrivateKey::new_key();
+    let start = Utc::now() - Duration::hours(1);
+    for i in 0..3 {
+        let mut block = {
+            let blockchain = node.blockchain().await;
+            BlockBuilder::on_top_of(&blockchain)
+                .timestamp(start + Duration::minutes(2 * i))
+                .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+                .finalize()
+                .unwrap()
+        };
+        block
+            .header
+            .mine_with_target_override(btclib::REGTEST_TARGET);
+        node.submit_block(block).await.unwrap();
+    }
+
+    let (status, body) = get(&node, "/stats").await;
+    assert_eq!(status, 200);
+    let stats: Vec<ChainStats> = serde_json::from_str(&body).unwrap();
+    assert_eq!(stats, vec![node.blockchain().await.stats(0..3)]);
+    assert_eq!(stats[0].average_interval, Some(120.0));
+
+    let (status, body) = get(&node, "/stats?start=1&window=1").await;
+    assert_eq!(status, 200);
+    let stats: Vec<ChainStats> = serde_json::from_str(&body).unwrap();
+    let bounds: Vec<_> = stats.iter().map(|s| (s.start, s.end)).collect();
+    assert_eq!(bounds, [(1, 2), (2, 3)]);
+
+    assert_eq!(get(&node, "/stats?start=one").await.0, 400);
+    assert_eq!(get(&node, "/stats?height=1").await.0, 400);
+    assert_eq!(get(&node, "/blocks").await.0, 404);
+
+    let (status, body) = get(&node, "/traffic").await;
+    assert_eq!(status, 200);
+    let traffic: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(traffic["total"]["total"]["bytes_sent"].is_u64());
+    assert!(traffic["peers"].is_object());
+
+    let (status, body) = get(&node, "/memory").await;
+    assert_eq!(status, 200);
+    let memory: MemoryUsage = serde_json::from_str(&body).unwrap();
+    assert_eq!(memory.peers.limit, Some(node::DEFAULT_PEER_MEMORY));
+    assert!(memory.utxo_entries > 0);
+}
+
+#[tokio::test]
+async fn balances_are_served_at_any_height() {
+    let node = Node::builder()
+        .port(0)
+        .regtest(true)
+        .http("127.0.0.1:0")
+        .spawn()
+        .await
+        .unwrap();
+    let key = PrivateKey::new_key();
+    for i in 0..2 {
+        let mut block = {
+            let blockchain = node.blockchain().await;
+            BlockBuilder::on_top_of(&blockchain)
+                .timestamp(Utc::now() - Duration::minutes(10 - i))
+                .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+                .finalize()
+                .unwrap()
+        };
+        block
+            .header
+            .mine_with_target_override(btclib::REGTEST_TARGET);
+        node.submit_block(block).await.unwrap();
+    }
+    let reward = node
+        .blockchain()
+        .await
+        .blocks()
+        .next()
+        .unwrap()
+        .transactions[0]
+        .outputs[0]
+        .value;
+    let key = fingerprint(&key.public_key());
+
+    let balance = |body: String| -> Amount {
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        serde_json::from_value(body["balance"].clone()).unwrap()
+    };
+    let (status, body) = get(&node, &format!("/balance/{key}?height=1")).await;
+    assert_eq!(status, 200);
+    assert_eq!(balance(body), reward);
+    let (_, body) = get(&node, &format!("/balance/{key}?height=0")).await;
+    assert_eq!(balance(body), Amount::ZERO);
+
+    let (status, body) = get(&node, &format!("/utxos/{key}")).await;
+    assert_eq!(status, 200);
+    let utxos: Vec<TransactionOutput> = serde_json::from_str(&body).unwrap();
+    assert_eq!(utxos.len(), 2);
+
+    assert_eq!(get(&node, &format!("/balance/{key}?height=3")).await.0, 404);
+    assert_eq!(get(&node, "/balance/abcd").await.0, 400);
+    assert_eq!(get(&node, &format!("/utxos/{key}?limit=1")).await.0, 400);
+}
+
+#[tokio::test]
+async fn holders_need_the_addrindex() {
+    let node = Node::builder()
+        .port(0)
+        .http("127.0.0.1:0")
+        .spawn()
+        .await
+        .unwrap();
+    assert_eq!(get(&node, "/richlist").await.0, 404);
+    assert_eq!(get(&node, "/distribution").await.0, 404);
+
+    let node = Node::builder()
+        .port(0)
+        .regtest(true)
+        .addrindex(true)
+        .http("127.0.0.1:0")
+        .spawn()
+        .await
+        .unwrap();
+    let keys = [PrivateKey::new_key(), PrivateKey::new_key()];
+    for (i, key) in keys.iter().enumerate() {
+        let mut block = {
+            let blockchain = node.blockchain().await;
+            BlockBuilder::on_top_of(&blockchain)
+                .timestamp(Utc::now() - Duration::minutes(10 - i as i64))
+                .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+                .finalize()
+                .unwrap()
+        };
+        block
+            .header
+            .mine_with_target_override(btclib::REGTEST_TARGET);
+        node.submit_block(block).await.unwrap();
+    }
+
+    let (status, body) = get(&node, "/richlist?limit=1").await;
+    assert_eq!(status, 200);
+    let holders: Vec<Holder> = serde_json::from_str(&body).unwrap();
+    assert_eq!(holders, node.blockchain().await.rich_list(1));
+    assert_eq!(get(&node, "/richlist?start=1").await.0, 400);
+
+    let (status, body) = get(&node, "/distribution").await;
+    assert_eq!(status, 200);
+    let buckets: Vec<SupplyBucket> = serde_json::from_str(&body).unwrap();
+    assert_eq!(buckets.iter().map(|bucket| bucket.holders).sum::<u64>(), 2);
+}
+
+#[tokio::test]
+async fn snapshots_are_served() {
+    let dir = std::env::temp_dir().join(format!("node-snapshots-{}", uuid::Uuid::new_v4()));
+    let seed = Node::builder()
+        .port(0)
+        .regtest(true)
+        .http("127.0.0.1:0")
+        .serve_snapshots(dir.join("snapshots"))
+        .schedule(Schedule {
+            snapshot: std::time::Duration::from_millis(20),
+            ..Schedule::default()
+        })
+        .spawn()
+        .await
+        .unwrap();
+    let key = PrivateKey::new_key();
+    for i in 0..2 {
+        seed.submit_block(next_block(&seed, &key, 10 - i).await)
+            .await
+            .unwrap();
+    }
+    let snapshot = loop {
+        match seed.snapshot() {
+            Some(snapshot) if snapshot.height == 2 => break snapshot,
+            _ => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+        }
+    };
+
+    let (status, body) = get(&seed, "/snapshot").await;
+    assert_eq!(status, 200);
+    assert_eq!(serde_json::from_str::<Snapshot>(&body).unwrap(), snapshot);
+    let (status, file) = get_bytes(&seed, &format!("/snapshots/{}", snapshot.file)).await;
+    assert_eq!(status, 200);
+    assert_eq!(file.len() as u64, snapshot.size);
+    assert_eq!(Hash::of_bytes(&file).to_hex(), snapshot.sha256);
+    let blockchain = Blockchain::load(file.as_slice()).unwrap();
+    assert_eq!(blockchain.tip_hash().to_hex(), snapshot.tip);
+    assert_eq!(blockchain.utxos().len(), snapshot.utxos);
+    // nothing but snapshots
+    assert_eq!(get(&seed, "/snapshots/snapshot.json").await.0, 404);
+    assert_eq!(
+        get(&seed, "/snapshots/../snapshots/chain-2.cbor").await.0,
+        404
+    );
+    assert_eq!(get(&seed, "/snapshots/chain-7.cbor").await.0, 404);
+
+    // a node started from it gets what came after from its peers
+    seed.submit_block(next_block(&seed, &key, 5).await)
+        .await
+        .unwrap();
+    let store = dir.join("blockchain.cbor");
+    std::fs::write(&store, &file).unwrap();
+    let peer = format!("127.0.0.1:{}", seed.local_addr().port());
+    let node = Node::builder()
+        .port(0)
+        .regtest(true)
+        .store(&store)
+        .peers([peer])
+        .spawn()
+        .await
+        .unwrap();
+    assert_eq!(node.blockchain().await.block_height(), 3);
+    assert_eq!(
+        node.blockchain().await.tip_hash(),
+        seed.blockchain().await.tip_hash()
+    );
+}
+
+#[tokio::test]
+async fn snapshots_need_the_http_endpoint() {
+    let dir = std::env::temp_dir().join(format!("node-snapshots-{}", uuid::Uuid::new_v4()));
+    assert!(
+        Node::builder()
+            .port(0)
+            .serve_snapshots(dir)
+            .spawn()
+            .await
+            .is_err()
+    );
+    let node = Node::builder()
+        .port(0)
+        .http("127.0.0.1:0")
+        .spawn()
+        .await
+        .unwrap();
+    assert_eq!(get(&node, "/snapshot").await.0, 404);
+}
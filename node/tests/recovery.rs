@@ -0,0 +1,121 @@
+use btclib::{crypto::PrivateKey, types::BlockBuilder, util::Saveable};
+use node::{Node, NodeBuilder, Schedule};
+
+use chrono::{Duration, Utc};
+use common::mined_chain;
+use std::path::Path;
+use std::time::Duration as StdDuration;
+
+mod common;
+
+/// a node on `store` that never saves it by itself
+fn builder(store: &Path) -> NodeBuilder {
+    Node::builder()
+        .port(0)
+        .regtest(true)
+        .store(store)
+        .schedule(Schedule {
+            save: StdDuration::ZERO,
+            ..Schedule::default()
+        })
+}
+
+#[tokio::test]
+async fn blocks_since_the_last_save_are_replayed() {
+    let dir = std::env::temp_dir().join(format!("node-recovery-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let store = dir.join("blockchain.cbor");
+
+    let node = builder(&store).spawn().await.unwrap();
+    let key = PrivateKey::new_key();
+    for i in 0..3 {
+        let mut block = {
+            let blockchain = node.blockchain().await;
+            BlockBuilder::on_top_of(&blockchain)
+                .timestamp(Utc::now() - Duration::minutes(10 - i))
+                .coinbase_to(key.public_key(), blockchain.calculate_block_reward())
+                .finalize()
+                .unwrap()
+        };
+        block
+            .header
+            .mine_with_target_override(btclib::REGTEST_TARGET);
+        node.submit_block(block).await.unwrap();
+    }
+    let tip = node.blockchain().await.tip_hash();
+    // gone without saving, like a crash
+    drop(node);
+    assert!(!store.exists());
+
+    let node = builder(&store).spawn().await.unwrap();
+    assert_eq!(node.blockchain().await.block_height(), 3);
+    assert_eq!(node.blockchain().await.tip_hash(), tip);
+    // what was replayed is saved right away, and the log starts over
+    assert!(store.exists());
+    assert_eq!(
+        std::fs::metadata(dir.join("blockchain.cbor.wal"))
+            .unwrap()
+            .len(),
+        0
+    );
+    drop(node);
+
+    let node = builder(&store).spawn().await.unwrap();
+    assert_eq!(node.blockchain().await.tip_hash(), tip);
+    drop(node);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_store_with_broken_blocks_is_refused() {
+    let dir = std::env::temp_dir().join(format!("node-recovery-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let store = dir.join("blockchain.cbor");
+    let blockchain = mined_chain(3);
+    let mut bytes = vec![];
+    blockchain.save(&mut bytes).unwrap();
+
+    // another id for the last coinbase output, wherever it's saved, no longer matches the block's
+    // merkle root
+    let tip = blockchain.blocks().last().unwrap();
+    let id = *tip.transactions[0].outputs[0].unique_id.as_bytes();
+    let at: Vec<usize> = (0..bytes.len() - 16)
+        .filter(|&i| bytes[i..i + 16] == id)
+        .collect();
+    assert!(!at.is_empty());
+    for i in at {
+        bytes[i..i + 16].copy_from_slice(uuid::Uuid::new_v4().as_bytes());
+    }
+    std::fs::write(&store, &bytes).unwrap();
+
+    let Err(error) = builder(&store).spawn().await else {
+        panic!("a node started on broken blocks");
+    };
+    assert!(
+        error.to_string().contains("failed its self-check"),
+        "{error:#}"
+    );
+    // a check that doesn't go as deep lets it through
+    let node = builder(&store).check_depth(0).spawn().await.unwrap();
+    assert_eq!(node.blockchain().await.block_height(), 3);
+    drop(node);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_stale_target_is_worked_out_again() {
+    let dir = std::env::temp_dir().join(format!("node-recovery-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let store = dir.join("blockchain.cbor");
+    let mut blockchain = mined_chain(btclib::DIFFICULTY_UPDATE_INTERVAL);
+    let target = blockchain.target();
+    blockchain.try_adjust_target();
+    assert_ne!(blockchain.target(), target);
+    blockchain.save_to_file(&store).unwrap();
+
+    let node = builder(&store).spawn().await.unwrap();
+    assert_eq!(node.blockchain().await.target(), target);
+    assert!(node.blockchain().await.self_check(100).is_ok());
+    drop(node);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,43 @@
+use node::{Node, Schedule};
+
+use std::time::Duration;
+
+/// short enough for a few runs to happen while the test waits
+const OFTEN: Duration = Duration::from_millis(20);
+
+#[tokio::test]
+async fn tasks_report_how_they_went() {
+    let dir = std::env::temp_dir().join(format!("node-scheduler-{}", uuid::Uuid::new_v4()));
+    // saves go through a temporary file, a directory in its place makes every one of them fail
+    std::fs::create_dir_all(dir.join("blockchain.cbor.tmp")).unwrap();
+    let node = Node::builder()
+        .port(0)
+        .store(dir.join("blockchain.cbor"))
+        .schedule(Schedule {
+            cleanup: OFTEN,
+            save: OFTEN,
+            peers: OFTEN,
+            rebroadcast: Duration::ZERO,
+            checkpoint: Duration::ZERO,
+            snapshot: Duration::ZERO,
+        })
+        .spawn()
+        .await
+        .unwrap();
+    tokio::time::sleep(OFTEN * 10).await;
+
+    let tasks = node.status().await.tasks;
+    let names: Vec<_> = tasks.iter().map(|task| task.name.as_str()).collect();
+    assert_eq!(names, ["cleanup", "save", "peers"]);
+    for task in &tasks {
+        assert!(task.runs > 1, "{} ran {} times", task.name, task.runs);
+        assert!(task.last_run.is_some());
+    }
+    // the failures don't stop the task
+    let save = &tasks[1];
+    assert_eq!(save.failures, save.runs);
+    assert!(save.last_error.is_some());
+    assert_eq!((tasks[0].failures, tasks[0].last_error.as_ref()), (0, None));
+    drop(node);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,70 @@
+use btclib::{crypto::PrivateKey, types::BlockBuilder};
+use node::Node;
+
+use chrono::{Duration, Utc};
+use common::spend;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+mod common;
+
+#[tokio::test]
+async fn double_spends_are_posted() {
+    let endpoint = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hooks/btc", endpoint.local_addr().unwrap());
+    let node = Node::builder()
+        .port(0)
+        .regtest(true)
+        .webhook(url)
+        .spawn()
+        .await
+        .unwrap();
+
+    let key = PrivateKey::new_key();
+    let reward = node.blockchain().await.calculate_block_reward();
+    let mut genesis = BlockBuilder::new()
+        .timestamp(Utc::now() - Duration::minutes(1))
+        .coinbase_to(key.public_key(), reward)
+        .finalize()
+        .unwrap();
+    genesis
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    node.submit_block(genesis.clone()).await.unwrap();
+    let output = &genesis.transactions[0].outputs[0];
+    let payment = spend(output, &key, &PrivateKey::new_key());
+    let refund = spend(output, &key, &key);
+    node.submit_transaction(payment.clone()).await.unwrap();
+    node.submit_transaction(refund.clone()).await.unwrap();
+
+    let (mut stream, _) = endpoint.accept().await.unwrap();
+    let mut request = vec![0; 4096];
+    let mut read = 0;
+    // the JSON ends with the only closing brace
+    while !String::from_utf8_lossy(&request[..read]).contains("}") {
+        let n = stream.read(&mut request[read..]).await.unwrap();
+        assert!(n > 0, "the node hung up before the body");
+        read += n;
+    }
+    stream
+        .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+        .await
+        .unwrap();
+    let request = String::from_utf8_lossy(&request[..read]).into_owned();
+    let (head, body) = request.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("POST /hooks/btc HTTP/1.1\r\n"));
+    assert!(head.contains("Content-Type: application/json"));
+    let body: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(body["event"], "double_spend");
+    assert_eq!(body["txid"], payment.hash().to_hex());
+    assert_eq!(body["conflicting"], refund.hash().to_hex());
+    assert_eq!(body["outputs"][0], output.hash().to_hex());
+    assert_eq!(body["outcome"], "Replaced");
+}
+
+#[tokio::test]
+async fn only_http_webhooks() {
+    for url in ["https://example.com/hook", "example.com", "http:///hook"] {
+        assert!(Node::builder().port(0).webhook(url).spawn().await.is_err());
+    }
+}
@@ -0,0 +1,107 @@
+//! A peer that doesn't play by the rules, for checking the node refuses what it sends, bans it
+//! when it should and keeps serving everyone else. It talks to the node over an in-memory
+//! connection under an address of its choosing, so bans hit that address and not every test
+//! connecting from localhost.
+
+use btclib::{
+    crypto::PrivateKey,
+    network::{Encoding, Envelope, Message},
+};
+use node::NodeHandle;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::time::timeout;
+
+/// how long the node gets to answer before it counts as not answering
+const PATIENCE: Duration = Duration::from_secs(5);
+/// how long the node gets to hang up, nothing coming in that time is taken as the connection
+/// staying open
+const HANG_UP: Duration = Duration::from_millis(200);
+
+pub struct Adversary {
+    stream: DuplexStream,
+    pub addr: String,
+}
+
+impl Adversary {
+    /// connect to `node` as if from `addr`, e.g. "10.6.6.6:4242"
+    pub fn connect(node: &NodeHandle, addr: &str) -> Self {
+        Adversary {
+            stream: node.connect(addr),
+            addr: addr.to_string(),
+        }
+    }
+
+    /// write `bytes` as they are, length prefix and all, ignoring a connection the node closed
+    pub async fn send_bytes(&mut self, bytes: &[u8]) {
+        let _ = self.stream.write_all(bytes).await;
+    }
+
+    /// a frame saying it's `len` bytes long, followed by `payload`, whatever its length
+    pub async fn send_lying_frame(&mut self, len: u64, payload: &[u8]) {
+        let mut frame = len.to_be_bytes().to_vec();
+        frame.extend_from_slice(payload);
+        self.send_bytes(&frame).await;
+    }
+
+    /// `payload` as a message of `kind`, whatever it holds
+    pub async fn send_envelope(&mut self, kind: u16, payload: &[u8]) {
+        let envelope = Envelope {
+            kind,
+            compression: None,
+            payload: payload.to_vec(),
+        }
+        .to_bytes();
+        self.send_lying_frame(envelope.len() as u64, &envelope)
+            .await;
+    }
+
+    pub async fn send(&mut self, message: &Message) {
+        self.send_bytes(&message.to_frame().unwrap()).await;
+    }
+
+    /// Close the sending half, e.g. halfway through a frame
+    pub async fn stop_sending(&mut self) {
+        let _ = self.stream.shutdown().await;
+    }
+
+    /// the next message, None once the node hung up. Panics if nothing comes for long
+    pub async fn receive(&mut self) -> Option<Message> {
+        timeout(PATIENCE, self.read())
+            .await
+            .expect("the node neither answered nor hung up")
+    }
+
+    pub async fn ask(&mut self, message: &Message) -> Option<Message> {
+        self.send(message).await;
+        self.receive().await
+    }
+
+    /// whether the node closed the connection, reading whatever it still sent before
+    pub async fn is_hung_up(&mut self) -> bool {
+        loop {
+            match timeout(HANG_UP, self.read()).await {
+                Ok(None) => return true,
+                Ok(Some(_)) => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    async fn read(&mut self) -> Option<Message> {
+        let len = self.stream.read_u64().await.ok()?;
+        let mut frame = vec![0; usize::try_from(len).ok()?];
+        self.stream.read_exact(&mut frame).await.ok()?;
+        Envelope::parse(&frame).ok()?.open(Encoding::Cbor).ok()
+    }
+}
+
+/// Whether `node` still answers a well-behaved peer
+pub async fn is_serving(node: &NodeHandle) -> bool {
+    let key = PrivateKey::new_key().public_key();
+    let mut peer = Adversary::connect(node, "192.0.2.1:8333");
+    matches!(
+        timeout(PATIENCE, peer.ask(&Message::FetchUTXOs(key))).await,
+        Ok(Some(Message::UTXOs(_)))
+    )
+}
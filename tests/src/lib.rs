@@ -0,0 +1,130 @@
+//! End to end tests of a node, a miner and a wallet together, in `tests/`. The miner and the
+//! wallet's core are the ones the binaries run, set up here the way the tests need them. They
+//! connect to the node over TCP like any client, so everything goes through the node's listener,
+//! handshake and handlers. `Client` is for asking the node what the tests check.
+
+pub mod adversary;
+
+use anyhow::{Result, anyhow};
+use btclib::{
+    chain_params::ChainParams,
+    crypto::{PrivateKey, PublicKey},
+    finality::Finality,
+    network::{Capabilities, Encoding, Message, NodeStatus, PROTOCOL_VERSION},
+    types::{Amount, Block, Payout},
+    util::Saveable,
+};
+use miner::Miner;
+use node::NodeHandle;
+use tokio::net::TcpStream;
+use uuid::Uuid;
+use wallet::core::{Config, Core, FeeConfig, FeeType, Key};
+use wallet::util::DisplayConfig;
+
+/// A connection to a node, after the handshake
+pub struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    pub async fn connect(node: &NodeHandle, capabilities: Capabilities) -> Result<Self> {
+        let mut stream = TcpStream::connect(("127.0.0.1", node.local_addr().port())).await?;
+        let hello = Message::Hello {
+            version: PROTOCOL_VERSION,
+            encodings: vec![Encoding::Cbor],
+            time: None,
+            nonce: None,
+            capabilities,
+        };
+        hello.send_async(&mut stream).await?;
+        match Message::receive_async(&mut stream).await? {
+            Message::Welcome { .. } => Ok(Client { stream }),
+            message => Err(anyhow!("expected a welcome, got {message:?}")),
+        }
+    }
+
+    /// send `message` and read the reply, turning an `Error` into one
+    pub async fn ask(&mut self, message: Message) -> Result<Message> {
+        message.send_async(&mut self.stream).await?;
+        match Message::receive_async(&mut self.stream).await? {
+            Message::Error { code, reason } => Err(anyhow!("{code:?}: {reason}")),
+            reply => Ok(reply),
+        }
+    }
+
+    pub async fn status(&mut self) -> Result<NodeStatus> {
+        match self.ask(Message::GetStatus).await? {
+            Message::Status(status) => Ok(*status),
+            reply => Err(anyhow!("expected the status, got {reply:?}")),
+        }
+    }
+}
+
+/// where a miner or wallet on this machine reaches `node`, it listens on every interface
+fn address(node: &NodeHandle) -> String {
+    format!("127.0.0.1:{}", node.local_addr().port())
+}
+
+/// A miner paying `pays`, its ledger in a file of its own. Its mining thread is running, see
+/// `mine` for having it find a block
+pub async fn miner(node: &NodeHandle, pays: PublicKey) -> Result<Miner> {
+    let ledger = std::env::temp_dir().join(format!("integration-ledger-{}.cbor", Uuid::new_v4()));
+    let miner = Miner::new(
+        address(node),
+        Payout::single(pays),
+        ChainParams::regtest(),
+        None,
+        true,
+        &[],
+        ledger,
+    )
+    .await?;
+    miner.spawn_mining_thread();
+    Ok(miner)
+}
+
+/// Have `miner` fetch a template, mine it and submit it, the way its loop does, returning the
+/// block. Only for regtest nodes, anything else would take too long
+pub async fn mine(miner: &Miner) -> Result<Block> {
+    miner.fetch_template().await?;
+    let block = miner.found().await?;
+    miner.submit_block(block.clone()).await?;
+    Ok(block)
+}
+
+/// A wallet with one new key, paying a fixed `fee` per transaction. Its config, keys and the
+/// files it keeps go in a directory of its own. Returns the key to pay it at as well
+pub async fn wallet(node: &NodeHandle, fee: Amount) -> Result<(Core, PublicKey)> {
+    let dir = std::env::temp_dir().join(format!("integration-wallet-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+    let private = PrivateKey::new_key();
+    let key = Key {
+        public: dir.join("key_pub.pem"),
+        private: dir.join("key_priv.cbor"),
+        retired: false,
+        label: None,
+        handed_out: None,
+    };
+    private.public_key().save_to_file(&key.public)?;
+    private.save_to_file(&key.private)?;
+    let config = Config {
+        keys: vec![key],
+        contacts: vec![],
+        default_node: address(node),
+        fee_config: FeeConfig {
+            fee_type: FeeType::Fixed,
+            value: fee.to_sat() as f64,
+        },
+        invoices: dir.join("invoices.cbor"),
+        schedules: vec![],
+        schedule_state: dir.join("schedules.cbor"),
+        display: DisplayConfig::default(),
+        price: None,
+        frozen: dir.join("frozen.cbor"),
+        trusted_nodes: vec![],
+        finality: Finality::default(),
+    };
+    let path = dir.join("wallet_config.toml");
+    std::fs::write(&path, toml::to_string_pretty(&config)?)?;
+    Ok((Core::load_config(path).await?, private.public_key()))
+}
@@ -0,0 +1,106 @@
+use btclib::{finality::Finality, types::Amount};
+use integration::{mine, miner, wallet};
+use node::{Node, NodeHandle};
+use wallet::core::BroadcastStatus;
+
+/// confirmations before a block counts as final on the test node
+const CONFIRMATIONS: u64 = 3;
+
+async fn spawn() -> NodeHandle {
+    Node::builder()
+        .port(0)
+        .regtest(true)
+        .txindex(true)
+        .addrindex(true)
+        .finality(Finality::Confirmations(CONFIRMATIONS))
+        .spawn()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn mining_paying_and_looking_it_up() {
+    let node = spawn().await;
+    let fee = Amount::from_sat(1000);
+    let (alice, alice_key) = wallet(&node, fee).await.unwrap();
+    let (bob, bob_key) = wallet(&node, fee).await.unwrap();
+    let miner = miner(&node, alice_key).await.unwrap();
+
+    // there's no coinbase maturity, waiting for the block to be final stands in for it
+    let first = mine(&miner).await.unwrap();
+    let reward = first.transactions[0].outputs[0].value;
+    alice.fetch_utxos().await.unwrap();
+    assert_eq!(alice.get_balance(), reward);
+    while alice.fetch_status().await.unwrap().finalized.is_none() {
+        mine(&miner).await.unwrap();
+    }
+    let status = alice.fetch_status().await.unwrap();
+    assert_eq!(status.height, CONFIRMATIONS);
+    alice.fetch_utxos().await.unwrap();
+    let mined = alice.get_balance();
+    assert_eq!(mined, reward.checked_mul(CONFIRMATIONS).unwrap());
+
+    // alice pays bob, it's in the mempool until mined
+    let payment = alice
+        .create_transaction(&bob_key, Amount::ONE_BTC, None)
+        .unwrap();
+    alice.send_transaction(payment.clone()).await.unwrap();
+    alice.check_broadcasts().await.unwrap();
+    assert!(matches!(
+        alice.broadcasts()[0].status,
+        BroadcastStatus::Waiting { .. }
+    ));
+    bob.fetch_utxos().await.unwrap();
+    assert_eq!(bob.get_balance(), Amount::ZERO);
+    assert!(bob.fetch_history().await.unwrap().is_empty());
+    alice.fetch_utxos().await.unwrap();
+    assert!(alice.spendable_balance() < mined);
+    assert!(alice.create_transaction(&bob_key, mined, None).is_err());
+
+    let block = mine(&miner).await.unwrap();
+    assert!(
+        block
+            .transactions
+            .iter()
+            .any(|tx| tx.hash() == payment.hash())
+    );
+    alice.check_broadcasts().await.unwrap();
+    assert_eq!(
+        alice.broadcasts()[0].status,
+        BroadcastStatus::Confirmed {
+            height: Some(CONFIRMATIONS)
+        }
+    );
+    bob.fetch_utxos().await.unwrap();
+    assert_eq!(bob.get_balance(), Amount::ONE_BTC);
+
+    // both see it in their history
+    let history = bob.fetch_history().await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].txid, payment.hash());
+    assert_eq!(history[0].height, CONFIRMATIONS);
+    assert_eq!(history[0].received, Amount::ONE_BTC);
+    let history = alice.fetch_history().await.unwrap();
+    let paid = history.last().unwrap();
+    assert_eq!(paid.txid, payment.hash());
+    assert!(paid.sent > paid.received);
+    let fees = block.transactions[0].outputs[0]
+        .value
+        .checked_sub(reward)
+        .unwrap();
+    assert_eq!(fees, fee);
+    alice.fetch_utxos().await.unwrap();
+    assert_eq!(
+        alice.get_balance(),
+        mined
+            .checked_add(block.transactions[0].outputs[0].value)
+            .and_then(|total| total.checked_sub(Amount::ONE_BTC))
+            .and_then(|total| total.checked_sub(fee))
+            .unwrap()
+    );
+
+    // the miner kept every block it found
+    let ledger = miner.ledger_summary().await;
+    let found = format!("{} blocks found, 0 orphaned", CONFIRMATIONS + 1);
+    assert!(ledger.starts_with(&found), "{ledger}");
+}
@@ -0,0 +1,192 @@
+use btclib::{
+    crypto::PrivateKey,
+    network::{Encoding, ErrorCode, KNOWN_KINDS, MAX_MESSAGE_SIZE, Message, PROTOCOL_VERSION},
+    types::{Amount, Block, BlockBuilder},
+};
+use integration::adversary::{Adversary, is_serving};
+use node::{Node, NodeHandle};
+
+async fn spawn() -> NodeHandle {
+    Node::builder().port(0).regtest(true).spawn().await.unwrap()
+}
+
+fn is_unsupported(reply: Option<Message>) -> bool {
+    matches!(
+        reply,
+        Some(Message::Error {
+            code: ErrorCode::Unsupported,
+            ..
+        })
+    )
+}
+
+/// a block paying more than the reward, on top of the node's tip
+async fn invalid_block(node: &NodeHandle) -> Block {
+    let blockchain = node.blockchain().await;
+    let reward = blockchain.calculate_block_reward();
+    let mut block = BlockBuilder::on_top_of(&blockchain)
+        .coinbase_to(
+            PrivateKey::new_key().public_key(),
+            reward.checked_add(Amount::ONE_SAT).unwrap(),
+        )
+        .finalize()
+        .unwrap();
+    block
+        .header
+        .mine_with_target_override(btclib::REGTEST_TARGET);
+    block
+}
+
+#[tokio::test]
+async fn lying_about_lengths() {
+    let node = spawn().await;
+
+    let mut too_big = Adversary::connect(&node, "10.6.6.1:1");
+    too_big
+        .send_lying_frame(MAX_MESSAGE_SIZE as u64 + 1, &[0; 64])
+        .await;
+    assert!(too_big.is_hung_up().await);
+
+    // the most it may announce, but it never sends it
+    let mut short = Adversary::connect(&node, "10.6.6.2:1");
+    short
+        .send_lying_frame(MAX_MESSAGE_SIZE as u64, &[0; 64])
+        .await;
+    assert!(!short.is_hung_up().await);
+    short.stop_sending().await;
+    assert!(short.is_hung_up().await);
+
+    // too short to say what it holds, answered like any message the node can't make out
+    let mut empty = Adversary::connect(&node, "10.6.6.3:1");
+    empty.send_lying_frame(0, &[]).await;
+    assert!(is_unsupported(empty.receive().await));
+    empty.send_lying_frame(1, &[0]).await;
+    assert!(is_unsupported(empty.receive().await));
+    assert!(matches!(
+        empty.ask(&Message::AskDifference(0)).await,
+        Some(Message::Difference(0))
+    ));
+    assert!(is_serving(&node).await);
+}
+
+#[tokio::test]
+async fn garbage_messages() {
+    let node = spawn().await;
+    let mut peer = Adversary::connect(&node, "10.6.6.4:1");
+    let difference = Message::AskDifference(0).encode().unwrap();
+
+    // not CBOR, from a newer version, another message than it says, compressed with nothing known
+    peer.send_envelope(Message::GetStatus.kind(), &[0xff; 32])
+        .await;
+    assert!(is_unsupported(peer.receive().await));
+    peer.send_envelope(KNOWN_KINDS + 7, &[0xff; 32]).await;
+    assert!(is_unsupported(peer.receive().await));
+    peer.send_envelope(Message::GetStatus.kind(), &difference)
+        .await;
+    assert!(is_unsupported(peer.receive().await));
+    peer.send_envelope(0xc000 | Message::AskDifference(0).kind(), &difference)
+        .await;
+    assert!(is_unsupported(peer.receive().await));
+
+    assert!(!peer.is_hung_up().await);
+    assert!(is_serving(&node).await);
+}
+
+#[tokio::test]
+async fn invalid_blocks_get_a_peer_banned() {
+    let node = spawn().await;
+    let mut peer = Adversary::connect(&node, "10.6.6.5:1");
+    let block = invalid_block(&node).await;
+
+    // once is forgiven
+    peer.send(&Message::NewBlock(block.clone())).await;
+    assert!(!peer.is_hung_up().await);
+    peer.send(&Message::NewBlock(block)).await;
+    assert!(peer.is_hung_up().await);
+    assert_eq!(node.blockchain().await.block_height(), 0);
+
+    // from any port
+    let mut again = Adversary::connect(&node, "10.6.6.5:2");
+    assert!(again.is_hung_up().await);
+    assert!(is_serving(&node).await);
+}
+
+#[tokio::test]
+async fn huge_requests() {
+    let node = spawn().await;
+    let key = PrivateKey::new_key().public_key();
+    let mut peer = Adversary::connect(&node, "10.6.6.6:1");
+
+    let Some(Message::GeneratedBlocks(hashes)) = peer
+        .ask(&Message::GenerateBlocks(key.clone(), u32::MAX))
+        .await
+    else {
+        panic!("expected the generated blocks");
+    };
+    assert_eq!(hashes.len(), btclib::MAX_GENERATED_BLOCKS as usize);
+    let Some(Message::Headers(headers)) = peer.ask(&Message::FetchHeaderRange(0, u32::MAX)).await
+    else {
+        panic!("expected headers");
+    };
+    assert!(!headers.is_empty());
+    assert!(headers.len() <= btclib::MAX_HEADER_RANGE as usize);
+
+    // a flood of requests it never reads the replies to only holds up its own connection
+    let flood = Message::FetchUTXOs(key).to_frame().unwrap().repeat(5000);
+    let mut flooding = Adversary::connect(&node, "10.6.6.7:1");
+    tokio::spawn(async move { flooding.send_bytes(&flood).await });
+    assert!(is_serving(&node).await);
+    assert!(!peer.is_hung_up().await);
+}
+
+#[tokio::test]
+async fn out_of_order_handshakes() {
+    let node = spawn().await;
+    let hello = Message::Hello {
+        version: PROTOCOL_VERSION,
+        encodings: vec![Encoding::Cbor],
+        time: None,
+        nonce: None,
+        capabilities: Default::default(),
+    };
+
+    // answering a hello nobody sent ends the conversation
+    let mut welcomer = Adversary::connect(&node, "10.6.6.8:1");
+    let policy = node.blockchain().await.mempool_policy().clone();
+    welcomer
+        .send(&Message::Welcome {
+            version: PROTOCOL_VERSION,
+            encoding: Encoding::Cbor,
+            policy,
+            time: None,
+            identity: None,
+            capabilities: Default::default(),
+        })
+        .await;
+    assert!(welcomer.is_hung_up().await);
+
+    // a hello late, twice or from a version that never was is answered all the same
+    let mut late = Adversary::connect(&node, "10.6.6.9:1");
+    assert!(matches!(
+        late.ask(&Message::AskDifference(0)).await,
+        Some(Message::Difference(0))
+    ));
+    for _ in 0..2 {
+        assert!(matches!(
+            late.ask(&hello).await,
+            Some(Message::Welcome { .. })
+        ));
+    }
+    let nonsense = Message::Hello {
+        version: 0,
+        encodings: vec![],
+        time: None,
+        nonce: None,
+        capabilities: Default::default(),
+    };
+    let Some(Message::Welcome { encoding, .. }) = late.ask(&nonsense).await else {
+        panic!("expected a welcome");
+    };
+    assert_eq!(encoding, Encoding::Cbor);
+    assert!(is_serving(&node).await);
+}
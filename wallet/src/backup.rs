@@ -0,0 +1,276 @@
+//! Cold backups: the config, every key, contact and state file it points at, and the history the node
+//! had for the keys, in one file sealed with a passphrase like encrypted keys are, see
+//! `btclib::crypto::keyfile`. Restoring puts the files back where they were, relative to where the
+//! wallet runs, and the wallet then rescans the chain.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use btclib::crypto::keyfile;
+use btclib::types::AddressActivity;
+use serde::{Deserialize, Serialize};
+
+use crate::core::Config;
+
+/// what a backup file starts with
+pub const BACKUP_MAGIC: &[u8] = b"BTCRS-WALLET-BACKUP";
+/// environment variable read for the backup passphrase before prompting
+pub const BACKUP_PASSPHRASE_ENV: &str = "BTCRS_BACKUP_PASSPHRASE";
+
+#[derive(Serialize, Deserialize)]
+struct Backup {
+    config_path: PathBuf,
+    /// the config first, then the files it points at
+    files: Vec<(PathBuf, Vec<u8>)>,
+    history: Vec<AddressActivity>,
+}
+
+/// What went into a backup or came out of one
+#[derive(Debug)]
+pub struct Summary {
+    pub config_path: PathBuf,
+    pub files: usize,
+    pub history: usize,
+}
+
+fn passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(BACKUP_PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("Backup passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(anyhow::anyhow!("The passphrase can't be empty"));
+    }
+    if confirm && rpassword::prompt_password("Once more: ")? != passphrase {
+        return Err(anyhow::anyhow!("Passphrases don't match"));
+    }
+    Ok(passphrase)
+}
+
+/// Seal the wallet `config_path` is the config of into `output`, with `history` as the node had
+/// it. Contacts whose key file is missing are left out, the wallet's own keys can't be
+pub fn backup(config_path: &Path, history: Vec<AddressActivity>, output: &Path) -> Result<Summary> {
+    seal(config_path, history, output, || passphrase(true))
+}
+
+/// `backup`, asking `passphrase` for the passphrase once the files are read
+fn seal(
+    config_path: &Path,
+    history: Vec<AddressActivity>,
+    output: &Path,
+    passphrase: impl FnOnce() -> Result<String>,
+) -> Result<Summary> {
+    let text = fs::read(config_path)
+        .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
+    let config: Config = toml::from_str(std::str::from_utf8(&text)?)?;
+    let mut files = vec![(config_path.to_path_buf(), text)];
+    for key in &config.keys {
+        for path in [&key.public, &key.private] {
+            let data = fs::read(path)
+                .with_context(|| format!("Failed to read key file: {}", path.display()))?;
+            files.push((path.clone(), data));
+        }
+    }
+    for path in [&config.invoices, &config.schedule_state, &config.frozen] {
+        if path.exists() {
+            files.push((path.clone(), fs::read(path)?));
+        }
+    }
+    for contact in &config.contacts {
+        match fs::read(&contact.key) {
+            Ok(data) => files.push((contact.key.clone(), data)),
+            Err(e) => println!("Leaving out contact {}: {e}", contact.name),
+        }
+    }
+
+    let summary = Summary {
+        config_path: config_path.to_path_buf(),
+        files: files.len(),
+        history: history.len(),
+    };
+    let backup = Backup {
+        config_path: config_path.to_path_buf(),
+        files,
+        history,
+    };
+    let mut plaintext = vec![];
+    ciborium::into_writer(&backup, &mut plaintext)?;
+    fs::write(
+        output,
+        keyfile::seal(BACKUP_MAGIC, &plaintext, &passphrase()?),
+    )?;
+    Ok(summary)
+}
+
+/// Write the files of the backup in `input` back. Files that are there already with other
+/// contents are only overwritten with `force`
+pub fn restore(input: &Path, force: bool) -> Result<Summary> {
+    open(input, force, || passphrase(false))
+}
+
+/// `restore`, asking `passphrase` for the passphrase once the file looks like a backup
+fn open(input: &Path, force: bool, passphrase: impl FnOnce() -> Result<String>) -> Result<Summary> {
+    let data =
+        fs::read(input).with_context(|| format!("Failed to read backup: {}", input.display()))?;
+    if !data.starts_with(BACKUP_MAGIC) {
+        return Err(anyhow::anyhow!(
+            "{} is not a wallet backup",
+            input.display()
+        ));
+    }
+    let plaintext = keyfile::open(BACKUP_MAGIC, &data, &passphrase()?)
+        .ok_or_else(|| anyhow::anyhow!("Failed to decrypt the backup, wrong passphrase?"))?;
+    let backup: Backup = ciborium::from_reader(plaintext.as_slice())?;
+
+    if !force {
+        for (path, data) in &backup.files {
+            if fs::read(path).is_ok_and(|existing| existing != *data) {
+                return Err(anyhow::anyhow!(
+                    "{} is there already and differs from the backup, restore with --force to \
+                     overwrite it",
+                    path.display()
+                ));
+            }
+        }
+    }
+    for (path, data) in &backup.files {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+    }
+    Ok(Summary {
+        config_path: backup.config_path,
+        files: backup.files.len(),
+        history: backup.history.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use uuid::Uuid;
+
+    /// a wallet in a directory of its own: a key, a contact and the invoices, the config last
+    fn wallet() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("wallet-backup-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+        fs::write(path("key.pub.pem"), "public").unwrap();
+        fs::write(path("key.priv.cbor"), "private").unwrap();
+        fs::write(path("alice.pub.pem"), "alice").unwrap();
+        fs::write(path("invoices.cbor"), "invoices").unwrap();
+        let config = format!(
+            r#"default_node = "127.0.0.1:9000"
+invoices = "{}"
+schedule_state = "{}"
+frozen = "{}"
+keys = [{{ public = "{}", private = "{}" }}]
+contacts = [{{ name = "alice", key = "{}" }}]
+
+[fee_config]
+fee_type = "Fixed"
+value = 1000.0
+"#,
+            path("invoices.cbor"),
+            path("schedule.cbor"),
+            path("frozen.cbor"),
+            path("key.pub.pem"),
+            path("key.priv.cbor"),
+            path("alice.pub.pem"),
+        );
+        fs::write(path("wallet_config.toml"), config).unwrap();
+        let config_path = dir.join("wallet_config.toml");
+        (dir, config_path)
+    }
+
+    /// every file in `dir` with its contents, by name
+    fn contents(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let data = fs::read(&path).unwrap();
+                (path, data)
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn with(passphrase: &str) -> impl FnOnce() -> Result<String> {
+        let passphrase = passphrase.to_string();
+        move || Ok(passphrase)
+    }
+
+    /// a backup of a new wallet, with the wallet's files and the file it was sealed into
+    fn sealed() -> (PathBuf, Vec<(PathBuf, Vec<u8>)>, PathBuf) {
+        let (dir, config_path) = wallet();
+        let files = contents(&dir);
+        let output = dir.with_extension("backup");
+        let summary = seal(&config_path, vec![], &output, with("hunter2")).unwrap();
+        assert_eq!(summary.files, 5);
+        (dir, files, output)
+    }
+
+    #[test]
+    fn restoring_puts_the_wallet_back() {
+        let (dir, files, output) = sealed();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let summary = open(&output, false, with("hunter2")).unwrap();
+        assert_eq!(summary.config_path, dir.join("wallet_config.toml"));
+        assert_eq!(summary.files, 5);
+        assert_eq!(contents(&dir), files);
+
+        // the same files again are fine, changed ones only go with force
+        open(&output, false, with("hunter2")).unwrap();
+        fs::write(dir.join("invoices.cbor"), "newer invoices").unwrap();
+        assert!(open(&output, false, with("hunter2")).is_err());
+        assert_eq!(
+            fs::read(dir.join("invoices.cbor")).unwrap(),
+            b"newer invoices"
+        );
+        open(&output, true, with("hunter2")).unwrap();
+        assert_eq!(contents(&dir), files);
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn a_wrong_passphrase_is_refused() {
+        let (dir, _, output) = sealed();
+        fs::remove_dir_all(&dir).unwrap();
+        let e = open(&output, false, with("hunter3")).unwrap_err();
+        assert!(e.to_string().contains("wrong passphrase"), "{e}");
+        assert!(!dir.exists());
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn damaged_backups_are_refused() {
+        let (dir, _, output) = sealed();
+        fs::remove_dir_all(&dir).unwrap();
+        let data = fs::read(&output).unwrap();
+
+        let mut tampered = data.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        fs::write(&output, &tampered).unwrap();
+        assert!(open(&output, false, with("hunter2")).is_err());
+
+        fs::write(&output, &data[..data.len() / 2]).unwrap();
+        assert!(open(&output, false, with("hunter2")).is_err());
+
+        fs::write(&output, &data[1..]).unwrap();
+        let e = open(&output, false, with("hunter2")).unwrap_err();
+        assert!(e.to_string().contains("is not a wallet backup"), "{e}");
+        assert!(!dir.exists());
+        fs::remove_file(output).unwrap();
+    }
+}
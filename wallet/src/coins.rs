@@ -0,0 +1,95 @@
+//! Coin control: UTXOs frozen out of coin selection, e.g. to keep a payment apart from the rest,
+//! and picking the ones a payment spends by hand. Frozen outputs are kept by hash in the file the
+//! config's `frozen` points at, and don't count towards the spendable balance.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use btclib::sha256::Hash;
+use btclib::types::TransactionOutput;
+
+/// An unspent output of one of the wallet's keys
+#[derive(Debug, Clone)]
+pub struct Coin {
+    pub output: TransactionOutput,
+    /// a transaction in the mempool spends it already
+    pub pending: bool,
+    pub frozen: bool,
+    /// paid to a retired key, the wallet has no private key to spend it with
+    pub retired: bool,
+}
+
+impl Coin {
+    /// whether coin selection may pick it
+    pub fn is_spendable(&self) -> bool {
+        !self.pending && !self.frozen && !self.retired
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<Hash>> {
+    match fs::read(path) {
+        Ok(data) => Ok(ciborium::from_reader(data.as_slice())?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save(path: &Path, frozen: &[Hash]) -> Result<()> {
+    let mut data = vec![];
+    ciborium::into_writer(frozen, &mut data)?;
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use btclib::crypto::PrivateKey;
+    use btclib::types::Amount;
+    use uuid::Uuid;
+
+    #[test]
+    fn the_frozen_list_is_kept_in_its_file() {
+        let path = std::env::temp_dir().join(format!("wallet-frozen-{}.cbor", Uuid::new_v4()));
+        assert!(load(&path).unwrap().is_empty());
+        let frozen = [Hash::of_bytes(b"one"), Hash::of_bytes(b"two")];
+        save(&path, &frozen).unwrap();
+        assert_eq!(load(&path).unwrap(), frozen);
+        save(&path, &frozen[1..]).unwrap();
+        assert_eq!(load(&path).unwrap(), frozen[1..]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn only_coins_held_back_by_nothing_are_spendable() {
+        let coin = Coin {
+            output: TransactionOutput {
+                value: Amount::ONE_BTC,
+                unique_id: Uuid::new_v4(),
+                pubkey: PrivateKey::new_key().public_key(),
+            },
+            pending: false,
+            frozen: false,
+            retired: false,
+        };
+        assert!(coin.is_spendable());
+        for held_back in [
+            Coin {
+                pending: true,
+                ..coin.clone()
+            },
+            Coin {
+                frozen: true,
+                ..coin.clone()
+            },
+            Coin {
+                retired: true,
+                ..coin.clone()
+            },
+        ] {
+            assert!(!held_back.is_spendable());
+        }
+    }
+}
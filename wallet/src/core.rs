@@ -1,33 +1,70 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use tracing::*;
 
 use anyhow::{Context, Result};
 use btclib::crypto::{PrivateKey, PublicKey, Signature};
-use btclib::network::Message;
-use btclib::types::{Transaction, TransactionInput, TransactionOutput};
+use btclib::finality::{ChainTip, Finality};
+use btclib::network::{
+    BloomFilter, Capabilities, Encoding, ErrorCode, Message, NodeStatus, PROTOCOL_VERSION,
+    Rejection, check_identity, new_nonce,
+};
+use btclib::sha256::Hash;
+use btclib::types::{
+    AddressActivity, Amount, Block, EvictionReason, Invoice, MempoolAcceptance, MempoolPolicy,
+    MempoolTxInfo, Transaction, TransactionBuilder, TransactionOutput, UtxoDiff,
+};
 use btclib::util::Saveable;
 
-use crossbeam_skiplist::SkipMap;
+use chrono::{DateTime, Utc};
+use crossbeam_skiplist::{SkipMap, SkipSet};
 use kanal::{AsyncSender, Sender};
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::coins::{self, Coin};
+use crate::invoices::{self, Issued};
+use crate::price::{Price, PriceConfig, PriceProvider};
+use crate::schedule::{self, MAX_INTERVAL_SECS, RunOutcome, ScheduleState, ScheduledPayment};
+use crate::util::{self, DisplayConfig};
+
+/// sync points kept per key to roll back to when the chain reorganizes
+const SYNC_POINTS: usize = 16;
+
+/// blocks' worth of waiting after which a sent transaction counts as stuck
+const STUCK_BLOCKS: u64 = 6;
+
+/// share of the node's mempool expiry left when a sent transaction gets warned about
+const EXPIRY_WARNING: f64 = 0.25;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Key {
-    public: PathBuf,
-    private: PathBuf,
+    pub public: PathBuf,
+    pub private: PathBuf,
+    /// rotated out: only watched for payments still coming in, its private key isn't loaded
+    #[serde(default)]
+    pub retired: bool,
+    /// what it was handed out for, see `receive::hand_out`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// when `receive` handed it out to be paid once, `None` for the keys change goes to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handed_out: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone)]
 struct LoadedKey {
     public: PublicKey,
-    private: PrivateKey,
+    /// `None` for retired keys
+    private: Option<PrivateKey>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -70,12 +107,139 @@ pub struct Config {
     pub contacts: Vec<Recipient>,
     pub default_node: String,
     pub fee_config: FeeConfig,
+    /// where the invoices the wallet made are kept
+    #[serde(default = "default_invoices")]
+    pub invoices: PathBuf,
+    /// standing orders, see `run_schedules`
+    #[serde(default)]
+    pub schedules: Vec<ScheduledPayment>,
+    /// where what became of the standing orders is kept
+    #[serde(default = "default_schedule_state")]
+    pub schedule_state: PathBuf,
+    /// how amounts are shown
+    #[serde(default)]
+    pub display: DisplayConfig,
+    /// where to get the price of a bitcoin from to show fiat values, none if left out
+    #[serde(default)]
+    pub price: Option<PriceConfig>,
+    /// where the outputs kept out of coin selection are listed
+    #[serde(default = "default_frozen")]
+    pub frozen: PathBuf,
+    /// identities of the nodes the wallet talks to, as `node --status` shows them. A node that
+    /// can't prove it's one of them is refused, with none any node goes
+    #[serde(default)]
+    pub trusted_nodes: Vec<String>,
+    /// when transactions count as final, see `btclib::finality`
+    #[serde(default)]
+    pub finality: Finality,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        toml::from_str(&fs::read_to_string(path)?)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))
+    }
+}
+
+fn default_invoices() -> PathBuf {
+    PathBuf::from("invoices.cbor")
+}
+
+fn default_schedule_state() -> PathBuf {
+    PathBuf::from("schedules.cbor")
+}
+
+fn default_frozen() -> PathBuf {
+    PathBuf::from("frozen.cbor")
+}
+
+/// A key's UTXOs as they were with `height` blocks on the chain, the last one hashing to `tip`
+#[derive(Debug, Clone)]
+struct SyncPoint {
+    height: u64,
+    tip: Hash,
+    utxos: Vec<(TransactionOutput, bool)>,
+}
+
+/// Where a transaction the wallet sent stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BroadcastStatus {
+    /// in the node's mempool, `position` in line once it was checked
+    Waiting { position: Option<usize> },
+    /// in a block, at `height` if the node has a txindex to tell
+    Confirmed { height: Option<u64> },
+    /// out of the mempool and the node wouldn't take it again
+    Dropped { reason: String },
+}
+
+impl fmt::Display for BroadcastStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BroadcastStatus::Waiting { position: None } => write!(f, "waiting"),
+            BroadcastStatus::Waiting {
+                position: Some(position),
+            } => write!(f, "waiting, #{} in line", position + 1),
+            BroadcastStatus::Confirmed { height: None } => write!(f, "confirmed"),
+            BroadcastStatus::Confirmed {
+                height: Some(height),
+            } => write!(f, "confirmed in block {height}"),
+            BroadcastStatus::Dropped { reason } => write!(f, "dropped: {reason}"),
+        }
+    }
+}
+
+/// A transaction the wallet sent, followed until it's mined
+#[derive(Debug, Clone)]
+pub struct Broadcast {
+    pub transaction: Transaction,
+    /// when it was first sent
+    pub sent: Instant,
+    pub status: BroadcastStatus,
+    /// how many times it was sent again after dropping out of the mempool
+    pub rebroadcasts: u32,
+    /// when the node drops it from its mempool unless it gets mined first, as of the last check
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Broadcast {
+    fn new(transaction: Transaction) -> Self {
+        Broadcast {
+            transaction,
+            sent: Instant::now(),
+            status: BroadcastStatus::Waiting { position: None },
+            rebroadcasts: 0,
+            expires: None,
+        }
+    }
+
+    /// how long until the node drops it, if it's still waiting
+    pub fn expires_in(&self) -> Option<Duration> {
+        let expires = self.expires?;
+        matches!(self.status, BroadcastStatus::Waiting { .. })
+            .then(|| (expires - Utc::now()).to_std().unwrap_or_default())
+    }
+
+    /// still waiting with less than `EXPIRY_WARNING` of the node's `max_transaction_age` left
+    pub fn is_expiring(&self, policy: &MempoolPolicy) -> bool {
+        self.expires_in().is_some_and(|left| {
+            (left.as_secs() as f64) < policy.max_transaction_age as f64 * EXPIRY_WARNING
+        })
+    }
+
+    /// still waiting after `STUCK_BLOCKS` blocks should have come by
+    pub fn is_stuck(&self) -> bool {
+        matches!(self.status, BroadcastStatus::Waiting { .. })
+            && self.sent.elapsed().as_secs() > STUCK_BLOCKS * btclib::IDEAL_BLOCK_TIME
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct UtxoStore {
     keys: Vec<LoadedKey>,
     utxos: Arc<SkipMap<PublicKey, Vec<(TransactionOutput, bool)>>>,
+    /// the latest blocks each key's UTXOs were synced to, oldest first. The last one is what
+    /// `utxos` is up to date with
+    sync_points: Arc<SkipMap<PublicKey, VecDeque<SyncPoint>>>,
 }
 
 impl UtxoStore {
@@ -83,12 +247,117 @@ impl UtxoStore {
         UtxoStore {
             keys: Vec::new(),
             utxos: Arc::new(SkipMap::new()),
+            sync_points: Arc::new(SkipMap::new()),
         }
     }
 
     fn add_key(&mut self, key: LoadedKey) {
         self.keys.push(key);
     }
+
+    /// the private key for `public`, if it's one of the keys and not retired
+    fn private_key(&self, public: &PublicKey) -> Option<&PrivateKey> {
+        self.keys
+            .iter()
+            .find(|key| key.public == *public)?
+            .private
+            .as_ref()
+    }
+
+    /// where change and payments to the wallet go, the first key that isn't retired
+    fn receiving_key(&self) -> Option<&LoadedKey> {
+        self.keys.iter().find(|key| key.private.is_some())
+    }
+
+    /// the chain height a key's UTXOs are up to date with
+    fn height(&self, key: &PublicKey) -> u64 {
+        self.sync_points
+            .get(key)
+            .and_then(|entry| entry.value().back().map(|point| point.height))
+            .unwrap_or(0)
+    }
+
+    /// Go back to the newest sync point of `key` still on the chain, or to nothing if none is.
+    /// `on_chain` tells whether the node's block at a height has the given hash. Returns the
+    /// height the key is back at if it had to roll back
+    fn roll_back(&self, key: &PublicKey, on_chain: impl Fn(u64, &Hash) -> bool) -> Option<u64> {
+        let mut points = self.sync_points.get(key)?.value().clone();
+        let newest = points.back()?.height;
+        while let Some(point) = points.back() {
+            if on_chain(point.height, &point.tip) {
+                break;
+            }
+            points.pop_back();
+        }
+        let height = match points.back() {
+            Some(point) if point.height == newest => return None,
+            Some(point) => {
+                self.utxos.insert(key.clone(), point.utxos.clone());
+                point.height
+            }
+            None => {
+                self.utxos.remove(key);
+                0
+            }
+        };
+        self.sync_points.insert(key.clone(), points);
+        Some(height)
+    }
+
+    /// an unspent output of one of the keys by its hash, with the key
+    fn output(&self, hash: &Hash) -> Option<(PublicKey, TransactionOutput)> {
+        self.utxos.iter().find_map(|entry| {
+            entry
+                .value()
+                .iter()
+                .find(|(output, _)| output.hash() == *hash)
+                .map(|(output, _)| (entry.key().clone(), output.clone()))
+        })
+    }
+
+    /// forget everything about a key's UTXOs so they are fetched again from scratch
+    fn reset(&self, key: &PublicKey) {
+        self.utxos.remove(key);
+        self.sync_points.remove(key);
+    }
+
+    /// bring a key's UTXOs up to `diff.height`
+    fn apply_diff(&self, key: &PublicKey, diff: UtxoDiff) {
+        let mut utxos = match self.utxos.get(key) {
+            Some(entry) if !diff.full => entry.value().clone(),
+            _ => vec![],
+        };
+        let removed: HashSet<Hash> = diff.removed.into_iter().collect();
+        utxos.retain(|(output, _)| !removed.contains(&output.hash()));
+        utxos.extend(diff.added.into_iter().map(|output| (output, false)));
+        let marked: HashSet<Hash> = diff.marked.into_iter().collect();
+        for (output, is_marked) in &mut utxos {
+            *is_marked = marked.contains(&output.hash());
+        }
+
+        let mut points = self
+            .sync_points
+            .get(key)
+            .map(|entry| entry.value().clone())
+            .unwrap_or_default();
+        // only the marks changed
+        if points
+            .back()
+            .is_some_and(|point| point.height == diff.height && point.tip == diff.tip)
+        {
+            points.pop_back();
+        }
+        points.push_back(SyncPoint {
+            height: diff.height,
+            tip: diff.tip,
+            utxos: utxos.clone(),
+        });
+        if points.len() > SYNC_POINTS {
+            points.pop_front();
+        }
+        self.utxos.insert(key.clone(), utxos);
+        self.sync_points.insert(key.clone(), points);
+    }
 }
 
 #[derive(Debug)]
@@ -97,77 +366,884 @@ pub struct Core {
     utxos: UtxoStore,
     pub tx_sender: Sender<Transaction>,
     pub stream: Mutex<TcpStream>,
+    /// what the node takes into its mempool, from its Welcome
+    pub policy: MempoolPolicy,
+    /// what the wallet sent, by hash, see `check_broadcasts`
+    broadcasts: SkipMap<Hash, Broadcast>,
+    /// the invoices the wallet made, by id, see `match_invoices`
+    invoices: SkipMap<Uuid, Issued>,
+    /// where each standing order stands, by name
+    schedules: SkipMap<String, ScheduleState>,
+    /// outputs coin selection leaves alone, by hash, see `freeze`
+    frozen: SkipSet<Hash>,
+    price_provider: Option<Box<dyn PriceProvider>>,
+    /// the last price `price_provider` gave
+    price: std::sync::RwLock<Option<Price>>,
+}
+
+/// names have to tell standing orders apart, and intervals be sensible
+fn check_schedules(schedules: &[ScheduledPayment]) -> Result<()> {
+    let mut names = HashSet::new();
+    for payment in schedules {
+        if !names.insert(&payment.name) {
+            return Err(anyhow::anyhow!(
+                "Two standing orders are called {}",
+                payment.name
+            ));
+        }
+        if !(1..=MAX_INTERVAL_SECS).contains(&payment.interval_secs) {
+            return Err(anyhow::anyhow!(
+                "Standing order {} needs an interval from 1 to {MAX_INTERVAL_SECS} seconds",
+                payment.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Say hello to a node to learn its mempool policy and what it supports, once it proved to be one
+/// of the `trusted` ones. The connection stays CBOR
+async fn handshake(
+    stream: &mut TcpStream,
+    trusted: &[String],
+) -> Result<(MempoolPolicy, Capabilities)> {
+    let nonce = new_nonce();
+    let hello = Message::Hello {
+        version: PROTOCOL_VERSION,
+        encodings: vec![Encoding::Cbor],
+        // a wallet's clock says nothing about the network's
+        time: None,
+        nonce: Some(nonce),
+        capabilities: Capabilities::FILTERS | Capabilities::ZSTD | Capabilities::LZ4,
+    };
+    hello.send_async(stream).await?;
+    match Message::receive_async(stream).await? {
+        Message::Welcome {
+            policy,
+            identity,
+            capabilities,
+            ..
+        } => {
+            check_identity(identity.as_ref(), &nonce, trusted)?;
+            Ok((policy, capabilities))
+        }
+        _ => Err(anyhow::anyhow!("Unexpected response from node")),
+    }
 }
 
 impl Core {
-    fn new(config: Config, utxos: UtxoStore, stream: TcpStream) -> Self {
+    fn new(config: Config, utxos: UtxoStore, stream: TcpStream, policy: MempoolPolicy) -> Self {
         let (tx_sender, _) = kanal::bounded(10);
         Core {
             config,
             utxos,
             tx_sender,
             stream: Mutex::new(stream),
+            policy,
+            broadcasts: SkipMap::new(),
+            invoices: SkipMap::new(),
+            schedules: SkipMap::new(),
+            frozen: SkipSet::new(),
+            price_provider: None,
+            price: std::sync::RwLock::new(None),
         }
     }
 
     pub async fn load_config(config_path: PathBuf) -> Result<Self> {
         info!("Loading core from config: {:?}", config_path);
-        let config: Config = toml::from_str(&fs::read_to_string(&config_path)?)
-            .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
+        let config = Config::load(&config_path)?;
         let mut utxos = UtxoStore::new();
-        let stream = TcpStream::connect(&config.default_node).await?;
+        let mut stream = TcpStream::connect(&config.default_node).await?;
+        let (policy, _) = handshake(&mut stream, &config.trusted_nodes).await?;
+        debug!("Node mempool policy: {:?}", policy);
         // load keys from config
         for key in &config.keys {
             debug!("Loading key pair: {:?}", key.public);
             let public = PublicKey::load_from_file(&key.public)
                 .with_context(|| "Failed to load public key specified in the file")?;
+            if key.retired {
+                utxos.add_key(LoadedKey {
+                    public,
+                    private: None,
+                });
+                continue;
+            }
             let private = PrivateKey::load_from_file(&key.private)
                 .with_context(|| "Failed to load private key specified in the file")?;
-            utxos.add_key(LoadedKey { public, private });
+            // a duress passphrase unlocks the decoy in the key file instead, which the public
+            // key file doesn't name. Go with it without a word, see `keyfile`
+            let public = if private.public_key() == public {
+                public
+            } else {
+                private.public_key()
+            };
+            utxos.add_key(LoadedKey {
+                public,
+                private: Some(private),
+            });
         }
+        let issued = invoices::load(&config.invoices)
+            .with_context(|| format!("Failed to load invoices: {}", config.invoices.display()))?;
+        check_schedules(&config.schedules)?;
+        let states = schedule::load(&config.schedule_state).with_context(|| {
+            format!(
+                "Failed to load standing orders: {}",
+                config.schedule_state.display()
+            )
+        })?;
+        let frozen = coins::load(&config.frozen).with_context(|| {
+            format!("Failed to load frozen outputs: {}", config.frozen.display())
+        })?;
+
+        let price_provider = config
+            .price
+            .as_ref()
+            .map(PriceConfig::provider)
+            .transpose()?;
 
-        Ok(Core::new(config, utxos, stream))
+        let mut core = Core::new(config, utxos, stream, policy);
+        core.price_provider = price_provider;
+        for issued in issued {
+            core.invoices.insert(issued.invoice.id, issued);
+        }
+        for (name, state) in states {
+            core.schedules.insert(name, state);
+        }
+        for hash in frozen {
+            core.frozen.insert(hash);
+        }
+        Ok(core)
     }
 
-    /// Fetch UTXOs from the node for all loaded keys
+    /// Fetch what changed about the UTXOs of all loaded keys since they were last fetched, after
+    /// rolling back whatever a reorg took off the chain
     pub async fn fetch_utxos(&self) -> Result<()> {
+        if let Some(height) = self.detect_reorg().await? {
+            warn!("The chain reorganized, rescanning from height {height}");
+        }
         debug!("Fetching UTXOs from node: {}", self.config.default_node);
         for key in &self.utxos.keys {
-            let message = Message::FetchUTXOs(key.public.clone());
+            let since = self.utxos.height(&key.public);
+            let message = Message::FetchUTXOsSince(key.public.clone(), since);
             message.send_async(&mut *self.stream.lock().await).await?;
-            if let Message::UTXOs(utxos) =
+            if let Message::UTXOsSince(diff) =
                 Message::receive_async(&mut *self.stream.lock().await).await?
             {
-                debug!("Received {} UTXOs for key: {}", utxos.len(), key.public);
-                // replace the entire UTXO set for this key
-                self.utxos.utxos.insert(
-                    key.public.clone(),
-                    utxos
-                        .into_iter()
-                        .map(|(marked, output)| (output, marked))
-                        .collect(),
+                debug!(
+                    "Received {} new and {} spent UTXOs for key: {}",
+                    diff.added.len(),
+                    diff.removed.len(),
+                    key.public
                 );
+                self.utxos.apply_diff(&key.public, diff);
             } else {
                 error!("Unexpected response from node");
                 return Err(anyhow::anyhow!("Unexpected response from node"));
             }
         }
         info!("UTXOs fetched successfully!");
+        for invoice in self.match_invoices()? {
+            info!("Invoice {} for {} was paid", invoice.id, invoice.amount);
+        }
         Ok(())
     }
 
-    /// Send a transaction to the node
+    /// Ask to be paid `amount` to the first key, for `expiry` from now. The invoice is kept so
+    /// the payment can be matched to it
+    pub fn create_invoice(
+        &self,
+        amount: Amount,
+        memo: String,
+        expiry: chrono::Duration,
+    ) -> Result<Invoice> {
+        let key = self
+            .utxos
+            .receiving_key()
+            .and_then(|key| key.private.as_ref())
+            .ok_or_else(|| anyhow::anyhow!("The wallet has no keys to be paid to"))?;
+        let invoice = Invoice::new(key, amount, memo, expiry);
+        self.invoices.insert(
+            invoice.id,
+            Issued {
+                invoice: invoice.clone(),
+                paid: None,
+            },
+        );
+        self.save_invoices()?;
+        Ok(invoice)
+    }
+
+    /// Make the standing orders that came due, with UTXOs fetched first so a payment the wallet
+    /// can afford isn't skipped for being behind
+    pub async fn run_schedules(&self) -> Result<()> {
+        let now = chrono::Utc::now();
+        let mut due = vec![];
+        for payment in &self.config.schedules {
+            let mut state = self
+                .schedules
+                .get(&payment.name)
+                .map(|entry| entry.value().clone())
+                .unwrap_or_else(|| ScheduleState::new(now));
+            let at = state.take_due(payment.interval(), now);
+            self.schedules.insert(payment.name.clone(), state);
+            if let Some(at) = at {
+                due.push((payment, at));
+            }
+        }
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        if let Err(e) = self.fetch_utxos().await {
+            warn!("Failed to fetch UTXOs before standing orders: {e}");
+        }
+        for (payment, at) in due {
+            let outcome = self.run_scheduled(payment).await;
+            info!("Standing order {} due at {at}: {outcome}", payment.name);
+            let mut state = self
+                .schedules
+                .get(&payment.name)
+                .map(|entry| entry.value().clone())
+                .unwrap_or_else(|| ScheduleState::new(now));
+            state.record(at, outcome);
+            self.schedules.insert(payment.name.clone(), state);
+        }
+        self.save_schedules()
+    }
+
+    async fn run_scheduled(&self, payment: &ScheduledPayment) -> RunOutcome {
+        let skipped = |reason: String| RunOutcome::Skipped { reason };
+        let recipient = match self
+            .config
+            .contacts
+            .iter()
+            .find(|contact| contact.name == payment.recipient)
+            .map(Recipient::load)
+        {
+            Some(Ok(recipient)) => recipient,
+            Some(Err(e)) => return skipped(format!("failed to load the contact's key: {e}")),
+            None => return skipped(format!("no contact called {}", payment.recipient)),
+        };
+        let needed = payment
+            .amount
+            .checked_add(self.calculate_fee(payment.amount))
+            .unwrap_or(Amount::MAX_MONEY);
+        let spendable = self.spendable_balance();
+        if spendable < needed {
+            return skipped(format!(
+                "insufficient funds, {} needed, {} there",
+                self.format_amount(needed),
+                self.format_amount(spendable)
+            ));
+        }
+        let memo = Some(format!("standing order {}", payment.name));
+        let transaction = match self.create_transaction(&recipient.key, payment.amount, memo) {
+            Ok(transaction) => transaction,
+            Err(e) => return skipped(e.to_string()),
+        };
+        let txid = transaction.hash();
+        match self.send_transaction(transaction).await {
+            Ok(()) => RunOutcome::Paid { txid },
+            Err(e) => skipped(e.to_string()),
+        }
+    }
+
+    /// The standing orders with where they stand, in the order of the config. `None` for ones
+    /// that never came up yet
+    pub fn schedules(&self) -> Vec<(ScheduledPayment, Option<ScheduleState>)> {
+        self.config
+            .schedules
+            .iter()
+            .map(|payment| {
+                let state = self
+                    .schedules
+                    .get(&payment.name)
+                    .map(|entry| entry.value().clone());
+                (payment.clone(), state)
+            })
+            .collect()
+    }
+
+    fn save_schedules(&self) -> Result<()> {
+        let states = self
+            .schedules
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        schedule::save(&self.config.schedule_state, &states)
+    }
+
+    /// The invoices the wallet made, oldest first
+    pub fn invoices(&self) -> Vec<Issued> {
+        let mut invoices: Vec<Issued> = self
+            .invoices
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        invoices.sort_by_key(|issued| issued.invoice.created);
+        invoices
+    }
+
+    fn save_invoices(&self) -> Result<()> {
+        invoices::save(&self.config.invoices, &self.invoices())
+    }
+
+    /// Mark the invoices an unspent output of the wallet pays as paid. Returns the ones that
+    /// weren't before
+    pub fn match_invoices(&self) -> Result<Vec<Invoice>> {
+        let mut paid = vec![];
+        for entry in self.invoices.iter() {
+            let issued = entry.value();
+            if issued.paid.is_some() {
+                continue;
+            }
+            let Some(utxos) = self.utxos.utxos.get(&issued.invoice.pubkey) else {
+                continue;
+            };
+            let Some((output, _)) = utxos
+                .value()
+                .iter()
+                .find(|(output, _)| issued.invoice.is_paid_by(output))
+            else {
+                continue;
+            };
+            self.invoices.insert(
+                issued.invoice.id,
+                Issued {
+                    invoice: issued.invoice.clone(),
+                    paid: Some(output.hash()),
+                },
+            );
+            paid.push(issued.invoice.clone());
+        }
+        if !paid.is_empty() {
+            self.save_invoices()?;
+        }
+        Ok(paid)
+    }
+
+    /// Check the blocks the keys were synced to are still on the node's chain, and roll back the
+    /// keys whose block isn't to the newest one still there. Returns the lowest height a key
+    /// went back to, `None` if there was no reorg
+    pub async fn detect_reorg(&self) -> Result<Option<u64>> {
+        // hash of the node's block at each height a key was synced to, asked once and only
+        // until a key's newest sync point still on the chain is found
+        let mut hashes: HashMap<u64, Option<Hash>> = HashMap::new();
+        hashes.insert(0, Some(Hash::zero()));
+        let mut stream = self.stream.lock().await;
+        for key in &self.utxos.keys {
+            let Some(points) = self.utxos.sync_points.get(&key.public) else {
+                continue;
+            };
+            for point in points.value().iter().rev() {
+                let hash = match hashes.get(&point.height) {
+                    Some(hash) => *hash,
+                    None => {
+                        Message::FetchHeaderRange(point.height - 1, 1)
+                            .send_async(&mut *stream)
+                            .await?;
+                        let Message::Headers(headers) =
+                            Message::receive_async(&mut *stream).await?
+                        else {
+                            return Err(anyhow::anyhow!("Unexpected response from node"));
+                        };
+                        let hash = headers.first().map(|header| header.hash);
+                        hashes.insert(point.height, hash);
+                        hash
+                    }
+                };
+                if hash == Some(point.tip) {
+                    break;
+                }
+            }
+        }
+        drop(stream);
+
+        let on_chain = |height: u64, tip: &Hash| hashes.get(&height) == Some(&Some(*tip));
+        Ok(self
+            .utxos
+            .keys
+            .iter()
+            .filter_map(|key| self.utxos.roll_back(&key.public, on_chain))
+            .min())
+    }
+
+    /// Forget the UTXOs of all keys and fetch them again from scratch
+    pub async fn rescan(&self) -> Result<()> {
+        for key in &self.utxos.keys {
+            self.utxos.reset(&key.public);
+        }
+        self.fetch_utxos().await
+    }
+
+    /// Have the node scan the chain for every key, for keys that were just imported. Their UTXOs
+    /// are replaced with what it found, and their history from `from` on is returned oldest first
+    pub async fn scan_keys(&self, from: u64) -> Result<Vec<AddressActivity>> {
+        let mut history = vec![];
+        let mut stream = self.stream.lock().await;
+        for key in &self.utxos.keys {
+            Message::ScanAddress(key.public.clone(), from)
+                .send_async(&mut *stream)
+                .await?;
+            let scan = match Message::receive_async(&mut *stream).await? {
+                Message::AddressScanned(scan) => scan,
+                Message::Error { code, reason } => {
+                    return Err(anyhow::anyhow!("Scan refused ({code:?}): {reason}"));
+                }
+                _ => return Err(anyhow::anyhow!("Unexpected response from node")),
+            };
+            debug!(
+                "Scan found {} UTXOs and {} history entries for key: {}",
+                scan.utxos.len(),
+                scan.history.len(),
+                key.public
+            );
+            let marked = scan
+                .utxos
+                .iter()
+                .filter(|(_, marked)| *marked)
+                .map(|(output, _)| output.hash())
+                .collect();
+            self.utxos.reset(&key.public);
+            self.utxos.apply_diff(
+                &key.public,
+                UtxoDiff {
+                    height: scan.height,
+                    tip: scan.tip,
+                    full: true,
+                    added: scan.utxos.into_iter().map(|(output, _)| output).collect(),
+                    removed: vec![],
+                    marked,
+                },
+            );
+            history.extend(scan.history);
+        }
+        history.sort_by_key(|activity| activity.height);
+        Ok(history)
+    }
+
+    /// Subscribe to the node with a bloom filter of the wallet's keys on a separate connection,
+    /// and only fetch the UTXOs again when it relays something that concerns them
+    pub async fn watch_utxos(&self) -> Result<()> {
+        let tweak = uuid::Uuid::new_v4().as_u128() as u32;
+        let mut filter = BloomFilter::new(self.utxos.keys.len(), 0.0001, tweak);
+        for key in &self.utxos.keys {
+            filter.insert_pubkey(&key.public);
+        }
+
+        let mut stream = TcpStream::connect(&self.config.default_node).await?;
+        let (_, capabilities) = handshake(&mut stream, &self.config.trusted_nodes).await?;
+        if !capabilities.contains(Capabilities::FILTERS) {
+            return Err(anyhow::anyhow!("Node does not support bloom filters"));
+        }
+        Message::SetFilter(filter).send_async(&mut stream).await?;
+        self.fetch_utxos().await?;
+        loop {
+            match Message::receive_async(&mut stream).await? {
+                Message::NewTransaction(transaction) => {
+                    debug!("Node relayed transaction {}", transaction.hash());
+                    self.fetch_utxos().await?;
+                }
+                Message::FilteredBlock(block) if block.matches.is_empty() => {}
+                Message::FilteredBlock(block) => {
+                    if !block.verify() {
+                        warn!("Node relayed transactions that are not in their block");
+                    }
+                    self.fetch_utxos().await?;
+                }
+                Message::Evicted { txid, reason } => {
+                    warn!("Transaction {txid} was dropped from the mempool: {reason:?}");
+                    self.fetch_utxos().await?;
+                    if reason == EvictionReason::Expired
+                        && let Some(entry) = self.broadcasts.get(&txid)
+                    {
+                        self.rebroadcast(entry.value().clone()).await?;
+                    }
+                }
+                Message::Error { code, reason } => {
+                    return Err(anyhow::anyhow!("Filter refused ({code:?}): {reason}"));
+                }
+                _ => return Err(anyhow::anyhow!("Unexpected message from node")),
+            }
+        }
+    }
+
+    /// where the node's chain is now, for the finality policy to judge transactions by
+    pub async fn chain_tip(&self) -> Result<ChainTip> {
+        Ok(ChainTip::from(&self.fetch_status().await?))
+    }
+
+    /// Fetch the history of all loaded keys from the node, oldest first
+    pub async fn fetch_history(&self) -> Result<Vec<AddressActivity>> {
+        let mut history = vec![];
+        let mut stream = self.stream.lock().await;
+        for key in &self.utxos.keys {
+            Message::FetchHistory(key.public.clone())
+                .send_async(&mut *stream)
+                .await?;
+            match Message::receive_async(&mut *stream).await? {
+                Message::History(activity) => history.extend(activity),
+                Message::Error { code, reason } => {
+                    return Err(anyhow::anyhow!("History refused ({code:?}): {reason}"));
+                }
+                _ => return Err(anyhow::anyhow!("Unexpected response from node")),
+            }
+        }
+        history.sort_by_key(|activity| activity.height);
+        Ok(history)
+    }
+
+    /// The transactions that paid `key` before: on chain if the node keeps an addrindex to tell,
+    /// and the ones the wallet sent that it still follows. Paying a key again lets anyone watching
+    /// tie the payments together
+    pub async fn payments_to(&self, key: &PublicKey) -> HashSet<Hash> {
+        let mut paid: HashSet<Hash> = self
+            .broadcasts
+            .iter()
+            .filter(|entry| {
+                let outputs = &entry.value().transaction.outputs;
+                outputs.iter().any(|output| output.pubkey == *key)
+            })
+            .map(|entry| *entry.key())
+            .collect();
+        let mut stream = self.stream.lock().await;
+        let message = Message::FetchHistory(key.clone());
+        if let Err(e) = message.send_async(&mut *stream).await {
+            debug!("Failed to ask for the history of {key}: {e}");
+            return paid;
+        }
+        match Message::receive_async(&mut *stream).await {
+            Ok(Message::History(activity)) => paid.extend(
+                activity
+                    .iter()
+                    .filter(|activity| activity.received > Amount::ZERO)
+                    .map(|activity| activity.txid),
+            ),
+            // no addrindex, what the wallet sent is all there is to go by
+            Ok(message) => debug!("No history for {key}: {message:?}"),
+            Err(e) => debug!("Failed to fetch the history of {key}: {e}"),
+        }
+        paid
+    }
+
+    /// The node's block at `height`, which has to be on its chain
+    pub async fn fetch_block(&self, height: u64) -> Result<Block> {
+        let mut stream = self.stream.lock().await;
+        Message::FetchBlock(height as usize)
+            .send_async(&mut *stream)
+            .await?;
+        match Message::receive_async(&mut *stream).await? {
+            Message::NewBlock(block) => Ok(block),
+            _ => Err(anyhow::anyhow!("Unexpected response from node")),
+        }
+    }
+
+    /// whether every input of `transaction` carries a signature of one of the wallet's keys
+    pub fn signed_every_input(&self, transaction: &Transaction) -> bool {
+        !transaction.inputs.is_empty()
+            && transaction.inputs.iter().all(|input| {
+                let message = transaction.signature_hash(&input.prev_transaction_output_hash);
+                self.utxos
+                    .keys
+                    .iter()
+                    .any(|key| input.signature.verify(&message, &key.public))
+            })
+    }
+
+    /// Sign the inputs of `transaction` spending the wallet's unspent outputs that don't carry a
+    /// valid signature yet. Returns how many it signed
+    pub fn sign_inputs(&self, transaction: &mut Transaction) -> usize {
+        let mut signed = 0;
+        for i in 0..transaction.inputs.len() {
+            let hash = transaction.inputs[i].prev_transaction_output_hash;
+            let Some((pubkey, _)) = self.utxos.output(&hash) else {
+                continue;
+            };
+            let message = transaction.signature_hash(&hash);
+            if transaction.inputs[i].signature.verify(&message, &pubkey) {
+                continue;
+            }
+            let Some(key) = self.utxos.private_key(&pubkey) else {
+                continue;
+            };
+            transaction.inputs[i].signature = Signature::sign_output(&message, key);
+            signed += 1;
+        }
+        signed
+    }
+
+    /// Ask the node how it and its chain are doing
+    pub async fn fetch_status(&self) -> Result<NodeStatus> {
+        let mut stream = self.stream.lock().await;
+        Message::GetStatus.send_async(&mut *stream).await?;
+        match Message::receive_async(&mut *stream).await? {
+            Message::Status(status) => Ok(*status),
+            _ => Err(anyhow::anyhow!("Unexpected response from node")),
+        }
+    }
+
+    /// What's waiting in the node's mempool, the best paying transactions first
+    pub async fn fetch_mempool(&self) -> Result<Vec<MempoolTxInfo>> {
+        let mut stream = self.stream.lock().await;
+        Message::FetchMempool.send_async(&mut *stream).await?;
+        match Message::receive_async(&mut *stream).await? {
+            Message::MempoolTransactions(info) => Ok(info),
+            _ => Err(anyhow::anyhow!("Unexpected response from node")),
+        }
+    }
+
+    /// Where a transaction stands in the node's mempool, `None` if it isn't there (anymore)
+    pub async fn fetch_mempool_tx(&self, txid: Hash) -> Result<Option<MempoolTxInfo>> {
+        let mut stream = self.stream.lock().await;
+        Message::FetchMempoolTx(txid)
+            .send_async(&mut *stream)
+            .await?;
+        match Message::receive_async(&mut *stream).await? {
+            Message::MempoolTx { info, .. } => Ok(Some(info)),
+            Message::Error {
+                code: ErrorCode::NotFound,
+                ..
+            } => Ok(None),
+            _ => Err(anyhow::anyhow!("Unexpected response from node")),
+        }
+    }
+
+    /// Ask the node whether it would take a transaction, without it going anywhere yet
+    pub async fn test_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<std::result::Result<MempoolAcceptance, Rejection>> {
+        let mut stream = self.stream.lock().await;
+        Message::TestTransaction(transaction.clone())
+            .send_async(&mut *stream)
+            .await?;
+        match Message::receive_async(&mut *stream).await? {
+            Message::TransactionTested { result, .. } => Ok(result),
+            _ => Err(anyhow::anyhow!("Unexpected response from node")),
+        }
+    }
+
+    /// Submit a transaction to the node, the inner result says whether it took it
+    async fn submit(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<std::result::Result<Hash, Rejection>> {
+        let mut stream = self.stream.lock().await;
+        Message::SubmitTransaction(transaction.clone())
+            .send_async(&mut *stream)
+            .await?;
+        match Message::receive_async(&mut *stream).await? {
+            Message::Ack(hash) => Ok(Ok(hash)),
+            Message::Error { code, reason } => Ok(Err(Rejection { code, reason })),
+            _ => {
+                error!("Unexpected response from node");
+                Err(anyhow::anyhow!("Unexpected response from node"))
+            }
+        }
+    }
+
+    /// Send a transaction to the node, and keep an eye on it until it's mined
     pub async fn send_transaction(&self, transaction: Transaction) -> Result<()> {
         debug!("Sending transaction to node: {}", self.config.default_node);
-        let message = Message::SubmitTransaction(transaction);
-        message.send_async(&mut *self.stream.lock().await).await?;
-        info!("Transaction sent successfully!");
+        match self.submit(&transaction).await? {
+            Ok(hash) => {
+                info!("Transaction {hash} accepted by the node");
+                if !self.broadcasts.contains_key(&hash) {
+                    self.broadcasts.insert(hash, Broadcast::new(transaction));
+                }
+                Ok(())
+            }
+            Err(Rejection { code, reason }) => {
+                error!("Transaction rejected ({code:?}): {reason}");
+                Err(anyhow::anyhow!("Transaction rejected ({code:?}): {reason}"))
+            }
+        }
+    }
+
+    /// The transactions the wallet sent, oldest first
+    pub fn broadcasts(&self) -> Vec<Broadcast> {
+        let mut broadcasts: Vec<Broadcast> = self
+            .broadcasts
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect();
+        broadcasts.sort_by_key(|broadcast| broadcast.sent);
+        broadcasts
+    }
+
+    /// Check on the transactions the wallet sent that are still waiting: where they stand in the
+    /// mempool, or whether they made it into a block. The ones that left the mempool without
+    /// being mined, e.g. because they expired, are sent again
+    pub async fn check_broadcasts(&self) -> Result<()> {
+        let waiting: Vec<Broadcast> = self
+            .broadcasts()
+            .into_iter()
+            .filter(|broadcast| matches!(broadcast.status, BroadcastStatus::Waiting { .. }))
+            .collect();
+        for mut broadcast in waiting {
+            let txid = broadcast.transaction.hash();
+            broadcast.status = match self.fetch_mempool_tx(txid).await? {
+                Some(info) => {
+                    broadcast.expires = Some(self.policy.expires(info.received));
+                    BroadcastStatus::Waiting {
+                        position: Some(info.position),
+                    }
+                }
+                None => match self.confirmation(&broadcast.transaction).await? {
+                    Some(status) => status,
+                    None => {
+                        self.rebroadcast(broadcast).await?;
+                        continue;
+                    }
+                },
+            };
+            if broadcast.is_expiring(&self.policy) {
+                warn!(
+                    "Transaction {txid} expires from the node's mempool in {}, it gets sent again \
+                     then, or its fee can be raised now with `bump {txid}`",
+                    crate::util::elapsed(broadcast.expires_in().unwrap_or_default())
+                );
+            } else if broadcast.is_stuck() {
+                warn!(
+                    "Transaction {txid} has been waiting for {}, its fee can be raised with \
+                     `bump {txid}`",
+                    crate::util::elapsed(broadcast.sent.elapsed())
+                );
+            }
+            self.broadcasts.insert(txid, broadcast);
+        }
+        Ok(())
+    }
+
+    /// `Confirmed` if a transaction that isn't in the mempool made it into a block, `None` if it
+    /// didn't. Nodes without a txindex can't say, then it counts as mined if the outputs it
+    /// spends are gone from the keys' UTXOs
+    async fn confirmation(&self, transaction: &Transaction) -> Result<Option<BroadcastStatus>> {
+        let mut stream = self.stream.lock().await;
+        Message::FetchTransaction(transaction.hash())
+            .send_async(&mut *stream)
+            .await?;
+        match Message::receive_async(&mut *stream).await? {
+            Message::TransactionInfo { height, .. } => Ok(Some(BroadcastStatus::Confirmed {
+                height: Some(height),
+            })),
+            Message::Error {
+                code: ErrorCode::NotFound,
+                ..
+            } => Ok(None),
+            Message::Error {
+                code: ErrorCode::NotAllowed,
+                ..
+            } => {
+                let spent = transaction.inputs.iter().all(|input| {
+                    self.utxos
+                        .output(&input.prev_transaction_output_hash)
+                        .is_none()
+                });
+                Ok(spent.then_some(BroadcastStatus::Confirmed { height: None }))
+            }
+            _ => Err(anyhow::anyhow!("Unexpected response from node")),
+        }
+    }
+
+    /// Send a transaction that left the mempool again, it's dropped for good if the node
+    /// refuses it
+    async fn rebroadcast(&self, mut broadcast: Broadcast) -> Result<()> {
+        let txid = broadcast.transaction.hash();
+        broadcast.status = match self.submit(&broadcast.transaction).await? {
+            Ok(_) => {
+                info!("Transaction {txid} left the mempool without being mined, sent it again");
+                broadcast.rebroadcasts += 1;
+                broadcast.expires = Some(self.policy.expires(Utc::now()));
+                BroadcastStatus::Waiting { position: None }
+            }
+            Err(Rejection { code, reason }) => {
+                warn!("Transaction {txid} left the mempool and can't be sent again: {reason}");
+                BroadcastStatus::Dropped {
+                    reason: format!("{reason} ({code:?})"),
+                }
+            }
+        };
+        self.broadcasts.insert(txid, broadcast);
         Ok(())
     }
 
+    /// Replace a transaction the wallet sent that isn't mined yet with one spending the same
+    /// outputs for the same payments, paying twice the fee and at least what the node asks for
+    /// on top. The difference comes out of the change. Returns the replacement
+    pub async fn bump_fee(&self, txid: Hash) -> Result<Transaction> {
+        let broadcast = self
+            .broadcasts
+            .get(&txid)
+            .map(|entry| entry.value().clone())
+            .ok_or_else(|| anyhow::anyhow!("Transaction {txid} wasn't sent by this wallet"))?;
+        if let BroadcastStatus::Confirmed { .. } = broadcast.status {
+            return Err(anyhow::anyhow!("Transaction {txid} is already mined"));
+        }
+        let change_key = self
+            .utxos
+            .receiving_key()
+            .ok_or_else(|| anyhow::anyhow!("No keys loaded"))?;
+        let old = &broadcast.transaction;
+
+        let mut builder = TransactionBuilder::new();
+        let mut spent = Amount::ZERO;
+        for input in &old.inputs {
+            let (pubkey, output) = self
+                .utxos
+                .output(&input.prev_transaction_output_hash)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Transaction {txid} spends outputs that are gone, mined?")
+                })?;
+            let key = self
+                .utxos
+                .private_key(&pubkey)
+                .ok_or_else(|| anyhow::anyhow!("No private key for {pubkey}"))?;
+            builder = builder.spend(&output, key);
+            spent = spent
+                .checked_add(output.value)
+                .ok_or_else(|| anyhow::anyhow!("UTXO values overflow"))?;
+        }
+        let old_fee = spent
+            .checked_sub(old.output_value()?)
+            .ok_or_else(|| anyhow::anyhow!("Transaction {txid} spends less than it pays"))?;
+        let fee = old_fee
+            .checked_mul(2)
+            .max(old_fee.checked_add(self.policy.min_fee(old.size())))
+            .ok_or_else(|| anyhow::anyhow!("Fee is too large"))?;
+
+        // the change went last, to the first key
+        let payments = match old.outputs.split_last() {
+            Some((last, payments)) if !payments.is_empty() && last.pubkey == change_key.public => {
+                payments
+            }
+            _ => &old.outputs[..],
+        };
+        for payment in payments {
+            builder = builder.pay_to(payment.pubkey.clone(), payment.value);
+        }
+        builder = builder.change_to(change_key.public.clone(), fee);
+        if let Some(memo) = &old.memo {
+            builder = builder.memo(memo.clone());
+        }
+        let replacement = builder
+            .finalize()
+            .map_err(|e| anyhow::anyhow!("Not enough change to pay a fee of {fee}: {e}"))?;
+        self.check_policy(&replacement, fee)?;
+
+        self.send_transaction(replacement.clone()).await?;
+        self.broadcasts.remove(&txid);
+        Ok(replacement)
+    }
+
     /// Send a transaction to the node
-    pub fn send_transaction_async(&self, recipient: &str, amount: u64) -> Result<()> {
-        info!("Preparing to send {} satoshis to {}", amount, recipient);
+    pub fn send_transaction_async(
+        &self,
+        recipient: &str,
+        amount: Amount,
+        memo: Option<String>,
+    ) -> Result<()> {
+        info!("Preparing to send {} to {}", amount, recipient);
         let recipient_key = self
             .config
             .contacts
@@ -176,90 +1252,737 @@ impl Core {
             .ok_or_else(|| anyhow::anyhow!("Recipient not found"))?
             .load()?
             .key;
-        let transaction = self.create_transaction(&recipient_key, amount)?;
+        let transaction = self.create_transaction(&recipient_key, amount, memo)?;
         debug!("Sending transaction asynchronously");
         self.tx_sender.send(transaction)?;
         info!("Transaction sent successfully!");
         Ok(())
     }
 
-    pub fn create_transaction(&self, recipient: &PublicKey, amount: u64) -> Result<Transaction> {
+    pub fn create_transaction(
+        &self,
+        recipient: &PublicKey,
+        amount: Amount,
+        memo: Option<String>,
+    ) -> Result<Transaction> {
+        debug!("Creating transaction for {} to {:?}", amount, recipient);
+        let builder = TransactionBuilder::new().pay_to(recipient.clone(), amount);
+        self.fund(builder, amount, memo, None)
+    }
+
+    /// `create_transaction` spending exactly the outputs `coins`, frozen or not, with whatever
+    /// they have left over as change
+    pub fn create_transaction_from(
+        &self,
+        recipient: &PublicKey,
+        amount: Amount,
+        memo: Option<String>,
+        coins: &[Hash],
+    ) -> Result<Transaction> {
         debug!(
-            "Creating transaction for {} satoshis to {:?}",
-            amount, recipient
+            "Creating transaction for {} to {:?} from {} outputs",
+            amount,
+            recipient,
+            coins.len()
         );
+        let builder = TransactionBuilder::new().pay_to(recipient.clone(), amount);
+        self.fund(builder, amount, memo, Some(coins))
+    }
+
+    /// A transaction moving everything the keys `from` hold to `to`, frozen outputs too, less the
+    /// fee, e.g. to rotate them out. Outputs a waiting transaction spends already are left be
+    pub fn sweep(&self, from: &[PublicKey], to: &PublicKey) -> Result<Transaction> {
+        let outputs: Vec<TransactionOutput> = self
+            .coins()
+            .into_iter()
+            .filter(|coin| !coin.pending && !coin.retired && from.contains(&coin.output.pubkey))
+            .map(|coin| coin.output)
+            .collect();
+        if outputs.is_empty() {
+            return Err(anyhow::anyhow!("The keys hold nothing to sweep"));
+        }
+        let total = Amount::checked_sum(outputs.iter().map(|output| output.value))
+            .ok_or_else(|| anyhow::anyhow!("UTXO values overflow"))?;
+        debug!("Sweeping {} from {} outputs", total, outputs.len());
+        let mut builder = TransactionBuilder::new();
+        for output in &outputs {
+            let key = self.utxos.private_key(&output.pubkey).unwrap();
+            builder = builder.spend(output, key);
+        }
+        let mut fee = self.calculate_fee(total);
+        let mut transaction = builder.clone().change_to(to.clone(), fee).finalize()?;
+        // the node's floor goes by size, which the fee doesn't change
+        let min_fee = self.policy.min_fee(transaction.size());
+        if fee < min_fee {
+            fee = min_fee;
+            transaction = builder.change_to(to.clone(), fee).finalize()?;
+        }
+        if transaction.outputs.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The fee of {fee} takes all of the {total} there is to sweep"
+            ));
+        }
+        self.check_policy(&transaction, fee)?;
+        Ok(transaction)
+    }
+
+    /// Create a transaction paying `invoice`, once it's checked to be signed by the key it asks to
+    /// be paid to and still open
+    pub fn pay_invoice(&self, invoice: &Invoice) -> Result<Transaction> {
+        if !invoice.verify() {
+            return Err(anyhow::anyhow!("The invoice signature is not valid"));
+        }
+        if invoice.is_expired(chrono::Utc::now()) {
+            return Err(anyhow::anyhow!(
+                "The invoice expired at {}",
+                invoice.expires
+            ));
+        }
+        debug!("Creating transaction for invoice {}", invoice.id);
+        let builder = TransactionBuilder::new().pay_invoice(invoice);
+        self.fund(builder, invoice.amount, None, None)
+    }
+
+    /// Spend enough of the wallet's UTXOs that aren't frozen for `builder`'s payments of `amount`
+    /// and the fee, or all of the ones `coins` picks
+    fn fund(
+        &self,
+        builder: TransactionBuilder,
+        amount: Amount,
+        memo: Option<String>,
+        coins: Option<&[Hash]>,
+    ) -> Result<Transaction> {
         let fee = self.calculate_fee(amount);
-        let total_amount = amount + fee;
-        let mut inputs = Vec::new();
-        let mut input_sum = 0;
-        for entry in self.utxos.utxos.iter() {
-            let pubkey = entry.key();
-            let utxos = entry.value();
-            for (utxo, marked) in utxos.iter() {
-                if *marked {
-                    continue; // Skip marked UTXOs
-                }
-                if input_sum >= total_amount {
-                    break;
-                }
-                inputs.push(TransactionInput {
-                    prev_transaction_output_hash: utxo.hash(),
-                    signature: Signature::sign_output(
-                        &utxo.hash(),
-                        &self
-                            .utxos
-                            .keys
-                            .iter()
-                            .find(|k| k.public == *pubkey)
-                            .unwrap()
-                            .private,
-                    ),
-                });
-                input_sum += utxo.value;
-            }
-            if input_sum >= total_amount {
+        let total_amount = amount
+            .checked_add(fee)
+            .ok_or_else(|| anyhow::anyhow!("Amount plus fee is too large"))?;
+        let change_key = self
+            .utxos
+            .receiving_key()
+            .ok_or_else(|| anyhow::anyhow!("No keys to send the change to"))?;
+        let mut builder = builder.change_to(change_key.public.clone(), fee);
+        if let Some(memo) = memo {
+            builder = builder.memo(memo);
+        }
+        let utxos = match coins {
+            Some(coins) => self.picked(coins)?,
+            None => self
+                .coins()
+                .into_iter()
+                .filter(Coin::is_spendable)
+                .map(|coin| coin.output)
+                .collect(),
+        };
+        let mut input_sum = Amount::ZERO;
+        for utxo in &utxos {
+            if coins.is_none() && input_sum >= total_amount {
                 break;
             }
+            let key = self.utxos.private_key(&utxo.pubkey).unwrap();
+            builder = builder.spend(utxo, key);
+            input_sum = input_sum
+                .checked_add(utxo.value)
+                .ok_or_else(|| anyhow::anyhow!("UTXO values overflow"))?;
         }
 
         if input_sum < total_amount {
-            return Err(anyhow::anyhow!("Insufficient funds"));
+            return Err(match coins {
+                Some(_) => anyhow::anyhow!(
+                    "The outputs picked have {input_sum}, {total_amount} is needed with the fee"
+                ),
+                None => anyhow::anyhow!("Insufficient funds"),
+            });
         }
 
-        let mut outputs = vec![TransactionOutput {
-            value: amount,
-            unique_id: uuid::Uuid::new_v4(),
-            pubkey: self.utxos.keys[0].public.clone(),
-        }];
+        let transaction = builder.finalize()?;
+        self.check_policy(&transaction, fee)?;
+        Ok(transaction)
+    }
 
-        if input_sum > total_amount {
-            outputs.push(TransactionOutput {
-                value: input_sum - total_amount,
-                unique_id: uuid::Uuid::new_v4(),
-                pubkey: self.utxos.keys[0].public.clone(),
-            });
+    /// the outputs `coins` picks, as long as they are the wallet's and no waiting transaction
+    /// spends them
+    fn picked(&self, coins: &[Hash]) -> Result<Vec<TransactionOutput>> {
+        let owned: HashMap<Hash, Coin> = self
+            .coins()
+            .into_iter()
+            .map(|coin| (coin.output.hash(), coin))
+            .collect();
+        let mut picked = vec![];
+        let mut seen = HashSet::new();
+        for hash in coins {
+            if !seen.insert(hash) {
+                return Err(anyhow::anyhow!("Output {hash} is picked twice"));
+            }
+            let coin = owned.get(hash).ok_or_else(|| {
+                anyhow::anyhow!("Output {hash} isn't an unspent one of the wallet")
+            })?;
+            if coin.pending {
+                return Err(anyhow::anyhow!(
+                    "Output {hash} is spent by a transaction waiting to be mined"
+                ));
+            }
+            if coin.retired {
+                return Err(anyhow::anyhow!(
+                    "Output {hash} is paid to a retired key, which the wallet can't spend from"
+                ));
+            }
+            picked.push(coin.output.clone());
         }
+        Ok(picked)
+    }
+
+    /// The unspent outputs of the wallet's keys, key by key
+    pub fn coins(&self) -> Vec<Coin> {
+        self.utxos
+            .utxos
+            .iter()
+            .flat_map(|entry| {
+                entry
+                    .value()
+                    .iter()
+                    .map(|(output, marked)| Coin {
+                        frozen: self.frozen.contains(&output.hash()),
+                        retired: self.utxos.private_key(entry.key()).is_none(),
+                        output: output.clone(),
+                        pending: *marked,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Keep the output `hash` out of coin selection until it's unfrozen. Only the wallet's
+    /// unspent outputs can be frozen
+    pub fn freeze(&self, hash: Hash) -> Result<()> {
+        if self.utxos.output(&hash).is_none() {
+            return Err(anyhow::anyhow!(
+                "Output {hash} isn't an unspent one of the wallet"
+            ));
+        }
+        self.frozen.insert(hash);
+        self.save_frozen()
+    }
+
+    /// Let coin selection spend the output `hash` again. Returns whether it was frozen
+    pub fn unfreeze(&self, hash: Hash) -> Result<bool> {
+        if self.frozen.remove(&hash).is_none() {
+            return Ok(false);
+        }
+        self.save_frozen()?;
+        Ok(true)
+    }
 
-        Ok(Transaction::new(inputs, outputs))
+    fn save_frozen(&self) -> Result<()> {
+        let frozen: Vec<Hash> = self.frozen.iter().map(|entry| *entry.value()).collect();
+        coins::save(&self.config.frozen, &frozen)
+    }
+
+    /// the node would refuse it anyway
+    fn check_policy(&self, transaction: &Transaction, fee: Amount) -> Result<()> {
+        let size = transaction.size();
+        if size > self.policy.max_transaction_size {
+            return Err(anyhow::anyhow!(
+                "Transaction has {size} bytes, the node takes at most {}",
+                self.policy.max_transaction_size
+            ));
+        }
+        let min_fee = self.policy.min_fee(size);
+        if fee < min_fee {
+            return Err(anyhow::anyhow!(
+                "Fee of {fee} is too low, the node wants at least {min_fee} for {size} bytes"
+            ));
+        }
+        Ok(())
     }
 
     /// Calculate fee noooo :(
-    pub fn calculate_fee(&self, amount: u64) -> u64 {
-        match self.config.fee_config.fee_type {
+    pub fn calculate_fee(&self, amount: Amount) -> Amount {
+        Amount::from_sat(match self.config.fee_config.fee_type {
             FeeType::Fixed => self.config.fee_config.value as u64,
-            FeeType::Percent => (amount as f64 * self.config.fee_config.value / 100.0) as u64,
+            FeeType::Percent => {
+                (amount.to_sat() as f64 * self.config.fee_config.value / 100.0) as u64
+            }
+        })
+    }
+
+    /// what the wallet can spend, leaving out frozen UTXOs, ones of retired keys and ones a
+    /// waiting transaction spends already
+    pub fn spendable_balance(&self) -> Amount {
+        Amount::checked_sum(
+            self.coins()
+                .into_iter()
+                .filter(Coin::is_spendable)
+                .map(|coin| coin.output.value),
+        )
+        .unwrap_or(Amount::MAX_MONEY)
+    }
+
+    /// what the frozen UTXOs hold
+    pub fn frozen_balance(&self) -> Amount {
+        Amount::checked_sum(
+            self.coins()
+                .into_iter()
+                .filter(|coin| coin.frozen)
+                .map(|coin| coin.output.value),
+        )
+        .unwrap_or(Amount::MAX_MONEY)
+    }
+
+    /// `amount` the way the config's `display` asks for
+    pub fn format_amount(&self, amount: Amount) -> String {
+        util::format_amount(amount, &self.config.display)
+    }
+
+    /// `format_amount` with what it's worth in fiat after it, if there is a recent price
+    pub fn format_with_fiat(&self, amount: Amount) -> String {
+        match self.fiat(amount) {
+            Some(fiat) => format!("{} (~{fiat})", self.format_amount(amount)),
+            None => self.format_amount(amount),
         }
     }
 
+    /// what `amount` is worth at the last price, if it's recent enough
+    pub fn fiat(&self, amount: Amount) -> Option<String> {
+        let price = self.price()?;
+        let value = amount.to_btc() * price.per_btc;
+        Some(util::format_fiat(
+            value,
+            &price.currency,
+            &self.config.display,
+        ))
+    }
+
+    /// the last price of a bitcoin, unless it's too old to show
+    pub fn price(&self) -> Option<Price> {
+        let max_age = self.config.price.as_ref()?.max_age();
+        let price = self.price.read().ok()?.clone()?;
+        (chrono::Utc::now() - price.fetched <= max_age).then_some(price)
+    }
+
+    /// where prices come from, if the config has a `[price]` table
+    pub fn price_source(&self) -> Option<String> {
+        self.price_provider.as_ref().map(|provider| provider.name())
+    }
+
+    /// Ask the price provider for the price of a bitcoin and keep it. `None` without a provider
+    pub async fn refresh_price(&self) -> Result<Option<Price>> {
+        let Some(provider) = &self.price_provider else {
+            return Ok(None);
+        };
+        let price = provider.fetch().await?;
+        debug!("1 BTC is {} {}", price.per_btc, price.currency);
+        if let Ok(mut cached) = self.price.write() {
+            *cached = Some(price.clone());
+        }
+        Ok(Some(price))
+    }
+
     /// Get the current balance yeeyy
-    pub fn get_balance(&self) -> u64 {
+    pub fn get_balance(&self) -> Amount {
         let balance = self
             .utxos
             .utxos
             .iter()
-            .map(|entry| entry.value().iter().map(|utxo| utxo.0.value).sum::<u64>())
-            .sum();
-        debug!("Current balance: {} satoshis", balance);
+            .try_fold(Amount::ZERO, |sum, entry| {
+                sum.checked_add(Amount::checked_sum(
+                    entry.value().iter().map(|utxo| utxo.0.value),
+                )?)
+            })
+            .unwrap_or(Amount::MAX_MONEY);
+        debug!("Current balance: {}", balance);
         balance
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use btclib::network::CompactHeader;
+    use tokio::net::TcpListener;
+
+    /// the hash of the block at `height` on chain `fork`
+    fn tip(height: u64, fork: u8) -> Hash {
+        Hash::of_bytes(&[&height.to_be_bytes()[..], &[fork]].concat())
+    }
+
+    fn output(key: &PublicKey, sats: u64) -> TransactionOutput {
+        TransactionOutput {
+            value: Amount::from_sat(sats),
+            unique_id: Uuid::new_v4(),
+            pubkey: key.clone(),
+        }
+    }
+
+    fn diff(height: u64, fork: u8, added: Vec<TransactionOutput>) -> UtxoDiff {
+        UtxoDiff {
+            height,
+            tip: tip(height, fork),
+            full: false,
+            added,
+            removed: vec![],
+            marked: vec![],
+        }
+    }
+
+    /// a store with one key, synced block by block up to `height` on chain 0, one output a block
+    fn synced(height: u64) -> (UtxoStore, PublicKey) {
+        let key = PrivateKey::new_key().public_key();
+        let mut store = UtxoStore::new();
+        store.add_key(LoadedKey {
+            public: key.clone(),
+            private: None,
+        });
+        for height in 1..=height {
+            store.apply_diff(&key, diff(height, 0, vec![output(&key, height)]));
+        }
+        (store, key)
+    }
+
+    fn values(store: &UtxoStore, key: &PublicKey) -> Vec<u64> {
+        store.utxos.get(key).map_or(vec![], |entry| {
+            entry
+                .value()
+                .iter()
+                .map(|(o, _)| o.value.to_sat())
+                .collect()
+        })
+    }
+
+    fn heights(store: &UtxoStore, key: &PublicKey) -> Vec<u64> {
+        let points = store.sync_points.get(key).unwrap();
+        points.value().iter().map(|point| point.height).collect()
+    }
+
+    #[test]
+    fn every_diff_leaves_a_sync_point() {
+        let (store, key) = synced(3);
+        assert_eq!(heights(&store, &key), [1, 2, 3]);
+        assert_eq!(store.height(&key), 3);
+        assert_eq!(values(&store, &key), [1, 2, 3]);
+
+        // only the marks changed, the point is replaced rather than added
+        let spent = store.utxos.get(&key).unwrap().value()[0].0.hash();
+        store.apply_diff(
+            &key,
+            UtxoDiff {
+                marked: vec![spent],
+                ..diff(3, 0, vec![])
+            },
+        );
+        assert_eq!(heights(&store, &key), [1, 2, 3]);
+        let points = store.sync_points.get(&key).unwrap();
+        let marks: Vec<bool> = points.value()[2].utxos.iter().map(|(_, m)| *m).collect();
+        assert_eq!(marks, [true, false, false]);
+
+        // a new block on the same height is a new point
+        store.apply_diff(&key, diff(3, 1, vec![]));
+        assert_eq!(heights(&store, &key), [1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn only_the_latest_sync_points_are_kept() {
+        let (store, key) = synced(SYNC_POINTS as u64 + 4);
+        assert_eq!(
+            heights(&store, &key),
+            (5..=SYNC_POINTS as u64 + 4).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn roll_back_to_the_newest_point_still_on_chain() {
+        let (store, key) = synced(5);
+        // the chain forked after block 3
+        let roll_back =
+            store.roll_back(&key, |height, hash| height <= 3 && *hash == tip(height, 0));
+        assert_eq!(roll_back, Some(3));
+        assert_eq!(heights(&store, &key), [1, 2, 3]);
+        assert_eq!(store.height(&key), 3);
+        assert_eq!(values(&store, &key), [1, 2, 3]);
+
+        // and the next diff carries on from there
+        store.apply_diff(&key, diff(4, 1, vec![output(&key, 40)]));
+        assert_eq!(values(&store, &key), [1, 2, 3, 40]);
+    }
+
+    #[test]
+    fn roll_back_with_no_point_on_chain_drops_everything() {
+        let (store, key) = synced(3);
+        assert_eq!(store.roll_back(&key, |_, _| false), Some(0));
+        assert_eq!(store.height(&key), 0);
+        assert!(heights(&store, &key).is_empty());
+        assert!(values(&store, &key).is_empty());
+    }
+
+    #[test]
+    fn no_roll_back_while_the_newest_point_is_on_chain() {
+        let (store, key) = synced(3);
+        assert_eq!(
+            store.roll_back(&key, |height, hash| *hash == tip(height, 0)),
+            None
+        );
+        assert_eq!(heights(&store, &key), [1, 2, 3]);
+
+        // the same height with only the mark changing is still no reorg
+        let spent = store.utxos.get(&key).unwrap().value()[0].0.hash();
+        store.apply_diff(
+            &key,
+            UtxoDiff {
+                marked: vec![spent],
+                ..diff(3, 0, vec![])
+            },
+        );
+        assert_eq!(
+            store.roll_back(&key, |height, hash| *hash == tip(height, 0)),
+            None
+        );
+        assert_eq!(values(&store, &key), [1, 2, 3]);
+
+        // a key that was never synced has nothing to roll back
+        let other = PrivateKey::new_key().public_key();
+        assert_eq!(store.roll_back(&other, |_, _| false), None);
+    }
+
+    /// a node answering header requests from a chain that is on fork 0 up to `fork_after` and on
+    /// fork 1 above it, up to `height`. Keys have nothing new since where they are nor any
+    /// history, and every transaction submitted is taken
+    async fn node(height: u64, fork_after: u64) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            loop {
+                let reply = match Message::receive_async(&mut stream).await {
+                    Ok(Message::FetchHeaderRange(start, _)) => {
+                        // the block at `start` is the one `start + 1` blocks in
+                        let at = start + 1;
+                        let headers = (at <= height)
+                            .then(|| CompactHeader {
+                                hash: tip(at, (at > fork_after) as u8),
+                                header: vec![],
+                            })
+                            .into_iter()
+                            .collect();
+                        Message::Headers(headers)
+                    }
+                    Ok(Message::FetchUTXOsSince(_, since)) => {
+                        Message::UTXOsSince(diff(since, 0, vec![]))
+                    }
+                    Ok(Message::SubmitTransaction(transaction)) => Message::Ack(transaction.hash()),
+                    Ok(Message::FetchHistory(_)) => Message::History(vec![]),
+                    _ => break,
+                };
+                reply.send_async(&mut stream).await.unwrap();
+            }
+        });
+        TcpStream::connect(address).await.unwrap()
+    }
+
+    fn connected(store: UtxoStore, stream: TcpStream) -> Core {
+        let config: Config = toml::from_str(
+            "keys = []\ncontacts = []\ndefault_node = \"\"\n\
+             [fee_config]\nfee_type = \"Fixed\"\nvalue = 0.0\n",
+        )
+        .unwrap();
+        Core::new(config, store, stream, MempoolPolicy::default())
+    }
+
+    #[tokio::test]
+    async fn detect_reorg_rolls_back_to_what_the_node_still_has() {
+        let (store, key) = synced(5);
+        let core = connected(store, node(6, 5).await);
+        assert_eq!(core.detect_reorg().await.unwrap(), None);
+        assert_eq!(core.utxos.height(&key), 5);
+
+        let (store, key) = synced(5);
+        let core = connected(store, node(6, 2).await);
+        assert_eq!(core.detect_reorg().await.unwrap(), Some(2));
+        assert_eq!(core.utxos.height(&key), 2);
+        assert_eq!(values(&core.utxos, &key), [1, 2]);
+
+        // the node's chain got shorter than the wallet's
+        let (store, key) = synced(5);
+        let core = connected(store, node(3, 3).await);
+        assert_eq!(core.detect_reorg().await.unwrap(), Some(3));
+        assert_eq!(values(&core.utxos, &key), [1, 2, 3]);
+
+        // nothing in common at all
+        let (store, key) = synced(3);
+        let core = connected(store, node(3, 0).await);
+        assert_eq!(core.detect_reorg().await.unwrap(), Some(0));
+        assert!(values(&core.utxos, &key).is_empty());
+    }
+
+    /// a wallet whose one key holds outputs of `values`, paying a fixed 10 sat fee and keeping
+    /// the frozen outputs in a file of its own
+    async fn funded(values: &[u64]) -> (Core, Vec<TransactionOutput>) {
+        let key = PrivateKey::new_key();
+        let mut store = UtxoStore::new();
+        store.add_key(LoadedKey {
+            public: key.public_key(),
+            private: Some(key.clone()),
+        });
+        let outputs: Vec<_> = values
+            .iter()
+            .map(|sats| output(&key.public_key(), *sats))
+            .collect();
+        store.apply_diff(&key.public_key(), diff(1, 0, outputs.clone()));
+        let mut core = connected(store, node(1, 1).await);
+        core.config.fee_config.value = 10.0;
+        core.config.frozen =
+            std::env::temp_dir().join(format!("wallet-frozen-{}.cbor", Uuid::new_v4()));
+        (core, outputs)
+    }
+
+    fn spent(transaction: &Transaction) -> Vec<Hash> {
+        transaction
+            .inputs
+            .iter()
+            .map(|input| input.prev_transaction_output_hash)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn frozen_coins_are_never_selected() {
+        let (core, outputs) = funded(&[1000, 2000, 3000]).await;
+        let recipient = PrivateKey::new_key().public_key();
+        core.freeze(outputs[0].hash()).unwrap();
+        core.freeze(outputs[2].hash()).unwrap();
+        assert!(core.freeze(Hash::of_bytes(b"someone else's")).is_err());
+        assert_eq!(core.spendable_balance(), Amount::from_sat(2000));
+        assert_eq!(core.frozen_balance(), Amount::from_sat(4000));
+
+        let transaction = core
+            .create_transaction(&recipient, Amount::from_sat(1500), None)
+            .unwrap();
+        assert_eq!(spent(&transaction), [outputs[1].hash()]);
+        assert!(
+            core.create_transaction(&recipient, Amount::from_sat(2500), None)
+                .is_err()
+        );
+
+        // unfrozen it's picked again, the other one still isn't
+        assert!(core.unfreeze(outputs[0].hash()).unwrap());
+        assert!(!core.unfreeze(outputs[0].hash()).unwrap());
+        let mut frozen = coins::load(&core.config.frozen).unwrap();
+        assert_eq!(frozen.pop(), Some(outputs[2].hash()));
+        assert!(frozen.is_empty());
+        let transaction = core
+            .create_transaction(&recipient, Amount::from_sat(2500), None)
+            .unwrap();
+        assert_eq!(spent(&transaction), [outputs[0].hash(), outputs[1].hash()]);
+        fs::remove_file(&core.config.frozen).unwrap();
+    }
+
+    #[tokio::test]
+    async fn coin_control_spends_only_the_picked_outputs() {
+        let (core, outputs) = funded(&[1000, 2000, 3000]).await;
+        let recipient = PrivateKey::new_key().public_key();
+        let amount = Amount::from_sat(500);
+        let transaction = core
+            .create_transaction_from(&recipient, amount, None, &[outputs[2].hash()])
+            .unwrap();
+        assert_eq!(spent(&transaction), [outputs[2].hash()]);
+
+        // all of them, frozen ones too
+        core.freeze(outputs[1].hash()).unwrap();
+        let picked = [outputs[1].hash(), outputs[0].hash()];
+        let transaction = core
+            .create_transaction_from(&recipient, amount, None, &picked)
+            .unwrap();
+        assert_eq!(spent(&transaction), picked);
+
+        let e = core
+            .create_transaction_from(
+                &recipient,
+                Amount::from_sat(995),
+                None,
+                &[outputs[0].hash()],
+            )
+            .unwrap_err();
+        assert!(e.to_string().starts_with("The outputs picked have"), "{e}");
+        let twice = [outputs[0].hash(), outputs[0].hash()];
+        assert!(
+            core.create_transaction_from(&recipient, amount, None, &twice)
+                .is_err()
+        );
+        let unknown = [Hash::of_bytes(b"someone else's")];
+        assert!(
+            core.create_transaction_from(&recipient, amount, None, &unknown)
+                .is_err()
+        );
+        fs::remove_file(&core.config.frozen).unwrap();
+    }
+
+    #[tokio::test]
+    async fn standing_orders_record_every_run() {
+        let (mut core, _) = funded(&[10_000]).await;
+        let dir = std::env::temp_dir().join(format!("wallet-schedules-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let alice = PrivateKey::new_key().public_key();
+        alice.save_to_file(dir.join("alice.pem")).unwrap();
+        core.config.contacts = vec![Recipient {
+            name: "alice".to_string(),
+            key: dir.join("alice.pem"),
+        }];
+        core.config.schedule_state = dir.join("schedule.cbor");
+        let order = |name: &str, recipient: &str| ScheduledPayment {
+            name: name.to_string(),
+            recipient: recipient.to_string(),
+            amount: Amount::from_sat(1000),
+            interval_secs: 3600,
+        };
+        core.config.schedules = vec![order("rent", "alice"), order("gift", "bob")];
+
+        let before = Utc::now();
+        core.run_schedules().await.unwrap();
+        let states = schedule::load(&core.config.schedule_state).unwrap();
+        let rent = &states["rent"];
+        assert_eq!(rent.runs.len(), 1);
+        let RunOutcome::Paid { txid } = rent.runs[0].outcome else {
+            panic!("expected rent to be paid: {}", rent.runs[0].outcome);
+        };
+        assert_eq!(core.broadcasts()[0].transaction.hash(), txid);
+        assert!(rent.runs[0].due >= before);
+        assert_eq!(rent.next, rent.runs[0].due + chrono::Duration::hours(1));
+        let gift = &states["gift"];
+        let RunOutcome::Skipped { reason } = &gift.runs[0].outcome else {
+            panic!("expected the gift to be skipped: {}", gift.runs[0].outcome);
+        };
+        assert_eq!(reason, "no contact called bob");
+        assert_eq!(gift.next, rent.next);
+
+        // neither is due again for an hour
+        core.run_schedules().await.unwrap();
+        let states = schedule::load(&core.config.schedule_state).unwrap();
+        assert_eq!(states["rent"].runs.len(), 1);
+        assert_eq!(states["gift"].runs.len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn paying_a_key_again_is_noticed() {
+        let (core, _) = funded(&[10_000]).await;
+        let payee = PrivateKey::new_key().public_key();
+        assert!(core.payments_to(&payee).await.is_empty());
+
+        let amount = Amount::from_sat(1000);
+        let first = core.create_transaction(&payee, amount, None).unwrap();
+        core.send_transaction(first.clone()).await.unwrap();
+        assert_eq!(
+            core.payments_to(&payee).await,
+            HashSet::from([first.hash()])
+        );
+
+        let again = core
+            .create_transaction(&payee, amount, Some("again".to_string()))
+            .unwrap();
+        core.send_transaction(again.clone()).await.unwrap();
+        assert_eq!(
+            core.payments_to(&payee).await,
+            HashSet::from([first.hash(), again.hash()])
+        );
+        let other = PrivateKey::new_key().public_key();
+        assert!(core.payments_to(&other).await.is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+//! Invoices the wallet asked to be paid, see `btclib::types::Invoice`. They are kept in the file
+//! the config's `invoices` points at, and marked paid once an output paying them shows up among
+//! the wallet's UTXOs.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use btclib::sha256::Hash;
+use btclib::types::Invoice;
+use chrono::{DateTime, Utc};
+use qrcode::QrCode;
+use qrcode::render::unicode::Dense1x2;
+use serde::{Deserialize, Serialize};
+
+/// how long invoices made with the `invoice` command are good for
+pub const INVOICE_EXPIRY_HOURS: i64 = 24;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Issued {
+    pub invoice: Invoice,
+    /// hash of the output that paid it
+    pub paid: Option<Hash>,
+}
+
+pub enum InvoiceStatus {
+    Open,
+    Paid(Hash),
+    Expired,
+}
+
+impl fmt::Display for InvoiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvoiceStatus::Open => write!(f, "open"),
+            InvoiceStatus::Paid(output) => write!(f, "paid by output {output}"),
+            InvoiceStatus::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+impl Issued {
+    /// a payment that shows up late still counts
+    pub fn status(&self, now: DateTime<Utc>) -> InvoiceStatus {
+        match self.paid {
+            Some(output) => InvoiceStatus::Paid(output),
+            None if self.invoice.is_expired(now) => InvoiceStatus::Expired,
+            None => InvoiceStatus::Open,
+        }
+    }
+}
+
+/// the invoices saved at `path`, none if there is no file yet
+pub fn load(path: &Path) -> Result<Vec<Issued>> {
+    match fs::read(path) {
+        Ok(data) => Ok(ciborium::from_reader(data.as_slice())?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save(path: &Path, invoices: &[Issued]) -> Result<()> {
+    let mut data = vec![];
+    ciborium::into_writer(invoices, &mut data)?;
+    fs::write(path, data)?;
+    Ok(())
+}
+
+/// `invoice`'s URI as a QR code to print on a terminal. Uppercase, which fits a smaller code
+pub fn qr(invoice: &Invoice) -> Result<String> {
+    let code = QrCode::new(invoice.to_uri().to_uppercase())?;
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
@@ -0,0 +1,25 @@
+//! A toy wallet, as a library so tests and other programs can load one and pay with it in-process
+//! instead of driving the binary:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! let core = wallet::core::Core::load_config("wallet_config.toml".into()).await?;
+//! core.fetch_utxos().await?;
+//! println!("{}", core.get_balance());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The interactive prompt, the plain commands and the threshold commands stay in the binary.
+
+pub mod backup;
+pub mod coins;
+pub mod core;
+pub mod invoices;
+pub mod price;
+pub mod receive;
+pub mod records;
+pub mod rotate;
+pub mod schedule;
+pub mod util;
+pub mod wallets;
@@ -2,16 +2,24 @@ use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use tokio::time::{self, Duration};
 
-use btclib::types::Transaction;
+use btclib::crypto::{PrivateKey, PublicKey, Signature};
+use btclib::finality::Finality;
+use btclib::sha256::Hash;
+use btclib::types::{Amount, INVOICE_URI_SCHEME, Invoice, Transaction};
+use btclib::util::Saveable;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio::task::JoinHandle;
 
-use crate::core::*;
+use crate::plain::PlainCommand;
+use crate::threshold::ThresholdCommand;
+use wallet::core::*;
+use wallet::{backup, invoices, receive, records, rotate, util, wallets};
 
-mod core;
+mod plain;
 mod tasks;
-mod util;
+mod threshold;
 
 #[derive(Parser)]
 #[command(author, version, about,long_about = None)]
@@ -22,6 +30,16 @@ struct Cli {
     config: Option<PathBuf>,
     #[arg(short, long, value_name = "ADDRESS")]
     node: Option<String>,
+    /// run one of the wallets made with new-wallet instead of the one --config points at
+    #[arg(short, long, value_name = "NAME", conflicts_with = "config")]
+    wallet: Option<String>,
+    /// never start the interactive prompt, e.g. for screen readers and scripts. Run one of
+    /// balance, send, receive, history or contacts with it
+    #[arg(long)]
+    no_ui: bool,
+    /// print what the plain commands output as JSON
+    #[arg(long)]
+    json: bool,
 }
 
 /// This tells us a little about how the wallet should function. It should read a config
@@ -37,6 +55,89 @@ enum Commands {
         #[arg(short, long, value_name = "FILE")]
         output: PathBuf,
     },
+    /// Create a wallet of its own with a new key pair under `wallets/`, to run with --wallet
+    NewWallet { name: String },
+    /// Seal the config, keys, contacts and history of the wallet into one file with a passphrase
+    Backup { file: PathBuf },
+    /// Put the files of a backup back where they were and rescan the chain
+    Restore {
+        file: PathBuf,
+        /// overwrite files that are there already with other contents
+        #[arg(long)]
+        force: bool,
+    },
+    /// Add a key pair that was used elsewhere to the wallet, and have the node scan the chain
+    /// for what it owns
+    ImportKey {
+        #[arg(long, value_name = "FILE")]
+        public: PathBuf,
+        #[arg(long, value_name = "FILE")]
+        private: PathBuf,
+        /// the height the key was first used at, its history starts there
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+    },
+    /// Write what the wallet's keys took part in to a file for record keeping. Needs a node with
+    /// --addrindex
+    ExportTransactions {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = records::Format::Csv)]
+        format: records::Format,
+        /// first day to export, as YYYY-MM-DD in UTC
+        #[arg(long)]
+        since: Option<chrono::NaiveDate>,
+        /// last day to export
+        #[arg(long)]
+        until: Option<chrono::NaiveDate>,
+    },
+    /// Read a raw transaction made elsewhere, as hex, from a file or `-` for stdin, and sign
+    /// the inputs spending the wallet's outputs
+    ImportTransaction {
+        source: String,
+        /// send it to the node once signed, instead of printing it
+        #[arg(long)]
+        send: bool,
+        /// write the signed transaction's hex here instead of printing it
+        #[arg(short, long, value_name = "FILE", conflicts_with = "send")]
+        output: Option<PathBuf>,
+    },
+    /// Make a new key pair for the wallet to be paid to, sweep what the old keys hold to it and
+    /// retire them once that's mined
+    RotateKeys {
+        /// encrypt the new private key, prompting for the passphrase
+        #[arg(long)]
+        encrypt: bool,
+        /// also copy the new public key here, to hand out to whoever pays the wallet
+        #[arg(long, value_name = "FILE")]
+        export: Option<PathBuf>,
+        /// seconds to wait for the sweep to be mined before leaving the old keys to retire-keys
+        #[arg(long, default_value_t = 600)]
+        wait: u64,
+    },
+    /// Retire the keys after the first one that hold nothing anymore, e.g. once a rotate-keys
+    /// sweep is mined
+    RetireKeys,
+    /// Sign a message with a private key to prove you control it
+    SignMessage {
+        #[arg(short, long, value_name = "FILE")]
+        key: PathBuf,
+        message: String,
+    },
+    /// Check a hex signature made with sign-message
+    VerifyMessage {
+        #[arg(short, long, value_name = "FILE")]
+        key: PathBuf,
+        #[arg(short, long)]
+        signature: String,
+        message: String,
+    },
+    /// Threshold (t-of-n) keys: run the key generation ceremony and co-sign transactions
+    Threshold {
+        #[command(subcommand)]
+        command: ThresholdCommand,
+    },
+    #[command(flatten)]
+    Plain(PlainCommand),
 }
 
 #[tokio::main]
@@ -46,26 +147,412 @@ async fn main() -> Result<()> {
         Some(Commands::GenerateConfig { output }) => {
             return generate_dummy_config(output);
         }
+        Some(Commands::SignMessage { key, message }) => {
+            let key = PrivateKey::load_from_file(&key)
+                .with_context(|| format!("Failed to load private key: {}", key.display()))?;
+            let signature = key.sign_message(message.as_bytes());
+            println!("{}", hex::encode(signature.to_compact()));
+            return Ok(());
+        }
+        Some(Commands::VerifyMessage {
+            key,
+            signature,
+            message,
+        }) => {
+            let key = PublicKey::load_from_file(&key)
+                .with_context(|| format!("Failed to load public key: {}", key.display()))?;
+            let signature = Signature::from_compact(&hex::decode(signature)?)?;
+            if key.verify_message(message.as_bytes(), &signature) {
+                println!("Signature is valid");
+                return Ok(());
+            }
+            return Err(anyhow::anyhow!(
+                "Signature is not valid for this key and message"
+            ));
+        }
+        Some(Commands::Threshold { command }) => {
+            return threshold::run(command);
+        }
+        Some(Commands::NewWallet { name }) => {
+            let node = cli.node.unwrap_or_else(|| "127.0.0.1:9000".to_string());
+            let path = wallets::create(&name, node)?;
+            println!("Wallet {name} created at: {}", path.display());
+            return Ok(());
+        }
+        Some(Commands::Backup { file }) => {
+            let config_path = config_path(cli.wallet.as_deref(), cli.config)?;
+            // the node may not be around, or not have the addrindex
+            let history = match Core::load_config(config_path.clone()).await {
+                Ok(core) => core.fetch_history().await,
+                Err(e) => Err(e),
+            };
+            let history = history.unwrap_or_else(|e| {
+                println!("Leaving out the history: {e}");
+                vec![]
+            });
+            let summary = backup::backup(&config_path, history, &file)?;
+            println!(
+                "Backed up {} files and {} history entries to: {}",
+                summary.files,
+                summary.history,
+                file.display()
+            );
+            return Ok(());
+        }
+        Some(Commands::Restore { file, force }) => {
+            let summary = backup::restore(&file, force)?;
+            println!(
+                "Restored {} files, the config is at: {}",
+                summary.files,
+                summary.config_path.display()
+            );
+            let mut core = Core::load_config(summary.config_path)
+                .await
+                .with_context(|| "Restored, but failed to load the config to rescan")?;
+            if let Some(node) = cli.node {
+                core.config.default_node = node;
+            }
+            core.rescan().await?;
+            println!(
+                "Rescanned, balance: {}",
+                core.format_with_fiat(core.get_balance())
+            );
+            match core.fetch_history().await {
+                Ok(history) => println!(
+                    "The node has {} history entries for the keys, the backup had {}",
+                    history.len(),
+                    summary.history
+                ),
+                Err(e) => println!("Failed to fetch history: {e}"),
+            }
+            return Ok(());
+        }
+        Some(Commands::ImportKey {
+            public,
+            private,
+            from,
+        }) => {
+            let config_path = config_path(cli.wallet.as_deref(), cli.config)?;
+            import_key(&config_path, public, private)?;
+            let mut core = Core::load_config(config_path)
+                .await
+                .with_context(|| "Imported, but failed to load the config to scan")?;
+            if let Some(node) = cli.node {
+                core.config.default_node = node;
+            }
+            let history = core.scan_keys(from).await?;
+            println!(
+                "Scanned from block {from}, {} history entries, balance: {}",
+                history.len(),
+                core.format_with_fiat(core.get_balance())
+            );
+            return Ok(());
+        }
+        Some(Commands::RotateKeys {
+            encrypt,
+            export,
+            wait,
+        }) => {
+            let config_path = config_path(cli.wallet.as_deref(), cli.config)?;
+            return rotate_keys(&config_path, cli.node, encrypt, export, wait).await;
+        }
+        Some(Commands::RetireKeys) => {
+            let config_path = config_path(cli.wallet.as_deref(), cli.config)?;
+            let core = load_core(&config_path, cli.node).await?;
+            let old: Vec<Key> = core
+                .config
+                .keys
+                .iter()
+                .filter(|key| !key.retired)
+                .skip(1)
+                // whoever was handed one may still pay it
+                .filter(|key| key.handed_out.is_none())
+                .cloned()
+                .collect();
+            let empty = empty_keys(&core, &old).await?;
+            let retired = rotate::retire(&config_path, &empty)?;
+            println!(
+                "Retired {retired} keys, {} still hold coins",
+                old.len() - empty.len()
+            );
+            return Ok(());
+        }
+        Some(Commands::ExportTransactions {
+            file,
+            format,
+            since,
+            until,
+        }) => {
+            let config_path = config_path(cli.wallet.as_deref(), cli.config)?;
+            let mut core = Core::load_config(config_path)
+                .await
+                .with_context(|| "Failed to load config")?;
+            if let Some(node) = cli.node {
+                core.config.default_node = node;
+            }
+            let records = records::export(&core, since, until).await?;
+            let contents = match format {
+                records::Format::Csv => records::to_csv(&records),
+                records::Format::Json => serde_json::to_string_pretty(&records)?,
+            };
+            std::fs::write(&file, contents)?;
+            println!(
+                "Exported {} transactions to: {}",
+                records.len(),
+                file.display()
+            );
+            return Ok(());
+        }
+        Some(Commands::ImportTransaction {
+            source,
+            send,
+            output,
+        }) => {
+            let mut transaction = records::read_raw(&source)?;
+            let config_path = config_path(cli.wallet.as_deref(), cli.config)?;
+            let mut core = Core::load_config(config_path)
+                .await
+                .with_context(|| "Failed to load config")?;
+            if let Some(node) = cli.node {
+                core.config.default_node = node;
+            }
+            core.fetch_utxos().await?;
+            let signed = core.sign_inputs(&mut transaction);
+            println!(
+                "Signed {signed} of {} inputs, the transaction pays {} to {} outputs",
+                transaction.inputs.len(),
+                core.format_amount(transaction.output_value()?),
+                transaction.outputs.len()
+            );
+            if let Some(memo) = &transaction.memo {
+                println!("Memo: {memo}");
+            }
+            if !send {
+                match output {
+                    Some(path) => {
+                        std::fs::write(&path, transaction.to_hex())?;
+                        println!("Saved to: {}", path.display());
+                    }
+                    None => println!("{}", transaction.to_hex()),
+                }
+                return Ok(());
+            }
+            if let Err(rejection) = core.test_transaction(&transaction).await? {
+                return Err(anyhow::anyhow!(
+                    "The node would refuse it ({:?}): {}",
+                    rejection.code,
+                    rejection.reason
+                ));
+            }
+            core.send_transaction(transaction.clone()).await?;
+            println!("Sent {}", transaction.hash());
+            return Ok(());
+        }
+        Some(Commands::Plain(command)) => {
+            let config_path = config_path(cli.wallet.as_deref(), cli.config)?;
+            return plain::run(command, &config_path, cli.node, cli.json).await;
+        }
+        None if cli.no_ui => {
+            return Err(anyhow::anyhow!(
+                "--no-ui needs a command: balance, send, receive, history or contacts"
+            ));
+        }
         None => {}
     }
-    let config_path = cli
-        .config
-        .unwrap_or_else(|| PathBuf::from("wallet_config.toml"));
-    let mut core = Core::load_config(config_path.clone())
+    let config_path = config_path(cli.wallet.as_deref(), cli.config)?;
+    let session = Session::open(cli.wallet, config_path, cli.node).await?;
+    run_cli(session).await?;
+    Ok(())
+}
+
+/// the config --wallet or else --config points at
+fn config_path(wallet: Option<&str>, config: Option<PathBuf>) -> Result<PathBuf> {
+    match wallet {
+        Some(name) => wallets::config_path(name),
+        None => Ok(config.unwrap_or_else(|| PathBuf::from("wallet_config.toml"))),
+    }
+}
+
+async fn load_core(config_path: &Path, node: Option<String>) -> Result<Core> {
+    let mut core = Core::load_config(config_path.to_path_buf())
         .await
         .with_context(|| "Failed to load config")?;
-    if let Some(node) = cli.node {
+    if let Some(node) = node {
         core.config.default_node = node;
     }
-    let (tx_sender, tx_receiver) = kanal::bounded(10);
-    core.tx_sender = tx_sender;
-    let core = Arc::new(core);
-    tokio::spawn(update_utxos(core.clone()));
-    tokio::spawn(handle_transactions(tx_receiver.clone_async(), core.clone()));
-    run_cli(core).await?;
+    Ok(core)
+}
+
+/// the public key files of the keys in `keys` that hold no coins, per the node
+async fn empty_keys(core: &Core, keys: &[Key]) -> Result<Vec<PathBuf>> {
+    core.fetch_utxos().await?;
+    let coins = core.coins();
+    let mut empty = vec![];
+    for key in keys {
+        let public = PublicKey::load_from_file(&key.public)
+            .with_context(|| format!("Failed to load public key: {}", key.public.display()))?;
+        if !coins.iter().any(|coin| coin.output.pubkey == public) {
+            empty.push(key.public.clone());
+        }
+    }
+    Ok(empty)
+}
+
+/// how often `rotate_keys` checks whether the sweep was mined
+const SWEEP_POLL: Duration = Duration::from_secs(5);
+
+/// The rotate-keys walkthrough: a new key first in the config, a sweep of the old ones' coins to
+/// it, the new key handed out, and the old keys retired once the sweep is mined
+async fn rotate_keys(
+    config_path: &Path,
+    node: Option<String>,
+    encrypt: bool,
+    export: Option<PathBuf>,
+    wait: u64,
+) -> Result<()> {
+    let old: Vec<Key> = Config::load(config_path)?
+        .keys
+        .into_iter()
+        .filter(|key| !key.retired)
+        .collect();
+    if old.is_empty() {
+        return Err(anyhow::anyhow!("The wallet has no keys to rotate"));
+    }
+    let passphrase = encrypt.then(rotate::prompt_passphrase).transpose()?;
+    let key = rotate::add_key(config_path, passphrase.as_deref())?;
+    println!("New key: {}", key.public.display());
+    let new = PublicKey::load_from_file(&key.public)?;
+
+    let core = load_core(config_path, node).await?;
+    core.fetch_utxos().await?;
+    let mut from = vec![];
+    for key in &old {
+        from.push(PublicKey::load_from_file(&key.public)?);
+    }
+    if core
+        .coins()
+        .iter()
+        .any(|coin| !coin.pending && from.contains(&coin.output.pubkey))
+    {
+        let transaction = core.sweep(&from, &new)?;
+        if let Err(rejection) = core.test_transaction(&transaction).await? {
+            return Err(anyhow::anyhow!(
+                "The node would refuse the sweep ({:?}): {}",
+                rejection.code,
+                rejection.reason
+            ));
+        }
+        core.send_transaction(transaction.clone()).await?;
+        println!(
+            "Sweeping {} to the new key in {}",
+            core.format_amount(transaction.output_value()?),
+            transaction.hash()
+        );
+    }
+
+    if let Some(path) = export {
+        std::fs::copy(&key.public, &path)?;
+        println!("Hand out {} to be paid from now on", path.display());
+    }
+    let old_files: Vec<PathBuf> = old.iter().map(|key| key.public.clone()).collect();
+    for name in rotate::repoint_contacts(&old_files, &key.public)? {
+        println!("Wallet {name} now pays the new key");
+    }
+
+    let deadline = time::Instant::now() + Duration::from_secs(wait);
+    loop {
+        if empty_keys(&core, &old).await?.len() == old.len() {
+            let retired = rotate::retire(config_path, &old_files)?;
+            println!("Retired {retired} old keys, they are only watched from now on");
+            return Ok(());
+        }
+        if time::Instant::now() >= deadline {
+            println!("The sweep isn't mined yet, run retire-keys once it is");
+            return Ok(());
+        }
+        time::sleep(SWEEP_POLL).await;
+    }
+}
+
+/// Add the key pair in `public` and `private` to the config at `config_path`, once they are
+/// checked to go together
+fn import_key(config_path: &Path, public: PathBuf, private: PathBuf) -> Result<()> {
+    let pubkey = PublicKey::load_from_file(&public)
+        .with_context(|| format!("Failed to load public key: {}", public.display()))?;
+    let privkey = PrivateKey::load_from_file(&private)
+        .with_context(|| format!("Failed to load private key: {}", private.display()))?;
+    if privkey.public_key() != pubkey {
+        return Err(anyhow::anyhow!(
+            "The private key doesn't go with the public key"
+        ));
+    }
+    let mut config: Config = toml::from_str(&std::fs::read_to_string(config_path)?)
+        .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
+    if config.keys.iter().any(|key| key.public == public) {
+        return Err(anyhow::anyhow!(
+            "{} is in the wallet already",
+            public.display()
+        ));
+    }
+    config.keys.push(Key {
+        public,
+        private,
+        retired: false,
+        label: None,
+        handed_out: None,
+    });
+    std::fs::write(config_path, toml::to_string_pretty(&config)?)?;
     Ok(())
 }
 
+/// A loaded wallet and the tasks keeping it up to date, they stop when it's dropped
+struct Session {
+    /// `None` for the one --config points at
+    name: Option<String>,
+    config_path: PathBuf,
+    core: Arc<Core>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Session {
+    async fn open(
+        name: Option<String>,
+        config_path: PathBuf,
+        node: Option<String>,
+    ) -> Result<Self> {
+        let mut core = Core::load_config(config_path.clone())
+            .await
+            .with_context(|| "Failed to load config")?;
+        if let Some(node) = node {
+            core.config.default_node = node;
+        }
+        let (tx_sender, tx_receiver) = kanal::bounded(10);
+        core.tx_sender = tx_sender;
+        let core = Arc::new(core);
+        let tasks = vec![
+            update_utxos(core.clone()).await,
+            handle_transactions(tx_receiver.clone_async(), core.clone()).await,
+            monitor_broadcasts(core.clone()).await,
+            run_schedules(core.clone()).await,
+            refresh_price(core.clone()).await,
+        ];
+        Ok(Session {
+            name,
+            config_path,
+            core,
+            tasks,
+        })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
 fn generate_dummy_config(path: PathBuf) -> Result<()> {
     let dummy_config = Config {
         keys: vec![],
@@ -84,6 +571,14 @@ fn generate_dummy_config(path: PathBuf) -> Result<()> {
             fee_type: FeeType::Percent,
             value: 0.1,
         },
+        invoices: PathBuf::from("invoices.cbor"),
+        schedules: vec![],
+        schedule_state: PathBuf::from("schedules.cbor"),
+        display: util::DisplayConfig::default(),
+        price: None,
+        frozen: PathBuf::from("frozen.cbor"),
+        trusted_nodes: vec![],
+        finality: Finality::default(),
     };
 
     let config_str = toml::to_string_pretty(&dummy_config)?;
@@ -92,9 +587,67 @@ fn generate_dummy_config(path: PathBuf) -> Result<()> {
     Ok(())
 }
 
-async fn run_cli(core: Arc<Core>) -> Result<()> {
+/// runs of each standing order the `schedules` command lists
+const SCHEDULE_RUNS_SHOWN: usize = 5;
+
+/// an invoice from its URI, or from the file `invoice` saved it to
+fn read_invoice(source: &str) -> Result<Invoice> {
+    let scheme = INVOICE_URI_SCHEME.len();
+    if source
+        .get(..scheme)
+        .is_some_and(|start| start.eq_ignore_ascii_case(INVOICE_URI_SCHEME))
+    {
+        return Ok(Invoice::from_uri(source)?);
+    }
+    Invoice::load_from_file(source).with_context(|| format!("Failed to load invoice: {source}"))
+}
+
+/// Say so if `key`, which `payee` is paid to, was paid before
+async fn warn_reuse(core: &Core, key: &PublicKey, payee: &str) {
+    let paid = core.payments_to(key).await.len();
+    if paid > 0 {
+        println!(
+            "Warning: {payee} was paid to this key {paid} times before, paying it again ties the \
+             payments together. Ask for a fresh one, e.g. from their wallet's `receive`"
+        );
+    }
+}
+
+/// Send `transaction` once the node says it would take it
+async fn test_and_send(core: &Core, transaction: Transaction) -> Result<()> {
+    // see whether the node takes it before it's out there
+    match core.test_transaction(&transaction).await {
+        Ok(Ok(acceptance)) => {
+            println!("Paying a fee of {}", core.format_amount(acceptance.fee));
+            if !acceptance.replaces.is_empty() {
+                println!(
+                    "Replacing {} waiting transactions",
+                    acceptance.replaces.len()
+                );
+            }
+        }
+        Ok(Err(rejection)) => {
+            println!(
+                "The node would refuse it ({:?}): {}",
+                rejection.code, rejection.reason
+            );
+            return Ok(());
+        }
+        Err(e) => println!("Failed to test the transaction: {e}"),
+    }
+    core.tx_sender.send(transaction)?;
+    println!("Transaction sent successfully");
+    core.fetch_utxos().await?;
+    Ok(())
+}
+
+async fn run_cli(mut session: Session) -> Result<()> {
     loop {
-        print!("> ");
+        let core = session.core.clone();
+        match &session.name {
+            Some(name) => print!("{name}> "),
+            None => print!("> "),
+        }
         io::stdout().flush()?;
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
@@ -105,15 +658,27 @@ async fn run_cli(core: Arc<Core>) -> Result<()> {
 
         match parts[0] {
             "balance" => {
-                println!("Current balance: {} BTC", core.get_balance() / (10 * 8));
+                println!(
+                    "Current balance: {}",
+                    core.format_with_fiat(core.get_balance())
+                );
+                let frozen = core.frozen_balance();
+                if frozen > Amount::ZERO {
+                    println!(
+                        "Frozen: {}, spendable: {}",
+                        core.format_amount(frozen),
+                        core.format_amount(core.spendable_balance())
+                    );
+                }
             }
             "send" => {
-                if parts.len() != 3 {
-                    println!("Usage: send <recipient> <amount>");
+                if parts.len() < 3 {
+                    println!("Usage: send <recipient> <amount> [memo]");
                     continue;
                 }
                 let recipient = parts[1];
-                let amount: u64 = parts[2].parse()?;
+                let amount = Amount::from_sat(parts[2].parse()?);
+                let memo = (parts.len() > 3).then(|| parts[3..].join(" "));
                 let recipient_key = core
                     .config
                     .contacts
@@ -125,13 +690,446 @@ async fn run_cli(core: Arc<Core>) -> Result<()> {
                 if let Err(e) = core.fetch_utxos().await {
                     println!("Failed to fetch utxos: {e}");
                 };
-                let transaction = core.create_transaction(&recipient_key, amount)?;
-                core.tx_sender.send(transaction)?;
-                println!("Transaction sent successfully");
-                core.fetch_utxos().await?;
+                warn_reuse(&core, &recipient_key, recipient).await;
+                let transaction = core.create_transaction(&recipient_key, amount, memo)?;
+                if let Some(memo) = &transaction.memo {
+                    println!("Memo: {memo}");
+                }
+                test_and_send(&core, transaction).await?;
+            }
+            "sendfrom" => {
+                let (Some(recipient), Some(Ok(amount)), Some(picked)) = (
+                    parts.get(1),
+                    parts.get(2).map(|amount| amount.parse()),
+                    parts.get(3),
+                ) else {
+                    println!("Usage: sendfrom <recipient> <amount> <output>[,<output>...] [memo]");
+                    continue;
+                };
+                let picked: Result<Vec<Hash>, _> = picked.split(',').map(str::parse).collect();
+                let Ok(picked) = picked else {
+                    println!("Outputs go by their hash, as \"coins\" lists them");
+                    continue;
+                };
+                let memo = (parts.len() > 4).then(|| parts[4..].join(" "));
+                let recipient_key = match core
+                    .config
+                    .contacts
+                    .iter()
+                    .find(|r| r.name == *recipient)
+                    .map(Recipient::load)
+                {
+                    Some(Ok(recipient)) => recipient.key,
+                    Some(Err(e)) => {
+                        println!("Failed to load the contact's key: {e}");
+                        continue;
+                    }
+                    None => {
+                        println!("Recipient not found");
+                        continue;
+                    }
+                };
+                if let Err(e) = core.fetch_utxos().await {
+                    println!("Failed to fetch utxos: {e}");
+                };
+                warn_reuse(&core, &recipient_key, recipient).await;
+                let amount = Amount::from_sat(amount);
+                match core.create_transaction_from(&recipient_key, amount, memo, &picked) {
+                    Ok(transaction) => test_and_send(&core, transaction).await?,
+                    Err(e) => println!("Failed to create the transaction: {e}"),
+                }
+            }
+            "coins" => {
+                let coins = core.coins();
+                if coins.is_empty() {
+                    println!("No unspent outputs");
+                }
+                for coin in coins {
+                    let states: Vec<&str> = [
+                        (coin.frozen, "frozen"),
+                        (coin.retired, "retired key"),
+                        (coin.pending, "being spent"),
+                    ]
+                    .into_iter()
+                    .filter_map(|(set, state)| set.then_some(state))
+                    .collect();
+                    let state = if states.is_empty() {
+                        String::new()
+                    } else {
+                        format!("  {}", states.join(", "))
+                    };
+                    println!(
+                        "{}  {}{state}",
+                        coin.output.hash(),
+                        core.format_amount(coin.output.value)
+                    );
+                }
+            }
+            "freeze" | "unfreeze" => {
+                let Some(Ok(hash)) = parts.get(1).map(|hash| hash.parse::<Hash>()) else {
+                    println!("Usage: {} <output>", parts[0]);
+                    continue;
+                };
+                if parts[0] == "freeze" {
+                    match core.freeze(hash) {
+                        Ok(()) => println!("Output {hash} is frozen"),
+                        Err(e) => println!("Failed to freeze the output: {e}"),
+                    }
+                } else {
+                    match core.unfreeze(hash) {
+                        Ok(true) => println!("Output {hash} can be spent again"),
+                        Ok(false) => println!("Output {hash} wasn't frozen"),
+                        Err(e) => println!("Failed to unfreeze the output: {e}"),
+                    }
+                }
+            }
+            "invoice" => {
+                let Some(Ok(amount)) = parts.get(1).map(|amount| amount.parse()) else {
+                    println!("Usage: invoice <amount> [memo]");
+                    continue;
+                };
+                let memo = parts.get(2..).unwrap_or_default().join(" ");
+                let expiry = chrono::Duration::hours(invoices::INVOICE_EXPIRY_HOURS);
+                let invoice = match core.create_invoice(Amount::from_sat(amount), memo, expiry) {
+                    Ok(invoice) => invoice,
+                    Err(e) => {
+                        println!("Failed to create the invoice: {e}");
+                        continue;
+                    }
+                };
+                let file = PathBuf::from(format!("invoice-{}.cbor", invoice.id.simple()));
+                invoice.save_to_file(&file)?;
+                match invoices::qr(&invoice) {
+                    Ok(qr) => println!("{qr}"),
+                    Err(e) => println!("Failed to draw a QR code: {e}"),
+                }
+                println!("{}", invoice.to_uri());
+                println!(
+                    "Invoice {} for {} saved to: {}, it expires at {}",
+                    invoice.id,
+                    core.format_amount(invoice.amount),
+                    file.display(),
+                    invoice.expires
+                );
+            }
+            "invoices" => {
+                let issued = core.invoices();
+                if issued.is_empty() {
+                    println!("No invoices yet");
+                }
+                let now = chrono::Utc::now();
+                for issued in issued {
+                    println!(
+                        "{}  {}  {}  {}",
+                        issued.invoice.id,
+                        core.format_amount(issued.invoice.amount),
+                        issued.status(now),
+                        issued.invoice.memo
+                    );
+                }
+            }
+            "pay" => {
+                let Some(source) = parts.get(1) else {
+                    println!("Usage: pay <invoice URI or file>");
+                    continue;
+                };
+                let invoice = match read_invoice(source) {
+                    Ok(invoice) => invoice,
+                    Err(e) => {
+                        println!("Failed to read the invoice: {e}");
+                        continue;
+                    }
+                };
+                let payee = core
+                    .config
+                    .contacts
+                    .iter()
+                    .filter_map(|contact| contact.load().ok())
+                    .find(|contact| contact.key == invoice.pubkey)
+                    .map_or_else(
+                        || "a key that is not a contact".to_string(),
+                        |contact| contact.name,
+                    );
+                println!(
+                    "Invoice for {} to {payee}, expires at {}",
+                    core.format_with_fiat(invoice.amount),
+                    invoice.expires
+                );
+                if !invoice.memo.is_empty() {
+                    println!("Memo: {}", invoice.memo);
+                }
+                if let Err(e) = core.fetch_utxos().await {
+                    println!("Failed to fetch utxos: {e}");
+                };
+                warn_reuse(&core, &invoice.pubkey, &payee).await;
+                match core.pay_invoice(&invoice) {
+                    Ok(transaction) => test_and_send(&core, transaction).await?,
+                    Err(e) => println!("Failed to pay the invoice: {e}"),
+                }
+            }
+            "history" => match (core.fetch_history().await, core.chain_tip().await) {
+                (Ok(history), _) if history.is_empty() => println!("No transactions yet"),
+                (Ok(history), Ok(tip)) => {
+                    let policy = core.config.finality.policy();
+                    for activity in history {
+                        println!(
+                            "block {:>5}  {}  received {}  sent {}{}",
+                            activity.height,
+                            activity.txid,
+                            core.format_with_fiat(activity.received),
+                            core.format_with_fiat(activity.sent),
+                            if policy.is_final(activity.height, &tip) {
+                                "  final"
+                            } else {
+                                ""
+                            }
+                        );
+                    }
+                }
+                (Err(e), _) | (_, Err(e)) => println!("Failed to fetch history: {e}"),
+            },
+            "rescan" => match parts.get(1).map(|from| from.parse::<u64>()) {
+                None => match core.rescan().await {
+                    Ok(()) => println!(
+                        "Rescanned, balance: {}",
+                        core.format_with_fiat(core.get_balance())
+                    ),
+                    Err(e) => println!("Failed to rescan: {e}"),
+                },
+                Some(Ok(from)) => match core.scan_keys(from).await {
+                    Ok(history) => {
+                        for activity in &history {
+                            println!(
+                                "block {:>5}  {}  received {}  sent {}",
+                                activity.height,
+                                activity.txid,
+                                core.format_amount(activity.received),
+                                core.format_amount(activity.sent)
+                            );
+                        }
+                        println!(
+                            "Rescanned, balance: {}",
+                            core.format_with_fiat(core.get_balance())
+                        );
+                    }
+                    Err(e) => println!("Failed to rescan: {e}"),
+                },
+                Some(Err(_)) => println!("Usage: rescan [from_height]"),
+            },
+            "status" => match core.fetch_status().await {
+                Ok(status) => println!("{status}"),
+                Err(e) => println!("Failed to fetch node status: {e}"),
+            },
+            "mempool" => match parts.get(1) {
+                None => match core.fetch_mempool().await {
+                    Ok(mempool) if mempool.is_empty() => println!("The mempool is empty"),
+                    Ok(mempool) => {
+                        for info in mempool {
+                            println!(
+                                "#{:<5} {}  {:.2} sat/byte  fee {}  waiting {}",
+                                info.position + 1,
+                                info.txid,
+                                info.fee_rate(),
+                                core.format_amount(info.fee),
+                                util::waiting(&info)
+                            );
+                        }
+                    }
+                    Err(e) => println!("Failed to fetch the mempool: {e}"),
+                },
+                Some(txid) => {
+                    let Ok(txid) = txid.parse() else {
+                        println!("Usage: mempool [txid]");
+                        continue;
+                    };
+                    match core.fetch_mempool_tx(txid).await {
+                        Ok(Some(info)) => println!(
+                            "{} is #{} in line, paying {:.2} sat/byte ({} for {} bytes), waiting {}",
+                            info.txid,
+                            info.position + 1,
+                            info.fee_rate(),
+                            core.format_amount(info.fee),
+                            info.size,
+                            util::waiting(&info)
+                        ),
+                        Ok(None) => println!("{txid} is not in the mempool, mined or dropped?"),
+                        Err(e) => println!("Failed to fetch the transaction: {e}"),
+                    }
+                }
+            },
+            "pending" => {
+                if let Err(e) = core.check_broadcasts().await {
+                    println!("Failed to check on sent transactions: {e}");
+                }
+                let broadcasts = core.broadcasts();
+                if broadcasts.is_empty() {
+                    println!("No transactions sent yet");
+                }
+                for broadcast in broadcasts {
+                    let txid = broadcast.transaction.hash();
+                    print!(
+                        "{txid}  {}  sent {} ago",
+                        broadcast.status,
+                        util::elapsed(broadcast.sent.elapsed())
+                    );
+                    if broadcast.rebroadcasts > 0 {
+                        print!(", sent again {} times", broadcast.rebroadcasts);
+                    }
+                    if let Some(left) = broadcast.expires_in() {
+                        print!(", expires in {}", util::elapsed(left));
+                    }
+                    println!();
+                    if broadcast.is_expiring(&core.policy) {
+                        println!(
+                            "  about to expire, it's sent again once it does, or raise its fee now \
+                             with \"bump {txid}\""
+                        );
+                    } else if broadcast.is_stuck() {
+                        println!("  taking a while, raise its fee with \"bump {txid}\"");
+                    }
+                }
+            }
+            "bump" => {
+                let Some(Ok(txid)) = parts.get(1).map(|txid| txid.parse()) else {
+                    println!("Usage: bump <txid>");
+                    continue;
+                };
+                match core.bump_fee(txid).await {
+                    Ok(replacement) => {
+                        println!("Replaced {txid} with {}", replacement.hash());
+                        core.fetch_utxos().await?;
+                    }
+                    Err(e) => println!("Failed to bump the fee: {e}"),
+                }
+            }
+            "price" => match core.refresh_price().await {
+                Ok(Some(price)) => println!(
+                    "1 BTC is {} from {}, your balance is worth {}",
+                    util::format_fiat(price.per_btc, &price.currency, &core.config.display),
+                    core.price_source().unwrap_or_default(),
+                    core.fiat(core.get_balance()).unwrap_or_default()
+                ),
+                Ok(None) => println!("No price source, add one to the config under [price]"),
+                Err(e) => println!("Failed to fetch the price: {e}"),
+            },
+            "schedules" => {
+                let schedules = core.schedules();
+                if schedules.is_empty() {
+                    println!("No standing orders, add them to the config under [[schedules]]");
+                }
+                for (payment, state) in schedules {
+                    println!(
+                        "{}: {} to {} every {}",
+                        payment.name,
+                        core.format_amount(payment.amount),
+                        payment.recipient,
+                        util::elapsed(std::time::Duration::from_secs(payment.interval_secs))
+                    );
+                    let Some(state) = state else {
+                        println!("  not run yet");
+                        continue;
+                    };
+                    let shown = state.runs.len().saturating_sub(SCHEDULE_RUNS_SHOWN);
+                    for run in &state.runs[shown..] {
+                        println!("  {}  {}", run.due, run.outcome);
+                    }
+                    println!("  next due at {}", state.next);
+                }
+            }
+            "receive" => {
+                let encrypt = parts.get(1) == Some(&"--encrypt");
+                let words = &parts[1 + encrypt as usize..];
+                let label = (!words.is_empty()).then(|| words.join(" "));
+                let passphrase = match encrypt.then(rotate::prompt_passphrase).transpose() {
+                    Ok(passphrase) => passphrase,
+                    Err(e) => {
+                        println!("{e}");
+                        continue;
+                    }
+                };
+                let key =
+                    match receive::hand_out(&session.config_path, label, passphrase.as_deref()) {
+                        Ok(key) => key,
+                        Err(e) => {
+                            println!("Failed to make a new key: {e}");
+                            continue;
+                        }
+                    };
+                match PublicKey::load_from_file(&key.public) {
+                    Ok(public) => println!("{}", hex::encode(public.to_bytes())),
+                    Err(e) => println!("Failed to load the new key: {e}"),
+                }
+                println!(
+                    "Hand out {} to be paid once, a new one for the next payment",
+                    key.public.display()
+                );
+                // the running wallet only watches the keys it was opened with
+                let node = Some(core.config.default_node.clone());
+                let path = session.config_path.clone();
+                match Session::open(session.name.clone(), path, node).await {
+                    Ok(opened) => session = opened,
+                    Err(e) => println!("Failed to load the new key, restart the wallet: {e}"),
+                }
+            }
+            "addresses" => {
+                let handed_out = receive::handed_out(&core.config);
+                if handed_out.is_empty() {
+                    println!("No addresses handed out yet, `receive` makes one");
+                }
+                for key in handed_out {
+                    let paid = match PublicKey::load_from_file(&key.public) {
+                        Ok(public) => match core.payments_to(&public).await.len() {
+                            0 => "unused".to_string(),
+                            1 => "paid".to_string(),
+                            paid => format!("paid {paid} times, reused"),
+                        },
+                        Err(e) => format!("key file missing: {e}"),
+                    };
+                    println!(
+                        "{}  {}  {paid}  {}",
+                        key.handed_out.unwrap().format("%Y-%m-%d %H:%M"),
+                        key.public.display(),
+                        key.label.as_deref().unwrap_or_default()
+                    );
+                }
+            }
+            "wallets" => match wallets::list() {
+                Ok(names) if names.is_empty() => {
+                    println!("No wallets yet, create one with `wallet new-wallet <name>`")
+                }
+                Ok(names) => {
+                    for name in names {
+                        let current = session.name.as_ref() == Some(&name);
+                        println!("{} {name}", if current { "*" } else { " " });
+                    }
+                }
+                Err(e) => println!("Failed to list wallets: {e}"),
+            },
+            "wallet" => {
+                let Some(name) = parts.get(1) else {
+                    println!("Usage: wallet <name>");
+                    continue;
+                };
+                let opened = match wallets::config_path(name) {
+                    Ok(path) => Session::open(Some(name.to_string()), path, None).await,
+                    Err(e) => Err(e),
+                };
+                match opened {
+                    Ok(opened) => {
+                        // stops the tasks of the one it replaces
+                        session = opened;
+                        println!("Switched to wallet {name}");
+                    }
+                    Err(e) => println!("Failed to open wallet {name}: {e}"),
+                }
             }
             "exit" => break,
-            _ => println!("Unknown command, available commands are: \"balance\", \"send\""),
+            _ => println!(
+                "Unknown command, available commands are: \"balance\", \"send\", \"sendfrom\", \
+                 \"coins\", \"freeze\", \"unfreeze\", \"history\", \"rescan\", \"status\", \
+                 \"mempool\", \"pending\", \"bump\", \"invoice\", \"invoices\", \"pay\", \
+                 \"schedules\", \"price\", \"receive\", \"addresses\", \"wallets\", \"wallet\""
+            ),
         }
     }
     Ok(())
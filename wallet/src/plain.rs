@@ -0,0 +1,231 @@
+//! Plain commands for `wallet --no-ui`, for screen readers and scripts. Each one does one thing
+//! and exits, printing one line per item, or JSON with `--json`, see `btclib::output`. Amounts
+//! are in satoshis in the JSON and shown the way the config's `display` asks for otherwise.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use btclib::crypto::PublicKey;
+use btclib::output::{BalanceOutput, ContactOutput, HistoryEntry, KeyOutput, SentOutput};
+use btclib::sha256::Hash;
+use btclib::types::Amount;
+use btclib::util::Saveable;
+use clap::Subcommand;
+
+use wallet::core::{Config, Core, Key};
+use wallet::{receive, rotate};
+
+#[derive(Subcommand)]
+pub enum PlainCommand {
+    /// What the wallet holds and what of it can be spent right now
+    Balance,
+    /// Pay a contact
+    Send {
+        recipient: String,
+        /// in satoshis
+        amount: u64,
+        memo: Option<String>,
+        /// spend exactly these outputs, by hash, instead of picking some that aren't frozen
+        #[arg(long, value_delimiter = ',')]
+        coins: Vec<Hash>,
+    },
+    /// The keys the wallet can be paid to
+    Receive {
+        /// hand out a new key to be paid to once instead
+        #[arg(long)]
+        new: bool,
+        /// what the new key is for
+        #[arg(long, requires = "new")]
+        label: Option<String>,
+        /// encrypt the new private key, prompting for the passphrase
+        #[arg(long, requires = "new")]
+        encrypt: bool,
+    },
+    /// What happened to the wallet's keys on chain, oldest first. Needs a node with --addrindex
+    History,
+    /// The contacts in the config
+    Contacts,
+}
+
+impl PlainCommand {
+    /// the ones that don't need the node
+    fn offline(&self) -> bool {
+        matches!(self, PlainCommand::Receive { .. } | PlainCommand::Contacts)
+    }
+}
+
+pub async fn run(
+    command: PlainCommand,
+    config_path: &Path,
+    node: Option<String>,
+    json: bool,
+) -> Result<()> {
+    if command.offline() {
+        let config = Config::load(config_path)?;
+        return match command {
+            PlainCommand::Receive {
+                new: true,
+                label,
+                encrypt,
+            } => {
+                let passphrase = encrypt.then(rotate::prompt_passphrase).transpose()?;
+                let key = receive::hand_out(config_path, label, passphrase.as_deref())?;
+                print_keys(&[key], json)
+            }
+            PlainCommand::Receive { new: false, .. } => print_keys(&config.keys, json),
+            _ => contacts(&config, json),
+        };
+    }
+
+    let mut core = Core::load_config(config_path.to_path_buf())
+        .await
+        .with_context(|| "Failed to load config")?;
+    if let Some(node) = node {
+        core.config.default_node = node;
+    }
+    match command {
+        PlainCommand::Balance => {
+            core.fetch_utxos().await?;
+            let (balance, spendable) = (core.get_balance(), core.spendable_balance());
+            let frozen = core.frozen_balance();
+            if json {
+                let output = BalanceOutput {
+                    balance: balance.to_sat(),
+                    spendable: spendable.to_sat(),
+                    frozen: frozen.to_sat(),
+                };
+                println!("{}", serde_json::to_string(&output)?);
+            } else {
+                println!("balance: {}", core.format_amount(balance));
+                println!("spendable: {}", core.format_amount(spendable));
+                println!("frozen: {}", core.format_amount(frozen));
+            }
+        }
+        PlainCommand::Send {
+            recipient,
+            amount,
+            memo,
+            coins,
+        } => {
+            let recipient = core
+                .config
+                .contacts
+                .iter()
+                .find(|contact| contact.name == recipient)
+                .ok_or_else(|| anyhow::anyhow!("No contact called {recipient}"))?
+                .load()?;
+            core.fetch_utxos().await?;
+            let paid = core.payments_to(&recipient.key).await.len();
+            if paid > 0 {
+                eprintln!(
+                    "warning: {} was paid to this key {paid} times before",
+                    recipient.name
+                );
+            }
+            let amount = Amount::from_sat(amount);
+            let transaction = if coins.is_empty() {
+                core.create_transaction(&recipient.key, amount, memo)?
+            } else {
+                core.create_transaction_from(&recipient.key, amount, memo, &coins)?
+            };
+            let txid = transaction.hash();
+            let fee = core.calculate_fee(amount);
+            core.send_transaction(transaction).await?;
+            if json {
+                let output = SentOutput {
+                    txid: txid.to_hex(),
+                    amount: amount.to_sat(),
+                    fee: fee.to_sat(),
+                };
+                println!("{}", serde_json::to_string(&output)?);
+            } else {
+                println!("sent: {txid}");
+                println!("fee: {}", core.format_amount(fee));
+            }
+        }
+        PlainCommand::History => {
+            let history = core.fetch_history().await?;
+            let tip = core.chain_tip().await?;
+            let policy = core.config.finality.policy();
+            if json {
+                let entries: Vec<HistoryEntry> = history
+                    .iter()
+                    .map(|activity| {
+                        HistoryEntry::new(activity, policy.is_final(activity.height, &tip))
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string(&entries)?);
+            } else {
+                for activity in history {
+                    println!(
+                        "block {}, transaction {}, received {}, sent {}{}",
+                        activity.height,
+                        activity.txid,
+                        core.format_amount(activity.received),
+                        core.format_amount(activity.sent),
+                        if policy.is_final(activity.height, &tip) {
+                            ", final"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+        }
+        PlainCommand::Receive { .. } | PlainCommand::Contacts => {
+            unreachable!("handled offline")
+        }
+    }
+    Ok(())
+}
+
+/// the ones of `keys` that aren't retired
+fn print_keys(keys: &[Key], json: bool) -> Result<()> {
+    let mut output = vec![];
+    for key in keys.iter().filter(|key| !key.retired) {
+        let pubkey = PublicKey::load_from_file(&key.public)
+            .with_context(|| format!("Failed to load public key: {}", key.public.display()))?;
+        output.push(KeyOutput {
+            key_file: key.public.display().to_string(),
+            pubkey: hex::encode(pubkey.to_bytes()),
+            label: key.label.clone(),
+        });
+    }
+    if json {
+        println!("{}", serde_json::to_string(&output)?);
+    } else {
+        for key in output {
+            match key.label {
+                Some(label) => println!("{} {} {label}", key.pubkey, key.key_file),
+                None => println!("{} {}", key.pubkey, key.key_file),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn contacts(config: &Config, json: bool) -> Result<()> {
+    let contacts: Vec<ContactOutput> = config
+        .contacts
+        .iter()
+        .map(|contact| ContactOutput {
+            name: contact.name.clone(),
+            key_file: contact.key.display().to_string(),
+            pubkey: contact
+                .load()
+                .ok()
+                .map(|loaded| hex::encode(loaded.key.to_bytes())),
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string(&contacts)?);
+    } else {
+        for contact in contacts {
+            let pubkey = contact
+                .pubkey
+                .unwrap_or_else(|| "key file missing".to_string());
+            println!("{}: {pubkey}", contact.name);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,198 @@
+//! Fiat values next to amounts, from a `PriceProvider`. The wallet asks it for the price of a
+//! bitcoin in the background every so often and keeps the last answer, so showing a balance
+//! never waits on it. A toy chain's coins have no market of course, the `static` provider is for
+//! demos and the `http` one for a price feed or a mock of one.
+//!
+//! The http provider GETs a JSON document and takes the price at a JSON pointer in it, e.g.
+//! `/bitcoin/usd` for `{"bitcoin":{"usd":65000.5}}`. Plain http:// only, like the node's webhook.
+
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// how long the http provider waits for an answer
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a bitcoin was worth when it was asked
+#[derive(Debug, Clone)]
+pub struct Price {
+    pub currency: String,
+    pub per_btc: f64,
+    pub fetched: DateTime<Utc>,
+}
+
+pub trait PriceProvider: Send + Sync + fmt::Debug {
+    /// where the prices come from, to show along with them
+    fn name(&self) -> String;
+    fn fetch(&self) -> BoxFuture<'_, Result<Price>>;
+}
+
+/// The `[price]` table of the config
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PriceConfig {
+    /// what the price is in, e.g. `USD`
+    pub currency: String,
+    #[serde(flatten)]
+    pub source: PriceSource,
+    /// how often to ask for the price again
+    #[serde(default = "default_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+fn default_refresh_secs() -> u64 {
+    300
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "source", rename_all = "lowercase")]
+pub enum PriceSource {
+    /// always the same price
+    Static { per_btc: f64 },
+    /// the number at `pointer` in the JSON `url` answers with
+    Http { url: String, pointer: String },
+}
+
+impl PriceConfig {
+    pub fn provider(&self) -> Result<Box<dyn PriceProvider>> {
+        Ok(match &self.source {
+            PriceSource::Static { per_btc } => Box::new(StaticPrice {
+                currency: self.currency.clone(),
+                per_btc: *per_btc,
+            }),
+            PriceSource::Http { url, pointer } => Box::new(HttpPrice::parse(
+                url,
+                pointer.clone(),
+                self.currency.clone(),
+            )?),
+        })
+    }
+
+    /// a price older than this is not shown any more
+    pub fn max_age(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.refresh_secs.saturating_mul(3).min(i32::MAX as u64) as i64)
+    }
+}
+
+#[derive(Debug)]
+pub struct StaticPrice {
+    pub currency: String,
+    pub per_btc: f64,
+}
+
+impl PriceProvider for StaticPrice {
+    fn name(&self) -> String {
+        "a fixed price".to_string()
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, Result<Price>> {
+        Box::pin(async move {
+            Ok(Price {
+                currency: self.currency.clone(),
+                per_btc: self.per_btc,
+                fetched: Utc::now(),
+            })
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpPrice {
+    url: String,
+    /// host and port as given, for the Host header
+    authority: String,
+    /// what to connect to
+    addr: String,
+    path: String,
+    pointer: String,
+    currency: String,
+}
+
+impl HttpPrice {
+    pub fn parse(url: &str, pointer: String, currency: String) -> Result<Self> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| anyhow!("price feed {url} is not an http:// url"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() {
+            bail!("price feed {url} has no host");
+        }
+        let addr = if authority.contains(':') {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok(HttpPrice {
+            url: url.to_string(),
+            authority: authority.to_string(),
+            addr,
+            path: path.to_string(),
+            pointer,
+            currency,
+        })
+    }
+
+    /// the body of the answer to a GET of the url. HTTP/1.0 so it can't come chunked
+    async fn get(&self) -> Result<Vec<u8>> {
+        let mut stream = TcpStream::connect(&self.addr).await?;
+        let head = format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\n\r\n",
+            self.path, self.authority
+        );
+        stream.write_all(head.as_bytes()).await?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await?;
+
+        let end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .context("no end of headers in the response")?;
+        let status = String::from_utf8_lossy(&response[..end]);
+        let status = status.lines().next().unwrap_or_default();
+        let code = status
+            .split_whitespace()
+            .nth(1)
+            .context("no status line in the response")?;
+        if !code.starts_with('2') {
+            bail!("price feed answered {status}");
+        }
+        Ok(response.split_off(end + 4))
+    }
+}
+
+impl PriceProvider for HttpPrice {
+    fn name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, Result<Price>> {
+        Box::pin(async move {
+            let body = tokio::time::timeout(HTTP_TIMEOUT, self.get())
+                .await
+                .map_err(|_| anyhow!("price feed didn't answer in time"))??;
+            let json: serde_json::Value = serde_json::from_slice(&body)?;
+            let value = json
+                .pointer(&self.pointer)
+                .with_context(|| format!("nothing at {} in the answer", self.pointer))?;
+            let per_btc = match value {
+                serde_json::Value::String(s) => s.parse().ok(),
+                value => value.as_f64(),
+            }
+            .filter(|price: &f64| price.is_finite() && *price >= 0.0)
+            .with_context(|| format!("{value} is not a price"))?;
+            Ok(Price {
+                currency: self.currency.clone(),
+                per_btc,
+                fetched: Utc::now(),
+            })
+        })
+    }
+}
@@ -0,0 +1,111 @@
+//! One-time addresses: `receive` hands out a key of its own for every payment asked for, so
+//! nobody watching the chain can tie the payments to the wallet together. There's no HD
+//! derivation to get them from, so each one is a new random key pair with its files next to the
+//! config like the ones rotate-keys makes, and like those encrypted if asked. They go last in the
+//! config's keys, after the one change goes to, and remember their label and when they were
+//! handed out.
+
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::Utc;
+
+use crate::core::{Config, Key};
+use crate::rotate;
+
+/// Make a new key pair to be paid to once, for `label` if given, encrypted with `passphrase` if
+/// there is one, and add it to the config
+pub fn hand_out(
+    config_path: &Path,
+    label: Option<String>,
+    passphrase: Option<&str>,
+) -> Result<Key> {
+    let mut config = Config::load(config_path)?;
+    let key = Key {
+        label,
+        handed_out: Some(Utc::now()),
+        ..rotate::new_key_pair(config_path, passphrase)?
+    };
+    config.keys.push(key.clone());
+    rotate::save(config_path, &config)?;
+    Ok(key)
+}
+
+/// The keys `hand_out` made, oldest first
+pub fn handed_out(config: &Config) -> Vec<&Key> {
+    let mut keys: Vec<&Key> = config
+        .keys
+        .iter()
+        .filter(|key| key.handed_out.is_some())
+        .collect();
+    keys.sort_by_key(|key| key.handed_out);
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    use btclib::crypto::{PrivateKey, PublicKey};
+    use btclib::util::Saveable;
+    use uuid::Uuid;
+
+    /// a config with no keys yet, in a directory of its own
+    fn config() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("wallet-receive-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("wallet_config.toml");
+        fs::write(
+            &config_path,
+            "keys = []\ncontacts = []\ndefault_node = \"\"\n\
+             [fee_config]\nfee_type = \"Fixed\"\nvalue = 0.0\n",
+        )
+        .unwrap();
+        (dir, config_path)
+    }
+
+    #[test]
+    fn every_payment_gets_a_new_key() {
+        let (dir, config_path) = config();
+        let first = hand_out(&config_path, Some("rent".to_string()), None).unwrap();
+        let second = hand_out(&config_path, None, None).unwrap();
+        assert_ne!(first.public, second.public);
+        assert_ne!(
+            PublicKey::load_from_file(&first.public).unwrap(),
+            PublicKey::load_from_file(&second.public).unwrap()
+        );
+
+        let config = Config::load(&config_path).unwrap();
+        assert_eq!(config.keys.len(), 2);
+        let handed_out = handed_out(&config);
+        assert_eq!(handed_out[0].public, first.public);
+        assert_eq!(handed_out[0].label.as_deref(), Some("rent"));
+        assert_eq!(handed_out[1].public, second.public);
+        assert_eq!(handed_out[1].label, None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keys_handed_out_encrypted_need_the_passphrase() {
+        let (dir, config_path) = config();
+        let key = hand_out(&config_path, None, Some("hunter2")).unwrap();
+        let private = PrivateKey::load_from_file_with_passphrase(&key.private, "hunter2").unwrap();
+        assert_eq!(
+            private.public_key(),
+            PublicKey::load_from_file(&key.public).unwrap()
+        );
+        assert!(PrivateKey::load_from_file_with_passphrase(&key.private, "hunter3").is_err());
+
+        // and ones that aren't don't
+        let key = hand_out(&config_path, None, None).unwrap();
+        let private = PrivateKey::load_from_file_with_passphrase(&key.private, "").unwrap();
+        assert_eq!(
+            private.public_key(),
+            PublicKey::load_from_file(&key.public).unwrap()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+//! Transactions in and out of the wallet for record keeping. `export` lists what the wallet's keys
+//! took part in over a range of days, as CSV or JSON, see `btclib::output::TransactionRecord`. The
+//! history comes from the node's addrindex, the details from its blocks. `read_raw` reads a
+//! transaction made elsewhere in `Transaction::to_hex` form, for the wallet to sign its inputs
+//! of and send.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use btclib::finality::ChainTip;
+use btclib::output::{RecordOutput, TransactionRecord};
+use btclib::sha256::Hash;
+use btclib::types::{Amount, Block, Transaction};
+use chrono::{Days, NaiveDate, NaiveTime};
+use clap::ValueEnum;
+
+use crate::core::Core;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+/// The transactions of the wallet's keys mined from the start of `since` to the end of `until`,
+/// oldest first, either open ended if left out
+pub async fn export(
+    core: &Core,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Result<Vec<TransactionRecord>> {
+    let start = since.map(|day| day.and_time(NaiveTime::MIN).and_utc());
+    let end = until
+        .and_then(|day| day.checked_add_days(Days::new(1)))
+        .map(|day| day.and_time(NaiveTime::MIN).and_utc());
+    let tip = ChainTip::from(&core.fetch_status().await?);
+    let policy = core.config.finality.policy();
+
+    // a transaction shows up once for every key of the wallet it involves
+    let mut activity: Vec<(u64, Hash, Amount, Amount)> = vec![];
+    let mut seen: HashMap<Hash, usize> = HashMap::new();
+    for entry in core.fetch_history().await? {
+        match seen.get(&entry.txid) {
+            Some(&i) => {
+                let (_, _, received, sent) = &mut activity[i];
+                *received = received
+                    .checked_add(entry.received)
+                    .unwrap_or(Amount::MAX_MONEY);
+                *sent = sent.checked_add(entry.sent).unwrap_or(Amount::MAX_MONEY);
+            }
+            None => {
+                seen.insert(entry.txid, activity.len());
+                activity.push((entry.height, entry.txid, entry.received, entry.sent));
+            }
+        }
+    }
+
+    let mut records = vec![];
+    // the last block fetched, the history is in the order of the chain
+    let mut fetched: Option<(u64, Block)> = None;
+    for (height, txid, received, sent) in activity {
+        if fetched.as_ref().is_none_or(|(at, _)| *at != height) {
+            fetched = Some((height, core.fetch_block(height).await?));
+        }
+        let (_, block) = fetched.as_ref().unwrap();
+        let time = block.header.timestamp;
+        if start.is_some_and(|start| time < start) || end.is_some_and(|end| time >= end) {
+            continue;
+        }
+        let transaction = block
+            .transactions
+            .iter()
+            .find(|transaction| transaction.hash() == txid)
+            .ok_or_else(|| anyhow::anyhow!("Block {height} doesn't hold {txid}, reorganized?"))?;
+        let fee = core
+            .signed_every_input(transaction)
+            .then(|| sent.checked_sub(transaction.output_value().ok()?))
+            .flatten();
+        records.push(TransactionRecord {
+            txid: txid.to_hex(),
+            height,
+            time,
+            confirmations: tip.height.saturating_sub(height),
+            finalized: policy.is_final(height, &tip),
+            inputs: transaction
+                .inputs
+                .iter()
+                .map(|input| input.prev_transaction_output_hash.to_hex())
+                .collect(),
+            outputs: transaction
+                .outputs
+                .iter()
+                .map(|output| RecordOutput {
+                    pubkey: hex::encode(output.pubkey.to_bytes()),
+                    value: output.value.to_sat(),
+                })
+                .collect(),
+            received: received.to_sat(),
+            sent: sent.to_sat(),
+            fee: fee.map(|fee| fee.to_sat()),
+            memo: transaction.memo.clone(),
+        });
+    }
+    Ok(records)
+}
+
+/// A field quoted if it has to be. Ones a spreadsheet would take for a formula get a `'` first
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@']) {
+        format!("'{field}")
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// One line per record after a header. Inputs and `pubkey:value` outputs are separated by spaces
+pub fn to_csv(records: &[TransactionRecord]) -> String {
+    let mut csv = String::from(
+        "txid,height,time,confirmations,final,received,sent,fee,memo,inputs,outputs\n",
+    );
+    for record in records {
+        let outputs: Vec<String> = record
+            .outputs
+            .iter()
+            .map(|output| format!("{}:{}", output.pubkey, output.value))
+            .collect();
+        let fields = [
+            record.txid.clone(),
+            record.height.to_string(),
+            record.time.to_rfc3339(),
+            record.confirmations.to_string(),
+            record.finalized.to_string(),
+            record.received.to_string(),
+            record.sent.to_string(),
+            record.fee.map(|fee| fee.to_string()).unwrap_or_default(),
+            record.memo.clone().unwrap_or_default(),
+            record.inputs.join(" "),
+            outputs.join(" "),
+        ];
+        let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// The transaction in `source`: its hex, a file holding it, or `-` to read it from stdin
+pub fn read_raw(source: &str) -> Result<Transaction> {
+    let hex = if source == "-" {
+        let mut hex = String::new();
+        std::io::stdin().read_to_string(&mut hex)?;
+        hex
+    } else if Path::new(source).is_file() {
+        fs::read_to_string(source).with_context(|| format!("Failed to read {source}"))?
+    } else {
+        source.to_string()
+    };
+    Ok(Transaction::from_hex(&hex)?)
+}
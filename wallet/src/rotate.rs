@@ -0,0 +1,105 @@
+//! Key rotation: a new key pair goes first in the config, so payments and change go to it, and
+//! everything the old keys hold is swept to it in one transaction. Once that's mined the old keys
+//! are retired: the wallet stops loading their private keys and only watches them for payments
+//! still coming in, see `Key::retired`. Contacts of the other wallets under `wallets/` that named
+//! an old key are pointed at the new one.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use btclib::crypto::PrivateKey;
+use btclib::util::Saveable;
+
+use crate::core::{Config, Key};
+use crate::wallets;
+
+pub fn save(config_path: &Path, config: &Config) -> Result<()> {
+    fs::write(config_path, toml::to_string_pretty(config)?)?;
+    Ok(())
+}
+
+/// Ask twice for the passphrase to encrypt a new private key with
+pub fn prompt_passphrase() -> Result<String> {
+    let passphrase = rpassword::prompt_password("Passphrase for the new key: ")?;
+    if rpassword::prompt_password("Once more: ")? != passphrase {
+        return Err(anyhow::anyhow!("The passphrases don't match"));
+    }
+    Ok(passphrase)
+}
+
+/// Make a new key pair next to the config, encrypted with `passphrase` if there is one, and put
+/// it first in the config's keys
+pub fn add_key(config_path: &Path, passphrase: Option<&str>) -> Result<Key> {
+    let mut config = Config::load(config_path)?;
+    let key = new_key_pair(config_path, passphrase)?;
+    config.keys.insert(0, key.clone());
+    save(config_path, &config)?;
+    Ok(key)
+}
+
+/// The files of a new key pair next to the config, which is left as it is
+pub fn new_key_pair(config_path: &Path, passphrase: Option<&str>) -> Result<Key> {
+    let dir = config_path.parent().unwrap_or(Path::new(""));
+    // the first free number, so an earlier rotation's files are never overwritten
+    let (public, private) = (1..)
+        .map(|n| {
+            (
+                dir.join(format!("key_{n}_pub.pem")),
+                dir.join(format!("key_{n}_priv.cbor")),
+            )
+        })
+        .find(|(public, private)| !public.exists() && !private.exists())
+        .unwrap();
+
+    let key = PrivateKey::new_key();
+    key.public_key().save_to_file(&public)?;
+    match passphrase {
+        Some(passphrase) => key.save_encrypted_to_file(&private, passphrase)?,
+        None => key.save_to_file(&private)?,
+    }
+    Ok(Key {
+        public,
+        private,
+        retired: false,
+        label: None,
+        handed_out: None,
+    })
+}
+
+/// Mark the keys with the public key files `public` retired. Returns how many were
+pub fn retire(config_path: &Path, public: &[PathBuf]) -> Result<usize> {
+    let mut config = Config::load(config_path)?;
+    let mut retired = 0;
+    for key in &mut config.keys {
+        if !key.retired && public.contains(&key.public) {
+            key.retired = true;
+            retired += 1;
+        }
+    }
+    save(config_path, &config)?;
+    Ok(retired)
+}
+
+/// Point the contacts of the wallets under `wallets/` that name one of the key files `old` at
+/// `new` instead. Returns the names of the wallets changed
+pub fn repoint_contacts(old: &[PathBuf], new: &Path) -> Result<Vec<String>> {
+    let mut changed = vec![];
+    for name in wallets::list()? {
+        let path = wallets::config_path(&name)?;
+        let mut config = Config::load(&path)
+            .with_context(|| format!("Failed to read the config of wallet {name}"))?;
+        let mut any = false;
+        for contact in &mut config.contacts {
+            if old.contains(&contact.key) {
+                contact.key = new.to_path_buf();
+                any = true;
+            }
+        }
+        if any {
+            save(&path, &config)?;
+            changed.push(name);
+        }
+    }
+    Ok(changed)
+}
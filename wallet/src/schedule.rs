@@ -0,0 +1,232 @@
+//! Standing orders: payments to a contact the config asks for every so often. When each one is
+//! next due and what became of its runs so far is kept in the file the config's `schedule_state`
+//! points at, so they carry on where they left off when the wallet restarts. A run is skipped
+//! when the wallet can't afford it, and runs that came due while the wallet wasn't running are
+//! counted as missed instead of all being paid at once.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use btclib::sha256::Hash;
+use btclib::types::Amount;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// runs remembered per standing order, the oldest are forgotten first
+const RUNS_KEPT: usize = 100;
+/// longest interval a standing order can have, about ten years
+pub const MAX_INTERVAL_SECS: u64 = 10 * 365 * 24 * 3600;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduledPayment {
+    /// what the order is known by, unique in the config
+    pub name: String,
+    /// name of the contact to pay
+    pub recipient: String,
+    pub amount: Amount,
+    /// from 1 to `MAX_INTERVAL_SECS`
+    pub interval_secs: u64,
+}
+
+impl ScheduledPayment {
+    pub fn interval(&self) -> Duration {
+        Duration::seconds(self.interval_secs.min(MAX_INTERVAL_SECS) as i64)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RunOutcome {
+    Paid {
+        txid: Hash,
+    },
+    /// the wallet was running but couldn't pay
+    Skipped {
+        reason: String,
+    },
+    /// came due `runs` times in a row while the wallet wasn't running
+    Missed {
+        runs: u64,
+    },
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunOutcome::Paid { txid } => write!(f, "paid in {txid}"),
+            RunOutcome::Skipped { reason } => write!(f, "skipped: {reason}"),
+            RunOutcome::Missed { runs: 1 } => write!(f, "missed, the wallet wasn't running"),
+            RunOutcome::Missed { runs } => {
+                write!(f, "missed {runs} times, the wallet wasn't running")
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Run {
+    pub due: DateTime<Utc>,
+    pub outcome: RunOutcome,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleState {
+    pub next: DateTime<Utc>,
+    /// oldest first
+    pub runs: Vec<Run>,
+}
+
+impl ScheduleState {
+    /// a standing order the wallet hasn't seen before is due right away
+    pub fn new(now: DateTime<Utc>) -> Self {
+        ScheduleState {
+            next: now,
+            runs: vec![],
+        }
+    }
+
+    /// When the run to make now was due, if one is. The times it came due before that, up to
+    /// `now`, are recorded as missed
+    pub fn take_due(&mut self, interval: Duration, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if now < self.next || interval <= Duration::zero() {
+            return None;
+        }
+        let interval_secs = interval.num_seconds().max(1);
+        let missed = (now - self.next).num_seconds() / interval_secs;
+        if missed > 0 {
+            self.record(
+                self.next,
+                RunOutcome::Missed {
+                    runs: missed as u64,
+                },
+            );
+            self.next += Duration::seconds(missed * interval_secs);
+        }
+        let due = self.next;
+        self.next += interval;
+        Some(due)
+    }
+
+    pub fn record(&mut self, due: DateTime<Utc>, outcome: RunOutcome) {
+        self.runs.push(Run { due, outcome });
+        if self.runs.len() > RUNS_KEPT {
+            self.runs.remove(0);
+        }
+    }
+}
+
+/// the state saved at `path` by standing order name, nothing if there is no file yet
+pub fn load(path: &Path) -> Result<HashMap<String, ScheduleState>> {
+    match fs::read(path) {
+        Ok(data) => Ok(ciborium::from_reader(data.as_slice())?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save(path: &Path, states: &HashMap<String, ScheduleState>) -> Result<()> {
+    let mut data = vec![];
+    ciborium::into_writer(states, &mut data)?;
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn at(hours: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap() + Duration::hours(hours)
+    }
+
+    #[test]
+    fn runs_come_due_once_an_interval() {
+        let hour = Duration::hours(1);
+        let mut state = ScheduleState::new(at(0));
+        assert_eq!(state.take_due(hour, at(0)), Some(at(0)));
+        assert_eq!(state.next, at(1));
+        assert_eq!(state.take_due(hour, at(0)), None);
+        assert_eq!(state.take_due(hour, at(1) - Duration::seconds(1)), None);
+
+        // a bit late is still the run that was due, and the next keeps to the schedule
+        assert_eq!(
+            state.take_due(hour, at(1) + Duration::minutes(10)),
+            Some(at(1))
+        );
+        assert_eq!(state.next, at(2));
+        assert!(state.runs.is_empty());
+
+        assert_eq!(state.take_due(Duration::zero(), at(5)), None);
+        assert_eq!(state.next, at(2));
+    }
+
+    #[test]
+    fn runs_due_while_not_running_are_missed() {
+        let hour = Duration::hours(1);
+        let mut state = ScheduleState::new(at(0));
+        state.take_due(hour, at(0));
+
+        // due at 1, 2 and 3, only the one at 3 is made
+        assert_eq!(
+            state.take_due(hour, at(3) + Duration::minutes(30)),
+            Some(at(3))
+        );
+        assert_eq!(state.next, at(4));
+        assert_eq!(state.runs.len(), 1);
+        assert_eq!(state.runs[0].due, at(1));
+        assert!(matches!(
+            state.runs[0].outcome,
+            RunOutcome::Missed { runs: 2 }
+        ));
+    }
+
+    #[test]
+    fn history_keeps_the_latest_runs() {
+        let mut state = ScheduleState::new(at(0));
+        let txid = Hash::of_bytes(b"paid");
+        state.record(at(0), RunOutcome::Paid { txid });
+        state.record(
+            at(1),
+            RunOutcome::Skipped {
+                reason: "insufficient funds".to_string(),
+            },
+        );
+        assert_eq!(state.runs[0].outcome.to_string(), format!("paid in {txid}"));
+        assert_eq!(
+            state.runs[1].outcome.to_string(),
+            "skipped: insufficient funds"
+        );
+
+        for hours in 2..RUNS_KEPT as i64 + 2 {
+            state.record(at(hours), RunOutcome::Missed { runs: 1 });
+        }
+        assert_eq!(state.runs.len(), RUNS_KEPT);
+        assert_eq!(state.runs[0].due, at(2));
+        assert_eq!(state.runs[RUNS_KEPT - 1].due, at(RUNS_KEPT as i64 + 1));
+    }
+
+    #[test]
+    fn state_is_kept_in_its_file() {
+        let path =
+            std::env::temp_dir().join(format!("wallet-schedule-{}.cbor", uuid::Uuid::new_v4()));
+        assert!(load(&path).unwrap().is_empty());
+        let mut state = ScheduleState::new(at(0));
+        state.take_due(Duration::hours(1), at(0));
+        state.record(
+            at(0),
+            RunOutcome::Paid {
+                txid: Hash::of_bytes(b"paid"),
+            },
+        );
+        save(&path, &HashMap::from([("rent".to_string(), state)])).unwrap();
+
+        let states = load(&path).unwrap();
+        assert_eq!(states["rent"].next, at(1));
+        assert_eq!(states["rent"].runs[0].due, at(0));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -5,8 +5,12 @@ use tokio::time;
 use tokio::time::Duration;
 use tracing::*;
 
+/// Keep the UTXOs up to date, polling the node if it can't be subscribed to
 pub async fn update_utxos(core: Arc<Core>) -> JoinHandle<()> {
     tokio::spawn(async move {
+        if let Err(e) = core.watch_utxos().await {
+            warn!("Subscription to the node ended: {e}, polling instead");
+        }
         let mut interval = time::interval(Duration::from_secs(20));
         loop {
             interval.tick().await;
@@ -17,6 +21,48 @@ pub async fn update_utxos(core: Arc<Core>) -> JoinHandle<()> {
     })
 }
 
+/// Keep an eye on the transactions the wallet sent until they're mined
+pub async fn monitor_broadcasts(core: Arc<Core>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            if let Err(e) = core.check_broadcasts().await {
+                error!("Failed to check on sent transactions: {e}");
+            }
+        }
+    })
+}
+
+/// Make the standing orders in the config as they come due
+pub async fn run_schedules(core: Arc<Core>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            if let Err(e) = core.run_schedules().await {
+                error!("Failed to run standing orders: {e}");
+            }
+        }
+    })
+}
+
+/// Keep the price of a bitcoin fresh, if the config says where to get it from
+pub async fn refresh_price(core: Arc<Core>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let Some(config) = &core.config.price else {
+            return;
+        };
+        let mut interval = time::interval(Duration::from_secs(config.refresh_secs.max(1)));
+        loop {
+            interval.tick().await;
+            if let Err(e) = core.refresh_price().await {
+                warn!("Failed to fetch the price: {e}");
+            }
+        }
+    })
+}
+
 pub async fn handle_transactions(
     rx: kanal::AsyncReceiver<Transaction>,
     core: Arc<Core>,
@@ -0,0 +1,236 @@
+//! Offline commands for FROST threshold keys. Participants exchange the files these commands
+//! write through whatever channel they trust: commitments can be public, DKG shares have to go
+//! privately to the participant they are addressed to.
+//!
+//! Co-signing works on a partially signed transaction file: a transaction whose input spending
+//! the group output does not carry a valid signature yet. `aggregate` fills it in.
+
+use anyhow::{Context, Result, anyhow};
+use clap::Subcommand;
+use serde::{Serialize, de::DeserializeOwned};
+
+use btclib::crypto::frost::{
+    DkgCommitment, DkgSecret, DkgShare, FrostSession, KeyShare, SignatureShare, SigningCommitment,
+    SigningNonces, dkg_round1, frost_commit,
+};
+use btclib::types::Transaction;
+use btclib::util::Saveable;
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+#[derive(Subcommand)]
+pub enum ThresholdCommand {
+    /// Start a key generation ceremony, the commitment file goes to every other participant
+    DkgStart {
+        #[arg(long)]
+        id: u16,
+        #[arg(long)]
+        threshold: u16,
+        #[arg(long)]
+        participants: u16,
+        #[arg(long, value_name = "FILE")]
+        state: PathBuf,
+        #[arg(long, value_name = "FILE")]
+        commitment: PathBuf,
+    },
+    /// Check everyone's commitments and write a share file for each other participant
+    DkgShares {
+        #[arg(long, value_name = "FILE")]
+        state: PathBuf,
+        #[arg(long = "commitment", value_name = "FILE", required = true)]
+        commitments: Vec<PathBuf>,
+        #[arg(long, value_name = "DIR")]
+        out_dir: PathBuf,
+    },
+    /// Check the shares sent to us and write our key share and the group public key
+    DkgFinish {
+        #[arg(long, value_name = "FILE")]
+        state: PathBuf,
+        #[arg(long = "commitment", value_name = "FILE", required = true)]
+        commitments: Vec<PathBuf>,
+        #[arg(long = "share", value_name = "FILE")]
+        shares: Vec<PathBuf>,
+        #[arg(long, value_name = "FILE")]
+        key_share: PathBuf,
+        #[arg(long, value_name = "FILE")]
+        public_key: PathBuf,
+    },
+    /// Generate nonces for one signing session, the commitment file goes to the coordinator
+    Commit {
+        #[arg(long, value_name = "FILE")]
+        key_share: PathBuf,
+        #[arg(long, value_name = "FILE")]
+        nonces: PathBuf,
+        #[arg(long, value_name = "FILE")]
+        commitment: PathBuf,
+    },
+    /// Co-sign an input of a partially signed transaction, the nonces file is deleted afterwards
+    Sign {
+        #[arg(long, value_name = "FILE")]
+        key_share: PathBuf,
+        #[arg(long, value_name = "FILE")]
+        nonces: PathBuf,
+        #[arg(long = "commitment", value_name = "FILE", required = true)]
+        commitments: Vec<PathBuf>,
+        #[arg(long, value_name = "FILE")]
+        transaction: PathBuf,
+        #[arg(long)]
+        input: usize,
+        #[arg(long, value_name = "FILE")]
+        share: PathBuf,
+    },
+    /// Combine the signature shares into the input's signature and save the transaction
+    Aggregate {
+        #[arg(long, value_name = "FILE")]
+        key_share: PathBuf,
+        #[arg(long = "commitment", value_name = "FILE", required = true)]
+        commitments: Vec<PathBuf>,
+        #[arg(long = "share", value_name = "FILE", required = true)]
+        shares: Vec<PathBuf>,
+        #[arg(long, value_name = "FILE")]
+        transaction: PathBuf,
+        #[arg(long)]
+        input: usize,
+    },
+}
+
+pub fn run(command: ThresholdCommand) -> Result<()> {
+    match command {
+        ThresholdCommand::DkgStart {
+            id,
+            threshold,
+            participants,
+            state,
+            commitment,
+        } => {
+            let (secret, public) = dkg_round1(id, threshold, participants)?;
+            write(&state, &secret)?;
+            write(&commitment, &public)?;
+            println!("Send {} to every other participant", commitment.display());
+        }
+        ThresholdCommand::DkgShares {
+            state,
+            commitments,
+            out_dir,
+        } => {
+            let secret: DkgSecret = read(&state)?;
+            let commitments: Vec<DkgCommitment> = read_all(&commitments)?;
+            fs::create_dir_all(&out_dir)?;
+            for share in secret.round2(&commitments)? {
+                let path = out_dir.join(format!("share_{}_to_{}.cbor", share.from, share.to));
+                write(&path, &share)?;
+                println!(
+                    "Send {} privately to participant {}",
+                    path.display(),
+                    share.to
+                );
+            }
+        }
+        ThresholdCommand::DkgFinish {
+            state,
+            commitments,
+            shares,
+            key_share,
+            public_key,
+        } => {
+            let secret: DkgSecret = read(&state)?;
+            let commitments: Vec<DkgCommitment> = read_all(&commitments)?;
+            let shares: Vec<DkgShare> = read_all(&shares)?;
+            let share = secret.finish(&commitments, &shares)?;
+            write(&key_share, &share)?;
+            share.group().public_key().save_to_file(&public_key)?;
+            // the DKG state holds our polynomial, nothing needs it anymore
+            fs::remove_file(&state)?;
+            println!(
+                "Group key {} ({}-of-{}) saved to {}",
+                share.group().public_key(),
+                share.group().threshold(),
+                commitments.len(),
+                public_key.display()
+            );
+        }
+        ThresholdCommand::Commit {
+            key_share,
+            nonces,
+            commitment,
+        } => {
+            let share: KeyShare = read(&key_share)?;
+            let (secret, public) = frost_commit(&share);
+            write(&nonces, &secret)?;
+            write(&commitment, &public)?;
+            println!("Send {} to the signing coordinator", commitment.display());
+        }
+        ThresholdCommand::Sign {
+            key_share,
+            nonces,
+            commitments,
+            transaction,
+            input,
+            share,
+        } => {
+            let key: KeyShare = read(&key_share)?;
+            let session = session(&key, &commitments, &transaction, input)?.0;
+            let secret: SigningNonces = read(&nonces)?;
+            // never let the same nonces sign twice
+            fs::remove_file(&nonces)?;
+            write(&share, &session.sign(&key, secret)?)?;
+            println!("Send {} to the signing coordinator", share.display());
+        }
+        ThresholdCommand::Aggregate {
+            key_share,
+            commitments,
+            shares,
+            transaction: path,
+            input,
+        } => {
+            let key: KeyShare = read(&key_share)?;
+            let (session, mut transaction) = session(&key, &commitments, &path, input)?;
+            let shares: Vec<SignatureShare> = read_all(&shares)?;
+            if let Some(bad) = shares.iter().find(|share| !session.verify_share(share)) {
+                return Err(anyhow!(
+                    "Invalid signature share from participant {}",
+                    bad.id
+                ));
+            }
+            transaction.inputs[input].signature = session.aggregate(&shares)?;
+            transaction.save_to_file(&path)?;
+            println!("Input {input} of {} is signed", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn session(
+    key: &KeyShare,
+    commitments: &[PathBuf],
+    path: &Path,
+    input: usize,
+) -> Result<(FrostSession, Transaction)> {
+    let commitments: Vec<SigningCommitment> = read_all(commitments)?;
+    let transaction = Transaction::load_from_file(path)
+        .with_context(|| format!("Failed to load transaction: {}", path.display()))?;
+    let output_hash = transaction
+        .inputs
+        .get(input)
+        .ok_or_else(|| anyhow!("Transaction has no input {input}"))?
+        .prev_transaction_output_hash;
+    let message = transaction.signature_hash(&output_hash);
+    let session = FrostSession::new(key.group().clone(), commitments, &message)?;
+    Ok((session, transaction))
+}
+
+fn read<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    ciborium::from_reader(file).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn read_all<T: DeserializeOwned>(paths: &[PathBuf]) -> Result<Vec<T>> {
+    paths.iter().map(|path| read(path)).collect()
+}
+
+fn write<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let file = File::create(path)?;
+    ciborium::into_writer(value, file)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -1,10 +1,14 @@
 use std::panic;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::*;
 
 use anyhow::Result;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
+use btclib::types::{Amount, MempoolTxInfo};
+use serde::{Deserialize, Serialize};
+
 use crate::core::Core;
 
 /// Initialize tracing to save logs into logs folder
@@ -27,13 +31,225 @@ pub fn setup_panic_hook() {
     }));
 }
 
-/// Convert satoshis to a BTC string
-pub fn sats_to_btc(sats: u64) -> String {
-    let btc = sats as f64 / 100_000_000.0;
-    format!("{} BTC", btc)
+/// How long a mempool transaction has been waiting, e.g. `1h 5m` or `42s`
+pub fn waiting(info: &MempoolTxInfo) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64);
+    let seconds = (now - info.received.timestamp()).max(0);
+    elapsed(Duration::from_secs(seconds as u64))
+}
+
+/// A duration the way `waiting` puts it
+pub fn elapsed(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
 }
 
 /// Make it BIGGER
 pub fn big_mode_btc(core: &Core) -> String {
-    text_to_ascii_art::convert(sats_to_btc(core.get_balance())).unwrap()
+    text_to_ascii_art::convert(core.format_amount(core.get_balance())).unwrap()
+}
+
+/// What amounts are shown in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    Sat,
+    #[serde(rename = "mbtc")]
+    MilliBtc,
+    #[default]
+    Btc,
+}
+
+impl Unit {
+    /// decimals shown, so every satoshi is
+    fn decimals(self) -> u32 {
+        match self {
+            Unit::Sat => 0,
+            Unit::MilliBtc => 5,
+            Unit::Btc => 8,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Unit::Sat => "sat",
+            Unit::MilliBtc => "mBTC",
+            Unit::Btc => "BTC",
+        }
+    }
+}
+
+/// Whose way of writing numbers to follow
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// 1,234.5
+    #[default]
+    En,
+    /// 1.234,5
+    De,
+    /// 1 234,5, with a narrow no-break space
+    Fr,
+}
+
+impl Locale {
+    /// the thousands and the decimal separator
+    fn separators(self) -> (char, char) {
+        match self {
+            Locale::En => (',', '.'),
+            Locale::De => ('.', ','),
+            Locale::Fr => ('\u{202f}', ','),
+        }
+    }
+}
+
+/// How the wallet shows amounts, the `[display]` table of the config
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayConfig {
+    #[serde(default)]
+    pub unit: Unit,
+    #[serde(default)]
+    pub locale: Locale,
+    /// group the digits before the decimal separator in thousands
+    #[serde(default = "grouped")]
+    pub thousands_separators: bool,
+}
+
+fn grouped() -> bool {
+    true
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            unit: Unit::default(),
+            locale: Locale::default(),
+            thousands_separators: grouped(),
+        }
+    }
+}
+
+/// `amount` in the unit and the way `display` asks for, e.g. `1,234.56780000 BTC`. Worked out
+/// from the satoshis with integers, so no amount loses any of them on the way
+pub fn format_amount(amount: Amount, display: &DisplayConfig) -> String {
+    let number = format_fixed(amount.to_sat(), display.unit.decimals(), display);
+    format!("{number} {}", display.unit.symbol())
+}
+
+/// A fiat value with two decimals the way `display` asks for, e.g. `1,234.56 USD`
+pub fn format_fiat(value: f64, currency: &str, display: &DisplayConfig) -> String {
+    let cents = (value.max(0.0) * 100.0).round() as u64;
+    format!("{} {currency}", format_fixed(cents, 2, display))
+}
+
+/// `units` of a `decimals` places fixed point number, with the separators of `display`
+fn format_fixed(units: u64, decimals: u32, display: &DisplayConfig) -> String {
+    let (thousands, decimal) = display.locale.separators();
+    let scale = 10u64.pow(decimals);
+    let whole = (units / scale).to_string();
+
+    let mut formatted = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if display.thousands_separators && i > 0 && (whole.len() - i).is_multiple_of(3) {
+            formatted.push(thousands);
+        }
+        formatted.push(digit);
+    }
+    if decimals > 0 {
+        formatted.push(decimal);
+        formatted.push_str(&format!(
+            "{:0width$}",
+            units % scale,
+            width = decimals as usize
+        ));
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(unit: Unit, locale: Locale, thousands_separators: bool) -> DisplayConfig {
+        DisplayConfig {
+            unit,
+            locale,
+            thousands_separators,
+        }
+    }
+
+    #[test]
+    fn every_unit_shows_every_satoshi() {
+        let en = |unit| display(unit, Locale::En, true);
+        for (sats, unit, shown) in [
+            (0, Unit::Sat, "0 sat"),
+            (0, Unit::MilliBtc, "0.00000 mBTC"),
+            (0, Unit::Btc, "0.00000000 BTC"),
+            (1, Unit::Sat, "1 sat"),
+            (1, Unit::MilliBtc, "0.00001 mBTC"),
+            (1, Unit::Btc, "0.00000001 BTC"),
+            (123_456_789, Unit::Sat, "123,456,789 sat"),
+            (123_456_789, Unit::MilliBtc, "1,234.56789 mBTC"),
+            (123_456_789, Unit::Btc, "1.23456789 BTC"),
+            (100_000_000, Unit::Btc, "1.00000000 BTC"),
+            (
+                2_100_000_000_000_000,
+                Unit::Sat,
+                "2,100,000,000,000,000 sat",
+            ),
+            (
+                2_100_000_000_000_000,
+                Unit::MilliBtc,
+                "21,000,000,000.00000 mBTC",
+            ),
+            (2_100_000_000_000_000, Unit::Btc, "21,000,000.00000000 BTC"),
+        ] {
+            assert_eq!(format_amount(Amount::from_sat(sats), &en(unit)), shown);
+        }
+        assert_eq!(
+            format_amount(Amount::MAX_MONEY, &en(Unit::Btc)),
+            "21,000,000.00000000 BTC"
+        );
+    }
+
+    #[test]
+    fn separators_follow_the_locale() {
+        let amount = Amount::from_sat(123_456_789_012);
+        for (locale, grouped, shown) in [
+            (Locale::En, true, "1,234.56789012 BTC"),
+            (Locale::De, true, "1.234,56789012 BTC"),
+            (Locale::Fr, true, "1\u{202f}234,56789012 BTC"),
+            (Locale::En, false, "1234.56789012 BTC"),
+            (Locale::De, false, "1234,56789012 BTC"),
+            (Locale::Fr, false, "1234,56789012 BTC"),
+        ] {
+            let display = display(Unit::Btc, locale, grouped);
+            assert_eq!(format_amount(amount, &display), shown);
+        }
+        // grouping only starts past three digits
+        for (sats, shown) in [(999, "999"), (1_000, "1.000"), (999_999, "999.999")] {
+            let display = display(Unit::Sat, Locale::De, true);
+            assert_eq!(format_fixed(sats, 0, &display), shown);
+        }
+    }
+
+    #[test]
+    fn fiat_has_two_decimals_and_no_sign() {
+        for (value, locale, shown) in [
+            (0.0, Locale::En, "0.00 USD"),
+            (1234.567, Locale::En, "1,234.57 USD"),
+            (1234.5, Locale::De, "1.234,50 USD"),
+            (0.004, Locale::Fr, "0,00 USD"),
+            (-12.5, Locale::En, "0.00 USD"),
+        ] {
+            let display = display(Unit::Btc, locale, true);
+            assert_eq!(format_fiat(value, "USD", &display), shown);
+        }
+    }
 }
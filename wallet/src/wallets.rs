@@ -0,0 +1,118 @@
+//! Several wallets side by side, e.g. a payer and a payee for a demo. Each one has a directory
+//! of its own under `WALLETS_DIR` with its config and keys, and is picked with `--wallet <name>`.
+//! Paths in the configs stay relative to where the wallet runs, like in any other config.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use btclib::crypto::PrivateKey;
+use btclib::finality::Finality;
+use btclib::util::Saveable;
+
+use crate::core::{Config, FeeConfig, FeeType, Key, Recipient};
+use crate::util::DisplayConfig;
+
+pub const WALLETS_DIR: &str = "wallets";
+const CONFIG_FILE: &str = "wallet_config.toml";
+
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "Wallet names can only have letters, digits, '-' and '_'"
+        ));
+    }
+    Ok(())
+}
+
+fn dir(name: &str) -> PathBuf {
+    PathBuf::from(WALLETS_DIR).join(name)
+}
+
+/// where the config of the wallet called `name` is
+pub fn config_path(name: &str) -> Result<PathBuf> {
+    check_name(name)?;
+    let path = dir(name).join(CONFIG_FILE);
+    if !path.exists() {
+        return Err(anyhow::anyhow!(
+            "No wallet called {name}, create it with `wallet new-wallet {name}`"
+        ));
+    }
+    Ok(path)
+}
+
+/// The names of the wallets there are, sorted
+pub fn list() -> Result<Vec<String>> {
+    let entries = match fs::read_dir(WALLETS_DIR) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut names = vec![];
+    for entry in entries {
+        let entry = entry?;
+        if entry.path().join(CONFIG_FILE).exists() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Create the wallet called `name` with a new key pair, talking to `node`. The other wallets'
+/// keys are its contacts, by their wallet's name. Returns the path of its config
+pub fn create(name: &str, node: String) -> Result<PathBuf> {
+    check_name(name)?;
+    let dir = dir(name);
+    if dir.exists() {
+        return Err(anyhow::anyhow!("Wallet {name} already exists"));
+    }
+    let mut contacts = vec![];
+    for other in list()? {
+        let config: Config = toml::from_str(&fs::read_to_string(config_path(&other)?)?)
+            .with_context(|| format!("Failed to read the config of wallet {other}"))?;
+        if let Some(key) = config.keys.iter().find(|key| !key.retired) {
+            contacts.push(Recipient {
+                name: other,
+                key: key.public.clone(),
+            });
+        }
+    }
+
+    fs::create_dir_all(&dir)?;
+    let private = PrivateKey::new_key();
+    let key = Key {
+        public: dir.join("key_pub.pem"),
+        private: dir.join("key_priv.cbor"),
+        retired: false,
+        label: None,
+        handed_out: None,
+    };
+    private.public_key().save_to_file(&key.public)?;
+    private.save_to_file(&key.private)?;
+
+    let config = Config {
+        keys: vec![key],
+        contacts,
+        default_node: node,
+        fee_config: FeeConfig {
+            fee_type: FeeType::Percent,
+            value: 0.1,
+        },
+        invoices: dir.join("invoices.cbor"),
+        schedules: vec![],
+        schedule_state: dir.join("schedules.cbor"),
+        display: DisplayConfig::default(),
+        price: None,
+        frozen: dir.join("frozen.cbor"),
+        trusted_nodes: vec![],
+        finality: Finality::default(),
+    };
+    let path = dir.join(CONFIG_FILE);
+    fs::write(&path, toml::to_string_pretty(&config)?)?;
+    Ok(path)
+}